@@ -0,0 +1,846 @@
+//! `pensieve`: fingerprint, compare and index files from the command line.
+
+use pensieve::compact::CompactDigest;
+use pensieve::index::BkTree;
+use pensieve::{BitOrder, TbfConfig, Thresholding, similarity};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+const USAGE: &str = "\
+usage: pensieve [options] <command> [args]
+
+commands:
+  collapse <file>...            print the hex digest of every file
+  compare <a> <b>               print the similarity (0 to 1) of two files
+  index add <index> <file>...   add files to an index, creating it if needed
+  index query <index> <file>    list indexed files near a file, closest first
+  calibrate <file>...           suggest a tolerance for readings of one source
+  watch <dir>                   fingerprint the files under a directory as
+                                they appear or change, and report groups of
+                                near-duplicates
+
+options:
+  --tolerance <t>          tolerated fraction of bit flips, 0.05 to 0.25
+                           (default 0.125)
+  --bit-order <msb|lsb>    order of the bits within each byte (default msb)
+  --thresholding <symmetric|one-sided|parity|mean>
+                           how chunks collapse (default one-sided); the
+                           tolerance only moves one-sided thresholds
+  --max-distance <n>       radius of `index query` in digest bits and of
+                           `watch` in chunk levels (default 0)
+  --interval <seconds>     time between two scans of `watch` (default 2)
+  --once                   make `watch` scan once and exit
+  --config <file>          read `tolerance = ...`, `bit-order = ...`,
+                           `thresholding = ...` and `max-distance = ...`
+                           lines; command-line flags take precedence
+  -h, --help               print this help
+";
+
+/// Name of the file, in the watched directory, `watch` keeps its index in.
+const WATCH_STATE: &str = ".pensieve-watch";
+
+/// Tolerances tried by `calibrate`, from strictest to loosest.
+const CALIBRATION_STEPS: [f32; 9] = [0.05, 0.075, 0.1, 0.125, 0.15, 0.175, 0.2, 0.225, 0.25];
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args, &mut std::io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(message)) => {
+            eprintln!("pensieve: {message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(Failure::Runtime(message)) => {
+            eprintln!("pensieve: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Why a command did not complete.
+#[derive(Debug, PartialEq)]
+enum Failure {
+    /// The command line was malformed; exit status 2.
+    Usage(String),
+    /// The command failed on its inputs; exit status 1.
+    Runtime(String),
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(error: std::io::Error) -> Self {
+        Failure::Runtime(error.to_string())
+    }
+}
+
+/// Parameters shared by all commands, after merging flags and config file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Settings {
+    config: TbfConfig,
+    max_distance: u32,
+}
+
+/// Values given on the command line or in a config file; `None` if unset.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Overrides {
+    tolerance: Option<f32>,
+    bit_order: Option<BitOrder>,
+    thresholding: Option<Thresholding>,
+    max_distance: Option<u32>,
+}
+
+impl Overrides {
+    /// Applies one `name = value` setting.
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "tolerance" => {
+                let tolerance: f32 = value
+                    .parse()
+                    .map_err(|_| format!("invalid tolerance `{value}`"))?;
+                if !(TbfConfig::MIN_TOLERANCE..=TbfConfig::MAX_TOLERANCE).contains(&tolerance) {
+                    return Err(format!("tolerance {value} is outside 0.05 to 0.25"));
+                }
+                self.tolerance = Some(tolerance);
+            }
+            "bit-order" => {
+                self.bit_order = Some(match value {
+                    "msb" => BitOrder::MsbFirst,
+                    "lsb" => BitOrder::LsbFirst,
+                    _ => return Err(format!("invalid bit order `{value}`, expected msb or lsb")),
+                });
+            }
+            "thresholding" => {
+                self.thresholding = Some(match value {
+                    "symmetric" => Thresholding::Symmetric,
+                    "one-sided" => Thresholding::OneSided,
+                    "parity" => Thresholding::Parity,
+                    "mean" => Thresholding::Mean,
+                    _ => {
+                        return Err(format!(
+                            "invalid thresholding `{value}`, expected symmetric, one-sided, parity or mean"
+                        ));
+                    }
+                });
+            }
+            "max-distance" => {
+                let max_distance = value
+                    .parse()
+                    .map_err(|_| format!("invalid max distance `{value}`"))?;
+                self.max_distance = Some(max_distance);
+            }
+            _ => return Err(format!("unknown setting `{name}`")),
+        }
+        Ok(())
+    }
+
+    /// Reads `name = value` lines; blank lines and `#` comments are skipped.
+    fn parse_config(text: &str) -> Result<Self, String> {
+        let mut overrides = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `name = value`", number + 1))?;
+            overrides
+                .set(name.trim(), value.trim())
+                .map_err(|e| format!("line {}: {e}", number + 1))?;
+        }
+        Ok(overrides)
+    }
+
+    /// `self` where set, `fallback` elsewhere.
+    fn or(self, fallback: Self) -> Self {
+        Self {
+            tolerance: self.tolerance.or(fallback.tolerance),
+            bit_order: self.bit_order.or(fallback.bit_order),
+            thresholding: self.thresholding.or(fallback.thresholding),
+            max_distance: self.max_distance.or(fallback.max_distance),
+        }
+    }
+
+    fn settings(self) -> Settings {
+        Settings {
+            config: self
+                .tolerance
+                .map_or_else(TbfConfig::default, TbfConfig::new)
+                .with_bit_order(self.bit_order.unwrap_or_default())
+                .with_thresholding(self.thresholding.unwrap_or_default()),
+            max_distance: self.max_distance.unwrap_or(0),
+        }
+    }
+}
+
+/// Runs the command line `args` (without the program name), writing results to `out`.
+fn run(args: &[String], out: &mut impl Write) -> Result<(), Failure> {
+    let mut flags = Overrides::default();
+    let mut config_file = None;
+    let mut watching = Watching {
+        interval: Duration::from_secs(2),
+        once: false,
+    };
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| Failure::Usage(format!("{flag} needs a value")))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                out.write_all(USAGE.as_bytes())?;
+                return Ok(());
+            }
+            "--tolerance" => flags
+                .set("tolerance", value(arg)?)
+                .map_err(Failure::Usage)?,
+            "--bit-order" => flags
+                .set("bit-order", value(arg)?)
+                .map_err(Failure::Usage)?,
+            "--thresholding" => flags
+                .set("thresholding", value(arg)?)
+                .map_err(Failure::Usage)?,
+            "--max-distance" => flags
+                .set("max-distance", value(arg)?)
+                .map_err(Failure::Usage)?,
+            "--config" => config_file = Some(PathBuf::from(value(arg)?)),
+            "--interval" => {
+                let seconds = value(arg)?;
+                watching.interval = seconds
+                    .parse()
+                    .ok()
+                    .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                    .ok_or_else(|| Failure::Usage(format!("invalid interval `{seconds}`")))?;
+            }
+            "--once" => watching.once = true,
+            flag if flag.starts_with("--") => {
+                return Err(Failure::Usage(format!("unknown option `{flag}`")));
+            }
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    let from_file = match &config_file {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| in_file(path, e))?;
+            Overrides::parse_config(&text)
+                .map_err(|e| Failure::Usage(format!("{}: {e}", path.display())))?
+        }
+        None => Overrides::default(),
+    };
+    let settings = flags.or(from_file).settings();
+
+    match positional.as_slice() {
+        ["collapse", files @ ..] if !files.is_empty() => collapse(files, &settings, out),
+        ["compare", a, b] => compare(a, b, &settings, out),
+        ["index", "add", index, files @ ..] if !files.is_empty() => {
+            index_add(Path::new(index), files, &settings, out)
+        }
+        ["index", "query", index, file] => index_query(Path::new(index), file, &settings, out),
+        ["calibrate", files @ ..] if files.len() >= 2 => calibrate(files, &settings, out),
+        ["watch", dir] => watch(Path::new(dir), &settings, watching, out),
+        [] => Err(Failure::Usage("no command given".into())),
+        [
+            command @ ("collapse" | "compare" | "index" | "calibrate" | "watch"),
+            ..,
+        ] => Err(Failure::Usage(format!("wrong arguments for `{command}`"))),
+        [command, ..] => Err(Failure::Usage(format!("unknown command `{command}`"))),
+    }
+}
+
+fn collapse(files: &[&str], settings: &Settings, out: &mut impl Write) -> Result<(), Failure> {
+    for file in files {
+        let digest = settings.config.collapse(&read(file)?);
+        writeln!(out, "{}  {file}", hex(&digest))?;
+    }
+    Ok(())
+}
+
+fn compare(a: &str, b: &str, settings: &Settings, out: &mut impl Write) -> Result<(), Failure> {
+    let score = similarity(&read(a)?, &read(b)?, &settings.config);
+    let verdict = if score == 1.0 { "match" } else { "different" };
+    writeln!(out, "{score:.4}  {verdict}")?;
+    Ok(())
+}
+
+/// An index is a [`BkTree::save`] file at `index` mapping digests to line
+/// numbers of the sidecar `<index>.names`, which lists the indexed paths.
+fn index_add(
+    index: &Path,
+    files: &[&str],
+    settings: &Settings,
+    out: &mut impl Write,
+) -> Result<(), Failure> {
+    let (mut tree, mut names) = if index.exists() {
+        load_index(index)?
+    } else {
+        (BkTree::new(), Vec::new())
+    };
+    for file in files {
+        if file.contains('\n') {
+            return Err(Failure::Runtime(format!(
+                "{file:?}: paths cannot contain newlines"
+            )));
+        }
+        let digest = settings.config.collapse(&read(file)?);
+        tree.insert(&digest, names.len() as u64).map_err(|_| {
+            Failure::Runtime(format!(
+                "{file}: every file in one index must have the same length ({} bytes)",
+                tree.key_len().unwrap_or_default()
+            ))
+        })?;
+        names.push(file.to_string());
+    }
+
+    let save = std::fs::File::create(index).and_then(|f| tree.save(std::io::BufWriter::new(f)));
+    save.map_err(|e| in_file(index, e))?;
+    let names_path = names_path(index);
+    let mut listing = names.join("\n");
+    listing.push('\n');
+    std::fs::write(&names_path, listing).map_err(|e| in_file(&names_path, e))?;
+    writeln!(
+        out,
+        "added {} files, index holds {}",
+        files.len(),
+        tree.len()
+    )?;
+    Ok(())
+}
+
+fn index_query(
+    index: &Path,
+    file: &str,
+    settings: &Settings,
+    out: &mut impl Write,
+) -> Result<(), Failure> {
+    let (tree, names) = load_index(index)?;
+    let digest = settings.config.collapse(&read(file)?);
+    let matches = tree
+        .query_within(&digest, settings.max_distance)
+        .map_err(|_| {
+            Failure::Runtime(format!(
+                "{file}: indexed files are {} bytes long",
+                tree.key_len().unwrap_or_default()
+            ))
+        })?;
+    for m in matches {
+        writeln!(out, "{}\t{}", m.distance, names[*m.value as usize])?;
+    }
+    Ok(())
+}
+
+/// Collapses readings of one source under every calibration step and
+/// recommends the strictest tolerance that collapses them all identically.
+fn calibrate(files: &[&str], settings: &Settings, out: &mut impl Write) -> Result<(), Failure> {
+    let readings = files
+        .iter()
+        .map(|f| read(f))
+        .collect::<Result<Vec<_>, _>>()?;
+    writeln!(out, "tolerance  min-similarity  identical")?;
+    let mut recommended = None;
+    for tolerance in CALIBRATION_STEPS {
+        let config = settings.config.with_tolerance(tolerance);
+        let min_similarity = readings[1..]
+            .iter()
+            .map(|reading| similarity(&readings[0], reading, &config))
+            .fold(1.0f32, f32::min);
+        let identical = min_similarity == 1.0;
+        writeln!(
+            out,
+            "{tolerance:<9.3}  {min_similarity:<14.4}  {}",
+            if identical { "yes" } else { "no" }
+        )?;
+        if identical && recommended.is_none() {
+            recommended = Some(tolerance);
+        }
+    }
+    match recommended {
+        Some(tolerance) => writeln!(out, "recommended tolerance: {tolerance}")?,
+        None => writeln!(out, "no tolerance collapses these readings identically")?,
+    }
+    Ok(())
+}
+
+/// How `watch` runs, from `--interval` and `--once`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Watching {
+    interval: Duration,
+    once: bool,
+}
+
+/// What `watch` remembers of a file, to collapse it again only once it
+/// changed.
+#[derive(Debug, Clone, PartialEq)]
+struct Watched {
+    len: u64,
+    modified: u128,  // Nanoseconds since the Unix epoch.
+    levels: Vec<u8>, // Packed chunk levels, as in a `CompactDigest`.
+}
+
+/// Scans `dir` every `watching.interval`, collapsing new and changed files
+/// into the index `<dir>/.pensieve-watch`, and reports the groups of files
+/// whose chunk levels differ in at most `--max-distance` chunks that
+/// gained a member. The first scan, and every scan with `--once`, reports
+/// all groups. Only files of equal length can be near-duplicates.
+fn watch(
+    dir: &Path,
+    settings: &Settings,
+    watching: Watching,
+    out: &mut impl Write,
+) -> Result<(), Failure> {
+    let state = dir.join(WATCH_STATE);
+    let mut index = load_watched(&state, settings)?;
+    let mut first = true;
+    loop {
+        let mut changed = Vec::new();
+        let mut seen = Vec::new();
+        scan(dir, dir, &mut |name, path, metadata| {
+            seen.push(name.clone());
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos());
+            let unchanged = index
+                .get(&name)
+                .is_some_and(|w| w.len == metadata.len() && w.modified == modified);
+            if !unchanged {
+                let compact = CompactDigest::collapse(&read_path(path)?, &settings.config);
+                index.insert(
+                    name.clone(),
+                    Watched {
+                        len: metadata.len(),
+                        modified,
+                        levels: compact.packed_levels().to_vec(),
+                    },
+                );
+                changed.push(name);
+            }
+            Ok(())
+        })?;
+        seen.sort();
+        let removed = index.len() - seen.len();
+        index.retain(|name, _| seen.binary_search(name).is_ok());
+        save_watched(&state, settings, &index)?;
+
+        if first || watching.once || !changed.is_empty() || removed > 0 {
+            writeln!(
+                out,
+                "indexed {} files ({} new or changed, {removed} removed)",
+                index.len(),
+                changed.len()
+            )?;
+        }
+        for group in near_duplicates(&index, settings.max_distance) {
+            if first || watching.once || group.iter().any(|name| changed.contains(name)) {
+                writeln!(out, "near-duplicates:")?;
+                for name in group {
+                    writeln!(out, "  {name}")?;
+                }
+            }
+        }
+        out.flush()?;
+        if watching.once {
+            return Ok(());
+        }
+        first = false;
+        std::thread::sleep(watching.interval);
+    }
+}
+
+/// Calls `visit` with the path relative to `root`, the path and the
+/// metadata of every file under `dir`, in sorted order; skips the index
+/// and files whose names cannot be stored in it.
+fn scan(
+    root: &Path,
+    dir: &Path,
+    visit: &mut impl FnMut(String, &Path, std::fs::Metadata) -> Result<(), Failure>,
+) -> Result<(), Failure> {
+    let mut entries = std::fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| in_file(dir, e))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let metadata = entry.metadata().map_err(|e| in_file(&path, e))?;
+        let name = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .into_owned();
+        if metadata.is_dir() {
+            scan(root, &path, visit)?;
+        } else if metadata.is_file() && name != WATCH_STATE && !name.contains(['\t', '\n']) {
+            visit(name, &path, metadata)?;
+        }
+    }
+    Ok(())
+}
+
+/// Groups of two or more indexed files whose chunk levels differ in at
+/// most `max_distance` chunks, directly or through other members.
+fn near_duplicates(index: &BTreeMap<String, Watched>, max_distance: u32) -> Vec<Vec<&String>> {
+    let entries: Vec<(&String, &Watched)> = index.iter().collect();
+    // Only files of equal length are compared, so each length gets a tree.
+    let mut trees: BTreeMap<(u64, usize), BkTree<usize>> = BTreeMap::new();
+    for (i, (_, watched)) in entries.iter().enumerate() {
+        trees
+            .entry((watched.len, watched.levels.len()))
+            .or_default()
+            .insert(&watched.levels, i)
+            .expect("keys of one tree have one length");
+    }
+    // Union-find over the entries.
+    let mut parent: Vec<usize> = (0..entries.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for (i, (_, watched)) in entries.iter().enumerate() {
+        let near = trees[&(watched.len, watched.levels.len())]
+            .query_within(&watched.levels, max_distance)
+            .expect("keys of one tree have one length");
+        for j in near.into_iter().map(|m| *m.value) {
+            let (ri, rj) = (root(&mut parent, i), root(&mut parent, j));
+            parent[ri.max(rj)] = ri.min(rj);
+        }
+    }
+    let mut groups: BTreeMap<usize, Vec<&String>> = BTreeMap::new();
+    for (i, (name, _)) in entries.iter().enumerate() {
+        groups.entry(root(&mut parent, i)).or_default().push(name);
+    }
+    groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+/// First line of the index, naming the parameters its digests were
+/// collapsed with; an index written under others is rebuilt.
+fn watch_header(settings: &Settings) -> String {
+    let config = &settings.config;
+    format!(
+        "# pensieve watch v2 tolerance={} bit-order={} thresholding={}",
+        config.tolerance_permille(),
+        config.bit_order().id(),
+        config.thresholding().id()
+    )
+}
+
+/// Reads the index of `watch`: after the header, one `path<TAB>length<TAB>
+/// modified<TAB>hex packed levels` line per file.
+fn load_watched(state: &Path, settings: &Settings) -> Result<BTreeMap<String, Watched>, Failure> {
+    let text = match std::fs::read_to_string(state) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(in_file(state, e)),
+    };
+    let mut lines = text.lines();
+    if lines.next() != Some(watch_header(settings).as_str()) {
+        return Ok(BTreeMap::new());
+    }
+    let corrupt = |number: usize| {
+        Failure::Runtime(format!(
+            "{}: line {}: corrupt entry",
+            state.display(),
+            number + 2
+        ))
+    };
+    let mut index = BTreeMap::new();
+    for (number, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [name, len, modified, levels] = fields[..] else {
+            return Err(corrupt(number));
+        };
+        let levels = (0..levels.len())
+            .step_by(2)
+            .map(|i| {
+                levels
+                    .get(i..i + 2)
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| corrupt(number))?;
+        let watched = Watched {
+            len: len.parse().map_err(|_| corrupt(number))?,
+            modified: modified.parse().map_err(|_| corrupt(number))?,
+            levels,
+        };
+        index.insert(name.to_string(), watched);
+    }
+    Ok(index)
+}
+
+fn save_watched(
+    state: &Path,
+    settings: &Settings,
+    index: &BTreeMap<String, Watched>,
+) -> Result<(), Failure> {
+    let mut text = watch_header(settings);
+    text.push('\n');
+    for (name, watched) in index {
+        text.push_str(&format!(
+            "{name}\t{}\t{}\t{}\n",
+            watched.len,
+            watched.modified,
+            hex(&watched.levels)
+        ));
+    }
+    std::fs::write(state, text).map_err(|e| in_file(state, e))
+}
+
+fn load_index(index: &Path) -> Result<(BkTree<u64>, Vec<String>), Failure> {
+    let tree = std::fs::File::open(index)
+        .and_then(|f| BkTree::load(std::io::BufReader::new(f)))
+        .map_err(|e| in_file(index, e))?;
+    let names_path = names_path(index);
+    let names: Vec<String> = std::fs::read_to_string(&names_path)
+        .map_err(|e| in_file(&names_path, e))?
+        .lines()
+        .map(str::to_string)
+        .collect();
+    if names.len() != tree.len() {
+        return Err(Failure::Runtime(format!(
+            "{}: index holds {} entries but lists {} names",
+            index.display(),
+            tree.len(),
+            names.len()
+        )));
+    }
+    Ok((tree, names))
+}
+
+fn names_path(index: &Path) -> PathBuf {
+    let mut path = index.as_os_str().to_owned();
+    path.push(".names");
+    PathBuf::from(path)
+}
+
+fn read(file: &str) -> Result<Vec<u8>, Failure> {
+    read_path(Path::new(file))
+}
+
+fn read_path(path: &Path) -> Result<Vec<u8>, Failure> {
+    std::fs::read(path).map_err(|e| in_file(path, e))
+}
+
+fn in_file(path: &Path, error: std::io::Error) -> Failure {
+    Failure::Runtime(format!("{}: {error}", path.display()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh scratch directory under the system temp dir.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pensieve-cli-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, bytes: &[u8]) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, bytes).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn run_ok(args: &[&str]) -> String {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn run_err(args: &[&str]) -> Failure {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        run(&args, &mut Vec::new()).unwrap_err()
+    }
+
+    fn reading(noise: u8) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..2].fill(0xFF);
+        bytes[0] ^= noise;
+        bytes
+    }
+
+    #[test]
+    fn test_collapse_and_compare() {
+        let dir = scratch("compare");
+        let a = write(&dir, "a", &reading(0));
+        let b = write(&dir, "b", &reading(1));
+        let c = write(&dir, "c", &[0; 16]);
+
+        let collapsed = run_ok(&["collapse", &a, &b]);
+        let lines: Vec<&str> = collapsed.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            format!("{}  {a}", hex(&TbfConfig::default().collapse(&reading(0))))
+        );
+        assert_eq!(lines[0].split(' ').next(), lines[1].split(' ').next());
+
+        assert_eq!(run_ok(&["compare", &a, &b]), "1.0000  match\n");
+        assert!(run_ok(&["--tolerance", "0.05", "compare", &a, &c]).ends_with("different\n"));
+    }
+
+    #[test]
+    fn test_index_add_and_query() {
+        let dir = scratch("index");
+        let index = dir.join("corpus.idx");
+        let index = index.to_str().unwrap();
+        let a = write(&dir, "a", &reading(0));
+        let c = write(&dir, "c", &[0; 16]);
+        let probe = write(&dir, "probe", &reading(3));
+
+        assert_eq!(
+            run_ok(&["index", "add", index, &a]),
+            "added 1 files, index holds 1\n"
+        );
+        assert_eq!(
+            run_ok(&["index", "add", index, &c]),
+            "added 1 files, index holds 2\n"
+        );
+        assert_eq!(
+            run_ok(&["index", "query", index, &probe]),
+            format!("0\t{a}\n")
+        );
+        let near = run_ok(&["--max-distance", "128", "index", "query", index, &probe]);
+        assert_eq!(near.lines().count(), 2);
+
+        let short = write(&dir, "short", &[0; 3]);
+        assert!(matches!(
+            run_err(&["index", "add", index, &short]),
+            Failure::Runtime(_)
+        ));
+    }
+
+    #[test]
+    fn test_calibrate_recommends_strictest_identical_tolerance() {
+        let dir = scratch("calibrate");
+        let a = write(&dir, "a", &reading(0));
+        let mut sparse = [0u8; 16];
+        sparse[0] = 0x80;
+        let b = write(&dir, "b", &sparse);
+        // 15 of 16 bits lost in chunk 0: only one-sided threshold 1 keeps it set.
+        let report = run_ok(&["--thresholding", "one-sided", "calibrate", &a, &b]);
+        assert!(report.starts_with("tolerance"));
+        assert!(
+            report.ends_with("recommended tolerance: 0.05\n"),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn test_watch_keeps_an_index_and_groups_near_duplicates() {
+        let dir = scratch("watch");
+        std::fs::create_dir(dir.join("inbox")).unwrap();
+        write(&dir, "a", &reading(0));
+        write(&dir, "inbox/b", &reading(1));
+        write(&dir, "c", &[0; 16]);
+        let dir_arg = dir.to_str().unwrap();
+
+        assert_eq!(
+            run_ok(&["watch", "--once", dir_arg]),
+            "indexed 3 files (3 new or changed, 0 removed)\nnear-duplicates:\n  a\n  inbox/b\n"
+        );
+        let state = std::fs::read_to_string(dir.join(WATCH_STATE)).unwrap();
+        assert_eq!(state.lines().count(), 4);
+        // One byte of packed levels per file; only chunk 0 of a reading is set.
+        let levels: Vec<&str> = state.lines().skip(1).map(|l| &l[l.len() - 3..]).collect();
+        assert_eq!(levels, ["\t80", "\t00", "\t80"]);
+        // Chunk 0 is the only one in which `c` differs.
+        assert!(
+            run_ok(&["--max-distance", "1", "watch", "--once", dir_arg])
+                .ends_with("near-duplicates:\n  a\n  c\n  inbox/b\n")
+        );
+
+        // Unchanged files are not collapsed again; new ones join groups.
+        std::fs::remove_file(dir.join("a")).unwrap();
+        write(&dir, "d", &[0; 16]);
+        assert_eq!(
+            run_ok(&["watch", "--once", dir_arg]),
+            "indexed 3 files (1 new or changed, 1 removed)\nnear-duplicates:\n  c\n  d\n"
+        );
+        // Another configuration rebuilds the index.
+        let rebuilt = run_ok(&["--tolerance", "0.05", "watch", "--once", dir_arg]);
+        assert!(rebuilt.starts_with("indexed 3 files (3 new or changed, 0 removed)\n"));
+
+        assert!(matches!(
+            run_err(&["watch", "--once", "/nonexistent/pensieve"]),
+            Failure::Runtime(_)
+        ));
+        assert!(matches!(
+            run_err(&["--interval", "-1", "watch", dir_arg]),
+            Failure::Usage(_)
+        ));
+    }
+
+    #[test]
+    fn test_config_file_and_flag_precedence() {
+        let dir = scratch("config");
+        let config = write(
+            &dir,
+            "pensieve.conf",
+            b"# triage defaults\ntolerance = 0.2\nbit-order = lsb\nthresholding = one-sided\nmax-distance = 4\n",
+        );
+        let parsed = Overrides::parse_config(&std::fs::read_to_string(&config).unwrap()).unwrap();
+        assert_eq!(parsed.tolerance, Some(0.2));
+        assert_eq!(parsed.bit_order, Some(BitOrder::LsbFirst));
+        assert_eq!(parsed.thresholding, Some(Thresholding::OneSided));
+        assert_eq!(parsed.max_distance, Some(4));
+
+        let flags = Overrides {
+            tolerance: Some(0.1),
+            bit_order: None,
+            thresholding: None,
+            max_distance: None,
+        };
+        let settings = flags.or(parsed).settings();
+        assert_eq!(settings.config.tolerance(), 0.1);
+        assert_eq!(settings.config.bit_order(), BitOrder::LsbFirst);
+        assert_eq!(settings.config.thresholding(), Thresholding::OneSided);
+        assert_eq!(settings.max_distance, 4);
+
+        assert!(Overrides::parse_config("tolerance = 0.5").is_err());
+        assert!(Overrides::parse_config("colour = blue").is_err());
+        assert!(Overrides::parse_config("tolerance").is_err());
+        assert!(Overrides::parse_config("bit-order = middle").is_err());
+        assert_eq!(
+            Overrides::parse_config("thresholding = mean")
+                .unwrap()
+                .thresholding,
+            Some(Thresholding::Mean)
+        );
+        assert!(Overrides::parse_config("thresholding = both").is_err());
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        assert!(run_ok(&["--help"]).starts_with("usage:"));
+        assert!(matches!(run_err(&[]), Failure::Usage(_)));
+        assert!(matches!(run_err(&["compare", "a"]), Failure::Usage(_)));
+        assert_eq!(
+            run_err(&["bogus"]),
+            Failure::Usage("unknown command `bogus`".into())
+        );
+        assert!(matches!(run_err(&["--tolerance"]), Failure::Usage(_)));
+        assert!(matches!(run_err(&["--frobnicate", "x"]), Failure::Usage(_)));
+        assert!(matches!(
+            run_err(&["collapse", "/nonexistent/pensieve"]),
+            Failure::Runtime(_)
+        ));
+    }
+}
@@ -0,0 +1,263 @@
+//! `#[derive(FuzzyFingerprint)]` for the `pensieve` crate.
+//!
+//! Use it through `pensieve` with the `derive` feature, which re-exports
+//! the macro next to the `pensieve::record::FuzzyFingerprint` trait it
+//! implements. The generated `fingerprint` pushes every field of the
+//! struct, in declaration order, onto a `RecordFingerprint`:
+//!
+//! ```ignore
+//! use pensieve::record::FuzzyFingerprint;
+//!
+//! #[derive(FuzzyFingerprint)]
+//! struct Customer {
+//!     #[fuzzy(skip)]
+//!     id: u64,
+//!     #[fuzzy(tolerance = 0.25)]
+//!     name: String,
+//!     #[fuzzy(weight = 3)]
+//!     email: String,
+//! }
+//! ```
+//!
+//! Field attributes tune each field: `#[fuzzy(skip)]` leaves it out,
+//! `#[fuzzy(tolerance = 0.2)]` collapses it at its own tolerance and
+//! `#[fuzzy(weight = 3)]` sets its weight; the last two combine, as in
+//! `#[fuzzy(tolerance = 0.2, weight = 3)]`. Every field that is not skipped
+//! must implement `pensieve::record::CanonicalBytes`. Structs with named fields, tuple
+//! structs and unit structs are supported; enums, unions and generic
+//! structs are rejected.
+//!
+//! The crate has no dependencies, so the input is read straight from the
+//! token stream rather than through a full Rust parser.
+
+use proc_macro::{Delimiter, Spacing, TokenStream, TokenTree};
+
+/// Implements `pensieve::record::FuzzyFingerprint` for a struct.
+#[proc_macro_derive(FuzzyFingerprint, attributes(fuzzy))]
+pub fn derive_fuzzy_fingerprint(input: TokenStream) -> TokenStream {
+    let code = match expand(input) {
+        Ok(code) => code,
+        Err(message) => format!("::core::compile_error!({message:?});"),
+    };
+    code.parse().expect("generated code is valid Rust")
+}
+
+/// The impl block for the struct in `input`.
+fn expand(input: TokenStream) -> Result<String, String> {
+    let mut tokens = input.into_iter().peekable();
+    let mut name = None;
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Ident(ident) if ident.to_string() == "struct" => {
+                match tokens.next() {
+                    Some(TokenTree::Ident(ident)) => name = Some(ident.to_string()),
+                    _ => return Err("expected a struct name".to_string()),
+                }
+                break;
+            }
+            TokenTree::Ident(ident) if matches!(ident.to_string().as_str(), "enum" | "union") => {
+                return Err(format!(
+                    "FuzzyFingerprint can only be derived for structs, not for {ident}s"
+                ));
+            }
+            _ => {} // Attributes and visibility.
+        }
+    }
+    let name = name.ok_or("expected a struct")?;
+
+    let fields = match tokens.next() {
+        Some(TokenTree::Group(body)) if body.delimiter() == Delimiter::Brace => {
+            fields(body.stream(), true)?
+        }
+        Some(TokenTree::Group(body)) if body.delimiter() == Delimiter::Parenthesis => {
+            fields(body.stream(), false)?
+        }
+        Some(TokenTree::Punct(punct)) if punct.as_char() == ';' => Vec::new(),
+        Some(TokenTree::Punct(punct)) if punct.as_char() == '<' => {
+            return Err("FuzzyFingerprint cannot be derived for generic structs".to_string());
+        }
+        _ => return Err("FuzzyFingerprint cannot be derived for this struct".to_string()),
+    };
+
+    let pushes: String = fields
+        .iter()
+        .map(|field| {
+            let config = match &field.tolerance {
+                Some(tolerance) => format!("&config.with_tolerance({tolerance})"),
+                None => "config".to_string(),
+            };
+            format!(
+                "fingerprint.push_weighted(&self.{}, {config}, {});",
+                field.accessor, field.weight
+            )
+        })
+        .collect();
+    Ok(format!(
+        "impl ::pensieve::record::FuzzyFingerprint for {name} {{
+            fn fingerprint(
+                &self,
+                config: &::pensieve::TbfConfig,
+            ) -> ::pensieve::record::RecordFingerprint {{
+                #[allow(unused_mut)]
+                let mut fingerprint = ::pensieve::record::RecordFingerprint::new();
+                {pushes}
+                let _ = config;
+                fingerprint
+            }}
+        }}"
+    ))
+}
+
+/// A fingerprinted field.
+struct Field {
+    /// Its name, or its index in a tuple struct.
+    accessor: String,
+    /// Tolerance literal overriding the caller's, if any.
+    tolerance: Option<String>,
+    weight: u32,
+}
+
+/// The fingerprinted fields of a struct body, in declaration order.
+fn fields(body: TokenStream, named: bool) -> Result<Vec<Field>, String> {
+    let mut fields = Vec::new();
+    for (index, field) in split_fields(body).into_iter().enumerate() {
+        let mut tokens = field.into_iter().peekable();
+        let mut options = Options::default();
+        // Attributes: `#` followed by a bracketed group.
+        while matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '#') {
+            tokens.next();
+            let Some(TokenTree::Group(attribute)) = tokens.next() else {
+                return Err("malformed field attribute".to_string());
+            };
+            options.parse(attribute.stream())?;
+        }
+        let accessor = if named {
+            // Visibility, then the field name right before the `:`.
+            let mut previous = None;
+            for token in tokens {
+                match token {
+                    TokenTree::Punct(p) if p.as_char() == ':' => break,
+                    token => previous = Some(token),
+                }
+            }
+            match previous {
+                Some(TokenTree::Ident(ident)) => ident.to_string(),
+                _ => return Err("expected a field name".to_string()),
+            }
+        } else {
+            index.to_string()
+        };
+        if options.skip {
+            if options.tolerance.is_some() || options.weight.is_some() {
+                return Err(format!(
+                    "skipped field `{accessor}` cannot have a tolerance or weight"
+                ));
+            }
+            continue;
+        }
+        fields.push(Field {
+            accessor,
+            tolerance: options.tolerance,
+            weight: options.weight.unwrap_or(1),
+        });
+    }
+    Ok(fields)
+}
+
+/// The `#[fuzzy(...)]` options of one field.
+#[derive(Default)]
+struct Options {
+    skip: bool,
+    tolerance: Option<String>,
+    weight: Option<u32>,
+}
+
+impl Options {
+    /// Adds the options of one attribute's contents; attributes other than
+    /// `fuzzy` are ignored.
+    fn parse(&mut self, attribute: TokenStream) -> Result<(), String> {
+        let mut tokens = attribute.into_iter();
+        match tokens.next() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "fuzzy" => {}
+            _ => return Ok(()),
+        }
+        let arguments = match (tokens.next(), tokens.next()) {
+            (Some(TokenTree::Group(arguments)), None)
+                if arguments.delimiter() == Delimiter::Parenthesis =>
+            {
+                arguments.stream()
+            }
+            _ => return Err("expected #[fuzzy(...)]".to_string()),
+        };
+        for option in split_fields(arguments) {
+            let [TokenTree::Ident(key), rest @ ..] = option.as_slice() else {
+                return Err("expected a fuzzy option name".to_string());
+            };
+            let key = key.to_string();
+            let value = match rest {
+                [] => None,
+                [TokenTree::Punct(eq), TokenTree::Literal(value)] if eq.as_char() == '=' => {
+                    Some(value.to_string())
+                }
+                _ => return Err(format!("malformed fuzzy option `{key}`")),
+            };
+            match (key.as_str(), value) {
+                ("skip", None) if !self.skip => self.skip = true,
+                ("tolerance", Some(value)) if self.tolerance.is_none() => {
+                    match value.parse::<f32>() {
+                        Ok(t) if (0.05..=0.25).contains(&t) => self.tolerance = Some(value),
+                        _ => {
+                            return Err(format!(
+                                "tolerance must be a number from 0.05 to 0.25, not {value}"
+                            ));
+                        }
+                    }
+                }
+                ("weight", Some(value)) if self.weight.is_none() => match value.parse::<u32>() {
+                    Ok(weight) if weight > 0 => self.weight = Some(weight),
+                    _ => {
+                        return Err(format!(
+                            "weight must be a positive integer, not {value}; use skip to leave a field out"
+                        ));
+                    }
+                },
+                ("skip" | "tolerance" | "weight", _) => {
+                    return Err(format!("fuzzy option `{key}` is malformed or repeated"));
+                }
+                _ => {
+                    return Err(format!(
+                        "unknown fuzzy option `{key}`; expected skip, tolerance or weight"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The tokens of each comma-separated field (or attribute option). Commas
+/// inside generic arguments, such as `BTreeMap<K, V>`, do not separate.
+fn split_fields(body: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut fields = vec![Vec::new()];
+    let mut depth = 0usize; // Open `<` of generic arguments.
+    let mut after_dash = false; // Whether a `-` of `->` came right before.
+    for token in body {
+        if let TokenTree::Punct(punct) = &token {
+            match punct.as_char() {
+                '<' => depth += 1,
+                '>' if !after_dash => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => {
+                    fields.push(Vec::new());
+                    continue;
+                }
+                _ => {}
+            }
+            after_dash = punct.as_char() == '-' && punct.spacing() == Spacing::Joint;
+        } else {
+            after_dash = false;
+        }
+        fields.last_mut().expect("never empty").push(token);
+    }
+    fields.retain(|field| !field.is_empty()); // A trailing comma.
+    fields
+}
@@ -0,0 +1,92 @@
+use pensieve::TbfConfig;
+use pensieve::record::{FuzzyFingerprint, RecordFingerprint};
+use std::collections::BTreeMap;
+
+#[derive(FuzzyFingerprint)]
+struct Customer {
+    #[fuzzy(skip)]
+    id: u64,
+    /// Full name.
+    pub name: String,
+    #[fuzzy(skip)]
+    notes: BTreeMap<String, String>,
+    #[fuzzy(skip)]
+    format: fn(u8, u8) -> Option<u8>,
+    pub(crate) email: String,
+    visits: Vec<u32>,
+}
+
+#[derive(FuzzyFingerprint)]
+struct Reading(u16, #[fuzzy(skip)] BTreeMap<String, u8>, [u8; 4]);
+
+#[derive(FuzzyFingerprint)]
+struct Nothing;
+
+#[derive(FuzzyFingerprint)]
+struct Event {
+    #[fuzzy(tolerance = 0.25)]
+    message: String,
+    #[fuzzy(weight = 3, tolerance = 0.05)]
+    #[doc = "Unrelated attributes are left alone."]
+    source: u32,
+}
+
+#[test]
+fn test_named_fields() {
+    let config = TbfConfig::new(0.125);
+    let customer = |id, name: &str| Customer {
+        id,
+        name: name.to_string(),
+        notes: BTreeMap::new(),
+        format: |a, b| a.checked_add(b),
+        email: "ada@example.com".to_string(),
+        visits: vec![3, 4],
+    };
+    let (a, b) = (customer(1, "Ada Lovelace"), customer(2, "Ada Lovelacf"));
+    assert_eq!(a.fingerprint(&config), b.fingerprint(&config));
+    let fingerprint = a.fingerprint(&config);
+    let fields: Vec<&[u8]> = fingerprint.fields().collect();
+    assert_eq!(fields.len(), 3);
+    assert_eq!(fields[1], config.collapse(b"ada@example.com"));
+    assert_eq!(fields[2], config.collapse(&[0, 0, 0, 3, 0, 0, 0, 4]));
+    assert_eq!(a.id + b.id, 3);
+    assert!(a.notes.is_empty());
+    assert_eq!((a.format)(1, 2), Some(3));
+
+    let c = customer(1, "Grace Brewster Hopper");
+    assert_eq!(
+        a.fingerprint(&config)
+            .matching_fields(&c.fingerprint(&config)),
+        2
+    );
+}
+
+#[test]
+fn test_tuple_and_unit_structs() {
+    let config = TbfConfig::new(0.125);
+    let reading = Reading(0x0102, BTreeMap::new(), [9; 4]);
+    let mut expected = RecordFingerprint::new();
+    expected.push(&0x0102u16, &config);
+    expected.push(&[9u8; 4], &config);
+    assert_eq!(reading.fingerprint(&config), expected);
+    assert!(reading.1.is_empty());
+    assert_eq!(Nothing.fingerprint(&config), RecordFingerprint::new());
+}
+
+#[test]
+fn test_tolerances_and_weights() {
+    let config = TbfConfig::new(0.125);
+    let event = Event {
+        message: "disk full on /dev/sda1".to_string(),
+        source: 0xFFFF_FFFF,
+    };
+    let mut expected = RecordFingerprint::new();
+    expected.push_weighted(&event.message, &TbfConfig::new(0.25), 1);
+    expected.push_weighted(&event.source, &TbfConfig::new(0.05), 3);
+    assert_eq!(event.fingerprint(&config), expected);
+
+    let elsewhere = Event { source: 0, ..event };
+    let (a, b) = (elsewhere.fingerprint(&config), expected);
+    assert_eq!(a.matching_fields(&b), 1);
+    assert_eq!(a.score(&b), 0.25);
+}
@@ -0,0 +1,125 @@
+/*
+ * C interface to the Pensieve library (crate `pensieve-ffi`).
+ *
+ * Ownership rules:
+ * - Every buffer is owned by the caller. Inputs are only read during the
+ *   call, outputs are written into caller-provided memory of the documented
+ *   size, and no pointer is retained after a function returns.
+ * - A PensieveIndex is owned by the library: create it with
+ *   pensieve_index_new() and release it exactly once with
+ *   pensieve_index_free().
+ * - A null pointer is accepted for any input buffer whose length is 0.
+ * - Hosts that cannot allocate in the library's address space (a
+ *   WebAssembly instance's linear memory) get buffers from
+ *   pensieve_buffer_alloc() and return them with pensieve_buffer_free().
+ *
+ * Functions returning int32_t report one of the PENSIEVE_* status codes;
+ * outputs are only written on PENSIEVE_OK unless documented otherwise.
+ *
+ * Keep in sync with src/lib.rs; a test checks that every export appears here.
+ */
+
+#ifndef PENSIEVE_H
+#define PENSIEVE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes. */
+#define PENSIEVE_OK 0                  /* The call succeeded. */
+#define PENSIEVE_NULL_POINTER (-1)     /* A required pointer was null. */
+#define PENSIEVE_INVALID_LENGTH (-2)   /* A buffer or key had the wrong length. */
+#define PENSIEVE_INVALID_PARAMETER (-3) /* A parameter was out of range. */
+#define PENSIEVE_BUFFER_TOO_SMALL (-4) /* The output buffer was too small. */
+
+/* Fixed buffer sizes. */
+#define PENSIEVE_KEY_LEN 32    /* Key written by pensieve_enroll / pensieve_derive_key. */
+#define PENSIEVE_HELPER_LEN 36 /* Helper data written by pensieve_enroll. */
+#define PENSIEVE_SALT_LEN 32   /* Randomness consumed by pensieve_enroll. */
+
+/* Opaque near-duplicate index over fixed-length keys with uint64_t values. */
+typedef struct PensieveIndex PensieveIndex;
+
+/*
+ * Collapses `input` with the constant-time collapse, writing `input_len`
+ * bytes to `out`. Returns PENSIEVE_BUFFER_TOO_SMALL (writing nothing) if
+ * `out_len < input_len`. `input` and `out` must not overlap.
+ */
+int32_t pensieve_collapse(const uint8_t *input, size_t input_len, float tolerance,
+                          uint8_t *out, size_t out_len);
+
+/* Similarity of `a` and `b` from 0.0 to 1.0; -1.0 if a required pointer is null. */
+float pensieve_similarity(const uint8_t *a, size_t a_len, const uint8_t *b, size_t b_len,
+                          float tolerance);
+
+/*
+ * Writes the number of bit positions at which `a` and `b` differ to
+ * `*distance_out`. Returns PENSIEVE_INVALID_LENGTH (writing nothing) if the
+ * buffers have different lengths.
+ */
+int32_t pensieve_hamming(const uint8_t *a, size_t a_len, const uint8_t *b, size_t b_len,
+                         uint32_t *distance_out);
+
+/*
+ * Enrolls a noisy `input`, writing PENSIEVE_KEY_LEN bytes of key to `key_out`
+ * and PENSIEVE_HELPER_LEN bytes of helper data (to store alongside the
+ * enrollment) to `helper_out`. `salt` must point to PENSIEVE_SALT_LEN fresh
+ * bytes from a cryptographically secure generator; reusing a salt links
+ * enrollments of the same source.
+ */
+int32_t pensieve_enroll(const uint8_t *input, size_t input_len, float tolerance,
+                        const uint8_t *salt, uint8_t *key_out, uint8_t *helper_out);
+
+/*
+ * Re-derives the key for a new reading of an enrolled source from the
+ * PENSIEVE_HELPER_LEN bytes of `helper` written by pensieve_enroll, writing
+ * PENSIEVE_KEY_LEN bytes to `key_out`.
+ */
+int32_t pensieve_derive_key(const uint8_t *input, size_t input_len, const uint8_t *helper,
+                            uint8_t *key_out);
+
+/*
+ * Allocates a zeroed buffer of `len` bytes owned by the caller until it is
+ * passed to pensieve_buffer_free with the same `len`.
+ */
+uint8_t *pensieve_buffer_alloc(size_t len);
+
+/* Releases a buffer from pensieve_buffer_alloc. Null is ignored. */
+void pensieve_buffer_free(uint8_t *buffer, size_t len);
+
+/* Creates an empty index; release it with pensieve_index_free. */
+PensieveIndex *pensieve_index_new(void);
+
+/* Releases an index. Null is ignored. */
+void pensieve_index_free(PensieveIndex *index);
+
+/* Number of entries in `index`, 0 for null. */
+size_t pensieve_index_len(const PensieveIndex *index);
+
+/*
+ * Stores `value` under `key`. All keys of an index must have the length of
+ * the first one (PENSIEVE_INVALID_LENGTH otherwise).
+ */
+int32_t pensieve_index_insert(PensieveIndex *index, const uint8_t *key, size_t key_len,
+                              uint64_t value);
+
+/*
+ * Finds the values stored under keys at most `max_distance` bits from
+ * `query`, closest first. `*count_out` is set to the number of matches. If it
+ * exceeds `values_capacity`, the closest `values_capacity` values are written
+ * and PENSIEVE_BUFFER_TOO_SMALL is returned, so the caller can retry with a
+ * larger buffer.
+ */
+int32_t pensieve_index_query(const PensieveIndex *index, const uint8_t *query,
+                             size_t query_len, uint32_t max_distance, uint64_t *values_out,
+                             size_t values_capacity, size_t *count_out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PENSIEVE_H */
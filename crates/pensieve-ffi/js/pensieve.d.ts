@@ -0,0 +1,35 @@
+// Type declarations for `pensieve.mjs`.
+
+/** A non-OK status code returned by the library. */
+export declare class PensieveError extends Error {
+  readonly status: number;
+  constructor(status: number);
+}
+
+export declare class Pensieve {
+  /** Wraps an already instantiated `pensieve_ffi.wasm`. */
+  constructor(instance: WebAssembly.Instance);
+
+  /**
+   * Instantiates the module from its bytes, a `Response` or a promise of
+   * either (such as the result of `fetch`).
+   */
+  static load(
+    source: BufferSource | Response | PromiseLike<BufferSource | Response>,
+  ): Promise<Pensieve>;
+
+  /** Collapses `input`; the result has the same length. */
+  collapse(input: Uint8Array, tolerance: number): Uint8Array;
+
+  /** How alike `a` and `b` are, from 0 to 1. */
+  similarity(a: Uint8Array, b: Uint8Array, tolerance: number): number;
+
+  /**
+   * Enrolls a noisy secret with a fresh salt from `crypto.getRandomValues`,
+   * returning the 32-byte key and the 36 bytes of helper data to store.
+   */
+  enroll(input: Uint8Array, tolerance: number): { key: Uint8Array; helper: Uint8Array };
+
+  /** Re-derives the key of an enrolled source from a new reading. */
+  deriveKey(input: Uint8Array, helper: Uint8Array): Uint8Array;
+}
@@ -0,0 +1,136 @@
+// JavaScript bindings over the WebAssembly build of `pensieve-ffi`.
+//
+//   cargo build -p pensieve-ffi --release --target wasm32-unknown-unknown
+//
+// produces `pensieve_ffi.wasm`, a module without imports exporting the C ABI
+// declared in `include/pensieve.h`. This wrapper copies `Uint8Array`s in and
+// out of the instance's memory so callers never handle pointers:
+//
+//   import { Pensieve } from "./pensieve.mjs";
+//   const pensieve = await Pensieve.load(fetch("pensieve_ffi.wasm"));
+//   const { key, helper } = pensieve.enroll(reading, 0.125);
+//   const again = pensieve.deriveKey(nextReading, helper);
+//
+// Secrets passed to `enroll`/`deriveKey` only ever live in this page's memory.
+
+const OK = 0;
+const KEY_LEN = 32;
+const HELPER_LEN = 36;
+const SALT_LEN = 32;
+
+const MESSAGES = {
+  [-1]: "null pointer",
+  [-2]: "invalid length",
+  [-3]: "invalid parameter",
+  [-4]: "buffer too small",
+};
+
+/** A non-OK status code returned by the library. */
+export class PensieveError extends Error {
+  constructor(status) {
+    super(`pensieve: ${MESSAGES[status] ?? `status ${status}`}`);
+    this.name = "PensieveError";
+    this.status = status;
+  }
+}
+
+export class Pensieve {
+  #exports;
+
+  /** Wraps an already instantiated `pensieve_ffi.wasm`. */
+  constructor(instance) {
+    this.#exports = instance.exports;
+  }
+
+  /**
+   * Instantiates the module from its bytes, a `Response` or a promise of
+   * either (such as the result of `fetch`).
+   */
+  static async load(source) {
+    const resolved = await source;
+    const { instance } =
+      typeof Response !== "undefined" && resolved instanceof Response
+        ? await WebAssembly.instantiateStreaming(resolved, {})
+        : await WebAssembly.instantiate(resolved, {});
+    return new Pensieve(instance);
+  }
+
+  /** Collapses `input`; the result has the same length. */
+  collapse(input, tolerance) {
+    return this.#withBuffers([input, input.length], ([inPtr, outPtr]) => {
+      this.#check(
+        this.#exports.pensieve_collapse(inPtr, input.length, tolerance, outPtr, input.length),
+      );
+      return this.#read(outPtr, input.length);
+    });
+  }
+
+  /** How alike `a` and `b` are, from 0 to 1. */
+  similarity(a, b, tolerance) {
+    return this.#withBuffers([a, b], ([aPtr, bPtr]) =>
+      this.#exports.pensieve_similarity(aPtr, a.length, bPtr, b.length, tolerance),
+    );
+  }
+
+  /**
+   * Enrolls a noisy secret with a fresh salt from `crypto.getRandomValues`,
+   * returning the key and the helper data to store.
+   */
+  enroll(input, tolerance) {
+    const salt = crypto.getRandomValues(new Uint8Array(SALT_LEN));
+    return this.#withBuffers(
+      [input, salt, KEY_LEN, HELPER_LEN],
+      ([inPtr, saltPtr, keyPtr, helperPtr]) => {
+        this.#check(
+          this.#exports.pensieve_enroll(inPtr, input.length, tolerance, saltPtr, keyPtr, helperPtr),
+        );
+        return { key: this.#read(keyPtr, KEY_LEN), helper: this.#read(helperPtr, HELPER_LEN) };
+      },
+    );
+  }
+
+  /** Re-derives the key of an enrolled source from a new reading. */
+  deriveKey(input, helper) {
+    if (helper.length !== HELPER_LEN) {
+      throw new PensieveError(-2);
+    }
+    return this.#withBuffers([input, helper, KEY_LEN], ([inPtr, helperPtr, keyPtr]) => {
+      this.#check(this.#exports.pensieve_derive_key(inPtr, input.length, helperPtr, keyPtr));
+      return this.#read(keyPtr, KEY_LEN);
+    });
+  }
+
+  // Allocates one buffer per spec (a byte array to copy in, or a length to
+  // leave zeroed), runs `body` on their addresses and frees them again. Key
+  // material is wiped before the memory is returned.
+  #withBuffers(specs, body) {
+    const e = this.#exports;
+    const lengths = specs.map((spec) => (typeof spec === "number" ? spec : spec.length));
+    const ptrs = lengths.map((len) => e.pensieve_buffer_alloc(len));
+    try {
+      // Views are made after all allocations, which may grow (and detach) memory.
+      specs.forEach((spec, i) => {
+        if (typeof spec !== "number") {
+          new Uint8Array(e.memory.buffer, ptrs[i], lengths[i]).set(spec);
+        }
+      });
+      return body(ptrs);
+    } finally {
+      ptrs.forEach((ptr, i) => {
+        new Uint8Array(e.memory.buffer, ptr, lengths[i]).fill(0);
+        e.pensieve_buffer_free(ptr, lengths[i]);
+      });
+    }
+  }
+
+  // Copies `len` bytes out of the instance (a fresh view, as calls may grow memory).
+  #read(ptr, len) {
+    return new Uint8Array(this.#exports.memory.buffer, ptr, len).slice();
+  }
+
+  #check(status) {
+    if (status !== OK) {
+      throw new PensieveError(status);
+    }
+  }
+}
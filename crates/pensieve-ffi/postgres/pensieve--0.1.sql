@@ -0,0 +1,31 @@
+-- Near-duplicate matching with Pensieve; see pensieve.c.
+
+\echo Use "CREATE EXTENSION pensieve" to load this file. \quit
+
+-- The collapse of `input` at `tolerance` (0.05 to 0.25, clamped).
+CREATE FUNCTION pensieve_collapse(input bytea, tolerance real)
+RETURNS bytea
+AS 'MODULE_PATHNAME', 'pg_pensieve_collapse'
+LANGUAGE C IMMUTABLE STRICT PARALLEL SAFE;
+
+-- How alike `a` and `b` are at `tolerance`, from 0.0 to 1.0; 1.0 iff they
+-- collapse to the same value.
+CREATE FUNCTION pensieve_similarity(a bytea, b bytea, tolerance real)
+RETURNS real
+AS 'MODULE_PATHNAME', 'pg_pensieve_similarity'
+LANGUAGE C IMMUTABLE STRICT PARALLEL SAFE;
+
+-- Number of differing bits of two values of the same length.
+CREATE FUNCTION pensieve_hamming(a bytea, b bytea)
+RETURNS integer
+AS 'MODULE_PATHNAME', 'pg_pensieve_hamming'
+LANGUAGE C IMMUTABLE STRICT PARALLEL SAFE;
+
+-- `a <~> b` is pensieve_hamming(a, b), e.g.
+--   SELECT id FROM photos WHERE phash <~> $1 <= 6;
+CREATE OPERATOR <~> (
+    LEFTARG = bytea,
+    RIGHTARG = bytea,
+    FUNCTION = pensieve_hamming,
+    COMMUTATOR = <~>
+);
@@ -0,0 +1,85 @@
+/*
+ * PostgreSQL functions over the Pensieve C ABI (include/pensieve.h).
+ *
+ * Every function is a thin wrapper: arguments are detoasted, handed to the
+ * static library and the result is copied into a palloc'd datum. Status
+ * codes other than PENSIEVE_OK become SQL errors.
+ */
+
+#include "postgres.h"
+
+#include "fmgr.h"
+
+#include "pensieve.h"
+
+PG_MODULE_MAGIC;
+
+/* Raises an SQL error for a non-OK status from the library. */
+static void
+check_status(int32_t status, const char *function)
+{
+	switch (status)
+	{
+		case PENSIEVE_OK:
+			return;
+		case PENSIEVE_INVALID_LENGTH:
+			ereport(ERROR,
+					(errcode(ERRCODE_INVALID_PARAMETER_VALUE),
+					 errmsg("%s: arguments must have the same length", function)));
+			break;
+		default:
+			ereport(ERROR,
+					(errcode(ERRCODE_INTERNAL_ERROR),
+					 errmsg("%s: pensieve status %d", function, status)));
+	}
+}
+
+PG_FUNCTION_INFO_V1(pg_pensieve_collapse);
+
+/* pensieve_collapse(input bytea, tolerance real) RETURNS bytea */
+Datum
+pg_pensieve_collapse(PG_FUNCTION_ARGS)
+{
+	bytea	   *input = PG_GETARG_BYTEA_PP(0);
+	float4		tolerance = PG_GETARG_FLOAT4(1);
+	size_t		len = VARSIZE_ANY_EXHDR(input);
+	bytea	   *result = (bytea *) palloc(VARHDRSZ + len);
+
+	SET_VARSIZE(result, VARHDRSZ + len);
+	check_status(pensieve_collapse((const uint8_t *) VARDATA_ANY(input), len, tolerance,
+								   (uint8_t *) VARDATA(result), len),
+				 "pensieve_collapse");
+	PG_RETURN_BYTEA_P(result);
+}
+
+PG_FUNCTION_INFO_V1(pg_pensieve_similarity);
+
+/* pensieve_similarity(a bytea, b bytea, tolerance real) RETURNS real */
+Datum
+pg_pensieve_similarity(PG_FUNCTION_ARGS)
+{
+	bytea	   *a = PG_GETARG_BYTEA_PP(0);
+	bytea	   *b = PG_GETARG_BYTEA_PP(1);
+	float4		tolerance = PG_GETARG_FLOAT4(2);
+
+	PG_RETURN_FLOAT4(pensieve_similarity((const uint8_t *) VARDATA_ANY(a), VARSIZE_ANY_EXHDR(a),
+										 (const uint8_t *) VARDATA_ANY(b), VARSIZE_ANY_EXHDR(b),
+										 tolerance));
+}
+
+PG_FUNCTION_INFO_V1(pg_pensieve_hamming);
+
+/* pensieve_hamming(a bytea, b bytea) RETURNS integer, also the <~> operator */
+Datum
+pg_pensieve_hamming(PG_FUNCTION_ARGS)
+{
+	bytea	   *a = PG_GETARG_BYTEA_PP(0);
+	bytea	   *b = PG_GETARG_BYTEA_PP(1);
+	uint32_t	distance = 0;
+
+	check_status(pensieve_hamming((const uint8_t *) VARDATA_ANY(a), VARSIZE_ANY_EXHDR(a),
+								  (const uint8_t *) VARDATA_ANY(b), VARSIZE_ANY_EXHDR(b),
+								  &distance),
+				 "pensieve_hamming");
+	PG_RETURN_INT32((int32) distance);
+}
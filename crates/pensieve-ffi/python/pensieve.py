@@ -0,0 +1,196 @@
+"""Python bindings over the `pensieve-ffi` shared library.
+
+Build the library with
+
+    cargo build -p pensieve-ffi --release
+
+and point ``PENSIEVE_FFI_LIB`` at ``target/release/libpensieve_ffi.so`` (or
+``.dylib`` / ``pensieve_ffi.dll``), or pass the path to :func:`load`.
+Without either, the system library search path is used.
+
+    >>> import pensieve
+    >>> pensieve.similarity(b"\\xff" * 16, b"\\xfe" + b"\\xff" * 15, 0.125)
+    1.0
+
+Only the standard library is needed; the C ABI is declared in
+``include/pensieve.h``.
+"""
+
+import ctypes
+import ctypes.util
+import os
+
+__all__ = [
+    "PensieveError",
+    "Index",
+    "collapse",
+    "similarity",
+    "enroll",
+    "derive_key",
+    "load",
+]
+
+OK = 0
+BUFFER_TOO_SMALL = -4
+KEY_LEN = 32
+HELPER_LEN = 36
+SALT_LEN = 32
+
+_MESSAGES = {
+    -1: "null pointer",
+    -2: "invalid length",
+    -3: "invalid parameter",
+    -4: "buffer too small",
+}
+
+_u8p = ctypes.POINTER(ctypes.c_uint8)
+_lib = None
+
+
+class PensieveError(Exception):
+    """A non-OK status code returned by the library."""
+
+    def __init__(self, status):
+        super().__init__(_MESSAGES.get(status, f"status {status}"))
+        self.status = status
+
+
+def load(path=None):
+    """Loads the shared library (once); later calls return the loaded one."""
+    global _lib
+    if _lib is not None:
+        return _lib
+    path = path or os.environ.get("PENSIEVE_FFI_LIB") or ctypes.util.find_library("pensieve_ffi")
+    if path is None:
+        raise OSError("pensieve_ffi library not found; set PENSIEVE_FFI_LIB")
+    lib = ctypes.CDLL(path)
+    size_t, i32 = ctypes.c_size_t, ctypes.c_int32
+    signatures = {
+        "pensieve_collapse": (i32, [_u8p, size_t, ctypes.c_float, _u8p, size_t]),
+        "pensieve_similarity": (ctypes.c_float, [_u8p, size_t, _u8p, size_t, ctypes.c_float]),
+        "pensieve_enroll": (i32, [_u8p, size_t, ctypes.c_float, _u8p, _u8p, _u8p]),
+        "pensieve_derive_key": (i32, [_u8p, size_t, _u8p, _u8p]),
+        "pensieve_index_new": (ctypes.c_void_p, []),
+        "pensieve_index_free": (None, [ctypes.c_void_p]),
+        "pensieve_index_len": (size_t, [ctypes.c_void_p]),
+        "pensieve_index_insert": (i32, [ctypes.c_void_p, _u8p, size_t, ctypes.c_uint64]),
+        "pensieve_index_query": (
+            i32,
+            [
+                ctypes.c_void_p,
+                _u8p,
+                size_t,
+                ctypes.c_uint32,
+                ctypes.POINTER(ctypes.c_uint64),
+                size_t,
+                ctypes.POINTER(size_t),
+            ],
+        ),
+    }
+    for name, (restype, argtypes) in signatures.items():
+        function = getattr(lib, name)
+        function.restype = restype
+        function.argtypes = argtypes
+    _lib = lib
+    return lib
+
+
+def _check(status):
+    if status != OK:
+        raise PensieveError(status)
+
+
+def _buffer(data):
+    """A C copy of ``data`` (bytes-like) and its length."""
+    data = bytes(data)
+    return (ctypes.c_uint8 * len(data)).from_buffer_copy(data), len(data)
+
+
+def collapse(data, tolerance):
+    """Collapses ``data``; the result has the same length."""
+    buffer, length = _buffer(data)
+    out = (ctypes.c_uint8 * length)()
+    _check(load().pensieve_collapse(buffer, length, tolerance, out, length))
+    return bytes(out)
+
+
+def similarity(a, b, tolerance):
+    """How alike ``a`` and ``b`` are, from 0.0 to 1.0."""
+    (a, a_len), (b, b_len) = _buffer(a), _buffer(b)
+    return load().pensieve_similarity(a, a_len, b, b_len, tolerance)
+
+
+def enroll(data, tolerance):
+    """Enrolls a noisy secret with a salt from ``os.urandom``; returns
+    ``(key, helper)`` where ``helper`` is the public data to store."""
+    buffer, length = _buffer(data)
+    salt, _ = _buffer(os.urandom(SALT_LEN))
+    key = (ctypes.c_uint8 * KEY_LEN)()
+    helper = (ctypes.c_uint8 * HELPER_LEN)()
+    _check(load().pensieve_enroll(buffer, length, tolerance, salt, key, helper))
+    return bytes(key), bytes(helper)
+
+
+def derive_key(data, helper):
+    """Re-derives the key of an enrolled source from a new reading."""
+    if len(helper) != HELPER_LEN:
+        raise PensieveError(-2)
+    buffer, length = _buffer(data)
+    helper, _ = _buffer(helper)
+    key = (ctypes.c_uint8 * KEY_LEN)()
+    _check(load().pensieve_derive_key(buffer, length, helper, key))
+    return bytes(key)
+
+
+class Index:
+    """Near-duplicate index over equal-length keys with integer values.
+
+    Use as a context manager, or call :meth:`close`, to release it promptly.
+    """
+
+    def __init__(self):
+        self._handle = load().pensieve_index_new()
+
+    def __len__(self):
+        return load().pensieve_index_len(self._live())
+
+    def insert(self, key, value):
+        """Stores ``value`` (0 to 2**64 - 1) under ``key``."""
+        buffer, length = _buffer(key)
+        _check(load().pensieve_index_insert(self._live(), buffer, length, value))
+
+    def query(self, key, max_distance):
+        """Values under keys at most ``max_distance`` bits from ``key``,
+        closest first."""
+        buffer, length = _buffer(key)
+        capacity, count = 16, ctypes.c_size_t()
+        while True:
+            values = (ctypes.c_uint64 * capacity)()
+            status = load().pensieve_index_query(
+                self._live(), buffer, length, max_distance, values, capacity, ctypes.byref(count)
+            )
+            if status != BUFFER_TOO_SMALL:
+                _check(status)
+                return list(values[: count.value])
+            capacity = count.value
+
+    def close(self):
+        """Releases the index; further use raises ``ValueError``."""
+        if self._handle is not None:
+            load().pensieve_index_free(self._handle)
+            self._handle = None
+
+    def _live(self):
+        if self._handle is None:
+            raise ValueError("index is closed")
+        return self._handle
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc):
+        self.close()
+
+    def __del__(self):
+        if getattr(self, "_handle", None) is not None and _lib is not None:
+            self.close()
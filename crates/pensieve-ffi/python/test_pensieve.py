@@ -0,0 +1,47 @@
+"""Tests for the Python bindings; run after building `pensieve-ffi` with
+
+    PENSIEVE_FFI_LIB=target/debug/libpensieve_ffi.so \
+        python3 -m unittest discover crates/pensieve-ffi/python
+"""
+
+import unittest
+
+import pensieve
+
+
+class PensieveTest(unittest.TestCase):
+    def test_collapse(self):
+        data = bytes([0xFF] + [0] * 15)
+        noisy = bytes([0xFE] + [0] * 15)
+        self.assertEqual(len(pensieve.collapse(data, 0.05)), 16)
+        self.assertEqual(pensieve.collapse(data, 0.05), pensieve.collapse(noisy, 0.05))
+        self.assertEqual(pensieve.collapse(b"", 0.05), b"")
+
+    def test_similarity(self):
+        self.assertEqual(pensieve.similarity(b"\xff" * 16, b"\xff" * 16, 0.1), 1.0)
+        self.assertLess(pensieve.similarity(b"\xff" * 16, b"\x00" * 16, 0.1), 0.5)
+
+    def test_enroll_and_derive_key(self):
+        data = bytes([0xFF] + [0] * 15)
+        key, helper = pensieve.enroll(data, 0.05)
+        self.assertEqual(pensieve.derive_key(bytes([0xFE] + [0] * 15), helper), key)
+        with self.assertRaises(pensieve.PensieveError):
+            pensieve.derive_key(data, helper[:-1])
+
+    def test_index(self):
+        with pensieve.Index() as index:
+            for value in range(40):
+                index.insert(bytes([value, 0]), value)
+            self.assertEqual(len(index), 40)
+            # Keys one bit away from [0, 0]: 1, 2, 4, 8, 16, 32.
+            self.assertEqual(sorted(index.query(b"\x00\x00", 1)), [0, 1, 2, 4, 8, 16, 32])
+            self.assertEqual(len(index.query(b"\x00\x00", 16)), 40)
+            with self.assertRaises(pensieve.PensieveError) as caught:
+                index.insert(b"\x00", 0)
+            self.assertEqual(caught.exception.status, -2)
+        with self.assertRaises(ValueError):
+            len(index)
+
+
+if __name__ == "__main__":
+    unittest.main()
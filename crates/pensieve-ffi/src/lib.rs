@@ -0,0 +1,537 @@
+//! C ABI over the Pensieve library.
+//!
+//! The declarations C callers compile against live in
+//! `include/pensieve.h`; a test keeps the header in sync with the exported
+//! symbols.
+//!
+//! # Ownership
+//! - Every buffer is owned by the caller. Inputs are only read during the
+//!   call, outputs are written into caller-provided memory of the documented
+//!   size, and no pointer is retained after a function returns.
+//! - A `PensieveIndex` is owned by the library: create it with
+//!   [`pensieve_index_new`] and release it exactly once with
+//!   [`pensieve_index_free`].
+//! - A null pointer is accepted for any input buffer whose length is 0.
+//! - Hosts that cannot allocate in the library's address space (a
+//!   WebAssembly instance's linear memory) get buffers from
+//!   [`pensieve_buffer_alloc`] and return them with [`pensieve_buffer_free`].
+//!
+//! # WebAssembly
+//! Built for `wasm32-unknown-unknown`, the same exports form a WebAssembly
+//! module without imports; `js/pensieve.mjs` wraps it for JavaScript and
+//! TypeScript with `Uint8Array` in and out.
+//!
+//! # Python
+//! `python/pensieve.py` loads the shared library with `ctypes` and exposes
+//! collapse, similarity, the fuzzy extractor and the index as plain Python
+//! functions and classes, with no build step beyond this crate.
+//!
+//! # PostgreSQL
+//! `postgres/` is a PGXS extension over the static library, adding
+//! `pensieve_collapse(bytea, real)`, `pensieve_similarity(bytea, bytea,
+//! real)` and a Hamming-distance operator `<~>` on `bytea` to SQL.
+//!
+//! Functions returning `int32_t` report one of the `PENSIEVE_*` status codes;
+//! outputs are only written on [`PENSIEVE_OK`] unless documented otherwise.
+
+use pensieve::fuzzy_extractor::{FuzzyExtractor, HelperData};
+use pensieve::index::BkTree;
+use pensieve::{Error, TbfConfig};
+
+/// The call succeeded.
+pub const PENSIEVE_OK: i32 = 0;
+/// A required pointer was null.
+pub const PENSIEVE_NULL_POINTER: i32 = -1;
+/// A buffer or key had the wrong length.
+pub const PENSIEVE_INVALID_LENGTH: i32 = -2;
+/// A parameter was out of range.
+pub const PENSIEVE_INVALID_PARAMETER: i32 = -3;
+/// The output buffer was too small; see the function for what was written.
+pub const PENSIEVE_BUFFER_TOO_SMALL: i32 = -4;
+
+/// Bytes in a key written by [`pensieve_enroll`] and [`pensieve_derive_key`].
+pub const PENSIEVE_KEY_LEN: usize = 32;
+/// Bytes in the helper data written by [`pensieve_enroll`].
+pub const PENSIEVE_HELPER_LEN: usize = HelperData::ENCODED_LEN;
+/// Bytes of caller-supplied randomness [`pensieve_enroll`] consumes.
+pub const PENSIEVE_SALT_LEN: usize = 32;
+
+/// Opaque near-duplicate index over fixed-length keys with `uint64_t` values.
+pub struct PensieveIndex(BkTree<u64>);
+
+/// Collapses `input` with the constant-time collapse, writing `input_len`
+/// bytes to `out`.
+///
+/// Returns [`PENSIEVE_BUFFER_TOO_SMALL`] (writing nothing) if
+/// `out_len < input_len`.
+///
+/// # Safety
+/// `input` must be valid for `input_len` reads and `out` for `out_len`
+/// writes; the two must not overlap.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pensieve_collapse(
+    input: *const u8,
+    input_len: usize,
+    tolerance: f32,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    let Some(input) = (unsafe { slice(input, input_len) }) else {
+        return PENSIEVE_NULL_POINTER;
+    };
+    let Some(out) = (unsafe { slice_mut(out, out_len) }) else {
+        return PENSIEVE_NULL_POINTER;
+    };
+    if out.len() < input.len() {
+        return PENSIEVE_BUFFER_TOO_SMALL;
+    }
+    out[..input.len()].copy_from_slice(&pensieve::collapse_ct(input, tolerance));
+    PENSIEVE_OK
+}
+
+/// Similarity of `a` and `b` from 0.0 to 1.0, see `pensieve::similarity`;
+/// -1.0 if a required pointer is null.
+///
+/// # Safety
+/// `a` must be valid for `a_len` reads and `b` for `b_len` reads.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pensieve_similarity(
+    a: *const u8,
+    a_len: usize,
+    b: *const u8,
+    b_len: usize,
+    tolerance: f32,
+) -> f32 {
+    match unsafe { (slice(a, a_len), slice(b, b_len)) } {
+        (Some(a), Some(b)) => pensieve::similarity(a, b, &TbfConfig::new(tolerance)),
+        _ => -1.0,
+    }
+}
+
+/// Writes the number of bit positions at which `a` and `b` differ to
+/// `*distance_out`.
+///
+/// Returns [`PENSIEVE_INVALID_LENGTH`] (writing nothing) if the buffers have
+/// different lengths.
+///
+/// # Safety
+/// `a` must be valid for `a_len` reads, `b` for `b_len` reads and
+/// `distance_out` for one write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pensieve_hamming(
+    a: *const u8,
+    a_len: usize,
+    b: *const u8,
+    b_len: usize,
+    distance_out: *mut u32,
+) -> i32 {
+    let (Some(a), Some(b)) = (unsafe { (slice(a, a_len), slice(b, b_len)) }) else {
+        return PENSIEVE_NULL_POINTER;
+    };
+    if distance_out.is_null() {
+        return PENSIEVE_NULL_POINTER;
+    }
+    if a.len() != b.len() {
+        return PENSIEVE_INVALID_LENGTH;
+    }
+    unsafe { *distance_out = pensieve::distance::hamming(a, b) };
+    PENSIEVE_OK
+}
+
+/// Enrolls a noisy `input`, writing the derived key and the helper data to
+/// store alongside the enrollment.
+///
+/// `salt` must be [`PENSIEVE_SALT_LEN`] fresh bytes from a cryptographically
+/// secure generator; reusing a salt links enrollments of the same source.
+///
+/// # Safety
+/// `input` must be valid for `input_len` reads, `salt` for
+/// [`PENSIEVE_SALT_LEN`] reads, `key_out` for [`PENSIEVE_KEY_LEN`] writes and
+/// `helper_out` for [`PENSIEVE_HELPER_LEN`] writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pensieve_enroll(
+    input: *const u8,
+    input_len: usize,
+    tolerance: f32,
+    salt: *const u8,
+    key_out: *mut u8,
+    helper_out: *mut u8,
+) -> i32 {
+    let (Some(input), Some(salt)) = (unsafe { slice(input, input_len) }, unsafe {
+        slice(salt, PENSIEVE_SALT_LEN)
+    }) else {
+        return PENSIEVE_NULL_POINTER;
+    };
+    let (Some(key_out), Some(helper_out)) =
+        (unsafe { slice_mut(key_out, PENSIEVE_KEY_LEN) }, unsafe {
+            slice_mut(helper_out, PENSIEVE_HELPER_LEN)
+        })
+    else {
+        return PENSIEVE_NULL_POINTER;
+    };
+
+    // The extractor draws exactly one salt from its entropy source.
+    let mut rng = |dest: &mut [u8]| dest.copy_from_slice(salt);
+    let (key, helper) = FuzzyExtractor::new(tolerance).generate(input, &mut rng);
+    key_out.copy_from_slice(key.as_bytes());
+    helper_out.copy_from_slice(&helper.to_bytes());
+    PENSIEVE_OK
+}
+
+/// Re-derives the key for a new reading of an enrolled source from the
+/// helper data written by [`pensieve_enroll`].
+///
+/// # Safety
+/// `input` must be valid for `input_len` reads, `helper` for
+/// [`PENSIEVE_HELPER_LEN`] reads and `key_out` for [`PENSIEVE_KEY_LEN`]
+/// writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pensieve_derive_key(
+    input: *const u8,
+    input_len: usize,
+    helper: *const u8,
+    key_out: *mut u8,
+) -> i32 {
+    let (Some(input), Some(helper)) = (unsafe { slice(input, input_len) }, unsafe {
+        slice(helper, PENSIEVE_HELPER_LEN)
+    }) else {
+        return PENSIEVE_NULL_POINTER;
+    };
+    let Some(key_out) = (unsafe { slice_mut(key_out, PENSIEVE_KEY_LEN) }) else {
+        return PENSIEVE_NULL_POINTER;
+    };
+    let helper = match HelperData::from_bytes(helper) {
+        Ok(helper) => helper,
+        Err(e) => return status(&e),
+    };
+    key_out.copy_from_slice(FuzzyExtractor::reproduce(input, &helper).as_bytes());
+    PENSIEVE_OK
+}
+
+/// Allocates a zeroed buffer of `len` bytes owned by the caller until it is
+/// passed to [`pensieve_buffer_free`] with the same `len`.
+#[unsafe(no_mangle)]
+pub extern "C" fn pensieve_buffer_alloc(len: usize) -> *mut u8 {
+    Box::into_raw(vec![0u8; len].into_boxed_slice()) as *mut u8
+}
+
+/// Releases a buffer from [`pensieve_buffer_alloc`]. Null is ignored.
+///
+/// # Safety
+/// `buffer` must be null or come from [`pensieve_buffer_alloc`] called with
+/// this `len`, and not have been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pensieve_buffer_free(buffer: *mut u8, len: usize) {
+    if !buffer.is_null() {
+        drop(unsafe { Box::from_raw(core::ptr::slice_from_raw_parts_mut(buffer, len)) });
+    }
+}
+
+/// Creates an empty index; release it with [`pensieve_index_free`].
+#[unsafe(no_mangle)]
+pub extern "C" fn pensieve_index_new() -> *mut PensieveIndex {
+    Box::into_raw(Box::new(PensieveIndex(BkTree::new())))
+}
+
+/// Releases an index. Null is ignored.
+///
+/// # Safety
+/// `index` must be null or come from [`pensieve_index_new`] and not have
+/// been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pensieve_index_free(index: *mut PensieveIndex) {
+    if !index.is_null() {
+        drop(unsafe { Box::from_raw(index) });
+    }
+}
+
+/// Number of entries in `index`, 0 for null.
+///
+/// # Safety
+/// `index` must be null or a live index.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pensieve_index_len(index: *const PensieveIndex) -> usize {
+    unsafe { index.as_ref() }.map_or(0, |index| index.0.len())
+}
+
+/// Stores `value` under `key`. All keys of an index must have the length of
+/// the first one ([`PENSIEVE_INVALID_LENGTH`] otherwise).
+///
+/// # Safety
+/// `index` must be a live index and `key` valid for `key_len` reads.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pensieve_index_insert(
+    index: *mut PensieveIndex,
+    key: *const u8,
+    key_len: usize,
+    value: u64,
+) -> i32 {
+    let (Some(index), Some(key)) = (unsafe { index.as_mut() }, unsafe { slice(key, key_len) })
+    else {
+        return PENSIEVE_NULL_POINTER;
+    };
+    match index.0.insert(key, value) {
+        Ok(()) => PENSIEVE_OK,
+        Err(e) => status(&e),
+    }
+}
+
+/// Finds the values stored under keys at most `max_distance` bits from
+/// `query`, closest first.
+///
+/// `*count_out` is set to the number of matches. If it exceeds
+/// `values_capacity`, the closest `values_capacity` values are written and
+/// [`PENSIEVE_BUFFER_TOO_SMALL`] is returned, so the caller can retry with a
+/// larger buffer.
+///
+/// # Safety
+/// `index` must be a live index, `query` valid for `query_len` reads,
+/// `values_out` for `values_capacity` writes of `uint64_t` and `count_out`
+/// for one write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pensieve_index_query(
+    index: *const PensieveIndex,
+    query: *const u8,
+    query_len: usize,
+    max_distance: u32,
+    values_out: *mut u64,
+    values_capacity: usize,
+    count_out: *mut usize,
+) -> i32 {
+    let (Some(index), Some(query)) = (unsafe { index.as_ref() }, unsafe {
+        slice(query, query_len)
+    }) else {
+        return PENSIEVE_NULL_POINTER;
+    };
+    let Some(count_out) = (unsafe { count_out.as_mut() }) else {
+        return PENSIEVE_NULL_POINTER;
+    };
+    if values_capacity > 0 && values_out.is_null() {
+        return PENSIEVE_NULL_POINTER;
+    }
+    let matches = match index.0.query_within(query, max_distance) {
+        Ok(matches) => matches,
+        Err(e) => return status(&e),
+    };
+    *count_out = matches.len();
+    let written = matches.len().min(values_capacity);
+    for (i, m) in matches.iter().take(written).enumerate() {
+        unsafe { values_out.add(i).write(*m.value) };
+    }
+    if written < matches.len() {
+        PENSIEVE_BUFFER_TOO_SMALL
+    } else {
+        PENSIEVE_OK
+    }
+}
+
+/// Maps a library error to a status code.
+fn status(error: &Error) -> i32 {
+    match error {
+        Error::InvalidLength { .. } | Error::InputTooShort { .. } => PENSIEVE_INVALID_LENGTH,
+        _ => PENSIEVE_INVALID_PARAMETER,
+    }
+}
+
+/// Borrows a caller buffer; `None` only for a null pointer with a non-zero length.
+unsafe fn slice<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        Some(&[])
+    } else if ptr.is_null() {
+        None
+    } else {
+        Some(unsafe { core::slice::from_raw_parts(ptr, len) })
+    }
+}
+
+/// Mutable counterpart of [`slice`].
+unsafe fn slice_mut<'a>(ptr: *mut u8, len: usize) -> Option<&'a mut [u8]> {
+    if len == 0 {
+        Some(&mut [])
+    } else if ptr.is_null() {
+        None
+    } else {
+        Some(unsafe { core::slice::from_raw_parts_mut(ptr, len) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::ptr;
+
+    #[test]
+    fn test_collapse_matches_library() {
+        let input = [0x5Au8; 16];
+        let mut out = [0u8; 20];
+        let status =
+            unsafe { pensieve_collapse(input.as_ptr(), input.len(), 0.125, out.as_mut_ptr(), 20) };
+        assert_eq!(status, PENSIEVE_OK);
+        assert_eq!(out[..16], pensieve::collapse_ct(&input, 0.125)[..]);
+        assert_eq!(
+            unsafe { pensieve_collapse(input.as_ptr(), 16, 0.125, out.as_mut_ptr(), 15) },
+            PENSIEVE_BUFFER_TOO_SMALL
+        );
+        assert_eq!(
+            unsafe { pensieve_collapse(ptr::null(), 16, 0.125, out.as_mut_ptr(), 20) },
+            PENSIEVE_NULL_POINTER
+        );
+        assert_eq!(
+            unsafe { pensieve_collapse(ptr::null(), 0, 0.125, ptr::null_mut(), 0) },
+            PENSIEVE_OK
+        );
+    }
+
+    #[test]
+    fn test_enroll_then_derive_key() {
+        let mut enrolled = [0u8; 16];
+        enrolled[0] = 0xFF;
+        let mut noisy = enrolled;
+        noisy[0] ^= 1;
+        let salt = [7u8; PENSIEVE_SALT_LEN];
+        let mut key = [0u8; PENSIEVE_KEY_LEN];
+        let mut helper = [0u8; PENSIEVE_HELPER_LEN];
+        let status = unsafe {
+            pensieve_enroll(
+                enrolled.as_ptr(),
+                16,
+                0.05,
+                salt.as_ptr(),
+                key.as_mut_ptr(),
+                helper.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, PENSIEVE_OK);
+        assert_eq!(helper[..32], salt);
+
+        let mut again = [0u8; PENSIEVE_KEY_LEN];
+        let status =
+            unsafe { pensieve_derive_key(noisy.as_ptr(), 16, helper.as_ptr(), again.as_mut_ptr()) };
+        assert_eq!(status, PENSIEVE_OK);
+        assert_eq!(again, key);
+    }
+
+    #[test]
+    fn test_similarity() {
+        let a = [0xFFu8; 16];
+        assert_eq!(
+            unsafe { pensieve_similarity(a.as_ptr(), 16, a.as_ptr(), 16, 0.1) },
+            1.0
+        );
+        assert_eq!(
+            unsafe { pensieve_similarity(ptr::null(), 16, a.as_ptr(), 16, 0.1) },
+            -1.0
+        );
+    }
+
+    #[test]
+    fn test_hamming() {
+        let mut distance = 0u32;
+        let status = unsafe {
+            pensieve_hamming([0xF0, 1].as_ptr(), 2, [0x0F, 1].as_ptr(), 2, &mut distance)
+        };
+        assert_eq!((status, distance), (PENSIEVE_OK, 8));
+        assert_eq!(
+            unsafe { pensieve_hamming([0].as_ptr(), 1, [0, 0].as_ptr(), 2, &mut distance) },
+            PENSIEVE_INVALID_LENGTH
+        );
+        assert_eq!(
+            unsafe { pensieve_hamming(ptr::null(), 0, ptr::null(), 0, ptr::null_mut()) },
+            PENSIEVE_NULL_POINTER
+        );
+    }
+
+    #[test]
+    fn test_index_lifecycle() {
+        let index = pensieve_index_new();
+        unsafe {
+            assert_eq!(
+                pensieve_index_insert(index, [0b0000_0000].as_ptr(), 1, 10),
+                PENSIEVE_OK
+            );
+            assert_eq!(
+                pensieve_index_insert(index, [0b0000_0001].as_ptr(), 1, 11),
+                PENSIEVE_OK
+            );
+            assert_eq!(
+                pensieve_index_insert(index, [0b1111_1111].as_ptr(), 1, 12),
+                PENSIEVE_OK
+            );
+            assert_eq!(
+                pensieve_index_insert(index, [0, 0].as_ptr(), 2, 13),
+                PENSIEVE_INVALID_LENGTH
+            );
+            assert_eq!(pensieve_index_len(index), 3);
+
+            let (mut values, mut count) = ([0u64; 1], 0usize);
+            let status = pensieve_index_query(
+                index,
+                [0].as_ptr(),
+                1,
+                1,
+                values.as_mut_ptr(),
+                1,
+                &mut count,
+            );
+            assert_eq!(
+                (status, count, values[0]),
+                (PENSIEVE_BUFFER_TOO_SMALL, 2, 10)
+            );
+
+            let mut values = [0u64; 4];
+            let status = pensieve_index_query(
+                index,
+                [0].as_ptr(),
+                1,
+                1,
+                values.as_mut_ptr(),
+                4,
+                &mut count,
+            );
+            assert_eq!((status, count), (PENSIEVE_OK, 2));
+            assert_eq!(values[..2], [10, 11]);
+
+            pensieve_index_free(index);
+            pensieve_index_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_buffer_lifecycle() {
+        for len in [0, 1, 100] {
+            let (input, out) = (pensieve_buffer_alloc(len), pensieve_buffer_alloc(len));
+            assert!(!input.is_null() && !out.is_null());
+            unsafe {
+                input.write_bytes(0xA5, len);
+                assert_eq!(pensieve_collapse(input, len, 0.1, out, len), PENSIEVE_OK);
+                let collapsed = core::slice::from_raw_parts(out, len);
+                assert_eq!(collapsed, pensieve::collapse_ct(&vec![0xA5; len], 0.1));
+                pensieve_buffer_free(input, len);
+                pensieve_buffer_free(out, len);
+            }
+        }
+        unsafe { pensieve_buffer_free(ptr::null_mut(), 3) };
+    }
+
+    #[test]
+    fn test_header_declares_every_export() {
+        let header = include_str!("../include/pensieve.h");
+        let source = include_str!("lib.rs");
+        let exports: Vec<&str> = source
+            .lines()
+            .filter_map(|line| {
+                let line = line.trim_start();
+                let rest = line
+                    .strip_prefix("pub unsafe extern \"C\" fn ")
+                    .or_else(|| line.strip_prefix("pub extern \"C\" fn "))
+                    .or_else(|| line.strip_prefix("pub const "))?;
+                rest.split(['(', ':']).next()
+            })
+            .collect();
+        assert!(exports.len() >= 19, "found only {exports:?}");
+        for name in exports {
+            assert!(header.contains(name), "`{name}` missing from pensieve.h");
+        }
+        assert_eq!(PENSIEVE_HELPER_LEN, 36);
+    }
+}
@@ -0,0 +1,252 @@
+//! The matching engine behind every transport: requests in, responses out.
+
+use pensieve::index::ShardedBkTree;
+use pensieve::{BitOrder, TbfConfig, similarity};
+
+/// Newest protocol revision this server speaks.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Parameters a client proposes in [`Request::Hello`] and the server
+/// answers with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    /// Protocol revision.
+    pub protocol: u8,
+    /// Collapse algorithm revision, see [`TbfConfig::ALGORITHM_VERSION`].
+    pub algorithm: u8,
+    /// Order of the bits within each byte.
+    pub bit_order: BitOrder,
+    /// Tolerated fraction of bit flips.
+    pub tolerance: f32,
+}
+
+/// One call of the service.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// Negotiates the session's parameters; optional, sessions start with
+    /// the server's defaults.
+    Hello(Params),
+    /// Collapses an input.
+    Collapse(Vec<u8>),
+    /// Scores how alike two inputs are.
+    Compare(Vec<u8>, Vec<u8>),
+    /// Stores `value` under the collapse of `input` in the shared index.
+    Insert {
+        /// Raw input; the index holds its collapse.
+        input: Vec<u8>,
+        /// Caller-chosen value returned by queries.
+        value: u64,
+    },
+    /// Finds indexed values whose digests are near the collapse of `input`.
+    Query {
+        /// Raw input.
+        input: Vec<u8>,
+        /// Query radius in bits.
+        max_distance: u32,
+    },
+}
+
+/// The answer to one [`Request`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// The parameters now in effect for the session.
+    Hello(Params),
+    /// The collapsed digest.
+    Digest(Vec<u8>),
+    /// Similarity from 0.0 to 1.0; 1.0 iff the inputs collapse alike.
+    Similarity(f32),
+    /// The value was stored.
+    Inserted,
+    /// `(value, distance)` of every match, closest first.
+    Matches(Vec<(u64, u32)>),
+    /// The request was rejected; the session continues.
+    Error(String),
+}
+
+/// Shards of the index; sessions insert into different shards at once.
+const INDEX_SHARDS: usize = 16;
+
+/// State shared by every session: the near-duplicate index.
+#[derive(Debug)]
+pub struct Engine {
+    index: ShardedBkTree<u64>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self {
+            index: ShardedBkTree::new(INDEX_SHARDS).expect("at least one shard"),
+        }
+    }
+}
+
+impl Engine {
+    /// An engine with an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of indexed digests.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Whether nothing is indexed yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Answers `request` for a session currently using `config`, which a
+    /// successful [`Request::Hello`] replaces.
+    pub fn handle(&self, config: &mut TbfConfig, request: Request) -> Response {
+        match request {
+            Request::Hello(proposed) => match negotiate(proposed) {
+                Ok(accepted) => {
+                    *config = TbfConfig::new(accepted.tolerance).with_bit_order(accepted.bit_order);
+                    Response::Hello(accepted)
+                }
+                Err(message) => Response::Error(message),
+            },
+            Request::Collapse(input) => Response::Digest(config.collapse(&input)),
+            Request::Compare(a, b) => Response::Similarity(similarity(&a, &b, config)),
+            Request::Insert { input, value } => {
+                match self.index.insert(&config.collapse(&input), value) {
+                    Ok(()) => Response::Inserted,
+                    Err(_) => Response::Error(key_len_error(&self.index)),
+                }
+            }
+            Request::Query {
+                input,
+                max_distance,
+            } => match self
+                .index
+                .query_within(&config.collapse(&input), max_distance)
+            {
+                Ok(matches) => Response::Matches(matches),
+                Err(_) => Response::Error(key_len_error(&self.index)),
+            },
+        }
+    }
+}
+
+/// The parameters the server accepts for a `proposed` session: the older of
+/// the two protocol revisions, and otherwise exactly what was proposed.
+fn negotiate(proposed: Params) -> Result<Params, String> {
+    if proposed.protocol == 0 {
+        return Err("protocol version 0 does not exist".to_string());
+    }
+    if proposed.algorithm != TbfConfig::ALGORITHM_VERSION {
+        return Err(format!(
+            "algorithm version {} is not supported, this server runs {}",
+            proposed.algorithm,
+            TbfConfig::ALGORITHM_VERSION
+        ));
+    }
+    if !(TbfConfig::MIN_TOLERANCE..=TbfConfig::MAX_TOLERANCE).contains(&proposed.tolerance) {
+        return Err(format!(
+            "tolerance {} is outside 0.05 to 0.25",
+            proposed.tolerance
+        ));
+    }
+    Ok(Params {
+        protocol: proposed.protocol.min(PROTOCOL_VERSION),
+        ..proposed
+    })
+}
+
+fn key_len_error(index: &ShardedBkTree<u64>) -> String {
+    format!(
+        "every indexed input must be {} bytes long",
+        index.key_len().unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(noise: u8) -> Vec<u8> {
+        let mut bytes = vec![0u8; 16];
+        bytes[..2].fill(0xFF);
+        bytes[0] ^= noise;
+        bytes
+    }
+
+    #[test]
+    fn test_requests() {
+        let engine = Engine::new();
+        let mut config = TbfConfig::default();
+        assert_eq!(
+            engine.handle(&mut config, Request::Collapse(reading(0))),
+            Response::Digest(TbfConfig::default().collapse(&reading(0)))
+        );
+        assert_eq!(
+            engine.handle(&mut config, Request::Compare(reading(0), reading(1))),
+            Response::Similarity(1.0)
+        );
+        for (value, input) in [(7, reading(0)), (8, vec![0; 16])] {
+            let insert = Request::Insert { input, value };
+            assert_eq!(engine.handle(&mut config, insert), Response::Inserted);
+        }
+        let query = Request::Query {
+            input: reading(1),
+            max_distance: 0,
+        };
+        assert_eq!(
+            engine.handle(&mut config, query),
+            Response::Matches(vec![(7, 0)])
+        );
+        let short = Request::Insert {
+            input: vec![1],
+            value: 9,
+        };
+        assert_eq!(
+            engine.handle(&mut config, short),
+            Response::Error("every indexed input must be 16 bytes long".to_string())
+        );
+        assert_eq!(engine.len(), 2);
+    }
+
+    #[test]
+    fn test_negotiation() {
+        let engine = Engine::new();
+        let mut config = TbfConfig::default();
+        let proposed = Params {
+            protocol: 9,
+            algorithm: TbfConfig::ALGORITHM_VERSION,
+            bit_order: BitOrder::LsbFirst,
+            tolerance: 0.05,
+        };
+        assert_eq!(
+            engine.handle(&mut config, Request::Hello(proposed)),
+            Response::Hello(Params {
+                protocol: PROTOCOL_VERSION,
+                ..proposed
+            })
+        );
+        assert_eq!(
+            config,
+            TbfConfig::new(0.05).with_bit_order(BitOrder::LsbFirst)
+        );
+
+        for rejected in [
+            Params {
+                algorithm: 1,
+                ..proposed
+            },
+            Params {
+                tolerance: 0.5,
+                ..proposed
+            },
+            Params {
+                protocol: 0,
+                ..proposed
+            },
+        ] {
+            let response = engine.handle(&mut config, Request::Hello(rejected));
+            assert!(matches!(response, Response::Error(_)), "{rejected:?}");
+        }
+        // Rejected proposals leave the session as it was.
+        assert_eq!(config.tolerance(), 0.05);
+    }
+}
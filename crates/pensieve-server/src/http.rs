@@ -0,0 +1,412 @@
+//! A minimal HTTP/JSON front-end to the same [`Engine`] for quick
+//! integrations.
+//!
+//! Every endpoint takes a `POST` whose body is a flat JSON object and
+//! answers with one. Inputs are base64 (RFC 4648, padding optional) and
+//! digests lowercase hex.
+//!
+//! | Endpoint | Request | Response |
+//! |----------|---------|----------|
+//! | `/collapse` | `{"input": b64}` | `{"digest": hex}` |
+//! | `/compare` | `{"a": b64, "b": b64}` | `{"similarity": score}` |
+//! | `/index/insert` | `{"input": b64, "value": u64}` | `{"inserted": true}` |
+//! | `/index/query` | `{"input": b64, "max_distance": u32}` | `{"matches": [{"value": u64, "distance": u32}, ...]}` |
+//!
+//! Failures answer `{"error": message}` with status 400, 404 or 405. HTTP
+//! calls are stateless, so there is no Hello: every call runs with the
+//! server's configuration. Each connection carries one request.
+
+use crate::engine::{Engine, Request, Response};
+use pensieve::TbfConfig;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Read, Write};
+
+/// Largest accepted request body, in bytes.
+pub const MAX_BODY: usize = 16 << 20;
+
+/// Longest accepted request or header line, in bytes.
+const MAX_LINE: u64 = 8 << 10;
+
+/// Reads one request from `reader`, answers it on `writer` and flushes.
+pub fn serve(
+    engine: &Engine,
+    config: TbfConfig,
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    let (status, body) = match read_request(&mut reader)? {
+        Ok(call) => respond(engine, config, &call.method, &call.path, &call.body),
+        Err(message) => (400, error(&message)),
+    };
+    let reason = match status {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Bad Request",
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    writer.flush()
+}
+
+/// The status and JSON body answering `method` on `path`.
+pub fn respond(
+    engine: &Engine,
+    mut config: TbfConfig,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> (u16, String) {
+    if !matches!(
+        path,
+        "/collapse" | "/compare" | "/index/insert" | "/index/query"
+    ) {
+        return (404, error(&format!("no endpoint `{path}`")));
+    }
+    if method != "POST" {
+        return (405, error("every endpoint takes a POST"));
+    }
+    let request = match parse_request(path, body) {
+        Ok(request) => request,
+        Err(message) => return (400, error(&message)),
+    };
+    match engine.handle(&mut config, request) {
+        Response::Digest(digest) => (200, format!("{{\"digest\":\"{}\"}}", hex(&digest))),
+        Response::Similarity(score) => (200, format!("{{\"similarity\":{score}}}")),
+        Response::Inserted => (200, "{\"inserted\":true}".to_string()),
+        Response::Matches(matches) => {
+            let mut out = "{\"matches\":[".to_string();
+            for (i, (value, distance)) in matches.iter().enumerate() {
+                let comma = if i == 0 { "" } else { "," };
+                let _ = write!(out, "{comma}{{\"value\":{value},\"distance\":{distance}}}");
+            }
+            out.push_str("]}");
+            (200, out)
+        }
+        Response::Error(message) => (400, error(&message)),
+        Response::Hello(_) => unreachable!("no endpoint negotiates"),
+    }
+}
+
+/// The parts of an HTTP request the endpoints look at.
+struct Call {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// The next request, or why it is malformed.
+fn read_request(reader: &mut impl BufRead) -> io::Result<Result<Call, String>> {
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(path), Some(_version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Ok(Err("malformed request line".to_string()));
+    };
+    let mut content_length = 0;
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Ok(Err("malformed header".to_string()));
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            match value.trim().parse() {
+                Ok(len) if len <= MAX_BODY => content_length = len,
+                _ => return Ok(Err("invalid or oversized Content-Length".to_string())),
+            }
+        }
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Ok(Call {
+        method: method.to_string(),
+        path: path.to_string(),
+        body,
+    }))
+}
+
+/// One CRLF- or LF-terminated line, without the terminator.
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    reader.take(MAX_LINE).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "truncated or overlong HTTP line",
+        ));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// A field value of a request body.
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Number(u64),
+}
+
+/// The engine request for a call of `path` with a JSON `body`.
+fn parse_request(path: &str, body: &[u8]) -> Result<Request, String> {
+    let fields = parse_object(body)?;
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| format!("missing field `{name}`"))
+    };
+    let bytes = |name: &str| match field(name)? {
+        Value::String(text) => unbase64(text).map_err(|e| format!("field `{name}`: {e}")),
+        Value::Number(_) => Err(format!("field `{name}` must be a base64 string")),
+    };
+    let number = |name: &str| match field(name)? {
+        Value::Number(n) => Ok(*n),
+        Value::String(_) => Err(format!("field `{name}` must be a number")),
+    };
+    Ok(match path {
+        "/collapse" => Request::Collapse(bytes("input")?),
+        "/compare" => Request::Compare(bytes("a")?, bytes("b")?),
+        "/index/insert" => Request::Insert {
+            input: bytes("input")?,
+            value: number("value")?,
+        },
+        _ => Request::Query {
+            input: bytes("input")?,
+            max_distance: u32::try_from(number("max_distance")?)
+                .map_err(|_| "field `max_distance` is out of range".to_string())?,
+        },
+    })
+}
+
+/// The fields of a flat JSON object whose values are strings or
+/// non-negative integers.
+fn parse_object(body: &[u8]) -> Result<Vec<(String, Value)>, String> {
+    let text = std::str::from_utf8(body).map_err(|_| "body is not UTF-8")?;
+    let mut rest = text
+        .trim()
+        .strip_prefix('{')
+        .and_then(|inner| inner.strip_suffix('}'))
+        .ok_or("body is not a JSON object")?
+        .trim_start();
+    let mut fields = Vec::new();
+    while !rest.is_empty() {
+        let (key, after) = parse_string(rest)?;
+        let after = after
+            .trim_start()
+            .strip_prefix(':')
+            .ok_or("expected `:` after a key")?
+            .trim_start();
+        let (value, after) = if after.starts_with('"') {
+            let (text, after) = parse_string(after)?;
+            (Value::String(text), after)
+        } else {
+            let end = after
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after.len());
+            let number = after[..end]
+                .parse()
+                .map_err(|_| format!("field `{key}` must be a string or a non-negative integer"))?;
+            (Value::Number(number), &after[end..])
+        };
+        fields.push((key, value));
+        let after = after.trim_start();
+        rest = match after.strip_prefix(',') {
+            Some(next) if !next.trim().is_empty() => next.trim_start(),
+            None if after.is_empty() => after,
+            _ => return Err("expected `,` between fields".to_string()),
+        };
+    }
+    Ok(fields)
+}
+
+/// A JSON string at the start of `text` and what follows it. Only the
+/// escapes base64 and field names can need are supported.
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let body = text.strip_prefix('"').ok_or("expected a string")?;
+    let mut out = String::new();
+    let mut chars = body.char_indices();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '"' => return Ok((out, &body[offset + 1..])),
+            '\\' => match chars.next() {
+                Some((_, e @ ('"' | '\\' | '/'))) => out.push(e),
+                _ => return Err("unsupported escape in string".to_string()),
+            },
+            c => out.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+/// Decodes standard base64, with or without padding.
+fn unbase64(text: &str) -> Result<Vec<u8>, String> {
+    let digits = text.trim_end_matches('=');
+    let mut out = Vec::with_capacity(digits.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for c in digits.bytes() {
+        let digit = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(format!("invalid base64 character `{}`", c as char)),
+        };
+        acc = (acc << 6 | u32::from(digit)) & 0xFFFF;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 6 {
+        return Err("truncated base64".to_string());
+    }
+    Ok(out)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{b:02x}");
+        out
+    })
+}
+
+/// An error body carrying `message`.
+fn error(message: &str) -> String {
+    let escaped = message.chars().fold(String::new(), |mut out, c| {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+        out
+    });
+    format!("{{\"error\":\"{escaped}\"}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Base64 of 0xFF followed by fifteen zero bytes, and of the same with
+    // the lowest bit of the first byte flipped.
+    const READING: &str = "/wAAAAAAAAAAAAAAAAAAAA==";
+    const NOISY: &str = "/gAAAAAAAAAAAAAAAAAAAA";
+
+    fn post(engine: &Engine, path: &str, body: &str) -> (u16, String) {
+        respond(engine, TbfConfig::default(), "POST", path, body.as_bytes())
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let engine = Engine::new();
+        let mut reading = vec![0u8; 16];
+        reading[0] = 0xFF;
+        let digest = hex(&TbfConfig::default().collapse(&reading));
+        assert_eq!(
+            post(
+                &engine,
+                "/collapse",
+                &format!(r#"{{"input": "{READING}"}}"#)
+            ),
+            (200, format!(r#"{{"digest":"{digest}"}}"#))
+        );
+        assert_eq!(
+            post(
+                &engine,
+                "/compare",
+                &format!(r#"{{"a":"{READING}","b":"{NOISY}"}}"#)
+            ),
+            (200, r#"{"similarity":1}"#.to_string())
+        );
+        assert_eq!(
+            post(
+                &engine,
+                "/index/insert",
+                &format!(r#"{{"input":"{READING}","value":42}}"#)
+            ),
+            (200, r#"{"inserted":true}"#.to_string())
+        );
+        assert_eq!(
+            post(
+                &engine,
+                "/index/query",
+                &format!(r#"{{"max_distance":0,"input":"{NOISY}"}}"#)
+            ),
+            (
+                200,
+                r#"{"matches":[{"value":42,"distance":0}]}"#.to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_rejections() {
+        let engine = Engine::new();
+        let status = |method: &str, path: &str, body: &str| {
+            respond(&engine, TbfConfig::default(), method, path, body.as_bytes()).0
+        };
+        assert_eq!(status("POST", "/nope", "{}"), 404);
+        assert_eq!(status("GET", "/collapse", ""), 405);
+        assert_eq!(status("POST", "/collapse", "{}"), 400);
+        assert_eq!(status("POST", "/collapse", r#"{"input":"@@"}"#), 400);
+        assert_eq!(status("POST", "/collapse", r#"{"input":1}"#), 400);
+        assert_eq!(status("POST", "/collapse", r#"{"input":"AA",}"#), 400);
+        assert_eq!(status("POST", "/collapse", "[]"), 400);
+        let query = r#"{"input":"AA","max_distance":4294967296}"#;
+        assert_eq!(status("POST", "/index/query", query), 400);
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(unbase64(""), Ok(Vec::new()));
+        assert_eq!(unbase64("Zg=="), Ok(b"f".to_vec()));
+        assert_eq!(unbase64("Zm8"), Ok(b"fo".to_vec()));
+        assert_eq!(unbase64("Zm9vYmFy"), Ok(b"foobar".to_vec()));
+        assert!(unbase64("Zm9vY").is_err());
+        assert!(unbase64("Zm-v").is_err());
+    }
+
+    #[test]
+    fn test_serve() {
+        let engine = Engine::new();
+        let body = format!(r#"{{"input":"{READING}"}}"#);
+        let request = format!(
+            "POST /collapse HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let mut output = Vec::new();
+        serve(
+            &engine,
+            TbfConfig::default(),
+            request.as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"), "{output}");
+        assert!(output.ends_with("\"}"), "{output}");
+
+        let mut output = Vec::new();
+        serve(
+            &engine,
+            TbfConfig::default(),
+            &b"nonsense\r\n\r\n"[..],
+            &mut output,
+        )
+        .unwrap();
+        assert!(output.starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+    }
+}
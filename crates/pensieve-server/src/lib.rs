@@ -0,0 +1,16 @@
+//! `pensieve-server`: the Pensieve matcher as a network service.
+//!
+//! Clients open a TCP connection and stream framed requests (see [`wire`]):
+//! Collapse, Compare, Index Insert and Index Query, optionally preceded by
+//! a Hello negotiating the protocol revision, tolerance and bit order. All
+//! connections share one in-memory index of collapsed inputs, held by the
+//! [`engine`]. With the `http` feature the same engine is also served over
+//! HTTP/JSON, see `http`.
+//!
+//! The library half exists so Rust clients can reuse the codec; the
+//! `pensieve-server` binary is the server itself.
+
+pub mod engine;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod wire;
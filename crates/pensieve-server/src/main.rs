@@ -0,0 +1,256 @@
+//! The `pensieve-server` binary: accepts connections and answers each on
+//! its own thread, see the library docs for the protocol.
+
+use pensieve::TbfConfig;
+use pensieve_server::engine::{Engine, Response};
+use pensieve_server::wire;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+const USAGE: &str = "\
+usage: pensieve-server [options]
+
+options:
+  --listen <addr>     address to accept connections on
+                      (default 127.0.0.1:7878)
+  --http <addr>       also serve HTTP/JSON on this address (needs the
+                      `http` feature)
+  --tolerance <t>     tolerance of sessions that send no Hello, 0.05 to
+                      0.25 (default 0.125)
+  -h, --help          print this help
+";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match Options::parse(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("pensieve-server: {message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let engine = Arc::new(Engine::new());
+    #[cfg(feature = "http")]
+    if let Some(address) = &options.http {
+        let Some(listener) = bind(address) else {
+            return ExitCode::FAILURE;
+        };
+        let (engine, config) = (Arc::clone(&engine), options.config);
+        std::thread::spawn(move || {
+            accept(listener, &engine, config, |engine, config, stream| {
+                let reader = BufReader::new(stream.try_clone()?);
+                pensieve_server::http::serve(engine, config, reader, stream)
+            })
+        });
+    }
+    let Some(listener) = bind(&options.listen) else {
+        return ExitCode::FAILURE;
+    };
+    accept(
+        listener,
+        &engine,
+        options.config,
+        |engine, config, stream| {
+            let reader = BufReader::new(stream.try_clone()?);
+            serve(engine, config, reader, BufWriter::new(stream))
+        },
+    );
+    ExitCode::SUCCESS
+}
+
+/// A listener on `address`, or `None` after reporting why there is none.
+fn bind(address: &str) -> Option<TcpListener> {
+    match TcpListener::bind(address) {
+        Ok(listener) => {
+            eprintln!("pensieve-server: listening on {address}");
+            Some(listener)
+        }
+        Err(e) => {
+            eprintln!("pensieve-server: {address}: {e}");
+            None
+        }
+    }
+}
+
+/// Hands every connection on `listener` to `handle` on a thread of its own.
+fn accept(
+    listener: TcpListener,
+    engine: &Arc<Engine>,
+    config: TbfConfig,
+    handle: fn(&Engine, TbfConfig, TcpStream) -> io::Result<()>,
+) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let engine = Arc::clone(engine);
+        std::thread::spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_default();
+            if let Err(e) = handle(&engine, config, stream) {
+                eprintln!("pensieve-server: {peer}: {e}");
+            }
+        });
+    }
+}
+
+/// Command-line settings.
+#[derive(Debug, PartialEq)]
+struct Options {
+    listen: String,
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    http: Option<String>,
+    config: TbfConfig,
+}
+
+impl Options {
+    /// The options in `args`, or `None` if help was requested.
+    fn parse(args: &[String]) -> Result<Option<Self>, String> {
+        let mut options = Self {
+            listen: "127.0.0.1:7878".to_string(),
+            http: None,
+            config: TbfConfig::default(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--listen" => options.listen = value()?.clone(),
+                "--http" if cfg!(feature = "http") => options.http = Some(value()?.clone()),
+                "--http" => return Err("--http needs the `http` feature".to_string()),
+                "--tolerance" => {
+                    let value = value()?;
+                    let tolerance: f32 = value
+                        .parse()
+                        .map_err(|_| format!("invalid tolerance `{value}`"))?;
+                    if !(TbfConfig::MIN_TOLERANCE..=TbfConfig::MAX_TOLERANCE).contains(&tolerance) {
+                        return Err(format!("tolerance {value} is outside 0.05 to 0.25"));
+                    }
+                    options.config = TbfConfig::new(tolerance);
+                }
+                _ => return Err(format!("unknown argument `{arg}`")),
+            }
+        }
+        Ok(Some(options))
+    }
+}
+
+/// Answers the requests read from one connection until it closes.
+///
+/// Responses are flushed whenever no further request is already buffered,
+/// so streamed requests are answered in batches.
+fn serve(
+    engine: &Engine,
+    mut config: TbfConfig,
+    mut reader: BufReader<impl Read>,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    while let Some(payload) = wire::read_frame(&mut reader)? {
+        let response = match wire::decode_request(&payload) {
+            Ok(request) => engine.handle(&mut config, request),
+            Err(message) => Response::Error(message),
+        };
+        wire::write_frame(&mut writer, &wire::encode_response(&response))?;
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pensieve::BitOrder;
+    use pensieve_server::engine::{Params, Request};
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn frames(requests: &[Request]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for request in requests {
+            wire::write_frame(&mut out, &wire::encode_request(request)).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_streamed_session() {
+        let engine = Engine::new();
+        let reading = |noise: u8| {
+            let mut bytes = vec![0u8; 16];
+            bytes[0] = 0xFF ^ noise;
+            bytes
+        };
+        let hello = Params {
+            protocol: 1,
+            algorithm: TbfConfig::ALGORITHM_VERSION,
+            bit_order: BitOrder::MsbFirst,
+            tolerance: 0.125,
+        };
+        let mut input = frames(&[
+            Request::Hello(hello),
+            Request::Insert {
+                input: reading(0),
+                value: 42,
+            },
+            Request::Query {
+                input: reading(1),
+                max_distance: 0,
+            },
+        ]);
+        wire::write_frame(&mut input, &[0x7F]).unwrap();
+        let mut output = Vec::new();
+        serve(
+            &engine,
+            TbfConfig::default(),
+            BufReader::new(input.as_slice()),
+            &mut output,
+        )
+        .unwrap();
+
+        let mut responses = output.as_slice();
+        let mut next = || {
+            let payload = wire::read_frame(&mut responses).unwrap().unwrap();
+            wire::decode_response(&payload).unwrap()
+        };
+        assert_eq!(next(), Response::Hello(hello));
+        assert_eq!(next(), Response::Inserted);
+        assert_eq!(next(), Response::Matches(vec![(42, 0)]));
+        assert!(matches!(next(), Response::Error(_)));
+        assert!(responses.is_empty());
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(
+            Options::parse(&args(&["--listen", "0.0.0.0:9000", "--tolerance", "0.2"])),
+            Ok(Some(Options {
+                listen: "0.0.0.0:9000".to_string(),
+                http: None,
+                config: TbfConfig::new(0.2),
+            }))
+        );
+        assert_eq!(Options::parse(&args(&["-h"])), Ok(None));
+        assert_eq!(
+            Options::parse(&args(&["--http", "127.0.0.1:8080"])).map(|o| o.unwrap().http),
+            if cfg!(feature = "http") {
+                Ok(Some("127.0.0.1:8080".to_string()))
+            } else {
+                Err("--http needs the `http` feature".to_string())
+            }
+        );
+        assert!(Options::parse(&args(&["--tolerance", "0.9"])).is_err());
+        assert!(Options::parse(&args(&["--listen"])).is_err());
+        assert!(Options::parse(&args(&["serve"])).is_err());
+    }
+}
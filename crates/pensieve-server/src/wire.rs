@@ -0,0 +1,292 @@
+//! The binary framing of requests and responses on a connection.
+//!
+//! A connection carries a stream of frames in each direction: a
+//! little-endian `u32` payload length, then the payload. The server answers
+//! every request frame with exactly one response frame, in order, so a
+//! client may stream any number of requests without waiting for answers.
+//!
+//! A payload starts with a tag byte; integers and floats are little-endian.
+//!
+//! | Tag | Request | Body |
+//! |-----|---------|------|
+//! | 1 | Hello | protocol (u8), algorithm (u8), bit order id (u8), tolerance (f32) |
+//! | 2 | Collapse | input |
+//! | 3 | Compare | length of a (u32), a, b |
+//! | 4 | Insert | value (u64), input |
+//! | 5 | Query | max distance (u32), input |
+//!
+//! | Tag | Response | Body |
+//! |-----|----------|------|
+//! | 0x81 | Hello | as the request |
+//! | 0x82 | Digest | digest |
+//! | 0x83 | Similarity | score (f32) |
+//! | 0x84 | Inserted | empty |
+//! | 0x85 | Matches | count (u32), then value (u64) and distance (u32) each |
+//! | 0xFF | Error | UTF-8 message |
+//!
+//! A malformed payload gets an Error response; a frame longer than
+//! [`MAX_FRAME`] ends the connection.
+
+use crate::engine::{Params, Request, Response};
+use pensieve::BitOrder;
+use std::io::{self, Read, Write};
+
+/// Largest accepted payload, in bytes.
+pub const MAX_FRAME: usize = 64 << 20;
+
+/// Reads one frame's payload; `None` at a clean end of stream.
+pub fn read_frame(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {len} bytes exceeds the limit of {MAX_FRAME}"),
+        ));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+/// Writes `payload` as one frame.
+pub fn write_frame(writer: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|&len| len as usize <= MAX_FRAME)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(payload)
+}
+
+/// Serializes a request payload.
+pub fn encode_request(request: &Request) -> Vec<u8> {
+    let mut out = Vec::new();
+    match request {
+        Request::Hello(params) => {
+            out.push(1);
+            put_params(&mut out, params);
+        }
+        Request::Collapse(input) => {
+            out.push(2);
+            out.extend_from_slice(input);
+        }
+        Request::Compare(a, b) => {
+            out.push(3);
+            out.extend_from_slice(&(a.len() as u32).to_le_bytes());
+            out.extend_from_slice(a);
+            out.extend_from_slice(b);
+        }
+        Request::Insert { input, value } => {
+            out.push(4);
+            out.extend_from_slice(&value.to_le_bytes());
+            out.extend_from_slice(input);
+        }
+        Request::Query {
+            input,
+            max_distance,
+        } => {
+            out.push(5);
+            out.extend_from_slice(&max_distance.to_le_bytes());
+            out.extend_from_slice(input);
+        }
+    }
+    out
+}
+
+/// Parses a request payload.
+pub fn decode_request(payload: &[u8]) -> Result<Request, String> {
+    let (&tag, body) = payload.split_first().ok_or("empty request")?;
+    match tag {
+        1 => Ok(Request::Hello(take_params(body)?)),
+        2 => Ok(Request::Collapse(body.to_vec())),
+        3 => {
+            let (a_len, rest) = take::<4>(body)?;
+            let a_len = u32::from_le_bytes(a_len) as usize;
+            if rest.len() < a_len {
+                return Err("compare request is shorter than its first input".to_string());
+            }
+            let (a, b) = rest.split_at(a_len);
+            Ok(Request::Compare(a.to_vec(), b.to_vec()))
+        }
+        4 => {
+            let (value, input) = take::<8>(body)?;
+            Ok(Request::Insert {
+                input: input.to_vec(),
+                value: u64::from_le_bytes(value),
+            })
+        }
+        5 => {
+            let (max_distance, input) = take::<4>(body)?;
+            Ok(Request::Query {
+                input: input.to_vec(),
+                max_distance: u32::from_le_bytes(max_distance),
+            })
+        }
+        _ => Err(format!("unknown request tag {tag}")),
+    }
+}
+
+/// Serializes a response payload.
+pub fn encode_response(response: &Response) -> Vec<u8> {
+    let mut out = Vec::new();
+    match response {
+        Response::Hello(params) => {
+            out.push(0x81);
+            put_params(&mut out, params);
+        }
+        Response::Digest(digest) => {
+            out.push(0x82);
+            out.extend_from_slice(digest);
+        }
+        Response::Similarity(score) => {
+            out.push(0x83);
+            out.extend_from_slice(&score.to_le_bytes());
+        }
+        Response::Inserted => out.push(0x84),
+        Response::Matches(matches) => {
+            out.push(0x85);
+            out.extend_from_slice(&(matches.len() as u32).to_le_bytes());
+            for (value, distance) in matches {
+                out.extend_from_slice(&value.to_le_bytes());
+                out.extend_from_slice(&distance.to_le_bytes());
+            }
+        }
+        Response::Error(message) => {
+            out.push(0xFF);
+            out.extend_from_slice(message.as_bytes());
+        }
+    }
+    out
+}
+
+/// Parses a response payload.
+pub fn decode_response(payload: &[u8]) -> Result<Response, String> {
+    let (&tag, body) = payload.split_first().ok_or("empty response")?;
+    match tag {
+        0x81 => Ok(Response::Hello(take_params(body)?)),
+        0x82 => Ok(Response::Digest(body.to_vec())),
+        0x83 => Ok(Response::Similarity(f32::from_le_bytes(exact::<4>(body)?))),
+        0x84 if body.is_empty() => Ok(Response::Inserted),
+        0x85 => {
+            let (count, rest) = take::<4>(body)?;
+            if rest.len() != u32::from_le_bytes(count) as usize * 12 {
+                return Err("match list has the wrong length".to_string());
+            }
+            Ok(Response::Matches(
+                rest.chunks_exact(12)
+                    .map(|m| {
+                        let value = u64::from_le_bytes(m[..8].try_into().expect("8 bytes"));
+                        let distance = u32::from_le_bytes(m[8..].try_into().expect("4 bytes"));
+                        (value, distance)
+                    })
+                    .collect(),
+            ))
+        }
+        0xFF => Ok(Response::Error(String::from_utf8_lossy(body).into_owned())),
+        _ => Err(format!("unknown response tag {tag}")),
+    }
+}
+
+fn put_params(out: &mut Vec<u8>, params: &Params) {
+    out.push(params.protocol);
+    out.push(params.algorithm);
+    out.push(params.bit_order.id());
+    out.extend_from_slice(&params.tolerance.to_le_bytes());
+}
+
+fn take_params(body: &[u8]) -> Result<Params, String> {
+    let [protocol, algorithm, bit_order, tolerance @ ..] = exact::<7>(body)?;
+    Ok(Params {
+        protocol,
+        algorithm,
+        bit_order: BitOrder::from_id(bit_order).ok_or("unsupported bit order")?,
+        tolerance: f32::from_le_bytes(tolerance),
+    })
+}
+
+/// The first `N` bytes of `body` and the rest.
+fn take<const N: usize>(body: &[u8]) -> Result<([u8; N], &[u8]), String> {
+    let (head, rest) = body
+        .split_first_chunk::<N>()
+        .ok_or("request is too short")?;
+    Ok((*head, rest))
+}
+
+/// `body`, which must be exactly `N` bytes.
+fn exact<const N: usize>(body: &[u8]) -> Result<[u8; N], String> {
+    body.try_into()
+        .map_err(|_| format!("expected {N} bytes, found {}", body.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pensieve::TbfConfig;
+
+    #[test]
+    fn test_roundtrips() {
+        let params = Params {
+            protocol: 1,
+            algorithm: TbfConfig::ALGORITHM_VERSION,
+            bit_order: BitOrder::LsbFirst,
+            tolerance: 0.2,
+        };
+        for request in [
+            Request::Hello(params),
+            Request::Collapse(vec![1, 2, 3]),
+            Request::Compare(vec![1], vec![2, 3]),
+            Request::Compare(Vec::new(), Vec::new()),
+            Request::Insert {
+                input: vec![9; 5],
+                value: u64::MAX,
+            },
+            Request::Query {
+                input: vec![],
+                max_distance: 3,
+            },
+        ] {
+            assert_eq!(decode_request(&encode_request(&request)), Ok(request));
+        }
+        for response in [
+            Response::Hello(params),
+            Response::Digest(vec![0xAA; 4]),
+            Response::Similarity(0.75),
+            Response::Inserted,
+            Response::Matches(vec![(1, 0), (2, 5)]),
+            Response::Error("no".to_string()),
+        ] {
+            assert_eq!(decode_response(&encode_response(&response)), Ok(response));
+        }
+    }
+
+    #[test]
+    fn test_malformed_payloads() {
+        assert!(decode_request(&[]).is_err());
+        assert!(decode_request(&[9]).is_err());
+        assert!(decode_request(&[3, 5, 0, 0, 0, 1]).is_err());
+        assert!(decode_request(&[4, 1, 2]).is_err());
+        assert!(decode_request(&[1, 1, 2, 7, 0, 0, 0, 0]).is_err());
+        assert!(decode_response(&[0x85, 2, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, b"abc").unwrap();
+        write_frame(&mut stream, b"").unwrap();
+        let mut reader = stream.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(b"abc".to_vec()));
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(Vec::new()));
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+
+        let huge = (MAX_FRAME as u32 + 1).to_le_bytes();
+        assert!(read_frame(&mut &huge[..]).is_err());
+        assert!(read_frame(&mut &[3, 0, 0, 0, 1][..]).is_err());
+    }
+}
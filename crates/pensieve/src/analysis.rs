@@ -0,0 +1,1695 @@
+//! Statistical analysis of collapse configurations.
+//!
+//! These functions answer "how much entropy survives the collapse?" so that
+//! keys derived from collapsed values (see [`crate::fuzzy_extractor`] and
+//! [`crate::privacy_amplification`]) can be sized responsibly.
+//! [`measure_statistics`] adds the empirical collision, bias and avalanche
+//! numbers that justify a configuration for deduplication and search, and
+//! [`calibrate`] picks the tolerance that best separates labeled pairs, and
+//! [`calibrate_layout`] the assignment of input bits to chunks.
+//! [`stability`] predicts whether one input will collapse reliably, and
+//! [`evaluate`] reports the false-accept and false-reject rates of a
+//! configuration on labeled pairs, [`predict`] the rates to expect before
+//! any are collected, and [`leakage`] how much a stored digest
+//! or its helper data tells about the input. [`explain`] shows why two
+//! inputs did not collapse equally, and [`sweep`] under which tolerances
+//! they would. [`drift`] compares two corpora of a long-lived deployment
+//! and advises whether to recalibrate.
+
+use std::collections::HashMap;
+
+use crate::entropy::EntropySource;
+use crate::layout::LearnedLayout;
+use crate::{
+    ChunkLayout, Error, FinalTransform, TbfConfig, Thresholding, ct_chunk_levels, similarity,
+};
+
+/// z-score of the one-sided 99.5% bound used by the most-common-value
+/// estimator (NIST SP 800-90B, section 6.3.1).
+const MCV_Z: f64 = 2.576;
+
+/// Min-entropy estimate of the collapsed output for one configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinEntropyEstimate {
+    /// Conservative min-entropy of the whole collapsed value, in bits.
+    pub bits: f64,
+    /// Min-entropy of each chunk level on its own, in bits (at most 1 each).
+    /// Their sum is an upper bound on `bits` whenever levels are correlated.
+    pub per_chunk_bits: Vec<f64>,
+    /// Number of samples the estimate is based on (0 for model-based estimates).
+    pub sample_count: usize,
+    /// Number of distinct collapsed values observed (0 for model-based estimates).
+    pub distinct_outputs: usize,
+}
+
+/// Estimates the min-entropy of collapsed outputs from a corpus of samples.
+///
+/// Every sample must have the same length `input_len` (others are ignored).
+/// The whole-value estimate uses the most-common-value estimator with a 99%
+/// upper confidence bound on the probability of the most frequent collapsed
+/// value, so small corpora give pessimistic (lower) results. The result is
+/// also capped by the sum of the per-chunk estimates.
+pub fn estimate_min_entropy<'a>(
+    samples: impl IntoIterator<Item = &'a [u8]>,
+    input_len: usize,
+    tolerance: f32,
+) -> MinEntropyEstimate {
+    let chunk_count = ChunkLayout::new(input_len, tolerance).map_or(0, |l| l.chunk_count());
+    let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut ones = vec![0usize; chunk_count];
+    let mut n = 0usize;
+
+    for sample in samples.into_iter().filter(|s| s.len() == input_len) {
+        let levels = ct_chunk_levels(sample, tolerance);
+        for (count, &level) in ones.iter_mut().zip(&levels) {
+            *count += usize::from(level);
+        }
+        *counts.entry(levels).or_insert(0) += 1;
+        n += 1;
+    }
+
+    if n == 0 || chunk_count == 0 {
+        return MinEntropyEstimate {
+            bits: 0.0,
+            per_chunk_bits: vec![0.0; chunk_count],
+            sample_count: n,
+            distinct_outputs: counts.len(),
+        };
+    }
+
+    let per_chunk_bits: Vec<f64> = ones
+        .iter()
+        .map(|&k| {
+            let p1 = k as f64 / n as f64;
+            mcv_min_entropy(p1.max(1.0 - p1), n)
+        })
+        .collect();
+
+    let most_common = *counts.values().max().expect("at least one sample") as f64 / n as f64;
+    let bits = mcv_min_entropy(most_common, n).min(per_chunk_bits.iter().sum());
+
+    MinEntropyEstimate {
+        bits,
+        per_chunk_bits,
+        sample_count: n,
+        distinct_outputs: counts.len(),
+    }
+}
+
+/// Computes the min-entropy of collapsed outputs analytically for a source
+/// whose bits are independent and each equal to 1 with probability
+/// `one_probability`.
+///
+/// Chunk levels are then independent too, so the per-chunk min-entropies
+/// add up exactly. This models biased sources (stuck-at cells, sparse
+/// features), where the collapse concentrates probability mass on the
+/// all-ones level much faster than the raw bias suggests.
+pub fn min_entropy_for_biased_bits(
+    input_len: usize,
+    tolerance: f32,
+    one_probability: f64,
+) -> MinEntropyEstimate {
+    let q = one_probability.clamp(0.0, 1.0);
+    let per_chunk_bits: Vec<f64> = match ChunkLayout::new(input_len, tolerance) {
+        None => Vec::new(),
+        Some(layout) => layout
+            .ranges()
+            .map(|(start, end)| {
+                let p1 = binomial_upper_tail(end - start, q, layout.threshold as usize);
+                -p1.max(1.0 - p1).log2()
+            })
+            .collect(),
+    };
+    MinEntropyEstimate {
+        bits: per_chunk_bits.iter().sum(),
+        per_chunk_bits,
+        sample_count: 0,
+        distinct_outputs: 0,
+    }
+}
+
+/// Information about an input that its digest and helper data reveal,
+/// returned by [`leakage`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeakageReport {
+    /// Shannon entropy of the input under the model, in bits, for scale.
+    pub input_bits: f64,
+    /// Information each chunk level carries about the input, in bits (at
+    /// most 1 each), in chunk order.
+    pub per_chunk_bits: Vec<f64>,
+    /// Information a reader of the digest recovers, in bits: the sum of
+    /// `per_chunk_bits`, or 0 under [`FinalTransform::KeyedPrf`] for
+    /// readers without the key. [`FinalTransform::Hash`] hides nothing
+    /// here, since its few levels can all be guessed.
+    pub digest_bits: f64,
+    /// Information a reader of the
+    /// [`HelperData`](crate::fuzzy_extractor::HelperData) of an enrollment
+    /// under the same tolerance recovers, in bits: 0, since it holds only
+    /// a random salt and the tolerance. Whoever can also check guesses
+    /// against the extracted key learns the sum of `per_chunk_bits`.
+    pub helper_bits: f64,
+}
+
+/// Quantifies what digests of `input_len`-byte inputs collapsed under
+/// `config` reveal about inputs whose bits are independent and each equal
+/// to 1 with probability `one_probability`.
+///
+/// A digest is a function of the input, so it reveals exactly the entropy
+/// of its chunk levels. Chunks are disjoint and independent under the
+/// model, except under [`Thresholding::Mean`], where every level depends
+/// on the whole input; each of its levels is then counted as a full bit,
+/// an upper bound. Security reviews should compare `digest_bits` with the
+/// entropy the deployment relies on.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::analysis::leakage;
+/// use pensieve::{FinalTransform, TbfConfig, Thresholding};
+///
+/// // Symmetric thresholds split unbiased chunks evenly.
+/// let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+/// let report = leakage(32, &config, 0.5);
+/// assert_eq!(report.per_chunk_bits.len(), 8);
+/// assert!(report.digest_bits > 7.5 && report.digest_bits <= 8.0);
+/// assert_eq!(report.input_bits, 256.0);
+///
+/// let keyed = config.with_transform(FinalTransform::KeyedPrf { key: [7; 32] });
+/// assert_eq!(leakage(32, &keyed, 0.5).digest_bits, 0.0);
+/// ```
+pub fn leakage(input_len: usize, config: &TbfConfig, one_probability: f64) -> LeakageReport {
+    let q = one_probability.clamp(0.0, 1.0);
+    let per_chunk_bits: Vec<f64> = match ChunkLayout::for_config(input_len * 8, config) {
+        None => Vec::new(),
+        Some(layout) => layout
+            .ranges()
+            .map(|(start, end)| {
+                let len = end - start;
+                match config.thresholding() {
+                    Thresholding::OneSided | Thresholding::Symmetric => {
+                        binary_entropy(binomial_upper_tail(len, q, layout.threshold as usize))
+                    }
+                    // An odd number of set bits.
+                    Thresholding::Parity => {
+                        binary_entropy((1.0 - (1.0 - 2.0 * q).powi(len as i32)) / 2.0)
+                    }
+                    Thresholding::Mean => 1.0,
+                }
+            })
+            .collect(),
+    };
+    let levels_bits = per_chunk_bits.iter().sum();
+    LeakageReport {
+        input_bits: (input_len * 8) as f64 * binary_entropy(q),
+        digest_bits: match config.transform() {
+            FinalTransform::KeyedPrf { .. } => 0.0,
+            _ => levels_bits,
+        },
+        per_chunk_bits,
+        helper_bits: 0.0,
+    }
+}
+
+/// Shannon entropy of a bit equal to 1 with probability `p`, in bits.
+fn binary_entropy(p: f64) -> f64 {
+    [p, 1.0 - p]
+        .iter()
+        .filter(|&&p| p > 0.0)
+        .map(|&p| -p * p.log2())
+        .sum()
+}
+
+/// How likely noise is to change the digest of one input, returned by
+/// [`stability`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StabilityReport {
+    /// Probability that noise at the configured rate changes the digest.
+    pub change_probability: f64,
+    /// Probability that it changes each chunk level, in chunk order.
+    pub per_chunk_change_probability: Vec<f64>,
+    /// The margin of every chunk, as in [`TbfConfig::collapse_with_margins`].
+    pub margins: Vec<u32>,
+}
+
+/// Estimates the probability that noise flipping every bit of `input`
+/// independently with probability `config.tolerance()` changes its digest.
+///
+/// A chunk changes level when the flips inside it move its popcount by at
+/// least its margin in the right direction, which is computed exactly from
+/// the two binomial distributions of bits flipping up and down. Chunks are
+/// disjoint, so they change independently. Under [`Thresholding::Mean`]
+/// the noise in the other chunks, which moves the mean, is ignored.
+///
+/// An enrollment flow can reject a capture whose digest is likely to change
+/// and ask for another one. Like [`TbfConfig::collapse_with_margins`],
+/// this branches on the input and is meant for public data.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::analysis::stability;
+/// use pensieve::{TbfConfig, Thresholding};
+///
+/// let config = TbfConfig::new(0.05).with_thresholding(Thresholding::Symmetric);
+/// let mut capture = [0u8; 16];
+/// capture[..2].fill(0xFF);
+/// assert!(stability(&capture, &config).change_probability < 0.01);
+/// // Chunk 0 one bit past half: a single lost bit drops it.
+/// capture[1] = 0x80;
+/// assert!(stability(&capture, &config).change_probability > 0.25);
+/// ```
+pub fn stability(input: &[u8], config: &TbfConfig) -> StabilityReport {
+    let (_, margins) = config.collapse_with_margins(input);
+    let p = f64::from(config.tolerance());
+    let per_chunk_change_probability: Vec<f64> =
+        match ChunkLayout::for_config(input.len() * 8, config) {
+            None => Vec::new(),
+            Some(layout) => {
+                let input = config.msb_first(input);
+                let sums: Vec<u32> = layout
+                    .ranges()
+                    .map(|(start, end)| crate::ct_popcount_range(&input, start, end))
+                    .collect();
+                let total = sums.iter().map(|&sum| u64::from(sum)).sum();
+                layout
+                    .ranges()
+                    .zip(&sums)
+                    .zip(&margins)
+                    .map(|((range @ (start, end), &sum), &margin)| {
+                        let (len, sum) = (end - start, sum as usize);
+                        if config.thresholding() == Thresholding::Parity {
+                            // An odd number of flips.
+                            return (1.0 - (1.0 - 2.0 * p).powi(len as i32)) / 2.0;
+                        }
+                        if margin == u32::MAX {
+                            return 0.0;
+                        }
+                        // Bits flipping towards the other level, and back.
+                        let (towards, back) = match layout.level(range, sum as u32, total) {
+                            1 => (sum, len - sum),
+                            _ => (len - sum, sum),
+                        };
+                        net_flips_at_least(towards, back, p, margin as usize)
+                    })
+                    .collect()
+            }
+        };
+    StabilityReport {
+        change_probability: 1.0
+            - per_chunk_change_probability
+                .iter()
+                .map(|q| 1.0 - q)
+                .product::<f64>(),
+        per_chunk_change_probability,
+        margins,
+    }
+}
+
+/// One chunk on which two inputs collapsed to different levels, listed by
+/// [`explain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkMismatch {
+    /// Position of the chunk.
+    pub index: usize,
+    /// Bits in the chunk.
+    pub bits: usize,
+    /// Set bits of the chunk in each input.
+    pub popcounts: (u32, u32),
+    /// Set bits from which the chunk collapses to 1; 0 under
+    /// [`Thresholding::Parity`] and [`Thresholding::Mean`], which have no
+    /// fixed threshold.
+    pub threshold: u32,
+    /// Level of the chunk in each input.
+    pub levels: (u8, u8),
+    /// Bits of the chunk that differ between the inputs.
+    pub differing_bits: u32,
+}
+
+/// Why two inputs did or did not collapse equally, returned by [`explain`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MismatchReport {
+    /// Whether the inputs collapse to the same digest.
+    pub matches: bool,
+    /// Whether the inputs have different lengths, which never match and
+    /// whose chunks are not compared.
+    pub lengths_differ: bool,
+    /// Number of chunks compared.
+    pub chunk_count: usize,
+    /// The chunks whose levels differ, in order.
+    pub mismatches: Vec<ChunkMismatch>,
+    /// The supported tolerance closest to the configured one under which
+    /// the inputs collapse equally, the larger on a tie; `None` if there
+    /// is none. Only [`Thresholding::OneSided`] thresholds move with the
+    /// tolerance, so under the other rules this is the configured
+    /// tolerance for a match and `None` otherwise.
+    pub matching_tolerance: Option<f32>,
+}
+
+/// Explains why `a` and `b` do or do not collapse to the same digest under
+/// `config`: which chunks disagree, their popcounts and thresholds, and the
+/// nearest tolerance that would have matched them.
+///
+/// Like [`stability`], this branches on the inputs and is meant for
+/// public data, such as records that failed to deduplicate.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::analysis::explain;
+/// use pensieve::TbfConfig;
+///
+/// // Chunk 0 holds 1 and 2 set bits of 16, around the 12.5% threshold.
+/// let (mut a, mut b) = ([0u8; 16], [0u8; 16]);
+/// a[0] = 0b1;
+/// b[0] = 0b11;
+/// let config = TbfConfig::new(0.125);
+/// let report = explain(&a, &b, &config);
+/// assert!(!report.matches);
+/// assert_eq!(report.mismatches.len(), 1);
+/// let chunk = &report.mismatches[0];
+/// assert_eq!((chunk.index, chunk.popcounts, chunk.threshold), (0, (1, 2), 2));
+/// // One per-mille more raises the threshold past both.
+/// assert_eq!(report.matching_tolerance, Some(0.126));
+/// ```
+pub fn explain(a: &[u8], b: &[u8], config: &TbfConfig) -> MismatchReport {
+    let layout = match ChunkLayout::for_config(a.len() * 8, config) {
+        Some(layout) if a.len() == b.len() => layout,
+        _ => {
+            return MismatchReport {
+                matches: a.len() == b.len(),
+                lengths_differ: a.len() != b.len(),
+                chunk_count: 0,
+                mismatches: Vec::new(),
+                matching_tolerance: (a.len() == b.len()).then(|| config.tolerance()),
+            };
+        }
+    };
+    let (a, b) = (config.msb_first(a), config.msb_first(b));
+    let xor: Vec<u8> = a.iter().zip(b.iter()).map(|(x, y)| x ^ y).collect();
+    let popcounts = |input: &[u8]| -> Vec<u32> {
+        layout
+            .ranges()
+            .map(|(start, end)| crate::ct_popcount_range(input, start, end))
+            .collect()
+    };
+    let (sums_a, sums_b) = (popcounts(&a), popcounts(&b));
+    let (levels_a, levels_b) = (layout.levels(&sums_a), layout.levels(&sums_b));
+    let mismatches: Vec<ChunkMismatch> = layout
+        .ranges()
+        .enumerate()
+        .filter(|&(i, _)| levels_a[i] != levels_b[i])
+        .map(|(index, (start, end))| ChunkMismatch {
+            index,
+            bits: end - start,
+            popcounts: (sums_a[index], sums_b[index]),
+            threshold: layout.threshold,
+            levels: (levels_a[index], levels_b[index]),
+            differing_bits: crate::ct_popcount_range(&xor, start, end),
+        })
+        .collect();
+
+    let matches = mismatches.is_empty();
+    let matching_tolerance = if matches {
+        Some(config.tolerance())
+    } else if config.thresholding() == Thresholding::OneSided {
+        let permille = config.tolerance_permille();
+        let agrees = |permille: u16| agree_at(layout.total_bits, &sums_a, &sums_b, permille);
+        (1..=TbfConfig::MAX_TOLERANCE_PERMILLE - TbfConfig::MIN_TOLERANCE_PERMILLE)
+            .flat_map(|step| [permille.checked_add(step), permille.checked_sub(step)])
+            .flatten()
+            .filter(|p| {
+                (TbfConfig::MIN_TOLERANCE_PERMILLE..=TbfConfig::MAX_TOLERANCE_PERMILLE).contains(p)
+            })
+            .find(|&p| agrees(p))
+            .map(|p| f32::from(p) / 1000.0)
+    } else {
+        None
+    };
+    MismatchReport {
+        matches,
+        lengths_differ: false,
+        chunk_count: layout.chunk_count(),
+        mismatches,
+        matching_tolerance,
+    }
+}
+
+/// Whether `a` and `b` collapse equally under every tolerance of
+/// `tolerances`, under [`Thresholding::OneSided`] (the only rule the
+/// tolerance moves) and bits read MSB-first. Each tolerance is reported
+/// clamped and rounded as in [`TbfConfig::new`].
+///
+/// The chunks do not depend on the tolerance, so the popcounts are counted
+/// once and every tolerance costs a comparison per chunk. Inputs of
+/// different lengths never match.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::analysis::sweep;
+///
+/// // Chunk 0 holds 1 and 2 set bits of 16.
+/// let (mut a, mut b) = ([0u8; 16], [0u8; 16]);
+/// a[0] = 0b1;
+/// b[0] = 0b11;
+/// let matches = sweep(&a, &b, [0.05, 0.1, 0.125, 0.15, 0.2]);
+/// assert_eq!(
+///     matches,
+///     [(0.05, true), (0.1, false), (0.125, false), (0.15, true), (0.2, true)]
+/// );
+/// ```
+pub fn sweep(a: &[u8], b: &[u8], tolerances: impl IntoIterator<Item = f32>) -> Vec<(f32, bool)> {
+    let sums = match ChunkLayout::for_bits(a.len() * 8, TbfConfig::MIN_TOLERANCE) {
+        Some(layout) if a.len() == b.len() => {
+            let popcounts = |input: &[u8]| -> Vec<u32> {
+                layout
+                    .ranges()
+                    .map(|(start, end)| crate::ct_popcount_range(input, start, end))
+                    .collect()
+            };
+            Some((layout.total_bits, popcounts(a), popcounts(b)))
+        }
+        _ => None,
+    };
+    tolerances
+        .into_iter()
+        .map(|tolerance| {
+            let permille = TbfConfig::new(tolerance).tolerance_permille();
+            let matches = match &sums {
+                Some((bits, sums_a, sums_b)) => agree_at(*bits, sums_a, sums_b, permille),
+                None => a.len() == b.len(), // Both empty.
+            };
+            (f32::from(permille) / 1000.0, matches)
+        })
+        .collect()
+}
+
+/// Whether chunks with popcounts `sums_a` and `sums_b`, in an input of
+/// `total_bits`, collapse to the same levels under the one-sided threshold
+/// of `permille`: both popcounts of every chunk fall on the same side of it.
+fn agree_at(total_bits: usize, sums_a: &[u32], sums_b: &[u32], permille: u16) -> bool {
+    let threshold = ChunkLayout::for_permille(total_bits, u32::from(permille))
+        .expect("non-empty input")
+        .threshold;
+    sums_a
+        .iter()
+        .zip(sums_b)
+        .all(|(&x, &y)| (x >= threshold) == (y >= threshold))
+}
+
+/// Tolerances tried by [`calibrate`]: 5% to 25% in steps of 1.25%.
+const CALIBRATION_STEPS: usize = 17;
+
+/// Chooses the tolerance that best separates same-source pairs from
+/// different-source pairs.
+///
+/// Every tolerance from 5% to 25% in steps of 1.25%, under
+/// [`Thresholding::OneSided`] (the only rule the tolerance moves), is
+/// scored by the
+/// fraction of `genuine_pairs` that collapse identically minus the fraction
+/// of `impostor_pairs` that do (Youden's J). Ties are broken by the gap
+/// between the mean [`similarity`] of genuine and impostor pairs, then in
+/// favor of the stricter tolerance. Pairs of different lengths never
+/// collapse identically. With no pairs at all the default configuration is
+/// returned.
+pub fn calibrate(genuine_pairs: &[(&[u8], &[u8])], impostor_pairs: &[(&[u8], &[u8])]) -> TbfConfig {
+    let match_rate = |pairs: &[(&[u8], &[u8])], config: &TbfConfig| {
+        let matches = pairs
+            .iter()
+            .filter(|(a, b)| a.len() == b.len() && config.chunk_levels(a) == config.chunk_levels(b))
+            .count();
+        matches as f64 / pairs.len().max(1) as f64
+    };
+    let mean_similarity = |pairs: &[(&[u8], &[u8])], config: &TbfConfig| {
+        let total: f64 = pairs
+            .iter()
+            .map(|(a, b)| f64::from(similarity(a, b, config)))
+            .sum();
+        total / pairs.len().max(1) as f64
+    };
+    if genuine_pairs.is_empty() && impostor_pairs.is_empty() {
+        return TbfConfig::default();
+    }
+
+    let step =
+        (TbfConfig::MAX_TOLERANCE - TbfConfig::MIN_TOLERANCE) / (CALIBRATION_STEPS - 1) as f32;
+    let mut best: Option<(f64, f64, TbfConfig)> = None;
+    for i in 0..CALIBRATION_STEPS {
+        let config = TbfConfig::new(TbfConfig::MIN_TOLERANCE + step * i as f32);
+        let separation = match_rate(genuine_pairs, &config) - match_rate(impostor_pairs, &config);
+        let gap =
+            mean_similarity(genuine_pairs, &config) - mean_similarity(impostor_pairs, &config);
+        // Strictly better only, so the earliest (strictest) tolerance wins ties.
+        if best.is_none_or(|(s, g, _)| (separation, gap) > (s, g)) {
+            best = Some((separation, gap, config));
+        }
+    }
+    best.expect("at least one step").2
+}
+
+/// Searches for the assignment of input bits to chunks that best separates
+/// same-source pairs from different-source pairs under `config`.
+///
+/// Starting from the contiguous layout of the collapse, each of
+/// `iterations` steps exchanges the chunks of two random bits and keeps the
+/// exchange if it raises the score: the fraction of `genuine_pairs`
+/// that collapse identically minus the fraction of `impostor_pairs` that do
+/// (Youden's J), with ties broken by the same difference in the fraction of
+/// chunk levels the pairs agree on. Exchanges keep the chunk sizes, so the
+/// thresholds of `config` still apply; tune the tolerance with
+/// [`calibrate`] first. The search is greedy and may settle on a local
+/// optimum; run it with several `rng` seeds and keep the best
+/// [`evaluate`]d result.
+///
+/// Fails with [`Error::InvalidParameter`] without pairs or with empty
+/// inputs, and with [`Error::InvalidLength`] if the inputs differ in
+/// length.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::analysis::{calibrate, calibrate_layout};
+/// use pensieve::entropy::OsEntropy;
+/// use pensieve::layout::LearnedLayout;
+///
+/// let genuine: Vec<(&[u8], &[u8])> = vec![(&[0x5A; 16], &[0x5B; 16])];
+/// let impostor: Vec<(&[u8], &[u8])> = vec![(&[0x5A; 16], &[0xA5; 16])];
+/// let config = calibrate(&genuine, &impostor);
+/// let layout = calibrate_layout(&genuine, &impostor, &config, 500, &mut OsEntropy).unwrap();
+///
+/// // Ship the layout with its configuration, and collapse with it later.
+/// let layout = LearnedLayout::from_bytes(&layout.to_bytes()).unwrap();
+/// assert_eq!(layout.config(), &config);
+/// assert_eq!(layout.collapse(&[0x5A; 16]).unwrap().len(), 16);
+/// ```
+pub fn calibrate_layout(
+    genuine_pairs: &[(&[u8], &[u8])],
+    impostor_pairs: &[(&[u8], &[u8])],
+    config: &TbfConfig,
+    iterations: usize,
+    rng: &mut impl EntropySource,
+) -> Result<LearnedLayout, Error> {
+    let Some(&(first, _)) = genuine_pairs.first().or(impostor_pairs.first()) else {
+        return Err(Error::InvalidParameter("at least one pair is required"));
+    };
+    let mut layout = LearnedLayout::contiguous(config, first.len())?;
+    // Popcounts of every chunk of every input, kept up to date across
+    // exchanges instead of recounted.
+    let mut sides = Vec::new();
+    for &(a, b) in genuine_pairs.iter().chain(impostor_pairs) {
+        for input in [a, b] {
+            let sums = layout.sums(input)?;
+            sides.push((config.msb_first(input).into_owned(), sums));
+        }
+    }
+    let chunk_layout = layout.chunk_layout();
+    let score = |sides: &[(Vec<u8>, Vec<u32>)]| {
+        let (mut matches, mut agreement) = ([0usize; 2], [0usize; 2]);
+        for (pair, sides) in sides.chunks_exact(2).enumerate() {
+            let class = usize::from(pair >= genuine_pairs.len());
+            let a = chunk_layout.levels(&sides[0].1);
+            let b = chunk_layout.levels(&sides[1].1);
+            let agree = a.iter().zip(&b).filter(|(x, y)| x == y).count();
+            matches[class] += usize::from(agree == a.len());
+            agreement[class] += agree;
+        }
+        let rate = |count: usize, pairs: usize| count as f64 / pairs.max(1) as f64;
+        let chunks = chunk_layout.chunk_count();
+        (
+            rate(matches[0], genuine_pairs.len()) - rate(matches[1], impostor_pairs.len()),
+            rate(agreement[0], genuine_pairs.len() * chunks)
+                - rate(agreement[1], impostor_pairs.len() * chunks),
+        )
+    };
+    // Moves bit `a` from chunk `from` to chunk `to`, and bit `b` back.
+    let exchange = |sides: &mut [(Vec<u8>, Vec<u32>)], (a, b): (usize, usize), (from, to)| {
+        for (bits, sums) in sides {
+            let bit = |i: usize| u32::from(bits[i / 8] >> (7 - i % 8) & 1);
+            sums[from] = sums[from] - bit(a) + bit(b);
+            sums[to] = sums[to] - bit(b) + bit(a);
+        }
+    };
+
+    let bits = layout.chunks().len();
+    let mut best = score(&sides);
+    for _ in 0..iterations {
+        let (a, b) = (random_below(bits, rng), random_below(bits, rng));
+        let chunks: (usize, usize) = (layout.chunks()[a].into(), layout.chunks()[b].into());
+        if chunks.0 == chunks.1 {
+            continue;
+        }
+        exchange(&mut sides, (a, b), chunks);
+        let candidate = score(&sides);
+        if candidate > best {
+            layout.swap(a, b);
+            best = candidate;
+        } else {
+            exchange(&mut sides, (a, b), (chunks.1, chunks.0));
+        }
+    }
+    Ok(layout)
+}
+
+/// False-accept and false-reject rates at one similarity threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RocPoint {
+    /// Pairs are accepted when their [`similarity`] is at least this.
+    pub threshold: f32,
+    /// Fraction of impostor pairs accepted.
+    pub false_accept_rate: f64,
+    /// Fraction of genuine pairs rejected.
+    pub false_reject_rate: f64,
+}
+
+/// Matcher metrics of one configuration on labeled pairs, returned by
+/// [`evaluate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RocReport {
+    /// Fraction of impostor pairs that collapse identically.
+    pub false_accept_rate: f64,
+    /// Fraction of genuine pairs that do not collapse identically.
+    pub false_reject_rate: f64,
+    /// The rates when accepting by [`similarity`] instead, at every
+    /// similarity observed in either set of pairs, in ascending order of
+    /// threshold. The point at threshold 1.0, if any, is the exact-match
+    /// decision above.
+    pub curve: Vec<RocPoint>,
+    /// The smallest rate at which some threshold of `curve` makes both
+    /// error rates at most equal to it.
+    pub equal_error_rate: f64,
+}
+
+/// Measures how well `config` tells `genuine_pairs` from `impostor_pairs`.
+///
+/// A pair is accepted when both inputs collapse to the same output, which
+/// gives one false-accept and one false-reject rate. [`similarity`] grades
+/// the pairs in between, so sweeping a threshold over it traces the whole
+/// ROC curve and locates the equal-error rate, the standard summaries for
+/// comparing matchers. Pairs of different lengths are never accepted, and
+/// an empty set of pairs has error rates of 0.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::analysis::evaluate;
+///
+/// let (enrolled, noisy, other) = ([0xFFu8; 16], [0xFEu8; 16], [0u8; 16]);
+/// let report = evaluate(
+///     &TbfConfig::new(0.125),
+///     &[(&enrolled[..], &noisy[..])],
+///     &[(&enrolled[..], &other[..])],
+/// );
+/// assert_eq!((report.false_accept_rate, report.false_reject_rate), (0.0, 0.0));
+/// assert_eq!(report.equal_error_rate, 0.0);
+/// ```
+pub fn evaluate(
+    config: &TbfConfig,
+    genuine_pairs: &[(&[u8], &[u8])],
+    impostor_pairs: &[(&[u8], &[u8])],
+) -> RocReport {
+    let scores = |pairs: &[(&[u8], &[u8])]| {
+        let mut scores: Vec<f32> = pairs
+            .iter()
+            .map(|(a, b)| similarity(a, b, config))
+            .collect();
+        scores.sort_by(f32::total_cmp);
+        scores
+    };
+    let (genuine, impostor) = (scores(genuine_pairs), scores(impostor_pairs));
+    let rate = |count: usize, pairs: &[f32]| count as f64 / pairs.len().max(1) as f64;
+    let point = |threshold: f32| RocPoint {
+        threshold,
+        false_accept_rate: rate(
+            impostor.len() - impostor.partition_point(|&s| s < threshold),
+            &impostor,
+        ),
+        false_reject_rate: rate(genuine.partition_point(|&s| s < threshold), &genuine),
+    };
+
+    let mut thresholds: Vec<f32> = genuine.iter().chain(&impostor).copied().collect();
+    thresholds.sort_by(f32::total_cmp);
+    thresholds.dedup();
+    let curve: Vec<RocPoint> = thresholds.into_iter().map(point).collect();
+    // Similarity is 1.0 exactly when the outputs are identical.
+    let exact = point(1.0);
+    RocReport {
+        false_accept_rate: exact.false_accept_rate,
+        false_reject_rate: exact.false_reject_rate,
+        equal_error_rate: curve
+            .iter()
+            .map(|p| p.false_accept_rate.max(p.false_reject_rate))
+            .fold(
+                exact.false_accept_rate.max(exact.false_reject_rate),
+                f64::min,
+            ),
+        curve,
+    }
+}
+
+/// Exact-match error rates expected of a configuration, returned by
+/// [`predict`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PredictedRates {
+    /// Probability that two unrelated inputs collapse identically.
+    pub false_accept_rate: f64,
+    /// Probability that a noisy recapture collapses differently.
+    pub false_reject_rate: f64,
+}
+
+/// Predicts the rates [`evaluate`] would measure for inputs of `input_len`
+/// bytes, without labeled pairs.
+///
+/// The model sets every input bit independently with probability
+/// `one_probability`, as in [`leakage`], and a
+/// genuine recapture flips each bit independently with probability
+/// `bit_error_rate`. A chunk of an unrelated input matches when both land
+/// on the same level; a chunk of a recapture changes as in [`stability`],
+/// averaged over the popcount of the enrolled chunk. Under
+/// [`Thresholding::Mean`] every chunk is compared with half its length,
+/// ignoring the other chunks. Real data is biased and correlated, so treat
+/// the figures as a starting point and confirm them with [`evaluate`].
+///
+/// # Examples
+/// ```rust
+/// use pensieve::analysis::predict;
+/// use pensieve::{TbfConfig, Thresholding};
+///
+/// let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+/// let rates = predict(32, &config, 0.5, 0.01);
+/// // Eight unbiased levels.
+/// assert!((rates.false_accept_rate - 1.0 / 256.0).abs() < 1e-3);
+/// assert!(rates.false_reject_rate > 0.0 && rates.false_reject_rate < 0.5);
+/// ```
+pub fn predict(
+    input_len: usize,
+    config: &TbfConfig,
+    one_probability: f64,
+    bit_error_rate: f64,
+) -> PredictedRates {
+    let (q, p) = (
+        one_probability.clamp(0.0, 1.0),
+        bit_error_rate.clamp(0.0, 1.0),
+    );
+    let Some(layout) = ChunkLayout::for_config(input_len * 8, config) else {
+        return PredictedRates {
+            false_accept_rate: 1.0,
+            false_reject_rate: 0.0,
+        };
+    };
+    let (mut accept, mut keep) = (1.0, 1.0);
+    for (start, end) in layout.ranges() {
+        let len = end - start;
+        if config.thresholding() == Thresholding::Parity {
+            let odd = (1.0 - (1.0 - 2.0 * q).powi(len as i32)) / 2.0;
+            accept *= odd * odd + (1.0 - odd) * (1.0 - odd);
+            keep *= 1.0 - (1.0 - (1.0 - 2.0 * p).powi(len as i32)) / 2.0;
+            continue;
+        }
+        let threshold = match config.thresholding() {
+            Thresholding::Mean => len / 2 + 1,
+            _ => layout.threshold as usize,
+        };
+        let one = binomial_upper_tail(len, q, threshold);
+        accept *= one * one + (1.0 - one) * (1.0 - one);
+        let change: f64 = binomial_pmf(len, q)
+            .iter()
+            .enumerate()
+            .map(|(sum, weight)| {
+                weight
+                    * if sum >= threshold {
+                        net_flips_at_least(sum, len - sum, p, sum + 1 - threshold)
+                    } else {
+                        net_flips_at_least(len - sum, sum, p, threshold - sum)
+                    }
+            })
+            .sum();
+        keep *= 1.0 - change.min(1.0);
+    }
+    PredictedRates {
+        false_accept_rate: accept,
+        false_reject_rate: 1.0 - keep,
+    }
+}
+
+/// Collision rate of input pairs at one Hamming distance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionPoint {
+    /// Bits flipped between the two inputs of each pair.
+    pub distance: usize,
+    /// Fraction of pairs that collapsed to the same output.
+    pub rate: f64,
+}
+
+/// Empirical behavior of one configuration on uniformly random inputs,
+/// returned by [`measure_statistics`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatisticsReport {
+    /// Number of random inputs every figure is based on.
+    pub trials: usize,
+    /// Collision rate against distance, at distances 1, 2, 4, ... up to half
+    /// the input bits plus the distance the tolerance nominally absorbs,
+    /// in ascending order.
+    pub collision_curve: Vec<CollisionPoint>,
+    /// Fraction of outputs with each output bit set (MSB-first), 0.5 for an
+    /// unbiased bit.
+    pub bit_bias: Vec<f64>,
+    /// Largest `|bias - 0.5|` over all output bits.
+    pub max_bias: f64,
+    /// Mean fraction of output bits that change when one random input bit
+    /// flips; a cryptographic hash scores 0.5, a noise-tolerant collapse
+    /// should score close to 0.
+    pub avalanche: f64,
+    /// Fraction of single-bit flips that leave the output unchanged.
+    pub single_flip_stability: f64,
+}
+
+/// Measures collision rate against Hamming distance, per-bit output bias
+/// and avalanche behavior of `config` on `trials` uniformly random inputs
+/// of `input_len` bytes drawn from `rng`.
+///
+/// The figures are sample estimates with a standard error of about
+/// `0.5 / sqrt(trials)`; a few thousand trials are enough to compare
+/// configurations.
+pub fn measure_statistics(
+    config: &TbfConfig,
+    input_len: usize,
+    trials: usize,
+    rng: &mut impl EntropySource,
+) -> StatisticsReport {
+    let total_bits = input_len * 8;
+    let tolerance = config.tolerance();
+    let mut distances: Vec<usize> = std::iter::successors(Some(1usize), |d| Some(d * 2))
+        .take_while(|&d| d <= total_bits / 2)
+        .collect();
+    let nominal = (tolerance as f64 * total_bits as f64).round() as usize;
+    if nominal > 0 {
+        distances.push(nominal);
+    }
+    distances.sort_unstable();
+    distances.dedup();
+
+    let mut collisions = vec![0usize; distances.len()];
+    let mut ones = vec![0usize; total_bits];
+    let (mut changed_bits, mut stable_flips) = (0usize, 0usize);
+    let mut input = vec![0u8; input_len];
+    for _ in 0..trials {
+        rng.fill_bytes(&mut input);
+        let output = config.collapse(&input);
+        for (bit, count) in ones.iter_mut().enumerate() {
+            *count += usize::from(output[bit / 8] >> (7 - bit % 8) & 1);
+        }
+        if total_bits == 0 {
+            continue;
+        }
+
+        let mut flipped = input.clone();
+        flip_bit(&mut flipped, random_below(total_bits, rng));
+        let changed = hamming(&output, &config.collapse(&flipped));
+        changed_bits += changed;
+        stable_flips += usize::from(changed == 0);
+
+        for (&distance, count) in distances.iter().zip(collisions.iter_mut()) {
+            let mut noisy = input.clone();
+            for position in random_positions(total_bits, distance, rng) {
+                flip_bit(&mut noisy, position);
+            }
+            *count += usize::from(config.collapse(&noisy) == output);
+        }
+    }
+
+    let per_trial = |count: usize| count as f64 / trials.max(1) as f64;
+    let bit_bias: Vec<f64> = ones.iter().map(|&count| per_trial(count)).collect();
+    StatisticsReport {
+        trials,
+        collision_curve: distances
+            .iter()
+            .zip(&collisions)
+            .map(|(&distance, &count)| CollisionPoint {
+                distance,
+                rate: per_trial(count),
+            })
+            .collect(),
+        max_bias: bit_bias
+            .iter()
+            .fold(0.0, |max, b| f64::max(max, (b - 0.5).abs())),
+        bit_bias,
+        avalanche: per_trial(changed_bits) / total_bits.max(1) as f64,
+        single_flip_stability: per_trial(stable_flips),
+    }
+}
+
+/// The collapse of one corpus, summarized by [`drift`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusSummary {
+    /// Number of captures.
+    pub captures: usize,
+    /// Number of distinct sources captured.
+    pub sources: usize,
+    /// Fraction of pairs of captures of one source that collapse
+    /// identically; `None` without such pairs.
+    pub match_rate: Option<f64>,
+    /// Fraction of pairs of captures of different sources that collapse
+    /// identically; `None` without such pairs.
+    pub false_match_rate: Option<f64>,
+    /// Fraction of captures with each chunk at level 1, in chunk order.
+    pub chunk_bias: Vec<f64>,
+    /// Fraction of chunk levels a single bit flip would change (margin 1),
+    /// over all captures and chunks.
+    pub fragile_rate: f64,
+}
+
+/// What [`drift`] advises about the configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recommendation {
+    /// The current corpus collapses like the baseline.
+    Keep,
+    /// The collapse has shifted measurably: the match rate dropped by more
+    /// than 1%, the false-match rate rose by more than 0.1%, or a chunk's
+    /// bias moved by more than 0.1. Keep watching.
+    Monitor,
+    /// The collapse has shifted enough to hurt matching: the match rate
+    /// dropped by more than 5%, the false-match rate rose by more than 1%,
+    /// or a chunk's bias moved by more than 0.2. Recalibrate, for instance
+    /// with [`calibrate`] on fresh labeled pairs, and re-enroll.
+    Recalibrate,
+}
+
+/// How the collapse of a deployment's captures changed between two
+/// corpora, returned by [`drift`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftReport {
+    /// The older corpus.
+    pub baseline: CorpusSummary,
+    /// The newer corpus.
+    pub current: CorpusSummary,
+    /// Fraction of pairs of a baseline and a current capture of one source
+    /// that collapse identically: how well digests enrolled then match
+    /// captures now. `None` if no source is in both corpora.
+    pub cross_match_rate: Option<f64>,
+    /// Largest drop from the baseline match rate to the current or the
+    /// cross match rate, 0.0 if neither dropped or either is unknown.
+    pub match_rate_drop: f64,
+    /// Current minus baseline false-match rate, 0.0 if either is unknown.
+    pub false_match_rise: f64,
+    /// Current minus baseline bias of every chunk.
+    pub bias_shift: Vec<f64>,
+    /// Largest `|bias_shift|`.
+    pub max_bias_shift: f64,
+    /// What the figures above advise.
+    pub recommendation: Recommendation,
+}
+
+/// Compares how `config` collapses two corpora of captures of the same
+/// deployment, such as last month's and this month's sensor readings, to
+/// tell whether the sources or sensors drifted away from the configuration.
+///
+/// Every capture is labeled with the source it was captured from. Within
+/// each corpus, pairs of captures of one source measure the match rate and
+/// pairs of different sources the false-match rate; pairs across the
+/// corpora measure whether old enrollments still match. Per-chunk bias
+/// shows which part of the input moved. All pairs are counted, from the
+/// digests' frequencies rather than pair by pair.
+///
+/// Fails with [`Error::InvalidParameter`] if either corpus is empty and
+/// with [`Error::InvalidLength`] unless all captures have one length. Like
+/// [`TbfConfig::collapse_with_margins`], this branches on the inputs and is
+/// meant for public data.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::analysis::{Recommendation, drift};
+///
+/// let config = TbfConfig::new(0.125);
+/// let (a, b) = ([0xFFu8; 16], [0x00u8; 16]);
+/// let mut a_worn = a;
+/// a_worn[..6].fill(0); // A sensor region went dark.
+/// let last_month = [(1, &a[..]), (1, &a[..]), (2, &b[..]), (2, &b[..])];
+/// let this_month = [(1, &a_worn[..]), (1, &a[..]), (2, &b[..]), (2, &b[..])];
+///
+/// let report = drift(&config, &last_month, &this_month).unwrap();
+/// assert_eq!(report.baseline.match_rate, Some(1.0));
+/// assert_eq!(report.current.match_rate, Some(0.5));
+/// assert_eq!(report.recommendation, Recommendation::Recalibrate);
+/// ```
+pub fn drift(
+    config: &TbfConfig,
+    baseline: &[(u64, &[u8])],
+    current: &[(u64, &[u8])],
+) -> Result<DriftReport, Error> {
+    let (Some(&(_, first)), false) = (baseline.first(), current.is_empty()) else {
+        return Err(Error::InvalidParameter("corpora must not be empty"));
+    };
+    if let Some(&(_, other)) = baseline
+        .iter()
+        .chain(current)
+        .find(|(_, capture)| capture.len() != first.len())
+    {
+        return Err(Error::InvalidLength {
+            expected: first.len(),
+            found: other.len(),
+        });
+    }
+
+    let digests = |corpus: &[(u64, &[u8])]| -> Vec<(u64, Vec<u8>)> {
+        corpus
+            .iter()
+            .map(|&(source, capture)| (source, config.chunk_levels(capture)))
+            .collect()
+    };
+    let (old, new) = (digests(baseline), digests(current));
+    let baseline_summary = summarize(config, baseline, &old);
+    let current_summary = summarize(config, current, &new);
+
+    // Pairs across the corpora: per source, and per source and digest.
+    let mut old_sources: HashMap<u64, usize> = HashMap::new();
+    let mut old_digests: HashMap<(u64, &[u8]), usize> = HashMap::new();
+    for (source, digest) in &old {
+        *old_sources.entry(*source).or_default() += 1;
+        *old_digests.entry((*source, digest)).or_default() += 1;
+    }
+    let (mut cross_pairs, mut cross_matches) = (0usize, 0usize);
+    for (source, digest) in &new {
+        cross_pairs += old_sources.get(source).copied().unwrap_or(0);
+        cross_matches += old_digests
+            .get(&(*source, &digest[..]))
+            .copied()
+            .unwrap_or(0);
+    }
+    let cross_match_rate = (cross_pairs > 0).then(|| cross_matches as f64 / cross_pairs as f64);
+
+    let match_rate_drop = match baseline_summary.match_rate {
+        Some(before) => [current_summary.match_rate, cross_match_rate]
+            .into_iter()
+            .flatten()
+            .map(|after| before - after)
+            .fold(0.0, f64::max),
+        None => 0.0,
+    };
+    let false_match_rise = match (
+        baseline_summary.false_match_rate,
+        current_summary.false_match_rate,
+    ) {
+        (Some(before), Some(after)) => after - before,
+        _ => 0.0,
+    };
+    let bias_shift: Vec<f64> = current_summary
+        .chunk_bias
+        .iter()
+        .zip(&baseline_summary.chunk_bias)
+        .map(|(after, before)| after - before)
+        .collect();
+    let max_bias_shift = bias_shift.iter().fold(0.0, |max, s| f64::max(max, s.abs()));
+    let recommendation =
+        if match_rate_drop > 0.05 || false_match_rise > 0.01 || max_bias_shift > 0.2 {
+            Recommendation::Recalibrate
+        } else if match_rate_drop > 0.01 || false_match_rise > 0.001 || max_bias_shift > 0.1 {
+            Recommendation::Monitor
+        } else {
+            Recommendation::Keep
+        };
+    Ok(DriftReport {
+        baseline: baseline_summary,
+        current: current_summary,
+        cross_match_rate,
+        match_rate_drop,
+        false_match_rise,
+        bias_shift,
+        max_bias_shift,
+        recommendation,
+    })
+}
+
+/// The [`CorpusSummary`] of `corpus`, whose chunk levels are `digests`.
+fn summarize(
+    config: &TbfConfig,
+    corpus: &[(u64, &[u8])],
+    digests: &[(u64, Vec<u8>)],
+) -> CorpusSummary {
+    let pairs = |n: usize| n * n.saturating_sub(1) / 2;
+    let mut sources: HashMap<u64, usize> = HashMap::new();
+    let mut by_digest: HashMap<&[u8], usize> = HashMap::new();
+    let mut by_source_digest: HashMap<(u64, &[u8]), usize> = HashMap::new();
+    for (source, digest) in digests {
+        *sources.entry(*source).or_default() += 1;
+        *by_digest.entry(digest).or_default() += 1;
+        *by_source_digest.entry((*source, digest)).or_default() += 1;
+    }
+    let genuine_pairs: usize = sources.values().map(|&n| pairs(n)).sum();
+    let genuine_matches: usize = by_source_digest.values().map(|&n| pairs(n)).sum();
+    let impostor_pairs = pairs(digests.len()) - genuine_pairs;
+    let impostor_matches = by_digest.values().map(|&n| pairs(n)).sum::<usize>() - genuine_matches;
+    let rate = |matches: usize, pairs: usize| (pairs > 0).then(|| matches as f64 / pairs as f64);
+
+    let chunks = digests.first().map_or(0, |(_, digest)| digest.len());
+    let mut ones = vec![0usize; chunks];
+    let mut fragile = 0usize;
+    for ((_, digest), (_, capture)) in digests.iter().zip(corpus) {
+        for (count, level) in ones.iter_mut().zip(digest) {
+            *count += usize::from(*level);
+        }
+        let (_, margins) = config.collapse_with_margins(capture);
+        fragile += margins.iter().filter(|&&margin| margin <= 1).count();
+    }
+    CorpusSummary {
+        captures: digests.len(),
+        sources: sources.len(),
+        match_rate: rate(genuine_matches, genuine_pairs),
+        false_match_rate: rate(impostor_matches, impostor_pairs),
+        chunk_bias: ones
+            .iter()
+            .map(|&count| count as f64 / digests.len() as f64)
+            .collect(),
+        fragile_rate: fragile as f64 / (digests.len() * chunks).max(1) as f64,
+    }
+}
+
+fn flip_bit(bytes: &mut [u8], bit: usize) {
+    bytes[bit / 8] ^= 0x80 >> (bit % 8);
+}
+
+fn hamming(a: &[u8], b: &[u8]) -> usize {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x ^ y).count_ones() as usize)
+        .sum()
+}
+
+/// Roughly uniform integer in `0..bound`; the modulo bias of a 64-bit draw
+/// is irrelevant for statistics.
+fn random_below(bound: usize, rng: &mut impl EntropySource) -> usize {
+    let mut bytes = [0u8; 8];
+    rng.fill_bytes(&mut bytes);
+    (u64::from_le_bytes(bytes) % bound as u64) as usize
+}
+
+/// `count` distinct positions in `0..bound` (partial Fisher-Yates).
+fn random_positions(bound: usize, count: usize, rng: &mut impl EntropySource) -> Vec<usize> {
+    let mut pool: Vec<usize> = (0..bound).collect();
+    for i in 0..count.min(bound) {
+        let j = i + random_below(bound - i, rng);
+        pool.swap(i, j);
+    }
+    pool.truncate(count);
+    pool
+}
+
+/// Most-common-value estimate: `-log2` of the 99% upper bound on `p_max`.
+fn mcv_min_entropy(p_max: f64, n: usize) -> f64 {
+    let spread = if n > 1 {
+        MCV_Z * (p_max * (1.0 - p_max) / (n - 1) as f64).sqrt()
+    } else {
+        1.0
+    };
+    -(p_max + spread).min(1.0).log2()
+}
+
+/// P[A - B >= at_least] for independent A ~ Binomial(`towards`, p) and
+/// B ~ Binomial(`back`, p).
+fn net_flips_at_least(towards: usize, back: usize, p: f64, at_least: usize) -> f64 {
+    // Upper tails of A, then one term per value of B.
+    let mut tail = binomial_pmf(towards, p);
+    for k in (0..towards).rev() {
+        tail[k] += tail[k + 1];
+    }
+    let total: f64 = binomial_pmf(back, p)
+        .iter()
+        .enumerate()
+        .map(|(b, pb)| pb * tail.get(at_least + b).copied().unwrap_or(0.0))
+        .sum();
+    total.min(1.0)
+}
+
+/// P[Binomial(n, p) = k] for every `k` in `0..=n`.
+fn binomial_pmf(n: usize, p: f64) -> Vec<f64> {
+    if p <= 0.0 || p >= 1.0 {
+        let mut pmf = vec![0.0; n + 1];
+        pmf[if p <= 0.0 { 0 } else { n }] = 1.0;
+        return pmf;
+    }
+    let (ln_p, ln_q) = (p.ln(), (1.0 - p).ln());
+    let mut ln_choose = 0.0; // ln C(n, k), updated incrementally.
+    (0..=n)
+        .map(|k| {
+            if k > 0 {
+                ln_choose += ((n - k + 1) as f64).ln() - (k as f64).ln();
+            }
+            (ln_choose + k as f64 * ln_p + (n - k) as f64 * ln_q).exp()
+        })
+        .collect()
+}
+
+/// P[Binomial(n, p) >= at_least].
+pub(crate) fn binomial_upper_tail(n: usize, p: f64, at_least: usize) -> f64 {
+    if at_least == 0 {
+        return 1.0;
+    }
+    if at_least > n {
+        return 0.0;
+    }
+    if p <= 0.0 {
+        return 0.0;
+    }
+    if p >= 1.0 {
+        return 1.0;
+    }
+    let (ln_p, ln_q) = (p.ln(), (1.0 - p).ln());
+    let mut ln_choose = 0.0; // ln C(n, k), updated incrementally.
+    let mut total = 0.0;
+    for k in 0..=n {
+        if k > 0 {
+            ln_choose += ((n - k + 1) as f64).ln() - (k as f64).ln();
+        }
+        if k >= at_least {
+            total += (ln_choose + k as f64 * ln_p + (n - k) as f64 * ln_q).exp();
+        }
+    }
+    total.min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    #[test]
+    fn test_constant_corpus_has_no_entropy() {
+        let samples = vec![[0x3Cu8; 16]; 200];
+        let estimate = estimate_min_entropy(samples.iter().map(|s| s.as_slice()), 16, 0.1);
+        assert_eq!(estimate.sample_count, 200);
+        assert_eq!(estimate.distinct_outputs, 1);
+        assert!(estimate.bits < 0.05);
+    }
+
+    #[test]
+    fn test_structured_corpus_entropy_is_bounded_by_chunks() {
+        // Each sample sets a random subset of its eight 2-byte chunks to all ones.
+        let mut rng = seeded(7);
+        let samples: Vec<Vec<u8>> = (0..5000)
+            .map(|_| {
+                let mut pick = [0u8; 1];
+                rng(&mut pick);
+                (0..16)
+                    .map(|i| {
+                        if (pick[0] >> (i / 2)) & 1 == 1 {
+                            0xFF
+                        } else {
+                            0
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let estimate = estimate_min_entropy(samples.iter().map(Vec::as_slice), 16, 0.25);
+        assert_eq!(estimate.per_chunk_bits.len(), 8);
+        assert!(estimate.bits <= 8.0);
+        assert!(estimate.bits > 5.0, "got {}", estimate.bits);
+    }
+
+    #[test]
+    fn test_samples_of_other_lengths_are_ignored() {
+        let samples: Vec<&[u8]> = vec![&[1, 2, 3], &[1, 2]];
+        let estimate = estimate_min_entropy(samples, 3, 0.1);
+        assert_eq!(estimate.sample_count, 1);
+    }
+
+    #[test]
+    fn test_biased_bits_model() {
+        // Uniform bits: the one-sided threshold makes nearly every chunk 1.
+        let uniform = min_entropy_for_biased_bits(16, 0.05, 0.5);
+        assert_eq!(uniform.per_chunk_bits.len(), 8);
+        assert!(uniform.bits < 0.01);
+        // Very sparse bits: thresholds sit in the middle of the distribution.
+        let sparse = min_entropy_for_biased_bits(16, 0.05, 0.03);
+        assert!(sparse.bits > 4.0, "got {}", sparse.bits);
+        assert!(min_entropy_for_biased_bits(0, 0.05, 0.5).bits == 0.0);
+    }
+
+    #[test]
+    fn test_leakage_follows_the_level_distribution() {
+        let config = TbfConfig::new(0.125);
+        // Parity levels of unbiased bits are fair coins.
+        let report = leakage(64, &config.with_thresholding(Thresholding::Parity), 0.5);
+        assert_eq!(report.per_chunk_bits, vec![1.0; 8]);
+        assert_eq!((report.digest_bits, report.helper_bits), (8.0, 0.0));
+
+        // Nearly all-ones input almost always collapses to all ones.
+        let biased = leakage(64, &config, 0.99);
+        assert!(biased.digest_bits < 0.01, "got {}", biased.digest_bits);
+        assert!(biased.input_bits > 40.0);
+
+        let hashed = leakage(64, &config.with_transform(FinalTransform::Hash), 0.3);
+        let keyed = config.with_transform(FinalTransform::KeyedPrf { key: [1; 32] });
+        let keyed = leakage(64, &keyed, 0.3);
+        assert!(hashed.digest_bits > 0.0);
+        assert_eq!(keyed.per_chunk_bits, hashed.per_chunk_bits);
+        assert_eq!(keyed.digest_bits, 0.0);
+
+        let empty = leakage(0, &config, 0.5);
+        assert!(empty.per_chunk_bits.is_empty() && empty.digest_bits == 0.0);
+    }
+
+    #[test]
+    fn test_explain_lists_disagreeing_chunks() {
+        let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+        let (mut a, mut b) = ([0u8; 32], [0u8; 32]);
+        a[..4].fill(0xFF);
+        b[..4].fill(0xFF);
+        b[12..16].fill(0xFF);
+        b[1] = 0xFE;
+        let report = explain(&a, &b, &config);
+        assert!(!report.matches && !report.lengths_differ);
+        assert_eq!(report.chunk_count, 8);
+        assert_eq!(
+            report.mismatches,
+            vec![ChunkMismatch {
+                index: 3,
+                bits: 32,
+                popcounts: (0, 32),
+                threshold: 17,
+                levels: (0, 1),
+                differing_bits: 32,
+            }]
+        );
+        // No tolerance moves a symmetric threshold.
+        assert_eq!(report.matching_tolerance, None);
+
+        let same = explain(&a, &a, &config);
+        assert!(same.matches && same.mismatches.is_empty());
+        assert_eq!(same.matching_tolerance, Some(0.125));
+        let other = explain(&a, &a[1..], &config);
+        assert!(other.lengths_differ && !other.matches);
+        assert_eq!(other.matching_tolerance, None);
+
+        // 3 and 6 set bits of 32 straddle the 12.5% threshold of 4, and
+        // stop straddling it below 9.4% or from 18.8%.
+        let one_sided = config.with_thresholding(Thresholding::OneSided);
+        let (mut a, mut b) = ([0u8; 32], [0u8; 32]);
+        a[0] = 0x07;
+        b[0] = 0x3F;
+        let report = explain(&a, &b, &one_sided);
+        assert_eq!(report.mismatches[0].threshold, 4);
+        assert_eq!(report.matching_tolerance, Some(0.093));
+    }
+
+    #[test]
+    fn test_sweep_agrees_with_collapsing() {
+        let mut rng = seeded(406);
+        let (mut a, mut b) = ([0u8; 48], [0u8; 48]);
+        rng(&mut a);
+        // Chunk 0 holds 12 and 4 set bits of 48; the rest are equal.
+        a[..6].copy_from_slice(&[0xFF, 0x0F, 0, 0, 0, 0]);
+        b.copy_from_slice(&a);
+        b[..2].copy_from_slice(&[0x0F, 0]);
+        let tolerances: Vec<f32> = (50..=250).step_by(7).map(|p| p as f32 / 1000.0).collect();
+        let swept = sweep(&a, &b, tolerances.iter().copied());
+        assert_eq!(swept.len(), tolerances.len());
+        for (&tolerance, &(reported, matches)) in tolerances.iter().zip(&swept) {
+            assert_eq!(reported, tolerance);
+            let collapse = |input: &[u8]| crate::collapse_ct(input, tolerance);
+            assert_eq!(matches, collapse(&a) == collapse(&b));
+        }
+        assert!(swept.iter().any(|&(_, m)| m) && swept.iter().any(|&(_, m)| !m));
+
+        assert_eq!(sweep(&[], &[], [0.01]), [(0.05, true)]);
+        assert_eq!(sweep(&a, &a[1..], [0.1]), [(0.1, false)]);
+    }
+
+    #[test]
+    fn test_statistics_report() {
+        let one_sided = TbfConfig::new(0.05);
+        let report = measure_statistics(&one_sided, 16, 300, &mut seeded(11));
+        assert_eq!(report.trials, 300);
+        assert_eq!(report.bit_bias.len(), 128);
+        let distances: Vec<usize> = report.collision_curve.iter().map(|p| p.distance).collect();
+        assert_eq!(distances, [1, 2, 4, 6, 8, 16, 32, 64]);
+        // Uniform inputs almost always clear the 5% threshold, so outputs
+        // are nearly constant: heavy bias, no avalanche, collisions everywhere.
+        assert!(report.max_bias > 0.4);
+        assert!(report.avalanche < 0.05);
+        assert!(report.single_flip_stability > 0.9);
+        assert!(report.collision_curve.iter().all(|p| p.rate > 0.9));
+
+        // Symmetric thresholds sit at the middle of uniform chunks instead.
+        let report = measure_statistics(
+            &one_sided.with_thresholding(Thresholding::Symmetric),
+            16,
+            300,
+            &mut seeded(11),
+        );
+        assert!(report.max_bias < 0.25, "got {}", report.max_bias);
+        assert!(report.avalanche > 0.01, "got {}", report.avalanche);
+        let far = report.collision_curve.last().expect("non-empty");
+        assert!(far.rate < 0.1, "got {}", far.rate);
+
+        let empty = measure_statistics(&TbfConfig::default(), 0, 10, &mut seeded(1));
+        assert!(empty.collision_curve.is_empty() && empty.bit_bias.is_empty());
+    }
+
+    #[test]
+    fn test_calibrate_separates_labeled_pairs() {
+        // Pairs share random bytes and differ in how many leading bits of
+        // the first 16-bit chunk are set. Genuine pairs set 1 vs 2 or 2 vs 3
+        // bits, impostors 0 vs 3: only threshold 1 (the strictest
+        // tolerances) matches every genuine pair and no impostor.
+        let mut rng = seeded(3);
+        let mut pair = |ones_a: u32, ones_b: u32| {
+            let mut a = vec![0u8; 16];
+            rng(&mut a[2..]);
+            let mut b = a.clone();
+            a[0] = !(0xFF >> ones_a);
+            b[0] = !(0xFF >> ones_b);
+            (a, b)
+        };
+        let mut genuine = Vec::new();
+        let mut impostor = Vec::new();
+        for i in 0..20 {
+            genuine.push(if i % 2 == 0 { pair(1, 2) } else { pair(2, 3) });
+            impostor.push(pair(0, 3));
+        }
+        fn borrow(pairs: &[(Vec<u8>, Vec<u8>)]) -> Vec<(&[u8], &[u8])> {
+            pairs
+                .iter()
+                .map(|(a, b)| (a.as_slice(), b.as_slice()))
+                .collect()
+        }
+        let config = calibrate(&borrow(&genuine), &borrow(&impostor));
+        assert_eq!(config.tolerance(), 0.05);
+        assert_eq!(config.thresholding(), Thresholding::OneSided);
+
+        assert_eq!(calibrate(&[], &[]), TbfConfig::default());
+    }
+
+    #[test]
+    fn test_calibrate_layout_gathers_noisy_bits() {
+        // One bit of every contiguous chunk is noise, so any chunk at its
+        // threshold flips. A layout gathering the noisy bits in fewer chunks
+        // matches more unseen readings of a source.
+        let mut fill = seeded(417);
+        let mut pairs = |count: usize| {
+            let (mut genuine, mut impostor) = (Vec::new(), Vec::new());
+            for _ in 0..count {
+                let mut a = vec![0u8; 16];
+                fill(&mut a);
+                let mut noise = [0u8; 1];
+                fill(&mut noise);
+                let b: Vec<u8> = (0..16)
+                    .map(|i| a[i] ^ (i % 2 == 0) as u8 & noise[0] >> (i / 2))
+                    .collect();
+                let mut c = vec![0u8; 16];
+                fill(&mut c);
+                genuine.push((a.clone(), b));
+                impostor.push((a, c));
+            }
+            (genuine, impostor)
+        };
+        fn borrow(pairs: &[(Vec<u8>, Vec<u8>)]) -> Vec<(&[u8], &[u8])> {
+            pairs
+                .iter()
+                .map(|(a, b)| (a.as_slice(), b.as_slice()))
+                .collect()
+        }
+        let (genuine, impostor) = pairs(200);
+        let (held_genuine, held_impostor) = pairs(100);
+        let config = TbfConfig::default().with_thresholding(Thresholding::Symmetric);
+        let layout = calibrate_layout(
+            &borrow(&genuine),
+            &borrow(&impostor),
+            &config,
+            3000,
+            &mut seeded(5),
+        )
+        .unwrap();
+        let contiguous = LearnedLayout::contiguous(&config, 16).unwrap();
+        let matches = |layout: &LearnedLayout, pairs: &[(Vec<u8>, Vec<u8>)]| {
+            pairs
+                .iter()
+                .filter(|(a, b)| layout.collapse(a) == layout.collapse(b))
+                .count()
+        };
+        assert!(matches(&layout, &held_genuine) > matches(&contiguous, &held_genuine));
+        assert!(matches(&layout, &held_impostor) <= 2);
+        assert!(matches(&layout, &held_impostor) <= 2);
+
+        assert!(calibrate_layout(&[], &[], &config, 10, &mut seeded(0)).is_err());
+        let uneven: [(&[u8], &[u8]); 1] = [(&[0; 4], &[0; 5])];
+        assert_eq!(
+            calibrate_layout(&uneven, &[], &config, 10, &mut seeded(0)),
+            Err(Error::InvalidLength {
+                expected: 4,
+                found: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_stability_follows_margins() {
+        let config = TbfConfig::new(0.125);
+        // Threshold 2 of 16 bits: an empty chunk changes when 2 of its 16
+        // bits flip up, a full one never does.
+        let mut input = [0u8; 16];
+        input[..2].fill(0xFF);
+        let report = stability(&input, &config);
+        assert_eq!(report.margins, [15, 2, 2, 2, 2, 2, 2, 2]);
+        let empty = binomial_upper_tail(16, 0.125, 2);
+        assert!((report.per_chunk_change_probability[1] - empty).abs() < 1e-12);
+        let expected = 1.0 - (1.0 - empty).powi(7) * (1.0 - report.per_chunk_change_probability[0]);
+        assert!((report.change_probability - expected).abs() < 1e-12);
+        assert!(report.per_chunk_change_probability[0] < 1e-9);
+
+        // 2 set bits of 16 with threshold 2: losing one drops the chunk
+        // unless another bit flips up to compensate.
+        input[2] = 0x03;
+        let edge = stability(&input, &config).per_chunk_change_probability[1];
+        assert!((edge - net_flips_at_least(2, 14, 0.125, 1)).abs() < 1e-12);
+        assert!(edge < binomial_upper_tail(2, 0.125, 1));
+
+        let parity = config.with_thresholding(Thresholding::Parity);
+        let odd = stability(&input, &parity).per_chunk_change_probability[0];
+        assert!((odd - (1.0 - 0.75f64.powi(16)) / 2.0).abs() < 1e-12);
+        assert_eq!(stability(&[], &config).change_probability, 0.0);
+    }
+
+    #[test]
+    fn test_evaluate_traces_the_roc_curve() {
+        use crate::dataset::{NoiseModel, PairGenerator};
+        use crate::noise::Seeded;
+
+        let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+        let pairs: Vec<_> = PairGenerator::new(32, NoiseModel::Flips(6), Seeded::new(4))
+            .take(400)
+            .collect();
+        let labeled = |genuine: bool| -> Vec<(&[u8], &[u8])> {
+            pairs
+                .iter()
+                .filter(|pair| pair.genuine == genuine)
+                .map(|pair| (&pair.a[..], &pair.b[..]))
+                .collect()
+        };
+        let (genuine, impostor) = (labeled(true), labeled(false));
+        let report = evaluate(&config, &genuine, &impostor);
+
+        let rate = |pairs: &[(&[u8], &[u8])], identical: bool| {
+            pairs
+                .iter()
+                .filter(|(a, b)| (config.collapse(a) == config.collapse(b)) == identical)
+                .count() as f64
+                / pairs.len() as f64
+        };
+        assert_eq!(report.false_accept_rate, rate(&impostor, true));
+        assert_eq!(report.false_reject_rate, rate(&genuine, false));
+        assert!(report.false_reject_rate > 0.0);
+        let last = report.curve.last().unwrap();
+        assert_eq!(last.threshold, 1.0);
+        assert_eq!(last.false_reject_rate, report.false_reject_rate);
+
+        // Raising the threshold only trades false accepts for false rejects.
+        assert_eq!(report.curve[0].false_reject_rate, 0.0);
+        assert_eq!(report.curve[0].false_accept_rate, 1.0);
+        for step in report.curve.windows(2) {
+            assert!(step[0].threshold < step[1].threshold);
+            assert!(step[0].false_accept_rate >= step[1].false_accept_rate);
+            assert!(step[0].false_reject_rate <= step[1].false_reject_rate);
+        }
+        // Partial credit for near misses beats exact matching.
+        assert!(report.equal_error_rate < report.false_reject_rate);
+
+        let empty = evaluate(&config, &[], &[]);
+        assert_eq!(
+            (empty.false_accept_rate, empty.equal_error_rate),
+            (0.0, 0.0)
+        );
+        assert!(empty.curve.is_empty());
+    }
+
+    #[test]
+    fn test_drift_counts_pairs_and_flags_shifts() {
+        use crate::entropy::EntropySource;
+        use crate::noise::Seeded;
+
+        let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+        let mut rng = Seeded::new(439);
+        let mut corpus = |stuck: bool| -> Vec<(u64, Vec<u8>)> {
+            let mut out = Vec::new();
+            for source in 0..8u64 {
+                let mut base = [0u8; 16];
+                rng.fill_bytes(&mut base);
+                for _ in 0..4 {
+                    let mut capture = base.to_vec();
+                    flip_bit(&mut capture, random_below(128, &mut rng));
+                    if stuck {
+                        capture[..2].fill(0xFF);
+                    }
+                    out.push((source, capture));
+                }
+            }
+            out
+        };
+        fn borrow(corpus: &[(u64, Vec<u8>)]) -> Vec<(u64, &[u8])> {
+            corpus.iter().map(|(s, c)| (*s, &c[..])).collect()
+        }
+        let (old, new) = (corpus(false), corpus(true));
+        let (old, new) = (borrow(&old), borrow(&new));
+
+        // Counted pair by pair.
+        let brute = |corpus: &[(u64, &[u8])], genuine: bool| {
+            let (mut pairs, mut matches) = (0, 0);
+            for (i, (sa, a)) in corpus.iter().enumerate() {
+                for (sb, b) in &corpus[i + 1..] {
+                    if (sa == sb) == genuine {
+                        pairs += 1;
+                        matches += usize::from(config.collapse(a) == config.collapse(b));
+                    }
+                }
+            }
+            matches as f64 / pairs as f64
+        };
+        let same = drift(&config, &old, &old).unwrap();
+        assert_eq!(same.baseline, same.current);
+        assert_eq!(same.baseline.match_rate, Some(brute(&old, true)));
+        assert_eq!(same.baseline.false_match_rate, Some(brute(&old, false)));
+        assert_eq!((same.baseline.captures, same.baseline.sources), (32, 8));
+        assert_eq!((same.max_bias_shift, same.match_rate_drop), (0.0, 0.0));
+        assert_eq!(same.recommendation, Recommendation::Keep);
+
+        // The first chunk of every new capture is stuck at 1.
+        let shifted = drift(&config, &old, &new).unwrap();
+        assert_eq!(shifted.current.chunk_bias[0], 1.0);
+        assert_eq!(shifted.bias_shift[0], 1.0 - shifted.baseline.chunk_bias[0]);
+        assert!(shifted.max_bias_shift > 0.2);
+        assert_eq!(shifted.recommendation, Recommendation::Recalibrate);
+
+        assert!(drift(&config, &[], &old).is_err());
+        assert!(drift(&config, &old, &[]).is_err());
+        let short = [(0u64, &[0u8; 8][..])];
+        assert_eq!(
+            drift(&config, &old, &short),
+            Err(Error::InvalidLength {
+                expected: 16,
+                found: 8
+            })
+        );
+    }
+
+    #[test]
+    fn test_binomial_upper_tail() {
+        assert!((binomial_upper_tail(2, 0.5, 1) - 0.75).abs() < 1e-12);
+        assert_eq!(binomial_upper_tail(5, 0.3, 0), 1.0);
+        assert_eq!(binomial_upper_tail(5, 0.3, 6), 0.0);
+    }
+}
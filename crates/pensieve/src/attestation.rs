@@ -0,0 +1,305 @@
+//! Signed attestations of digests.
+//!
+//! An enrollment record in a fuzzy-authentication system is only as
+//! trustworthy as whoever wrote it: anyone who can write to the store can
+//! enroll a digest of their own input. An [`Attestation`] binds a
+//! [`DigestEnvelope`], which carries the digest and its parameters, and the
+//! time it was taken to an Ed25519 signature of the enrolling device, so a
+//! verifier holding the device's [`VerifyingKey`] can tell who produced a
+//! record and that it was not altered since.
+//!
+//! The signed message is a domain separation label, the timestamp and the
+//! envelope's [`encode`](DigestEnvelope::encode)ing. Timestamps are Unix
+//! time in seconds, as stated by the signer; checking them against a
+//! clock or an enrollment window is up to the verifier.
+//!
+//! Unlike a [`tag`](DigestEnvelope::tag), which anyone holding its key can
+//! produce, a signature can be checked without being able to forge one.
+
+use crate::crypto::ed25519::{self, SIGNATURE_LEN};
+use crate::entropy::EntropySource;
+use crate::envelope::DigestEnvelope;
+use crate::error::Error;
+use alloc::vec::Vec;
+
+/// Domain separation for signed messages.
+const SIGNATURE_LABEL: &[u8] = b"pensieve/attestation/v1";
+
+/// Bytes before the envelope: timestamp and envelope length.
+const HEADER_LEN: usize = 12;
+
+/// An Ed25519 secret key of an attesting device.
+#[derive(Clone)]
+pub struct SigningKey {
+    seed: [u8; 32],
+    verifying_key: VerifyingKey, // Derived from `seed`.
+}
+
+impl SigningKey {
+    /// A fresh key drawn from `rng`.
+    pub fn generate(rng: &mut impl EntropySource) -> Self {
+        let mut seed = [0u8; 32];
+        rng.fill_bytes(&mut seed);
+        Self::from_seed(seed)
+    }
+
+    /// The key with the 32-byte secret `seed` of RFC 8032.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        let verifying_key = VerifyingKey(ed25519::public_key(&seed));
+        Self {
+            seed,
+            verifying_key,
+        }
+    }
+
+    /// The secret seed, for storing the key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.seed
+    }
+
+    /// The public key verifiers check attestations with.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.verifying_key
+    }
+
+    /// Signs `envelope` as taken at `unix_seconds`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::attestation::{Attestation, SigningKey};
+    /// use pensieve::envelope::DigestEnvelope;
+    /// use pensieve::TbfConfig;
+    ///
+    /// let device = SigningKey::from_seed([7; 32]);
+    /// let envelope = DigestEnvelope::seal(&[0x5A; 16], &TbfConfig::new(0.125));
+    /// let record = device.attest(&envelope, 1_700_000_000).to_bytes();
+    ///
+    /// // The verifier knows the device's public key.
+    /// let attestation = Attestation::from_bytes(&record).unwrap();
+    /// assert!(attestation.verify(&device.verifying_key()));
+    /// assert!(attestation.envelope().verify(&[0x5A; 16]));
+    /// assert_eq!(attestation.timestamp(), 1_700_000_000);
+    /// ```
+    pub fn attest(&self, envelope: &DigestEnvelope, unix_seconds: i64) -> Attestation {
+        let encoded = envelope.encode();
+        let signature = ed25519::sign(&self.seed, &[&message(unix_seconds, &encoded)]);
+        Attestation {
+            envelope: envelope.clone(),
+            timestamp: unix_seconds,
+            signature,
+        }
+    }
+}
+
+impl core::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("SigningKey")
+            .field(&self.verifying_key)
+            .finish()
+    }
+}
+
+/// An Ed25519 public key, identifying an attesting device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VerifyingKey([u8; 32]);
+
+impl VerifyingKey {
+    /// Length of the serialized form.
+    pub const ENCODED_LEN: usize = 32;
+
+    /// The 32-byte encoding of RFC 8032.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        self.0
+    }
+
+    /// Parses the output of [`VerifyingKey::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidParameter`] if the bytes do not encode a
+    /// point of the curve.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let key: [u8; 32] = bytes.try_into().map_err(|_| Error::InvalidLength {
+            expected: Self::ENCODED_LEN,
+            found: bytes.len(),
+        })?;
+        if !ed25519::is_public_key(&key) {
+            return Err(Error::InvalidParameter("not an Ed25519 public key"));
+        }
+        Ok(Self(key))
+    }
+}
+
+/// A digest envelope and its timestamp, signed by a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attestation {
+    envelope: DigestEnvelope,
+    timestamp: i64, // Unix seconds.
+    signature: [u8; SIGNATURE_LEN],
+}
+
+impl Attestation {
+    /// The attested envelope.
+    pub fn envelope(&self) -> &DigestEnvelope {
+        &self.envelope
+    }
+
+    /// The time the signer attested the envelope at, in Unix seconds.
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    /// The Ed25519 signature.
+    pub fn signature(&self) -> &[u8; SIGNATURE_LEN] {
+        &self.signature
+    }
+
+    /// Whether the attestation was signed by the holder of the secret key
+    /// of `key`, and neither the envelope nor the timestamp changed since.
+    pub fn verify(&self, key: &VerifyingKey) -> bool {
+        let encoded = self.envelope.encode();
+        ed25519::verify(
+            &key.0,
+            &[&message(self.timestamp, &encoded)],
+            &self.signature,
+        )
+    }
+
+    /// Serializes as the timestamp (8 bytes) and envelope length (4 bytes),
+    /// little-endian, the [encoded](DigestEnvelope::encode) envelope and
+    /// the signature.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let encoded = self.envelope.encode();
+        let len = u32::try_from(encoded.len()).expect("envelope length fits in u32");
+        let mut out = Vec::with_capacity(HEADER_LEN + encoded.len() + SIGNATURE_LEN);
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&encoded);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    /// Parses the output of [`Attestation::to_bytes`], decoding the envelope
+    /// with [`DigestEnvelope::decode`]. The signature is not checked here.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, DigestEnvelope::decode)
+    }
+
+    /// [`Attestation::from_bytes`] for envelopes that may hold a
+    /// [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) digest,
+    /// whose key is `key`.
+    pub fn from_bytes_keyed(bytes: &[u8], key: &[u8; 32]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, |encoded| DigestEnvelope::decode_keyed(encoded, key))
+    }
+
+    fn from_bytes_with(
+        bytes: &[u8],
+        decode: impl FnOnce(&[u8]) -> Result<DigestEnvelope, Error>,
+    ) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::InvalidLength {
+                expected: HEADER_LEN,
+                found: bytes.len(),
+            });
+        }
+        let timestamp = i64::from_le_bytes(bytes[..8].try_into().expect("8 bytes"));
+        let len = u32::from_le_bytes(bytes[8..12].try_into().expect("4 bytes")) as usize;
+        let expected = len
+            .checked_add(HEADER_LEN + SIGNATURE_LEN)
+            .ok_or(Error::InvalidParameter("envelope length overflows"))?;
+        if bytes.len() != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+        let envelope = decode(&bytes[HEADER_LEN..HEADER_LEN + len])?;
+        let signature = bytes[HEADER_LEN + len..].try_into().expect("64 bytes");
+        Ok(Self {
+            envelope,
+            timestamp,
+            signature,
+        })
+    }
+}
+
+/// The signed message for `encoded` taken at `unix_seconds`.
+fn message(unix_seconds: i64, encoded: &[u8]) -> Vec<u8> {
+    [SIGNATURE_LABEL, &unix_seconds.to_le_bytes(), encoded].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use crate::{FinalTransform, TbfConfig};
+
+    #[test]
+    fn test_attestation_roundtrip() {
+        let device = SigningKey::generate(&mut seeded(1));
+        let other = SigningKey::generate(&mut seeded(2));
+        let envelope = DigestEnvelope::seal(&[0x11; 24], &TbfConfig::new(0.2));
+        let attestation = device.attest(&envelope, -86_400);
+        assert!(attestation.verify(&device.verifying_key()));
+        assert!(!attestation.verify(&other.verifying_key()));
+
+        let bytes = attestation.to_bytes();
+        assert_eq!(Attestation::from_bytes(&bytes), Ok(attestation.clone()));
+        assert_eq!(
+            Attestation::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::InvalidLength {
+                expected: bytes.len(),
+                found: bytes.len() - 1
+            })
+        );
+        assert!(Attestation::from_bytes(&bytes[..4]).is_err());
+
+        let key = VerifyingKey::from_bytes(&device.verifying_key().to_bytes()).unwrap();
+        assert_eq!(key, device.verifying_key());
+        assert_eq!(
+            SigningKey::from_seed(*device.as_bytes()).verifying_key(),
+            key
+        );
+        assert!(VerifyingKey::from_bytes(&[0xff; 32]).is_err());
+        assert!(VerifyingKey::from_bytes(&[0; 31]).is_err());
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let device = SigningKey::from_seed([3; 32]);
+        let key = device.verifying_key();
+        let envelope = DigestEnvelope::seal(&[0x00; 16], &TbfConfig::new(0.125));
+        let bytes = device.attest(&envelope, 1_700_000_000).to_bytes();
+
+        let mut later = bytes.clone();
+        later[0] ^= 1;
+        assert!(!Attestation::from_bytes(&later).unwrap().verify(&key));
+        let mut forged_signature = bytes.clone();
+        *forged_signature.last_mut().unwrap() ^= 1;
+        assert!(
+            !Attestation::from_bytes(&forged_signature)
+                .unwrap()
+                .verify(&key)
+        );
+
+        // Swapping in another digest, with its signature left in place.
+        let other = DigestEnvelope::seal(&[0xff; 16], &TbfConfig::new(0.125));
+        let swapped = Attestation {
+            envelope: other,
+            ..Attestation::from_bytes(&bytes).unwrap()
+        };
+        assert!(!swapped.verify(&key));
+    }
+
+    #[test]
+    fn test_keyed_envelopes() {
+        let prf_key = [9u8; 32];
+        let config =
+            TbfConfig::new(0.125).with_transform(FinalTransform::KeyedPrf { key: prf_key });
+        let device = SigningKey::from_seed([4; 32]);
+        let attestation = device.attest(&DigestEnvelope::seal(&[0x5A; 16], &config), 0);
+        let bytes = attestation.to_bytes();
+        assert!(Attestation::from_bytes(&bytes).is_err());
+        let decoded = Attestation::from_bytes_keyed(&bytes, &prf_key).unwrap();
+        assert!(decoded.verify(&device.verifying_key()));
+        assert!(decoded.envelope().verify(&[0x5A; 16]));
+    }
+}
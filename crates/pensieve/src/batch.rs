@@ -0,0 +1,264 @@
+//! Batched collapse and Hamming scoring behind exchangeable backends.
+//!
+//! Corpora of hundreds of millions of fingerprints are collapsed and
+//! scored in large batches, which accelerators such as GPUs process far
+//! faster than a loop over [`TbfConfig::collapse`]. A [`BatchBackend`]
+//! performs both operations on whole batches; [`CpuBackend`] is the
+//! reference implementation on top of the collapse and
+//! [`hamming`]. It is the only backend so far; an accelerated one
+//! implements the same trait.
+//!
+//! Whatever a backend computes must be bit for bit what the CPU computes.
+//! [`conformance`] checks that against the shared
+//! [`test_vectors`](crate::test_vectors), and [`WithFallback`] runs a
+//! backend with the CPU standing in whenever it fails, e.g. because no
+//! adapter is available.
+
+use crate::distance::hamming;
+use crate::test_vectors::{TestVector, generate};
+use crate::{Error, TbfConfig};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// An implementation of batched collapse and scoring.
+pub trait BatchBackend {
+    /// Short name for logs and metrics, such as `"cpu"`.
+    fn name(&self) -> &'static str;
+
+    /// The digest of every input under `config`, in order. Inputs may
+    /// differ in length.
+    fn collapse_batch(&self, config: &TbfConfig, inputs: &[&[u8]]) -> Result<Vec<Vec<u8>>, Error>;
+
+    /// The Hamming distance from `query` to every candidate, in order.
+    ///
+    /// Fails with [`Error::InvalidLength`] if a candidate is not as long as
+    /// `query`.
+    fn hamming_batch(&self, query: &[u8], candidates: &[&[u8]]) -> Result<Vec<u32>, Error>;
+}
+
+/// The reference backend, running on the calling thread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuBackend;
+
+impl BatchBackend for CpuBackend {
+    fn name(&self) -> &'static str {
+        "cpu"
+    }
+
+    fn collapse_batch(&self, config: &TbfConfig, inputs: &[&[u8]]) -> Result<Vec<Vec<u8>>, Error> {
+        Ok(inputs.iter().map(|input| config.collapse(input)).collect())
+    }
+
+    fn hamming_batch(&self, query: &[u8], candidates: &[&[u8]]) -> Result<Vec<u32>, Error> {
+        candidates
+            .iter()
+            .map(|candidate| {
+                if candidate.len() != query.len() {
+                    return Err(Error::InvalidLength {
+                        expected: query.len(),
+                        found: candidate.len(),
+                    });
+                }
+                Ok(hamming(query, candidate))
+            })
+            .collect()
+    }
+}
+
+/// A backend whose failed batches are redone by [`CpuBackend`].
+///
+/// A batch the CPU rejects as well, such as one with a candidate of the
+/// wrong length, still fails, with the CPU's error.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::batch::{BatchBackend, CpuBackend, WithFallback};
+/// use pensieve::Error;
+///
+/// /// A GPU backend on a machine without an adapter.
+/// struct NoAdapter;
+///
+/// impl BatchBackend for NoAdapter {
+///     fn name(&self) -> &'static str {
+///         "gpu"
+///     }
+///     fn collapse_batch(&self, _: &TbfConfig, _: &[&[u8]]) -> Result<Vec<Vec<u8>>, Error> {
+///         Err(Error::InvalidParameter("no adapter"))
+///     }
+///     fn hamming_batch(&self, _: &[u8], _: &[&[u8]]) -> Result<Vec<u32>, Error> {
+///         Err(Error::InvalidParameter("no adapter"))
+///     }
+/// }
+///
+/// let backend = WithFallback::new(NoAdapter);
+/// let config = TbfConfig::default();
+/// let inputs: [&[u8]; 2] = [&[0xF0; 16], &[0x0F; 16]];
+/// assert_eq!(
+///     backend.collapse_batch(&config, &inputs),
+///     CpuBackend.collapse_batch(&config, &inputs)
+/// );
+/// assert_eq!(backend.fallbacks(), 1);
+/// ```
+#[derive(Debug)]
+pub struct WithFallback<B> {
+    primary: B,
+    fallbacks: AtomicUsize,
+}
+
+impl<B: BatchBackend> WithFallback<B> {
+    /// Runs batches on `primary`, and on the CPU where it fails.
+    pub fn new(primary: B) -> Self {
+        Self {
+            primary,
+            fallbacks: AtomicUsize::new(0),
+        }
+    }
+
+    /// The backend tried first.
+    pub fn primary(&self) -> &B {
+        &self.primary
+    }
+
+    /// Number of batches the CPU has redone so far.
+    pub fn fallbacks(&self) -> usize {
+        self.fallbacks.load(Ordering::Relaxed)
+    }
+
+    fn fall_back<T>(
+        &self,
+        result: Result<T, Error>,
+        cpu: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        result.or_else(|_| {
+            self.fallbacks.fetch_add(1, Ordering::Relaxed);
+            cpu()
+        })
+    }
+}
+
+impl<B: BatchBackend> BatchBackend for WithFallback<B> {
+    fn name(&self) -> &'static str {
+        self.primary.name()
+    }
+
+    fn collapse_batch(&self, config: &TbfConfig, inputs: &[&[u8]]) -> Result<Vec<Vec<u8>>, Error> {
+        self.fall_back(self.primary.collapse_batch(config, inputs), || {
+            CpuBackend.collapse_batch(config, inputs)
+        })
+    }
+
+    fn hamming_batch(&self, query: &[u8], candidates: &[&[u8]]) -> Result<Vec<u32>, Error> {
+        self.fall_back(self.primary.hamming_batch(query, candidates), || {
+            CpuBackend.hamming_batch(query, candidates)
+        })
+    }
+}
+
+/// Indices of the [`test_vectors::generate`](crate::test_vectors::generate)
+/// vectors `backend` gets wrong; empty when it conforms.
+///
+/// Every vector is collapsed under its tolerance with the default
+/// configuration, in a single batch per
+/// tolerance, and the distance from its input to every other input of its
+/// length is scored in a single batch, both compared with [`CpuBackend`].
+/// A batch that fails counts against every vector in it.
+pub fn conformance(backend: &impl BatchBackend) -> Vec<usize> {
+    let vectors = generate();
+    let mut failed = alloc::vec![false; vectors.len()];
+
+    for group in groups(&vectors, |v| v.tolerance.to_bits()) {
+        let config = TbfConfig::new(vectors[group[0]].tolerance);
+        let inputs: Vec<&[u8]> = group.iter().map(|&i| &vectors[i].input[..]).collect();
+        match backend.collapse_batch(&config, &inputs) {
+            Ok(outputs) if outputs.len() == group.len() => {
+                for (&i, output) in group.iter().zip(outputs) {
+                    failed[i] |= output != vectors[i].output;
+                }
+            }
+            _ => group.iter().for_each(|&i| failed[i] = true),
+        }
+    }
+    for group in groups(&vectors, |v| v.input.len() as u32) {
+        let inputs: Vec<&[u8]> = group.iter().map(|&i| &vectors[i].input[..]).collect();
+        for &i in &group {
+            let scores = backend.hamming_batch(&vectors[i].input, &inputs);
+            if scores != CpuBackend.hamming_batch(&vectors[i].input, &inputs) {
+                failed[i] = true;
+            }
+        }
+    }
+    (0..vectors.len()).filter(|&i| failed[i]).collect()
+}
+
+/// Indices of `vectors` grouped by `key`, groups in order of first
+/// appearance.
+fn groups(vectors: &[TestVector], key: impl Fn(&TestVector) -> u32) -> Vec<Vec<usize>> {
+    let mut groups: Vec<(u32, Vec<usize>)> = Vec::new();
+    for (i, vector) in vectors.iter().enumerate() {
+        match groups.iter_mut().find(|(k, _)| *k == key(vector)) {
+            Some((_, group)) => group.push(i),
+            None => groups.push((key(vector), alloc::vec![i])),
+        }
+    }
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A backend that flips the last output bit of the first digest of
+    /// every batch of more than one input.
+    struct Faulty;
+
+    impl BatchBackend for Faulty {
+        fn name(&self) -> &'static str {
+            "faulty"
+        }
+
+        fn collapse_batch(
+            &self,
+            config: &TbfConfig,
+            inputs: &[&[u8]],
+        ) -> Result<Vec<Vec<u8>>, Error> {
+            let mut outputs = CpuBackend.collapse_batch(config, inputs)?;
+            if let (true, Some(byte)) = (inputs.len() > 1, outputs[0].last_mut()) {
+                *byte ^= 1;
+            }
+            Ok(outputs)
+        }
+
+        fn hamming_batch(&self, query: &[u8], candidates: &[&[u8]]) -> Result<Vec<u32>, Error> {
+            CpuBackend.hamming_batch(query, candidates)
+        }
+    }
+
+    #[test]
+    fn test_conformance_flags_diverging_backends() {
+        assert!(conformance(&CpuBackend).is_empty());
+        assert!(conformance(&WithFallback::new(CpuBackend)).is_empty());
+        let failed = conformance(&Faulty);
+        assert!(!failed.is_empty());
+        assert!(failed.len() < generate().len());
+    }
+
+    #[test]
+    fn test_scores_check_lengths() {
+        let candidates: [&[u8]; 3] = [&[0x00, 0x00], &[0xFF, 0x01], &[0x0F, 0xF0]];
+        assert_eq!(
+            CpuBackend.hamming_batch(&[0x00, 0x00], &candidates),
+            Ok(alloc::vec![0, 9, 8])
+        );
+        let backend = WithFallback::new(CpuBackend);
+        assert_eq!(
+            backend.hamming_batch(&[0x00], &candidates),
+            Err(Error::InvalidLength {
+                expected: 1,
+                found: 2
+            })
+        );
+        assert_eq!(backend.fallbacks(), 1);
+        assert_eq!(backend.name(), "cpu");
+    }
+}
@@ -0,0 +1,272 @@
+//! Biometric template protection: enroll, verify, revoke.
+//!
+//! [`BiometricVault`] packages the [`reusable`](crate::fuzzy_extractor::reusable)
+//! fuzzy extractor into the workflow integrators need. Enrolling a template
+//! yields an [`Enrollment`] (public helper data, safe to store) and a key;
+//! verifying a probe against the enrollment either releases the same key or
+//! rejects. Because every enrollment uses fresh lockers, revoking a leaked
+//! enrollment is just enrolling again: the new record is unlinkable to the
+//! old one.
+//!
+//! The extractor is sized from [`VaultParameters`]: the false-accept target
+//! fixes how many template bits each locker samples, and the false-reject
+//! target (at the expected genuine bit-error rate) fixes how many lockers are
+//! created. The FAR figure assumes template bits are independent and
+//! unbiased; real templates are not, so treat it as an upper bound on the
+//! security you get and validate it on your data.
+
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use crate::fuzzy_extractor::ExtractedKey;
+use crate::fuzzy_extractor::reusable::{LockerHelperData, SampleThenLock};
+
+/// Largest number of lockers a vault will create per enrollment.
+const MAX_LOCKERS: u32 = 1 << 16;
+
+/// Accuracy targets used to size a [`BiometricVault`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VaultParameters {
+    /// Maximum probability that an unrelated template is accepted.
+    pub target_far: f64,
+    /// Maximum probability that a genuine probe is rejected.
+    pub target_frr: f64,
+    /// Expected fraction of bits that differ between genuine captures.
+    pub genuine_bit_error_rate: f64,
+}
+
+impl Default for VaultParameters {
+    /// FAR 1e-6, FRR 1e-2 at a 10% genuine bit-error rate.
+    fn default() -> Self {
+        Self {
+            target_far: 1e-6,
+            target_frr: 1e-2,
+            genuine_bit_error_rate: 0.10,
+        }
+    }
+}
+
+/// Stored enrollment record; contains no secret material.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enrollment {
+    helper: LockerHelperData,
+}
+
+impl Enrollment {
+    /// Serializes the record for storage.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.helper.to_bytes()
+    }
+
+    /// Parses the output of [`Enrollment::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            helper: LockerHelperData::from_bytes(bytes)?,
+        })
+    }
+}
+
+/// Outcome of [`BiometricVault::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// The probe matched; carries the key released at enrollment.
+    Accept(ExtractedKey),
+    /// The probe did not match the enrollment.
+    Reject,
+}
+
+impl Decision {
+    /// Whether the probe was accepted.
+    pub fn is_accept(&self) -> bool {
+        matches!(self, Decision::Accept(_))
+    }
+}
+
+/// Enrolls and verifies biometric templates given as byte strings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiometricVault {
+    extractor: SampleThenLock,
+}
+
+impl BiometricVault {
+    /// Sizes a vault for the given targets.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if a target is outside (0, 1),
+    /// the bit-error rate is outside [0, 0.5), or meeting both targets would
+    /// need more than 65536 lockers.
+    pub fn new(parameters: VaultParameters) -> Result<Self, Error> {
+        let VaultParameters {
+            target_far,
+            target_frr,
+            genuine_bit_error_rate: p,
+        } = parameters;
+        if !(target_far > 0.0 && target_far < 1.0) {
+            return Err(Error::InvalidParameter("target FAR must be in (0, 1)"));
+        }
+        if !(target_frr > 0.0 && target_frr < 1.0) {
+            return Err(Error::InvalidParameter("target FRR must be in (0, 1)"));
+        }
+        if !(0.0..0.5).contains(&p) {
+            return Err(Error::InvalidParameter(
+                "bit-error rate must be in [0, 0.5)",
+            ));
+        }
+
+        // An impostor opens each locker with probability 2^-k, so FAR ~ L * 2^-k,
+        // while FRR = (1 - (1 - p)^k)^L. Alternate between the two until stable.
+        let mut subset_size = (1.0 / target_far).log2().ceil();
+        let mut locker_count = 1.0;
+        for _ in 0..32 {
+            let clean = (1.0 - p).powf(subset_size);
+            locker_count = if clean >= 1.0 {
+                1.0
+            } else {
+                (target_frr.ln() / (1.0 - clean).ln()).ceil().max(1.0)
+            };
+            if locker_count > f64::from(MAX_LOCKERS) {
+                return Err(Error::InvalidParameter("targets need too many lockers"));
+            }
+            let next = (locker_count / target_far).log2().ceil();
+            if next <= subset_size {
+                break;
+            }
+            subset_size = next;
+        }
+        if subset_size > f64::from(u16::MAX) {
+            return Err(Error::InvalidParameter("target FAR is too small"));
+        }
+
+        let extractor = SampleThenLock::new(subset_size as u16, locker_count as u32)?;
+        Ok(Self { extractor })
+    }
+
+    /// The underlying extractor parameters.
+    pub fn extractor(&self) -> &SampleThenLock {
+        &self.extractor
+    }
+
+    /// The false-accept rate implied by the chosen parameters (for unbiased,
+    /// independent template bits).
+    pub fn estimated_far(&self) -> f64 {
+        f64::from(self.extractor.locker_count())
+            * 2f64.powi(-i32::from(self.extractor.subset_size()))
+    }
+
+    /// Enrolls a template; returns the record to store and the released key.
+    ///
+    /// Fails if the template has fewer bits than one locker samples.
+    pub fn enroll(
+        &self,
+        template: &[u8],
+        rng: &mut impl EntropySource,
+    ) -> Result<(Enrollment, ExtractedKey), Error> {
+        let (key, helper) = self.extractor.generate(template, rng)?;
+        Ok((Enrollment { helper }, key))
+    }
+
+    /// Checks a probe against an enrollment.
+    pub fn verify(&self, probe: &[u8], enrollment: &Enrollment) -> Decision {
+        match SampleThenLock::reproduce(probe, &enrollment.helper) {
+            Some(key) => Decision::Accept(key),
+            None => Decision::Reject,
+        }
+    }
+
+    /// Revokes `old` by re-enrolling from a probe that verifies against it.
+    ///
+    /// The new enrollment uses fresh lockers and releases a fresh key, so it
+    /// cannot be linked to `old`; callers should delete `old` afterwards.
+    /// Fails with [`Error::KeyConfirmationFailed`] if the probe is rejected.
+    pub fn renew(
+        &self,
+        probe: &[u8],
+        old: &Enrollment,
+        rng: &mut impl EntropySource,
+    ) -> Result<(Enrollment, ExtractedKey), Error> {
+        if !self.verify(probe, old).is_accept() {
+            return Err(Error::KeyConfirmationFailed);
+        }
+        self.enroll(probe, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    fn template(seed: u64) -> Vec<u8> {
+        let mut t = vec![0u8; 128];
+        seeded(seed)(&mut t);
+        t
+    }
+
+    /// Flips every `stride`-th bit.
+    fn noisy(t: &[u8], stride: usize) -> Vec<u8> {
+        let mut out = t.to_vec();
+        for bit in (0..t.len() * 8).step_by(stride) {
+            out[bit / 8] ^= 0x80 >> (bit % 8);
+        }
+        out
+    }
+
+    #[test]
+    fn test_enroll_and_verify() {
+        let vault = BiometricVault::new(VaultParameters {
+            target_far: 1e-4,
+            target_frr: 1e-3,
+            genuine_bit_error_rate: 0.05,
+        })
+        .unwrap();
+        assert!(vault.estimated_far() <= 1e-4);
+
+        let enrolled = template(1);
+        let (enrollment, key) = vault.enroll(&enrolled, &mut seeded(2)).unwrap();
+        assert_eq!(
+            vault.verify(&noisy(&enrolled, 40), &enrollment),
+            Decision::Accept(key)
+        );
+        assert_eq!(vault.verify(&template(3), &enrollment), Decision::Reject);
+    }
+
+    #[test]
+    fn test_renew_revokes() {
+        let vault = BiometricVault::new(VaultParameters {
+            target_far: 1e-3,
+            target_frr: 1e-2,
+            genuine_bit_error_rate: 0.02,
+        })
+        .unwrap();
+        let enrolled = template(10);
+        let (old, old_key) = vault.enroll(&enrolled, &mut seeded(11)).unwrap();
+        let (new, new_key) = vault.renew(&enrolled, &old, &mut seeded(12)).unwrap();
+        assert_ne!(old, new);
+        assert_ne!(old_key, new_key);
+        assert_eq!(vault.verify(&enrolled, &new), Decision::Accept(new_key));
+        assert!(vault.renew(&template(99), &old, &mut seeded(13)).is_err());
+    }
+
+    #[test]
+    fn test_enrollment_roundtrip() {
+        let vault = BiometricVault::new(VaultParameters::default()).unwrap();
+        let (enrollment, _) = vault.enroll(&template(4), &mut seeded(5)).unwrap();
+        assert_eq!(
+            Enrollment::from_bytes(&enrollment.to_bytes()).unwrap(),
+            enrollment
+        );
+    }
+
+    #[test]
+    fn test_invalid_targets() {
+        let bad = |far, frr, p| {
+            BiometricVault::new(VaultParameters {
+                target_far: far,
+                target_frr: frr,
+                genuine_bit_error_rate: p,
+            })
+        };
+        assert!(bad(0.0, 0.01, 0.1).is_err());
+        assert!(bad(1e-6, 1.0, 0.1).is_err());
+        assert!(bad(1e-6, 0.01, 0.6).is_err());
+        assert!(bad(1e-12, 1e-9, 0.45).is_err()); // infeasible
+    }
+}
@@ -0,0 +1,260 @@
+//! Recovering the exact enrolled input from a noisy reading.
+//!
+//! A digest tells two readings apart from unrelated inputs, but throws away
+//! what the enrolled reading was. Where the value itself is needed, for
+//! instance to re-derive identifiers computed from it, [`enroll`] stores a
+//! code-offset over a [`RepetitionCode`] as [`CanonicalHelper`] data, and
+//! [`canonicalize`] corrects a later reading back to the enrolled one:
+//!
+//! - `enroll` pads the input with zeros to whole layers of the code, XORs it
+//!   with the codeword of a random value, and keeps a check value of the
+//!   input.
+//! - `canonicalize` XORs the padded reading with the helper data,
+//!   majority-decodes the random value, removes its codeword from the helper
+//!   data and verifies the result against the check value.
+//!
+//! A reading is corrected when fewer than half of the bits of every
+//! repetition group differ from the enrolled input. Copy `j` of data bit
+//! `i` covers input bit `j × layer_bits + i`, so a group is spread evenly
+//! over the input and a burst of noise touches many groups once each.
+//!
+//! The helper data is public but hides next to nothing: it reveals the XOR
+//! of the input bits within every group, leaving about one bit of the input
+//! unknown per group. Canonicalize values that need correcting, not values
+//! that must stay secret; derive keys from those with
+//! [`fuzzy_extractor`](crate::fuzzy_extractor) instead.
+
+use crate::crypto::ct_eq;
+use crate::crypto::hmac::HmacSha256;
+use crate::ecc::RepetitionCode;
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use alloc::vec::Vec;
+
+/// Bytes of the check value.
+const CHECK_LEN: usize = 16;
+/// Domain separation label for the check value.
+const CHECK_INFO: &[u8] = b"pensieve/canonical/v1/check";
+/// Bytes of the serialized header: repetitions, input length and check value.
+const HEADER_LEN: usize = 4 + 8 + CHECK_LEN;
+
+/// Public data for correcting readings back to the enrolled input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CanonicalHelper {
+    code: RepetitionCode,
+    input_len: usize,
+    check: [u8; CHECK_LEN], // MAC of the input under the random value.
+    offset: Vec<u8>,        // Padded input XOR the codeword of the random value.
+}
+
+impl CanonicalHelper {
+    /// The code correcting the readings.
+    pub fn code(&self) -> RepetitionCode {
+        self.code
+    }
+
+    /// Length of the enrolled input, and of every reading.
+    pub fn input_len(&self) -> usize {
+        self.input_len
+    }
+
+    /// Serializes as `repetitions (u32) || input length (u64) || check value
+    /// (16 bytes) || offset`, integers little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.offset.len());
+        out.extend_from_slice(&(self.code.repetitions() as u32).to_le_bytes());
+        out.extend_from_slice(&(self.input_len as u64).to_le_bytes());
+        out.extend_from_slice(&self.check);
+        out.extend_from_slice(&self.offset);
+        out
+    }
+
+    /// Parses the output of [`CanonicalHelper::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidParameter`] for an even repetition count
+    /// or an input length too large to pad, and with
+    /// [`Error::InvalidLength`] if the offset does not fit the input length.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::InvalidLength {
+                expected: HEADER_LEN,
+                found: bytes.len(),
+            });
+        }
+        let repetitions = u32::from_le_bytes(bytes[..4].try_into().expect("4 bytes")) as usize;
+        if repetitions.is_multiple_of(2) {
+            return Err(Error::InvalidParameter("repetition count must be odd"));
+        }
+        let input_len = u64::from_le_bytes(bytes[4..12].try_into().expect("8 bytes"));
+        let input_len = usize::try_from(input_len)
+            .map_err(|_| Error::InvalidParameter("input length does not fit in memory"))?;
+        let code = RepetitionCode::new(repetitions);
+        let offset = &bytes[HEADER_LEN..];
+        let expected = padded_len(input_len, code)
+            .filter(|&len| len.checked_add(HEADER_LEN).is_some())
+            .ok_or(Error::InvalidParameter(
+                "input length does not fit in memory",
+            ))?;
+        if offset.len() != expected {
+            return Err(Error::InvalidLength {
+                expected: HEADER_LEN + expected,
+                found: bytes.len(),
+            });
+        }
+        Ok(Self {
+            code,
+            input_len,
+            check: bytes[12..HEADER_LEN].try_into().expect("16 bytes"),
+            offset: offset.to_vec(),
+        })
+    }
+}
+
+/// Enrolls `input`, returning the helper data [`canonicalize`] corrects
+/// readings of it with.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::canonical::{canonicalize, enroll};
+/// use pensieve::ecc::RepetitionCode;
+/// use pensieve::noise::Seeded;
+///
+/// let enrolled = b"serial 0042-7781-AC".to_vec();
+/// // Use `OsEntropy` (needs `std`) for real enrollments.
+/// let helper = enroll(&enrolled, RepetitionCode::new(5), &mut Seeded::new(7));
+/// let mut reading = enrolled.clone();
+/// reading[3] ^= 0x40;
+/// reading[11] ^= 0x01;
+/// assert_eq!(canonicalize(&reading, &helper), Ok(enrolled));
+/// ```
+pub fn enroll(input: &[u8], code: RepetitionCode, rng: &mut impl EntropySource) -> CanonicalHelper {
+    let padded = padded_len(input.len(), code).expect("inputs in memory pad within usize");
+    let mut secret = alloc::vec![0u8; padded / code.repetitions()];
+    rng.fill_bytes(&mut secret);
+    let mut offset = code.encode(&secret);
+    for (byte, &bit) in offset.iter_mut().zip(input) {
+        *byte ^= bit;
+    }
+    CanonicalHelper {
+        code,
+        input_len: input.len(),
+        check: check_value(&secret, input),
+        offset,
+    }
+}
+
+/// Corrects `noisy_input` back to the input `helper` was enrolled from.
+///
+/// Fails with [`Error::InvalidLength`] if `noisy_input` is not as long as
+/// the enrolled input, and with [`Error::ReconstructionFailed`] when it is
+/// too noisy to correct (or a reading of another input).
+pub fn canonicalize(noisy_input: &[u8], helper: &CanonicalHelper) -> Result<Vec<u8>, Error> {
+    if noisy_input.len() != helper.input_len {
+        return Err(Error::InvalidLength {
+            expected: helper.input_len,
+            found: noisy_input.len(),
+        });
+    }
+    let mut padded = noisy_input.to_vec();
+    padded.resize(helper.offset.len(), 0);
+    let mut secret = alloc::vec![0u8; helper.offset.len() / helper.code.repetitions()];
+    helper
+        .code
+        .decode_masked_into(&helper.offset, Some(&padded), &mut secret)
+        .expect("padded to whole layers");
+    let mut input = helper.code.encode(&secret);
+    for (byte, &offset) in input.iter_mut().zip(&helper.offset) {
+        *byte ^= offset;
+    }
+    input.truncate(helper.input_len);
+    if !ct_eq(&check_value(&secret, &input), &helper.check) {
+        return Err(Error::ReconstructionFailed);
+    }
+    Ok(input)
+}
+
+/// `input_len` rounded up to whole layers of `code`, at least one byte per
+/// layer; `None` if that overflows.
+fn padded_len(input_len: usize, code: RepetitionCode) -> Option<usize> {
+    input_len
+        .div_ceil(code.repetitions())
+        .max(1)
+        .checked_mul(code.repetitions())
+}
+
+fn check_value(secret: &[u8], input: &[u8]) -> [u8; CHECK_LEN] {
+    let tag = HmacSha256::mac(secret, &[CHECK_INFO, input]);
+    let mut check = [0u8; CHECK_LEN];
+    check.copy_from_slice(&tag[..CHECK_LEN]);
+    check
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    #[test]
+    fn test_noisy_readings_come_back_exactly() {
+        let mut fill = seeded(415);
+        for (len, repetitions) in [(0, 1), (1, 3), (40, 7), (333, 9)] {
+            let mut enrolled = alloc::vec![0u8; len];
+            fill(&mut enrolled);
+            let code = RepetitionCode::new(repetitions);
+            let helper = enroll(&enrolled, code, &mut seeded(len as u64));
+            assert_eq!(helper.input_len(), len);
+            assert_eq!(
+                CanonicalHelper::from_bytes(&helper.to_bytes()),
+                Ok(helper.clone())
+            );
+
+            // Flip the first copy of every bit, if the code corrects that.
+            let mut reading = enrolled.clone();
+            if code.correctable_per_bit() > 0 {
+                let layer = helper.offset.len() / repetitions;
+                reading[..layer.min(len)]
+                    .iter_mut()
+                    .for_each(|b| *b ^= 0xFF);
+            }
+            assert_eq!(canonicalize(&reading, &helper), Ok(enrolled.clone()));
+        }
+    }
+
+    #[test]
+    fn test_uncorrectable_readings_are_rejected() {
+        let enrolled = [0x5Au8; 30];
+        let helper = enroll(&enrolled, RepetitionCode::new(3), &mut seeded(1));
+        // Bytes 0 and 10 hold two of the three copies of the same bits.
+        let mut reading = enrolled;
+        reading[0] ^= 0x01;
+        reading[10] ^= 0x01;
+        assert_eq!(
+            canonicalize(&reading, &helper),
+            Err(Error::ReconstructionFailed)
+        );
+        assert_eq!(
+            canonicalize(&enrolled[1..], &helper),
+            Err(Error::InvalidLength {
+                expected: 30,
+                found: 29
+            })
+        );
+
+        let mut bytes = helper.to_bytes();
+        bytes[0] = 2;
+        assert!(CanonicalHelper::from_bytes(&bytes).is_err());
+        assert!(CanonicalHelper::from_bytes(&helper.to_bytes()[..40]).is_err());
+
+        let mut bytes = helper.to_bytes();
+        bytes[0] = 7;
+        bytes[4..12].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            CanonicalHelper::from_bytes(&bytes),
+            Err(Error::InvalidParameter(
+                "input length does not fit in memory"
+            ))
+        );
+        bytes[4..12].copy_from_slice(&(usize::MAX as u64 - 2).to_le_bytes());
+        assert!(CanonicalHelper::from_bytes(&bytes).is_err());
+    }
+}
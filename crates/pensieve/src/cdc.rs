@@ -0,0 +1,338 @@
+//! File similarity over content-defined chunks.
+//!
+//! Collapsing a whole file, or fixed-size blocks of it, compares bits by
+//! position, so inserting a single byte near the start changes every block
+//! after it. A [`GearChunker`] instead cuts where the content says so: a
+//! Gear rolling hash of the last 64 bytes is updated at every byte, and a
+//! chunk ends where its top bits are all zero. An edit moves the cut points
+//! only near itself; past the next cut the chunks, and their collapses, are
+//! those of the original file again.
+//!
+//! A [`ChunkSet`] keeps the collapse of every chunk, reduced to the chunk
+//! length and chunk levels that determine it, and [`ChunkSet::similarity`]
+//! is the share of bytes in chunks the two files have in common.
+//!
+//! Chunks of dense, high-entropy data (compressed or encrypted files) all
+//! collapse alike, so they only differ by length: unrelated such files
+//! still score around 0.15 by coincidence. Judge their similarity against
+//! that baseline.
+//!
+//! Cut points and the similarity branch on the data; this is meant for
+//! public files.
+
+use crate::{Error, TbfConfig};
+use alloc::collections::BTreeMap;
+
+/// Random values the Gear hash adds for each byte value, from SplitMix64
+/// with a fixed seed so that cut points are the same everywhere.
+static GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0x7065_6e73_6965_7665u64; // "pensieve"
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Cuts inputs into content-defined chunks with a Gear rolling hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GearChunker {
+    min: usize,
+    max: usize,
+    mask: u64, // The top log2(average) bits.
+}
+
+impl GearChunker {
+    /// A chunker whose chunks are `min` to `max` bytes long: after `min`
+    /// bytes, each byte ends the chunk with probability `1 / average`, so
+    /// chunks average a little over `min + average` bytes.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless `average` is a power
+    /// of two and `1 <= min <= average <= max`.
+    pub fn new(min: usize, average: usize, max: usize) -> Result<Self, Error> {
+        if !(average.is_power_of_two() && 1 <= min && min <= average && average <= max) {
+            return Err(Error::InvalidParameter(
+                "chunk sizes must satisfy 1 <= min <= average <= max, with a power-of-two average",
+            ));
+        }
+        let bits = average.trailing_zeros();
+        Ok(Self {
+            min,
+            max,
+            mask: (!0u64).checked_shl(64 - bits).unwrap_or(0),
+        })
+    }
+
+    /// Length of the first chunk of `input`: at most `max`, and all of
+    /// `input` if no cut point comes before.
+    pub fn cut(&self, input: &[u8]) -> usize {
+        if input.len() <= self.min {
+            return input.len();
+        }
+        let end = input.len().min(self.max);
+        let mut hash = 0u64;
+        // The hash covers the last 64 bytes, so start it early enough to be
+        // warmed up at `min`.
+        for (i, &byte) in input[..end]
+            .iter()
+            .enumerate()
+            .skip(self.min.saturating_sub(64))
+        {
+            hash = (hash << 1).wrapping_add(GEAR[usize::from(byte)]);
+            if i + 1 >= self.min && hash & self.mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// The chunks of `input`, in order; they concatenate to `input`.
+    pub fn chunks<'a>(&self, mut input: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        let chunker = *self;
+        core::iter::from_fn(move || {
+            if input.is_empty() {
+                return None;
+            }
+            let (chunk, rest) = input.split_at(chunker.cut(input));
+            input = rest;
+            Some(chunk)
+        })
+    }
+}
+
+impl Default for GearChunker {
+    /// Chunks of 2 to 64 KiB, about 10 KiB on average.
+    fn default() -> Self {
+        Self::new(2 << 10, 8 << 10, 64 << 10).expect("valid chunk sizes")
+    }
+}
+
+/// The collapsed content-defined chunks of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkSet {
+    config: TbfConfig,
+    len: u64,
+    // Chunk length and chunk-level bitmask, packed like the keys of
+    // [`StoreDeduplicator`](crate::dedup::StoreDeduplicator), with the
+    // number of chunks collapsing to it.
+    chunks: BTreeMap<u64, u32>,
+}
+
+impl ChunkSet {
+    /// An empty set, for chunks collapsed under `config`.
+    fn empty(config: &TbfConfig) -> Self {
+        Self {
+            config: *config,
+            len: 0,
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    /// Collapses every chunk `chunker` cuts from `input` under `config`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::cdc::{ChunkSet, GearChunker};
+    ///
+    /// let mut state = 1u32;
+    /// let original: Vec<u8> = (0..1 << 16)
+    ///     .map(|_| {
+    ///         state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+    ///         (state >> 24) as u8
+    ///     })
+    ///     .collect();
+    /// let mut edited = original.clone();
+    /// edited.splice(30_000..30_000, *b"a small insertion");
+    ///
+    /// let chunker = GearChunker::new(256, 1024, 8192).unwrap();
+    /// let config = TbfConfig::new(0.125);
+    /// let a = ChunkSet::new(&original, &chunker, &config);
+    /// let b = ChunkSet::new(&edited, &chunker, &config);
+    /// assert!(a.similarity(&b) > 0.9);
+    /// ```
+    pub fn new(input: &[u8], chunker: &GearChunker, config: &TbfConfig) -> Self {
+        let mut set = Self::empty(config);
+        chunker.chunks(input).for_each(|chunk| set.insert(chunk));
+        set
+    }
+
+    /// Like [`ChunkSet::new`], reading the file from `reader` and holding
+    /// at most one maximal chunk in memory beyond the read buffer.
+    #[cfg(feature = "std")]
+    pub fn from_reader(
+        mut reader: impl std::io::Read,
+        chunker: &GearChunker,
+        config: &TbfConfig,
+    ) -> std::io::Result<Self> {
+        let mut set = Self::empty(config);
+        let mut buffer = alloc::vec::Vec::with_capacity(2 * chunker.max);
+        let mut eof = false;
+        loop {
+            while !eof && buffer.len() < chunker.max {
+                let start = buffer.len();
+                buffer.resize(start + chunker.max, 0);
+                let read = loop {
+                    match reader.read(&mut buffer[start..]) {
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                        result => break result?,
+                    }
+                };
+                buffer.truncate(start + read);
+                eof = read == 0;
+            }
+            if buffer.is_empty() {
+                return Ok(set);
+            }
+            let cut = chunker.cut(&buffer);
+            set.insert(&buffer[..cut]);
+            buffer.drain(..cut);
+        }
+    }
+
+    fn insert(&mut self, chunk: &[u8]) {
+        let levels = self
+            .config
+            .chunk_levels(chunk)
+            .iter()
+            .fold(0u64, |acc, &level| acc << 1 | u64::from(level));
+        *self
+            .chunks
+            .entry((chunk.len() as u64) << 8 | levels)
+            .or_default() += 1;
+        self.len += chunk.len() as u64;
+    }
+
+    /// Total length of the chunked input, in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the input was empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of chunks.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.values().map(|&n| n as usize).sum()
+    }
+
+    /// The share of bytes in chunks both files have: the bytes of the
+    /// chunks they share (with multiplicity) over the bytes of the chunks
+    /// either has, from 0.0 to 1.0; 1.0 if both are empty. Sets collapsed
+    /// under different configurations are never alike (0.0).
+    pub fn similarity(&self, other: &Self) -> f32 {
+        if self.config != other.config {
+            return 0.0;
+        }
+        let (mut shared, mut total) = (0u64, 0u64);
+        let mut count = |key: u64, (a, b): (u32, u32)| {
+            let len = key >> 8;
+            shared += len * u64::from(a.min(b));
+            total += len * u64::from(a.max(b));
+        };
+        for (&key, &a) in &self.chunks {
+            count(key, (a, other.chunks.get(&key).copied().unwrap_or(0)));
+        }
+        for (&key, &b) in &other.chunks {
+            if !self.chunks.contains_key(&key) {
+                count(key, (0, b));
+            }
+        }
+        if total == 0 {
+            1.0
+        } else {
+            (shared as f64 / total as f64) as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    fn random(len: usize, seed: u64) -> Vec<u8> {
+        let mut input = vec![0u8; len];
+        seeded(seed)(&mut input);
+        input
+    }
+
+    /// Random bytes with few bits set, whose chunks collapse differently.
+    fn sparse(len: usize, seed: u64) -> Vec<u8> {
+        random(len, seed).into_iter().map(|b| b & 0x11).collect()
+    }
+
+    #[test]
+    fn test_chunk_sizes() {
+        let chunker = GearChunker::new(64, 256, 1024).unwrap();
+        let input = random(1 << 16, 1);
+        let chunks: Vec<&[u8]> = chunker.chunks(&input).collect();
+        assert_eq!(chunks.concat(), input);
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(rest.iter().all(|c| (64..=1024).contains(&c.len())));
+        assert!(last.len() <= 1024);
+        let average = input.len() / chunks.len();
+        assert!((200..=500).contains(&average), "{average}");
+
+        assert!(GearChunker::new(0, 256, 1024).is_err());
+        assert!(GearChunker::new(64, 300, 1024).is_err());
+        assert!(GearChunker::new(64, 256, 128).is_err());
+        assert_eq!(GearChunker::new(1, 1, 1).unwrap().cut(b"abc"), 1);
+    }
+
+    #[test]
+    fn test_cuts_resynchronize_after_edits() {
+        let chunker = GearChunker::new(64, 256, 1024).unwrap();
+        let config = TbfConfig::new(0.125);
+        let original = sparse(1 << 16, 2);
+        let mut edited = original.clone();
+        edited.splice(20_000..20_000, random(5, 3));
+        edited.drain(40_000..40_010);
+        edited[50_000] ^= 0xFF;
+
+        let a = ChunkSet::new(&original, &chunker, &config);
+        let b = ChunkSet::new(&edited, &chunker, &config);
+        assert_eq!(a.len(), 1 << 16);
+        assert!(a.similarity(&b) > 0.9, "{}", a.similarity(&b));
+        assert_eq!(a.similarity(&a), 1.0);
+
+        let unrelated = ChunkSet::new(&sparse(1 << 16, 4), &chunker, &config);
+        assert!(a.similarity(&unrelated) < 0.05);
+        let other = ChunkSet::new(&original, &chunker, &TbfConfig::new(0.2));
+        assert_eq!(a.similarity(&other), 0.0);
+        let empty = ChunkSet::new(&[], &chunker, &config);
+        assert!(empty.is_empty());
+        assert_eq!(empty.similarity(&empty), 1.0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_reader() {
+        /// Returns at most 100 bytes per read.
+        struct Trickle<'a>(&'a [u8]);
+        impl std::io::Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = buf.len().min(100).min(self.0.len());
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+        let chunker = GearChunker::new(64, 256, 1024).unwrap();
+        let config = TbfConfig::new(0.125);
+        let input = random(20_000, 5);
+        let streamed = ChunkSet::from_reader(Trickle(&input), &chunker, &config).unwrap();
+        assert_eq!(streamed, ChunkSet::new(&input, &chunker, &config));
+        assert_eq!(streamed.chunk_count(), chunker.chunks(&input).count());
+    }
+}
@@ -0,0 +1,370 @@
+//! Tamper-evident logs of digests over time.
+//!
+//! A monitoring system that records fuzzy observations, one digest per
+//! reading, may later need to show that none of them was altered, dropped
+//! or inserted after the fact. A [`DigestChain`] links every entry to the
+//! ones before it: the head after an entry is the SHA-256 of the previous
+//! head, the entry's timestamp and its [`DigestEnvelope`], so the digest
+//! and every parameter it was collapsed with. Changing anything in an
+//! entry changes every later head.
+//!
+//! Publishing or countersigning a head from time to time anchors the log:
+//! an auditor holding an anchored head checks with
+//! [`DigestChain::verify`] that the entries up to it are exactly those the
+//! head was computed over, whatever was appended since. The chain itself
+//! is not secret and not authenticated; without an anchor kept out of the
+//! writer's reach, a writer can rebuild a whole altered chain.
+//!
+//! [`DigestChain::to_bytes`] writes the entries, not the heads, which are
+//! recomputed when reading:
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 4 | magic `PNSH` |
+//! | 1 | format version (currently 1) |
+//! | 4 | number of entries, little-endian |
+//! | 8 each | timestamp, little-endian, followed by |
+//! | 4 each | length of the encoded envelope, little-endian, and |
+//! | n each | the [`encode`](DigestEnvelope::encode)d envelope |
+//! | 4 | first 4 bytes of SHA-256 over everything above |
+//!
+//! The key of [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf)
+//! is never stored; chains of such digests are read with
+//! [`DigestChain::from_bytes_keyed`].
+
+use crate::crypto::sha256::Sha256;
+use crate::envelope::DigestEnvelope;
+use crate::{Error, TbfConfig};
+use alloc::vec::Vec;
+
+/// First bytes of every serialized chain.
+const MAGIC: [u8; 4] = *b"PNSH";
+/// Newest format this build writes and reads.
+const FORMAT_VERSION: u8 = 1;
+/// Bytes before the entries.
+const HEADER_LEN: usize = 9;
+/// Bytes of truncated SHA-256 after the entries.
+const CHECKSUM_LEN: usize = 4;
+/// Domain separation of the head of an empty chain.
+const GENESIS_INFO: &[u8] = b"pensieve digest chain genesis";
+/// Domain separation of the head after an entry.
+const LINK_INFO: &[u8] = b"pensieve digest chain link";
+
+/// One observation of a [`DigestChain`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainEntry {
+    timestamp: u64,
+    envelope: DigestEnvelope,
+    head: [u8; 32], // Chain head after this entry.
+}
+
+impl ChainEntry {
+    /// When the observation was made, in the caller's clock, such as Unix
+    /// seconds.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// The digest of the observation and its parameters.
+    pub fn envelope(&self) -> &DigestEnvelope {
+        &self.envelope
+    }
+
+    /// The head of the chain after this entry.
+    pub fn head(&self) -> [u8; 32] {
+        self.head
+    }
+}
+
+/// An append-only log of digests, each entry committing to all before it.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::chain::DigestChain;
+///
+/// let config = TbfConfig::new(0.125);
+/// let mut chain = DigestChain::new();
+/// chain.append(&[0x0F; 32], &config, 1_700_000_000).unwrap();
+/// let anchored = chain.append(&[0xF0; 32], &config, 1_700_000_060).unwrap();
+/// chain.append(&[0x3C; 32], &config, 1_700_000_120).unwrap();
+/// assert!(chain.verify(&anchored));
+///
+/// // Rewriting the first observation breaks the anchored head.
+/// let mut forged = DigestChain::new();
+/// forged.append(&[0x00; 32], &config, 1_700_000_000).unwrap();
+/// forged.append(&[0xF0; 32], &config, 1_700_000_060).unwrap();
+/// assert!(!forged.verify(&anchored));
+/// assert_eq!(DigestChain::from_bytes(&chain.to_bytes()).unwrap(), chain);
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DigestChain {
+    entries: Vec<ChainEntry>,
+}
+
+impl DigestChain {
+    /// An empty chain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The head of an empty chain, the same for every chain.
+    pub fn genesis() -> [u8; 32] {
+        Sha256::digest(&[GENESIS_INFO])
+    }
+
+    /// The entries, oldest first.
+    pub fn entries(&self) -> &[ChainEntry] {
+        &self.entries
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the chain has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The current head: that of the last entry, or
+    /// [`genesis`](Self::genesis) for an empty chain.
+    pub fn head(&self) -> [u8; 32] {
+        self.entries
+            .last()
+            .map_or_else(Self::genesis, |entry| entry.head)
+    }
+
+    /// Collapses `input` under `config`, appends the digest observed at
+    /// `timestamp` and returns the new head.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `timestamp` is before that
+    /// of the last entry.
+    pub fn append(
+        &mut self,
+        input: &[u8],
+        config: &TbfConfig,
+        timestamp: u64,
+    ) -> Result<[u8; 32], Error> {
+        self.append_envelope(DigestEnvelope::seal(input, config), timestamp)
+    }
+
+    /// [`DigestChain::append`] for a digest sealed elsewhere, such as by
+    /// the sensor that made the observation.
+    pub fn append_envelope(
+        &mut self,
+        envelope: DigestEnvelope,
+        timestamp: u64,
+    ) -> Result<[u8; 32], Error> {
+        if self
+            .entries
+            .last()
+            .is_some_and(|last| timestamp < last.timestamp)
+        {
+            return Err(Error::InvalidParameter("timestamps must not decrease"));
+        }
+        let head = link(&self.head(), timestamp, &envelope.encode());
+        self.entries.push(ChainEntry {
+            timestamp,
+            envelope,
+            head,
+        });
+        Ok(head)
+    }
+
+    /// Whether `anchored` is the head of this chain after some number of
+    /// its entries, the empty prefix included: that is, whether the
+    /// entries a trusted copy of that head was taken after are unchanged.
+    pub fn verify(&self, anchored: &[u8; 32]) -> bool {
+        *anchored == Self::genesis() || self.entries.iter().any(|entry| entry.head == *anchored)
+    }
+
+    /// Serializes in the format described in the [module documentation](self).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + CHECKSUM_LEN);
+        out.extend_from_slice(&MAGIC);
+        out.push(FORMAT_VERSION);
+        out.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for entry in &self.entries {
+            let encoded = entry.envelope.encode();
+            out.extend_from_slice(&entry.timestamp.to_le_bytes());
+            out.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+            out.extend_from_slice(&encoded);
+        }
+        let checksum = Sha256::digest(&[&out]);
+        out.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+        out
+    }
+
+    /// Parses the output of [`DigestChain::to_bytes`], recomputing every
+    /// head.
+    ///
+    /// Fails with [`Error::InvalidChecksum`] on corruption, with
+    /// [`Error::InvalidLength`] if the entries do not fill the bytes, and
+    /// with the errors of [`DigestEnvelope::decode`] for envelopes this
+    /// build cannot interpret. Alterations that keep the format intact are
+    /// not errors; they show as heads that no longer [`verify`](Self::verify).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, None)
+    }
+
+    /// [`DigestChain::from_bytes`] for chains that may hold
+    /// [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf)
+    /// digests, all under `key`.
+    pub fn from_bytes_keyed(bytes: &[u8], key: &[u8; 32]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, Some(key))
+    }
+
+    fn from_bytes_with(bytes: &[u8], key: Option<&[u8; 32]>) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+            return Err(Error::InvalidLength {
+                expected: HEADER_LEN + CHECKSUM_LEN,
+                found: bytes.len(),
+            });
+        }
+        if bytes[..4] != MAGIC {
+            return Err(Error::InvalidParameter("not a pensieve digest chain"));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if Sha256::digest(&[body])[..CHECKSUM_LEN] != *checksum {
+            return Err(Error::InvalidChecksum);
+        }
+        if bytes[4] != FORMAT_VERSION {
+            return Err(Error::InvalidParameter(
+                "unsupported digest chain format version",
+            ));
+        }
+
+        let count = u32::from_le_bytes(bytes[5..9].try_into().expect("4 bytes"));
+        let mut chain = Self::new();
+        let mut rest = &body[HEADER_LEN..];
+        for _ in 0..count {
+            let truncated = || Error::InvalidLength {
+                expected: body.len() + 1,
+                found: body.len(),
+            };
+            if rest.len() < 12 {
+                return Err(truncated());
+            }
+            let timestamp = u64::from_le_bytes(rest[..8].try_into().expect("8 bytes"));
+            let len = u32::from_le_bytes(rest[8..12].try_into().expect("4 bytes")) as usize;
+            let encoded = rest.get(12..12 + len).ok_or_else(truncated)?;
+            let envelope = match key {
+                Some(key) => DigestEnvelope::decode_keyed(encoded, key)?,
+                None => DigestEnvelope::decode(encoded)?,
+            };
+            chain.append_envelope(envelope, timestamp)?;
+            rest = &rest[12 + len..];
+        }
+        if !rest.is_empty() {
+            return Err(Error::InvalidLength {
+                expected: bytes.len() - rest.len(),
+                found: bytes.len(),
+            });
+        }
+        Ok(chain)
+    }
+}
+
+/// The head after an entry observed at `timestamp` with the `encoded`
+/// envelope, following `previous`.
+fn link(previous: &[u8; 32], timestamp: u64, encoded: &[u8]) -> [u8; 32] {
+    Sha256::digest(&[
+        LINK_INFO,
+        previous,
+        &timestamp.to_le_bytes(),
+        &(encoded.len() as u64).to_le_bytes(),
+        encoded,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FinalTransform, Thresholding};
+
+    fn chain(config: &TbfConfig) -> DigestChain {
+        let mut chain = DigestChain::new();
+        for (i, timestamp) in [10, 20, 30, 40].into_iter().enumerate() {
+            chain
+                .append(&[i as u8 * 0x41; 24], config, timestamp)
+                .unwrap();
+        }
+        chain
+    }
+
+    #[test]
+    fn test_every_altered_entry_breaks_later_heads() {
+        let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+        let original = chain(&config);
+        assert_eq!(original.len(), 4);
+        assert!(original.verify(&DigestChain::genesis()));
+        assert!(original.verify(&original.head()));
+        assert!(!original.verify(&[0; 32]));
+
+        for index in 0..original.len() {
+            let alterations = [
+                (original.entries[index].timestamp + 1, [0x41u8; 24], config),
+                (original.entries[index].timestamp, [0xFF; 24], config),
+                (
+                    original.entries[index].timestamp,
+                    [index as u8 * 0x41; 24],
+                    config.with_tolerance(0.2),
+                ),
+            ];
+            for (timestamp, input, config) in alterations {
+                let mut altered = DigestChain::new();
+                for (i, entry) in original.entries.iter().enumerate() {
+                    if i == index {
+                        altered.append(&input, &config, timestamp).unwrap();
+                    } else {
+                        altered
+                            .append_envelope(entry.envelope.clone(), entry.timestamp)
+                            .unwrap();
+                    }
+                }
+                for (i, entry) in original.entries.iter().enumerate() {
+                    assert_eq!(altered.verify(&entry.head), i < index, "{index} {i}");
+                }
+            }
+            // Dropping the entry breaks the later heads as well.
+            let mut dropped = original.clone();
+            dropped.entries.truncate(index);
+            for entry in &original.entries[index + 1..] {
+                dropped
+                    .append_envelope(entry.envelope.clone(), entry.timestamp)
+                    .unwrap();
+            }
+            assert!(!dropped.verify(&original.head()));
+        }
+        let mut late = original.clone();
+        assert!(late.append(&[0; 24], &config, 39).is_err());
+        assert_eq!(late, original);
+        assert!(late.append(&[0; 24], &config, 40).is_ok());
+    }
+
+    #[test]
+    fn test_bytes_round_trip_and_reject_damage() {
+        let keyed = TbfConfig::new(0.1).with_transform(FinalTransform::KeyedPrf { key: [7; 32] });
+        for config in [TbfConfig::new(0.125), keyed] {
+            let chain = chain(&config);
+            let bytes = chain.to_bytes();
+            let read = DigestChain::from_bytes_keyed(&bytes, &[7; 32]).unwrap();
+            assert_eq!(read, chain);
+            assert_eq!(read.head(), chain.head());
+            for cut in 0..bytes.len() {
+                assert!(DigestChain::from_bytes_keyed(&bytes[..cut], &[7; 32]).is_err());
+            }
+            let mut flipped = bytes.clone();
+            flipped[20] ^= 1;
+            assert_eq!(
+                DigestChain::from_bytes(&flipped),
+                Err(Error::InvalidChecksum)
+            );
+        }
+        assert!(DigestChain::from_bytes(&chain(&keyed).to_bytes()).is_err());
+        let empty = DigestChain::new();
+        assert_eq!(DigestChain::from_bytes(&empty.to_bytes()), Ok(empty));
+    }
+}
@@ -0,0 +1,181 @@
+//! Grouping inputs by their collapsed values.
+//!
+//! Clusters are formed greedily in input order: an input joins the cluster
+//! whose representative is closest to it (the earliest one on a tie) if that
+//! distance is within the radius, and founds a new cluster with itself as
+//! representative otherwise. Every member is therefore within the radius of
+//! its representative, though not necessarily of the other members, and the
+//! result depends on the input order whenever the radius is non-zero.
+//!
+//! Distances count the chunks whose collapsed level differs, so radius 0
+//! groups exactly the inputs with identical collapsed outputs. Inputs of
+//! different lengths never share a cluster.
+
+use crate::TbfConfig;
+use crate::index::BkTree;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// The result of [`cluster`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clustering {
+    /// Cluster id of every input, in input order; ids count up from 0 in
+    /// order of foundation.
+    pub assignments: Vec<usize>,
+    /// For every cluster id, the index of the input that founded it.
+    pub representatives: Vec<usize>,
+}
+
+/// Clusters `inputs` under `config`, merging inputs whose collapsed values
+/// differ in at most `radius` chunks.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::cluster::cluster;
+///
+/// let a = [0xFF; 16];
+/// let mut a_noisy = a;
+/// a_noisy[3] ^= 0b0000_0100;
+/// let b = [0x00; 16];
+/// let clustering = cluster([&a, &b, &a_noisy], &TbfConfig::new(0.125), 0);
+/// assert_eq!(clustering.assignments, [0, 1, 0]);
+/// assert_eq!(clustering.representatives, [0, 1]);
+/// ```
+pub fn cluster<I>(inputs: I, config: &TbfConfig, radius: u32) -> Clustering
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let mut clusterer = Clusterer::new(*config, radius);
+    let assignments = inputs
+        .into_iter()
+        .map(|input| clusterer.assign(input.as_ref()))
+        .collect();
+    Clustering {
+        assignments,
+        representatives: clusterer.representatives,
+    }
+}
+
+/// Streaming form of [`cluster`]: assigns inputs one at a time, keeping only
+/// the representatives' collapsed values in memory.
+#[derive(Debug, Clone)]
+pub struct Clusterer {
+    config: TbfConfig,
+    radius: u32,
+    by_len: BTreeMap<usize, BkTree<u64>>, // Per input length: packed levels -> cluster id.
+    representatives: Vec<usize>,          // Input index founding each cluster.
+    seen: usize,                          // Inputs assigned so far.
+}
+
+impl Clusterer {
+    /// Creates a clusterer with no clusters.
+    pub fn new(config: TbfConfig, radius: u32) -> Self {
+        Self {
+            config,
+            radius,
+            by_len: BTreeMap::new(),
+            representatives: Vec::new(),
+            seen: 0,
+        }
+    }
+
+    /// Assigns the next input and returns its cluster id; an id equal to
+    /// the previous [`Clusterer::cluster_count`] means the input founded a
+    /// new cluster.
+    pub fn assign(&mut self, input: &[u8]) -> usize {
+        let index = self.seen;
+        self.seen += 1;
+
+        let key = pack(&self.config.chunk_levels(input));
+        let tree = self.by_len.entry(input.len()).or_default();
+        let nearest = tree
+            .query_within(&key, self.radius)
+            .expect("keys of one input length have one length")
+            .iter()
+            .map(|m| (m.distance, *m.value as usize))
+            .min();
+        if let Some((_, id)) = nearest {
+            return id;
+        }
+
+        let id = self.representatives.len();
+        tree.insert(&key, id as u64)
+            .expect("keys of one input length have one length");
+        self.representatives.push(index);
+        id
+    }
+
+    /// Number of clusters founded so far.
+    pub fn cluster_count(&self) -> usize {
+        self.representatives.len()
+    }
+
+    /// For every cluster id, the index (in assignment order) of the input
+    /// that founded it.
+    pub fn representatives(&self) -> &[usize] {
+        &self.representatives
+    }
+}
+
+/// Packs 0/1 levels into bytes, MSB first.
+fn pack(levels: &[u8]) -> Vec<u8> {
+    let mut out = alloc::vec![0u8; levels.len().div_ceil(8)];
+    for (i, &level) in levels.iter().enumerate() {
+        out[i / 8] |= level << (7 - i % 8);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 16-byte input whose eight 16-bit chunks collapse to `levels`.
+    fn input(levels: u8) -> [u8; 16] {
+        let mut out = [0u8; 16];
+        for chunk in 0..8 {
+            if levels >> (7 - chunk) & 1 == 1 {
+                out[2 * chunk] = 0xFF;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_exact_clusters() {
+        let inputs = [input(0b1010_0000), input(0b0000_0001), input(0b1010_0000)];
+        let clustering = cluster(inputs, &TbfConfig::default(), 0);
+        assert_eq!(clustering.assignments, [0, 1, 0]);
+        assert_eq!(clustering.representatives, [0, 1]);
+    }
+
+    #[test]
+    fn test_radius_joins_nearest_representative() {
+        let inputs = [
+            input(0b1111_0000),
+            input(0b0000_1111),
+            input(0b1111_0001), // 1 chunk from #0, 5 from #1.
+            input(0b0011_1111), // 4 chunks from #0, 2 from #1.
+            input(0b1100_1100), // 4 from both: founds a cluster at radius 3.
+        ];
+        let clustering = cluster(inputs, &TbfConfig::default(), 3);
+        assert_eq!(clustering.assignments, [0, 1, 0, 1, 2]);
+        assert_eq!(clustering.representatives, [0, 1, 4]);
+    }
+
+    #[test]
+    fn test_streaming_and_lengths_separate() {
+        let mut clusterer = Clusterer::new(TbfConfig::default(), 8);
+        assert_eq!(clusterer.assign(&[0u8; 16]), 0);
+        assert_eq!(clusterer.assign(&[0u8; 17]), 1);
+        assert_eq!(clusterer.assign(&[0xFFu8; 16]), 0);
+        assert_eq!(clusterer.cluster_count(), 2);
+        assert_eq!(clusterer.representatives(), [0, 1]);
+        assert_eq!(
+            cluster([&[][..], &[]], &TbfConfig::default(), 0).assignments,
+            [0, 0]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! Column-at-a-time kernels over Apache Arrow's binary layout.
+//!
+//! An Arrow `BinaryArray` of `n` rows is three buffers: `n + 1` `i32`
+//! offsets, the concatenated values, and an optional validity bitmap whose
+//! bit `i` (least significant bit first) is set iff row `i` is not null.
+//! [`BinaryColumn`] borrows exactly those buffers, so a column from the
+//! `arrow` crate (`value_offsets()`, `values()` and the bytes of `nulls()`)
+//! or from any other Arrow implementation is processed without copying.
+//! Offsets of a sliced array may start past 0, but its validity bitmap must
+//! start at its first row.
+//!
+//! [`collapse_column`] fingerprints every row and returns a column in the
+//! same layout; equal fingerprints make a plain equi-join a fuzzy join.
+//! [`similarity_columns`] scores two columns row by row. Null rows stay
+//! null in both. `LargeBinaryArray` (`i64` offsets) is not supported.
+//!
+//! This crate does not depend on `arrow` or DataFusion: registering these
+//! kernels as a DataFusion scalar UDF is left to the caller, who wraps the
+//! buffers of the argument arrays and builds the result array from the
+//! returned ones.
+
+use crate::{Error, TbfConfig, similarity};
+use alloc::vec::Vec;
+
+/// A borrowed column of nullable byte strings in the Arrow binary layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BinaryColumn<'a> {
+    offsets: &'a [i32],
+    values: &'a [u8],
+    validity: Option<&'a [u8]>,
+}
+
+impl<'a> BinaryColumn<'a> {
+    /// Wraps the buffers of a column, as Arrow lays them out.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless there is at least one
+    /// offset, the offsets are non-negative, non-decreasing and within
+    /// `values`, and `validity`, if given, has a bit for every row.
+    pub fn new(
+        offsets: &'a [i32],
+        values: &'a [u8],
+        validity: Option<&'a [u8]>,
+    ) -> Result<Self, Error> {
+        let (&first, _) = offsets.split_first().ok_or(Error::InvalidParameter(
+            "a column needs at least one offset",
+        ))?;
+        if first < 0
+            || offsets.windows(2).any(|pair| pair[0] > pair[1])
+            || offsets[offsets.len() - 1] as usize > values.len()
+        {
+            return Err(Error::InvalidParameter(
+                "column offsets are out of order or range",
+            ));
+        }
+        let rows = offsets.len() - 1;
+        if validity.is_some_and(|bitmap| bitmap.len() < rows.div_ceil(8)) {
+            return Err(Error::InvalidParameter(
+                "validity bitmap is shorter than the column",
+            ));
+        }
+        Ok(Self {
+            offsets,
+            values,
+            validity,
+        })
+    }
+
+    /// Number of rows, nulls included.
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Whether the column has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The bytes of `row`, or `None` if it is null.
+    ///
+    /// # Panics
+    /// If `row` is out of range.
+    pub fn get(&self, row: usize) -> Option<&'a [u8]> {
+        assert!(row < self.len(), "row {row} of a column of {}", self.len());
+        let valid = self
+            .validity
+            .is_none_or(|bitmap| bitmap[row / 8] >> (row % 8) & 1 == 1);
+        let (start, end) = (self.offsets[row] as usize, self.offsets[row + 1] as usize);
+        valid.then(|| &self.values[start..end])
+    }
+
+    /// The rows in order, `None` for nulls.
+    pub fn iter(&self) -> impl Iterator<Item = Option<&'a [u8]>> + '_ {
+        (0..self.len()).map(|row| self.get(row))
+    }
+}
+
+/// An owned column in the Arrow binary layout, as returned by the kernels.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinaryColumnBuf {
+    offsets: Vec<i32>,
+    values: Vec<u8>,
+    validity: Option<Vec<u8>>,
+}
+
+impl BinaryColumnBuf {
+    /// Borrows the column.
+    pub fn as_column(&self) -> BinaryColumn<'_> {
+        BinaryColumn {
+            offsets: &self.offsets,
+            values: &self.values,
+            validity: self.validity.as_deref(),
+        }
+    }
+
+    /// The offsets, values and validity buffers, ready to become an Arrow
+    /// array without copying.
+    pub fn into_parts(self) -> (Vec<i32>, Vec<u8>, Option<Vec<u8>>) {
+        (self.offsets, self.values, self.validity)
+    }
+}
+
+/// Collapses every row of `column` under `config`.
+///
+/// Row `i` of the result is `config.collapse(row i)`, or null where the
+/// input is null. Fingerprints are as long as their inputs, so the result's
+/// offsets are the input's, rebased to start at 0.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::columnar::{BinaryColumn, collapse_column};
+///
+/// let values = [0xFF, 0, 0, 0, 0xFE, 0, 0, 0];
+/// let column = BinaryColumn::new(&[0, 4, 8, 8], &values, Some(&[0b011])).unwrap();
+/// let fingerprints = collapse_column(&column, &TbfConfig::new(0.125));
+/// let fingerprints = fingerprints.as_column();
+/// assert_eq!(fingerprints.get(0), fingerprints.get(1));
+/// assert_eq!(fingerprints.get(2), None);
+/// ```
+pub fn collapse_column(column: &BinaryColumn<'_>, config: &TbfConfig) -> BinaryColumnBuf {
+    let base = column.offsets[0];
+    let mut values = Vec::with_capacity((column.offsets[column.len()] - base) as usize);
+    for row in 0..column.len() {
+        match column.get(row) {
+            Some(input) => values.extend_from_slice(&config.collapse(input)),
+            // A null row may still span bytes; zero them to keep the offsets.
+            None => {
+                let span = column.offsets[row + 1] - column.offsets[row];
+                values.resize(values.len() + span as usize, 0);
+            }
+        }
+    }
+    BinaryColumnBuf {
+        offsets: column.offsets.iter().map(|offset| offset - base).collect(),
+        values,
+        validity: column
+            .validity
+            .map(|bitmap| bitmap[..column.len().div_ceil(8)].to_vec()),
+    }
+}
+
+/// The [`similarity`] of each pair of rows of `a` and `b` under `config`;
+/// `None` where either row is null.
+///
+/// Arrow's `Float32Array` can be collected directly from the result.
+///
+/// Fails with [`Error::InvalidLength`] if the columns have different
+/// numbers of rows.
+pub fn similarity_columns(
+    a: &BinaryColumn<'_>,
+    b: &BinaryColumn<'_>,
+    config: &TbfConfig,
+) -> Result<Vec<Option<f32>>, Error> {
+    if a.len() != b.len() {
+        return Err(Error::InvalidLength {
+            expected: a.len(),
+            found: b.len(),
+        });
+    }
+    Ok(a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| Some(similarity(a?, b?, config)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_collapse_column_matches_rows() {
+        let config = TbfConfig::new(0.125);
+        let rows: [&[u8]; 4] = [&[0xFF, 0, 0, 0], &[1, 2, 3], &[9, 9], &[]];
+        let mut offsets = vec![3]; // Columns sliced out of a larger array start past 0.
+        let mut values = vec![7, 7, 7];
+        for row in rows {
+            values.extend_from_slice(row);
+            offsets.push(values.len() as i32);
+        }
+        let column = BinaryColumn::new(&offsets, &values, Some(&[0b1101])).unwrap();
+        assert_eq!(column.get(1), None);
+
+        let collapsed = collapse_column(&column, &config);
+        let collapsed = collapsed.as_column();
+        assert_eq!(collapsed.len(), 4);
+        for (row, input) in rows.iter().enumerate() {
+            let expected = (row != 1).then(|| config.collapse(input));
+            assert_eq!(
+                collapsed.get(row).map(<[u8]>::to_vec),
+                expected,
+                "row {row}"
+            );
+        }
+        let (offsets, values, validity) = collapse_column(&column, &config).into_parts();
+        assert_eq!(offsets, [0, 4, 7, 9, 9]);
+        assert_eq!(values.len(), 9);
+        assert_eq!(validity, Some(vec![0b1101]));
+    }
+
+    #[test]
+    fn test_similarity_columns() {
+        let config = TbfConfig::new(0.125);
+        let a = BinaryColumn::new(
+            &[0, 4, 8, 12],
+            &[0xFF, 0, 0, 0, 1, 2, 3, 4, 0, 0, 0, 0],
+            None,
+        )
+        .unwrap();
+        let values = [0xFE, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let b = BinaryColumn::new(&[0, 4, 8, 12], &values, Some(&[0b011])).unwrap();
+        let scores = similarity_columns(&a, &b, &config).unwrap();
+        assert_eq!(scores[0], Some(1.0));
+        assert!(scores[1].unwrap() < 1.0);
+        assert_eq!(scores[2], None);
+
+        let short = BinaryColumn::new(&[0], &[], None).unwrap();
+        assert!(short.is_empty());
+        assert_eq!(
+            similarity_columns(&a, &short, &config),
+            Err(Error::InvalidLength {
+                expected: 3,
+                found: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_buffers() {
+        assert!(BinaryColumn::new(&[], &[], None).is_err());
+        assert!(BinaryColumn::new(&[-1, 0], &[], None).is_err());
+        assert!(BinaryColumn::new(&[0, 2, 1], &[0, 0], None).is_err());
+        assert!(BinaryColumn::new(&[0, 3], &[0, 0], None).is_err());
+        assert!(BinaryColumn::new(&[0; 10], &[], Some(&[0xFF])).is_err());
+        assert!(BinaryColumn::new(&[0; 9], &[], Some(&[0xFF])).is_ok());
+    }
+}
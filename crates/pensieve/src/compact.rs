@@ -0,0 +1,296 @@
+//! Digests stored as their chunk levels.
+//!
+//! A collapsed digest is as long as its input, yet it is determined by the
+//! configuration, the input length and at most eight chunk levels: every
+//! byte repeats a level of one chunk. A [`CompactDigest`] keeps only those,
+//! so indices and wire formats store a few bytes per digest however large
+//! the input, and [`CompactDigest::expand`] gives the full digest back.
+//! [`CompactDigest::from_digest`] goes the other way, so the round trip is
+//! lossless in both directions.
+//!
+//! [`CompactDigest::to_bytes`] writes
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 4 | magic `PNSC` |
+//! | 1 | format version (currently 1) |
+//! | 1 | algorithm version ([`TbfConfig::ALGORITHM_VERSION`]) |
+//! | 1 | bit order ([`BitOrder::id`](crate::BitOrder::id)) |
+//! | 1 | output transform ([`FinalTransform::id`](crate::FinalTransform::id)) |
+//! | 1 | mask base of [`FinalTransform::XorMask`](crate::FinalTransform::XorMask), otherwise 0 |
+//! | 1 | thresholding ([`Thresholding::id`](crate::Thresholding::id)) |
+//! | 4 | tolerance, f32 little-endian |
+//! | 8 | digest length in bytes, little-endian |
+//! | n | chunk levels, packed MSB-first (at most one byte) |
+//! | 4 | first 4 bytes of SHA-256 over everything above |
+//!
+//! As in a [`DigestEnvelope`](crate::envelope::DigestEnvelope), the key of
+//! [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) is never
+//! stored; such digests are read with [`CompactDigest::from_bytes_keyed`].
+//!
+//! ```rust
+//! use pensieve::TbfConfig;
+//! use pensieve::compact::CompactDigest;
+//!
+//! let config = TbfConfig::new(0.125);
+//! let input = vec![0xF7u8; 1 << 20];
+//! let compact = CompactDigest::collapse(&input, &config);
+//! let stored = compact.to_bytes();
+//! assert_eq!(stored.len(), 27);
+//!
+//! let restored = CompactDigest::from_bytes(&stored).unwrap();
+//! assert_eq!(restored.expand(), config.collapse(&input));
+//! assert_eq!(CompactDigest::from_digest(&restored.expand(), &config), Ok(compact));
+//! ```
+
+use crate::config::{pack_levels, unpack_levels};
+use crate::crypto::ct_eq;
+use crate::header::{self, CHECKSUM_LEN, Format};
+use crate::{Error, TbfConfig};
+use alloc::vec::Vec;
+
+/// Header of every serialized compact digest.
+const FORMAT: Format = Format {
+    magic: *b"PNSC",
+    version: 1,
+    foreign: "not a pensieve compact digest",
+    unsupported: "unsupported compact digest format version",
+    needs_key: "keyed transform needs from_bytes_keyed",
+};
+/// Bytes before the packed levels.
+const HEADER_LEN: usize = 22;
+
+/// A collapsed digest reduced to its configuration, length and chunk
+/// levels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactDigest {
+    config: TbfConfig,
+    len: usize,      // Length of the digest, and of its input.
+    packed: Vec<u8>, // Chunk levels, packed MSB-first with zero padding.
+}
+
+impl CompactDigest {
+    /// The compact form of the digest `config` collapses `input` to.
+    pub fn collapse(input: &[u8], config: &TbfConfig) -> Self {
+        Self {
+            config: *config,
+            len: input.len(),
+            packed: config.collapse_packed(input),
+        }
+    }
+
+    /// The compact form of `digest`, produced under `config`.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `config` cannot produce
+    /// `digest` from any input. Not constant-time; for the
+    /// [`KeyedPrf`](crate::FinalTransform::KeyedPrf) and
+    /// [`Hash`](crate::FinalTransform::Hash) transforms it tries every
+    /// combination of chunk levels, and a digest of a few bytes may be the
+    /// output of several; the first found is kept, which expands to the same
+    /// digest.
+    pub fn from_digest(digest: &[u8], config: &TbfConfig) -> Result<Self, Error> {
+        let chunks = config.chunk_count(digest.len());
+        let levels = config
+            .transform()
+            .invert(digest, chunks)
+            .ok_or(Error::InvalidParameter(
+                "digest was not produced under this configuration",
+            ))?;
+        Ok(Self {
+            config: *config,
+            len: digest.len(),
+            packed: pack_levels(&levels),
+        })
+    }
+
+    /// The configuration the digest was produced with.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// Length of the full digest, which is that of its input.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the digest is of the empty input.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The chunk levels, one 0/1 byte per chunk, as
+    /// [`TbfConfig::collapse_levels`] returns them.
+    pub fn levels(&self) -> Vec<u8> {
+        unpack_levels(&self.packed, self.config.chunk_count(self.len))
+    }
+
+    /// The chunk levels packed MSB-first, as
+    /// [`TbfConfig::collapse_packed`] returns them.
+    pub fn packed_levels(&self) -> &[u8] {
+        &self.packed
+    }
+
+    /// The full digest, as [`TbfConfig::collapse`] returns it.
+    pub fn expand(&self) -> Vec<u8> {
+        self.config
+            .expand_packed(&self.packed, self.len)
+            .expect("levels match the length")
+    }
+
+    /// Whether `input` collapses to this digest. The comparison is
+    /// constant-time.
+    pub fn verify(&self, input: &[u8]) -> bool {
+        input.len() == self.len && ct_eq(&self.config.collapse_packed(input), &self.packed)
+    }
+
+    /// Serializes in the format described in the [module documentation](self).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = FORMAT.write(&self.config, HEADER_LEN + self.packed.len() + CHECKSUM_LEN);
+        out.extend_from_slice(&(self.len as u64).to_le_bytes());
+        out.extend_from_slice(&self.packed);
+        header::seal(&mut out);
+        out
+    }
+
+    /// Parses the output of [`CompactDigest::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidLength`] if `bytes` is truncated or holds the
+    /// wrong number of levels for its length, with [`Error::InvalidChecksum`]
+    /// on corruption and with [`Error::InvalidParameter`] for digests this
+    /// build cannot interpret. Digests under
+    /// [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) need
+    /// [`CompactDigest::from_bytes_keyed`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, None)
+    }
+
+    /// [`CompactDigest::from_bytes`] for digests that may be under
+    /// [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) with
+    /// `key`. A wrong key is not detected here; [`CompactDigest::verify`] then
+    /// fails.
+    pub fn from_bytes_keyed(bytes: &[u8], key: &[u8; 32]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, Some(key))
+    }
+
+    fn from_bytes_with(bytes: &[u8], key: Option<&[u8; 32]>) -> Result<Self, Error> {
+        let (config, body) = FORMAT.open(bytes, HEADER_LEN, key)?;
+        let len = header::read_input_len(bytes)?;
+        let chunks = config.chunk_count(len);
+        let packed = &body[HEADER_LEN..];
+        if packed.len() != chunks.div_ceil(8) {
+            return Err(Error::InvalidLength {
+                expected: HEADER_LEN + chunks.div_ceil(8) + CHECKSUM_LEN,
+                found: bytes.len(),
+            });
+        }
+        Ok(Self {
+            config,
+            len,
+            // Repacking clears the padding bits, so equal digests compare equal.
+            packed: pack_levels(&unpack_levels(packed, chunks)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use crate::{BitOrder, FinalTransform, Thresholding};
+
+    fn configs() -> [TbfConfig; 5] {
+        let config = TbfConfig::new(0.125);
+        [
+            config,
+            config.with_transform(FinalTransform::Identity),
+            config
+                .with_bit_order(BitOrder::LsbFirst)
+                .with_thresholding(Thresholding::Parity),
+            config.with_transform(FinalTransform::KeyedPrf { key: [7; 32] }),
+            config.with_transform(FinalTransform::Hash),
+        ]
+    }
+
+    #[test]
+    fn test_round_trips_through_the_full_digest() {
+        let mut fill = seeded(0x399);
+        for config in configs() {
+            for len in [0, 1, 2, 3, 15, 16, 40, 127, 128, 1000] {
+                let mut input = alloc::vec![0u8; len];
+                fill(&mut input);
+                let compact = CompactDigest::collapse(&input, &config);
+                let digest = config.collapse(&input);
+                assert_eq!(compact.len(), len);
+                assert_eq!(compact.expand(), digest);
+                assert_eq!(compact.levels(), config.collapse_levels(&input));
+                assert!(compact.verify(&input));
+                let recovered = CompactDigest::from_digest(&digest, &config).unwrap();
+                assert_eq!(recovered.expand(), digest);
+                if len >= 4 {
+                    assert_eq!(recovered, compact);
+                }
+            }
+        }
+
+        // Bytes no input collapses to are not a digest.
+        let config = TbfConfig::new(0.125).with_transform(FinalTransform::Identity);
+        let mut digest = config.collapse(&[0xFF; 64]);
+        digest[5] ^= 1;
+        assert!(CompactDigest::from_digest(&digest, &config).is_err());
+        let config = TbfConfig::new(0.125).with_transform(FinalTransform::Hash);
+        assert!(CompactDigest::from_digest(&[0; 64], &config).is_err());
+    }
+
+    #[test]
+    fn test_serialization_round_trips_and_rejects_corruption() {
+        let input = [0xF0u8; 300];
+        for config in configs() {
+            let compact = CompactDigest::collapse(&input, &config);
+            let bytes = compact.to_bytes();
+            assert_eq!(bytes.len(), HEADER_LEN + 1 + CHECKSUM_LEN);
+            let decoded = match config.transform() {
+                FinalTransform::KeyedPrf { key } => {
+                    assert!(CompactDigest::from_bytes(&bytes).is_err());
+                    CompactDigest::from_bytes_keyed(&bytes, &key)
+                }
+                _ => CompactDigest::from_bytes(&bytes),
+            };
+            assert_eq!(decoded, Ok(compact));
+        }
+
+        let bytes = CompactDigest::collapse(&input, &TbfConfig::default()).to_bytes();
+        let mut corrupted = bytes.clone();
+        corrupted[HEADER_LEN] ^= 1;
+        assert_eq!(
+            CompactDigest::from_bytes(&corrupted),
+            Err(Error::InvalidChecksum)
+        );
+        assert!(matches!(
+            CompactDigest::from_bytes(&bytes[..HEADER_LEN]),
+            Err(Error::InvalidLength { .. })
+        ));
+        let empty = CompactDigest::collapse(&[], &TbfConfig::default());
+        assert!(empty.is_empty() && empty.expand().is_empty());
+        assert_eq!(CompactDigest::from_bytes(&empty.to_bytes()), Ok(empty));
+    }
+
+    #[test]
+    fn test_lengths_whose_bits_overflow_are_rejected() {
+        let resealed = |len: u64| {
+            let mut body = CompactDigest::collapse(&[0xF0; 300], &TbfConfig::default()).to_bytes();
+            body.truncate(body.len() - CHECKSUM_LEN);
+            body[14..HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+            header::seal(&mut body);
+            body
+        };
+        assert_eq!(
+            CompactDigest::from_bytes(&resealed(u64::MAX)),
+            Err(Error::InvalidParameter("input length overflows"))
+        );
+        let largest = (usize::MAX / 8) as u64;
+        assert_eq!(
+            CompactDigest::from_bytes(&resealed(largest)).map(|c| c.len),
+            Ok(largest as usize)
+        );
+    }
+}
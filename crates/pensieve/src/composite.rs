@@ -0,0 +1,128 @@
+//! Collapses of one input at several tolerances, computed together.
+//!
+//! The chunk boundaries of the collapse depend only on the input length;
+//! the tolerance merely sets the popcount threshold each chunk is compared
+//! against. A [`CompositeDigest`] therefore reads the input once, keeps the
+//! per-chunk popcounts, and derives the collapse at every requested
+//! tolerance from them. Consumers can match on the loosest resolution
+//! first and refine with the stricter ones, without re-reading the data.
+
+use crate::{ChunkLayout, FinalTransform, TbfConfig, ct_level, ct_popcount_range};
+use alloc::vec::Vec;
+
+/// The resolutions of [`CompositeDigest::standard`]: 5%, 12.5% and 25%.
+pub const STANDARD_TOLERANCES: [f32; 3] = [0.05, 0.125, 0.25];
+
+/// The collapses of one input at several tolerances.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompositeDigest {
+    digests: Vec<(f32, Vec<u8>)>, // (clamped tolerance, collapse_ct output), in request order.
+}
+
+impl CompositeDigest {
+    /// Collapses `input` at every tolerance in `tolerances` in one pass.
+    ///
+    /// Each digest equals [`collapse_ct`](crate::collapse_ct) of `input` at
+    /// that tolerance, and is computed in constant time like it. Tolerances
+    /// are clamped like in [`TbfConfig::new`]; duplicates after clamping
+    /// are kept.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::collapse_ct;
+    /// use pensieve::composite::CompositeDigest;
+    ///
+    /// let input = [0x5Au8; 32];
+    /// let composite = CompositeDigest::standard(&input);
+    /// assert_eq!(composite.get(0.125), Some(collapse_ct(&input, 0.125).as_slice()));
+    /// ```
+    pub fn new(input: &[u8], tolerances: &[f32]) -> Self {
+        let counts: Vec<u32> = ChunkLayout::new(input.len(), TbfConfig::MIN_TOLERANCE).map_or_else(
+            Vec::new,
+            |layout| {
+                layout
+                    .ranges()
+                    .map(|(start, end)| ct_popcount_range(input, start, end))
+                    .collect()
+            },
+        );
+        let digests = tolerances
+            .iter()
+            .map(|&tolerance| {
+                let tolerance = TbfConfig::new(tolerance).tolerance();
+                let digest = match ChunkLayout::new(input.len(), tolerance) {
+                    Some(layout) => {
+                        let levels: Vec<u8> = counts
+                            .iter()
+                            .map(|&sum| ct_level(sum, layout.threshold))
+                            .collect();
+                        FinalTransform::default().apply(&levels, input.len())
+                    }
+                    None => Vec::new(),
+                };
+                (tolerance, digest)
+            })
+            .collect();
+        Self { digests }
+    }
+
+    /// [`CompositeDigest::new`] at the [`STANDARD_TOLERANCES`].
+    pub fn standard(input: &[u8]) -> Self {
+        Self::new(input, &STANDARD_TOLERANCES)
+    }
+
+    /// The digest at `tolerance` (clamped), if it was requested.
+    pub fn get(&self, tolerance: f32) -> Option<&[u8]> {
+        let tolerance = TbfConfig::new(tolerance).tolerance();
+        self.resolutions()
+            .find(|&(t, _)| t == tolerance)
+            .map(|(_, digest)| digest)
+    }
+
+    /// Every `(tolerance, digest)` pair, in the order requested.
+    pub fn resolutions(&self) -> impl Iterator<Item = (f32, &[u8])> + '_ {
+        self.digests.iter().map(|(t, d)| (*t, d.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collapse_ct;
+    use crate::entropy::tests::seeded;
+
+    #[test]
+    fn test_every_resolution_matches_collapse_ct() {
+        let mut rng = seeded(42);
+        let tolerances = [0.0, 0.05, 0.1, 0.125, 0.2, 0.25, 0.9];
+        for len in [0usize, 1, 2, 7, 15, 16, 33, 300] {
+            let mut input = alloc::vec![0u8; len];
+            rng(&mut input);
+            input.iter_mut().for_each(|b| *b &= 0x13);
+            let composite = CompositeDigest::new(&input, &tolerances);
+            assert_eq!(composite.resolutions().count(), tolerances.len());
+            for (&requested, (tolerance, digest)) in tolerances.iter().zip(composite.resolutions())
+            {
+                assert_eq!(tolerance, requested.clamp(0.05, 0.25));
+                assert_eq!(digest, collapse_ct(&input, requested), "len {len}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_coarse_match_then_refine() {
+        let mut enrolled = [0u8; 16];
+        enrolled[0] = 0xFF;
+        let mut probe = enrolled;
+        probe[0] = 0b0000_0111; // 3 of 8 bits left; thresholds 1, 2 and 4.
+        let (a, b) = (
+            CompositeDigest::standard(&enrolled),
+            CompositeDigest::standard(&probe),
+        );
+        assert_eq!(a.get(0.05), b.get(0.05));
+        assert_eq!(a.get(0.125), b.get(0.125));
+        assert_ne!(a.get(0.25), b.get(0.25));
+        assert_eq!(a.get(0.2), None);
+        assert_eq!(a.get(0.01), a.get(0.05));
+    }
+}
@@ -0,0 +1,1218 @@
+//! Parameters of the collapse, bundled into one value that can be passed
+//! around and stored next to the digests it produced.
+
+use crate::{Error, FinalTransform};
+#[cfg(feature = "alloc")]
+use alloc::{borrow::Cow, vec::Vec};
+
+/// The order in which the bits of each input byte are read.
+///
+/// Chunks are contiguous runs of bits, so the order decides which bits of a
+/// byte that straddles a chunk boundary land in which chunk. Inputs that
+/// are dumps of LSB-first hardware registers should be collapsed
+/// [`LsbFirst`](BitOrder::LsbFirst) so that chunk boundaries fall between
+/// the same register bits as on the device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BitOrder {
+    /// Bit 7 of every byte first; the original behavior.
+    #[default]
+    MsbFirst,
+    /// Bit 0 of every byte first.
+    LsbFirst,
+}
+
+impl BitOrder {
+    /// Stable one-byte identifier, used by storage formats.
+    pub fn id(self) -> u8 {
+        match self {
+            Self::MsbFirst => 0,
+            Self::LsbFirst => 1,
+        }
+    }
+
+    /// Inverse of [`BitOrder::id`]; `None` for unknown identifiers.
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::MsbFirst),
+            1 => Some(Self::LsbFirst),
+            _ => None,
+        }
+    }
+}
+
+/// How the bits of a chunk reduce to its level.
+///
+/// The default rule sets a chunk to 1 as soon as `tolerance × chunk_size`
+/// of its bits are set, so at any supported tolerance a chunk that is
+/// three quarters zeros collapses exactly like one that is all ones. The
+/// symmetric rule decides by proximity instead: a chunk collapses to
+/// whichever of all-zeros and all-ones it is nearer to. It ignores the
+/// tolerance, so it is opt-in.
+///
+/// The right rule depends on the noise. Independent random flips leave a
+/// chunk near its true popcount, which both threshold rules absorb. Stuck-at
+/// bits bias every chunk the same way, which [`Mean`](Thresholding::Mean)
+/// cancels by comparing chunks against each other. [`Parity`](Thresholding::Parity)
+/// tolerates no flips at all but gives every chunk a uniform level whatever
+/// the bias, for inputs that are already error-corrected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Thresholding {
+    /// A chunk collapses to 1 when more than half of its nominal
+    /// `chunk_size` bits are set, and to 0 otherwise, ties included. Every
+    /// chunk within the tolerance (at most 25%) of all-zeros or all-ones
+    /// therefore keeps its level, but the tolerance does not move the
+    /// threshold: configurations differing only in tolerance collapse
+    /// alike. Since [algorithm version](TbfConfig::ALGORITHM_VERSION) 5.
+    Symmetric,
+    /// A chunk collapses to 1 when at least `ceil(tolerance × chunk_size)`
+    /// of its bits are set; the only rule of algorithm versions 1 to 4, and
+    /// that of [`collapse_deterministic`](crate::collapse_deterministic),
+    /// [`collapse_ct`](crate::collapse_ct), [`matches`](crate::matches) and
+    /// every other function taking a bare tolerance.
+    #[default]
+    OneSided,
+    /// A chunk collapses to its parity: 1 when an odd number of its bits
+    /// are set. Every bit flip toggles the level.
+    Parity,
+    /// A chunk collapses to 1 when its fraction of set bits is above that
+    /// of the whole input, and to 0 otherwise. A bias shared by all chunks
+    /// moves the mean with them; input with a single chunk, or with equally
+    /// dense chunks, collapses to all zeros.
+    Mean,
+}
+
+impl Thresholding {
+    /// Stable one-byte identifier, used by storage formats.
+    pub fn id(self) -> u8 {
+        match self {
+            Self::OneSided => 0,
+            Self::Symmetric => 1,
+            Self::Parity => 2,
+            Self::Mean => 3,
+        }
+    }
+
+    /// Inverse of [`Thresholding::id`]; `None` for unknown identifiers.
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::OneSided),
+            1 => Some(Self::Symmetric),
+            2 => Some(Self::Parity),
+            3 => Some(Self::Mean),
+            _ => None,
+        }
+    }
+}
+
+/// Configuration of Thresholded Bit Folding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TbfConfig {
+    permille: u16, // Tolerated bit flips per 1000 bits, already clamped.
+    bit_order: BitOrder,
+    thresholding: Thresholding,
+    transform: FinalTransform,
+}
+
+impl TbfConfig {
+    /// Revision of the collapse algorithm this configuration describes;
+    /// recorded wherever digests are stored so they can be re-verified.
+    ///
+    /// - 1: the original algorithm, defined for inputs up to 85 bytes.
+    /// - 2: the position-dependent XOR wraps modulo 256, defining the output
+    ///   for inputs of any length. Outputs of inputs up to 85 bytes are
+    ///   unchanged, so version 1 digests remain valid.
+    /// - 3: inputs under 16 bits fold into up to 8 chunks of 1 or 2 bits
+    ///   instead of a single chunk. Only digests of one-byte inputs (and of
+    ///   [`collapse_bits`](crate::collapse_bits) inputs under 16 bits)
+    ///   change; stored envelopes of those record the old chunk parameters
+    ///   and are rejected when decoded.
+    /// - 4: tolerances are rounded to the nearest per-mille and thresholds
+    ///   are computed in integer arithmetic instead of `f32`. Digests change
+    ///   where the tolerance was not a whole per-mille or `f32` rounding
+    ///   raised the threshold; their stored envelopes record the old
+    ///   threshold and are rejected when decoded.
+    /// - 5: configurations can opt into [`Thresholding::Symmetric`] and the
+    ///   other rules, recorded in envelopes. Digests under the default
+    ///   [`Thresholding::OneSided`] are unchanged, and envelopes of earlier
+    ///   versions decode with it.
+    pub const ALGORITHM_VERSION: u8 = 5;
+    /// Smallest supported tolerance (5%).
+    pub const MIN_TOLERANCE: f32 = 0.05;
+    /// Largest supported tolerance (25%).
+    pub const MAX_TOLERANCE: f32 = 0.25;
+    /// [`MIN_TOLERANCE`](Self::MIN_TOLERANCE) in per-mille.
+    pub const MIN_TOLERANCE_PERMILLE: u16 = 50;
+    /// [`MAX_TOLERANCE`](Self::MAX_TOLERANCE) in per-mille.
+    pub const MAX_TOLERANCE_PERMILLE: u16 = 250;
+    /// Shortest input, in bits, that [`try_collapse`](Self::try_collapse)
+    /// accepts: eight chunks of 16 bits, the first length at which every
+    /// supported tolerance absorbs a flip in every chunk. Shorter inputs
+    /// fold into fewer or smaller chunks, down to 1-bit chunks that
+    /// tolerate nothing, and collapse to so few distinct outputs that a
+    /// digest of them reveals the input.
+    pub const MIN_INPUT_BITS: usize = 128;
+
+    /// Creates a configuration reading bits MSB-first, thresholding
+    /// [one-sidedly](Thresholding::OneSided) and producing the default
+    /// [`FinalTransform`]. `tolerance` is clamped to
+    /// [`MIN_TOLERANCE`](Self::MIN_TOLERANCE)..=[`MAX_TOLERANCE`](Self::MAX_TOLERANCE),
+    /// and rounded to the nearest per-mille, exactly like
+    /// [`collapse_deterministic`](crate::collapse_deterministic) does.
+    pub fn new(tolerance: f32) -> Self {
+        Self::from_permille(crate::tolerance_permille(tolerance) as u16)
+    }
+
+    /// [`TbfConfig::new`] with the tolerance given in per-mille, clamped to
+    /// [`MIN_TOLERANCE_PERMILLE`](Self::MIN_TOLERANCE_PERMILLE)..=[`MAX_TOLERANCE_PERMILLE`](Self::MAX_TOLERANCE_PERMILLE).
+    /// Collapsing under such a configuration uses no floating point.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    ///
+    /// assert_eq!(TbfConfig::from_permille(125), TbfConfig::new(0.125));
+    /// assert_eq!(TbfConfig::from_permille(1000).tolerance_permille(), 250);
+    /// ```
+    pub fn from_permille(permille: u16) -> Self {
+        Self::new_const(permille)
+    }
+
+    /// [`TbfConfig::from_permille`] as a `const fn`, for configurations
+    /// fixed at compile time. Together with the other `const` builders and
+    /// [`TbfConfig::fixed`] it moves all per-call setup of a collapse of
+    /// known length into a constant.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::{FixedConfig, TbfConfig, Thresholding};
+    ///
+    /// const HEADER: FixedConfig = TbfConfig::new_const(125)
+    ///     .with_thresholding(Thresholding::OneSided)
+    ///     .fixed(64);
+    ///
+    /// let header = [0x5Au8; 64];
+    /// assert_eq!(HEADER.chunk_count(), 8);
+    /// # #[cfg(feature = "alloc")]
+    /// assert_eq!(HEADER.collapse(&header), Ok(HEADER.config().collapse(&header)));
+    /// # #[cfg(feature = "alloc")]
+    /// assert!(HEADER.collapse(&header[1..]).is_err());
+    /// ```
+    pub const fn new_const(permille: u16) -> Self {
+        let permille = if permille < Self::MIN_TOLERANCE_PERMILLE {
+            Self::MIN_TOLERANCE_PERMILLE
+        } else if permille > Self::MAX_TOLERANCE_PERMILLE {
+            Self::MAX_TOLERANCE_PERMILLE
+        } else {
+            permille
+        };
+        Self {
+            permille,
+            bit_order: BitOrder::MsbFirst,
+            thresholding: Thresholding::OneSided,
+            transform: FinalTransform::XorMask { base: 0xAA },
+        }
+    }
+
+    /// This configuration with `tolerance`, clamped and rounded like in
+    /// [`TbfConfig::new`].
+    pub fn with_tolerance(self, tolerance: f32) -> Self {
+        Self {
+            permille: Self::new(tolerance).permille,
+            ..self
+        }
+    }
+
+    /// This configuration with bits read in `bit_order`.
+    pub const fn with_bit_order(self, bit_order: BitOrder) -> Self {
+        Self { bit_order, ..self }
+    }
+
+    /// This configuration with chunk levels decided by `thresholding`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::{TbfConfig, Thresholding};
+    ///
+    /// // Two stuck-at-one bits in every byte: only the second half of the
+    /// // input is really set, and only the mean rule still sees that.
+    /// let mut clean = [0u8; 16];
+    /// clean[8..].fill(0xFF);
+    /// let dump = clean.map(|byte| byte | 0x03);
+    /// let mean = TbfConfig::new(0.125).with_thresholding(Thresholding::Mean);
+    /// # #[cfg(feature = "alloc")]
+    /// assert_eq!(mean.collapse(&dump), mean.collapse(&clean));
+    /// let one_sided = mean.with_thresholding(Thresholding::OneSided);
+    /// # #[cfg(feature = "alloc")]
+    /// assert_eq!(one_sided.collapse(&dump), one_sided.collapse(&[0xFF; 16]));
+    /// ```
+    pub const fn with_thresholding(self, thresholding: Thresholding) -> Self {
+        Self {
+            thresholding,
+            ..self
+        }
+    }
+
+    /// This configuration with output bytes produced by `transform`.
+    pub const fn with_transform(self, transform: FinalTransform) -> Self {
+        Self { transform, ..self }
+    }
+
+    /// The effective (clamped and rounded) tolerance.
+    pub fn tolerance(&self) -> f32 {
+        f32::from(self.permille) / 1000.0
+    }
+
+    /// The effective tolerance in per-mille.
+    pub const fn tolerance_permille(&self) -> u16 {
+        self.permille
+    }
+
+    /// The order in which bits of each input byte are read.
+    pub const fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    /// How the bits of a chunk reduce to its level.
+    pub const fn thresholding(&self) -> Thresholding {
+        self.thresholding
+    }
+
+    /// How chunk levels become output bytes.
+    pub const fn transform(&self) -> FinalTransform {
+        self.transform
+    }
+
+    /// This configuration bound to inputs of `input_len` bytes, with their
+    /// chunk boundaries and threshold computed once. See
+    /// [`TbfConfig::new_const`].
+    pub const fn fixed(self, input_len: usize) -> FixedConfig {
+        FixedConfig {
+            config: self,
+            input_len,
+            layout: crate::ChunkLayout::for_config(input_len * 8, &self),
+        }
+    }
+
+    /// Collapses `input` under this configuration in constant time. With
+    /// the default bit order, thresholding and transform this is [`collapse_ct`](crate::collapse_ct). Empty input collapses to the
+    /// empty vector, a sentinel rather than a fingerprint; see
+    /// [`collapse_deterministic`](crate::collapse_deterministic#empty-input).
+    ///
+    /// Inputs of every length are accepted, including those too short for
+    /// the collapse to tolerate or hide anything; use
+    /// [`try_collapse`](Self::try_collapse) unless short inputs are
+    /// expected.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::{BitOrder, TbfConfig};
+    ///
+    /// // 40 bits in two chunks of 20: bit 0 of byte 2 is bit 16 LSB-first
+    /// // (first chunk) but bit 23 MSB-first (second chunk), so it tips the
+    /// // first chunk past half its bits only LSB-first.
+    /// let dump = [0xFF, 0x03, 0x01, 0, 0];
+    /// let msb = TbfConfig::new(0.05);
+    /// let lsb = msb.with_bit_order(BitOrder::LsbFirst);
+    /// assert_ne!(msb.collapse(&dump), lsb.collapse(&dump));
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn collapse(&self, input: &[u8]) -> Vec<u8> {
+        if input.is_empty() {
+            return Vec::new(); // The documented empty sentinel.
+        }
+        let _timer = crate::metrics::time_collapse(input.len(), || self.chunk_count(input.len()));
+        self.transform.apply(&self.chunk_levels(input), input.len())
+    }
+
+    /// [`TbfConfig::collapse`] of `input`, if it holds at least
+    /// [`MIN_INPUT_BITS`](Self::MIN_INPUT_BITS) bits.
+    ///
+    /// Fails with [`Error::InputTooShort`] otherwise; callers collapsing
+    /// short inputs on purpose, such as small identifiers that need a
+    /// mapping rather than any tolerance, opt in with
+    /// [`collapse`](Self::collapse).
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::{Error, TbfConfig};
+    ///
+    /// let config = TbfConfig::default();
+    /// assert_eq!(config.try_collapse(&[0x5A; 16]), Ok(config.collapse(&[0x5A; 16])));
+    /// assert_eq!(
+    ///     config.try_collapse(&[192, 168, 0, 1]),
+    ///     Err(Error::InputTooShort {
+    ///         minimum_bits: 128,
+    ///         found_bits: 32
+    ///     })
+    /// );
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn try_collapse(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        if input.len() * 8 < Self::MIN_INPUT_BITS {
+            return Err(Error::InputTooShort {
+                minimum_bits: Self::MIN_INPUT_BITS,
+                found_bits: input.len() * 8,
+            });
+        }
+        Ok(self.collapse(input))
+    }
+
+    /// [`TbfConfig::collapse`] into `out`, which must be as long as `input`,
+    /// without allocating; for targets without a heap. Runs in constant
+    /// time like [`TbfConfig::collapse`].
+    ///
+    /// Fails with [`Error::InvalidLength`] if `out` is not as long as
+    /// `input`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    ///
+    /// let config = TbfConfig::new(0.125);
+    /// let mut digest = [0u8; 16];
+    /// config.collapse_into(&[0xF7; 16], &mut digest).unwrap();
+    /// # #[cfg(feature = "alloc")]
+    /// assert_eq!(digest[..], config.collapse(&[0xF7; 16])[..]);
+    /// ```
+    pub fn collapse_into(&self, input: &[u8], out: &mut [u8]) -> Result<(), Error> {
+        if out.len() != input.len() {
+            return Err(Error::InvalidLength {
+                expected: input.len(),
+                found: out.len(),
+            });
+        }
+        let _timer = crate::metrics::time_collapse(input.len(), || self.chunk_count(input.len()));
+        let (levels, chunks) = self.levels_array(input);
+        self.transform
+            .apply_at(&levels[..chunks], input.len(), 0, out);
+        Ok(())
+    }
+
+    /// [`TbfConfig::collapse`] of a value of fixed width, such as an
+    /// integer identifier or a hash, returned as a value of the same type
+    /// without allocating. Integers are collapsed as their big-endian bytes,
+    /// so bit 0 of the input is their most significant bit.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    ///
+    /// let config = TbfConfig::new(0.125);
+    /// let id: u32 = 0xFFFF_0F00;
+    /// let digest = config.collapse_fixed(id);
+    /// # #[cfg(feature = "alloc")]
+    /// assert_eq!(digest.to_be_bytes()[..], config.collapse(&id.to_be_bytes())[..]);
+    /// // One flipped bit in a mostly set half is absorbed.
+    /// assert_eq!(digest, config.collapse_fixed(id ^ 1 << 31));
+    /// ```
+    pub fn collapse_fixed<const N: usize, T: FixedBytes<N>>(&self, value: T) -> T {
+        let mut out = [0u8; N];
+        self.collapse_into(&value.to_bytes(), &mut out)
+            .expect("equal lengths");
+        T::from_bytes(out)
+    }
+
+    /// [`TbfConfig::collapse_fixed`] of a `u64`.
+    pub fn collapse_u64(&self, value: u64) -> u64 {
+        self.collapse_fixed(value)
+    }
+
+    /// [`TbfConfig::collapse_fixed`] of a `u128`.
+    pub fn collapse_u128(&self, value: u128) -> u128 {
+        self.collapse_fixed(value)
+    }
+
+    /// Passes the digest of `input` to `sink` a few bytes at a time, without
+    /// allocating.
+    pub(crate) fn collapse_streamed(&self, input: &[u8], sink: impl FnMut(&[u8])) {
+        let (levels, chunks) = self.levels_array(input);
+        self.transform.emit(&levels[..chunks], input.len(), sink);
+    }
+
+    /// The chunk levels of `input` in the first entries of an array, and
+    /// their number, in constant time.
+    fn levels_array(&self, input: &[u8]) -> ([u8; crate::MAX_CHUNKS], usize) {
+        crate::ct_layout_levels_array(
+            input,
+            self.bit_order,
+            crate::ChunkLayout::for_config(input.len() * 8, self),
+        )
+    }
+
+    /// [`TbfConfig::collapse`] together with the margin of every chunk: the
+    /// fewest bit flips inside it that would change its level, and so the
+    /// digest (`u32::MAX` if no flips inside it would). A chunk with margin
+    /// 1 barely crossed its threshold; a match decided by such chunks is
+    /// one flip from failing. Under [`Thresholding::Mean`] the rest of the
+    /// input is assumed unchanged.
+    ///
+    /// Margins are listed in chunk order. Unlike the digest they are
+    /// computed with branches, and they reveal how close each chunk's
+    /// popcount is to its threshold, so they are meant for public data.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    ///
+    /// // 8 chunks of 16 bits with threshold 2; chunk 0 holds 2 set bits.
+    /// let mut input = [0u8; 16];
+    /// input[0] = 0x03;
+    /// let config = TbfConfig::new(0.125);
+    /// let (digest, margins) = config.collapse_with_margins(&input);
+    /// assert_eq!(digest, config.collapse(&input));
+    /// assert_eq!(margins, [1, 2, 2, 2, 2, 2, 2, 2]);
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn collapse_with_margins(&self, input: &[u8]) -> (Vec<u8>, Vec<u32>) {
+        let Some(layout) = crate::ChunkLayout::for_config(input.len() * 8, self) else {
+            return (Vec::new(), Vec::new()); // The documented empty sentinel.
+        };
+        let input = self.msb_first(input);
+        let sums: Vec<u32> = layout
+            .ranges()
+            .map(|(start, end)| crate::ct_popcount_range(&input, start, end))
+            .collect();
+        let total = sums.iter().map(|&sum| u64::from(sum)).sum();
+        let margins = layout
+            .ranges()
+            .zip(&sums)
+            .map(|(range, &sum)| layout.margin(range, sum, total))
+            .collect();
+        (
+            self.transform.apply(&layout.levels(&sums), input.len()),
+            margins,
+        )
+    }
+
+    /// [`TbfConfig::collapse`], calling `progress(processed, total)` every
+    /// time another `interval` bytes of the input are counted and once
+    /// when all are, so tools collapsing huge inputs can show a progress
+    /// bar; `total` is the input length. Returning `false` from `progress`
+    /// cancels the collapse, so a service can stop burning CPU on a request
+    /// that has timed out.
+    ///
+    /// Fails with [`Error::Cancelled`] when cancelled and with
+    /// [`Error::InvalidParameter`] if `interval` is zero. For inputs read
+    /// from a file, see [`StreamingCollapse::read_from`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    ///
+    /// let config = TbfConfig::new(0.125);
+    /// let input = vec![0xF7u8; 1000];
+    /// let mut reports = Vec::new();
+    /// let digest = config
+    ///     .collapse_with_progress(&input, 400, |done, total| {
+    ///         reports.push((done, total));
+    ///         true
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(digest, config.collapse(&input));
+    /// assert_eq!(reports, [(400, 1000), (800, 1000), (1000, 1000)]);
+    ///
+    /// let cancelled = config.collapse_with_progress(&input, 400, |done, _| done < 800);
+    /// assert_eq!(cancelled, Err(pensieve::Error::Cancelled));
+    /// ```
+    ///
+    /// [`StreamingCollapse::read_from`]: crate::stream::StreamingCollapse::read_from
+    #[cfg(feature = "alloc")]
+    pub fn collapse_with_progress(
+        &self,
+        input: &[u8],
+        interval: usize,
+        mut progress: impl FnMut(usize, usize) -> bool,
+    ) -> Result<Vec<u8>, Error> {
+        if interval == 0 {
+            return Err(Error::InvalidParameter(
+                "progress interval must be non-zero",
+            ));
+        }
+        let _timer = crate::metrics::time_collapse(input.len(), || self.chunk_count(input.len()));
+        let mut state = crate::stream::StreamingCollapse::new(self.fixed(input.len()));
+        for piece in input.chunks(interval) {
+            state.update(piece)?;
+            if !progress(input.len() - state.remaining(), input.len()) {
+                return Err(Error::Cancelled);
+            }
+        }
+        let mut digest = alloc::vec![0; input.len()];
+        state.finish_into(&mut digest)?;
+        Ok(digest)
+    }
+
+    /// The compact form of [`TbfConfig::collapse`]: only the chunk levels,
+    /// one byte (0 or 1) per chunk, in chunk order. An input of any length
+    /// has at most a handful of chunks, so this digest stays a few bytes
+    /// where the full one is as long as the input; fingerprint databases
+    /// store it and [`TbfConfig::expand_levels`] recovers the full digest.
+    ///
+    /// The final transform is not applied, so the levels are readable
+    /// whatever the transform. Empty input gives the empty vector.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    ///
+    /// let config = TbfConfig::new(0.125);
+    /// let mut input = vec![0u8; 1 << 20];
+    /// input[..1 << 19].fill(0xFF);
+    /// let levels = config.collapse_levels(&input);
+    /// assert!(levels.len() <= 8);
+    /// assert_eq!(levels[0], 1);
+    /// assert_eq!(config.collapse_packed(&input).len(), 1);
+    /// assert_eq!(config.expand_levels(&levels, input.len()), Ok(config.collapse(&input)));
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn collapse_levels(&self, input: &[u8]) -> Vec<u8> {
+        if input.is_empty() {
+            return Vec::new();
+        }
+        self.chunk_levels(input)
+    }
+
+    /// [`TbfConfig::collapse_levels`] packed eight to a byte, MSB-first,
+    /// the last byte padded with zero bits.
+    #[cfg(feature = "alloc")]
+    pub fn collapse_packed(&self, input: &[u8]) -> Vec<u8> {
+        pack_levels(&self.collapse_levels(input))
+    }
+
+    /// The digest [`TbfConfig::collapse`] gives for an input of `input_len`
+    /// bytes whose chunks have `levels`, as returned by
+    /// [`TbfConfig::collapse_levels`].
+    ///
+    /// Fails with [`Error::InvalidLength`] if `levels` does not hold one
+    /// level per chunk of such an input, and with
+    /// [`Error::InvalidParameter`] if a level is neither 0 nor 1.
+    #[cfg(feature = "alloc")]
+    pub fn expand_levels(&self, levels: &[u8], input_len: usize) -> Result<Vec<u8>, Error> {
+        let chunks = self.chunk_count(input_len);
+        if levels.len() != chunks {
+            return Err(Error::InvalidLength {
+                expected: chunks,
+                found: levels.len(),
+            });
+        }
+        if levels.iter().any(|&level| level > 1) {
+            return Err(Error::InvalidParameter("chunk levels must be 0 or 1"));
+        }
+        if input_len == 0 {
+            return Ok(Vec::new());
+        }
+        Ok(self.transform.apply(levels, input_len))
+    }
+
+    /// [`TbfConfig::expand_levels`] for levels packed by
+    /// [`TbfConfig::collapse_packed`]. Padding bits are ignored.
+    ///
+    /// Fails with [`Error::InvalidLength`] if `packed` is not as long as the
+    /// packed levels of such an input.
+    #[cfg(feature = "alloc")]
+    pub fn expand_packed(&self, packed: &[u8], input_len: usize) -> Result<Vec<u8>, Error> {
+        let chunks = self.chunk_count(input_len);
+        if packed.len() != chunks.div_ceil(8) {
+            return Err(Error::InvalidLength {
+                expected: chunks.div_ceil(8),
+                found: packed.len(),
+            });
+        }
+        self.expand_levels(&unpack_levels(packed, chunks), input_len)
+    }
+
+    /// [`TbfConfig::collapse_levels`] of an input of `universe_bits` bits
+    /// given by the strictly increasing positions of its set bits, so a
+    /// sparse high-dimensional feature is never materialized. Bit `i` is
+    /// bit `i % 8` of byte `i / 8` in the configured bit order, and the
+    /// universe is padded with clear bits to whole bytes: the levels are
+    /// those of the dense input of `universe_bits.div_ceil(8)` bytes.
+    ///
+    /// Takes time proportional to the number of set bits, not the
+    /// universe. The positions decide the chunks counted, so unlike
+    /// [`TbfConfig::collapse`] this branches on the input; use it for
+    /// public data. Any iterator of positions works, such as those of a
+    /// roaring bitmap.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if the positions are not
+    /// strictly increasing or not below `universe_bits`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    ///
+    /// let config = TbfConfig::new(0.125);
+    /// let universe = 8 << 20;
+    /// let set = [3, 17, 4_000_000, 4_000_001, 8_000_000];
+    /// let levels = config.collapse_sparse_levels(set, universe).unwrap();
+    ///
+    /// let mut dense = vec![0u8; universe / 8];
+    /// for i in set {
+    ///     dense[i / 8] |= 0x80 >> (i % 8);
+    /// }
+    /// assert_eq!(levels, config.collapse_levels(&dense));
+    /// assert_eq!(config.collapse_sparse(set, universe), Ok(config.collapse(&dense)));
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn collapse_sparse_levels(
+        &self,
+        set_bits: impl IntoIterator<Item = usize>,
+        universe_bits: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let total_bits = universe_bits.div_ceil(8) * 8;
+        let Some(layout) = crate::ChunkLayout::for_config(total_bits, self) else {
+            return match set_bits.into_iter().next() {
+                None => Ok(Vec::new()),
+                Some(_) => Err(Error::InvalidParameter("set bit outside the universe")),
+            };
+        };
+        // Positions in the configured bit order are the MSB-first positions
+        // of the input `msb_first` would read.
+        let mut sums = alloc::vec![0u32; layout.chunk_count()];
+        let mut next = 0;
+        for bit in set_bits {
+            if bit < next {
+                return Err(Error::InvalidParameter(
+                    "set bits must be strictly increasing",
+                ));
+            }
+            if bit >= universe_bits {
+                return Err(Error::InvalidParameter("set bit outside the universe"));
+            }
+            sums[bit / layout.chunk_size] += 1;
+            next = bit + 1;
+        }
+        Ok(layout.levels(&sums))
+    }
+
+    /// The digest [`TbfConfig::collapse`] gives for the sparse input of
+    /// [`TbfConfig::collapse_sparse_levels`]. The digest is as long as the
+    /// dense input; keep the levels instead where that is too large.
+    #[cfg(feature = "alloc")]
+    pub fn collapse_sparse(
+        &self,
+        set_bits: impl IntoIterator<Item = usize>,
+        universe_bits: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let levels = self.collapse_sparse_levels(set_bits, universe_bits)?;
+        self.expand_levels(&levels, universe_bits.div_ceil(8))
+    }
+
+    /// Number of chunks an input of `input_len` bytes is split into.
+    pub(crate) fn chunk_count(&self, input_len: usize) -> usize {
+        input_len
+            .checked_mul(8)
+            .and_then(|bits| crate::ChunkLayout::for_config(bits, self))
+            .map_or(0, |layout| layout.ranges().count())
+    }
+
+    /// The chunk levels behind [`TbfConfig::collapse`], in constant time.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn chunk_levels(&self, input: &[u8]) -> Vec<u8> {
+        crate::ct_layout_levels(
+            &self.msb_first(input),
+            crate::ChunkLayout::for_config(input.len() * 8, self),
+        )
+    }
+
+    /// `input` with its bits rearranged so that reading them MSB-first
+    /// follows this configuration's bit order. Branch-free in the bytes.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn msb_first<'a>(&self, input: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.bit_order {
+            BitOrder::MsbFirst => Cow::Borrowed(input),
+            BitOrder::LsbFirst => Cow::Owned(input.iter().map(|b| b.reverse_bits()).collect()),
+        }
+    }
+}
+
+/// 0/1 chunk levels packed eight to a byte, MSB-first, the last byte padded
+/// with zero bits.
+#[cfg(feature = "alloc")]
+pub(crate) fn pack_levels(levels: &[u8]) -> Vec<u8> {
+    levels
+        .chunks(8)
+        .map(|levels| {
+            levels
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (k, &level)| byte | level << (7 - k))
+        })
+        .collect()
+}
+
+/// The first `chunks` levels packed by [`pack_levels`].
+#[cfg(feature = "alloc")]
+pub(crate) fn unpack_levels(packed: &[u8], chunks: usize) -> Vec<u8> {
+    (0..chunks)
+        .map(|k| packed[k / 8] >> (7 - k % 8) & 1)
+        .collect()
+}
+
+/// A value of `N` bytes, collapsed by [`TbfConfig::collapse_fixed`].
+///
+/// Implemented for byte arrays and for the integer types, as their
+/// big-endian bytes.
+pub trait FixedBytes<const N: usize>: Sized {
+    /// The bytes that are collapsed.
+    fn to_bytes(self) -> [u8; N];
+    /// The value whose bytes are `bytes`.
+    fn from_bytes(bytes: [u8; N]) -> Self;
+}
+
+impl<const N: usize> FixedBytes<N> for [u8; N] {
+    fn to_bytes(self) -> [u8; N] {
+        self
+    }
+
+    fn from_bytes(bytes: [u8; N]) -> Self {
+        bytes
+    }
+}
+
+macro_rules! impl_fixed_bytes {
+    ($($int:ty),*) => {$(
+        impl FixedBytes<{ size_of::<$int>() }> for $int {
+            fn to_bytes(self) -> [u8; size_of::<$int>()] {
+                self.to_be_bytes()
+            }
+
+            fn from_bytes(bytes: [u8; size_of::<$int>()]) -> Self {
+                Self::from_be_bytes(bytes)
+            }
+        }
+    )*};
+}
+
+impl_fixed_bytes!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// A [`TbfConfig`] bound to one input length, returned by
+/// [`TbfConfig::fixed`]. Its chunk layout is computed when it is built, at
+/// compile time for a constant, so collapsing only counts, compares and
+/// emits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedConfig {
+    config: TbfConfig,
+    input_len: usize,
+    layout: Option<crate::ChunkLayout>, // `None` for empty inputs.
+}
+
+impl FixedConfig {
+    /// The configuration inputs are collapsed with.
+    pub const fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// Length in bytes of the inputs this configuration collapses.
+    pub const fn input_len(&self) -> usize {
+        self.input_len
+    }
+
+    /// Number of chunks, and so of levels, of every input.
+    pub const fn chunk_count(&self) -> usize {
+        match &self.layout {
+            Some(layout) => layout.chunk_count(),
+            None => 0,
+        }
+    }
+
+    /// The precomputed layout, `None` for empty inputs.
+    pub(crate) fn layout(&self) -> Option<crate::ChunkLayout> {
+        self.layout
+    }
+
+    /// [`TbfConfig::collapse`] of `input`, without computing its layout.
+    ///
+    /// Fails with [`Error::InvalidLength`] if `input` is not
+    /// [`input_len`](Self::input_len) bytes long.
+    #[cfg(feature = "alloc")]
+    pub fn collapse(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        if input.len() != self.input_len {
+            return Err(Error::InvalidLength {
+                expected: self.input_len,
+                found: input.len(),
+            });
+        }
+        if input.is_empty() {
+            return Ok(Vec::new()); // The documented empty sentinel.
+        }
+        let _timer = crate::metrics::time_collapse(input.len(), || self.chunk_count());
+        let levels = crate::ct_layout_levels(&self.config.msb_first(input), self.layout);
+        Ok(self.config.transform.apply(&levels, input.len()))
+    }
+}
+
+impl Default for TbfConfig {
+    /// 12.5%, one tolerated flip per byte on average, bits MSB-first, the
+    /// default transform.
+    fn default() -> Self {
+        Self::new(0.125)
+    }
+}
+
+/// An output buffer reused across collapses, for callers collapsing inputs
+/// at a high rate that should not allocate per call.
+///
+/// The collapse keeps its other temporaries, at most eight chunk levels,
+/// on the stack, so the buffer grows only when an input is longer than
+/// every input before it, and a scratch sized up front with
+/// [`CollapseScratch::with_capacity`] never allocates. Keep one per thread.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::{CollapseScratch, TbfConfig};
+///
+/// let config = TbfConfig::default();
+/// let mut scratch = CollapseScratch::with_capacity(64);
+/// for reading in [[0xF0u8; 32], [0x0F; 32]] {
+///     let digest = scratch.collapse(&reading, &config);
+///     assert_eq!(digest, config.collapse(&reading));
+/// }
+/// assert_eq!(scratch.capacity(), 64);
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Default)]
+pub struct CollapseScratch {
+    out: Vec<u8>,
+}
+
+#[cfg(feature = "alloc")]
+impl CollapseScratch {
+    /// An empty scratch, allocating on its first collapse.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A scratch collapsing inputs of up to `len` bytes without allocating.
+    pub fn with_capacity(len: usize) -> Self {
+        Self {
+            out: alloc::vec![0; len],
+        }
+    }
+
+    /// Length of the longest input collapsed without allocating.
+    pub fn capacity(&self) -> usize {
+        self.out.len()
+    }
+
+    /// [`TbfConfig::collapse`] of `input` under `config`, written to this
+    /// scratch and valid until its next collapse. Runs in constant time
+    /// like [`TbfConfig::collapse`].
+    pub fn collapse(&mut self, input: &[u8], config: &TbfConfig) -> &[u8] {
+        if self.out.len() < input.len() {
+            self.out.resize(input.len(), 0);
+        }
+        let out = &mut self.out[..input.len()];
+        config
+            .collapse_into(input, out)
+            .expect("output as long as the input");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tolerance_is_clamped() {
+        assert_eq!(TbfConfig::new(0.01).tolerance(), 0.05);
+        assert_eq!(TbfConfig::new(0.9).tolerance(), 0.25);
+        assert_eq!(TbfConfig::new(0.2).tolerance(), 0.2);
+        assert_eq!(TbfConfig::default().tolerance(), 0.125);
+        assert_eq!(TbfConfig::new(f32::NAN).tolerance(), 0.05);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_fixed_configs_collapse_like_their_config() {
+        const FIXED: FixedConfig = TbfConfig::new_const(0).fixed(5);
+        assert_eq!(FIXED.config(), &TbfConfig::from_permille(50));
+        assert_eq!(FIXED.chunk_count(), 2);
+
+        let mut fill = crate::entropy::tests::seeded(403);
+        for config in [
+            TbfConfig::default(),
+            TbfConfig::new(0.2)
+                .with_bit_order(BitOrder::LsbFirst)
+                .with_thresholding(Thresholding::Mean),
+            TbfConfig::new(0.05).with_transform(FinalTransform::Hash),
+        ] {
+            for len in [0, 1, 3, 16, 17, 200] {
+                let mut input = alloc::vec![0u8; len];
+                fill(&mut input);
+                let fixed = config.fixed(len);
+                assert_eq!(fixed.input_len(), len);
+                assert_eq!(fixed.chunk_count(), config.collapse_levels(&input).len());
+                assert_eq!(fixed.collapse(&input), Ok(config.collapse(&input)));
+                assert!(config.fixed(len + 1).collapse(&input).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_tolerance_is_rounded_to_permille() {
+        assert_eq!(TbfConfig::new(0.1234).tolerance_permille(), 123);
+        assert_eq!(TbfConfig::new(0.1236).tolerance_permille(), 124);
+        assert_eq!(TbfConfig::new(0.1234).tolerance(), 0.123);
+        for permille in 0..=1000 {
+            let config = TbfConfig::from_permille(permille);
+            assert_eq!(TbfConfig::new(config.tolerance()), config);
+            assert_eq!(
+                config.tolerance_permille(),
+                permille.clamp(50, 250),
+                "{permille}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_bit_order_ids_roundtrip() {
+        assert_eq!(TbfConfig::default().bit_order(), BitOrder::MsbFirst);
+        for order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            assert_eq!(BitOrder::from_id(order.id()), Some(order));
+        }
+        assert_eq!(BitOrder::from_id(2), None);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_symmetric_thresholding_separates_mixed_chunks() {
+        assert_eq!(TbfConfig::default().thresholding(), Thresholding::OneSided);
+        for thresholding in [
+            Thresholding::OneSided,
+            Thresholding::Symmetric,
+            Thresholding::Parity,
+            Thresholding::Mean,
+        ] {
+            assert_eq!(Thresholding::from_id(thresholding.id()), Some(thresholding));
+        }
+        assert_eq!(Thresholding::from_id(4), None);
+
+        // Chunk 0 a quarter ones, chunk 1 all ones, chunk 2 just over half.
+        let mut input = [0u8; 16];
+        input[0] = 0x0F;
+        input[2..4].fill(0xFF);
+        input[4..6].copy_from_slice(&[0xFF, 0x80]);
+        let one_sided = TbfConfig::new(0.125);
+        let symmetric = one_sided.with_thresholding(Thresholding::Symmetric);
+        assert_eq!(one_sided.chunk_levels(&input), [1, 1, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(symmetric.chunk_levels(&input), [0, 1, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            one_sided.collapse(&input),
+            crate::collapse_ct(&input, 0.125)
+        );
+        // The tolerance no longer moves the threshold.
+        assert_eq!(
+            symmetric.with_tolerance(0.25).chunk_levels(&input),
+            symmetric.chunk_levels(&input)
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_parity_and_mean_aggregation() {
+        // Chunk 0 holds 3 set bits, chunk 1 holds 16, chunk 2 holds 2.
+        let mut input = [0u8; 16];
+        input[0] = 0x07;
+        input[2..4].fill(0xFF);
+        input[5] = 0x81;
+        let parity = TbfConfig::new(0.125).with_thresholding(Thresholding::Parity);
+        assert_eq!(parity.chunk_levels(&input), [1, 0, 0, 0, 0, 0, 0, 0]);
+        let mut flipped = input;
+        flipped[4] ^= 0x10;
+        assert_eq!(parity.chunk_levels(&flipped), [1, 0, 1, 0, 0, 0, 0, 0]);
+
+        // 21 of 128 bits are set: only chunks denser than 21/128 are 1.
+        let mean = parity.with_thresholding(Thresholding::Mean);
+        assert_eq!(mean.chunk_levels(&input), [1, 1, 0, 0, 0, 0, 0, 0]);
+        // A stuck-at-one bit in every byte raises every chunk alike.
+        let stuck = input.map(|byte| byte | 0x40);
+        assert_eq!(mean.chunk_levels(&stuck), mean.chunk_levels(&input));
+        assert_eq!(mean.chunk_levels(&[0x5A; 16]), [0; 8]);
+        assert_eq!(mean.collapse(&[]), []);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_compact_digests_expand_to_full_ones() {
+        let mut rng = crate::entropy::tests::seeded(4);
+        for len in [1, 2, 3, 17, 300] {
+            let mut input = alloc::vec![0u8; len];
+            rng(&mut input);
+            for transform in [FinalTransform::Identity, FinalTransform::Hash] {
+                let config = TbfConfig::new(0.1).with_transform(transform);
+                let levels = config.collapse_levels(&input);
+                assert!(!levels.is_empty() && levels.iter().all(|&level| level <= 1));
+                let packed = config.collapse_packed(&input);
+                assert_eq!(packed.len(), levels.len().div_ceil(8));
+                let full = config.collapse(&input);
+                assert_eq!(config.expand_levels(&levels, len), Ok(full.clone()));
+                assert_eq!(config.expand_packed(&packed, len), Ok(full));
+            }
+        }
+
+        let config = TbfConfig::default();
+        assert!(config.collapse_levels(&[]).is_empty());
+        assert_eq!(config.expand_levels(&[], 0), Ok(Vec::new()));
+        let levels = config.collapse_levels(&[0xFF; 64]);
+        assert!(matches!(
+            config.expand_levels(&levels[1..], 64),
+            Err(Error::InvalidLength { .. })
+        ));
+        let mut invalid = levels.clone();
+        invalid[0] = 2;
+        assert_eq!(
+            config.expand_levels(&invalid, 64),
+            Err(Error::InvalidParameter("chunk levels must be 0 or 1"))
+        );
+        assert!(config.expand_packed(&[0, 0], 64).is_err());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_margins_count_flips_to_change_a_level() {
+        let mut input = [0u8; 16];
+        input[2..4].fill(0xFF);
+        let margins = |config: TbfConfig, input: &[u8]| {
+            let (digest, margins) = config.collapse_with_margins(input);
+            assert_eq!(digest, config.collapse(input));
+            margins
+        };
+        let one_sided = TbfConfig::new(0.125);
+        assert_eq!(margins(one_sided, &input), [2, 15, 2, 2, 2, 2, 2, 2]);
+        let parity = one_sided.with_thresholding(Thresholding::Parity);
+        assert_eq!(margins(parity, &input), [1; 8]);
+        // 3 set bits in a 16-bit chunk lift it above the new mean, 19/128.
+        let mean = one_sided.with_thresholding(Thresholding::Mean);
+        assert_eq!(margins(mean, &input), [3, 16, 3, 3, 3, 3, 3, 3]);
+        // A lone chunk is always at the mean.
+        assert_eq!(margins(mean, &[0xFF, 0]), [u32::MAX]);
+
+        // 56 bits in chunks of 18, 18, 18 and 2: the last can never hold 10.
+        let symmetric = one_sided.with_thresholding(Thresholding::Symmetric);
+        assert_eq!(margins(symmetric, &[0; 7]), [10, 10, 10, u32::MAX]);
+        assert_eq!(
+            symmetric.collapse_with_margins(&[]),
+            (Vec::new(), Vec::new())
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_lsb_first_moves_chunk_boundaries() {
+        let msb = TbfConfig::new(0.05);
+        let lsb = msb.with_bit_order(BitOrder::LsbFirst);
+        // 40 bits in two chunks of 20 with threshold 1; the boundary splits byte 2.
+        let input = [0, 0, 0x01, 0, 0];
+        assert_eq!(msb.chunk_levels(&input), [0, 1]);
+        assert_eq!(lsb.chunk_levels(&input), [1, 0]);
+        assert_eq!(msb.collapse(&input), crate::collapse_ct(&input, 0.05));
+        assert_eq!(
+            lsb.collapse(&input),
+            crate::collapse_ct(&[0, 0, 0x80, 0, 0], 0.05)
+        );
+        // Byte-aligned chunks hold the same bits either way.
+        assert_eq!(msb.collapse(&[0x01; 16]), lsb.collapse(&[0x01; 16]));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_sparse_inputs_collapse_like_dense_ones() {
+        let mut fill = crate::entropy::tests::seeded(427);
+        let configs = [
+            TbfConfig::default(),
+            TbfConfig::new(0.05)
+                .with_bit_order(BitOrder::LsbFirst)
+                .with_thresholding(Thresholding::Symmetric),
+            TbfConfig::new(0.2).with_thresholding(Thresholding::Mean),
+        ];
+        for config in configs {
+            for universe in [1, 13, 128, 1003] {
+                let mut noise = alloc::vec![0u8; universe];
+                fill(&mut noise);
+                let set: Vec<usize> = (0..universe).filter(|&i| noise[i] < 40).collect();
+                let mut dense = alloc::vec![0u8; universe.div_ceil(8)];
+                for &i in &set {
+                    dense[i / 8] |= match config.bit_order() {
+                        BitOrder::MsbFirst => 0x80 >> (i % 8),
+                        BitOrder::LsbFirst => 1 << (i % 8),
+                    };
+                }
+                assert_eq!(
+                    config.collapse_sparse_levels(set.iter().copied(), universe),
+                    Ok(config.collapse_levels(&dense))
+                );
+                assert_eq!(
+                    config.collapse_sparse(set.iter().copied(), universe),
+                    Ok(config.collapse(&dense))
+                );
+            }
+        }
+        let config = TbfConfig::default();
+        assert_eq!(config.collapse_sparse([], 0), Ok(Vec::new()));
+        assert!(config.collapse_sparse([0], 0).is_err());
+        assert!(config.collapse_sparse([5, 5], 64).is_err());
+        assert!(config.collapse_sparse([9, 2], 64).is_err());
+        assert!(config.collapse_sparse([64], 64).is_err());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_fixed_width_values_collapse_like_their_bytes() {
+        let config = TbfConfig::new(0.1).with_bit_order(BitOrder::LsbFirst);
+        for value in [0u64, 1, 0xDEAD_BEEF_0000_FFFF, u64::MAX] {
+            let digest = config.collapse_u64(value);
+            assert_eq!(
+                digest.to_be_bytes()[..],
+                config.collapse(&value.to_be_bytes())[..]
+            );
+        }
+        let value = 0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210u128;
+        assert_eq!(
+            config.collapse_u128(value).to_be_bytes()[..],
+            config.collapse(&value.to_be_bytes())[..]
+        );
+        assert_eq!(
+            config.collapse_fixed(-2i16).to_be_bytes()[..],
+            config.collapse(&(-2i16).to_be_bytes())[..]
+        );
+        let hash = [0x5Au8; 32];
+        assert_eq!(config.collapse_fixed(hash)[..], config.collapse(&hash)[..]);
+        assert_eq!(config.collapse_fixed([0u8; 0]), []);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_scratch_reuses_its_buffer() {
+        let config = TbfConfig::new(0.1).with_transform(FinalTransform::Hash);
+        let mut scratch = CollapseScratch::new();
+        assert_eq!(scratch.collapse(&[], &config), []);
+        let long = [0x3Cu8; 100];
+        assert_eq!(scratch.collapse(&long, &config), config.collapse(&long));
+        let buffer = scratch.out.as_ptr();
+        for len in [1, 2, 16, 99, 100] {
+            let input = &long[..len];
+            assert_eq!(scratch.collapse(input, &config), config.collapse(input));
+        }
+        assert_eq!((scratch.out.as_ptr(), scratch.capacity()), (buffer, 100));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_short_inputs_are_refused() {
+        let config = TbfConfig::default();
+        for len in [0, 1, 4, 15] {
+            assert_eq!(
+                config.try_collapse(&vec![0xA5; len]),
+                Err(Error::InputTooShort {
+                    minimum_bits: 128,
+                    found_bits: 8 * len
+                })
+            );
+        }
+        for len in [16, 17, 100] {
+            let input = vec![0xA5; len];
+            assert_eq!(config.try_collapse(&input), Ok(config.collapse(&input)));
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! Matching codes read by people and OCR.
+//!
+//! Serial numbers, vouchers and licence plates are read off labels, and
+//! the reads confuse characters that look alike: `0` and `O`, `1`, `I` and
+//! `l`, `5` and `S`. Bit tolerance on the ASCII bytes does not follow
+//! those confusions: `0` and `O` differ in three bits, `0` and `1` in one.
+//! [`Confusions`] groups the characters a reader mixes up and
+//! [`encode`](Confusions::encode)s every character as a 32-bit word of its
+//! group, followed by a byte telling the members of the group apart, so:
+//!
+//! - characters of one group differ in at most two bits, only in the
+//!   member byte;
+//! - characters of different groups differ in at least 16 bits: the group
+//!   words are distinct non-constant words of the first-order Reed–Muller
+//!   code of length 32, which differ in 16 or 32 bits and all hold 16 set
+//!   bits.
+//!
+//! A [`CodeMatcher`] stores encoded codes in a [`BkTree`] and finds those
+//! a read differs from by at most a few confusions, but by no other
+//! character. [`Confusions::fingerprint`] collapses the encoding instead;
+//! as every group word has the same weight, a confusion moves a chunk by
+//! at most two bits, but different codes often collapse alike, so compare
+//! fingerprints only to narrow down candidates.
+//!
+//! Codes are read case-insensitively as ASCII letters and digits; spaces,
+//! dashes and everything else are skipped. Dropped and extra characters are
+//! not tolerated: codes of different lengths never match.
+
+use crate::index::BkTree;
+use crate::{Error, TbfConfig};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Bytes of the encoding of one character.
+pub const CHAR_BYTES: usize = 5;
+
+/// Most members of one confusion group.
+const MAX_MEMBERS: usize = 9;
+
+/// Most confusions a [`CodeMatcher`] tolerates: two bits each stay below
+/// the 16 bits of any other character.
+const MAX_CONFUSIONS: u32 = 7;
+
+/// Groups of characters a reader confuses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Confusions {
+    table: [(u8, u8); 36], // Per digit, then letter: (group, member).
+}
+
+/// Index of an ASCII letter or digit in a table, case-insensitively.
+fn slot(c: u8) -> Option<usize> {
+    match c.to_ascii_uppercase() {
+        c @ b'0'..=b'9' => Some(usize::from(c - b'0')),
+        c @ b'A'..=b'Z' => Some(usize::from(c - b'A') + 10),
+        _ => None,
+    }
+}
+
+/// The 32-bit word of `group`: the affine function `a0 ^ (lin · x)` over
+/// the 5-bit positions `x`, with `lin` non-zero.
+fn group_word(group: u8) -> u32 {
+    let lin = u32::from(group % 31) + 1;
+    let a0 = u32::from(group / 31);
+    (0..32).fold(0, |word, x| word << 1 | (a0 ^ (lin & x).count_ones() & 1))
+}
+
+impl Confusions {
+    /// Groups the characters of every string in `groups`; characters in no
+    /// group are confused with none.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if a group holds anything but
+    /// ASCII letters and digits, is empty or holds more than nine
+    /// characters, or if a character, in either case, is in two groups or
+    /// twice in one.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::confusable::Confusions;
+    ///
+    /// // Handwritten digits.
+    /// let digits = Confusions::new(&["17", "49", "38", "06"]).unwrap();
+    /// assert_eq!(digits.canonical("7 39-60"), "13400");
+    /// ```
+    pub fn new(groups: &[&str]) -> Result<Self, Error> {
+        const UNASSIGNED: (u8, u8) = (u8::MAX, 0);
+        let mut table = [UNASSIGNED; 36];
+        for (group, members) in groups.iter().enumerate() {
+            if members.is_empty() || members.len() > MAX_MEMBERS {
+                return Err(Error::InvalidParameter(
+                    "confusion groups hold one to nine characters",
+                ));
+            }
+            for (member, c) in members.bytes().enumerate() {
+                let slot = slot(c).ok_or(Error::InvalidParameter(
+                    "confusion groups hold ASCII letters and digits",
+                ))?;
+                if table[slot] != UNASSIGNED {
+                    return Err(Error::InvalidParameter(
+                        "a character is in more than one confusion group",
+                    ));
+                }
+                table[slot] = (group as u8, member as u8);
+            }
+        }
+        // At most 36 groups, well within the 62 group words.
+        let unassigned = table.iter_mut().filter(|entry| **entry == UNASSIGNED);
+        for (group, entry) in (groups.len() as u8..).zip(unassigned) {
+            *entry = (group, 0);
+        }
+        Ok(Self { table })
+    }
+
+    /// The confusions of OCR on printed codes: `0 O D Q`, `1 I L`, `2 Z`,
+    /// `5 S`, `6 G`, `8 B` and `U V`.
+    pub fn ocr() -> Self {
+        Self::new(&["0ODQ", "1IL", "2Z", "5S", "6G", "8B", "UV"]).expect("valid groups")
+    }
+
+    /// The letters and digits of `code` in upper case, each replaced by the
+    /// first character of its group.
+    pub fn canonical(&self, code: &str) -> String {
+        self.read(code)
+            .map(|(group, _)| {
+                let slot = self
+                    .table
+                    .iter()
+                    .position(|&entry| entry == (group, 0))
+                    .expect("every group has a first member");
+                char::from(b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ"[slot])
+            })
+            .collect()
+    }
+
+    /// The [`CHAR_BYTES`]-byte encodings of the letters and digits of
+    /// `code`: the group word, big-endian, then the member byte, zero for
+    /// the first member of a group and a single set bit for the others.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::confusable::Confusions;
+    /// use pensieve::distance::hamming;
+    ///
+    /// let ocr = Confusions::ocr();
+    /// let printed = ocr.encode("SN-8O41-Z");
+    /// assert_eq!(printed.len(), 7 * 5);
+    /// // Four confusions...
+    /// assert_eq!(hamming(&printed, &ocr.encode("5N-B041-2")), 4);
+    /// // ...are closer than one misread character.
+    /// assert_eq!(hamming(&printed, &ocr.encode("SN-8O47-Z")), 16);
+    /// ```
+    pub fn encode(&self, code: &str) -> Vec<u8> {
+        let mut out = Vec::with_capacity(code.len() * CHAR_BYTES);
+        for (group, member) in self.read(code) {
+            out.extend_from_slice(&group_word(group).to_be_bytes());
+            out.push(if member == 0 { 0 } else { 1 << (member - 1) });
+        }
+        out
+    }
+
+    /// The collapse of the [`encode`](Self::encode)d `code` under `config`.
+    pub fn fingerprint(&self, code: &str, config: &TbfConfig) -> Vec<u8> {
+        config.collapse(&self.encode(code))
+    }
+
+    /// The number of characters in which `a` and `b` are confused, or
+    /// `None` if they differ in length or in a character of another group.
+    pub fn confusions(&self, a: &str, b: &str) -> Option<u32> {
+        let (a, b) = (self.encode(a), self.encode(b));
+        if a.len() != b.len() {
+            return None;
+        }
+        confusions(&a, &b)
+    }
+
+    /// The (group, member) of every letter and digit of `code`.
+    fn read<'a>(&'a self, code: &'a str) -> impl Iterator<Item = (u8, u8)> + 'a {
+        code.bytes()
+            .filter_map(|c| slot(c).map(|slot| self.table[slot]))
+    }
+}
+
+impl Default for Confusions {
+    /// [`Confusions::ocr`].
+    fn default() -> Self {
+        Self::ocr()
+    }
+}
+
+/// Confused characters between two encodings of one length, or `None` if a
+/// character's group differs.
+fn confusions(a: &[u8], b: &[u8]) -> Option<u32> {
+    let mut count = 0;
+    for (a, b) in a.chunks(CHAR_BYTES).zip(b.chunks(CHAR_BYTES)) {
+        if a[..4] != b[..4] {
+            return None;
+        }
+        count += u32::from(a[4] != b[4]);
+    }
+    Some(count)
+}
+
+/// A stored code matching a read, found by [`CodeMatcher::query`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeMatch<'a, V> {
+    /// The value the code was inserted with.
+    pub value: &'a V,
+    /// Characters of the read confused with those of the code.
+    pub confusions: u32,
+}
+
+/// An index of codes, for looking up reads with a few confused characters.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::confusable::{CodeMatcher, Confusions};
+///
+/// let mut serials = CodeMatcher::new(Confusions::ocr(), 2).unwrap();
+/// serials.insert("QX-1050-8", "pallet 12");
+/// serials.insert("QX-1080-8", "pallet 13");
+/// let matches = serials.query("QX-IO50-8");
+/// assert_eq!(matches.len(), 1);
+/// assert_eq!((*matches[0].value, matches[0].confusions), ("pallet 12", 2));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CodeMatcher<V> {
+    confusions: Confusions,
+    max_confusions: u32,
+    by_len: BTreeMap<usize, BkTree<usize>>, // Per code length: encoding -> index into values.
+    values: Vec<V>,
+}
+
+impl<V> CodeMatcher<V> {
+    /// An empty index matching reads with at most `max_confusions` confused
+    /// characters, counted by code.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `max_confusions` exceeds
+    /// seven, beyond which a misread character could pass for confusions.
+    pub fn new(confusions: Confusions, max_confusions: u32) -> Result<Self, Error> {
+        if max_confusions > MAX_CONFUSIONS {
+            return Err(Error::InvalidParameter(
+                "at most seven confusions are tolerated",
+            ));
+        }
+        Ok(Self {
+            confusions,
+            max_confusions,
+            by_len: BTreeMap::new(),
+            values: Vec::new(),
+        })
+    }
+
+    /// The confusion groups codes are encoded with.
+    pub fn confusions(&self) -> &Confusions {
+        &self.confusions
+    }
+
+    /// Most confusions a match may have.
+    pub fn max_confusions(&self) -> u32 {
+        self.max_confusions
+    }
+
+    /// Number of stored codes.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether no code is stored.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Stores `code` with `value`.
+    pub fn insert(&mut self, code: &str, value: V) {
+        let encoded = self.confusions.encode(code);
+        self.by_len
+            .entry(encoded.len())
+            .or_default()
+            .insert(&encoded, self.values.len())
+            .expect("keys of one code length have one length");
+        self.values.push(value);
+    }
+
+    /// Every stored code that `read` matches with at most
+    /// [`max_confusions`](Self::max_confusions) confused characters and no
+    /// other difference: fewest confusions first, then in order of
+    /// insertion.
+    pub fn query(&self, read: &str) -> Vec<CodeMatch<'_, V>> {
+        let encoded = self.confusions.encode(read);
+        let Some(tree) = self.by_len.get(&encoded.len()) else {
+            return Vec::new();
+        };
+        // Every confusion flips at most two bits, any other character 16.
+        let mut matches: Vec<(u32, usize)> = tree
+            .query_within(&encoded, 2 * self.max_confusions)
+            .expect("keys of one code length have one length")
+            .into_iter()
+            .filter_map(|found| {
+                let count = confusions(found.key, &encoded)?;
+                (count <= self.max_confusions).then_some((count, *found.value))
+            })
+            .collect();
+        matches.sort_unstable();
+        matches
+            .into_iter()
+            .map(|(confusions, index)| CodeMatch {
+                value: &self.values[index],
+                confusions,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::hamming;
+
+    #[test]
+    fn test_group_words_are_far_apart_and_balanced() {
+        let words: Vec<u32> = (0..62).map(group_word).collect();
+        for (i, a) in words.iter().enumerate() {
+            assert_eq!(a.count_ones(), 16, "{i}");
+            for b in &words[..i] {
+                assert!((a ^ b).count_ones() >= 16, "{i}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_confusions_cost_at_most_two_bits() {
+        let ocr = Confusions::ocr();
+        let chars = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        for a in chars.chars() {
+            for b in chars.chars() {
+                let (a, b) = (a.to_string(), b.to_string());
+                let distance = hamming(&ocr.encode(&a), &ocr.encode(&b));
+                match ocr.confusions(&a, &b) {
+                    Some(0) => assert_eq!(a, b),
+                    Some(_) => assert!((1..=2).contains(&distance), "{a} {b}"),
+                    None => assert!(distance >= 16, "{a} {b}"),
+                }
+            }
+        }
+        assert_eq!(ocr.confusions("l0l", "1O1"), Some(3));
+        assert_eq!(ocr.confusions("ab-c", "ABC"), Some(0));
+        assert_eq!(ocr.confusions("ABC", "ABCD"), None);
+        assert_eq!(ocr.canonical("sn-b0lo"), "5N8010");
+    }
+
+    #[test]
+    fn test_invalid_groups_are_rejected() {
+        assert!(Confusions::new(&["0O", "o1"]).is_err());
+        assert!(Confusions::new(&["00"]).is_err());
+        assert!(Confusions::new(&["0-O"]).is_err());
+        assert!(Confusions::new(&["0123456789"]).is_err());
+        assert!(Confusions::new(&["012345678"]).is_ok());
+        assert!(Confusions::new(&["0O", ""]).is_err());
+        assert!(CodeMatcher::<()>::new(Confusions::ocr(), 8).is_err());
+    }
+
+    #[test]
+    fn test_matcher_tolerates_confusions_only() {
+        let mut matcher = CodeMatcher::new(Confusions::default(), 3).unwrap();
+        let codes = ["A1B2C3D4", "A1B2C3D5", "ZZ00", "HELLO-7", "HELL0-7"];
+        for (i, code) in codes.iter().enumerate() {
+            matcher.insert(code, i);
+        }
+        assert_eq!(matcher.len(), 5);
+        let found = |read: &str| -> Vec<(usize, u32)> {
+            matcher
+                .query(read)
+                .iter()
+                .map(|m| (*m.value, m.confusions))
+                .collect()
+        };
+        assert_eq!(found("AIB2C3D4"), [(0, 1)]);
+        assert_eq!(found("a1 b2 c3 d4"), [(0, 0)]);
+        assert_eq!(found("2Z0O"), [(2, 2)]);
+        assert_eq!(found("HELLO7"), [(3, 0), (4, 1)]);
+        assert_eq!(found("A1B2C3E4"), []);
+        assert_eq!(found("A1B2C3D"), []);
+        // A fourth confusion is one too many.
+        assert_eq!(found("22OD"), []);
+        assert_eq!(found("AI8ZC3D4"), [(0, 3)]);
+    }
+}
@@ -0,0 +1,44 @@
+//! Minimal, dependency-free cryptographic building blocks used by the
+//! protocol modules (`fuzzy_extractor`, `pake`, `mnemonic`, `attestation`,
+//! `oprf`). These are internal and deliberately not part of the public API.
+
+#[cfg(feature = "argon2")]
+pub(crate) mod argon2;
+#[cfg(feature = "argon2")]
+pub(crate) mod blake2b;
+#[cfg(feature = "alloc")]
+pub(crate) mod curve25519;
+#[cfg(feature = "alloc")]
+pub(crate) mod ed25519;
+pub(crate) mod hmac;
+pub(crate) mod sha256;
+#[cfg(feature = "alloc")]
+pub(crate) mod sha512;
+
+/// Constant-time equality of two byte slices (length is treated as public).
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    // Accumulate all differences so the running time doesn't depend on where they are.
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    diff == 0
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    /// Decodes a hex string in test vectors.
+    pub(crate) fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(super::ct_eq(b"abc", b"abc"));
+        assert!(!super::ct_eq(b"abc", b"abd"));
+        assert!(!super::ct_eq(b"abc", b"ab"));
+    }
+}
@@ -0,0 +1,380 @@
+//! Argon2id (RFC 9106), version 0x13.
+
+use super::blake2b::Blake2b;
+use alloc::vec;
+
+/// 64-bit words per 1 KiB memory block.
+const BLOCK_WORDS: usize = 128;
+/// Segments per lane pass (synchronization points).
+const SYNC_POINTS: u32 = 4;
+/// Algorithm version number.
+const VERSION: u32 = 0x13;
+/// Primitive type identifier for Argon2id.
+const TYPE_ID: u32 = 2;
+
+type Block = [u64; BLOCK_WORDS];
+
+/// Cost parameters in the units of RFC 9106.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Cost {
+    pub(crate) memory_kib: u32,  // m: memory size in KiB, at least 8 * lanes.
+    pub(crate) iterations: u32,  // t: number of passes, at least 1.
+    pub(crate) parallelism: u32, // p: number of lanes, 1..2^24.
+}
+
+/// Computes an Argon2id tag of `out.len()` bytes (at least 4).
+///
+/// Lanes are filled one after another rather than on separate threads; the
+/// result is identical, only slower for `parallelism > 1`.
+///
+/// # Panics
+/// Panics if `cost` violates the bounds documented on [`Cost`].
+pub(crate) fn argon2id(
+    password: &[u8],
+    salt: &[u8],
+    secret: &[u8],
+    associated: &[u8],
+    cost: Cost,
+    out: &mut [u8],
+) {
+    let lanes = cost.parallelism;
+    assert!((1..1 << 24).contains(&lanes), "invalid Argon2 parallelism");
+    assert!(cost.iterations >= 1, "Argon2 needs at least one pass");
+    assert!(
+        cost.memory_kib >= 8 * lanes,
+        "Argon2 memory below 8 KiB per lane"
+    );
+    assert!(out.len() >= 4, "Argon2 tags are at least 4 bytes");
+
+    let mut h0 = Blake2b::new(64);
+    for value in [
+        lanes,
+        out.len() as u32,
+        cost.memory_kib,
+        cost.iterations,
+        VERSION,
+        TYPE_ID,
+    ] {
+        h0.update(&value.to_le_bytes());
+    }
+    for field in [password, salt, secret, associated] {
+        h0.update(&(field.len() as u32).to_le_bytes());
+        h0.update(field);
+    }
+    let h0 = h0.finalize();
+
+    // Round memory down to a whole number of segments per lane.
+    let segment_length = cost.memory_kib / (SYNC_POINTS * lanes);
+    let lane_length = segment_length * SYNC_POINTS;
+    let block_count = lane_length * lanes;
+    let mut memory = vec![[0u64; BLOCK_WORDS]; block_count as usize];
+
+    for lane in 0..lanes {
+        for column in 0..2u32 {
+            let mut bytes = [0u8; 1024];
+            hash_long(
+                &[&h0, &column.to_le_bytes(), &lane.to_le_bytes()],
+                &mut bytes,
+            );
+            memory[(lane * lane_length + column) as usize] = block_from_bytes(&bytes);
+        }
+    }
+
+    let geometry = Geometry {
+        lanes,
+        lane_length,
+        segment_length,
+        block_count,
+        iterations: cost.iterations,
+    };
+    for pass in 0..cost.iterations {
+        for slice in 0..SYNC_POINTS {
+            for lane in 0..lanes {
+                fill_segment(&mut memory, &geometry, pass, slice, lane);
+            }
+        }
+    }
+
+    let mut last = memory[(lane_length - 1) as usize];
+    for lane in 1..lanes {
+        let block = &memory[(lane * lane_length + lane_length - 1) as usize];
+        for (word, other) in last.iter_mut().zip(block) {
+            *word ^= other;
+        }
+    }
+    let mut bytes = [0u8; 1024];
+    for (chunk, word) in bytes.chunks_exact_mut(8).zip(last) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    hash_long(&[&bytes], out);
+}
+
+/// Shape of the memory matrix.
+struct Geometry {
+    lanes: u32,
+    lane_length: u32,
+    segment_length: u32,
+    block_count: u32,
+    iterations: u32,
+}
+
+/// Fills one segment (a quarter of a lane) for the given pass and slice.
+fn fill_segment(memory: &mut [Block], geometry: &Geometry, pass: u32, slice: u32, lane: u32) {
+    // Argon2id: the first half of the first pass uses data-independent
+    // addressing (side-channel resistant), the rest data-dependent.
+    let data_independent = pass == 0 && slice < SYNC_POINTS / 2;
+
+    let zero = [0u64; BLOCK_WORDS];
+    let mut input = [0u64; BLOCK_WORDS];
+    let mut addresses = [0u64; BLOCK_WORDS];
+    if data_independent {
+        input[0] = pass as u64;
+        input[1] = lane as u64;
+        input[2] = slice as u64;
+        input[3] = geometry.block_count as u64;
+        input[4] = geometry.iterations as u64;
+        input[5] = TYPE_ID as u64;
+    }
+
+    // The first two blocks of every lane were seeded from H0.
+    let start = if pass == 0 && slice == 0 { 2 } else { 0 };
+    if data_independent && start != 0 {
+        next_addresses(&mut addresses, &mut input, &zero);
+    }
+
+    for index in start..geometry.segment_length {
+        let column = slice * geometry.segment_length + index;
+        let current = lane * geometry.lane_length + column;
+        let previous = if column == 0 {
+            current + geometry.lane_length - 1
+        } else {
+            current - 1
+        };
+
+        let pseudo_random = if data_independent {
+            if (index as usize).is_multiple_of(BLOCK_WORDS) {
+                next_addresses(&mut addresses, &mut input, &zero);
+            }
+            addresses[index as usize % BLOCK_WORDS]
+        } else {
+            memory[previous as usize][0]
+        };
+
+        let ref_lane = if pass == 0 && slice == 0 {
+            lane
+        } else {
+            ((pseudo_random >> 32) % geometry.lanes as u64) as u32
+        };
+        let ref_column = reference_column(
+            geometry,
+            pass,
+            slice,
+            index,
+            pseudo_random as u32,
+            ref_lane == lane,
+        );
+        let reference = ref_lane * geometry.lane_length + ref_column;
+
+        let prev_block = memory[previous as usize];
+        let ref_block = memory[reference as usize];
+        let target = &mut memory[current as usize];
+        // Version 0x13 XORs into the old block on later passes.
+        compress(&prev_block, &ref_block, target, pass > 0);
+    }
+}
+
+/// Maps `j1` into the permitted reference window (RFC 9106, section 3.4.1.2).
+fn reference_column(
+    geometry: &Geometry,
+    pass: u32,
+    slice: u32,
+    index: u32,
+    j1: u32,
+    same_lane: bool,
+) -> u32 {
+    let finished = if pass == 0 {
+        slice * geometry.segment_length
+    } else {
+        geometry.lane_length - geometry.segment_length
+    };
+    // Never reference the block being overwritten's predecessor twice, nor
+    // the block currently being computed in another lane.
+    let area: u32 = if same_lane {
+        finished + index - 1
+    } else if index == 0 {
+        finished - 1
+    } else {
+        finished
+    };
+
+    let x = (j1 as u64 * j1 as u64) >> 32;
+    let y = (area as u64 * x) >> 32;
+    let relative = area as u64 - 1 - y;
+
+    let start = if pass == 0 || slice == SYNC_POINTS - 1 {
+        0
+    } else {
+        (slice + 1) * geometry.segment_length
+    };
+    ((start as u64 + relative) % geometry.lane_length as u64) as u32
+}
+
+/// Advances the counter and regenerates the address block as G(0, G(0, input)).
+fn next_addresses(addresses: &mut Block, input: &mut Block, zero: &Block) {
+    input[6] += 1;
+    compress(zero, input, addresses, false);
+    let once = *addresses;
+    compress(zero, &once, addresses, false);
+}
+
+/// The compression function G, writing `P(x ^ y) ^ x ^ y` (XORed into the
+/// existing contents of `out` when `accumulate` is set).
+fn compress(x: &Block, y: &Block, out: &mut Block, accumulate: bool) {
+    let mut r = [0u64; BLOCK_WORDS];
+    for i in 0..BLOCK_WORDS {
+        r[i] = x[i] ^ y[i];
+    }
+    let mut z = r;
+
+    for row in 0..8 {
+        let mut v = [0usize; 16];
+        for (k, slot) in v.iter_mut().enumerate() {
+            *slot = 16 * row + k;
+        }
+        permute(&mut z, &v);
+    }
+    for column in 0..8 {
+        let mut v = [0usize; 16];
+        for (k, slot) in v.iter_mut().enumerate() {
+            *slot = 2 * column + 16 * (k / 2) + k % 2;
+        }
+        permute(&mut z, &v);
+    }
+
+    for i in 0..BLOCK_WORDS {
+        let value = z[i] ^ r[i];
+        out[i] = if accumulate { out[i] ^ value } else { value };
+    }
+}
+
+/// The permutation P (a BLAKE2b round with multiplication-hardened mixing)
+/// over the sixteen words of `block` selected by `v`.
+fn permute(block: &mut Block, v: &[usize; 16]) {
+    let mut s = [0u64; 16];
+    for (word, &i) in s.iter_mut().zip(v) {
+        *word = block[i];
+    }
+    mix(&mut s, 0, 4, 8, 12);
+    mix(&mut s, 1, 5, 9, 13);
+    mix(&mut s, 2, 6, 10, 14);
+    mix(&mut s, 3, 7, 11, 15);
+    mix(&mut s, 0, 5, 10, 15);
+    mix(&mut s, 1, 6, 11, 12);
+    mix(&mut s, 2, 7, 8, 13);
+    mix(&mut s, 3, 4, 9, 14);
+    for (word, &i) in s.iter().zip(v) {
+        block[i] = *word;
+    }
+}
+
+/// GB: BLAKE2b's G with each addition `a + b` replaced by `a + b + 2 * lo(a) * lo(b)`.
+fn mix(s: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize) {
+    fn fma(x: u64, y: u64) -> u64 {
+        let product = (x & 0xffff_ffff).wrapping_mul(y & 0xffff_ffff);
+        x.wrapping_add(y).wrapping_add(product.wrapping_mul(2))
+    }
+    s[a] = fma(s[a], s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_right(32);
+    s[c] = fma(s[c], s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_right(24);
+    s[a] = fma(s[a], s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_right(16);
+    s[c] = fma(s[c], s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_right(63);
+}
+
+/// The variable-length hash H' (RFC 9106, section 3.3).
+fn hash_long(parts: &[&[u8]], out: &mut [u8]) {
+    let len = (out.len() as u32).to_le_bytes();
+    if out.len() <= 64 {
+        let mut hasher = Blake2b::new(out.len());
+        hasher.update(&len);
+        for part in parts {
+            hasher.update(part);
+        }
+        out.copy_from_slice(&hasher.finalize());
+        return;
+    }
+
+    let mut hasher = Blake2b::new(64);
+    hasher.update(&len);
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut v = hasher.finalize();
+    // Emit the first half of each intermediate digest, then one final digest in full.
+    let mut written = 0;
+    loop {
+        out[written..written + 32].copy_from_slice(&v[..32]);
+        written += 32;
+        let remaining = out.len() - written;
+        if remaining <= 64 {
+            out[written..].copy_from_slice(&Blake2b::digest(remaining, &[&v]));
+            return;
+        }
+        v = Blake2b::digest(64, &[&v]);
+    }
+}
+
+fn block_from_bytes(bytes: &[u8; 1024]) -> Block {
+    let mut block = [0u64; BLOCK_WORDS];
+    for (word, chunk) in block.iter_mut().zip(bytes.chunks_exact(8)) {
+        *word = u64::from_le_bytes(chunk.try_into().expect("8 bytes"));
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::tests::hex;
+
+    #[test]
+    fn test_argon2id_rfc9106_vector() {
+        let cost = Cost {
+            memory_kib: 32,
+            iterations: 3,
+            parallelism: 4,
+        };
+        let mut tag = [0u8; 32];
+        argon2id(&[1; 32], &[2; 16], &[3; 8], &[4; 12], cost, &mut tag);
+        assert_eq!(
+            tag.to_vec(),
+            hex("0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659")
+        );
+    }
+
+    #[test]
+    fn test_argon2id_depends_on_cost() {
+        let base = Cost {
+            memory_kib: 16,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let mut a = [0u8; 32];
+        let mut b = [0u8; 32];
+        argon2id(b"pw", b"saltsalt", &[], &[], base, &mut a);
+        argon2id(
+            b"pw",
+            b"saltsalt",
+            &[],
+            &[],
+            Cost {
+                iterations: 2,
+                ..base
+            },
+            &mut b,
+        );
+        assert_ne!(a, b);
+    }
+}
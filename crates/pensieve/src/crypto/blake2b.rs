@@ -0,0 +1,187 @@
+//! BLAKE2b (RFC 7693), the hash underlying Argon2.
+
+use alloc::vec::Vec;
+
+/// Initialization vector (same as SHA-512's).
+const IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+/// Message word permutation for each of the 12 rounds.
+const SIGMA: [[usize; 16]; 12] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+];
+
+/// Incremental, unkeyed BLAKE2b with a variable output length (1..=64 bytes).
+#[derive(Clone)]
+pub(crate) struct Blake2b {
+    h: [u64; 8],       // Chaining value.
+    buffer: [u8; 128], // Pending input; never compressed until more data arrives.
+    buffer_len: usize, // Valid bytes in `buffer`.
+    counter: u128,     // Bytes compressed so far.
+    output_len: usize, // Digest length in bytes.
+}
+
+impl Blake2b {
+    /// Creates a hasher producing `output_len` bytes.
+    ///
+    /// # Panics
+    /// Panics unless `1 <= output_len <= 64`.
+    pub(crate) fn new(output_len: usize) -> Self {
+        assert!(
+            (1..=64).contains(&output_len),
+            "invalid BLAKE2b output length"
+        );
+        let mut h = IV;
+        // Parameter block: digest length, no key, fanout 1, depth 1.
+        h[0] ^= 0x0101_0000 ^ output_len as u64;
+        Self {
+            h,
+            buffer: [0; 128],
+            buffer_len: 0,
+            counter: 0,
+            output_len,
+        }
+    }
+
+    /// Absorbs `data`.
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // The final block must be compressed with the last-block flag, so a
+            // full buffer is only flushed once we know more input follows.
+            if self.buffer_len == 128 {
+                self.counter += 128;
+                let block = self.buffer;
+                self.compress(&block, false);
+                self.buffer_len = 0;
+            }
+            let take = (128 - self.buffer_len).min(data.len());
+            self.buffer[self.buffer_len..self.buffer_len + take].copy_from_slice(&data[..take]);
+            self.buffer_len += take;
+            data = &data[take..];
+        }
+    }
+
+    /// Finishes the hash, writing `output_len` bytes.
+    pub(crate) fn finalize(mut self) -> Vec<u8> {
+        self.counter += self.buffer_len as u128;
+        let mut block = [0u8; 128];
+        block[..self.buffer_len].copy_from_slice(&self.buffer[..self.buffer_len]);
+        self.compress(&block, true);
+
+        let mut out = Vec::with_capacity(64);
+        for word in self.h {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out.truncate(self.output_len);
+        out
+    }
+
+    /// One-shot convenience over the concatenation of `parts`.
+    pub(crate) fn digest(output_len: usize, parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = Self::new(output_len);
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize()
+    }
+
+    /// The compression function F.
+    fn compress(&mut self, block: &[u8; 128], last: bool) {
+        let mut m = [0u64; 16];
+        for (i, word) in block.chunks_exact(8).enumerate() {
+            m[i] = u64::from_le_bytes(word.try_into().expect("8 bytes"));
+        }
+
+        let mut v = [0u64; 16];
+        v[..8].copy_from_slice(&self.h);
+        v[8..].copy_from_slice(&IV);
+        v[12] ^= self.counter as u64;
+        v[13] ^= (self.counter >> 64) as u64;
+        if last {
+            v[14] = !v[14];
+        }
+
+        for s in &SIGMA {
+            mix(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+            mix(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+            mix(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+            mix(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+            mix(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+            mix(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+            mix(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+            mix(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+
+        for i in 0..8 {
+            self.h[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+}
+
+/// The mixing function G.
+fn mix(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::tests::hex;
+
+    #[test]
+    fn test_blake2b_512_abc() {
+        assert_eq!(
+            Blake2b::digest(64, &[b"abc"]),
+            hex(concat!(
+                "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1",
+                "7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_blake2b_512_empty() {
+        assert_eq!(
+            Blake2b::digest(64, &[]),
+            hex(concat!(
+                "786a02f742015903c6c6fd852552d272912f4740e15847618a86e217f71f5419",
+                "d25e1031afee585313896444934eb04b903a685b1448b755d56f701afe9be2ce"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_blake2b_block_boundary_incremental() {
+        let data = [0x61u8; 256];
+        let mut hasher = Blake2b::new(32);
+        hasher.update(&data[..128]);
+        hasher.update(&data[128..]);
+        assert_eq!(hasher.finalize(), Blake2b::digest(32, &[&data]));
+    }
+}
@@ -0,0 +1,345 @@
+//! Arithmetic over GF(2^255 - 19) and the X25519 function (RFC 7748).
+//!
+//! Field elements use five 51-bit limbs with `u128` products. All operations
+//! on secret values run in constant time; exponentiation is only ever used
+//! with public exponents.
+
+/// Mask selecting the low 51 bits of a limb.
+const MASK51: u64 = (1 << 51) - 1;
+
+/// An element of GF(2^255 - 19), loosely reduced (limbs may exceed 51 bits slightly).
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Fe([u64; 5]);
+
+impl Fe {
+    pub(crate) const ZERO: Fe = Fe([0, 0, 0, 0, 0]);
+    pub(crate) const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    /// Creates an element from a small integer.
+    pub(crate) const fn from_u64(v: u64) -> Fe {
+        Fe([v & MASK51, v >> 51, 0, 0, 0])
+    }
+
+    /// Decodes 32 little-endian bytes, ignoring the most significant bit.
+    pub(crate) fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let load = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().expect("8 bytes"));
+        Fe([
+            load(0) & MASK51,
+            (load(6) >> 3) & MASK51,
+            (load(12) >> 6) & MASK51,
+            (load(19) >> 1) & MASK51,
+            (load(24) >> 12) & MASK51,
+        ])
+    }
+
+    /// Encodes the canonical (fully reduced) little-endian representation.
+    pub(crate) fn to_bytes(self) -> [u8; 32] {
+        let mut h = self.carry().carry().0;
+
+        // Determine whether h >= p by checking if h + 19 overflows 2^255.
+        let mut q = (h[0] + 19) >> 51;
+        q = (h[1] + q) >> 51;
+        q = (h[2] + q) >> 51;
+        q = (h[3] + q) >> 51;
+        q = (h[4] + q) >> 51;
+
+        // Subtract q * p, i.e. add 19q and drop bit 255.
+        h[0] += 19 * q;
+        h[1] += h[0] >> 51;
+        h[0] &= MASK51;
+        h[2] += h[1] >> 51;
+        h[1] &= MASK51;
+        h[3] += h[2] >> 51;
+        h[2] &= MASK51;
+        h[4] += h[3] >> 51;
+        h[3] &= MASK51;
+        h[4] &= MASK51;
+
+        let words = [
+            h[0] | (h[1] << 51),
+            (h[1] >> 13) | (h[2] << 38),
+            (h[2] >> 26) | (h[3] << 25),
+            (h[3] >> 39) | (h[4] << 12),
+        ];
+        let mut out = [0u8; 32];
+        for (chunk, word) in out.chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    /// Propagates carries so every limb fits in 51 bits (plus a tiny excess in limb 0).
+    fn carry(&self) -> Fe {
+        let mut h = self.0;
+        let c = h[0] >> 51;
+        h[0] &= MASK51;
+        h[1] += c;
+        let c = h[1] >> 51;
+        h[1] &= MASK51;
+        h[2] += c;
+        let c = h[2] >> 51;
+        h[2] &= MASK51;
+        h[3] += c;
+        let c = h[3] >> 51;
+        h[3] &= MASK51;
+        h[4] += c;
+        let c = h[4] >> 51;
+        h[4] &= MASK51;
+        h[0] += c * 19;
+        Fe(h)
+    }
+
+    pub(crate) fn add(&self, rhs: &Fe) -> Fe {
+        let mut h = [0u64; 5];
+        for (i, limb) in h.iter_mut().enumerate() {
+            *limb = self.0[i] + rhs.0[i];
+        }
+        Fe(h).carry()
+    }
+
+    pub(crate) fn sub(&self, rhs: &Fe) -> Fe {
+        // Add 2p before subtracting so no limb underflows.
+        const TWO_P: [u64; 5] = [
+            0xFFFFFFFFFFFDA,
+            0xFFFFFFFFFFFFE,
+            0xFFFFFFFFFFFFE,
+            0xFFFFFFFFFFFFE,
+            0xFFFFFFFFFFFFE,
+        ];
+        let rhs = rhs.carry();
+        let mut h = [0u64; 5];
+        for (i, limb) in h.iter_mut().enumerate() {
+            *limb = self.0[i] + TWO_P[i] - rhs.0[i];
+        }
+        Fe(h).carry()
+    }
+
+    pub(crate) fn neg(&self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+
+    pub(crate) fn mul(&self, rhs: &Fe) -> Fe {
+        let a = self.0.map(u128::from);
+        let b = rhs.0.map(u128::from);
+        // Reduction uses 2^255 = 19 (mod p), folding the high limbs back in.
+        let b19 = [b[0], b[1] * 19, b[2] * 19, b[3] * 19, b[4] * 19];
+
+        let r0 = a[0] * b[0] + a[1] * b19[4] + a[2] * b19[3] + a[3] * b19[2] + a[4] * b19[1];
+        let r1 = a[0] * b[1] + a[1] * b[0] + a[2] * b19[4] + a[3] * b19[3] + a[4] * b19[2];
+        let r2 = a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * b19[4] + a[4] * b19[3];
+        let r3 = a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * b19[4];
+        let r4 = a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0];
+
+        let mask = MASK51 as u128;
+        let r1 = r1 + (r0 >> 51);
+        let r2 = r2 + (r1 >> 51);
+        let r3 = r3 + (r2 >> 51);
+        let r4 = r4 + (r3 >> 51);
+        let carry = (r4 >> 51) as u64;
+
+        Fe([
+            (r0 & mask) as u64 + carry * 19,
+            (r1 & mask) as u64,
+            (r2 & mask) as u64,
+            (r3 & mask) as u64,
+            (r4 & mask) as u64,
+        ])
+        .carry()
+    }
+
+    pub(crate) fn square(&self) -> Fe {
+        self.mul(self)
+    }
+
+    /// Raises to a public exponent given as 32 little-endian bytes.
+    pub(crate) fn pow(&self, exponent: &[u8; 32]) -> Fe {
+        let mut result = Fe::ONE;
+        for byte in exponent.iter().rev() {
+            for bit in (0..8).rev() {
+                result = result.square();
+                if (byte >> bit) & 1 == 1 {
+                    result = result.mul(self);
+                }
+            }
+        }
+        result
+    }
+
+    /// Multiplicative inverse via Fermat's little theorem (maps zero to zero).
+    pub(crate) fn invert(&self) -> Fe {
+        // p - 2 = 2^255 - 21
+        let mut exponent = [0xff; 32];
+        exponent[0] = 0xeb;
+        exponent[31] = 0x7f;
+        self.pow(&exponent)
+    }
+
+    /// Returns true if the element is zero or a quadratic residue (Euler's criterion).
+    pub(crate) fn is_square(&self) -> bool {
+        // (p - 1) / 2 = 2^254 - 10
+        let mut exponent = [0xff; 32];
+        exponent[0] = 0xf6;
+        exponent[31] = 0x3f;
+        let legendre = self.pow(&exponent).to_bytes();
+        legendre == Fe::ONE.to_bytes() || legendre == [0; 32]
+    }
+
+    /// Constant-time equality on the canonical encodings.
+    pub(crate) fn ct_eq(&self, rhs: &Fe) -> bool {
+        super::ct_eq(&self.to_bytes(), &rhs.to_bytes())
+    }
+
+    /// Swaps `a` and `b` iff `swap == 1`, without branching on `swap`.
+    pub(crate) fn cswap(a: &mut Fe, b: &mut Fe, swap: u64) {
+        let mask = 0u64.wrapping_sub(swap);
+        for i in 0..5 {
+            let t = mask & (a.0[i] ^ b.0[i]);
+            a.0[i] ^= t;
+            b.0[i] ^= t;
+        }
+    }
+
+    /// Returns `b` if `choice == 1`, otherwise `a`, without branching on `choice`.
+    pub(crate) fn select(a: &Fe, b: &Fe, choice: u64) -> Fe {
+        let mask = 0u64.wrapping_sub(choice);
+        let mut out = [0u64; 5];
+        for (i, limb) in out.iter_mut().enumerate() {
+            *limb = a.0[i] ^ (mask & (a.0[i] ^ b.0[i]));
+        }
+        Fe(out)
+    }
+}
+
+/// The Montgomery curve coefficient A of Curve25519.
+const CURVE_A: u64 = 486662;
+
+/// The X25519 function: scalar multiplication of a u-coordinate (RFC 7748, section 5).
+pub(crate) fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    // Clamp the scalar so it is a multiple of the cofactor with a fixed top bit.
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let x1 = Fe::from_bytes(u);
+    let mut x2 = Fe::ONE;
+    let mut z2 = Fe::ZERO;
+    let mut x3 = x1;
+    let mut z3 = Fe::ONE;
+    let mut swap = 0u64;
+    let a24 = Fe::from_u64((CURVE_A - 2) / 4);
+
+    // Montgomery ladder, one conditional swap per scalar bit.
+    for t in (0..255).rev() {
+        let k_t = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= k_t;
+        Fe::cswap(&mut x2, &mut x3, swap);
+        Fe::cswap(&mut z2, &mut z3, swap);
+        swap = k_t;
+
+        let a = x2.add(&z2);
+        let aa = a.square();
+        let b = x2.sub(&z2);
+        let bb = b.square();
+        let e = aa.sub(&bb);
+        let c = x3.add(&z3);
+        let d = x3.sub(&z3);
+        let da = d.mul(&a);
+        let cb = c.mul(&b);
+        x3 = da.add(&cb).square();
+        z3 = x1.mul(&da.sub(&cb).square());
+        x2 = aa.mul(&bb);
+        z2 = e.mul(&aa.add(&a24.mul(&e)));
+    }
+    Fe::cswap(&mut x2, &mut x3, swap);
+    Fe::cswap(&mut z2, &mut z3, swap);
+
+    x2.mul(&z2.invert()).to_bytes()
+}
+
+/// Maps 32 uniformly random bytes to the u-coordinate of a curve point using
+/// the Elligator 2 map (RFC 9380, section 6.7.1, with Z = 2).
+///
+/// The input's top bit is discarded, so the field element is uniform up to a
+/// statistically negligible bias of 19 / 2^255.
+pub(crate) fn elligator2(bytes: &[u8; 32]) -> [u8; 32] {
+    let r = Fe::from_bytes(bytes);
+    let a = Fe::from_u64(CURVE_A);
+
+    // tv1 = Z * r^2, with the exceptional case tv1 == -1 mapped to 0.
+    let tv1 = Fe::from_u64(2).mul(&r.square());
+    let exceptional = tv1.ct_eq(&Fe::ONE.neg()) as u64;
+    let tv1 = Fe::select(&tv1, &Fe::ZERO, exceptional);
+
+    // x1 = -A / (1 + Z * r^2), gx1 = x1^3 + A * x1^2 + x1.
+    let x1 = a.neg().mul(&tv1.add(&Fe::ONE).invert());
+    let gx1 = x1.add(&a).mul(&x1).add(&Fe::ONE).mul(&x1);
+
+    // If gx1 is not a square, the point with x2 = -x1 - A lies on the curve instead.
+    let x2 = x1.neg().sub(&a);
+    let use_x1 = gx1.is_square() as u64;
+    Fe::select(&x2, &x1, use_x1).to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::tests::hex;
+
+    /// The u-coordinate of the standard Curve25519 base point.
+    const X25519_BASEPOINT: [u8; 32] = {
+        let mut u = [0u8; 32];
+        u[0] = 9;
+        u
+    };
+
+    fn arr(s: &str) -> [u8; 32] {
+        hex(s).try_into().unwrap()
+    }
+
+    #[test]
+    fn test_x25519_rfc7748_vector() {
+        let scalar = arr("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+        let u = arr("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+        assert_eq!(
+            x25519(&scalar, &u).to_vec(),
+            hex("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552")
+        );
+    }
+
+    #[test]
+    fn test_x25519_rfc7748_diffie_hellman() {
+        let alice = arr("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob = arr("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let alice_pub = x25519(&alice, &X25519_BASEPOINT);
+        let bob_pub = x25519(&bob, &X25519_BASEPOINT);
+        assert_eq!(
+            alice_pub.to_vec(),
+            hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+        );
+        assert_eq!(
+            bob_pub.to_vec(),
+            hex("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f")
+        );
+        let shared = hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+        assert_eq!(x25519(&alice, &bob_pub).to_vec(), shared);
+        assert_eq!(x25519(&bob, &alice_pub).to_vec(), shared);
+    }
+
+    #[test]
+    fn test_elligator2_lands_on_curve() {
+        // A u-coordinate is on Curve25519 iff u^3 + A u^2 + u is a square.
+        let a = Fe::from_u64(CURVE_A);
+        for seed in 0u8..32 {
+            let u = Fe::from_bytes(&elligator2(&[seed; 32]));
+            let rhs = u.square().mul(&u).add(&a.mul(&u.square())).add(&u);
+            assert!(rhs.is_square(), "seed {seed} mapped off the curve");
+        }
+    }
+
+    #[test]
+    fn test_field_invert_roundtrip() {
+        let x = Fe::from_bytes(&[7u8; 32]);
+        assert!(x.mul(&x.invert()).ct_eq(&Fe::ONE));
+    }
+}
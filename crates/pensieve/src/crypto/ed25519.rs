@@ -0,0 +1,452 @@
+//! Ed25519 signatures (RFC 8032), over the field of [`super::curve25519`].
+//!
+//! Points use extended twisted Edwards coordinates and the complete
+//! addition law, so multiplication by a secret scalar runs in constant
+//! time. Scalars are reduced modulo the group order one bit at a time,
+//! which is slow but simple and constant-time; signing is not a hot path.
+
+use super::curve25519::{Fe, elligator2};
+use super::sha512::Sha512;
+use crate::entropy::EntropySource;
+
+/// The curve constant d = -121665/121666, little-endian.
+const D: [u8; 32] = [
+    0xa3, 0x78, 0x59, 0x13, 0xca, 0x4d, 0xeb, 0x75, 0xab, 0xd8, 0x41, 0x41, 0x4d, 0x0a, 0x70, 0x00,
+    0x98, 0xe8, 0x79, 0x77, 0x79, 0x40, 0xc7, 0x8c, 0x73, 0xfe, 0x6f, 0x2b, 0xee, 0x6c, 0x03, 0x52,
+];
+
+/// A square root of -1, little-endian.
+const SQRT_M1: [u8; 32] = [
+    0xb0, 0xa0, 0x0e, 0x4a, 0x27, 0x1b, 0xee, 0xc4, 0x78, 0xe4, 0x2f, 0xad, 0x06, 0x18, 0x43, 0x2f,
+    0xa7, 0xd7, 0xfb, 0x3d, 0x99, 0x00, 0x4d, 0x2b, 0x0b, 0xdf, 0xc1, 0x4f, 0x80, 0x24, 0x83, 0x2b,
+];
+
+/// Encoding of the base point: y = 4/5 with even x.
+const BASE: [u8; 32] = {
+    let mut base = [0x66; 32];
+    base[0] = 0x58;
+    base
+};
+
+/// The group order L = 2^252 + 27742317777372353535851937790883648493, as
+/// little-endian 64-bit limbs.
+const ORDER: [u64; 4] = [
+    0x5812631a5cf5d3ed,
+    0x14def9dea2f79cd6,
+    0,
+    0x1000000000000000,
+];
+
+/// Length of a signature.
+pub(crate) const SIGNATURE_LEN: usize = 64;
+
+/// A point of the Edwards curve in extended coordinates: x = X/Z, y = Y/Z
+/// and T = XY/Z.
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Point = Point {
+        x: Fe::ZERO,
+        y: Fe::ONE,
+        z: Fe::ONE,
+        t: Fe::ZERO,
+    };
+
+    /// The complete addition law for a = -1 (add-2008-hwcd-3); also doubles.
+    fn add(&self, rhs: &Point) -> Point {
+        let d2 = Fe::from_bytes(&D).add(&Fe::from_bytes(&D));
+        let a = self.y.sub(&self.x).mul(&rhs.y.sub(&rhs.x));
+        let b = self.y.add(&self.x).mul(&rhs.y.add(&rhs.x));
+        let c = self.t.mul(&d2).mul(&rhs.t);
+        let d = self.z.add(&self.z).mul(&rhs.z);
+        let (e, f, g, h) = (b.sub(&a), d.sub(&c), d.add(&c), b.add(&a));
+        Point {
+            x: e.mul(&f),
+            y: g.mul(&h),
+            z: f.mul(&g),
+            t: e.mul(&h),
+        }
+    }
+
+    fn neg(&self) -> Point {
+        Point {
+            x: self.x.neg(),
+            t: self.t.neg(),
+            ..*self
+        }
+    }
+
+    /// `scalar` (32 little-endian bytes) times the point, in constant time.
+    fn mul(&self, scalar: &[u8; 32]) -> Point {
+        let mut acc = Point::IDENTITY;
+        for byte in scalar.iter().rev() {
+            for bit in (0..8).rev() {
+                acc = acc.add(&acc);
+                let sum = acc.add(self);
+                let choice = u64::from(byte >> bit & 1);
+                acc = Point {
+                    x: Fe::select(&acc.x, &sum.x, choice),
+                    y: Fe::select(&acc.y, &sum.y, choice),
+                    z: Fe::select(&acc.z, &sum.z, choice),
+                    t: Fe::select(&acc.t, &sum.t, choice),
+                };
+            }
+        }
+        acc
+    }
+
+    /// The 32-byte encoding: y, with the low bit of x in the top bit.
+    fn encode(&self) -> [u8; 32] {
+        let z_inv = self.z.invert();
+        let mut out = self.y.mul(&z_inv).to_bytes();
+        out[31] |= (self.x.mul(&z_inv).to_bytes()[0] & 1) << 7;
+        out
+    }
+
+    /// An x such that (x, y) is on the curve, in constant time, and whether
+    /// one exists.
+    fn recover_x(y: &Fe) -> (Fe, bool) {
+        let y2 = y.square();
+        let u = y2.sub(&Fe::ONE);
+        let v = Fe::from_bytes(&D).mul(&y2).add(&Fe::ONE);
+        // x = u v^3 (u v^7)^((p - 5) / 8), up to a factor of sqrt(-1).
+        let mut exponent = [0xff; 32];
+        exponent[0] = 0xfd;
+        exponent[31] = 0x0f;
+        let v3 = v.square().mul(&v);
+        let x = u.mul(&v3).mul(&u.mul(&v3).mul(&v3).mul(&v).pow(&exponent));
+        let vx2 = v.mul(&x.square());
+        let flipped = vx2.ct_eq(&u.neg());
+        let x = Fe::select(&x, &x.mul(&Fe::from_bytes(&SQRT_M1)), u64::from(flipped));
+        (x, flipped | vx2.ct_eq(&u))
+    }
+
+    /// Decodes a point (RFC 8032, section 5.1.3); `None` for non-canonical
+    /// or off-curve encodings.
+    fn decode(bytes: &[u8; 32]) -> Option<Point> {
+        let y = Fe::from_bytes(bytes);
+        let mut unsigned = *bytes;
+        unsigned[31] &= 0x7f;
+        if y.to_bytes() != unsigned {
+            return None;
+        }
+        let (mut x, on_curve) = Self::recover_x(&y);
+        if !on_curve {
+            return None;
+        }
+        let sign = bytes[31] >> 7;
+        let x_bytes = x.to_bytes();
+        if x_bytes == [0; 32] && sign == 1 {
+            return None;
+        }
+        if x_bytes[0] & 1 != sign {
+            x = x.neg();
+        }
+        Some(Point {
+            x,
+            y,
+            z: Fe::ONE,
+            t: x.mul(&y),
+        })
+    }
+}
+
+/// `bytes`, a little-endian integer of any length, modulo the group order.
+pub(crate) fn reduce(bytes: &[u8]) -> [u8; 32] {
+    let mut r = [0u64; 4];
+    for byte in bytes.iter().rev() {
+        for bit in (0..8).rev() {
+            // r = 2r + bit < 2L < 2^254, then subtract L if that fits.
+            let mut carry = u64::from(byte >> bit & 1);
+            for limb in &mut r {
+                let next = *limb >> 63;
+                *limb = *limb << 1 | carry;
+                carry = next;
+            }
+            let mut diff = [0u64; 4];
+            let mut borrow = 0u64;
+            for i in 0..4 {
+                let (d1, b1) = r[i].overflowing_sub(ORDER[i]);
+                let (d2, b2) = d1.overflowing_sub(borrow);
+                diff[i] = d2;
+                borrow = u64::from(b1 | b2);
+            }
+            let keep = 0u64.wrapping_sub(borrow); // All ones if r < L.
+            for i in 0..4 {
+                r[i] = (r[i] & keep) | (diff[i] & !keep);
+            }
+        }
+    }
+    let mut out = [0u8; 32];
+    for (chunk, limb) in out.chunks_exact_mut(8).zip(r) {
+        chunk.copy_from_slice(&limb.to_le_bytes());
+    }
+    out
+}
+
+/// `(k * a + r) mod L` for 32-byte little-endian `k`, `a` and `r`.
+fn mul_add(k: &[u8; 32], a: &[u8; 32], r: &[u8; 32]) -> [u8; 32] {
+    let limbs = |bytes: &[u8; 32]| -> [u64; 4] {
+        core::array::from_fn(|i| {
+            u64::from_le_bytes(bytes[8 * i..][..8].try_into().expect("8 bytes"))
+        })
+    };
+    let (k, a) = (limbs(k), limbs(a));
+    let mut product = [0u64; 8];
+    for (i, r_limb) in limbs(r).into_iter().enumerate() {
+        product[i] = r_limb;
+    }
+    for i in 0..4 {
+        let mut carry = 0u128;
+        for j in 0..4 {
+            let t = u128::from(k[i]) * u128::from(a[j]) + u128::from(product[i + j]) + carry;
+            product[i + j] = t as u64;
+            carry = t >> 64;
+        }
+        let mut index = i + 4;
+        while carry != 0 {
+            let t = u128::from(product[index]) + carry;
+            product[index] = t as u64;
+            carry = t >> 64;
+            index += 1;
+        }
+    }
+    let mut bytes = [0u8; 64];
+    for (chunk, limb) in bytes.chunks_exact_mut(8).zip(product) {
+        chunk.copy_from_slice(&limb.to_le_bytes());
+    }
+    reduce(&bytes)
+}
+
+/// The clamped secret scalar and the nonce prefix of a 32-byte seed.
+fn expand(seed: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let hash = Sha512::digest(&[seed]);
+    let mut scalar: [u8; 32] = hash[..32].try_into().expect("32 bytes");
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    (scalar, hash[32..].try_into().expect("32 bytes"))
+}
+
+fn base() -> Point {
+    Point::decode(&BASE).expect("the base point decodes")
+}
+
+/// The public key of the secret key `seed`.
+pub(crate) fn public_key(seed: &[u8; 32]) -> [u8; 32] {
+    base().mul(&expand(seed).0).encode()
+}
+
+/// Whether `bytes` encode a point of the curve.
+pub(crate) fn is_public_key(bytes: &[u8; 32]) -> bool {
+    Point::decode(bytes).is_some()
+}
+
+/// A uniform nonzero scalar drawn from `rng`.
+pub(crate) fn random_scalar(rng: &mut impl EntropySource) -> [u8; 32] {
+    loop {
+        let mut wide = [0u8; 64];
+        rng.fill_bytes(&mut wide);
+        let scalar = reduce(&wide);
+        if scalar != [0; 32] {
+            return scalar;
+        }
+    }
+}
+
+/// Maps 32 uniformly random bytes to a point of the prime-order subgroup,
+/// through [`elligator2`] and the birational map to the Edwards curve,
+/// followed by clearing the cofactor. Runs in constant time.
+pub(crate) fn hash_to_point(bytes: &[u8; 32]) -> [u8; 32] {
+    let u = Fe::from_bytes(&elligator2(bytes));
+    // y = (u - 1) / (u + 1); u = -1 is not on the curve.
+    let y = u.sub(&Fe::ONE).mul(&u.add(&Fe::ONE).invert());
+    let (x, _) = Point::recover_x(&y);
+    let mut point = Point {
+        x,
+        y,
+        z: Fe::ONE,
+        t: x.mul(&y),
+    };
+    for _ in 0..3 {
+        point = point.add(&point);
+    }
+    point.encode()
+}
+
+/// `scalar` times the point `point` encodes, or `None` unless `point` is a
+/// canonical encoding of a point of the prime-order subgroup other than
+/// the identity. Constant-time in `scalar` only.
+pub(crate) fn mul_point(point: &[u8; 32], scalar: &[u8; 32]) -> Option<[u8; 32]> {
+    let point = Point::decode(point)?;
+    let mut order = [0u8; 32];
+    for (chunk, limb) in order.chunks_exact_mut(8).zip(ORDER) {
+        chunk.copy_from_slice(&limb.to_le_bytes());
+    }
+    let identity = Point::IDENTITY.encode();
+    if point.encode() == identity || point.mul(&order).encode() != identity {
+        return None;
+    }
+    Some(point.mul(scalar).encode())
+}
+
+/// The inverse of the nonzero `scalar` modulo the group order, by Fermat's
+/// little theorem.
+pub(crate) fn invert_scalar(scalar: &[u8; 32]) -> [u8; 32] {
+    // L - 2, little-endian.
+    let mut exponent = ORDER;
+    exponent[0] -= 2;
+    let mut acc = reduce(&[1]);
+    for limb in exponent.iter().rev() {
+        for bit in (0..64).rev() {
+            acc = mul_add(&acc, &acc, &[0; 32]);
+            let product = mul_add(&acc, scalar, &[0; 32]);
+            if limb >> bit & 1 == 1 {
+                acc = product;
+            }
+        }
+    }
+    acc
+}
+
+/// Signs the concatenation of `message` under the secret key `seed`.
+pub(crate) fn sign(seed: &[u8; 32], message: &[&[u8]]) -> [u8; SIGNATURE_LEN] {
+    let (scalar, prefix) = expand(seed);
+    let public = base().mul(&scalar).encode();
+    let mut hasher = Sha512::new();
+    hasher.update(&prefix);
+    message.iter().for_each(|part| hasher.update(part));
+    let r = reduce(&hasher.finalize());
+    let big_r = base().mul(&r).encode();
+    let k = challenge(&big_r, &public, message);
+    let mut signature = [0u8; SIGNATURE_LEN];
+    signature[..32].copy_from_slice(&big_r);
+    signature[32..].copy_from_slice(&mul_add(&k, &scalar, &r));
+    signature
+}
+
+/// Whether `signature` signs the concatenation of `message` under
+/// `public_key`. Rejects non-canonical scalars and key encodings.
+pub(crate) fn verify(public_key: &[u8; 32], message: &[&[u8]], signature: &[u8; 64]) -> bool {
+    let Some(a) = Point::decode(public_key) else {
+        return false;
+    };
+    let big_r: [u8; 32] = signature[..32].try_into().expect("32 bytes");
+    let s: [u8; 32] = signature[32..].try_into().expect("32 bytes");
+    if reduce(&s) != s {
+        return false;
+    }
+    let k = challenge(&big_r, public_key, message);
+    base().mul(&s).add(&a.neg().mul(&k)).encode() == big_r
+}
+
+/// The challenge scalar SHA-512(R || A || M) mod L.
+fn challenge(big_r: &[u8; 32], public_key: &[u8; 32], message: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha512::new();
+    hasher.update(big_r);
+    hasher.update(public_key);
+    message.iter().for_each(|part| hasher.update(part));
+    reduce(&hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::tests::hex;
+
+    fn arr<const N: usize>(s: &str) -> [u8; N] {
+        hex(s).try_into().unwrap()
+    }
+
+    #[test]
+    fn test_rfc8032_vectors() {
+        // Tests 1 and 2 of RFC 8032, section 7.1.
+        let cases = [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                "",
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            ),
+            (
+                "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+                "72",
+                "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+            ),
+        ];
+        for (seed, public, message, signature) in cases {
+            let (seed, public) = (arr::<32>(seed), arr::<32>(public));
+            let (message, signature) = (hex(message), arr::<64>(signature));
+            assert_eq!(public_key(&seed), public);
+            assert_eq!(sign(&seed, &[&message]), signature);
+            assert!(verify(&public, &[&message], &signature));
+        }
+    }
+
+    #[test]
+    fn test_rejects_forgeries() {
+        let seed = [1u8; 32];
+        let public = public_key(&seed);
+        let message: &[&[u8]] = &[b"pensieve attestation ", b"test message"];
+        let signature = sign(&seed, message);
+        // Cross-checked against OpenSSL.
+        assert_eq!(
+            public,
+            arr::<32>("8a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf3748801b40f6f5c")
+        );
+        assert!(verify(
+            &public,
+            &[b"pensieve attestation test message"],
+            &signature
+        ));
+        assert!(!verify(
+            &public,
+            &[b"pensieve attestation test messagf"],
+            &signature
+        ));
+        assert!(!verify(&public_key(&[2; 32]), message, &signature));
+        let mut tampered = signature;
+        tampered[40] ^= 1;
+        assert!(!verify(&public, message, &tampered));
+        // S + L is the same scalar, but not canonical.
+        let mut malleated = signature;
+        let s: [u8; 32] = signature[32..].try_into().unwrap();
+        let mut carry = 0u16;
+        for (i, byte) in malleated[32..].iter_mut().enumerate() {
+            let l = ORDER[i / 8].to_le_bytes()[i % 8];
+            let sum = u16::from(s[i]) + u16::from(l) + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(!verify(&public, message, &malleated));
+        assert!(!verify(&[0xff; 32], message, &signature));
+    }
+
+    #[test]
+    fn test_group_helpers() {
+        let scalar = reduce(&[0xab; 64]);
+        let mut one = [0u8; 32];
+        one[0] = 1;
+        assert_eq!(mul_add(&invert_scalar(&scalar), &scalar, &[0; 32]), one);
+
+        let point = hash_to_point(&[5; 32]);
+        assert_ne!(point, hash_to_point(&[6; 32]));
+        // Multiplying by a scalar and its inverse is the identity map.
+        let blinded = mul_point(&point, &scalar).unwrap();
+        assert_eq!(mul_point(&blinded, &invert_scalar(&scalar)), Some(point));
+        // The base point, but not its sum with a point of order 2.
+        assert!(mul_point(&BASE, &one).is_some());
+        let mut order_two = [0xff; 32]; // y = -1.
+        order_two[0] = 0xec;
+        order_two[31] = 0x7f;
+        let mixed = base().add(&Point::decode(&order_two).unwrap());
+        assert_eq!(mul_point(&mixed.encode(), &one), None);
+    }
+}
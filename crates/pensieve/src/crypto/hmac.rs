@@ -0,0 +1,205 @@
+use super::sha256::Sha256;
+#[cfg(feature = "alloc")]
+use super::sha512::Sha512;
+
+/// HMAC-SHA256 (RFC 2104) with an incremental interface.
+#[derive(Clone)]
+pub(crate) struct HmacSha256 {
+    inner: Sha256,       // Hash of (key ^ ipad) || message.
+    outer_key: [u8; 64], // key ^ opad, applied at finalization.
+}
+
+impl HmacSha256 {
+    /// Creates a MAC instance keyed with `key` (hashed first if longer than a block).
+    pub(crate) fn new(key: &[u8]) -> Self {
+        // Normalize the key to exactly one block.
+        let mut block = [0u8; Sha256::BLOCK_LEN];
+        if key.len() > Sha256::BLOCK_LEN {
+            block[..Sha256::OUTPUT_LEN].copy_from_slice(&Sha256::digest(&[key]));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner_key = [0u8; 64];
+        let mut outer_key = [0u8; 64];
+        for i in 0..64 {
+            inner_key[i] = block[i] ^ 0x36;
+            outer_key[i] = block[i] ^ 0x5c;
+        }
+
+        let mut inner = Sha256::new();
+        inner.update(&inner_key);
+        Self { inner, outer_key }
+    }
+
+    /// Absorbs message bytes.
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Returns the 32-byte tag.
+    pub(crate) fn finalize(self) -> [u8; 32] {
+        let inner_hash = self.inner.finalize();
+        Sha256::digest(&[&self.outer_key, &inner_hash])
+    }
+
+    /// One-shot convenience: MAC over the concatenation of `parts`.
+    pub(crate) fn mac(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+        let mut mac = Self::new(key);
+        for part in parts {
+            mac.update(part);
+        }
+        mac.finalize()
+    }
+}
+
+/// HKDF-SHA256 extract step (RFC 5869, section 2.2).
+pub(crate) fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; 32] {
+    HmacSha256::mac(salt, &[ikm])
+}
+
+/// HKDF-SHA256 expand step (RFC 5869, section 2.3), filling all of `okm`.
+///
+/// # Panics
+/// Panics if more than 255 * 32 bytes are requested, which RFC 5869 forbids.
+pub(crate) fn hkdf_expand(prk: &[u8; 32], info: &[u8], okm: &mut [u8]) {
+    assert!(
+        okm.len() <= 255 * 32,
+        "HKDF output length exceeds 255 blocks"
+    );
+    let mut previous: Option<[u8; 32]> = None;
+    for (counter, chunk) in okm.chunks_mut(32).enumerate() {
+        let mut mac = HmacSha256::new(prk);
+        if let Some(t) = previous {
+            mac.update(&t); // T(i) = HMAC(PRK, T(i-1) || info || i)
+        }
+        mac.update(info);
+        mac.update(&[(counter + 1) as u8]);
+        let t = mac.finalize();
+        chunk.copy_from_slice(&t[..chunk.len()]);
+        previous = Some(t);
+    }
+}
+
+/// HMAC-SHA512 (RFC 2104), as needed by PBKDF2 for BIP39 seeds.
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub(crate) struct HmacSha512 {
+    inner: Sha512,        // Hash of (key ^ ipad) || message.
+    outer_key: [u8; 128], // key ^ opad, applied at finalization.
+}
+
+#[cfg(feature = "alloc")]
+impl HmacSha512 {
+    /// Creates a MAC instance keyed with `key` (hashed first if longer than a block).
+    pub(crate) fn new(key: &[u8]) -> Self {
+        let mut block = [0u8; Sha512::BLOCK_LEN];
+        if key.len() > Sha512::BLOCK_LEN {
+            block[..Sha512::OUTPUT_LEN].copy_from_slice(&Sha512::digest(&[key]));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner_key = [0u8; 128];
+        let mut outer_key = [0u8; 128];
+        for i in 0..128 {
+            inner_key[i] = block[i] ^ 0x36;
+            outer_key[i] = block[i] ^ 0x5c;
+        }
+
+        let mut inner = Sha512::new();
+        inner.update(&inner_key);
+        Self { inner, outer_key }
+    }
+
+    /// Absorbs message bytes.
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Returns the 64-byte tag.
+    pub(crate) fn finalize(self) -> [u8; 64] {
+        let inner_hash = self.inner.finalize();
+        Sha512::digest(&[&self.outer_key, &inner_hash])
+    }
+}
+
+/// PBKDF2-HMAC-SHA512 (RFC 8018, section 5.2), filling all of `out`.
+#[cfg(feature = "alloc")]
+pub(crate) fn pbkdf2_sha512(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    // Keying HMAC is the expensive part of a tiny message, so key once and clone.
+    let keyed = HmacSha512::new(password);
+    for (index, chunk) in out.chunks_mut(64).enumerate() {
+        let mut mac = keyed.clone();
+        mac.update(salt);
+        mac.update(&(index as u32 + 1).to_be_bytes());
+        let mut u = mac.finalize();
+        let mut t = u;
+        for _ in 1..iterations {
+            let mut mac = keyed.clone();
+            mac.update(&u);
+            u = mac.finalize();
+            for (t, u) in t.iter_mut().zip(u) {
+                *t ^= u;
+            }
+        }
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::tests::hex;
+
+    #[test]
+    fn test_hmac_rfc4231_case_1() {
+        let key = [0x0b; 20];
+        assert_eq!(
+            HmacSha256::mac(&key, &[b"Hi There"]).to_vec(),
+            hex("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7")
+        );
+    }
+
+    #[test]
+    fn test_hmac_rfc4231_case_2() {
+        assert_eq!(
+            HmacSha256::mac(b"Jefe", &[b"what do ya want for nothing?"]).to_vec(),
+            hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+    }
+
+    #[test]
+    fn test_hkdf_rfc5869_case_1() {
+        let ikm = [0x0b; 22];
+        let salt = hex("000102030405060708090a0b0c");
+        let info = hex("f0f1f2f3f4f5f6f7f8f9");
+        let prk = hkdf_extract(&salt, &ikm);
+        assert_eq!(
+            prk.to_vec(),
+            hex("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5")
+        );
+        let mut okm = [0u8; 42];
+        hkdf_expand(&prk, &info, &mut okm);
+        assert_eq!(
+            okm.to_vec(),
+            hex(
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+            )
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_hmac_sha512_rfc4231_case_2() {
+        let mut mac = HmacSha512::new(b"Jefe");
+        mac.update(b"what do ya want for nothing?");
+        assert_eq!(
+            mac.finalize().to_vec(),
+            hex(concat!(
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554",
+                "9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+            ))
+        );
+    }
+}
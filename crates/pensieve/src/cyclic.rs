@@ -0,0 +1,195 @@
+//! Collapsing inputs whose start is only known up to a rotation.
+//!
+//! Captures of a periodic signal, or frames whose synchronization is
+//! ambiguous, arrive rotated by a few bit positions. Every bit then lands
+//! in another place, and even error-free readings collapse differently. A
+//! [`CyclicCollapse`] offers two remedies:
+//!
+//! - [`CyclicCollapse::collapse`] rotates the input to its canonical start
+//!   first, the rotation whose bits are lexicographically smallest, so every
+//!   rotation of an input gives the same digest. The smallest rotation
+//!   usually starts at the longest run of zeros; a bit flip that creates or
+//!   breaks such a run moves the start, and the digest with it.
+//! - [`CyclicCollapse::matches`] collapses one input at every rotation up to
+//!   the configured bound instead, which survives noise anywhere at the cost
+//!   of a collapse per rotation tried.
+//!
+//! Neither runs in constant time: the canonical start and the rotation
+//! found depend on the bits.
+
+use crate::{BitOrder, TbfConfig};
+use alloc::vec::Vec;
+
+/// A collapse that tolerates cyclic rotations of its input.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::cyclic::CyclicCollapse;
+/// use pensieve::{TbfConfig, Thresholding};
+///
+/// let frame = *b"\x00\x01sync-word and payload";
+/// // The same frame, captured three bits late.
+/// let late: Vec<u8> = (0..frame.len())
+///     .map(|i| frame[i] << 3 | frame[(i + 1) % frame.len()] >> 5)
+///     .collect();
+///
+/// let config = TbfConfig::new(0.1).with_thresholding(Thresholding::Symmetric);
+/// assert_ne!(config.collapse(&frame), config.collapse(&late));
+/// let cyclic = CyclicCollapse::new(config, 4);
+/// assert_eq!(cyclic.collapse(&frame), cyclic.collapse(&late));
+/// assert!(cyclic.matches(&frame, &late));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CyclicCollapse {
+    config: TbfConfig,
+    max_shift: usize, // Bits, in either direction.
+}
+
+impl CyclicCollapse {
+    /// Collapses under `config`, trying rotations of up to `max_shift` bits
+    /// either way in [`CyclicCollapse::matches`].
+    pub fn new(config: TbfConfig, max_shift: usize) -> Self {
+        Self { config, max_shift }
+    }
+
+    /// The configuration inputs are collapsed under.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// The largest rotation, in bits, [`CyclicCollapse::matches`] tries.
+    pub fn max_shift(&self) -> usize {
+        self.max_shift
+    }
+
+    /// Collapses `input` rotated to its canonical start, so that every
+    /// rotation of `input` gives the same digest.
+    pub fn collapse(&self, input: &[u8]) -> Vec<u8> {
+        let bits = self.bits(input);
+        self.collapse_rotated(&bits, least_rotation(&bits))
+    }
+
+    /// The rotation, in bits towards the front, that brings `input` to its
+    /// canonical start; bits are counted in the configured bit order.
+    pub fn canonical_rotation(&self, input: &[u8]) -> usize {
+        least_rotation(&self.bits(input))
+    }
+
+    /// Whether `b`, rotated by at most [`max_shift`](Self::max_shift) bits
+    /// either way, collapses like `a`. Inputs of different lengths never
+    /// match.
+    pub fn matches(&self, a: &[u8], b: &[u8]) -> bool {
+        if a.len() != b.len() {
+            return false;
+        }
+        let target = self.config.collapse(a);
+        let bits = self.bits(b);
+        let n = bits.len().max(1);
+        (0..=self.max_shift.min(n / 2)).any(|shift| {
+            self.collapse_rotated(&bits, shift % n) == target
+                || self.collapse_rotated(&bits, (n - shift % n) % n) == target
+        })
+    }
+
+    /// The bits of `input` in the configured bit order, one per byte.
+    fn bits(&self, input: &[u8]) -> Vec<u8> {
+        self.config
+            .msb_first(input)
+            .iter()
+            .flat_map(|&byte| (0..8).rev().map(move |k| byte >> k & 1))
+            .collect()
+    }
+
+    /// Collapses `bits` starting from bit `start` and wrapping around.
+    fn collapse_rotated(&self, bits: &[u8], start: usize) -> Vec<u8> {
+        let mut rotated = alloc::vec![0u8; bits.len() / 8];
+        for (i, &bit) in bits[start..].iter().chain(&bits[..start]).enumerate() {
+            rotated[i / 8] |= bit << (7 - i % 8);
+        }
+        // The bits are already in the configured order.
+        self.config
+            .with_bit_order(BitOrder::MsbFirst)
+            .collapse(&rotated)
+    }
+}
+
+/// The start of the lexicographically smallest rotation of `s`, the first
+/// one if several are equal.
+fn least_rotation(s: &[u8]) -> usize {
+    let n = s.len();
+    let (mut i, mut j, mut k) = (0, 1, 0);
+    while i < n && j < n && k < n {
+        let (a, b) = (s[(i + k) % n], s[(j + k) % n]);
+        if a == b {
+            k += 1;
+            continue;
+        }
+        if a > b {
+            i += k + 1;
+        } else {
+            j += k + 1;
+        }
+        if i == j {
+            j += 1;
+        }
+        k = 0;
+    }
+    i.min(j).min(n.saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Thresholding;
+    use crate::entropy::tests::seeded;
+
+    /// `input` rotated `shift` bits towards the front, MSB-first.
+    fn rotate(input: &[u8], shift: usize) -> Vec<u8> {
+        let n = input.len() * 8;
+        (0..input.len())
+            .map(|byte| {
+                (0..8).fold(0u8, |out, k| {
+                    let bit = (byte * 8 + k + shift) % n;
+                    out << 1 | input[bit / 8] >> (7 - bit % 8) & 1
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_every_rotation_collapses_alike() {
+        let mut fill = seeded(418);
+        let cyclic = CyclicCollapse::new(TbfConfig::new(0.1), 0);
+        for len in [1, 2, 7, 32] {
+            let mut input = alloc::vec![0u8; len];
+            fill(&mut input);
+            let digest = cyclic.collapse(&input);
+            for shift in 0..len * 8 {
+                assert_eq!(cyclic.collapse(&rotate(&input, shift)), digest);
+            }
+        }
+        assert!(cyclic.collapse(&[]).is_empty());
+        assert_eq!(least_rotation(&[1, 0, 1, 0, 0, 1]), 3);
+        assert_eq!(least_rotation(&[1, 1, 1]), 0);
+    }
+
+    #[test]
+    fn test_matches_tolerates_bounded_shifts_and_noise() {
+        let mut input = [0u8; 32];
+        seeded(1)(&mut input);
+        let cyclic = CyclicCollapse::new(
+            TbfConfig::new(0.1).with_thresholding(Thresholding::Symmetric),
+            5,
+        );
+        for shift in [1, 5, 256 - 5] {
+            let mut noisy = rotate(&input, shift);
+            noisy[9] ^= 0x10;
+            assert!(cyclic.matches(&input, &noisy), "shift {shift}");
+        }
+        let mut bits = [0u8; 32];
+        seeded(2)(&mut bits);
+        assert!(!cyclic.matches(&input, &bits));
+        assert!(!cyclic.matches(&input, &input[1..]));
+        assert!(CyclicCollapse::new(TbfConfig::new(0.1), 5).matches(&[], &[]));
+    }
+}
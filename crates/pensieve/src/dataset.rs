@@ -0,0 +1,300 @@
+//! Synthetic labeled pairs for evaluating configurations.
+//!
+//! A [`PairGenerator`] draws source inputs from an [`InputDistribution`]
+//! and captures each of them twice through a [`NoiseModel`]: a genuine
+//! pair holds two captures of one source, an impostor pair captures two
+//! independent sources. Because the ground truth is known, the pairs can
+//! be fed to [`calibrate`](crate::analysis::calibrate) or any other
+//! evaluation, and a seeded source (see [`noise::Seeded`]) makes the whole
+//! dataset reproducible. [`to_text`] and [`from_text`] store a dataset in a
+//! line-based text format, so the same pairs can be evaluated by other
+//! tools.
+
+use crate::Error;
+use crate::entropy::EntropySource;
+use crate::noise;
+use crate::test_vectors::{hex, unhex};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// How the bits of source inputs are drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputDistribution {
+    /// Every bit independently 0 or 1 with equal probability.
+    Uniform,
+    /// Every bit independently 1 with probability `one_probability`
+    /// (clamped to 0.0..=1.0), like a biased PUF or a sparse feature vector.
+    Biased {
+        /// Probability of each bit being 1.
+        one_probability: f64,
+    },
+}
+
+/// How a capture deviates from its source, using the generators of
+/// [`noise`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoiseModel {
+    /// Exactly this many distinct bits flip ([`noise::flip_random_bits`]).
+    Flips(usize),
+    /// Every bit flips independently with this probability
+    /// ([`noise::flip_each_bit`]).
+    BitErrorRate(f64),
+    /// A run of this many bits is randomized ([`noise::burst_corrupt`]).
+    Burst(usize),
+    /// This many bytes are zeroed ([`noise::erase_random_bytes`]).
+    Erasures(usize),
+}
+
+impl NoiseModel {
+    /// Applies one draw of this noise to `input`.
+    pub fn apply(self, input: &mut [u8], rng: &mut impl EntropySource) {
+        match self {
+            Self::Flips(count) => {
+                noise::flip_random_bits(input, count, rng);
+            }
+            Self::BitErrorRate(probability) => {
+                noise::flip_each_bit(input, probability, rng);
+            }
+            Self::Burst(length) => {
+                noise::burst_corrupt(input, length, rng);
+            }
+            Self::Erasures(count) => {
+                noise::erase_random_bytes(input, count, rng);
+            }
+        }
+    }
+}
+
+/// Two captures and whether they come from the same source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabeledPair {
+    /// The first capture.
+    pub a: Vec<u8>,
+    /// The second capture.
+    pub b: Vec<u8>,
+    /// Whether both captures come from the same source.
+    pub genuine: bool,
+}
+
+/// An endless iterator of [`LabeledPair`]s, alternating genuine and
+/// impostor pairs, starting with a genuine one.
+///
+/// # Examples
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use pensieve::analysis::calibrate;
+/// use pensieve::dataset::{InputDistribution, NoiseModel, PairGenerator};
+/// use pensieve::noise::Seeded;
+///
+/// // Sparse sources, like binarized features, with 4 bits flipped per capture.
+/// let sparse = InputDistribution::Biased { one_probability: 0.1 };
+/// let pairs: Vec<_> = PairGenerator::new(32, NoiseModel::Flips(4), Seeded::new(1))
+///     .with_distribution(sparse)
+///     .take(200)
+///     .collect();
+/// let labeled = |genuine: bool| -> Vec<(&[u8], &[u8])> {
+///     pairs
+///         .iter()
+///         .filter(|pair| pair.genuine == genuine)
+///         .map(|pair| (&pair.a[..], &pair.b[..]))
+///         .collect()
+/// };
+/// let (genuine, impostor) = (labeled(true), labeled(false));
+/// let config = calibrate(&genuine, &impostor);
+/// let matching = |pairs: &[(&[u8], &[u8])]| {
+///     pairs.iter().filter(|(a, b)| config.collapse(a) == config.collapse(b)).count()
+/// };
+/// assert!(matching(&genuine) > matching(&impostor));
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PairGenerator<R> {
+    input_len: usize,
+    distribution: InputDistribution,
+    noise: NoiseModel,
+    rng: R,
+    genuine: bool, // Label of the next pair.
+}
+
+impl<R: EntropySource> PairGenerator<R> {
+    /// Creates a generator of pairs of `input_len`-byte captures of
+    /// [uniform](InputDistribution::Uniform) sources, each capture
+    /// corrupted by `noise`, with all randomness drawn from `rng`.
+    pub fn new(input_len: usize, noise: NoiseModel, rng: R) -> Self {
+        Self {
+            input_len,
+            distribution: InputDistribution::Uniform,
+            noise,
+            rng,
+            genuine: true,
+        }
+    }
+
+    /// This generator with sources drawn from `distribution`.
+    pub fn with_distribution(self, distribution: InputDistribution) -> Self {
+        Self {
+            distribution,
+            ..self
+        }
+    }
+
+    fn source(&mut self) -> Vec<u8> {
+        let mut source = alloc::vec![0u8; self.input_len];
+        match self.distribution {
+            InputDistribution::Uniform => self.rng.fill_bytes(&mut source),
+            InputDistribution::Biased { one_probability } => {
+                // Flipping every bit of zeros sets each with that probability.
+                noise::flip_each_bit(&mut source, one_probability, &mut self.rng);
+            }
+        }
+        source
+    }
+
+    fn capture(&mut self, source: &[u8]) -> Vec<u8> {
+        let mut capture = source.to_vec();
+        self.noise.apply(&mut capture, &mut self.rng);
+        capture
+    }
+}
+
+impl<R: EntropySource> Iterator for PairGenerator<R> {
+    type Item = LabeledPair;
+
+    fn next(&mut self) -> Option<LabeledPair> {
+        let genuine = self.genuine;
+        self.genuine = !genuine;
+        let source = self.source();
+        let other = if genuine {
+            source.clone()
+        } else {
+            self.source()
+        };
+        Some(LabeledPair {
+            a: self.capture(&source),
+            b: self.capture(&other),
+            genuine,
+        })
+    }
+}
+
+/// Writes `pairs` one per line as `genuine` or `impostor` followed by the
+/// hex of both captures, separated by single spaces.
+pub fn to_text(pairs: &[LabeledPair]) -> String {
+    pairs
+        .iter()
+        .map(|pair| {
+            let label = if pair.genuine { "genuine" } else { "impostor" };
+            alloc::format!("{label} {} {}\n", hex(&pair.a), hex(&pair.b))
+        })
+        .collect()
+}
+
+/// Parses the output of [`to_text`], ignoring empty lines.
+///
+/// Fails with [`Error::InvalidParameter`] on a line that is not a label
+/// and two captures, and with [`Error::InvalidCharacter`] on invalid hex.
+pub fn from_text(text: &str) -> Result<Vec<LabeledPair>, Error> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [label, a, b] = fields[..] else {
+                return Err(Error::InvalidParameter("expected a label and two captures"));
+            };
+            let genuine = match label {
+                "genuine" => true,
+                "impostor" => false,
+                _ => return Err(Error::InvalidParameter("unknown pair label")),
+            };
+            Ok(LabeledPair {
+                a: unhex(a)?,
+                b: unhex(b)?,
+                genuine,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::Seeded;
+    use crate::{TbfConfig, Thresholding};
+
+    fn hamming(a: &[u8], b: &[u8]) -> u32 {
+        a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+    }
+
+    #[test]
+    fn test_pairs_follow_their_labels() {
+        let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+        let mut matches = [0usize; 2]; // Impostor, genuine.
+        let pairs: Vec<_> = PairGenerator::new(32, NoiseModel::Flips(3), Seeded::new(9))
+            .take(100)
+            .collect();
+        assert!(pairs.iter().step_by(2).all(|pair| pair.genuine));
+        assert!(pairs.iter().skip(1).step_by(2).all(|pair| !pair.genuine));
+        for pair in &pairs {
+            assert_eq!(pair.a.len(), 32);
+            let distance = hamming(&pair.a, &pair.b);
+            matches[usize::from(pair.genuine)] +=
+                usize::from(config.collapse(&pair.a) == config.collapse(&pair.b));
+            if pair.genuine {
+                // Two captures of 3 flips each.
+                assert!(distance <= 6 && distance.is_multiple_of(2));
+            } else {
+                assert!(distance > 64, "{distance}");
+            }
+        }
+        // Uniform chunks sit near the symmetric threshold, so even a few
+        // flips split some genuine pairs.
+        assert!(matches[1] > 25 && matches[0] == 0, "{matches:?}");
+
+        let regenerated: Vec<_> = PairGenerator::new(32, NoiseModel::Flips(3), Seeded::new(9))
+            .take(100)
+            .collect();
+        assert_eq!(regenerated, pairs);
+    }
+
+    #[test]
+    fn test_biased_sources_and_other_noise() {
+        let biased = InputDistribution::Biased {
+            one_probability: 0.0,
+        };
+        let mut erased = PairGenerator::new(16, NoiseModel::Erasures(2), Seeded::new(1))
+            .with_distribution(biased);
+        assert_eq!(erased.next().unwrap().a, [0; 16]);
+
+        let ones = InputDistribution::Biased {
+            one_probability: 1.0,
+        };
+        for noise in [NoiseModel::Burst(8), NoiseModel::BitErrorRate(0.01)] {
+            let pair = PairGenerator::new(16, noise, Seeded::new(2))
+                .with_distribution(ones)
+                .next()
+                .unwrap();
+            assert!(hamming(&pair.a, &[0xFF; 16]) <= 16);
+        }
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        let pairs: Vec<_> = PairGenerator::new(4, NoiseModel::Flips(1), Seeded::new(3))
+            .take(4)
+            .collect();
+        let text = to_text(&pairs);
+        assert!(text.starts_with("genuine "));
+        assert_eq!(text.lines().count(), 4);
+        assert_eq!(from_text(&text), Ok(pairs));
+        assert_eq!(from_text("\n"), Ok(Vec::new()));
+        assert_eq!(
+            from_text("genuine 00"),
+            Err(Error::InvalidParameter("expected a label and two captures"))
+        );
+        assert_eq!(
+            from_text("same 00 00"),
+            Err(Error::InvalidParameter("unknown pair label"))
+        );
+        assert!(from_text("impostor 0g 00").is_err());
+    }
+}
@@ -0,0 +1,439 @@
+//! Streaming near-duplicate detection.
+//!
+//! A [`Deduplicator`] collapses every record it sees and remembers the
+//! collapsed value of the first record that produced it. A later record
+//! collapsing to a remembered value is reported as a duplicate of that first
+//! record; anything else is unique and remembered in turn.
+//!
+//! Memory is bounded by a fixed number of remembered fingerprints. Once it is
+//! full, the [`Eviction`] policy picks the fingerprint to forget, after which
+//! records matching only that fingerprint count as unique again. Each
+//! fingerprint costs a few dozen bytes regardless of the record length,
+//! because a collapsed value is fully described by the record length and
+//! its (at most a handful of) chunk levels.
+//!
+//! With a [time to live](Deduplicator::with_ttl), fingerprints are also
+//! forgotten once they have not been seen for that long, so a long-running
+//! stream filter only suppresses recent repeats. Time is any monotonic
+//! clock the caller passes to [`Deduplicator::check_at`], such as seconds
+//! since the service started.
+//!
+//! A [`StoreDeduplicator`] makes the same decisions against fingerprints
+//! kept in a [`FingerprintStore`], typically a database, with no capacity
+//! limit and caller-chosen record ids.
+
+use crate::retention::Retention;
+use crate::store::FingerprintStore;
+use crate::{Error, TbfConfig};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// Which fingerprint a full [`Deduplicator`] (or entry a full
+/// [`MemoryStore`](crate::store::MemoryStore)) forgets to make room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// The fingerprint remembered longest ago.
+    Oldest,
+    /// The fingerprint matched (or first seen) longest ago, so fingerprints
+    /// that keep recurring stay.
+    LeastRecentlyUsed,
+    /// The fingerprint matched the fewest times since it was remembered,
+    /// the least recently used among those, so a few very frequent
+    /// fingerprints stay through bursts of one-off records.
+    LeastFrequentlyUsed,
+}
+
+/// The verdict on one record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// No remembered record collapses like this one; it is now remembered
+    /// under the given id.
+    Unique(u64),
+    /// The record collapses like the remembered record with the given id.
+    DuplicateOf(u64),
+}
+
+/// A collapsed value: the record length and its chunk levels, which together
+/// determine the whole collapsed output.
+type Fingerprint = (usize, Vec<u8>);
+
+/// Classifies a stream of records as unique or duplicates of earlier ones.
+///
+/// Records are numbered from 0 in the order they are checked, and a record's
+/// number is its id.
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    config: TbfConfig,
+    capacity: usize,
+    entries: BTreeMap<Fingerprint, u64>, // Remembered fingerprint -> id of its first record.
+    by_id: BTreeMap<u64, Fingerprint>,   // The same fingerprints by id.
+    retention: Retention,                // Eviction and expiry order of the ids.
+    next_id: u64,                        // Id of the next record checked.
+    evicted: u64,                        // Fingerprints forgotten to stay within capacity.
+    expired: u64,                        // Fingerprints forgotten for their age.
+}
+
+impl Deduplicator {
+    /// Creates a deduplicator remembering at most `capacity` fingerprints.
+    pub fn new(config: TbfConfig, capacity: usize, eviction: Eviction) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(Error::InvalidParameter(
+                "capacity must be at least one fingerprint",
+            ));
+        }
+        Ok(Self {
+            config,
+            capacity,
+            entries: BTreeMap::new(),
+            by_id: BTreeMap::new(),
+            retention: Retention::new(eviction),
+            next_id: 0,
+            evicted: 0,
+            expired: 0,
+        })
+    }
+
+    /// This deduplicator forgetting every fingerprint not seen for `ttl`
+    /// time units: a record arriving `ttl` or more after the last record
+    /// collapsing like it is unique again.
+    pub fn with_ttl(mut self, ttl: u64) -> Self {
+        self.retention.set_ttl(Some(ttl));
+        self
+    }
+
+    /// The configuration records are collapsed with.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// Maximum number of remembered fingerprints.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How long a fingerprint is remembered after it was last seen, if it
+    /// expires at all.
+    pub fn ttl(&self) -> Option<u64> {
+        self.retention.ttl()
+    }
+
+    /// Number of currently remembered fingerprints.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if nothing is remembered.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of fingerprints forgotten to stay within the capacity.
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// Number of fingerprints forgotten because they outlived the time to
+    /// live.
+    pub fn expired(&self) -> u64 {
+        self.expired
+    }
+
+    /// Classifies the next record of the stream, arriving at the time of
+    /// the latest [`Deduplicator::check_at`] or [`Deduplicator::expire`].
+    pub fn check(&mut self, record: &[u8]) -> Decision {
+        let now = self.retention.now();
+        self.check_at(record, now)
+    }
+
+    /// Classifies the next record of the stream, arriving at time `now`;
+    /// a `now` earlier than a previous one counts as that one.
+    pub fn check_at(&mut self, record: &[u8], now: u64) -> Decision {
+        self.expire(now);
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let fingerprint = (record.len(), self.config.chunk_levels(record));
+        if let Some(&first) = self.entries.get(&fingerprint) {
+            self.retention.touch(first);
+            crate::metrics::decided(true);
+            return Decision::DuplicateOf(first);
+        }
+
+        if self.entries.len() == self.capacity {
+            let victim = self.retention.victim().expect("capacity is non-zero");
+            self.forget(victim);
+            self.evicted += 1;
+        }
+        self.retention.insert(id);
+        self.by_id.insert(id, fingerprint.clone());
+        self.entries.insert(fingerprint, id);
+        crate::metrics::decided(false);
+        Decision::Unique(id)
+    }
+
+    /// Advances the clock to `now` without checking a record and forgets
+    /// the fingerprints that expired by then; returns how many.
+    pub fn expire(&mut self, now: u64) -> usize {
+        let expired = self.retention.expire(now);
+        for &id in &expired {
+            self.forget(id);
+        }
+        self.expired += expired.len() as u64;
+        expired.len()
+    }
+
+    fn forget(&mut self, id: u64) {
+        let fingerprint = self.by_id.remove(&id).expect("remembered");
+        self.entries.remove(&fingerprint);
+        self.retention.remove(id);
+    }
+
+    /// Lazily classifies every record of `records`, in order.
+    pub fn decisions<I>(&mut self, records: I) -> Decisions<'_, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        Decisions {
+            dedup: self,
+            records: records.into_iter(),
+        }
+    }
+
+    /// Forgets every fingerprint; record ids keep counting up.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.by_id.clear();
+        self.retention.clear();
+    }
+}
+
+/// Iterator returned by [`Deduplicator::decisions`].
+#[derive(Debug)]
+pub struct Decisions<'a, I> {
+    dedup: &'a mut Deduplicator,
+    records: I,
+}
+
+impl<I> Iterator for Decisions<'_, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = Decision;
+
+    fn next(&mut self) -> Option<Decision> {
+        let record = self.records.next()?;
+        Some(self.dedup.check(record.as_ref()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.records.size_hint()
+    }
+}
+
+/// Classifies records against the fingerprints in a [`FingerprintStore`].
+///
+/// Every unique record is stored under its id in band 0, bucketed by its
+/// fingerprint; the store should hold nothing else. Nothing is ever
+/// evicted, and the decisions of a deduplicator reopened over the same
+/// store carry on where the last one stopped.
+#[derive(Debug, Clone)]
+pub struct StoreDeduplicator<S> {
+    config: TbfConfig,
+    store: S,
+}
+
+impl<S: FingerprintStore> StoreDeduplicator<S> {
+    /// Classifies records collapsed with `config` against `store`.
+    pub fn new(config: TbfConfig, store: S) -> Self {
+        Self { config, store }
+    }
+
+    /// The configuration records are collapsed with.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// The backing store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Gives the backing store back.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Classifies the record with id `id`: a duplicate of the stored record
+    /// with the smallest id collapsing like it, or else unique and stored.
+    pub fn check(&mut self, id: u64, record: &[u8]) -> Result<Decision, S::Error> {
+        let bucket = self.bucket(record);
+        let fingerprint = bucket.to_le_bytes();
+        for stored in self.store.scan_band(0, bucket)? {
+            if self.store.get(stored)?.as_deref() == Some(&fingerprint[..]) {
+                crate::metrics::decided(true);
+                return Ok(Decision::DuplicateOf(stored));
+            }
+        }
+        self.store.put(id, &fingerprint, &[bucket])?;
+        crate::metrics::decided(false);
+        Ok(Decision::Unique(id))
+    }
+
+    /// Forgets the record stored under `id`; returns whether there was one.
+    pub fn forget(&mut self, id: u64) -> Result<bool, S::Error> {
+        self.store.delete(id)
+    }
+
+    /// The record length and chunk-level bitmask, packed into one key.
+    fn bucket(&self, record: &[u8]) -> u64 {
+        let levels = self
+            .config
+            .chunk_levels(record)
+            .iter()
+            .fold(0u64, |acc, &level| acc << 1 | u64::from(level));
+        (record.len() as u64) << 8 | levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 16-byte record whose first chunk is set iff `set`, plus `noise` in
+    /// the last byte (within tolerance).
+    fn record(set: bool, noise: u8) -> [u8; 16] {
+        let mut record = [0u8; 16];
+        record[0] = if set { 0xFF } else { 0x00 };
+        record[1] = 0xF0 * u8::from(set);
+        record[15] = noise & 1;
+        record
+    }
+
+    #[test]
+    fn test_noisy_copies_are_duplicates() {
+        let mut dedup = Deduplicator::new(TbfConfig::new(0.125), 16, Eviction::Oldest).unwrap();
+        let records = [
+            record(true, 0),
+            record(false, 0),
+            record(true, 1),
+            record(false, 1),
+        ];
+        let decisions: Vec<_> = dedup.decisions(&records).collect();
+        assert_eq!(
+            decisions,
+            [
+                Decision::Unique(0),
+                Decision::Unique(1),
+                Decision::DuplicateOf(0),
+                Decision::DuplicateOf(1),
+            ]
+        );
+        assert_eq!(dedup.len(), 2);
+        assert_eq!(dedup.check(&[0xAB; 3]), Decision::Unique(4));
+    }
+
+    #[test]
+    fn test_different_lengths_never_match() {
+        let mut dedup = Deduplicator::new(TbfConfig::default(), 4, Eviction::Oldest).unwrap();
+        assert_eq!(dedup.check(&[0; 16]), Decision::Unique(0));
+        assert_eq!(dedup.check(&[0; 17]), Decision::Unique(1));
+    }
+
+    #[test]
+    fn test_eviction_policies() {
+        // Capacity 2; A, B, A again, then C forces an eviction.
+        let a = [0xFFu8; 16];
+        let b = [0x00u8; 16];
+        let mut c = [0x00u8; 16];
+        c[0] = 0xFF;
+
+        let mut oldest = Deduplicator::new(TbfConfig::default(), 2, Eviction::Oldest).unwrap();
+        for r in [&a, &b, &a, &c] {
+            oldest.check(r);
+        }
+        assert_eq!(oldest.evicted(), 1);
+        assert_eq!(oldest.check(&a), Decision::Unique(4)); // A was the oldest.
+        assert_eq!(oldest.check(&c), Decision::DuplicateOf(3));
+
+        let mut lru =
+            Deduplicator::new(TbfConfig::default(), 2, Eviction::LeastRecentlyUsed).unwrap();
+        for r in [&a, &b, &a, &c] {
+            lru.check(r);
+        }
+        assert_eq!(lru.check(&a), Decision::DuplicateOf(0)); // B went instead.
+        assert_eq!(lru.check(&b), Decision::Unique(5));
+        assert_eq!(lru.len(), 2);
+    }
+
+    #[test]
+    fn test_least_frequently_used_and_ttl() {
+        let a = [0xFFu8; 16];
+        let b = [0x00u8; 16];
+        let mut c = [0x00u8; 16];
+        c[..2].fill(0xFF);
+
+        // A recurs twice, B is used last: B goes to make room for C.
+        let mut lfu =
+            Deduplicator::new(TbfConfig::default(), 2, Eviction::LeastFrequentlyUsed).unwrap();
+        for r in [&a, &a, &a, &b, &c] {
+            lfu.check(r);
+        }
+        assert_eq!(lfu.check(&a), Decision::DuplicateOf(0));
+        assert_eq!(lfu.check(&b), Decision::Unique(6));
+        assert_eq!(lfu.evicted(), 2); // B, then C.
+
+        let mut ttl = Deduplicator::new(TbfConfig::default(), 16, Eviction::Oldest)
+            .unwrap()
+            .with_ttl(10);
+        assert_eq!(ttl.ttl(), Some(10));
+        assert_eq!(ttl.check_at(&a, 0), Decision::Unique(0));
+        assert_eq!(ttl.check_at(&b, 5), Decision::Unique(1));
+        // Seeing A again keeps it alive for another 10.
+        assert_eq!(ttl.check_at(&a, 9), Decision::DuplicateOf(0));
+        assert_eq!(ttl.check_at(&c, 15), Decision::Unique(3));
+        assert_eq!((ttl.expired(), ttl.len()), (1, 2)); // B expired at 15.
+        assert_eq!(ttl.check(&a), Decision::DuplicateOf(0)); // Still at 15.
+        assert_eq!(ttl.check_at(&b, 3), Decision::Unique(5)); // Time stays at 15.
+        assert_eq!(ttl.expire(24), 0);
+        assert_eq!(ttl.expire(25), 3);
+        assert!(ttl.is_empty());
+        assert_eq!(ttl.evicted(), 0);
+    }
+
+    #[test]
+    fn test_store_deduplicator() {
+        use crate::store::MemoryStore;
+
+        let mut dedup = StoreDeduplicator::new(TbfConfig::new(0.125), MemoryStore::new());
+        assert_eq!(dedup.check(10, &record(true, 0)), Ok(Decision::Unique(10)));
+        assert_eq!(dedup.check(11, &record(false, 0)), Ok(Decision::Unique(11)));
+        assert_eq!(
+            dedup.check(12, &record(true, 1)),
+            Ok(Decision::DuplicateOf(10))
+        );
+        assert_eq!(dedup.check(13, &[0; 17]), Ok(Decision::Unique(13)));
+
+        // A reopened deduplicator remembers; forgotten records are unique again.
+        let mut reopened = StoreDeduplicator::new(TbfConfig::new(0.125), dedup.into_store());
+        assert_eq!(
+            reopened.check(14, &record(false, 1)),
+            Ok(Decision::DuplicateOf(11))
+        );
+        assert_eq!(reopened.forget(10), Ok(true));
+        assert_eq!(
+            reopened.check(15, &record(true, 0)),
+            Ok(Decision::Unique(15))
+        );
+        assert_eq!(reopened.store().len(), 3);
+    }
+
+    #[test]
+    fn test_zero_capacity_rejected() {
+        assert!(matches!(
+            Deduplicator::new(TbfConfig::default(), 0, Eviction::Oldest),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
+}
@@ -0,0 +1,570 @@
+//! Digests of data assembled from independently collapsed parts.
+//!
+//! A record, a file read in blocks or a message built from fields can be
+//! fingerprinted by collapsing each part on its own and then [`compose`]-ing
+//! the results. The composition keeps every part's digest and its
+//! boundaries, so composed digests are equal exactly when their parts are
+//! equal one by one: a noisy part still matches as long as it collapses
+//! like the original.
+//!
+//! # Algebraic properties
+//! For digests `a`, `b`, `c` collapsed under the same configuration:
+//!
+//! - **Order-sensitive**: `compose(&[a, b])` differs from
+//!   `compose(&[b, a])` unless `a == b`.
+//! - **Associative**: `compose(&[compose(&[a, b])?, c])`,
+//!   `compose(&[a, compose(&[b, c])?])` and `compose(&[a, b, c])` are
+//!   equal, so parts may be grouped in any way before composing.
+//! - **Single part**: `compose(&[a])` equals `a`.
+//! - **Not a collapse of the concatenation**: `compose` of the digests of
+//!   `x` and `y` generally differs from the digest of `x ++ y`, since the
+//!   chunking depends on each part's length. Compare composed digests
+//!   only with digests composed from the same split.
+//! - **Compatible parameters**: parts collapsed under different
+//!   [`TbfConfig`]s (tolerance, bit order or transform) cannot be composed.
+//!
+//! # Syncing
+//! A large composed digest, such as one part per file of a tree, changes a
+//! few parts at a time. [`diff`] lists only the parts that changed, by
+//! index, length and chunk levels (one byte, whatever the part's length),
+//! and [`apply`] rebuilds the new digest from the old one and that
+//! [`DigestDelta`], so an agent can keep a server's copy current by sending
+//! deltas. A delta names its base by a short hash and applies to nothing
+//! else.
+
+use crate::crypto::ct_eq;
+use crate::crypto::sha256::Sha256;
+use crate::envelope::{DigestEnvelope, tag_encoded};
+use crate::{ChunkLayout, Error, TbfConfig};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Domain separation for the hash naming a delta's base.
+const BASE_INFO: &[u8] = b"pensieve digest delta base";
+
+/// A collapsed digest, possibly composed of several parts, with the
+/// configuration that produced it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollapsedDigest {
+    config: TbfConfig,
+    bytes: Vec<u8>,    // The part digests, concatenated.
+    parts: Vec<usize>, // Length of each part, in order; they sum to `bytes.len()`.
+    levels: Vec<u8>,   // Chunk-level bitmask of each part, chunk 0 most significant.
+}
+
+impl CollapsedDigest {
+    /// The digest of `input` under `config`, as a single part.
+    pub fn collapse(input: &[u8], config: &TbfConfig) -> Self {
+        let levels = config.chunk_levels(input);
+        Self {
+            config: *config,
+            bytes: config.transform().apply(&levels, input.len()),
+            parts: vec![input.len()],
+            levels: vec![pack(&levels)],
+        }
+    }
+
+    /// The configuration every part was collapsed with.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// The part digests, concatenated.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The digest of each part, in order.
+    pub fn parts(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.parts.iter().scan(0, |start, &len| {
+            let part = &self.bytes[*start..*start + len];
+            *start += len;
+            Some(part)
+        })
+    }
+
+    /// Number of parts; 1 for a digest that was not composed.
+    pub fn part_count(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// HMAC-SHA256 under `key` over the digest of every part and the
+    /// parameters it was collapsed with, in the format of
+    /// [`DigestEnvelope`]: a single-part
+    /// digest has the [tag](crate::envelope::DigestEnvelope::tag) of its
+    /// envelope. Store it next to the digest, and
+    /// [`verify_tag`](Self::verify_tag) before trusting a digest read back
+    /// from storage others can write to.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::digest::{CollapsedDigest, compose};
+    ///
+    /// let key = b"server-side tag key";
+    /// let config = TbfConfig::new(0.125);
+    /// let fields = [[0x00u8; 16], [0xFF; 16]].map(|f| CollapsedDigest::collapse(&f, &config));
+    /// let digest = compose(&fields).unwrap();
+    /// let tag = digest.tag(key);
+    /// assert!(digest.verify_tag(key, &tag));
+    ///
+    /// // Other parts, or the same bytes under other parameters, fail.
+    /// let swapped = compose(&[fields[1].clone(), fields[0].clone()]).unwrap();
+    /// assert!(!swapped.verify_tag(key, &tag));
+    /// assert!(!digest.verify_tag(b"another key", &tag));
+    /// ```
+    pub fn tag(&self, key: &[u8]) -> [u8; 32] {
+        tag_encoded(
+            key,
+            self.parts()
+                .map(|part| DigestEnvelope::from_digest(&self.config, part.to_vec()).encode()),
+        )
+    }
+
+    /// Whether `tag` is the [`tag`](Self::tag) of the digest under `key`.
+    /// The comparison is constant-time.
+    pub fn verify_tag(&self, key: &[u8], tag: &[u8]) -> bool {
+        ct_eq(&self.tag(key), tag)
+    }
+}
+
+/// Composes `parts`, in order, into one digest.
+///
+/// See the [module documentation](self) for the properties of the result.
+/// Fails with [`Error::InvalidParameter`] if `parts` is empty or its
+/// digests were collapsed under different configurations.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::digest::{CollapsedDigest, compose};
+///
+/// let config = TbfConfig::new(0.125);
+/// let header = [0xF0u8; 8];
+/// let (body, mut noisy_body) = ([0x0Fu8; 16], [0x0Fu8; 16]);
+/// noisy_body[3] ^= 0x01;
+/// let digest = |body: &[u8]| {
+///     let parts = [
+///         CollapsedDigest::collapse(&header, &config),
+///         CollapsedDigest::collapse(body, &config),
+///     ];
+///     compose(&parts).unwrap()
+/// };
+/// assert_eq!(digest(&body), digest(&noisy_body));
+/// ```
+pub fn compose(parts: &[CollapsedDigest]) -> Result<CollapsedDigest, Error> {
+    let (first, rest) = parts
+        .split_first()
+        .ok_or(Error::InvalidParameter("nothing to compose"))?;
+    if rest.iter().any(|part| part.config != first.config) {
+        return Err(Error::InvalidParameter(
+            "parts were collapsed under different configurations",
+        ));
+    }
+    Ok(CollapsedDigest {
+        config: first.config,
+        bytes: parts
+            .iter()
+            .flat_map(|part| part.bytes.iter().copied())
+            .collect(),
+        parts: parts
+            .iter()
+            .flat_map(|part| part.parts.iter().copied())
+            .collect(),
+        levels: parts
+            .iter()
+            .flat_map(|part| part.levels.iter().copied())
+            .collect(),
+    })
+}
+
+/// The parts that changed from one digest to another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestDelta {
+    base: [u8; 8],                // Names the digest the delta applies to.
+    part_count: u32,              // Parts of the new digest.
+    changes: Vec<(u32, u64, u8)>, // Index, length and levels of each changed part, by index.
+}
+
+impl DigestDelta {
+    /// Length of the serialized header: base, part count and change count.
+    const HEADER_LEN: usize = 16;
+    /// Length of each serialized change.
+    const CHANGE_LEN: usize = 13;
+
+    /// Number of parts that changed or were added.
+    pub fn change_count(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Whether applying the delta leaves the parts unchanged (it may still
+    /// drop parts from the end).
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Serializes as `base (8 bytes) || part_count (u32) || change_count
+    /// (u32)` followed by every change as `index (u32) || length (u64) ||
+    /// levels (u8)`. All integers are little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::HEADER_LEN + self.changes.len() * Self::CHANGE_LEN);
+        out.extend_from_slice(&self.base);
+        out.extend_from_slice(&self.part_count.to_le_bytes());
+        out.extend_from_slice(&(self.changes.len() as u32).to_le_bytes());
+        for &(index, len, levels) in &self.changes {
+            out.extend_from_slice(&index.to_le_bytes());
+            out.extend_from_slice(&len.to_le_bytes());
+            out.push(levels);
+        }
+        out
+    }
+
+    /// Parses the output of [`DigestDelta::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidLength`] if `bytes` is truncated or too
+    /// long, and with [`Error::InvalidParameter`] unless the changes are in
+    /// increasing order of index and within the part count.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < Self::HEADER_LEN {
+            return Err(Error::InvalidLength {
+                expected: Self::HEADER_LEN,
+                found: bytes.len(),
+            });
+        }
+        let base = bytes[0..8].try_into().expect("8 bytes");
+        let part_count = u32::from_le_bytes(bytes[8..12].try_into().expect("4 bytes"));
+        let count = u32::from_le_bytes(bytes[12..16].try_into().expect("4 bytes")) as usize;
+        let expected = count
+            .checked_mul(Self::CHANGE_LEN)
+            .and_then(|n| n.checked_add(Self::HEADER_LEN))
+            .ok_or(Error::InvalidParameter("change count overflows"))?;
+        if bytes.len() != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let changes: Vec<(u32, u64, u8)> = bytes[Self::HEADER_LEN..]
+            .chunks_exact(Self::CHANGE_LEN)
+            .map(|change| {
+                (
+                    u32::from_le_bytes(change[0..4].try_into().expect("4 bytes")),
+                    u64::from_le_bytes(change[4..12].try_into().expect("8 bytes")),
+                    change[12],
+                )
+            })
+            .collect();
+        if changes.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+            || changes
+                .last()
+                .is_some_and(|&(index, ..)| index >= part_count)
+        {
+            return Err(Error::InvalidParameter(
+                "changes must be in increasing order of index within the part count",
+            ));
+        }
+        Ok(Self {
+            base,
+            part_count,
+            changes,
+        })
+    }
+}
+
+/// The delta that turns `a` into `b`: every part of `b` that `a` lacks or
+/// that differs from `a`'s part at the same index.
+///
+/// Fails with [`Error::InvalidParameter`] if the digests were collapsed
+/// under different configurations or `b` has more than `u32::MAX` parts.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::digest::{CollapsedDigest, DigestDelta, apply, compose, diff};
+///
+/// let config = TbfConfig::new(0.125);
+/// let files: Vec<Vec<u8>> = (0..100u8).map(|i| vec![i; 64]).collect();
+/// let digest = |files: &[Vec<u8>]| {
+///     let parts: Vec<_> = files
+///         .iter()
+///         .map(|file| CollapsedDigest::collapse(file, &config))
+///         .collect();
+///     compose(&parts).unwrap()
+/// };
+/// let old = digest(&files);
+/// let mut edited = files.clone();
+/// edited[42] = vec![0xFF; 80];
+/// let new = digest(&edited);
+///
+/// // The agent sends 29 bytes instead of the whole new digest.
+/// let delta = diff(&old, &new).unwrap();
+/// assert_eq!(delta.change_count(), 1);
+/// let sent = delta.to_bytes();
+/// assert_eq!(sent.len(), 29);
+///
+/// // The server holds `old` and rebuilds `new`.
+/// let received = DigestDelta::from_bytes(&sent).unwrap();
+/// assert_eq!(apply(&old, &received).unwrap(), new);
+/// ```
+pub fn diff(a: &CollapsedDigest, b: &CollapsedDigest) -> Result<DigestDelta, Error> {
+    if a.config != b.config {
+        return Err(Error::InvalidParameter(
+            "digests were collapsed under different configurations",
+        ));
+    }
+    let part_count = u32::try_from(b.part_count())
+        .map_err(|_| Error::InvalidParameter("too many parts for a delta"))?;
+    let old = |i: usize| Some((*a.parts.get(i)?, a.levels[i]));
+    let changes = b
+        .parts
+        .iter()
+        .zip(&b.levels)
+        .enumerate()
+        .filter(|&(i, (&len, &levels))| old(i) != Some((len, levels)))
+        .map(|(i, (&len, &levels))| (i as u32, len as u64, levels))
+        .collect();
+    Ok(DigestDelta {
+        base: base(a),
+        part_count,
+        changes,
+    })
+}
+
+/// The digest `delta` was computed to: `a` with its parts replaced, added
+/// or dropped as the delta lists them.
+///
+/// Fails with [`Error::InvalidParameter`] if `delta` was not computed from
+/// `a`, or was not produced by [`diff`] and adds a part without listing it
+/// or lists levels beyond a part's chunks.
+pub fn apply(a: &CollapsedDigest, delta: &DigestDelta) -> Result<CollapsedDigest, Error> {
+    if delta.base != base(a) {
+        return Err(Error::InvalidParameter(
+            "delta was computed from a different digest",
+        ));
+    }
+    let part_count = delta.part_count as usize;
+    let mut changes = delta.changes.iter().peekable();
+    let mut old_parts = a.parts();
+    let mut out = CollapsedDigest {
+        config: a.config,
+        bytes: Vec::with_capacity(a.bytes.len()),
+        parts: Vec::with_capacity(part_count),
+        levels: Vec::with_capacity(part_count),
+    };
+    for i in 0..part_count {
+        let old = old_parts.next();
+        match changes.next_if(|&&(index, ..)| index as usize == i) {
+            Some(&(_, len, levels)) => {
+                let len = usize::try_from(len)
+                    .map_err(|_| Error::InvalidParameter("part is too long"))?;
+                let chunks = ChunkLayout::new(len, a.config.tolerance())
+                    .map_or(0, |layout| layout.chunk_count());
+                if u32::from(levels) >> chunks != 0 {
+                    return Err(Error::InvalidParameter(
+                        "levels exceed the chunks of the part",
+                    ));
+                }
+                let unpacked: Vec<u8> = (0..chunks).rev().map(|j| levels >> j & 1).collect();
+                out.bytes.extend(a.config.transform().apply(&unpacked, len));
+                out.parts.push(len);
+                out.levels.push(levels);
+            }
+            None => {
+                let old = old.ok_or(Error::InvalidParameter("delta omits an added part"))?;
+                out.bytes.extend_from_slice(old);
+                out.parts.push(old.len());
+                out.levels.push(a.levels[i]);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Packs 0/1 chunk levels into a bitmask, chunk 0 in the most significant
+/// of the low `levels.len()` bits.
+fn pack(levels: &[u8]) -> u8 {
+    levels.iter().fold(0, |mask, &level| mask << 1 | level)
+}
+
+/// Names `digest` as the base of a delta: a hash of its part lengths and
+/// levels, which determine its bytes under its configuration.
+fn base(digest: &CollapsedDigest) -> [u8; 8] {
+    let mut summary = Vec::with_capacity(8 + 9 * digest.parts.len());
+    summary.extend_from_slice(&(digest.parts.len() as u64).to_le_bytes());
+    for (&len, &levels) in digest.parts.iter().zip(&digest.levels) {
+        summary.extend_from_slice(&(len as u64).to_le_bytes());
+        summary.push(levels);
+    }
+    let hash = Sha256::digest(&[BASE_INFO, &summary]);
+    hash[..8].try_into().expect("8 bytes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BitOrder;
+
+    fn digests(config: &TbfConfig) -> [CollapsedDigest; 3] {
+        [
+            CollapsedDigest::collapse(&[0xFF; 16], config),
+            CollapsedDigest::collapse(&[0x01, 0x02, 0x03], config),
+            CollapsedDigest::collapse(&[], config),
+        ]
+    }
+
+    #[test]
+    fn test_composition_laws() {
+        let config = TbfConfig::new(0.125);
+        let [a, b, c] = digests(&config);
+        let abc = compose(&[a.clone(), b.clone(), c.clone()]).unwrap();
+        let left = compose(&[compose(&[a.clone(), b.clone()]).unwrap(), c.clone()]).unwrap();
+        let right = compose(&[a.clone(), compose(&[b.clone(), c.clone()]).unwrap()]).unwrap();
+        assert_eq!(abc, left);
+        assert_eq!(abc, right);
+        assert_eq!(compose(core::slice::from_ref(&a)).unwrap(), a);
+        assert_ne!(
+            compose(&[a.clone(), b.clone()]),
+            compose(&[b.clone(), a.clone()])
+        );
+
+        assert_eq!(abc.part_count(), 3);
+        let parts: Vec<&[u8]> = abc.parts().collect();
+        assert_eq!(parts, [a.as_bytes(), b.as_bytes(), c.as_bytes()]);
+        assert_eq!(abc.as_bytes().len(), 19);
+
+        let mut joined = config.collapse(&[0xFF; 16]);
+        joined.extend(config.collapse(&[1, 2, 3]));
+        assert_eq!(compose(&[a, b]).unwrap().as_bytes(), joined);
+    }
+
+    #[test]
+    fn test_incompatible_parts() {
+        let config = TbfConfig::new(0.125);
+        let [a, ..] = digests(&config);
+        for other in [
+            TbfConfig::new(0.2),
+            config.with_bit_order(BitOrder::LsbFirst),
+        ] {
+            let b = CollapsedDigest::collapse(&[0xFF; 16], &other);
+            assert_eq!(
+                compose(&[a.clone(), b]),
+                Err(Error::InvalidParameter(
+                    "parts were collapsed under different configurations"
+                ))
+            );
+        }
+        assert!(compose(&[]).is_err());
+    }
+
+    fn composed(inputs: &[&[u8]], config: &TbfConfig) -> CollapsedDigest {
+        let parts: Vec<CollapsedDigest> = inputs
+            .iter()
+            .map(|input| CollapsedDigest::collapse(input, config))
+            .collect();
+        compose(&parts).unwrap()
+    }
+
+    #[test]
+    fn test_tags() {
+        let config = TbfConfig::new(0.125);
+        let [a, b, c] = digests(&config);
+        let key = [7u8; 32];
+        assert_eq!(
+            a.tag(&key),
+            DigestEnvelope::seal(&[0xFF; 16], &config).tag(&key)
+        );
+        let abc = compose(&[a.clone(), b.clone(), c.clone()]).unwrap();
+        let tag = abc.tag(&key);
+        assert!(abc.verify_tag(&key, &tag));
+        assert!(!abc.verify_tag(&key, &tag[..31]));
+        // Same bytes, split differently or under other parameters.
+        let [a2, b2, c2] = digests(&config.with_bit_order(BitOrder::LsbFirst));
+        assert_eq!(
+            compose(&[a2.clone(), b2.clone(), c2.clone()])
+                .unwrap()
+                .as_bytes(),
+            abc.as_bytes()
+        );
+        assert!(!compose(&[a2, b2, c2]).unwrap().verify_tag(&key, &tag));
+        assert!(!compose(&[a, b]).unwrap().verify_tag(&key, &tag));
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        for config in [
+            TbfConfig::new(0.125),
+            TbfConfig::new(0.125).with_transform(crate::FinalTransform::Hash),
+        ] {
+            let sparse = [0x11u8; 32];
+            let a = composed(&[&[0xFF; 16], &sparse, &[1, 2, 3], &[]], &config);
+            assert!(diff(&a, &a).unwrap().is_empty());
+
+            // One part changed, one added.
+            let b = composed(
+                &[&[0xFF; 16], &[0x00; 32], &[1, 2, 3], &[], &[0xFF; 24]],
+                &config,
+            );
+            let delta = diff(&a, &b).unwrap();
+            assert_eq!(delta.change_count(), 2);
+            assert_eq!(
+                DigestDelta::from_bytes(&delta.to_bytes()),
+                Ok(delta.clone())
+            );
+            assert_eq!(apply(&a, &delta).unwrap(), b);
+
+            // Parts dropped from the end.
+            let c = composed(&[&[0xFF; 16], &sparse], &config);
+            let delta = diff(&a, &c).unwrap();
+            assert!(delta.is_empty());
+            assert_eq!(apply(&a, &delta).unwrap(), c);
+
+            // Noise the collapse absorbs is not a change.
+            let mut noisy = sparse;
+            noisy[5] ^= 0x01;
+            let d = composed(&[&[0xFF; 16], &noisy, &[1, 2, 3], &[]], &config);
+            assert!(diff(&a, &d).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_invalid_deltas() {
+        let config = TbfConfig::new(0.125);
+        let a = composed(&[&[0xFF; 16], &[1, 2, 3]], &config);
+        let b = composed(&[&[0x00; 16], &[1, 2, 3], &[0xFF; 8]], &config);
+        let delta = diff(&a, &b).unwrap();
+        assert_eq!(
+            apply(&b, &delta),
+            Err(Error::InvalidParameter(
+                "delta was computed from a different digest"
+            ))
+        );
+        let other = CollapsedDigest::collapse(&[0xFF; 16], &TbfConfig::new(0.2));
+        assert!(diff(&a, &other).is_err());
+
+        let bytes = delta.to_bytes();
+        assert_eq!(bytes.len(), 16 + 2 * 13);
+        assert!(DigestDelta::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(DigestDelta::from_bytes(&bytes[..15]).is_err());
+        // Changes out of order.
+        let mut swapped = bytes.clone();
+        swapped[16..29].copy_from_slice(&bytes[29..42]);
+        swapped[29..42].copy_from_slice(&bytes[16..29]);
+        assert!(DigestDelta::from_bytes(&swapped).is_err());
+        // The added part left out.
+        let mut omitted = bytes[..29].to_vec();
+        omitted[12] = 1;
+        let omitted = DigestDelta::from_bytes(&omitted).unwrap();
+        assert_eq!(
+            apply(&a, &omitted),
+            Err(Error::InvalidParameter("delta omits an added part"))
+        );
+        // Levels of a 16-byte part beyond its 8 chunks are impossible, but
+        // a 3-byte part has a single chunk.
+        let mut wide = bytes.clone();
+        wide[20..28].copy_from_slice(&3u64.to_le_bytes());
+        wide[28] = 0b10;
+        let wide = DigestDelta::from_bytes(&wide).unwrap();
+        assert!(apply(&a, &wide).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+//! Hamming distance and population count over byte strings.
+//!
+//! Everything that matches digests (the [`index`](crate::index) structures,
+//! deduplication, clustering) reduces to counting differing bits. These
+//! functions work a 64-bit word at a time and compile to a hardware popcount
+//! where the target has one; [`popcount`], like the collapse, also picks
+//! AVX2, SSE4.2 or NEON at runtime when the `std` feature can detect them
+//! ([`popcount_backend`]).
+//!
+//! They branch on the data (`within` exits early), so use them on public
+//! values such as digests, not on secrets.
+//!
+//! [`lower_bound`] goes the other way: from two digests it bounds the
+//! distance between the inputs they were collapsed from.
+
+use crate::transform::level_index;
+use crate::{ChunkLayout, Error, FinalTransform, TbfConfig, Thresholding};
+
+/// Number of set bits in `bytes`, in constant time.
+pub fn popcount(bytes: &[u8]) -> u32 {
+    crate::popcount::ct_popcount(bytes)
+}
+
+/// The popcount implementation the collapse runs on this CPU: `"avx2"`,
+/// `"sse4.2"` or `"neon"` where the CPU supports them and the `std` feature
+/// is on to detect that, and `"portable"` otherwise.
+pub fn popcount_backend() -> &'static str {
+    crate::popcount::backend().name()
+}
+
+/// Number of bit positions at which `a` and `b` differ.
+///
+/// # Panics
+/// Panics if the slices have different lengths.
+pub fn hamming(a: &[u8], b: &[u8]) -> u32 {
+    let mut count = 0;
+    for_each_word_xor(a, b, |xor| {
+        count += xor.count_ones();
+        true
+    });
+    count
+}
+
+/// [`hamming`] divided by the number of bits compared, in `0.0..=1.0`
+/// (`0.0` for empty slices).
+///
+/// # Panics
+/// Panics if the slices have different lengths.
+pub fn normalized(a: &[u8], b: &[u8]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 0.0;
+    }
+    f64::from(hamming(a, b)) / (a.len() as f64 * 8.0)
+}
+
+/// Whether `a` and `b` differ in at most `max_bits` positions, stopping as
+/// soon as the answer is known to be `false`.
+///
+/// # Panics
+/// Panics if the slices have different lengths.
+pub fn within(a: &[u8], b: &[u8], max_bits: u32) -> bool {
+    let mut count = 0u32;
+    for_each_word_xor(a, b, |xor| {
+        count += xor.count_ones();
+        count <= max_bits
+    })
+}
+
+/// A provable lower bound on the Hamming distance between two inputs,
+/// computed from their digests `a` and `b` under `config` alone.
+///
+/// A chunk on which the digests disagree held at least the threshold of
+/// set bits in one input and fewer in the other, so the inputs differ in at
+/// least one of its bits. Chunks are disjoint, so the bound is the number
+/// of disagreeing chunks. An index can drop a candidate whose bound exceeds
+/// its search radius without fetching the candidate's input.
+///
+/// [`FinalTransform::KeyedPrf`] and [`FinalTransform::Hash`] hide the
+/// chunk levels, and under [`Thresholding::Mean`] a chunk's level also
+/// depends on the bits outside it; for them the bound is 1 if the digests
+/// differ and 0 otherwise.
+///
+/// Fails with [`Error::InvalidLength`] if the digests differ in length and
+/// with [`Error::InvalidParameter`] if either is not a digest `config` can
+/// produce.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::distance::lower_bound;
+///
+/// let config = TbfConfig::new(0.125);
+/// let (a, b) = ([0xFFu8; 16], [0u8; 16]);
+/// // Every one of the 8 chunks disagrees.
+/// # #[cfg(feature = "alloc")]
+/// assert_eq!(lower_bound(&config.collapse(&a), &config.collapse(&b), &config), Ok(8));
+/// ```
+pub fn lower_bound(a: &[u8], b: &[u8], config: &TbfConfig) -> Result<u32, Error> {
+    if a.len() != b.len() {
+        return Err(Error::InvalidLength {
+            expected: a.len(),
+            found: b.len(),
+        });
+    }
+    let mask = |i: usize| match config.transform() {
+        FinalTransform::XorMask { base } => Some(base.wrapping_add(i as u8)),
+        FinalTransform::Identity => Some(0),
+        FinalTransform::KeyedPrf { .. } | FinalTransform::Hash => None,
+    };
+    let Some(chunks) = ChunkLayout::new(a.len(), config.tolerance()).map(|l| l.chunk_count())
+    else {
+        return Ok(0); // Both empty.
+    };
+    if mask(0).is_none() {
+        return Ok(u32::from(a != b));
+    }
+
+    // Every bit of the unmasked digest is the level of one chunk; a digest
+    // under this configuration never disagrees with itself.
+    let levels = |digest: &[u8]| -> Result<[Option<u8>; 8], Error> {
+        let mut levels = [None; 8]; // At most 8 chunks.
+        for (i, &byte) in digest.iter().enumerate() {
+            let byte = byte ^ mask(i).expect("level-preserving");
+            for k in 0..8 {
+                let level = &mut levels[level_index(chunks, digest.len(), i, k)];
+                let bit = byte >> (7 - k) & 1;
+                if level.replace(bit).is_some_and(|seen| seen != bit) {
+                    return Err(Error::InvalidParameter(
+                        "not a digest under this configuration",
+                    ));
+                }
+            }
+        }
+        Ok(levels)
+    };
+    let disagreeing = levels(a)?
+        .into_iter()
+        .zip(levels(b)?)
+        .filter(|(level_a, level_b)| level_a != level_b)
+        .count() as u32;
+    Ok(match config.thresholding() {
+        Thresholding::Mean => disagreeing.min(1),
+        _ => disagreeing,
+    })
+}
+
+/// Feeds the XOR of `a` and `b`, a word at a time, to `f` until it returns
+/// `false`; returns whether it never did.
+fn for_each_word_xor(a: &[u8], b: &[u8], mut f: impl FnMut(u64) -> bool) -> bool {
+    assert_eq!(
+        a.len(),
+        b.len(),
+        "distance between slices of different lengths"
+    );
+    let mut wa = a.chunks_exact(8);
+    let mut wb = b.chunks_exact(8);
+    for (x, y) in wa.by_ref().zip(wb.by_ref()) {
+        let x = u64::from_ne_bytes(x.try_into().expect("8 bytes"));
+        let y = u64::from_ne_bytes(y.try_into().expect("8 bytes"));
+        if !f(x ^ y) {
+            return false;
+        }
+    }
+    let mut tail = [0u8; 8];
+    for (t, (x, y)) in tail
+        .iter_mut()
+        .zip(wa.remainder().iter().zip(wb.remainder()))
+    {
+        *t = x ^ y;
+    }
+    f(u64::from_ne_bytes(tail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    fn bitwise(a: &[u8], b: &[u8]) -> u32 {
+        a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+    }
+
+    #[test]
+    fn test_hamming_matches_bytewise_for_all_tail_lengths() {
+        let mut rng = seeded(1);
+        for len in 0..40 {
+            let (mut a, mut b) = (vec![0u8; len], vec![0u8; len]);
+            rng(&mut a);
+            rng(&mut b);
+            assert_eq!(hamming(&a, &b), bitwise(&a, &b));
+            assert_eq!(popcount(&a), bitwise(&a, &vec![0; len]));
+        }
+    }
+
+    #[test]
+    fn test_within_and_normalized() {
+        let a = [0xFFu8; 10];
+        let mut b = a;
+        b[9] = 0xF0; // 4 bits differ, all in the tail
+        assert!(within(&a, &b, 4));
+        assert!(!within(&a, &b, 3));
+        assert_eq!(normalized(&a, &b), 4.0 / 80.0);
+        assert_eq!(normalized(&[], &[]), 0.0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_lower_bound_never_exceeds_distance() {
+        let mut rng = seeded(8);
+        for transform in [FinalTransform::default(), FinalTransform::Identity] {
+            let config = TbfConfig::new(0.125).with_transform(transform);
+            for len in [1usize, 2, 7, 16, 50] {
+                for _ in 0..30 {
+                    let (mut a, mut b) = (vec![0u8; len], vec![0u8; len]);
+                    rng(&mut a);
+                    rng(&mut b);
+                    a.iter_mut().for_each(|x| *x &= 0x11);
+                    b.iter_mut().for_each(|x| *x &= 0x18);
+                    let (da, db) = (config.collapse(&a), config.collapse(&b));
+                    let bound = lower_bound(&da, &db, &config).unwrap();
+                    assert!(bound <= hamming(&a, &b), "len {len}");
+                    assert_eq!(bound == 0, da == db);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_lower_bound_is_tight_and_checks_digests() {
+        let config = TbfConfig::new(0.125);
+        // Thresholds of 2 in 16-bit chunks; one flip moves chunk 0 and 3 across.
+        let mut a = [0u8; 16];
+        a[0] = 0b11;
+        a[6] = 0b11;
+        let mut b = a;
+        b[0] = 0b01;
+        b[6] = 0b01;
+        let (da, db) = (config.collapse(&a), config.collapse(&b));
+        assert_eq!(lower_bound(&da, &db, &config), Ok(2));
+        assert_eq!(hamming(&a, &b), 2);
+
+        // A byte has eight 1-bit chunks, packed into its digest.
+        let (one, other) = (config.collapse(&[0b1010_0000]), config.collapse(&[0]));
+        assert_eq!(lower_bound(&one, &other, &config), Ok(2));
+
+        let parity = config.with_thresholding(Thresholding::Parity);
+        let bound = lower_bound(&parity.collapse(&a), &parity.collapse(&b), &parity);
+        assert_eq!(bound, Ok(2));
+        // Filling chunk 1 raises the mean past chunks 0 and 3 as well.
+        let mean = config.with_thresholding(Thresholding::Mean);
+        let mut c = a;
+        c[2..4].fill(0xFF);
+        let (ma, mc) = (mean.collapse(&a), mean.collapse(&c));
+        assert_eq!(lower_bound(&ma, &mc, &config), Ok(3));
+        assert_eq!(lower_bound(&ma, &mc, &mean), Ok(1));
+
+        let hashed = config.with_transform(FinalTransform::Hash);
+        let bound = lower_bound(&hashed.collapse(&a), &hashed.collapse(&b), &hashed);
+        assert_eq!(bound, Ok(1));
+        assert_eq!(lower_bound(&[], &[], &config), Ok(0));
+        assert!(lower_bound(&da, &db[..15], &config).is_err());
+        let mut forged = da.clone();
+        forged[8] ^= 0xFF; // Byte 8 repeats chunk 0.
+        assert!(lower_bound(&forged, &db, &config).is_err());
+        forged[8] ^= 0x01;
+        assert!(lower_bound(&forged, &db, &config).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "different lengths")]
+    fn test_length_mismatch_panics() {
+        hamming(&[0, 1], &[0]);
+    }
+}
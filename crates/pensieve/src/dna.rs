@@ -0,0 +1,402 @@
+//! Approximate matching of nucleotide sequences.
+//!
+//! Reads of the same DNA differ by sequencing errors, mostly
+//! substitutions of single bases, and come from either strand. This
+//! front-end [`encode`]s sequences at two bits per base (A, C, G, T as 0
+//! to 3, so a base and its complement add up to 3), where a substitution
+//! flips one or two bits, and canonicalizes every sequence to the smaller
+//! encoding of itself and its [`reverse_complement`], so both strands look
+//! alike:
+//! - [`fingerprint`] collapses the canonical encoding of a whole read, for
+//!   deduplicating or clustering reads of one length;
+//! - [`kmers`] shingles a sequence into canonical k-mers, for set
+//!   similarity;
+//! - a [`KmerIndex`] stores every k-mer of reference sequences in a
+//!   [`BkTree`] and finds the positions a k-base read matches with at most
+//!   a bounded rate of substitutions, on either strand.
+//!
+//! Bases are ASCII letters, in either case; anything else (such as `N`)
+//! is rejected. Insertions and deletions shift every later base and are
+//! not tolerated. Sequences are public data.
+
+use crate::index::BkTree;
+use crate::{Error, TbfConfig};
+use alloc::vec::Vec;
+
+/// The 2-bit code of `base`.
+fn code(base: u8) -> Result<u8, Error> {
+    match base.to_ascii_uppercase() {
+        b'A' => Ok(0),
+        b'C' => Ok(1),
+        b'G' => Ok(2),
+        b'T' => Ok(3),
+        _ => Err(Error::InvalidParameter("not a nucleotide (A, C, G or T)")),
+    }
+}
+
+/// Packs `sequence` at two bits per base, the first base in the top bits
+/// of the first byte, the last byte padded with zero bits.
+///
+/// Fails with [`Error::InvalidParameter`] on anything but A, C, G and T.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::dna::encode;
+///
+/// assert_eq!(encode(b"ACGTtg").unwrap(), [0b00_01_10_11, 0b11_10_00_00]);
+/// assert!(encode(b"ACNT").is_err());
+/// ```
+pub fn encode(sequence: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut out = alloc::vec![0u8; sequence.len().div_ceil(4)];
+    for (i, &base) in sequence.iter().enumerate() {
+        out[i / 4] |= code(base)? << (6 - 2 * (i % 4));
+    }
+    Ok(out)
+}
+
+/// The opposite strand of `sequence`: reversed, with A and T and C and G
+/// swapped, in upper case.
+///
+/// Fails with [`Error::InvalidParameter`] on anything but A, C, G and T.
+pub fn reverse_complement(sequence: &[u8]) -> Result<Vec<u8>, Error> {
+    sequence
+        .iter()
+        .rev()
+        .map(|&base| Ok(b"TGCA"[usize::from(code(base)?)]))
+        .collect()
+}
+
+/// The smaller of the [`encode`]d `sequence` and that of its
+/// [`reverse_complement`].
+pub fn canonical(sequence: &[u8]) -> Result<Vec<u8>, Error> {
+    let forward = encode(sequence)?;
+    let reverse = encode(&reverse_complement(sequence)?)?;
+    Ok(forward.min(reverse))
+}
+
+/// The collapse of the [`canonical`] encoding of `sequence` under
+/// `config`; both strands of a read get the same fingerprint.
+///
+/// A substitution flips one or two bits of the encoding, so reads of one
+/// length that differ in a few bases usually collapse alike. A
+/// substitution can also make the other strand the canonical one, after
+/// which the two reads are compared strand against strand; reads whose
+/// strand is known compare better by the collapse of their [`encode`]d
+/// bases.
+pub fn fingerprint(sequence: &[u8], config: &TbfConfig) -> Result<Vec<u8>, Error> {
+    Ok(config.collapse(&canonical(sequence)?))
+}
+
+/// The canonical code of every k-mer of `sequence`, in order: the k bases
+/// at two bits each, the first base most significant, or those of the
+/// k-mer's reverse complement, whichever is smaller. Sequences shorter
+/// than `k` have none.
+///
+/// Fails with [`Error::InvalidParameter`] unless `k` is in 1..=32, and on
+/// anything but A, C, G and T.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::dna::kmers;
+///
+/// // ACG and its reverse complement CGT share a code.
+/// assert_eq!(kmers(b"ACGT", 3).unwrap(), [0b00_01_10, 0b00_01_10]);
+/// ```
+pub fn kmers(sequence: &[u8], k: usize) -> Result<Vec<u64>, Error> {
+    if !(1..=32).contains(&k) {
+        return Err(Error::InvalidParameter("k-mers must hold 1 to 32 bases"));
+    }
+    let mask = u64::MAX >> (64 - 2 * k);
+    let (mut forward, mut reverse) = (0u64, 0u64);
+    let mut out = Vec::with_capacity(sequence.len().saturating_sub(k - 1));
+    for (i, &base) in sequence.iter().enumerate() {
+        let code = u64::from(code(base)?);
+        forward = (forward << 2 | code) & mask;
+        reverse = reverse >> 2 | (3 - code) << (2 * (k - 1));
+        if i + 1 >= k {
+            out.push(forward.min(reverse));
+        }
+    }
+    Ok(out)
+}
+
+/// Number of bases in which two k-mer codes differ.
+fn substitutions(a: u64, b: u64) -> u32 {
+    let diff = a ^ b;
+    ((diff | diff >> 1) & 0x5555_5555_5555_5555).count_ones()
+}
+
+/// The code of a sequence of at most 32 bases, not canonicalized.
+fn forward_code(sequence: &[u8]) -> Result<u64, Error> {
+    sequence
+        .iter()
+        .try_fold(0u64, |acc, &base| Ok(acc << 2 | u64::from(code(base)?)))
+}
+
+/// The code of the reverse complement of the k-mer coded `forward`.
+fn reverse_code(forward: u64, k: usize) -> u64 {
+    (0..k).fold(0, |reverse, i| {
+        reverse << 2 | (3 - (forward >> (2 * i) & 3))
+    })
+}
+
+/// A position of a reference sequence matching a read, found by
+/// [`KmerIndex::query`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KmerMatch<'a, V> {
+    /// The value the reference sequence was inserted with.
+    pub value: &'a V,
+    /// Offset of the matching k-mer in the reference sequence, in bases.
+    pub offset: usize,
+    /// Bases in which the read differs from the reference there.
+    pub substitutions: u32,
+    /// Whether the read matches the opposite strand of the reference.
+    pub reverse: bool,
+}
+
+/// An index of the k-mers of reference sequences, for looking up reads of
+/// k bases with a bounded substitution rate.
+///
+/// Every k-mer is stored once per occurrence under its canonical code, so
+/// memory grows with the total length of the references.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::dna::KmerIndex;
+///
+/// let mut index = KmerIndex::new(16, 0.125).unwrap();
+/// index.insert(b"TTGACCGTAGGCTAGCATCGATCGGATTACA", "chr1").unwrap();
+/// // Two sequencing errors in 16 bases, read from the opposite strand.
+/// let matches = index.query(b"GCGATCGAAGCTAGCC").unwrap();
+/// assert_eq!(matches.len(), 1);
+/// assert_eq!(*matches[0].value, "chr1");
+/// assert_eq!((matches[0].offset, matches[0].substitutions), (9, 2));
+/// assert!(matches[0].reverse);
+/// ```
+#[derive(Debug, Clone)]
+pub struct KmerIndex<V> {
+    k: usize,
+    max_substitutions: u32,
+    tree: BkTree<(usize, usize, bool)>, // Canonical code -> (sequence, offset, stored reversed).
+    values: Vec<V>,                     // Value of every inserted sequence.
+}
+
+impl<V> KmerIndex<V> {
+    /// An empty index of `k`-mers, matching reads that differ in at most
+    /// `substitution_rate × k` bases (rounded down) from a reference
+    /// k-mer.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless `k` is in 1..=32 and
+    /// `substitution_rate` in 0.0..1.0.
+    pub fn new(k: usize, substitution_rate: f32) -> Result<Self, Error> {
+        if !(1..=32).contains(&k) {
+            return Err(Error::InvalidParameter("k-mers must hold 1 to 32 bases"));
+        }
+        if !(0.0..1.0).contains(&substitution_rate) {
+            return Err(Error::InvalidParameter(
+                "substitution rate must be in 0.0..1.0",
+            ));
+        }
+        Ok(Self {
+            k,
+            max_substitutions: (substitution_rate * k as f32) as u32,
+            tree: BkTree::new(),
+            values: Vec::new(),
+        })
+    }
+
+    /// Bases per k-mer, and per read.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Most substitutions a match may have.
+    pub fn max_substitutions(&self) -> u32 {
+        self.max_substitutions
+    }
+
+    /// Number of stored k-mers.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Whether no k-mer is stored.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Stores every k-mer of `sequence` with `value`; sequences shorter
+    /// than k store none.
+    ///
+    /// Fails with [`Error::InvalidParameter`] on anything but A, C, G and
+    /// T, storing nothing.
+    pub fn insert(&mut self, sequence: &[u8], value: V) -> Result<(), Error> {
+        let codes = kmers(sequence, self.k)?;
+        let id = self.values.len();
+        self.values.push(value);
+        for (offset, (window, canonical)) in sequence.windows(self.k).zip(codes).enumerate() {
+            let reversed = canonical != forward_code(window)?;
+            self.tree
+                .insert(&canonical.to_be_bytes(), (id, offset, reversed))
+                .expect("keys are eight bytes");
+        }
+        Ok(())
+    }
+
+    /// Every stored position that `read` matches with at most
+    /// [`max_substitutions`](Self::max_substitutions) substitutions, on
+    /// either strand: fewest substitutions first, then in order of
+    /// insertion and offset.
+    ///
+    /// Fails with [`Error::InvalidLength`] unless `read` holds k bases, and
+    /// with [`Error::InvalidParameter`] on anything but A, C, G and T.
+    pub fn query(&self, read: &[u8]) -> Result<Vec<KmerMatch<'_, V>>, Error> {
+        if read.len() != self.k {
+            return Err(Error::InvalidLength {
+                expected: self.k,
+                found: read.len(),
+            });
+        }
+        let forward = forward_code(read)?;
+        let reverse = reverse_code(forward, self.k);
+        let mut best: Vec<((usize, usize), u32, bool)> = Vec::new();
+        // A substitution may turn the canonical strand around, so both
+        // strands of the read are looked up.
+        for (code, read_reversed) in [(forward, false), (reverse, true)] {
+            // Every substitution flips at most two bits of the code.
+            let found = self
+                .tree
+                .query_within(&code.to_be_bytes(), 2 * self.max_substitutions)
+                .expect("keys are eight bytes");
+            for found in found {
+                let stored = u64::from_be_bytes(found.key.try_into().expect("eight bytes"));
+                let substitutions = substitutions(code, stored);
+                if substitutions > self.max_substitutions {
+                    continue;
+                }
+                let &(id, offset, stored_reversed) = found.value;
+                let candidate = (
+                    (id, offset),
+                    substitutions,
+                    read_reversed != stored_reversed,
+                );
+                match best.iter_mut().find(|(at, ..)| *at == (id, offset)) {
+                    Some(existing) if existing.1 > substitutions => *existing = candidate,
+                    Some(_) => {}
+                    None => best.push(candidate),
+                }
+            }
+        }
+        best.sort_by_key(|&(at, substitutions, _)| (substitutions, at));
+        Ok(best
+            .into_iter()
+            .map(|((id, offset), substitutions, reverse)| KmerMatch {
+                value: &self.values[id],
+                offset,
+                substitutions,
+                reverse,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::EntropySource;
+    use crate::noise::Seeded;
+
+    fn random_sequence(len: usize, rng: &mut Seeded) -> Vec<u8> {
+        let mut bytes = alloc::vec![0u8; len];
+        rng.fill_bytes(&mut bytes);
+        bytes.iter().map(|b| b"ACGT"[usize::from(b & 3)]).collect()
+    }
+
+    /// `sequence` with the bases at `positions` replaced by another base.
+    fn mutate(sequence: &[u8], positions: &[usize]) -> Vec<u8> {
+        let mut out = sequence.to_vec();
+        for &i in positions {
+            out[i] = match out[i] {
+                b'A' => b'G',
+                b'C' => b'T',
+                b'G' => b'C',
+                _ => b'A',
+            };
+        }
+        out
+    }
+
+    #[test]
+    fn test_both_strands_canonicalize_alike() {
+        let mut rng = Seeded::new(434);
+        for len in [1, 7, 31, 64, 100] {
+            let sequence = random_sequence(len, &mut rng);
+            let opposite = reverse_complement(&sequence).unwrap();
+            assert_eq!(reverse_complement(&opposite).unwrap(), sequence);
+            assert_eq!(canonical(&sequence).unwrap(), canonical(&opposite).unwrap());
+            let config = TbfConfig::default();
+            assert_eq!(
+                fingerprint(&sequence, &config).unwrap(),
+                fingerprint(&opposite, &config).unwrap()
+            );
+            for k in [1, 5, 21, 32] {
+                let mut backwards = kmers(&opposite, k).unwrap();
+                backwards.reverse();
+                assert_eq!(kmers(&sequence, k).unwrap(), backwards);
+            }
+        }
+        assert_eq!(reverse_complement(b"aacgT").unwrap(), b"ACGTT");
+        assert!(kmers(b"ACGT", 0).is_err());
+        assert!(kmers(b"ACGT", 33).is_err());
+        assert!(kmers(b"AC", 3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_substitution_count() {
+        let read = b"ACGTACGTACGTACGT";
+        for positions in [&[][..], &[0], &[3, 9], &[1, 2, 15]] {
+            let mutated = mutate(read, positions);
+            assert_eq!(
+                substitutions(forward_code(read).unwrap(), forward_code(&mutated).unwrap()),
+                positions.len() as u32
+            );
+        }
+    }
+
+    #[test]
+    fn test_index_finds_reads_within_the_substitution_rate() {
+        let mut rng = Seeded::new(7);
+        let references: Vec<Vec<u8>> = (0..4).map(|_| random_sequence(200, &mut rng)).collect();
+        let mut index = KmerIndex::new(24, 0.1).unwrap();
+        for (i, reference) in references.iter().enumerate() {
+            index.insert(reference, i).unwrap();
+        }
+        assert_eq!(index.len(), 4 * (200 - 23));
+        assert_eq!(index.max_substitutions(), 2);
+
+        for (i, offset, positions) in [(0, 0, &[][..]), (1, 50, &[4]), (3, 176, &[0, 23])] {
+            let read = mutate(&references[i][offset..offset + 24], positions);
+            for (read, reverse) in [
+                (read.clone(), false),
+                (reverse_complement(&read).unwrap(), true),
+            ] {
+                let matches = index.query(&read).unwrap();
+                assert_eq!(matches.len(), 1, "{i} {offset}");
+                let found = matches[0];
+                assert_eq!(
+                    (
+                        *found.value,
+                        found.offset,
+                        found.substitutions,
+                        found.reverse
+                    ),
+                    (i, offset, positions.len() as u32, reverse)
+                );
+            }
+        }
+        let read = mutate(&references[2][10..34], &[1, 2, 3]);
+        assert!(index.query(&read).unwrap().is_empty());
+        assert!(index.query(&read[1..]).is_err());
+        assert!(index.query(b"ACGTACGTACGTNCGTACGTACGT").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! Error-correcting codes for protecting data that must be recovered exactly
+//! (not just collapsed) after noisy storage or transmission.
+//!
+//! Unlike [`crate::collapse_deterministic`], which throws information away on
+//! purpose, these codes add redundancy so the original bits come back.
+
+#[cfg(feature = "std")]
+use crate::analysis::binomial_upper_tail;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// A bit-level repetition code with majority-vote decoding.
+///
+/// Every data bit is stored `repetitions` times. The copies are laid out in
+/// whole "layers" (layer `j` holds copy `j` of every data bit), so a localized
+/// burst of damage hits different copies of many bits instead of all copies
+/// of a few. A data bit decodes correctly as long as fewer than half of its
+/// copies were flipped. Decoding does not branch on the codeword's contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepetitionCode {
+    repetitions: usize, // Always odd, so majority votes never tie.
+}
+
+impl RepetitionCode {
+    /// Upper bound on the per-bit decoding failure probability targeted by
+    /// [`RepetitionCode::for_bit_error_rate`], as a power of two.
+    pub const TARGET_FAILURE_LOG2: i32 = -40;
+
+    /// Creates a code with the given number of copies per bit.
+    ///
+    /// Even counts are rounded up to the next odd number; zero becomes one.
+    pub const fn new(repetitions: usize) -> Self {
+        Self {
+            repetitions: repetitions | 1,
+        }
+    }
+
+    /// Picks the smallest repetition count whose per-bit failure probability
+    /// is at most 2^[`TARGET_FAILURE_LOG2`](Self::TARGET_FAILURE_LOG2) when
+    /// each stored bit flips independently with probability `bit_error_rate`.
+    ///
+    /// `bit_error_rate` is clamped to the crate-wide 5%–25% range. Typical
+    /// results: 31 copies at 5%, 61 at 12.5%, 173 at 25%.
+    #[cfg(feature = "std")]
+    pub fn for_bit_error_rate(bit_error_rate: f32) -> Self {
+        let p = f64::from(bit_error_rate.clamp(0.05, 0.25));
+        let target = 2f64.powi(Self::TARGET_FAILURE_LOG2);
+        let mut r = 1;
+        while majority_failure_probability(r, p) > target {
+            r += 2;
+        }
+        Self { repetitions: r }
+    }
+
+    /// Probability that a data bit decodes wrongly when each stored copy
+    /// flips independently with probability `bit_error_rate`.
+    #[cfg(feature = "std")]
+    pub fn failure_probability(&self, bit_error_rate: f32) -> f64 {
+        majority_failure_probability(self.repetitions, f64::from(bit_error_rate.clamp(0.0, 1.0)))
+    }
+
+    /// Number of copies stored per data bit.
+    pub fn repetitions(&self) -> usize {
+        self.repetitions
+    }
+
+    /// Number of flipped copies of a single bit that are always corrected.
+    pub fn correctable_per_bit(&self) -> usize {
+        (self.repetitions - 1) / 2
+    }
+
+    /// Encodes `data`, producing `data.len() * repetitions` bytes.
+    #[cfg(feature = "alloc")]
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        // Copy j of bit i sits at bit position j * data_bits + i, which means
+        // each layer is simply a byte-for-byte copy of the data.
+        data.repeat(self.repetitions)
+    }
+
+    /// Allocation-free [`encode`](Self::encode) into `codeword`.
+    ///
+    /// Returns `None` (leaving `codeword` untouched) unless
+    /// `codeword.len() == data.len() * repetitions`.
+    pub fn encode_into(&self, data: &[u8], codeword: &mut [u8]) -> Option<()> {
+        if codeword.len() != data.len() * self.repetitions {
+            return None;
+        }
+        for layer in codeword.chunks_exact_mut(data.len().max(1)) {
+            layer.copy_from_slice(data);
+        }
+        Some(())
+    }
+
+    /// Decodes by per-bit majority vote across all layers.
+    ///
+    /// Returns `None` if `codeword` is not a whole number of layers long.
+    #[cfg(feature = "alloc")]
+    pub fn decode(&self, codeword: &[u8]) -> Option<Vec<u8>> {
+        if !codeword.len().is_multiple_of(self.repetitions) {
+            return None;
+        }
+        let mut data = alloc::vec![0u8; codeword.len() / self.repetitions];
+        self.decode_into(codeword, &mut data)?;
+        Some(data)
+    }
+
+    /// Allocation-free [`decode`](Self::decode) into `data`.
+    ///
+    /// Returns `None` unless `codeword.len() == data.len() * repetitions`.
+    pub fn decode_into(&self, codeword: &[u8], data: &mut [u8]) -> Option<()> {
+        self.decode_masked_into(codeword, None, data)
+    }
+
+    /// Decodes `codeword ^ mask` without materializing the XOR, as needed by
+    /// code-offset constructions whose codeword is split across two buffers.
+    pub(crate) fn decode_masked_into(
+        &self,
+        codeword: &[u8],
+        mask: Option<&[u8]>,
+        data: &mut [u8],
+    ) -> Option<()> {
+        let data_len = data.len();
+        if codeword.len() != data_len * self.repetitions
+            || mask.is_some_and(|mask| mask.len() != codeword.len())
+        {
+            return None;
+        }
+        let majority = self.repetitions / 2;
+
+        for (i, out) in data.iter_mut().enumerate() {
+            *out = 0;
+            for bit in 0..8 {
+                // Count how many copies of this bit are set.
+                let ones: usize = (i..codeword.len())
+                    .step_by(data_len.max(1))
+                    .map(|k| {
+                        let byte = codeword[k] ^ mask.map_or(0, |mask| mask[k]);
+                        usize::from((byte >> bit) & 1)
+                    })
+                    .sum();
+                // ones > majority  <=>  (majority - ones) is negative; no branch on the data.
+                let set = (majority.wrapping_sub(ones) >> (usize::BITS - 1)) as u8;
+                *out |= set << bit;
+            }
+        }
+        Some(())
+    }
+}
+
+/// P[Binomial(r, p) > r / 2]: the chance that the majority of `r` copies flip.
+#[cfg(feature = "std")]
+fn majority_failure_probability(r: usize, p: f64) -> f64 {
+    binomial_upper_tail(r, p, r / 2 + 1)
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repetition_roundtrip() {
+        let code = RepetitionCode::new(5);
+        let data = b"pensieve";
+        let encoded = code.encode(data);
+        assert_eq!(encoded.len(), data.len() * 5);
+        assert_eq!(code.decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_repetition_corrects_minority_flips() {
+        let code = RepetitionCode::new(5);
+        let data = [0b1010_1010u8, 0x00, 0xFF];
+        let mut encoded = code.encode(&data);
+        // Flip two of the five copies of every bit (layers 0 and 3).
+        for i in 0..data.len() {
+            encoded[i] ^= 0xFF;
+            encoded[3 * data.len() + i] ^= 0xFF;
+        }
+        assert_eq!(code.decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_repetition_majority_flips_not_corrected() {
+        let code = RepetitionCode::new(3);
+        let mut encoded = code.encode(&[0x0F]);
+        encoded[0] ^= 0x01;
+        encoded[1] ^= 0x01; // 2 of 3 copies of bit 0 flipped
+        assert_eq!(code.decode(&encoded).unwrap(), [0x0E]);
+    }
+
+    #[test]
+    fn test_repetition_rejects_ragged_length() {
+        assert_eq!(RepetitionCode::new(3).decode(&[0; 4]), None);
+    }
+
+    #[test]
+    fn test_repetition_into_matches_allocating() {
+        let code = RepetitionCode::new(3);
+        let data = [0x12, 0xF0];
+        let mut codeword = [0u8; 6];
+        code.encode_into(&data, &mut codeword).unwrap();
+        assert_eq!(codeword.to_vec(), code.encode(&data));
+
+        codeword[1] ^= 0xFF;
+        let mut decoded = [0u8; 2];
+        code.decode_into(&codeword, &mut decoded).unwrap();
+        assert_eq!(decoded, data);
+        assert_eq!(code.decode_into(&codeword, &mut [0u8; 3]), None);
+        assert_eq!(code.encode_into(&data, &mut [0u8; 5]), None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_for_bit_error_rate() {
+        assert_eq!(RepetitionCode::for_bit_error_rate(0.05).repetitions(), 31);
+        assert_eq!(RepetitionCode::for_bit_error_rate(0.10).repetitions(), 49);
+        assert_eq!(RepetitionCode::for_bit_error_rate(0.125).repetitions(), 61);
+        assert_eq!(RepetitionCode::for_bit_error_rate(0.25).repetitions(), 173);
+        assert_eq!(RepetitionCode::new(4).repetitions(), 5);
+    }
+}
@@ -0,0 +1,271 @@
+//! Bitstrings from floating-point embedding vectors.
+//!
+//! Neural encoders map semantically similar inputs to nearby `f32`
+//! vectors, but nearby floats share no bits a collapse could tolerate. A
+//! [`Quantizer`] turns each vector into a bitstring whose Hamming distance
+//! follows the vectors' geometry, ready for
+//! [`collapse_deterministic`](crate::collapse_deterministic) or the
+//! [`index`](crate::index) structures:
+//!
+//! - [`Projections`] keeps the signs of dot products with seeded random
+//!   directions. The chance that two vectors disagree on a bit is their
+//!   angle divided by π, so the Hamming distance estimates cosine
+//!   similarity, and any number of bits can be drawn from any number of
+//!   dimensions.
+//! - [`Thresholds`] keeps one bit per dimension, set when the value exceeds
+//!   that dimension's threshold, for example its median over a sample
+//!   ([`Thresholds::fit`]). It is cheaper and needs no randomness, but only
+//!   suits embeddings whose dimensions carry comparable information.
+//!
+//! Bitstrings are MSB-first and padded with zero bits to whole bytes. NaN
+//! values compare below every threshold.
+
+use crate::Error;
+use crate::simhash::mix;
+use alloc::vec::Vec;
+
+/// Turns embedding vectors of a fixed dimension into bitstrings.
+pub trait Quantizer {
+    /// Number of dimensions of the accepted vectors.
+    fn dimensions(&self) -> usize;
+
+    /// Number of bits produced per vector, before padding.
+    fn bits(&self) -> usize;
+
+    /// The bitstring of `embedding`, `bits().div_ceil(8)` bytes.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless `embedding` has
+    /// [`dimensions`](Self::dimensions) values.
+    fn quantize(&self, embedding: &[f32]) -> Result<Vec<u8>, Error>;
+}
+
+/// Sign bits of random projections.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projections {
+    dimensions: usize,
+    seed: u64,
+    directions: Vec<f32>, // `bits` directions of `dimensions` values, back to back.
+}
+
+impl Projections {
+    /// Draws `bits` random directions in `dimensions`-dimensional space from
+    /// `seed`. The same arguments always give the same directions, on every
+    /// platform, so the seed is all that needs storing alongside digests.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `dimensions` or `bits` is
+    /// zero.
+    pub fn new(dimensions: usize, bits: usize, seed: u64) -> Result<Self, Error> {
+        if dimensions == 0 || bits == 0 {
+            return Err(Error::InvalidParameter(
+                "projections need at least one dimension and one bit",
+            ));
+        }
+        let len = dimensions
+            .checked_mul(bits)
+            .ok_or(Error::InvalidParameter("projection matrix is too large"))?;
+        // Each entry is the sum of four 16-bit uniforms, centered: close
+        // enough to a Gaussian that the directions are spread evenly over
+        // the sphere, without floating-point library functions.
+        let directions = (0..len as u64)
+            .map(|i| {
+                let word = mix(seed ^ mix(i));
+                let sum: u32 = (0..4).map(|k| (word >> (16 * k)) as u16 as u32).sum();
+                sum as f32 / 65535.0 - 2.0
+            })
+            .collect();
+        Ok(Self {
+            dimensions,
+            seed,
+            directions,
+        })
+    }
+
+    /// The seed the directions were drawn from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Quantizer for Projections {
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn bits(&self) -> usize {
+        self.directions.len() / self.dimensions
+    }
+
+    fn quantize(&self, embedding: &[f32]) -> Result<Vec<u8>, Error> {
+        check_dimensions(embedding, self.dimensions)?;
+        Ok(pack(self.directions.chunks_exact(self.dimensions).map(
+            |direction| {
+                let dot: f32 = direction.iter().zip(embedding).map(|(d, x)| d * x).sum();
+                dot > 0.0
+            },
+        )))
+    }
+}
+
+/// One bit per dimension, compared against a per-dimension threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thresholds {
+    thresholds: Vec<f32>,
+}
+
+impl Thresholds {
+    /// Uses `thresholds[i]` for dimension `i`; all zeros keeps the signs.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `thresholds` is empty or
+    /// holds a NaN.
+    pub fn new(thresholds: Vec<f32>) -> Result<Self, Error> {
+        if thresholds.is_empty() {
+            return Err(Error::InvalidParameter(
+                "thresholds need at least one dimension",
+            ));
+        }
+        if thresholds.iter().any(|t| t.is_nan()) {
+            return Err(Error::InvalidParameter("threshold is NaN"));
+        }
+        Ok(Self { thresholds })
+    }
+
+    /// Uses the per-dimension medians of `samples`, so every bit is set for
+    /// about half of inputs resembling them.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if there are no samples or
+    /// they differ in dimension.
+    pub fn fit(samples: &[&[f32]]) -> Result<Self, Error> {
+        let dimensions = samples.first().map_or(0, |s| s.len());
+        if samples.iter().any(|s| s.len() != dimensions) {
+            return Err(Error::InvalidParameter(
+                "samples differ in number of dimensions",
+            ));
+        }
+        let mut column = Vec::with_capacity(samples.len());
+        let medians = (0..dimensions)
+            .map(|i| {
+                column.clear();
+                column.extend(samples.iter().map(|s| s[i]).filter(|v| !v.is_nan()));
+                column.sort_by(f32::total_cmp);
+                column
+                    .get(column.len().saturating_sub(1) / 2)
+                    .map_or(0.0, |&m| m)
+            })
+            .collect();
+        Self::new(medians)
+    }
+
+    /// The threshold of every dimension.
+    pub fn thresholds(&self) -> &[f32] {
+        &self.thresholds
+    }
+}
+
+impl Quantizer for Thresholds {
+    fn dimensions(&self) -> usize {
+        self.thresholds.len()
+    }
+
+    fn bits(&self) -> usize {
+        self.thresholds.len()
+    }
+
+    fn quantize(&self, embedding: &[f32]) -> Result<Vec<u8>, Error> {
+        check_dimensions(embedding, self.thresholds.len())?;
+        Ok(pack(
+            embedding.iter().zip(&self.thresholds).map(|(x, t)| x > t),
+        ))
+    }
+}
+
+fn check_dimensions(embedding: &[f32], dimensions: usize) -> Result<(), Error> {
+    if embedding.len() != dimensions {
+        return Err(Error::InvalidParameter(
+            "embedding has the wrong number of dimensions",
+        ));
+    }
+    Ok(())
+}
+
+/// Packs bits MSB-first, padding the last byte with zeros.
+fn pack(bits: impl ExactSizeIterator<Item = bool>) -> Vec<u8> {
+    let mut out = alloc::vec![0u8; bits.len().div_ceil(8)];
+    for (i, bit) in bits.enumerate() {
+        out[i / 8] |= u8::from(bit) << (7 - i % 8);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::hamming;
+
+    /// A deterministic pseudo-random vector with entries in -1.0..1.0.
+    fn vector(dimensions: usize, seed: u64) -> Vec<f32> {
+        (0..dimensions as u64)
+            .map(|i| {
+                (mix(seed.wrapping_mul(0x1_0000) ^ i) >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_projections_track_angle() {
+        let projections = Projections::new(64, 256, 7).unwrap();
+        assert_eq!((projections.dimensions(), projections.bits()), (64, 256));
+        let base = vector(64, 1);
+        let near: Vec<f32> = base
+            .iter()
+            .zip(vector(64, 2))
+            .map(|(b, n)| b + 0.2 * n)
+            .collect();
+        let scaled: Vec<f32> = base.iter().map(|b| b * 3.5).collect();
+        let opposite: Vec<f32> = base.iter().map(|b| -b).collect();
+
+        let digest = projections.quantize(&base).unwrap();
+        assert_eq!(digest.len(), 32);
+        assert_eq!(projections.quantize(&scaled).unwrap(), digest);
+        let near = hamming(&digest, &projections.quantize(&near).unwrap());
+        let far = hamming(&digest, &projections.quantize(&vector(64, 3)).unwrap());
+        assert!(near < 40, "near {near}");
+        assert!((96..160).contains(&far), "far {far}");
+        assert_eq!(
+            hamming(&digest, &projections.quantize(&opposite).unwrap()),
+            256
+        );
+
+        assert_eq!(Projections::new(64, 256, 7).unwrap(), projections);
+        assert_ne!(Projections::new(64, 256, 8).unwrap(), projections);
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let signs = Thresholds::new(alloc::vec![0.0; 10]).unwrap();
+        let bits = signs
+            .quantize(&[1.0, -1.0, 0.5, 0.0, f32::NAN, 2.0, -3.0, 1.0, 0.1, -0.1])
+            .unwrap();
+        assert_eq!(bits, [0b1010_0101, 0b1000_0000]);
+
+        let samples: [&[f32]; 3] = [&[1.0, 10.0], &[2.0, 30.0], &[3.0, 20.0]];
+        let fitted = Thresholds::fit(&samples).unwrap();
+        assert_eq!(fitted.thresholds(), [2.0, 20.0]);
+        assert_eq!(fitted.quantize(&[2.5, 19.0]).unwrap(), [0b1000_0000]);
+    }
+
+    #[test]
+    fn test_parameters_are_validated() {
+        assert!(Projections::new(0, 8, 0).is_err());
+        assert!(Projections::new(8, 0, 0).is_err());
+        assert!(
+            Projections::new(4, 8, 0)
+                .unwrap()
+                .quantize(&[0.0; 5])
+                .is_err()
+        );
+        assert!(Thresholds::new(Vec::new()).is_err());
+        assert!(Thresholds::new(alloc::vec![f32::NAN]).is_err());
+        assert!(Thresholds::fit(&[]).is_err());
+        assert!(Thresholds::fit(&[&[1.0], &[1.0, 2.0]]).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+//! Text and binary encodings of collapsed digests for use outside Rust.
+//!
+//! - [`multibase`] writes bytes as text behind a one-character prefix that
+//!   names the base, so a decoder needs no out-of-band agreement on the
+//!   alphabet.
+//! - [`Multihash`] frames a digest with a varint hash code and length, the
+//!   format content identifiers (CIDs) use. Pensieve digests carry the
+//!   private-use code [`PENSIEVE_MULTIHASH_CODE`], so they can be stored in
+//!   the same tables as CIDs without being mistaken for a cryptographic hash.
+//! - [`bech32`] writes bytes under a caller-chosen human-readable part with
+//!   a checksum that catches typos, for digests transcribed by people.
+//!
+//! # Helper data
+//! Fuzzy-extractor helper data has a `to_cbor`/`from_cbor` pair besides its
+//! compact `to_bytes` form. It is deterministic CBOR (RFC 8949, section
+//! 4.2.1): a map with unsigned integer keys in ascending order, where key 0
+//! is the schema version (currently 1), key 1 the kind of helper data (1 =
+//! plain, 2 = stretched, 3 = locker) and later keys hold the fields. A
+//! release reads every schema version up to its own, so stored helper data
+//! stays usable after upgrades, and any CBOR library can produce and parse
+//! it.
+//!
+//! ```rust
+//! use pensieve::encoding::{Multihash, multibase::Base};
+//!
+//! let digest = pensieve::collapse_ct(&[0x5A; 16], 0.125);
+//! let text = Multihash::pensieve(&digest).to_multibase(Base::Base32);
+//! assert!(text.starts_with('b'));
+//! assert_eq!(Multihash::from_multibase(&text).unwrap().digest(), digest);
+//! ```
+
+pub mod bech32;
+pub(crate) mod cbor;
+pub mod multibase;
+mod multihash;
+
+pub use multihash::{Multihash, PENSIEVE_MULTIHASH_CODE};
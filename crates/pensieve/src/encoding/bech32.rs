@@ -0,0 +1,262 @@
+//! Bech32 (BIP173) and bech32m (BIP350): human-readable part, separator
+//! `1`, base32 data and a 6-character BCH checksum.
+//!
+//! The checksum detects any error affecting up to four characters in
+//! strings of at most 90 characters, and almost every larger error, which
+//! makes it suited for digests that people copy by hand. Longer strings are
+//! encoded too, with a weaker (but still 30-bit) guarantee.
+
+use crate::error::Error;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+/// Characters in the checksum.
+const CHECKSUM_LEN: usize = 6;
+/// Longest human-readable part BIP173 allows.
+const MAX_HRP_LEN: usize = 83;
+
+/// Which checksum constant a string uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Variant {
+    /// The original BIP173 checksum.
+    Bech32,
+    /// The BIP350 checksum, which also catches insertions and deletions of
+    /// `q` next to a final `p`. Prefer it for new formats.
+    Bech32m,
+}
+
+impl Variant {
+    /// The value the checksum polymod must leave.
+    fn constant(self) -> u32 {
+        match self {
+            Variant::Bech32 => 1,
+            Variant::Bech32m => 0x2BC8_30A3,
+        }
+    }
+}
+
+/// Encodes `data` under the human-readable part `hrp`.
+///
+/// `hrp` must be 1 to 83 printable ASCII characters; it is written in
+/// lowercase. Fails with [`Error::InvalidParameter`] otherwise.
+pub fn encode(hrp: &str, data: &[u8], variant: Variant) -> Result<String, Error> {
+    let hrp = hrp.to_ascii_lowercase();
+    check_hrp(&hrp)?;
+    let mut values = to_base32(data);
+    let checksum = checksum(&hrp, &values, variant);
+    values.extend_from_slice(&checksum);
+
+    let mut out = String::with_capacity(hrp.len() + 1 + values.len());
+    out.push_str(&hrp);
+    out.push('1');
+    out.extend(values.iter().map(|&v| CHARSET[v as usize] as char));
+    Ok(out)
+}
+
+/// Decodes a bech32 or bech32m string into its (lowercase) human-readable
+/// part, data and variant.
+///
+/// Fails with [`Error::InvalidChecksum`] when the checksum matches neither
+/// variant, i.e. the string was mistyped; with [`Error::InvalidCharacter`]
+/// for characters outside the alphabet; and with [`Error::InvalidParameter`]
+/// for mixed case, a missing separator, an invalid human-readable part or
+/// non-zero padding bits.
+pub fn decode(text: &str) -> Result<(String, Vec<u8>, Variant), Error> {
+    if text.chars().any(|c| c.is_ascii_lowercase()) && text.chars().any(|c| c.is_ascii_uppercase())
+    {
+        return Err(Error::InvalidParameter("mixed-case bech32 string"));
+    }
+    if let Some(position) = text.chars().position(|c| !c.is_ascii()) {
+        return Err(Error::InvalidCharacter { position });
+    }
+    let text = text.to_ascii_lowercase();
+    let separator = text
+        .rfind('1')
+        .ok_or(Error::InvalidParameter("missing bech32 separator"))?;
+    let (hrp, data) = (&text[..separator], &text[separator + 1..]);
+    check_hrp(hrp)?;
+    if data.len() < CHECKSUM_LEN {
+        return Err(Error::InvalidLength {
+            expected: CHECKSUM_LEN,
+            found: data.len(),
+        });
+    }
+    let values = data
+        .bytes()
+        .enumerate()
+        .map(|(i, c)| {
+            let position = separator + 1 + i;
+            let value = CHARSET.iter().position(|&a| a == c);
+            value
+                .map(|v| v as u8)
+                .ok_or(Error::InvalidCharacter { position })
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    let residue = polymod(hrp, &values);
+    let variant = [Variant::Bech32, Variant::Bech32m]
+        .into_iter()
+        .find(|v| v.constant() == residue)
+        .ok_or(Error::InvalidChecksum)?;
+    let bytes = from_base32(&values[..values.len() - CHECKSUM_LEN])?;
+    Ok((String::from(hrp), bytes, variant))
+}
+
+/// Checks length and character range of a lowercase human-readable part.
+fn check_hrp(hrp: &str) -> Result<(), Error> {
+    if hrp.is_empty() || hrp.len() > MAX_HRP_LEN {
+        return Err(Error::InvalidParameter(
+            "bech32 human-readable part must be 1 to 83 characters",
+        ));
+    }
+    if let Some(position) = hrp.bytes().position(|c| !(33..=126).contains(&c)) {
+        return Err(Error::InvalidCharacter { position });
+    }
+    Ok(())
+}
+
+/// The BCH checksum remainder over the expanded `hrp` and `values`.
+fn polymod(hrp: &str, values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [
+        0x3B6A_57B2,
+        0x2650_8E6D,
+        0x1EA1_19FA,
+        0x3D42_33DD,
+        0x2A14_62B3,
+    ];
+    let expanded = hrp
+        .bytes()
+        .map(|c| c >> 5)
+        .chain([0])
+        .chain(hrp.bytes().map(|c| c & 31))
+        .chain(values.iter().copied());
+    expanded.fold(1u32, |chk, value| {
+        let top = chk >> 25;
+        let chk = (chk & 0x01FF_FFFF) << 5 ^ u32::from(value);
+        (0..5)
+            .filter(|i| top >> i & 1 == 1)
+            .fold(chk, |chk, i| chk ^ GENERATOR[i])
+    })
+}
+
+/// The six checksum values for `hrp` and `values`.
+fn checksum(hrp: &str, values: &[u8], variant: Variant) -> [u8; CHECKSUM_LEN] {
+    let mut padded = values.to_vec();
+    padded.extend_from_slice(&[0; CHECKSUM_LEN]);
+    let residue = polymod(hrp, &padded) ^ variant.constant();
+    core::array::from_fn(|i| (residue >> (5 * (5 - i)) & 31) as u8)
+}
+
+/// Regroups bytes into 5-bit values, zero-padding the last one.
+fn to_base32(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity((bytes.len() * 8).div_ceil(5));
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for &byte in bytes {
+        acc = (acc << 8 | u32::from(byte)) & 0xFFF;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push((acc >> bits & 31) as u8);
+        }
+    }
+    if bits > 0 {
+        out.push((acc << (5 - bits) & 31) as u8);
+    }
+    out
+}
+
+/// Inverse of [`to_base32`]; padding must be shorter than 5 bits and zero.
+fn from_base32(values: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::with_capacity(values.len() * 5 / 8);
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for &value in values {
+        acc = (acc << 5 | u32::from(value)) & 0xFFF;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 5 || acc & ((1 << bits) - 1) != 0 {
+        return Err(Error::InvalidParameter("non-zero bech32 padding"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bip173_and_bip350_vectors() {
+        assert_eq!(
+            decode("A12UEL5L"),
+            Ok((String::from("a"), Vec::new(), Variant::Bech32))
+        );
+        assert_eq!(
+            decode("a1lqfn3a"),
+            Ok((String::from("a"), Vec::new(), Variant::Bech32m))
+        );
+        let data = [
+            0x00, 0x44, 0x32, 0x14, 0xC7, 0x42, 0x54, 0xB6, 0x35, 0xCF, 0x84, 0x65, 0x3A, 0x56,
+            0xD7, 0xC6, 0x75, 0xBE, 0x77, 0xDF,
+        ];
+        let text = "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw";
+        assert_eq!(
+            encode("abcdef", &data, Variant::Bech32).as_deref(),
+            Ok(text)
+        );
+        assert_eq!(
+            decode(text),
+            Ok((String::from("abcdef"), data.to_vec(), Variant::Bech32))
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_and_typo_detection() {
+        let digest = crate::collapse_ct(&[0x3C; 24], 0.125);
+        let text = encode("PNSD", &digest, Variant::Bech32m).unwrap();
+        assert!(text.starts_with("pnsd1"));
+        assert_eq!(
+            decode(&text.to_ascii_uppercase()),
+            Ok((String::from("pnsd"), digest, Variant::Bech32m))
+        );
+
+        // Every single-character substitution in the data part is caught.
+        for i in 5..text.len() {
+            for &c in CHARSET {
+                let mut typo = text.clone().into_bytes();
+                if typo[i] == c {
+                    continue;
+                }
+                typo[i] = c;
+                let typo = String::from_utf8(typo).unwrap();
+                assert_eq!(decode(&typo), Err(Error::InvalidChecksum), "{typo}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_strings() {
+        assert_eq!(
+            decode("A12uEL5L"),
+            Err(Error::InvalidParameter("mixed-case bech32 string"))
+        );
+        assert_eq!(
+            decode("pzry9x0s0muk"),
+            Err(Error::InvalidParameter("missing bech32 separator"))
+        );
+        assert_eq!(
+            decode("1pzry9x0s0muk").unwrap_err(),
+            Error::InvalidParameter("bech32 human-readable part must be 1 to 83 characters")
+        );
+        assert_eq!(
+            decode("x1b4n0q5v"),
+            Err(Error::InvalidCharacter { position: 2 })
+        );
+        assert!(matches!(decode("a1qp"), Err(Error::InvalidLength { .. })));
+        assert!(encode("", &[1], Variant::Bech32).is_err());
+        assert!(encode("a b", &[1], Variant::Bech32).is_err());
+    }
+}
@@ -0,0 +1,379 @@
+//! The subset of deterministically encoded CBOR (RFC 8949, section 4.2.1)
+//! that helper data needs: unsigned integers, byte strings, arrays, maps
+//! with small integer keys and floats.
+//!
+//! The writer always emits the shortest form and definite lengths; the
+//! reader rejects anything else, so every value has exactly one encoding.
+
+use crate::error::Error;
+use alloc::vec::Vec;
+
+const UNSIGNED: u8 = 0;
+const BYTES: u8 = 2;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const SIMPLE: u8 = 7;
+
+/// Additional-information values of the float encodings.
+const HALF: u8 = 25;
+const SINGLE: u8 = 26;
+
+/// Newest helper data schema this build writes; every older one stays readable.
+const SCHEMA_VERSION: u64 = 1;
+
+/// Which helper data a CBOR map holds (map key 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HelperKind {
+    Plain = 1,
+    #[cfg(feature = "argon2")]
+    Stretched = 2,
+    Locker = 3,
+}
+
+/// Appends deterministic CBOR items to a buffer.
+#[derive(Debug, Default)]
+pub(crate) struct Writer {
+    out: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn uint(&mut self, value: u64) -> &mut Self {
+        self.head(UNSIGNED, value)
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.head(BYTES, value.len() as u64);
+        self.out.extend_from_slice(value);
+        self
+    }
+
+    /// Starts an array of `len` items, which the caller writes next.
+    pub(crate) fn array(&mut self, len: usize) -> &mut Self {
+        self.head(ARRAY, len as u64)
+    }
+
+    /// Starts a map of `len` pairs, which the caller writes next with keys
+    /// in ascending order.
+    pub(crate) fn map(&mut self, len: usize) -> &mut Self {
+        self.head(MAP, len as u64)
+    }
+
+    /// Writes `value` as a half-precision float if that is exact, otherwise
+    /// as a single-precision one.
+    pub(crate) fn f32(&mut self, value: f32) -> &mut Self {
+        match f32_to_f16_exact(value) {
+            Some(half) => {
+                self.out.push(SIMPLE << 5 | HALF);
+                self.out.extend_from_slice(&half.to_be_bytes());
+            }
+            None => {
+                self.out.push(SIMPLE << 5 | SINGLE);
+                self.out.extend_from_slice(&value.to_bits().to_be_bytes());
+            }
+        }
+        self
+    }
+
+    /// Starts a helper data map with `fields` entries after the schema
+    /// version (key 0) and `kind` (key 1); the caller writes keys 2 onward.
+    pub(crate) fn helper_header(&mut self, kind: HelperKind, fields: usize) -> &mut Self {
+        self.map(fields + 2).uint(0).uint(SCHEMA_VERSION);
+        self.uint(1).uint(kind as u64)
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.out
+    }
+
+    /// Writes a major type with its argument in the shortest form.
+    fn head(&mut self, major: u8, value: u64) -> &mut Self {
+        let major = major << 5;
+        match value {
+            0..=23 => self.out.push(major | value as u8),
+            24..=0xFF => self.out.extend_from_slice(&[major | 24, value as u8]),
+            0x100..=0xFFFF => {
+                self.out.push(major | 25);
+                self.out.extend_from_slice(&(value as u16).to_be_bytes());
+            }
+            0x1_0000..=0xFFFF_FFFF => {
+                self.out.push(major | 26);
+                self.out.extend_from_slice(&(value as u32).to_be_bytes());
+            }
+            _ => {
+                self.out.push(major | 27);
+                self.out.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        self
+    }
+}
+
+/// Reads deterministic CBOR items from a byte slice.
+#[derive(Debug)]
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub(crate) fn uint(&mut self) -> Result<u64, Error> {
+        self.head(UNSIGNED)
+    }
+
+    /// Reads an unsigned integer that must fit in `T`.
+    pub(crate) fn uint_as<T: TryFrom<u64>>(&mut self) -> Result<T, Error> {
+        T::try_from(self.uint()?).map_err(|_| Error::InvalidParameter("CBOR integer out of range"))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.head(BYTES)?;
+        self.take(usize::try_from(len).unwrap_or(usize::MAX))
+    }
+
+    /// Reads a byte string of exactly `N` bytes.
+    pub(crate) fn byte_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let bytes = self.bytes()?;
+        bytes.try_into().map_err(|_| Error::InvalidLength {
+            expected: N,
+            found: bytes.len(),
+        })
+    }
+
+    /// Reads an array header and returns its length.
+    pub(crate) fn array(&mut self) -> Result<usize, Error> {
+        let len = self.head(ARRAY)?;
+        // Every item takes at least one byte, which bounds honest lengths.
+        if len > self.bytes.len() as u64 {
+            return Err(Error::InvalidParameter("truncated CBOR"));
+        }
+        Ok(len as usize)
+    }
+
+    /// Reads an array header of exactly `len` items.
+    pub(crate) fn array_of(&mut self, len: usize) -> Result<(), Error> {
+        if self.array()? != len {
+            return Err(Error::InvalidParameter("unexpected CBOR array length"));
+        }
+        Ok(())
+    }
+
+    /// Reads a map header of exactly `len` pairs.
+    pub(crate) fn map_of(&mut self, len: usize) -> Result<(), Error> {
+        if self.head(MAP)? != len as u64 {
+            return Err(Error::InvalidParameter("unexpected CBOR map size"));
+        }
+        Ok(())
+    }
+
+    /// Reads a map key, which must be `key`; since callers ask for keys in
+    /// ascending order, this also enforces the deterministic key order.
+    pub(crate) fn key(&mut self, key: u64) -> Result<(), Error> {
+        if self.uint()? != key {
+            return Err(Error::InvalidParameter("unexpected CBOR map key"));
+        }
+        Ok(())
+    }
+
+    /// Reads the header written by [`Writer::helper_header`], accepting any
+    /// schema version up to the newest one.
+    pub(crate) fn helper_header(&mut self, kind: HelperKind, fields: usize) -> Result<(), Error> {
+        self.map_of(fields + 2)?;
+        self.key(0)?;
+        if !(1..=SCHEMA_VERSION).contains(&self.uint()?) {
+            return Err(Error::InvalidParameter(
+                "unsupported helper data schema version",
+            ));
+        }
+        self.key(1)?;
+        if self.uint()? != kind as u64 {
+            return Err(Error::InvalidParameter("CBOR holds other helper data"));
+        }
+        Ok(())
+    }
+
+    /// Reads a half- or single-precision float in its shortest exact form.
+    pub(crate) fn f32(&mut self) -> Result<f32, Error> {
+        let initial = *self.take(1)?.first().expect("1 byte");
+        match (initial >> 5, initial & 31) {
+            (SIMPLE, HALF) => {
+                let half = u16::from_be_bytes(self.take(2)?.try_into().expect("2 bytes"));
+                Ok(f16_to_f32(half))
+            }
+            (SIMPLE, SINGLE) => {
+                let bits = u32::from_be_bytes(self.take(4)?.try_into().expect("4 bytes"));
+                let value = f32::from_bits(bits);
+                if f32_to_f16_exact(value).is_some() {
+                    return Err(Error::InvalidParameter("non-canonical CBOR float"));
+                }
+                Ok(value)
+            }
+            _ => Err(Error::InvalidParameter("expected a CBOR float")),
+        }
+    }
+
+    /// Fails unless every byte was consumed.
+    pub(crate) fn finish(self) -> Result<(), Error> {
+        if !self.bytes.is_empty() {
+            return Err(Error::InvalidParameter("trailing bytes after CBOR item"));
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.bytes.len() {
+            return Err(Error::InvalidParameter("truncated CBOR"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    /// Reads the head of an item of type `major`, insisting on the
+    /// shortest argument encoding.
+    fn head(&mut self, major: u8) -> Result<u64, Error> {
+        let initial = *self.take(1)?.first().expect("1 byte");
+        if initial >> 5 != major {
+            return Err(Error::InvalidParameter("unexpected CBOR type"));
+        }
+        let (value, minimum) = match initial & 31 {
+            info @ 0..=23 => return Ok(u64::from(info)),
+            24 => (u64::from(self.take(1)?[0]), 24),
+            25 => (self.take_be(2)?, 0x100),
+            26 => (self.take_be(4)?, 0x1_0000),
+            27 => (self.take_be(8)?, 0x1_0000_0000),
+            _ => {
+                return Err(Error::InvalidParameter(
+                    "indefinite or reserved CBOR length",
+                ));
+            }
+        };
+        if value < minimum {
+            return Err(Error::InvalidParameter("non-canonical CBOR integer"));
+        }
+        Ok(value)
+    }
+
+    fn take_be(&mut self, len: usize) -> Result<u64, Error> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0u64, |acc, &b| acc << 8 | u64::from(b)))
+    }
+}
+
+/// The half-precision bits of `value` if the conversion is exact.
+fn f32_to_f16_exact(value: f32) -> Option<u16> {
+    let bits = value.to_bits();
+    let sign = (bits >> 16 & 0x8000) as u16;
+    let exponent = (bits >> 23 & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+    match exponent {
+        0 if mantissa == 0 => Some(sign),
+        0 => None, // Single subnormals are far below the half range.
+        0xFF if mantissa == 0 => Some(sign | 0x7C00),
+        0xFF => Some(0x7E00), // The canonical NaN.
+        _ => {
+            let unbiased = exponent - 127;
+            let full = mantissa | 0x80_0000;
+            match unbiased {
+                -14..=15 if mantissa & 0x1FFF == 0 => {
+                    Some(sign | ((unbiased + 15) as u16) << 10 | (mantissa >> 13) as u16)
+                }
+                -24..=-15 => {
+                    // Half subnormal: value = m * 2^-24 with m < 1024.
+                    let shift = -(unbiased + 1) as u32;
+                    (full & ((1 << shift) - 1) == 0).then(|| sign | (full >> shift) as u16)
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = u32::from(half & 0x8000) << 16;
+    let exponent = u32::from(half >> 10 & 0x1F);
+    let mantissa = u32::from(half & 0x3FF);
+    match exponent {
+        0 => {
+            let magnitude = mantissa as f32 / (1u32 << 24) as f32;
+            f32::from_bits(sign | magnitude.to_bits())
+        }
+        0x1F => f32::from_bits(sign | 0x7F80_0000 | mantissa << 13),
+        _ => f32::from_bits(sign | (exponent + 127 - 15) << 23 | mantissa << 13),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shortest_encodings() {
+        // Examples from RFC 8949, appendix A.
+        fn encode(write: impl FnOnce(&mut Writer) -> &mut Writer) -> Vec<u8> {
+            let mut writer = Writer::default();
+            write(&mut writer);
+            writer.into_bytes()
+        }
+        assert_eq!(encode(|w| w.uint(23)), [0x17]);
+        assert_eq!(encode(|w| w.uint(24)), [0x18, 0x18]);
+        assert_eq!(encode(|w| w.uint(1000)), [0x19, 0x03, 0xE8]);
+        assert_eq!(
+            encode(|w| w.uint(1_000_000)),
+            [0x1A, 0x00, 0x0F, 0x42, 0x40]
+        );
+        assert_eq!(encode(|w| w.bytes(&[1, 2, 3, 4])), [0x44, 1, 2, 3, 4]);
+        assert_eq!(encode(|w| w.f32(1.5)), [0xF9, 0x3E, 0x00]);
+        assert_eq!(encode(|w| w.f32(5.960_464_5e-8)), [0xF9, 0x00, 0x01]);
+        assert_eq!(encode(|w| w.f32(100_000.0)), [0xFA, 0x47, 0xC3, 0x50, 0x00]);
+        assert_eq!(encode(|w| w.f32(0.1)), [0xFA, 0x3D, 0xCC, 0xCC, 0xCD]);
+    }
+
+    #[test]
+    fn test_float_roundtrip() {
+        for value in [0.0f32, -0.0, 0.05, 0.125, 0.2, 0.25, 65504.0, 6.1e-5, 1e-7] {
+            let mut writer = Writer::default();
+            writer.f32(value);
+            let bytes = writer.into_bytes();
+            let mut reader = Reader::new(&bytes);
+            assert_eq!(reader.f32().unwrap().to_bits(), value.to_bits(), "{value}");
+            reader.finish().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reader_rejects_non_canonical_input() {
+        assert_eq!(
+            Reader::new(&[0x18, 0x05]).uint(),
+            Err(Error::InvalidParameter("non-canonical CBOR integer"))
+        );
+        assert_eq!(
+            Reader::new(&[0x5F]).bytes(),
+            Err(Error::InvalidParameter(
+                "indefinite or reserved CBOR length"
+            ))
+        );
+        assert_eq!(
+            Reader::new(&[0xFA, 0x3E, 0x00, 0x00, 0x00]).f32(),
+            Err(Error::InvalidParameter("non-canonical CBOR float"))
+        );
+        assert_eq!(
+            Reader::new(&[0x44, 1, 2]).bytes(),
+            Err(Error::InvalidParameter("truncated CBOR"))
+        );
+        assert_eq!(
+            Reader::new(&[0x01, 0x02]).finish(),
+            Err(Error::InvalidParameter("trailing bytes after CBOR item"))
+        );
+        let mut reader = Reader::new(&[0xA1, 0x02, 0x00]);
+        reader.map_of(1).unwrap();
+        assert_eq!(
+            reader.key(1),
+            Err(Error::InvalidParameter("unexpected CBOR map key"))
+        );
+    }
+}
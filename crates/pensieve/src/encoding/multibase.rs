@@ -0,0 +1,235 @@
+//! Multibase: bytes as text, prefixed with a character naming the base.
+//!
+//! Only the unpadded, single-case bases that survive URLs, file names and
+//! case-insensitive storage are supported; every other prefix is rejected.
+
+use crate::error::Error;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const BASE16: &[u8; 16] = b"0123456789abcdef";
+const BASE32: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+const BASE58: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// A supported multibase encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Base {
+    /// Lowercase hexadecimal, prefix `f`.
+    Base16,
+    /// RFC 4648 lowercase base32 without padding, prefix `b` (the CIDv1
+    /// default).
+    Base32,
+    /// Bitcoin's base58 alphabet, prefix `z` (the CIDv0 style).
+    Base58Btc,
+    /// RFC 4648 URL-safe base64 without padding, prefix `u`.
+    Base64Url,
+}
+
+impl Base {
+    /// The character that introduces this base in a multibase string.
+    pub fn prefix(self) -> char {
+        match self {
+            Base::Base16 => 'f',
+            Base::Base32 => 'b',
+            Base::Base58Btc => 'z',
+            Base::Base64Url => 'u',
+        }
+    }
+
+    /// The base introduced by `prefix`, if supported.
+    pub fn from_prefix(prefix: char) -> Option<Self> {
+        match prefix {
+            'f' => Some(Base::Base16),
+            'b' => Some(Base::Base32),
+            'z' => Some(Base::Base58Btc),
+            'u' => Some(Base::Base64Url),
+            _ => None,
+        }
+    }
+}
+
+/// Encodes `bytes` in `base`, prefix included.
+pub fn encode(base: Base, bytes: &[u8]) -> String {
+    let mut out = String::new();
+    out.push(base.prefix());
+    match base {
+        Base::Base16 => encode_bits(bytes, BASE16, 4, &mut out),
+        Base::Base32 => encode_bits(bytes, BASE32, 5, &mut out),
+        Base::Base58Btc => encode_base58(bytes, &mut out),
+        Base::Base64Url => encode_bits(bytes, BASE64URL, 6, &mut out),
+    }
+    out
+}
+
+/// Decodes a multibase string into its base and bytes.
+///
+/// Fails with [`Error::InvalidCharacter`] for characters outside the base's
+/// alphabet (the prefix is position 0), and with [`Error::InvalidParameter`]
+/// for an unsupported prefix or a non-canonical encoding, i.e. trailing bits
+/// that a conforming encoder would not have produced.
+pub fn decode(text: &str) -> Result<(Base, Vec<u8>), Error> {
+    let prefix = text
+        .chars()
+        .next()
+        .ok_or(Error::InvalidParameter("empty multibase string"))?;
+    let base = Base::from_prefix(prefix).ok_or(Error::InvalidParameter("unsupported multibase"))?;
+    let alphabet: &[u8] = match base {
+        Base::Base16 => BASE16,
+        Base::Base32 => BASE32,
+        Base::Base58Btc => BASE58,
+        Base::Base64Url => BASE64URL,
+    };
+    let digits = text
+        .chars()
+        .enumerate()
+        .skip(1)
+        .map(|(position, c)| {
+            alphabet
+                .iter()
+                .position(|&a| char::from(a) == c)
+                .map(|d| d as u8)
+                .ok_or(Error::InvalidCharacter { position })
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+    let bytes = match base {
+        Base::Base16 => decode_bits(&digits, 4)?,
+        Base::Base32 => decode_bits(&digits, 5)?,
+        Base::Base58Btc => decode_base58(&digits),
+        Base::Base64Url => decode_bits(&digits, 6)?,
+    };
+    Ok((base, bytes))
+}
+
+/// Encodes with a power-of-two alphabet of `width` bits per character,
+/// zero-padding the last character.
+fn encode_bits(bytes: &[u8], alphabet: &[u8], width: u32, out: &mut String) {
+    let mask = (1u32 << width) - 1;
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for &byte in bytes {
+        acc = acc << 8 | u32::from(byte);
+        bits += 8;
+        while bits >= width {
+            bits -= width;
+            out.push(alphabet[(acc >> bits & mask) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(alphabet[(acc << (width - bits) & mask) as usize] as char);
+    }
+}
+
+/// Inverse of [`encode_bits`] over already-looked-up digit values.
+fn decode_bits(digits: &[u8], width: u32) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::with_capacity(digits.len() * width as usize / 8);
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for &digit in digits {
+        acc = (acc << width | u32::from(digit)) & 0xFFFF;
+        bits += width;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    // Leftover bits are padding: fewer than a character's worth, all zero.
+    if bits >= width || acc & ((1 << bits) - 1) != 0 {
+        return Err(Error::InvalidParameter("non-canonical multibase encoding"));
+    }
+    Ok(out)
+}
+
+/// Base58 by repeated division; leading zero bytes become leading `1`s.
+fn encode_base58(bytes: &[u8], out: &mut String) {
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+    // Little-endian base-58 digits of the remaining big-endian number.
+    let mut digits: Vec<u8> = Vec::with_capacity(bytes.len() * 138 / 100 + 1);
+    for &byte in &bytes[zeros..] {
+        let mut carry = u32::from(byte);
+        for digit in digits.iter_mut() {
+            carry += u32::from(*digit) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    out.extend(core::iter::repeat_n('1', zeros));
+    out.extend(digits.iter().rev().map(|&d| BASE58[d as usize] as char));
+}
+
+/// Inverse of [`encode_base58`] over already-looked-up digit values.
+fn decode_base58(digits: &[u8]) -> Vec<u8> {
+    let zeros = digits.iter().take_while(|&&d| d == 0).count();
+    // Little-endian bytes of the number.
+    let mut bytes: Vec<u8> = Vec::with_capacity(digits.len() * 733 / 1000 + 1);
+    for &digit in &digits[zeros..] {
+        let mut carry = u32::from(digit);
+        for byte in bytes.iter_mut() {
+            carry += u32::from(*byte) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    bytes.extend(core::iter::repeat_n(0, zeros));
+    bytes.reverse();
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_vectors() {
+        // From the multibase specification's test suite.
+        let bytes = b"yes mani !";
+        assert_eq!(encode(Base::Base16, bytes), "f796573206d616e692021");
+        assert_eq!(encode(Base::Base32, bytes), "bpfsxgidnmfxgsibb");
+        assert_eq!(encode(Base::Base58Btc, bytes), "z7paNL19xttacUY");
+        assert_eq!(encode(Base::Base64Url, bytes), "ueWVzIG1hbmkgIQ");
+        assert_eq!(
+            encode(Base::Base58Btc, b"\0\0yes mani !"),
+            "z117paNL19xttacUY"
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_all_bases() {
+        for base in [Base::Base16, Base::Base32, Base::Base58Btc, Base::Base64Url] {
+            for len in 0..40 {
+                let bytes: Vec<u8> = (0..len).map(|i| (i * 73 + len) as u8 % 7).collect();
+                let text = encode(base, &bytes);
+                assert_eq!(decode(&text), Ok((base, bytes)), "{base:?}, {len} bytes");
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_input() {
+        assert_eq!(
+            decode(""),
+            Err(Error::InvalidParameter("empty multibase string"))
+        );
+        assert_eq!(
+            decode("m eWVz"),
+            Err(Error::InvalidParameter("unsupported multibase"))
+        );
+        assert_eq!(
+            decode("z7paNL0"),
+            Err(Error::InvalidCharacter { position: 6 })
+        );
+        assert_eq!(decode("fAB"), Err(Error::InvalidCharacter { position: 1 }));
+        assert_eq!(decode("béa"), Err(Error::InvalidCharacter { position: 1 }));
+        // One base32 character of data carries no full byte.
+        assert!(decode("bp").is_err());
+        // `f` odd length and non-zero padding bits are not canonical.
+        assert!(decode("f7").is_err());
+        assert!(decode("ueWVzIG1hbmkgIR").is_err());
+    }
+}
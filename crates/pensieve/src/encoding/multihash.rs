@@ -0,0 +1,183 @@
+//! Multihash framing: `varint(code) || varint(length) || digest`.
+
+use super::multibase::{self, Base};
+use crate::error::Error;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Multihash code of a Pensieve digest.
+///
+/// Taken from the private-use range `0x300000..=0x3FFFFF` of the multicodec
+/// table: a collapsed digest is a similarity fingerprint, not a
+/// collision-resistant hash, and must not claim a registered hash code.
+pub const PENSIEVE_MULTIHASH_CODE: u64 = 0x30_5053;
+
+/// Multihash varints are at most 9 bytes (63 bits).
+const MAX_VARINT_LEN: usize = 9;
+
+/// A digest tagged with its multihash code.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Multihash {
+    code: u64,
+    digest: Vec<u8>,
+}
+
+impl Multihash {
+    /// Tags `digest` with an arbitrary multihash `code`.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `code` does not fit the
+    /// 63 bits a multihash varint can carry.
+    pub fn new(code: u64, digest: &[u8]) -> Result<Self, Error> {
+        if code >> 63 != 0 {
+            return Err(Error::InvalidParameter("multihash code exceeds 63 bits"));
+        }
+        Ok(Self {
+            code,
+            digest: digest.to_vec(),
+        })
+    }
+
+    /// Tags a collapsed digest with [`PENSIEVE_MULTIHASH_CODE`].
+    pub fn pensieve(digest: &[u8]) -> Self {
+        Self {
+            code: PENSIEVE_MULTIHASH_CODE,
+            digest: digest.to_vec(),
+        }
+    }
+
+    /// The multihash code.
+    pub fn code(&self) -> u64 {
+        self.code
+    }
+
+    /// The digest without framing.
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    /// Serializes as `varint(code) || varint(length) || digest`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(2 * MAX_VARINT_LEN + self.digest.len());
+        write_varint(self.code, &mut out);
+        write_varint(self.digest.len() as u64, &mut out);
+        out.extend_from_slice(&self.digest);
+        out
+    }
+
+    /// Parses the output of [`Multihash::to_bytes`], whatever its code.
+    ///
+    /// Fails with [`Error::InvalidParameter`] for malformed varints and with
+    /// [`Error::InvalidLength`] when the digest is not exactly as long as
+    /// its length prefix says.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let (code, rest) = read_varint(bytes)?;
+        let (len, digest) = read_varint(rest)?;
+        if digest.len() as u64 != len {
+            return Err(Error::InvalidLength {
+                expected: usize::try_from(len).unwrap_or(usize::MAX),
+                found: digest.len(),
+            });
+        }
+        Ok(Self {
+            code,
+            digest: digest.to_vec(),
+        })
+    }
+
+    /// The serialized multihash as a multibase string.
+    pub fn to_multibase(&self, base: Base) -> String {
+        multibase::encode(base, &self.to_bytes())
+    }
+
+    /// Parses the output of [`Multihash::to_multibase`] in any supported base.
+    pub fn from_multibase(text: &str) -> Result<Self, Error> {
+        let (_, bytes) = multibase::decode(text)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+/// Appends `value` as an unsigned LEB128 varint.
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a minimally encoded varint of at most [`MAX_VARINT_LEN`] bytes and
+/// returns it with the remaining input.
+fn read_varint(bytes: &[u8]) -> Result<(u64, &[u8]), Error> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate().take(MAX_VARINT_LEN) {
+        value |= u64::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            // A trailing zero group means the same value fits in fewer bytes.
+            if byte == 0 && i > 0 {
+                return Err(Error::InvalidParameter("non-minimal multihash varint"));
+            }
+            return Ok((value, &bytes[i + 1..]));
+        }
+    }
+    Err(Error::InvalidParameter(if bytes.len() < MAX_VARINT_LEN {
+        "truncated multihash varint"
+    } else {
+        "multihash varint too long"
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_sha256_multihash() {
+        // The SHA-256 multihash of "hello world" from the multihash spec.
+        let digest = crate::crypto::sha256::Sha256::digest(&[b"hello world".as_slice()]);
+        let multihash = Multihash::new(0x12, &digest).unwrap();
+        let bytes = multihash.to_bytes();
+        assert_eq!(&bytes[..2], &[0x12, 0x20]);
+        assert_eq!(
+            multihash.to_multibase(Base::Base58Btc),
+            "zQmaozNR7DZHQK1ZcU9p7QdrshMvXqWK6gpu5rmrkPdT3L4"
+        );
+        assert_eq!(Multihash::from_bytes(&bytes), Ok(multihash));
+    }
+
+    #[test]
+    fn test_pensieve_code_roundtrip() {
+        let digest = crate::collapse_ct(&[0xA5; 32], 0.2);
+        let multihash = Multihash::pensieve(&digest);
+        // 0x305053 needs four varint bytes.
+        assert_eq!(&multihash.to_bytes()[..4], &[0xD3, 0xA0, 0xC1, 0x01]);
+        for base in [Base::Base16, Base::Base32, Base::Base58Btc, Base::Base64Url] {
+            let decoded = Multihash::from_multibase(&multihash.to_multibase(base)).unwrap();
+            assert_eq!(decoded.code(), PENSIEVE_MULTIHASH_CODE);
+            assert_eq!(decoded.digest(), digest);
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_bad_framing() {
+        assert_eq!(
+            Multihash::from_bytes(&[0x12, 0x02, 0xAA]),
+            Err(Error::InvalidLength {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            Multihash::from_bytes(&[0x92]),
+            Err(Error::InvalidParameter("truncated multihash varint"))
+        );
+        assert_eq!(
+            Multihash::from_bytes(&[0x92, 0x00, 0x00]),
+            Err(Error::InvalidParameter("non-minimal multihash varint"))
+        );
+        assert_eq!(
+            Multihash::from_bytes(&[0xFF; 12]),
+            Err(Error::InvalidParameter("multihash varint too long"))
+        );
+        assert!(Multihash::new(1 << 63, &[]).is_err());
+    }
+}
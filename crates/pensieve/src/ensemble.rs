@@ -0,0 +1,408 @@
+//! Fusing several fuzzy-matching algorithms into one decision.
+//!
+//! Every algorithm has noise it cannot absorb. The collapse compares bits
+//! by position, so an inserted byte defeats it; content-defined chunks
+//! realign after an insertion but lose a whole chunk to a single flip in
+//! it; a SimHash of n-grams shrugs off both but also scores unrelated
+//! inputs with a shared vocabulary as similar. An [`Ensemble`] scores a
+//! pair with several algorithms, each a [`FuzzyCollapse`], and fuses their
+//! verdicts with a [`Fusion`] rule, so one algorithm's blind spot is
+//! covered by the others: requiring agreement cuts false accepts,
+//! accepting any match cuts false rejects, and a weighted mean of the
+//! scores trades the two off by its threshold.
+//!
+//! The algorithms provided are the collapse itself ([`TbfConfig`]),
+//! [`SimHashScorer`] and [`ChunkScorer`], a context-triggered piecewise
+//! hash over [`ChunkSet`]s. Scoring branches on the inputs; this is meant
+//! for public data.
+
+use crate::cdc::{ChunkSet, GearChunker};
+use crate::distance::hamming;
+use crate::simhash::SimHash;
+use crate::tokenize::Tokenizer;
+use crate::{Error, TbfConfig, similarity};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A fuzzy-matching algorithm, scoring how alike two inputs are.
+pub trait FuzzyCollapse {
+    /// Short name for reports, such as `"tbf"`.
+    fn name(&self) -> &'static str;
+
+    /// How alike `a` and `b` are, from 0.0 to 1.0, where 1.0 means the
+    /// algorithm cannot tell them apart.
+    fn score(&self, a: &[u8], b: &[u8]) -> f32;
+}
+
+impl FuzzyCollapse for TbfConfig {
+    fn name(&self) -> &'static str {
+        "tbf"
+    }
+
+    /// The [`similarity`] of `a` and `b`: 1.0 exactly when they collapse
+    /// identically.
+    fn score(&self, a: &[u8], b: &[u8]) -> f32 {
+        similarity(a, b, self)
+    }
+}
+
+/// Scores pairs by the Hamming distance between [`SimHash`]es of their
+/// features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimHashScorer<T> {
+    tokenizer: T,
+    bits: usize,
+}
+
+impl<T: Tokenizer> SimHashScorer<T> {
+    /// Scores with `bits`-bit SimHashes of the features `tokenizer`
+    /// yields.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless `bits` is a positive
+    /// multiple of 8.
+    pub fn new(tokenizer: T, bits: usize) -> Result<Self, Error> {
+        SimHash::new(bits)?;
+        Ok(Self { tokenizer, bits })
+    }
+
+    fn simhash(&self, input: &[u8]) -> Vec<u8> {
+        let mut simhash = SimHash::new(self.bits).expect("checked by new");
+        simhash.add_features(&self.tokenizer, input);
+        simhash.finish()
+    }
+}
+
+impl<T: Tokenizer> FuzzyCollapse for SimHashScorer<T> {
+    fn name(&self) -> &'static str {
+        "simhash"
+    }
+
+    /// The share of SimHash bits that agree.
+    fn score(&self, a: &[u8], b: &[u8]) -> f32 {
+        let distance = hamming(&self.simhash(a), &self.simhash(b));
+        1.0 - distance as f32 / self.bits as f32
+    }
+}
+
+/// Scores pairs by the content-defined chunks they share, in the manner of
+/// context-triggered piecewise hashing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkScorer {
+    chunker: GearChunker,
+    config: TbfConfig,
+}
+
+impl ChunkScorer {
+    /// Scores with chunks cut by `chunker` and collapsed under `config`.
+    pub fn new(chunker: GearChunker, config: TbfConfig) -> Self {
+        Self { chunker, config }
+    }
+}
+
+impl FuzzyCollapse for ChunkScorer {
+    fn name(&self) -> &'static str {
+        "chunks"
+    }
+
+    /// The [`ChunkSet::similarity`] of `a` and `b`.
+    fn score(&self, a: &[u8], b: &[u8]) -> f32 {
+        let set = |input| ChunkSet::new(input, &self.chunker, &self.config);
+        set(a).similarity(&set(b))
+    }
+}
+
+/// How an [`Ensemble`] turns its members' scores into one decision.
+///
+/// Under the voting rules a member votes for a match when its score
+/// reaches its threshold, and [`Ensemble::score`] is the weighted share of
+/// such votes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fusion {
+    /// A match when members holding more than half of the weight vote for
+    /// one.
+    Majority,
+    /// A match when every member votes for one; fewest false accepts.
+    Unanimous,
+    /// A match when any member votes for one; fewest false rejects.
+    Any,
+    /// A match when the weighted mean of the scores, which is
+    /// [`Ensemble::score`], reaches `threshold`; member thresholds are
+    /// ignored.
+    WeightedMean {
+        /// Smallest fused score of a match.
+        threshold: f32,
+    },
+}
+
+/// One algorithm of an [`Ensemble`].
+struct Member {
+    algorithm: Box<dyn FuzzyCollapse>,
+    weight: f32,
+    threshold: f32,
+}
+
+/// One member's verdict on a pair, returned by [`Ensemble::votes`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vote {
+    /// The member's [`FuzzyCollapse::name`].
+    pub name: &'static str,
+    /// The member's score.
+    pub score: f32,
+    /// Whether the score reaches the member's threshold.
+    pub matched: bool,
+}
+
+/// Several [`FuzzyCollapse`] algorithms deciding together.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::cdc::GearChunker;
+/// use pensieve::ensemble::{ChunkScorer, Ensemble, Fusion, SimHashScorer};
+/// use pensieve::tokenize::ByteNgrams;
+///
+/// let config = TbfConfig::default();
+/// let ensemble = Ensemble::new(Fusion::Majority)
+///     .with(config, 1.0, 1.0)
+///     .unwrap()
+///     .with(SimHashScorer::new(ByteNgrams::new(4).unwrap(), 256).unwrap(), 1.0, 0.85)
+///     .unwrap()
+///     .with(ChunkScorer::new(GearChunker::new(16, 32, 256).unwrap(), config), 1.0, 0.5)
+///     .unwrap();
+///
+/// let report: Vec<u8> = (0..2000u32).flat_map(|i| (i * 7919 % 251).to_le_bytes()).collect();
+/// let mut edited = report.clone();
+/// edited.insert(100, b'!'); // Shifts every later byte.
+/// assert!(ensemble.matches(&report, &edited));
+/// assert!(!ensemble.matches(&report, &[0x55; 8000]));
+/// ```
+pub struct Ensemble {
+    members: Vec<Member>,
+    fusion: Fusion,
+}
+
+impl Ensemble {
+    /// An ensemble without members, deciding by `fusion`.
+    pub fn new(fusion: Fusion) -> Self {
+        Self {
+            members: Vec::new(),
+            fusion,
+        }
+    }
+
+    /// This ensemble with `algorithm` as another member, its vote counting
+    /// `weight` and going to a match from a score of `threshold` on.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless `weight` is positive
+    /// and finite and `threshold` is in 0.0..=1.0.
+    pub fn with(
+        mut self,
+        algorithm: impl FuzzyCollapse + 'static,
+        weight: f32,
+        threshold: f32,
+    ) -> Result<Self, Error> {
+        if !(weight.is_finite() && weight > 0.0) {
+            return Err(Error::InvalidParameter(
+                "member weights must be positive and finite",
+            ));
+        }
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(Error::InvalidParameter(
+                "member thresholds must be in 0.0..=1.0",
+            ));
+        }
+        self.members.push(Member {
+            algorithm: Box::new(algorithm),
+            weight,
+            threshold,
+        });
+        Ok(self)
+    }
+
+    /// The fusion rule.
+    pub fn fusion(&self) -> Fusion {
+        self.fusion
+    }
+
+    /// Number of members.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Whether the ensemble has no members.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Every member's verdict on `a` and `b`, in the order they were added.
+    pub fn votes(&self, a: &[u8], b: &[u8]) -> Vec<Vote> {
+        self.members
+            .iter()
+            .map(|member| {
+                let score = member.algorithm.score(a, b);
+                Vote {
+                    name: member.algorithm.name(),
+                    score,
+                    matched: score >= member.threshold,
+                }
+            })
+            .collect()
+    }
+
+    /// The fused score of `a` and `b`, from 0.0 to 1.0: the weighted mean
+    /// of the scores under [`Fusion::WeightedMean`], the weighted share of
+    /// votes for a match otherwise. 0.0 without members.
+    pub fn score(&self, a: &[u8], b: &[u8]) -> f32 {
+        self.fuse(&self.votes(a, b)).0
+    }
+
+    /// Whether the ensemble decides that `a` and `b` match. An ensemble
+    /// without members matches nothing.
+    pub fn matches(&self, a: &[u8], b: &[u8]) -> bool {
+        self.fuse(&self.votes(a, b)).1
+    }
+
+    /// The fused score and decision of `votes`.
+    fn fuse(&self, votes: &[Vote]) -> (f32, bool) {
+        if votes.is_empty() {
+            return (0.0, false);
+        }
+        let total: f32 = self.members.iter().map(|member| member.weight).sum();
+        let weighted = |value: &dyn Fn(&Vote) -> f32| -> f32 {
+            self.members
+                .iter()
+                .zip(votes)
+                .map(|(member, vote)| member.weight * value(vote))
+                .sum::<f32>()
+                / total
+        };
+        if let Fusion::WeightedMean { threshold } = self.fusion {
+            let score = weighted(&|vote| vote.score);
+            return (score, score >= threshold);
+        }
+        let share = weighted(&|vote| f32::from(u8::from(vote.matched)));
+        let decision = match self.fusion {
+            Fusion::Majority => share > 0.5,
+            Fusion::Unanimous => votes.iter().all(|vote| vote.matched),
+            _ => votes.iter().any(|vote| vote.matched),
+        };
+        (share, decision)
+    }
+}
+
+impl fmt::Debug for Ensemble {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ensemble")
+            .field(
+                "members",
+                &self
+                    .members
+                    .iter()
+                    .map(|member| (member.algorithm.name(), member.weight, member.threshold))
+                    .collect::<Vec<_>>(),
+            )
+            .field("fusion", &self.fusion)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::EntropySource;
+    use crate::noise::Seeded;
+    use crate::tokenize::ByteNgrams;
+    use alloc::vec;
+
+    /// A member scoring every pair the same.
+    struct Fixed(f32);
+
+    impl FuzzyCollapse for Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn score(&self, _: &[u8], _: &[u8]) -> f32 {
+            self.0
+        }
+    }
+
+    fn ensemble(fusion: Fusion, scores: &[(f32, f32)]) -> Ensemble {
+        scores
+            .iter()
+            .fold(Ensemble::new(fusion), |ensemble, &(score, weight)| {
+                ensemble.with(Fixed(score), weight, 0.5).unwrap()
+            })
+    }
+
+    #[test]
+    fn test_fusion_rules() {
+        let members = [(0.9, 1.0), (0.2, 1.0), (0.6, 1.0)];
+        let decide = |fusion| {
+            let ensemble = ensemble(fusion, &members);
+            (ensemble.score(b"", b""), ensemble.matches(b"", b""))
+        };
+        assert_eq!(decide(Fusion::Majority), (2.0 / 3.0, true));
+        assert_eq!(decide(Fusion::Unanimous), (2.0 / 3.0, false));
+        assert_eq!(decide(Fusion::Any), (2.0 / 3.0, true));
+        let mean = decide(Fusion::WeightedMean { threshold: 0.6 });
+        assert!((mean.0 - 17.0 / 30.0).abs() < 1e-6);
+        assert!(!mean.1);
+
+        // A heavy dissenter outvotes the rest.
+        let heavy = ensemble(Fusion::Majority, &[(0.9, 1.0), (0.6, 1.0), (0.1, 3.0)]);
+        assert!(!heavy.matches(b"", b""));
+        assert_eq!(heavy.score(b"", b""), 0.4);
+
+        let empty = Ensemble::new(Fusion::Any);
+        assert!(empty.is_empty());
+        assert_eq!(
+            (empty.score(b"a", b"a"), empty.matches(b"a", b"a")),
+            (0.0, false)
+        );
+        assert!(
+            Ensemble::new(Fusion::Any)
+                .with(Fixed(1.0), 0.0, 0.5)
+                .is_err()
+        );
+        assert!(
+            Ensemble::new(Fusion::Any)
+                .with(Fixed(1.0), 1.0, 1.5)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_members_cover_each_others_blind_spots() {
+        let config = TbfConfig::default();
+        let chunks = ChunkScorer::new(GearChunker::new(16, 32, 256).unwrap(), config);
+        let mut text = vec![0u8; 4000];
+        Seeded::new(7).fill_bytes(&mut text);
+        // An insertion shifts the bit positions the collapse compares.
+        let mut inserted = text.clone();
+        inserted.insert(10, 0);
+        assert!(config.score(&text, &inserted) < 1.0);
+        assert!(chunks.score(&text, &inserted) > 0.9);
+
+        let any = Ensemble::new(Fusion::Any)
+            .with(config, 1.0, 1.0)
+            .unwrap()
+            .with(chunks, 1.0, 0.8)
+            .unwrap();
+        assert!(any.matches(&text, &inserted));
+        let votes = any.votes(&text, &inserted);
+        assert_eq!(
+            votes
+                .iter()
+                .map(|v| (v.name, v.matched))
+                .collect::<Vec<_>>(),
+            [("tbf", false), ("chunks", true)]
+        );
+
+        let simhash = SimHashScorer::new(ByteNgrams::new(3).unwrap(), 128).unwrap();
+        assert_eq!(simhash.score(&text, &text), 1.0);
+        assert!(SimHashScorer::new(ByteNgrams::new(3).unwrap(), 12).is_err());
+        assert!(alloc::format!("{any:?}").contains("chunks"));
+    }
+}
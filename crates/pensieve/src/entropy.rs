@@ -0,0 +1,66 @@
+//! Sources of randomness for the protocol modules.
+//!
+//! The crate has no RNG dependency, so every API that needs fresh randomness
+//! (salts, ephemeral scalars) takes an [`EntropySource`]. Any closure
+//! `FnMut(&mut [u8])` qualifies, which keeps deterministic tests trivial;
+//! production code on Unix can use `OsEntropy` (feature `std`).
+
+/// A source of uniformly random bytes.
+///
+/// Implementations used for anything but tests must be cryptographically secure.
+pub trait EntropySource {
+    /// Fills `dest` entirely with random bytes.
+    fn fill_bytes(&mut self, dest: &mut [u8]);
+}
+
+impl<F: FnMut(&mut [u8])> EntropySource for F {
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self(dest)
+    }
+}
+
+/// The operating system's CSPRNG, read from `/dev/urandom`.
+#[cfg(all(unix, feature = "std"))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OsEntropy;
+
+#[cfg(all(unix, feature = "std"))]
+impl EntropySource for OsEntropy {
+    /// # Panics
+    /// Panics if `/dev/urandom` cannot be read, since continuing without
+    /// randomness would silently produce predictable keys.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        use std::io::Read;
+        std::fs::File::open("/dev/urandom")
+            .and_then(|mut f| f.read_exact(dest))
+            .expect("failed to read from /dev/urandom");
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    /// A deterministic, seeded byte source (SplitMix64) for reproducible tests.
+    pub(crate) fn seeded(seed: u64) -> impl FnMut(&mut [u8]) {
+        let mut state = seed;
+        move |dest: &mut [u8]| {
+            for chunk in dest.chunks_mut(8) {
+                state = state.wrapping_add(0x9E3779B97F4A7C15);
+                let mut z = state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+                z ^= z >> 31;
+                chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_seeded_is_reproducible() {
+        let (mut a, mut b) = ([0u8; 20], [0u8; 20]);
+        seeded(42)(&mut a);
+        seeded(42)(&mut b);
+        assert_eq!(a, b);
+        seeded(43)(&mut b);
+        assert_ne!(a, b);
+    }
+}
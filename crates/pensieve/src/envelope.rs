@@ -0,0 +1,524 @@
+//! Self-describing storage format for collapsed digests.
+//!
+//! A digest is only meaningful together with the parameters that produced
+//! it. A [`DigestEnvelope`] carries both, so a stored fingerprint can be
+//! re-verified years later without out-of-band configuration:
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 4 | magic `PNSD` |
+//! | 1 | envelope format version (currently 4) |
+//! | 1 | algorithm version ([`TbfConfig::ALGORITHM_VERSION`]) |
+//! | 1 | bit order ([`BitOrder::id`]) |
+//! | 1 | output transform ([`FinalTransform::id`]) |
+//! | 1 | mask base of [`FinalTransform::XorMask`], otherwise 0 |
+//! | 1 | thresholding ([`Thresholding::id`]) |
+//! | 4 | tolerance, f32 little-endian |
+//! | 4 | digest length in bytes, little-endian |
+//! | 4 | chunk size in bits, little-endian (0 for an empty digest) |
+//! | 4 | chunk threshold, little-endian (0 for an empty digest or [`Thresholding::Parity`] and [`Thresholding::Mean`]) |
+//! | n | digest |
+//! | 4 | first 4 bytes of SHA-256 over everything above |
+//!
+//! The chunk parameters are redundant with the length and tolerance; they
+//! are stored so that a decoder can check its own implementation derives
+//! the same chunking before trusting a comparison.
+//!
+//! The key of [`FinalTransform::KeyedPrf`] is never stored; such envelopes
+//! are decoded with [`DigestEnvelope::decode_keyed`].
+//!
+//! Services that compare stored digests under one fixed configuration
+//! should decode them with [`DigestEnvelope::decode_expecting`], which
+//! rejects envelopes declaring any other parameters.
+//!
+//! A [`tag`](DigestEnvelope::tag) authenticates an envelope under a key,
+//! so that digests stored where others can write, such as a shared
+//! database, cannot be replaced to force false matches.
+//!
+//! Format version 1 lacks the bit order byte and implies
+//! [`BitOrder::MsbFirst`]; versions 1 and 2 lack the transform bytes and
+//! imply the default transform; versions 1 to 3 lack the thresholding byte
+//! and imply [`Thresholding::OneSided`]. Such envelopes are still decoded.
+
+use crate::crypto::ct_eq;
+use crate::crypto::hmac::HmacSha256;
+use crate::header::{self, CHECKSUM_LEN, Format};
+use crate::{BitOrder, ChunkLayout, Error, FinalTransform, TbfConfig, Thresholding};
+use alloc::vec::Vec;
+
+/// Header of every envelope.
+const FORMAT: Format = Format {
+    magic: *b"PNSD",
+    version: 4,
+    foreign: "not a pensieve digest envelope",
+    unsupported: "unsupported envelope format version",
+    needs_key: "keyed transform needs decode_keyed",
+};
+/// Bytes before the digest.
+const HEADER_LEN: usize = 26;
+/// Domain separation for tags.
+const TAG_INFO: &[u8] = b"pensieve digest tag";
+
+/// A collapsed digest bundled with the configuration that produced it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestEnvelope {
+    config: TbfConfig,
+    digest: Vec<u8>, // Output of `TbfConfig::collapse` under `config`.
+}
+
+impl DigestEnvelope {
+    /// Collapses `input` under `config` and wraps the result.
+    pub fn seal(input: &[u8], config: &TbfConfig) -> Self {
+        Self {
+            config: *config,
+            digest: config.collapse(input),
+        }
+    }
+
+    /// Wraps a digest produced under `config`.
+    pub(crate) fn from_digest(config: &TbfConfig, digest: Vec<u8>) -> Self {
+        Self {
+            config: *config,
+            digest,
+        }
+    }
+
+    /// The configuration the digest was produced with.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// The collapsed digest.
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    /// Whether `input` collapses to the stored digest under the stored
+    /// configuration. The comparison is constant-time.
+    pub fn verify(&self, input: &[u8]) -> bool {
+        input.len() == self.digest.len() && ct_eq(&self.config.collapse(input), &self.digest)
+    }
+
+    /// [`DigestEnvelope::verify`], but only if the envelope was produced
+    /// under exactly `config`; a digest made with other parameters never
+    /// verifies, even if its input would collapse the same.
+    pub fn verify_with(&self, input: &[u8], config: &TbfConfig) -> bool {
+        self.config == *config && self.verify(input)
+    }
+
+    /// Serializes in the format described in the [module documentation](self).
+    pub fn encode(&self) -> Vec<u8> {
+        let (chunk_size, threshold) = chunk_params(self.digest.len(), &self.config);
+        let mut out = FORMAT.write(&self.config, HEADER_LEN + self.digest.len() + CHECKSUM_LEN);
+        out.extend_from_slice(&(self.digest.len() as u32).to_le_bytes());
+        out.extend_from_slice(&chunk_size.to_le_bytes());
+        out.extend_from_slice(&threshold.to_le_bytes());
+        out.extend_from_slice(&self.digest);
+        header::seal(&mut out);
+        out
+    }
+
+    /// HMAC-SHA256 under `key` of the [`encode`](Self::encode)d envelope,
+    /// covering the digest and every parameter it was produced with. The
+    /// key of a [`FinalTransform::KeyedPrf`] is not covered; it may double
+    /// as the tag key.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::envelope::DigestEnvelope;
+    ///
+    /// let key = b"server-side tag key";
+    /// let stored = DigestEnvelope::seal(&[0x5A; 16], &TbfConfig::new(0.125));
+    /// let tag = stored.tag(key);
+    ///
+    /// // Replacing the stored digest with one that matches another input
+    /// // does not go unnoticed.
+    /// let forged = DigestEnvelope::seal(&[0x00; 16], &TbfConfig::new(0.125));
+    /// assert!(stored.verify_tag(key, &tag));
+    /// assert!(!forged.verify_tag(key, &tag));
+    /// ```
+    pub fn tag(&self, key: &[u8]) -> [u8; 32] {
+        tag_encoded(key, [self.encode()])
+    }
+
+    /// Whether `tag` is the [`tag`](Self::tag) of the envelope under
+    /// `key`. The comparison is constant-time.
+    pub fn verify_tag(&self, key: &[u8], tag: &[u8]) -> bool {
+        ct_eq(&self.tag(key), tag)
+    }
+
+    /// Parses the output of [`DigestEnvelope::encode`].
+    ///
+    /// Fails with [`Error::InvalidChecksum`] on corruption and with
+    /// [`Error::InvalidParameter`] for envelopes this build cannot interpret:
+    /// unknown format or algorithm versions, unsupported tolerances, bit
+    /// orders, thresholdings or transforms, or chunk parameters that disagree with this
+    /// implementation. Envelopes of [`FinalTransform::KeyedPrf`] digests
+    /// need [`DigestEnvelope::decode_keyed`].
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        Self::decode_with(bytes, None)
+    }
+
+    /// [`DigestEnvelope::decode`] for envelopes that may hold a
+    /// [`FinalTransform::KeyedPrf`] digest, whose key is `key`. A wrong key
+    /// is not detected here; [`DigestEnvelope::verify`] then fails.
+    pub fn decode_keyed(bytes: &[u8], key: &[u8; 32]) -> Result<Self, Error> {
+        Self::decode_with(bytes, Some(key))
+    }
+
+    /// [`DigestEnvelope::decode`] for envelopes that must have been produced
+    /// under `expected`, taking the key of a [`FinalTransform::KeyedPrf`]
+    /// from it. Fails with [`Error::InvalidParameter`] if the envelope
+    /// declares any other tolerance, bit order, thresholding or transform, so stored
+    /// fingerprints made with incompatible settings are rejected up front
+    /// instead of silently never matching.
+    pub fn decode_expecting(bytes: &[u8], expected: &TbfConfig) -> Result<Self, Error> {
+        let key = match expected.transform() {
+            FinalTransform::KeyedPrf { key } => Some(key),
+            _ => None,
+        };
+        let envelope = Self::decode_with(bytes, key.as_ref())?;
+        if envelope.config != *expected {
+            return Err(Error::InvalidParameter(
+                "envelope parameters differ from the expected configuration",
+            ));
+        }
+        Ok(envelope)
+    }
+
+    fn decode_with(bytes: &[u8], key: Option<&[u8; 32]>) -> Result<Self, Error> {
+        // Older headers lack fields; everything after them starts at `at`.
+        let at = match bytes.get(4) {
+            Some(1) => 6,
+            Some(2) => 7,
+            Some(3) => 9,
+            _ => 10,
+        };
+        let header_len = at + 16;
+        if bytes.len() < header_len + CHECKSUM_LEN {
+            return Err(Error::InvalidLength {
+                expected: header_len + CHECKSUM_LEN,
+                found: bytes.len(),
+            });
+        }
+        if bytes[..4] != FORMAT.magic {
+            return Err(Error::InvalidParameter(FORMAT.foreign));
+        }
+        let digest_len = read_u32(bytes, at + 4) as usize;
+        let expected = digest_len
+            .checked_add(header_len + CHECKSUM_LEN)
+            .ok_or(Error::InvalidParameter("digest length overflows"))?;
+        if bytes.len() != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+        let body = header::checked_body(bytes)?;
+
+        // The fields older headers lack take the values their absence implies.
+        let [id, base] = header::transform_bytes(&FinalTransform::default());
+        let implied = [
+            BitOrder::MsbFirst.id(),
+            id,
+            base,
+            Thresholding::OneSided.id(),
+        ];
+        let mut fields = [0u8; header::CONFIG_LEN];
+        fields[..at].copy_from_slice(&bytes[..at]);
+        fields[at..10].copy_from_slice(&implied[at - 6..]);
+        fields[10..].copy_from_slice(&bytes[at..at + 4]);
+        // Older digests are unchanged in later versions, except those of
+        // one-byte inputs and of thresholds rounded differently in `f32`,
+        // whose chunk parameters fail the check below.
+        let config = FORMAT.read_config(&fields, key)?;
+        if (read_u32(bytes, at + 8), read_u32(bytes, at + 12)) != chunk_params(digest_len, &config)
+        {
+            return Err(Error::InvalidParameter(
+                "chunk parameters do not match this implementation",
+            ));
+        }
+        Ok(Self {
+            config,
+            digest: body[header_len..].to_vec(),
+        })
+    }
+}
+
+/// `(chunk_size, threshold)` for a digest of `len` bytes, zeros when empty.
+fn chunk_params(len: usize, config: &TbfConfig) -> (u32, u32) {
+    ChunkLayout::for_config(len * 8, config).map_or((0, 0), |layout| {
+        (layout.chunk_size as u32, layout.threshold)
+    })
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().expect("4 bytes"))
+}
+
+/// HMAC-SHA256 under `key` of a sequence of encoded envelopes, each
+/// prefixed with its length.
+pub(crate) fn tag_encoded(key: &[u8], envelopes: impl IntoIterator<Item = Vec<u8>>) -> [u8; 32] {
+    let mut mac = HmacSha256::new(key);
+    mac.update(TAG_INFO);
+    for envelope in envelopes {
+        mac.update(&(envelope.len() as u64).to_le_bytes());
+        mac.update(&envelope);
+    }
+    mac.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sealed() -> DigestEnvelope {
+        sealed_with(&TbfConfig::new(0.125))
+    }
+
+    fn sealed_with(config: &TbfConfig) -> DigestEnvelope {
+        let mut input = [0u8; 16];
+        input[..2].fill(0xFF);
+        DigestEnvelope::seal(&input, config)
+    }
+
+    /// A 16-byte input that collapses like the one [`sealed`] holds.
+    fn noisy() -> [u8; 16] {
+        let mut noisy = [0u8; 16];
+        noisy[..2].fill(0xFF);
+        noisy[0] = 0b1111_1110;
+        noisy
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let envelope = sealed();
+        let bytes = envelope.encode();
+        assert_eq!(bytes.len(), HEADER_LEN + 16 + CHECKSUM_LEN);
+        assert_eq!(&bytes[..10], b"PNSD\x04\x05\x00\x01\xAA\x00");
+        // 8 chunks of 16 bits with threshold ceil(0.125 * 16) = 2.
+        assert_eq!((read_u32(&bytes, 18), read_u32(&bytes, 22)), (16, 2));
+        assert_eq!(DigestEnvelope::decode(&bytes), Ok(envelope));
+
+        let symmetric =
+            sealed_with(&TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric));
+        let bytes = symmetric.encode();
+        // Threshold 9, more than half of the chunk.
+        assert_eq!((bytes[9], read_u32(&bytes, 22)), (1, 9));
+        assert_eq!(DigestEnvelope::decode(&bytes), Ok(symmetric));
+
+        let empty = DigestEnvelope::seal(&[], &TbfConfig::default());
+        assert_eq!(DigestEnvelope::decode(&empty.encode()), Ok(empty));
+    }
+
+    #[test]
+    fn test_bit_order_is_recorded() {
+        let input = [0, 0, 0x01, 0, 0];
+        let config = TbfConfig::new(0.05).with_bit_order(BitOrder::LsbFirst);
+        let envelope = DigestEnvelope::decode(&DigestEnvelope::seal(&input, &config).encode());
+        assert_eq!(envelope.as_ref().map(|e| *e.config()), Ok(config));
+        assert!(envelope.unwrap().verify(&input));
+    }
+
+    #[test]
+    fn test_transform_is_recorded() {
+        let input = [0x0Fu8; 20];
+        for transform in [FinalTransform::Identity, FinalTransform::Hash] {
+            let config = TbfConfig::new(0.1).with_transform(transform);
+            let envelope = DigestEnvelope::seal(&input, &config);
+            assert_eq!(DigestEnvelope::decode(&envelope.encode()), Ok(envelope));
+        }
+
+        let keyed = TbfConfig::new(0.1).with_transform(FinalTransform::KeyedPrf { key: [7; 32] });
+        let bytes = DigestEnvelope::seal(&input, &keyed).encode();
+        assert_eq!(
+            DigestEnvelope::decode(&bytes),
+            Err(Error::InvalidParameter(
+                "keyed transform needs decode_keyed"
+            ))
+        );
+        assert!(
+            DigestEnvelope::decode_keyed(&bytes, &[7; 32])
+                .unwrap()
+                .verify(&input)
+        );
+        assert!(
+            !DigestEnvelope::decode_keyed(&bytes, &[8; 32])
+                .unwrap()
+                .verify(&input)
+        );
+    }
+
+    #[test]
+    fn test_decodes_older_format_versions() {
+        // Formats before 4 imply one-sided thresholding.
+        let envelope = sealed();
+        let mut v3 = envelope.encode();
+        v3.truncate(v3.len() - CHECKSUM_LEN);
+        v3[4] = 3;
+        v3[5] = 4; // Algorithm version 4 predates format version 4.
+        v3.remove(9);
+        let mut v2 = v3.clone();
+        v2[4] = 2;
+        v2.drain(7..9);
+        let mut v1 = v2.clone();
+        v1[4] = 1;
+        v1[5] = 1; // Algorithm version 1 predates format version 2.
+        v1.remove(6);
+        for mut old in [v3, v2, v1] {
+            header::seal(&mut old);
+            assert_eq!(DigestEnvelope::decode(&old), Ok(envelope.clone()));
+        }
+    }
+
+    #[test]
+    fn test_verify_uses_stored_parameters() {
+        let envelope = DigestEnvelope::decode(&sealed().encode()).unwrap();
+        assert!(envelope.verify(&noisy()));
+        assert!(!envelope.verify(&[0u8; 16]));
+        assert!(!envelope.verify(&[0u8; 17]));
+    }
+
+    #[test]
+    fn test_declared_parameters_must_match() {
+        let envelope = sealed();
+        let bytes = envelope.encode();
+        let noisy = noisy();
+        let expected = TbfConfig::new(0.125);
+        assert_eq!(
+            DigestEnvelope::decode_expecting(&bytes, &expected),
+            Ok(envelope.clone())
+        );
+        assert!(envelope.verify_with(&noisy, &expected));
+
+        // 0.12 collapses the same, but was not the declared tolerance.
+        for other in [
+            TbfConfig::new(0.12),
+            expected.with_bit_order(BitOrder::LsbFirst),
+            expected.with_thresholding(Thresholding::Symmetric),
+            expected.with_transform(FinalTransform::Hash),
+        ] {
+            assert!(!envelope.verify_with(&noisy, &other));
+            assert_eq!(
+                DigestEnvelope::decode_expecting(&bytes, &other),
+                Err(Error::InvalidParameter(
+                    "envelope parameters differ from the expected configuration"
+                ))
+            );
+        }
+
+        let keyed = expected.with_transform(FinalTransform::KeyedPrf { key: [7; 32] });
+        let bytes = DigestEnvelope::seal(&noisy, &keyed).encode();
+        assert!(
+            DigestEnvelope::decode_expecting(&bytes, &keyed)
+                .unwrap()
+                .verify_with(&noisy, &keyed)
+        );
+    }
+
+    #[test]
+    fn test_one_byte_digests_of_version_2_are_rejected() {
+        let envelope = DigestEnvelope::seal(&[0x42], &TbfConfig::new(0.125));
+        let bytes = envelope.encode();
+        // Eight 1-bit chunks since version 3.
+        assert_eq!((read_u32(&bytes, 18), read_u32(&bytes, 22)), (1, 1));
+        assert_eq!(DigestEnvelope::decode(&bytes), Ok(envelope));
+
+        // Version 2 folded the byte into one chunk with threshold 1.
+        let mut old = bytes[..bytes.len() - CHECKSUM_LEN].to_vec();
+        old[5] = 2;
+        old[18..22].copy_from_slice(&8u32.to_le_bytes());
+        header::seal(&mut old);
+        assert_eq!(
+            DigestEnvelope::decode(&old),
+            Err(Error::InvalidParameter(
+                "chunk parameters do not match this implementation"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_f32_thresholds_of_version_3_are_rejected() {
+        let config = TbfConfig::new(0.15);
+        let envelope = DigestEnvelope::seal(&[0x11; 100], &config);
+        let bytes = envelope.encode();
+        // 15% of 100 bits is exactly 15 since version 4.
+        assert_eq!((read_u32(&bytes, 18), read_u32(&bytes, 22)), (100, 15));
+        assert_eq!(DigestEnvelope::decode(&bytes), Ok(envelope));
+
+        // Version 3 computed 0.15 * 100 in `f32` and rounded up to 16.
+        let mut old = bytes[..bytes.len() - CHECKSUM_LEN].to_vec();
+        old[5] = 3;
+        old[22..26].copy_from_slice(&16u32.to_le_bytes());
+        header::seal(&mut old);
+        assert_eq!(
+            DigestEnvelope::decode(&old),
+            Err(Error::InvalidParameter(
+                "chunk parameters do not match this implementation"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_every_flipped_bit_and_truncation_is_detected() {
+        let bytes = sealed().encode();
+        for bit in 0..bytes.len() * 8 {
+            let mut rotten = bytes.clone();
+            rotten[bit / 8] ^= 0x80 >> (bit % 8);
+            assert!(DigestEnvelope::decode(&rotten).is_err(), "bit {bit}");
+        }
+        for len in 0..bytes.len() {
+            assert!(
+                DigestEnvelope::decode(&bytes[..len]).is_err(),
+                "{len} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_damage_and_unknown_parameters() {
+        let bytes = sealed().encode();
+        let mut flipped = bytes.clone();
+        flipped[HEADER_LEN] ^= 1;
+        assert_eq!(
+            DigestEnvelope::decode(&flipped),
+            Err(Error::InvalidChecksum)
+        );
+        assert!(DigestEnvelope::decode(&bytes[..bytes.len() - 1]).is_err());
+
+        // Re-checksum tampered headers so only the semantic checks can catch them.
+        let reseal = |edit: &dyn Fn(&mut Vec<u8>)| {
+            let mut tampered = bytes[..bytes.len() - CHECKSUM_LEN].to_vec();
+            edit(&mut tampered);
+            header::seal(&mut tampered);
+            DigestEnvelope::decode(&tampered)
+        };
+        assert_eq!(
+            reseal(&|b| b[5] = 6),
+            Err(Error::InvalidParameter("unsupported algorithm version"))
+        );
+        assert_eq!(
+            reseal(&|b| b[6] = 2),
+            Err(Error::InvalidParameter("unsupported bit order"))
+        );
+        assert_eq!(
+            reseal(&|b| b[7] = 4),
+            Err(Error::InvalidParameter("unsupported output transform"))
+        );
+        assert_eq!(
+            reseal(&|b| b[9] = 4),
+            Err(Error::InvalidParameter("unsupported thresholding"))
+        );
+        assert_eq!(
+            reseal(&|b| b[10..14].copy_from_slice(&0.5f32.to_le_bytes())),
+            Err(Error::InvalidParameter("unsupported tolerance"))
+        );
+        assert_eq!(
+            reseal(&|b| b[22] = 3),
+            Err(Error::InvalidParameter(
+                "chunk parameters do not match this implementation"
+            ))
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use core::fmt;
+
+/// Errors returned by the fallible APIs of this crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A serialized value had the wrong number of bytes.
+    InvalidLength {
+        /// Number of bytes the format requires.
+        expected: usize,
+        /// Number of bytes that were supplied.
+        found: usize,
+    },
+    /// The peer's key confirmation did not verify, i.e. the two inputs did
+    /// not collapse to the same value (or the message was tampered with).
+    KeyConfirmationFailed,
+    /// A Diffie-Hellman exchange produced the all-zero shared secret, which
+    /// means the peer sent a low-order point.
+    LowOrderPoint,
+    /// A constructor was given parameters outside their valid range.
+    InvalidParameter(&'static str),
+    /// Too few shares survived decoding to reconstruct the secret.
+    NotEnoughShares {
+        /// Number of shares the scheme's threshold requires.
+        required: usize,
+        /// Number of distinct shares that decoded and verified.
+        valid: usize,
+    },
+    /// A noisy reading was too far from the enrolled one (or came from a
+    /// different source) to reconstruct the enrolled secret.
+    ReconstructionFailed,
+    /// A mnemonic contained a word that is not in the wordlist.
+    UnknownWord {
+        /// Position (0-based) of the offending word.
+        position: usize,
+    },
+    /// A checksum (of a mnemonic, a file or an encoded digest) did not
+    /// match the data it protects.
+    InvalidChecksum,
+    /// An encoded string contained a character outside its alphabet.
+    InvalidCharacter {
+        /// Position (0-based, in characters) of the offending character.
+        position: usize,
+    },
+    /// A configuration or input fell outside the bounds of a
+    /// [`Policy`](crate::policy::Policy).
+    PolicyViolation(&'static str),
+    /// A long operation was stopped by its caller before it finished.
+    Cancelled,
+    /// An input was too short to collapse with any tolerance or secrecy;
+    /// see [`TbfConfig::MIN_INPUT_BITS`](crate::TbfConfig::MIN_INPUT_BITS).
+    InputTooShort {
+        /// Shortest input accepted, in bits.
+        minimum_bits: usize,
+        /// Length of the input supplied, in bits.
+        found_bits: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidLength { expected, found } => {
+                write!(
+                    f,
+                    "invalid length: expected {expected} bytes, found {found}"
+                )
+            }
+            Error::KeyConfirmationFailed => write!(f, "key confirmation failed"),
+            Error::LowOrderPoint => write!(f, "peer sent a low-order point"),
+            Error::InvalidParameter(reason) => write!(f, "invalid parameter: {reason}"),
+            Error::NotEnoughShares { required, valid } => {
+                write!(f, "not enough shares: {required} required, {valid} usable")
+            }
+            Error::ReconstructionFailed => write!(f, "reconstruction failed"),
+            Error::UnknownWord { position } => write!(f, "unknown word at position {position}"),
+            Error::InvalidChecksum => write!(f, "invalid checksum"),
+            Error::InvalidCharacter { position } => {
+                write!(f, "invalid character at position {position}")
+            }
+            Error::PolicyViolation(reason) => write!(f, "policy violation: {reason}"),
+            Error::Cancelled => write!(f, "cancelled"),
+            Error::InputTooShort {
+                minimum_bits,
+                found_bits,
+            } => write!(
+                f,
+                "input too short: {found_bits} bits, at least {minimum_bits} required"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// For APIs reporting I/O failures, such as
+/// [`LogStore`](crate::store::LogStore), that also validate parameters.
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        let kind = match error {
+            Error::Cancelled => std::io::ErrorKind::Other,
+            _ => std::io::ErrorKind::InvalidInput,
+        };
+        std::io::Error::new(kind, error)
+    }
+}
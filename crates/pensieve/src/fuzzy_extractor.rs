@@ -0,0 +1,293 @@
+//! A fuzzy extractor built on Thresholded Bit Folding.
+//!
+//! `generate` collapses a noisy input and runs the collapsed value through a
+//! salted HKDF, returning a uniform key plus public [`HelperData`] (the salt
+//! and the tolerance). `reproduce` repeats the collapse on a fresh reading of
+//! the same source; whenever the two readings collapse equally, the keys are
+//! identical.
+//!
+//! The input is collapsed with [`collapse_ct`](crate::collapse_ct), so enrollment and
+//! reproduction run in time independent of the secret.
+//!
+//! The collapsed value carries at most one bit per chunk, so the extracted
+//! key is only as hard to guess as the collapsed space is large. Protocols
+//! built on top (see [`crate::pake`]) must therefore never let an attacker
+//! test guesses offline.
+//!
+//! When the same source must be enrolled many times (one enrollment per
+//! service, re-enrollment after revocation), use the [`reusable`] backend.
+//! With the `argon2` feature, the `stretched` backend makes every guess
+//! against a small collapsed space cost a memory-hard hash evaluation.
+
+use crate::TbfConfig;
+use crate::crypto::hmac::{HmacSha256, hkdf_expand};
+#[cfg(feature = "alloc")]
+use crate::encoding::cbor::{HelperKind, Reader, Writer};
+use crate::entropy::EntropySource;
+use crate::error::Error;
+
+#[cfg(feature = "alloc")]
+pub mod reusable;
+#[cfg(feature = "argon2")]
+pub mod stretched;
+
+/// Domain separation label for key derivation.
+const KEY_INFO: &[u8] = b"pensieve/fuzzy-extractor/v1/key";
+
+/// Public data needed to reproduce a key; safe to store next to the enrollment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HelperData {
+    salt: [u8; 32], // Random per enrollment, makes keys from the same source unlinkable.
+    tolerance: f32, // Tolerance the enrollment was collapsed with.
+}
+
+impl HelperData {
+    /// Length of the serialized form produced by [`HelperData::to_bytes`].
+    pub const ENCODED_LEN: usize = 36;
+
+    /// The per-enrollment salt.
+    pub fn salt(&self) -> &[u8; 32] {
+        &self.salt
+    }
+
+    /// The tolerance used at enrollment.
+    pub fn tolerance(&self) -> f32 {
+        self.tolerance
+    }
+
+    /// Serializes as `salt || tolerance (f32, little-endian)`.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut out = [0u8; Self::ENCODED_LEN];
+        out[..32].copy_from_slice(&self.salt);
+        out[32..].copy_from_slice(&self.tolerance.to_le_bytes());
+        out
+    }
+
+    /// Parses the output of [`HelperData::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != Self::ENCODED_LEN {
+            return Err(Error::InvalidLength {
+                expected: Self::ENCODED_LEN,
+                found: bytes.len(),
+            });
+        }
+        let mut salt = [0u8; 32];
+        salt.copy_from_slice(&bytes[..32]);
+        let tolerance = f32::from_le_bytes(bytes[32..].try_into().expect("4 bytes"));
+        Ok(Self { salt, tolerance })
+    }
+
+    /// Serializes as deterministic CBOR, the portable format for other
+    /// releases and languages (see [`crate::encoding`]):
+    /// `{0: version, 1: 1, 2: salt, 3: tolerance}`.
+    #[cfg(feature = "alloc")]
+    pub fn to_cbor(&self) -> alloc::vec::Vec<u8> {
+        let mut writer = Writer::default();
+        writer.helper_header(HelperKind::Plain, 2);
+        writer.uint(2).bytes(&self.salt).uint(3).f32(self.tolerance);
+        writer.into_bytes()
+    }
+
+    /// Parses the output of [`HelperData::to_cbor`] from this or any
+    /// earlier release.
+    #[cfg(feature = "alloc")]
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes);
+        reader.helper_header(HelperKind::Plain, 2)?;
+        let helper = Self::read_cbor_fields(&mut reader)?;
+        reader.finish()?;
+        Ok(helper)
+    }
+
+    /// Reads the salt (key 2) and tolerance (key 3) shared by every
+    /// collapse-based helper data.
+    #[cfg(feature = "alloc")]
+    fn read_cbor_fields(reader: &mut Reader<'_>) -> Result<Self, Error> {
+        reader.key(2)?;
+        let salt = reader.byte_array()?;
+        reader.key(3)?;
+        let tolerance = reader.f32()?;
+        Ok(Self { salt, tolerance })
+    }
+}
+
+/// A 256-bit key extracted from a noisy input.
+///
+/// Comparison is constant-time and `Debug` does not print the key material.
+#[derive(Clone)]
+pub struct ExtractedKey([u8; 32]);
+
+impl ExtractedKey {
+    /// Wraps key material derived elsewhere in the crate.
+    pub(crate) fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// The raw key bytes.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl PartialEq for ExtractedKey {
+    fn eq(&self, other: &Self) -> bool {
+        crate::crypto::ct_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ExtractedKey {}
+
+impl core::fmt::Debug for ExtractedKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("ExtractedKey(..)")
+    }
+}
+
+/// Derives stable keys from noisy inputs using
+/// [`collapse_ct`](crate::collapse_ct).
+///
+/// Needs no allocator: the collapsed value is fed to the key derivation as
+/// it is produced instead of being stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FuzzyExtractor {
+    tolerance: f32,
+}
+
+impl FuzzyExtractor {
+    /// Creates an extractor; `tolerance` has the same meaning (and clamping)
+    /// as in [`collapse_ct`](crate::collapse_ct).
+    pub fn new(tolerance: f32) -> Self {
+        Self { tolerance }
+    }
+
+    /// The tolerance new enrollments are collapsed with.
+    pub fn tolerance(&self) -> f32 {
+        self.tolerance
+    }
+
+    /// Enrolls `input`, returning the key and the helper data to store.
+    pub fn generate(
+        &self,
+        input: &[u8],
+        rng: &mut impl EntropySource,
+    ) -> (ExtractedKey, HelperData) {
+        let mut salt = [0u8; 32];
+        rng.fill_bytes(&mut salt);
+        let helper = HelperData {
+            salt,
+            tolerance: self.tolerance,
+        };
+        (Self::reproduce(input, &helper), helper)
+    }
+
+    /// Re-derives the key for a new reading of an enrolled source.
+    ///
+    /// The result equals the enrolled key iff `input` collapses to the same
+    /// value as the enrolled input under the stored tolerance.
+    pub fn reproduce(input: &[u8], helper: &HelperData) -> ExtractedKey {
+        // The HKDF extract step over the collapsed value, streamed.
+        let mut extract = HmacSha256::new(&helper.salt);
+        TbfConfig::new(helper.tolerance).collapse_streamed(input, |bytes| extract.update(bytes));
+        expand_key(&extract.finalize(), KEY_INFO)
+    }
+}
+
+/// Salted HKDF from input keying material to a 256-bit key.
+#[cfg(feature = "argon2")]
+fn derive_key(salt: &[u8; 32], ikm: &[u8], info: &[u8]) -> ExtractedKey {
+    expand_key(&crate::crypto::hmac::hkdf_extract(salt, ikm), info)
+}
+
+/// The HKDF expand step from a pseudorandom key to a 256-bit key.
+fn expand_key(prk: &[u8; 32], info: &[u8]) -> ExtractedKey {
+    let mut key = [0u8; 32];
+    hkdf_expand(prk, info, &mut key);
+    ExtractedKey(key)
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    #[test]
+    fn test_reproduce_within_tolerance() {
+        let mut enrolled = [0u8; 16];
+        enrolled[0] = 0b11111111;
+        let mut noisy = enrolled;
+        noisy[0] ^= 0b00000001; // 1 bit flip, within 5%
+
+        let extractor = FuzzyExtractor::new(0.05);
+        let (key, helper) = extractor.generate(&enrolled, &mut seeded(1));
+        assert_eq!(FuzzyExtractor::reproduce(&noisy, &helper), key);
+    }
+
+    #[test]
+    fn test_reproduce_beyond_tolerance_differs() {
+        let mut enrolled = [0u8; 16];
+        enrolled[0] = 0b11111111;
+        let mut noisy = enrolled;
+        noisy[0] ^= 0b11111111; // 8 bit flips, exceeds 5%
+
+        let extractor = FuzzyExtractor::new(0.05);
+        let (key, helper) = extractor.generate(&enrolled, &mut seeded(1));
+        assert_ne!(FuzzyExtractor::reproduce(&noisy, &helper), key);
+    }
+
+    #[test]
+    fn test_streamed_key_derivation_matches_collapsed_value() {
+        let mut input = [0u8; 300];
+        seeded(3)(&mut input);
+        let (key, helper) = FuzzyExtractor::new(0.1).generate(&input, &mut seeded(4));
+        let collapsed = crate::collapse_ct(&input, 0.1);
+        let prk = crate::crypto::hmac::hkdf_extract(helper.salt(), &collapsed);
+        assert_eq!(key, expand_key(&prk, KEY_INFO));
+    }
+
+    #[test]
+    fn test_salt_makes_enrollments_unlinkable() {
+        let input = [0xAB; 16];
+        let extractor = FuzzyExtractor::new(0.125);
+        let (key1, _) = extractor.generate(&input, &mut seeded(1));
+        let (key2, _) = extractor.generate(&input, &mut seeded(2));
+        assert_ne!(key1, key2);
+    }
+
+    #[test]
+    fn test_helper_data_roundtrip() {
+        let extractor = FuzzyExtractor::new(0.2);
+        let (_, helper) = extractor.generate(&[1, 2, 3, 4], &mut seeded(9));
+        let decoded = HelperData::from_bytes(&helper.to_bytes()).unwrap();
+        assert_eq!(decoded, helper);
+        assert_eq!(
+            HelperData::from_bytes(&[0; 3]),
+            Err(Error::InvalidLength {
+                expected: 36,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_helper_data_cbor() {
+        let helper = HelperData {
+            salt: [0x11; 32],
+            tolerance: 0.125,
+        };
+        let bytes = helper.to_cbor();
+        // {0: 1, 1: 1, 2: h'1111..', 3: 0.125 as a half float}
+        assert_eq!(&bytes[..7], &[0xA4, 0x00, 0x01, 0x01, 0x01, 0x02, 0x58]);
+        assert_eq!(&bytes[bytes.len() - 4..], &[0x03, 0xF9, 0x30, 0x00]);
+        assert_eq!(HelperData::from_cbor(&bytes), Ok(helper));
+
+        let mut newer = bytes.clone();
+        newer[2] = 0x02;
+        assert_eq!(
+            HelperData::from_cbor(&newer),
+            Err(Error::InvalidParameter(
+                "unsupported helper data schema version"
+            ))
+        );
+        assert!(HelperData::from_cbor(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
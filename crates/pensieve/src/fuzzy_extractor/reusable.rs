@@ -0,0 +1,433 @@
+//! Reusable fuzzy extractor based on digital lockers ("sample-then-lock").
+//!
+//! The collapse-based [`FuzzyExtractor`](super::FuzzyExtractor) derives every
+//! key from the same small collapsed value, so many enrollments of one noisy
+//! source all rest on that value's entropy. This backend follows Canetti,
+//! Fuller, Paneth, Reyzin and Smith ("Reusable Fuzzy Extractors for
+//! Low-Entropy Distributions", 2016) instead: at enrollment it samples many
+//! random subsets of input bit positions and locks the key under a hash of
+//! each subset's bits. Reproduction succeeds if at least one subset came
+//! through without a single bit error.
+//!
+//! Each locker is an independent hash commitment with its own nonce and
+//! positions, so enrolling the same source any number of times does not
+//! leak more than the lockers themselves: security rests on the entropy of
+//! the sampled bits (roughly `subset_size` bits if input bits are close to
+//! uniform), not on the number of enrollments.
+//!
+//! Pick `subset_size` large enough for that entropy and `locker_count` large
+//! enough that some subset survives the noise; see
+//! [`SampleThenLock::failure_probability`].
+
+use super::ExtractedKey;
+use crate::crypto::ct_eq;
+use crate::crypto::hmac::{hkdf_expand, hkdf_extract};
+use crate::encoding::cbor::{HelperKind, Reader, Writer};
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Domain separation label for locker pads.
+const LOCKER_INFO: &[u8] = b"pensieve/fuzzy-extractor/v1/locker";
+/// Bytes of the per-locker nonce.
+const NONCE_LEN: usize = 16;
+/// Zero bytes appended to the key inside a locker to recognize a correct unlock.
+const CHECK_LEN: usize = 16;
+/// Bytes of locked material per locker (key plus check bytes).
+const SEALED_LEN: usize = 32 + CHECK_LEN;
+
+/// One digital locker: the key encrypted under a subset of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Locker {
+    nonce: [u8; NONCE_LEN],   // Random, makes the locker's hash independent.
+    positions: Vec<u32>,      // Sampled bit positions (MSB-first), public.
+    sealed: [u8; SEALED_LEN], // (key || zeros) XOR pad(nonce, sampled bits).
+}
+
+/// Public data produced at enrollment by [`SampleThenLock::generate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockerHelperData {
+    input_bits: u32,      // Length of the enrolled input, in bits.
+    subset_size: u16,     // Positions sampled per locker.
+    lockers: Vec<Locker>, // All lockers, tried in order.
+}
+
+impl LockerHelperData {
+    /// Number of lockers stored.
+    pub fn locker_count(&self) -> usize {
+        self.lockers.len()
+    }
+
+    /// Serializes as `input_bits (u32) || subset_size (u16) || locker_count (u32)`
+    /// followed by every locker as `nonce || positions (u32 each) || sealed`.
+    /// All integers are little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let locker_len = NONCE_LEN + 4 * usize::from(self.subset_size) + SEALED_LEN;
+        let mut out = Vec::with_capacity(10 + self.lockers.len() * locker_len);
+        out.extend_from_slice(&self.input_bits.to_le_bytes());
+        out.extend_from_slice(&self.subset_size.to_le_bytes());
+        out.extend_from_slice(&(self.lockers.len() as u32).to_le_bytes());
+        for locker in &self.lockers {
+            out.extend_from_slice(&locker.nonce);
+            for position in &locker.positions {
+                out.extend_from_slice(&position.to_le_bytes());
+            }
+            out.extend_from_slice(&locker.sealed);
+        }
+        out
+    }
+
+    /// Parses the output of [`LockerHelperData::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 10 {
+            return Err(Error::InvalidLength {
+                expected: 10,
+                found: bytes.len(),
+            });
+        }
+        let input_bits = u32::from_le_bytes(bytes[0..4].try_into().expect("4 bytes"));
+        let subset_size = u16::from_le_bytes(bytes[4..6].try_into().expect("2 bytes"));
+        let count = u32::from_le_bytes(bytes[6..10].try_into().expect("4 bytes")) as usize;
+
+        let locker_len = NONCE_LEN + 4 * usize::from(subset_size) + SEALED_LEN;
+        let expected = count
+            .checked_mul(locker_len)
+            .and_then(|n| n.checked_add(10))
+            .ok_or(Error::InvalidParameter("locker count overflows"))?;
+        if bytes.len() != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let lockers = bytes[10..]
+            .chunks_exact(locker_len)
+            .map(|chunk| {
+                let (nonce, rest) = chunk.split_at(NONCE_LEN);
+                let (positions, sealed) = rest.split_at(rest.len() - SEALED_LEN);
+                let positions: Vec<u32> = positions
+                    .chunks_exact(4)
+                    .map(|p| u32::from_le_bytes(p.try_into().expect("4 bytes")))
+                    .collect();
+                if positions.iter().any(|&p| p >= input_bits) {
+                    return Err(Error::InvalidParameter("locker position out of range"));
+                }
+                Ok(Locker {
+                    nonce: nonce.try_into().expect("nonce length"),
+                    positions,
+                    sealed: sealed.try_into().expect("sealed length"),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            input_bits,
+            subset_size,
+            lockers,
+        })
+    }
+
+    /// Serializes as deterministic CBOR (see [`crate::encoding`]):
+    /// `{0: version, 1: 3, 2: input_bits, 3: subset_size, 4: lockers}`,
+    /// every locker being `[nonce, [positions...], sealed]`.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.helper_header(HelperKind::Locker, 3);
+        writer.uint(2).uint(self.input_bits.into());
+        writer.uint(3).uint(self.subset_size.into());
+        writer.uint(4).array(self.lockers.len());
+        for locker in &self.lockers {
+            writer.array(3).bytes(&locker.nonce);
+            writer.array(locker.positions.len());
+            for &position in &locker.positions {
+                writer.uint(position.into());
+            }
+            writer.bytes(&locker.sealed);
+        }
+        writer.into_bytes()
+    }
+
+    /// Parses the output of [`LockerHelperData::to_cbor`] from this or any
+    /// earlier release.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes);
+        reader.helper_header(HelperKind::Locker, 3)?;
+        reader.key(2)?;
+        let input_bits: u32 = reader.uint_as()?;
+        reader.key(3)?;
+        let subset_size: u16 = reader.uint_as()?;
+        reader.key(4)?;
+        let count = reader.array()?;
+        let lockers = (0..count)
+            .map(|_| {
+                reader.array_of(3)?;
+                let nonce = reader.byte_array()?;
+                reader.array_of(usize::from(subset_size))?;
+                let positions = (0..subset_size)
+                    .map(|_| match reader.uint_as::<u32>()? {
+                        p if p < input_bits => Ok(p),
+                        _ => Err(Error::InvalidParameter("locker position out of range")),
+                    })
+                    .collect::<Result<Vec<u32>, _>>()?;
+                let sealed = reader.byte_array()?;
+                Ok(Locker {
+                    nonce,
+                    positions,
+                    sealed,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        reader.finish()?;
+        Ok(Self {
+            input_bits,
+            subset_size,
+            lockers,
+        })
+    }
+}
+
+/// The sample-then-lock reusable fuzzy extractor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleThenLock {
+    subset_size: u16,  // Input bits hashed into each locker.
+    locker_count: u32, // Number of independent lockers.
+}
+
+impl SampleThenLock {
+    /// Creates an extractor sampling `subset_size` bits into each of
+    /// `locker_count` lockers.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if either is zero.
+    pub fn new(subset_size: u16, locker_count: u32) -> Result<Self, Error> {
+        if subset_size == 0 {
+            return Err(Error::InvalidParameter("subset size must be at least 1"));
+        }
+        if locker_count == 0 {
+            return Err(Error::InvalidParameter("locker count must be at least 1"));
+        }
+        Ok(Self {
+            subset_size,
+            locker_count,
+        })
+    }
+
+    /// Number of bits sampled per locker.
+    pub fn subset_size(&self) -> u16 {
+        self.subset_size
+    }
+
+    /// Number of lockers created per enrollment.
+    pub fn locker_count(&self) -> u32 {
+        self.locker_count
+    }
+
+    /// Probability that reproduction fails when every input bit flips
+    /// independently with probability `bit_error_rate`:
+    /// `(1 - (1 - p)^subset_size)^locker_count`.
+    #[cfg(feature = "std")]
+    pub fn failure_probability(&self, bit_error_rate: f64) -> f64 {
+        let clean = (1.0 - bit_error_rate).powi(i32::from(self.subset_size));
+        (1.0 - clean).powf(f64::from(self.locker_count))
+    }
+
+    /// Enrolls `input`, returning the key and the lockers to store.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `input` has fewer bits than
+    /// `subset_size` or more than `u32::MAX` bits.
+    pub fn generate(
+        &self,
+        input: &[u8],
+        rng: &mut impl EntropySource,
+    ) -> Result<(ExtractedKey, LockerHelperData), Error> {
+        let input_bits = u32::try_from(input.len() * 8)
+            .map_err(|_| Error::InvalidParameter("input longer than u32::MAX bits"))?;
+        if input_bits < u32::from(self.subset_size) {
+            return Err(Error::InvalidParameter(
+                "input has fewer bits than the subset size",
+            ));
+        }
+
+        let mut key = [0u8; 32];
+        rng.fill_bytes(&mut key);
+        let mut plaintext = [0u8; SEALED_LEN];
+        plaintext[..32].copy_from_slice(&key);
+
+        let lockers = (0..self.locker_count)
+            .map(|_| {
+                let mut nonce = [0u8; NONCE_LEN];
+                rng.fill_bytes(&mut nonce);
+                let positions = sample_positions(input_bits, self.subset_size, rng);
+                let pad = pad(&nonce, &sample_bits(input, &positions));
+                let mut sealed = [0u8; SEALED_LEN];
+                for (i, byte) in sealed.iter_mut().enumerate() {
+                    *byte = plaintext[i] ^ pad[i];
+                }
+                Locker {
+                    nonce,
+                    positions,
+                    sealed,
+                }
+            })
+            .collect();
+
+        let helper = LockerHelperData {
+            input_bits,
+            subset_size: self.subset_size,
+            lockers,
+        };
+        Ok((ExtractedKey::from_bytes(key), helper))
+    }
+
+    /// Tries every locker against a new reading of the enrolled source.
+    ///
+    /// Returns `None` if the input length differs from the enrollment or no
+    /// locker's subset is error-free. All lockers are always tried, so the
+    /// running time does not reveal which one opened.
+    pub fn reproduce(input: &[u8], helper: &LockerHelperData) -> Option<ExtractedKey> {
+        if input.len() * 8 != helper.input_bits as usize {
+            return None;
+        }
+        let mut key = [0u8; 32];
+        let mut found = 0u8; // 1 once a locker opened.
+        for locker in &helper.lockers {
+            let pad = pad(&locker.nonce, &sample_bits(input, &locker.positions));
+            let mut opened = [0u8; SEALED_LEN];
+            for (i, byte) in opened.iter_mut().enumerate() {
+                *byte = locker.sealed[i] ^ pad[i];
+            }
+            // Keep the key of the first locker that opens without branching
+            // on which one it was.
+            let open = u8::from(ct_eq(&opened[32..], &[0u8; CHECK_LEN]));
+            let mask = 0u8.wrapping_sub(open & !found);
+            for (k, o) in key.iter_mut().zip(&opened[..32]) {
+                *k ^= mask & (*k ^ o);
+            }
+            found |= open;
+        }
+        (found == 1).then(|| ExtractedKey::from_bytes(key))
+    }
+}
+
+/// Draws `count` distinct positions in `0..input_bits` (partial Fisher-Yates).
+fn sample_positions(input_bits: u32, count: u16, rng: &mut impl EntropySource) -> Vec<u32> {
+    let mut pool: Vec<u32> = (0..input_bits).collect();
+    for i in 0..usize::from(count) {
+        let remaining = pool.len() - i;
+        let j = i + uniform_below(remaining as u32, rng) as usize;
+        pool.swap(i, j);
+    }
+    pool.truncate(usize::from(count));
+    pool
+}
+
+/// Uniform integer in `0..bound` by rejection sampling (`bound > 0`).
+fn uniform_below(bound: u32, rng: &mut impl EntropySource) -> u32 {
+    // Reject the top partial range so every residue is equally likely.
+    let limit = u32::MAX - u32::MAX % bound;
+    loop {
+        let mut bytes = [0u8; 4];
+        rng.fill_bytes(&mut bytes);
+        let v = u32::from_le_bytes(bytes);
+        if v < limit {
+            return v % bound;
+        }
+    }
+}
+
+/// Packs the input bits at `positions` (MSB-first numbering) into bytes.
+fn sample_bits(input: &[u8], positions: &[u32]) -> Vec<u8> {
+    let mut packed = vec![0u8; positions.len().div_ceil(8)];
+    for (i, &position) in positions.iter().enumerate() {
+        let p = position as usize;
+        let bit = (input[p / 8] >> (7 - p % 8)) & 1;
+        packed[i / 8] |= bit << (7 - i % 8);
+    }
+    packed
+}
+
+/// The one-time pad protecting a locker, derived from its nonce and sampled bits.
+fn pad(nonce: &[u8; NONCE_LEN], sampled: &[u8]) -> [u8; SEALED_LEN] {
+    let prk = hkdf_extract(nonce, sampled);
+    let mut pad = [0u8; SEALED_LEN];
+    hkdf_expand(&prk, LOCKER_INFO, &mut pad);
+    pad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    fn source() -> Vec<u8> {
+        let mut input = vec![0u8; 64];
+        seeded(100)(&mut input);
+        input
+    }
+
+    #[test]
+    fn test_reproduce_with_sparse_errors() {
+        let extractor = SampleThenLock::new(24, 200).unwrap();
+        let enrolled = source();
+        let (key, helper) = extractor.generate(&enrolled, &mut seeded(1)).unwrap();
+
+        // Flip 2% of the 512 bits, spread across the input.
+        let mut noisy = enrolled.clone();
+        for i in (0..64).step_by(6) {
+            noisy[i] ^= 0x10;
+        }
+        assert_eq!(SampleThenLock::reproduce(&noisy, &helper), Some(key));
+    }
+
+    #[test]
+    fn test_unrelated_input_fails() {
+        let extractor = SampleThenLock::new(24, 50).unwrap();
+        let (_, helper) = extractor.generate(&source(), &mut seeded(1)).unwrap();
+        let mut other = vec![0u8; 64];
+        seeded(999)(&mut other);
+        assert_eq!(SampleThenLock::reproduce(&other, &helper), None);
+        assert_eq!(SampleThenLock::reproduce(&other[..10], &helper), None);
+    }
+
+    #[test]
+    fn test_enrollments_are_independent() {
+        let extractor = SampleThenLock::new(16, 10).unwrap();
+        let input = source();
+        let (key1, helper1) = extractor.generate(&input, &mut seeded(1)).unwrap();
+        let (key2, helper2) = extractor.generate(&input, &mut seeded(2)).unwrap();
+        assert_ne!(key1, key2);
+        assert_ne!(helper1, helper2);
+        assert_eq!(SampleThenLock::reproduce(&input, &helper1), Some(key1));
+        assert_eq!(SampleThenLock::reproduce(&input, &helper2), Some(key2));
+    }
+
+    #[test]
+    fn test_helper_roundtrip() {
+        let extractor = SampleThenLock::new(8, 3).unwrap();
+        let (_, helper) = extractor.generate(&source(), &mut seeded(5)).unwrap();
+        let bytes = helper.to_bytes();
+        assert_eq!(LockerHelperData::from_bytes(&bytes).unwrap(), helper);
+        assert!(LockerHelperData::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let cbor = helper.to_cbor();
+        assert_eq!(LockerHelperData::from_cbor(&cbor).unwrap(), helper);
+        assert!(LockerHelperData::from_cbor(&cbor[..cbor.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_parameter_validation() {
+        assert!(SampleThenLock::new(0, 1).is_err());
+        assert!(SampleThenLock::new(1, 0).is_err());
+        let extractor = SampleThenLock::new(100, 1).unwrap();
+        assert!(extractor.generate(&[0u8; 4], &mut seeded(1)).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_failure_probability() {
+        let extractor = SampleThenLock::new(32, 400).unwrap();
+        assert!(extractor.failure_probability(0.1) < 1e-5);
+        assert!(extractor.failure_probability(0.0) == 0.0);
+    }
+}
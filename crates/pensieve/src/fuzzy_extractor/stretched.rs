@@ -0,0 +1,301 @@
+//! Argon2id stretching of the collapsed value (feature `argon2`).
+//!
+//! A collapsed value carries at most one bit per chunk, so the plain
+//! [`FuzzyExtractor`] key can be brute-forced by anyone holding the helper
+//! data and a way to test candidates. This backend runs the collapsed value
+//! through Argon2id (RFC 9106), salted with the per-enrollment salt, before
+//! the HKDF step. Stretching does not add entropy: it multiplies the cost
+//! of every guess by one memory-hard hash evaluation, which matters most
+//! for short codes and ambient inputs whose collapsed space is small.
+//!
+//! Reproduction pays the same cost, so pick [`Argon2Params`] that are
+//! acceptable on the slowest device that has to reproduce keys.
+
+use super::{ExtractedKey, FuzzyExtractor, HelperData, derive_key};
+use crate::collapse_ct;
+use crate::crypto::argon2::{Cost, argon2id};
+use crate::encoding::cbor::{HelperKind, Reader, Writer};
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use alloc::vec::Vec;
+
+/// Domain separation label for key derivation from a stretched value.
+const KEY_INFO: &[u8] = b"pensieve/fuzzy-extractor/v1/stretched-key";
+/// Upper bound on the memory cost accepted from (possibly untrusted) helper data.
+const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+
+/// Argon2id cost parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+    memory_kib: u32,  // Memory cost in KiB.
+    iterations: u32,  // Passes over memory.
+    parallelism: u32, // Lanes (computed sequentially, but part of the result).
+}
+
+impl Argon2Params {
+    /// Length of the serialized parameters.
+    pub const ENCODED_LEN: usize = 12;
+
+    /// Validates the parameters: at least one pass, 1 to 255 lanes and
+    /// between `8 * parallelism` KiB and 4 GiB of memory.
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self, Error> {
+        if iterations == 0 {
+            return Err(Error::InvalidParameter("iterations must be at least 1"));
+        }
+        if !(1..=255).contains(&parallelism) {
+            return Err(Error::InvalidParameter("parallelism must be in 1..=255"));
+        }
+        if memory_kib < 8 * parallelism {
+            return Err(Error::InvalidParameter(
+                "memory must be at least 8 KiB per lane",
+            ));
+        }
+        if memory_kib > MAX_MEMORY_KIB {
+            return Err(Error::InvalidParameter("memory must not exceed 4 GiB"));
+        }
+        Ok(Self {
+            memory_kib,
+            iterations,
+            parallelism,
+        })
+    }
+
+    /// Memory cost in KiB.
+    pub fn memory_kib(&self) -> u32 {
+        self.memory_kib
+    }
+
+    /// Number of passes over memory.
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    /// Number of lanes.
+    pub fn parallelism(&self) -> u32 {
+        self.parallelism
+    }
+
+    fn to_bytes(self) -> [u8; Self::ENCODED_LEN] {
+        let mut out = [0u8; Self::ENCODED_LEN];
+        out[..4].copy_from_slice(&self.memory_kib.to_le_bytes());
+        out[4..8].copy_from_slice(&self.iterations.to_le_bytes());
+        out[8..].copy_from_slice(&self.parallelism.to_le_bytes());
+        out
+    }
+
+    fn from_bytes(bytes: &[u8; Self::ENCODED_LEN]) -> Result<Self, Error> {
+        let word = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().expect("4 bytes"));
+        Self::new(word(0), word(4), word(8))
+    }
+}
+
+impl Default for Argon2Params {
+    /// 19 MiB, 2 passes, 1 lane (the OWASP baseline for Argon2id).
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Public data needed to reproduce a stretched key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StretchedHelperData {
+    helper: HelperData,   // Salt and tolerance, as for the plain extractor.
+    params: Argon2Params, // Cost the enrollment was stretched with.
+}
+
+impl StretchedHelperData {
+    /// Length of the serialized form produced by [`StretchedHelperData::to_bytes`].
+    pub const ENCODED_LEN: usize = HelperData::ENCODED_LEN + Argon2Params::ENCODED_LEN;
+
+    /// Salt and tolerance.
+    pub fn helper(&self) -> &HelperData {
+        &self.helper
+    }
+
+    /// The Argon2id cost used at enrollment.
+    pub fn params(&self) -> Argon2Params {
+        self.params
+    }
+
+    /// Serializes as `helper || memory_kib || iterations || parallelism`
+    /// (integers as little-endian `u32`).
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut out = [0u8; Self::ENCODED_LEN];
+        out[..HelperData::ENCODED_LEN].copy_from_slice(&self.helper.to_bytes());
+        out[HelperData::ENCODED_LEN..].copy_from_slice(&self.params.to_bytes());
+        out
+    }
+
+    /// Parses the output of [`StretchedHelperData::to_bytes`], rejecting
+    /// cost parameters [`Argon2Params::new`] would refuse.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != Self::ENCODED_LEN {
+            return Err(Error::InvalidLength {
+                expected: Self::ENCODED_LEN,
+                found: bytes.len(),
+            });
+        }
+        let (helper, params) = bytes.split_at(HelperData::ENCODED_LEN);
+        Ok(Self {
+            helper: HelperData::from_bytes(helper)?,
+            params: Argon2Params::from_bytes(params.try_into().expect("12 bytes"))?,
+        })
+    }
+
+    /// Serializes as deterministic CBOR (see [`crate::encoding`]):
+    /// `{0: version, 1: 2, 2: salt, 3: tolerance, 4: memory_kib,
+    /// 5: iterations, 6: parallelism}`.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.helper_header(HelperKind::Stretched, 5);
+        writer.uint(2).bytes(&self.helper.salt);
+        writer.uint(3).f32(self.helper.tolerance);
+        writer.uint(4).uint(self.params.memory_kib.into());
+        writer.uint(5).uint(self.params.iterations.into());
+        writer.uint(6).uint(self.params.parallelism.into());
+        writer.into_bytes()
+    }
+
+    /// Parses the output of [`StretchedHelperData::to_cbor`] from this or
+    /// any earlier release, rejecting cost parameters [`Argon2Params::new`]
+    /// would refuse.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes);
+        reader.helper_header(HelperKind::Stretched, 5)?;
+        let helper = HelperData::read_cbor_fields(&mut reader)?;
+        let mut cost = [0u32; 3];
+        for (key, value) in (4..).zip(&mut cost) {
+            reader.key(key)?;
+            *value = reader.uint_as()?;
+        }
+        reader.finish()?;
+        Ok(Self {
+            helper,
+            params: Argon2Params::new(cost[0], cost[1], cost[2])?,
+        })
+    }
+}
+
+/// A [`FuzzyExtractor`] that stretches the collapsed value with Argon2id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StretchedExtractor {
+    extractor: FuzzyExtractor,
+    params: Argon2Params,
+}
+
+impl StretchedExtractor {
+    /// Creates an extractor collapsing with `tolerance` and stretching with `params`.
+    pub fn new(tolerance: f32, params: Argon2Params) -> Self {
+        Self {
+            extractor: FuzzyExtractor::new(tolerance),
+            params,
+        }
+    }
+
+    /// The cost new enrollments are stretched with.
+    pub fn params(&self) -> Argon2Params {
+        self.params
+    }
+
+    /// Enrolls `input`, returning the key and the helper data to store.
+    pub fn generate(
+        &self,
+        input: &[u8],
+        rng: &mut impl EntropySource,
+    ) -> (ExtractedKey, StretchedHelperData) {
+        let mut salt = [0u8; 32];
+        rng.fill_bytes(&mut salt);
+        let helper = StretchedHelperData {
+            helper: HelperData {
+                salt,
+                tolerance: self.extractor.tolerance(),
+            },
+            params: self.params,
+        };
+        (Self::reproduce(input, &helper), helper)
+    }
+
+    /// Re-derives the key for a new reading of an enrolled source.
+    pub fn reproduce(input: &[u8], helper: &StretchedHelperData) -> ExtractedKey {
+        let collapsed = collapse_ct(input, helper.helper.tolerance);
+        let params = helper.params;
+        let mut stretched = [0u8; 32];
+        argon2id(
+            &collapsed,
+            &helper.helper.salt,
+            &[],
+            KEY_INFO,
+            Cost {
+                memory_kib: params.memory_kib,
+                iterations: params.iterations,
+                parallelism: params.parallelism,
+            },
+            &mut stretched,
+        );
+        derive_key(&helper.helper.salt, &stretched, KEY_INFO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    fn cheap() -> Argon2Params {
+        Argon2Params::new(64, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn test_stretched_reproduce_within_tolerance() {
+        let mut enrolled = [0u8; 16];
+        enrolled[0] = 0b11111111;
+        let mut noisy = enrolled;
+        noisy[0] ^= 0b00000001;
+
+        let extractor = StretchedExtractor::new(0.05, cheap());
+        let (key, helper) = extractor.generate(&enrolled, &mut seeded(1));
+        assert_eq!(StretchedExtractor::reproduce(&noisy, &helper), key);
+    }
+
+    #[test]
+    fn test_stretched_key_differs_from_plain_and_by_cost() {
+        let input = [0x5A; 16];
+        let (stretched, helper) =
+            StretchedExtractor::new(0.1, cheap()).generate(&input, &mut seeded(3));
+        let (plain, _) = FuzzyExtractor::new(0.1).generate(&input, &mut seeded(3));
+        assert_ne!(stretched, plain);
+
+        let costlier = StretchedHelperData {
+            params: Argon2Params::new(64, 2, 1).unwrap(),
+            ..helper
+        };
+        assert_ne!(StretchedExtractor::reproduce(&input, &costlier), stretched);
+    }
+
+    #[test]
+    fn test_stretched_helper_roundtrip_and_validation() {
+        let (_, helper) = StretchedExtractor::new(0.2, cheap()).generate(&[7; 8], &mut seeded(5));
+        let bytes = helper.to_bytes();
+        assert_eq!(StretchedHelperData::from_bytes(&bytes).unwrap(), helper);
+
+        let mut zero_passes = bytes;
+        zero_passes[40..44].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            StretchedHelperData::from_bytes(&zero_passes),
+            Err(Error::InvalidParameter(_))
+        ));
+        assert!(Argon2Params::new(7, 1, 1).is_err());
+        assert!(Argon2Params::new(64, 1, 0).is_err());
+
+        let cbor = helper.to_cbor();
+        assert_eq!(StretchedHelperData::from_cbor(&cbor).unwrap(), helper);
+        assert_eq!(
+            HelperData::from_cbor(&cbor),
+            Err(Error::InvalidParameter("unexpected CBOR map size"))
+        );
+    }
+}
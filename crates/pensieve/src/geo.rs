@@ -0,0 +1,254 @@
+//! Fingerprints of geographic coordinates.
+//!
+//! Latitude and longitude are quantized to a grid of cells, the way a
+//! geohash does: the longitude range is halved repeatedly, then the
+//! latitude range, alternating, and each halving contributes one bit. The
+//! interleaved bits name a cell; `bits` of them give cells
+//! `360 / 2^ceil(bits/2)` degrees wide and `180 / 2^floor(bits/2)` degrees
+//! tall, and the first `n` bits of any code are the code of the enclosing
+//! coarser cell. Truncated to a multiple of five bits, the code spells the
+//! familiar base32 geohash.
+//!
+//! Points in the same cell always encode alike, so their collapses match.
+//! Two points close to each other may still fall on either side of a cell
+//! edge; [`GeoEncoder::neighbors`] lists the codes of the cells around a
+//! point so that a lookup can check all of them. With cells sized by
+//! [`GeoEncoder::for_radius`], any two points within the radius are in the
+//! same or adjacent cells.
+//!
+//! Only the cell leaves the device, not the coordinates: for privacy-
+//! preserving deduplication, collapse with a keyed
+//! [`FinalTransform`](crate::FinalTransform).
+
+use crate::{Error, TbfConfig};
+use alloc::vec::Vec;
+
+/// Approximate length of one degree of latitude, and of longitude at the
+/// equator, in meters.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Quantizes coordinates to grid cells of a fixed precision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeoEncoder {
+    bits: u32,
+}
+
+impl GeoEncoder {
+    /// An encoder producing `bits`-bit cell codes.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless `bits` is from 1 to 64.
+    pub fn new(bits: u32) -> Result<Self, Error> {
+        if !(1..=64).contains(&bits) {
+            return Err(Error::InvalidParameter("cell codes have 1 to 64 bits"));
+        }
+        Ok(Self { bits })
+    }
+
+    /// The most precise encoder whose cells are at least `meters` tall, and
+    /// at least `meters` wide up to 60° of latitude, where meridians are
+    /// half as far apart as at the equator.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless `meters` is finite,
+    /// positive and small enough for such cells to exist.
+    pub fn for_radius(meters: f64) -> Result<Self, Error> {
+        if !(meters.is_finite() && meters > 0.0) {
+            return Err(Error::InvalidParameter(
+                "radius must be finite and positive",
+            ));
+        }
+        (1..=64)
+            .rev()
+            .map(|bits| Self { bits })
+            .find(|encoder| {
+                let (width, height) = encoder.cell_degrees();
+                height * METERS_PER_DEGREE >= meters && width * METERS_PER_DEGREE / 2.0 >= meters
+            })
+            .ok_or(Error::InvalidParameter("radius exceeds the coarsest cells"))
+    }
+
+    /// Length of the cell codes, in bits.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Width and height of a cell, in degrees.
+    pub fn cell_degrees(&self) -> (f64, f64) {
+        let (lon_bits, lat_bits) = self.split();
+        (360.0 / pow2(lon_bits), 180.0 / pow2(lat_bits))
+    }
+
+    /// The code of the cell containing `(latitude, longitude)`, in the low
+    /// [`bits`](Self::bits) bits.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless the latitude is within
+    /// ±90° and the longitude within ±180°.
+    pub fn cell(&self, latitude: f64, longitude: f64) -> Result<u64, Error> {
+        let (lat, lon) = self.indices(latitude, longitude)?;
+        Ok(self.interleave(lat, lon))
+    }
+
+    /// The [`cell`](Self::cell) code as bytes, most significant bit first
+    /// and zero-padded at the end.
+    pub fn encode(&self, latitude: f64, longitude: f64) -> Result<Vec<u8>, Error> {
+        Ok(self.code_bytes(self.cell(latitude, longitude)?))
+    }
+
+    /// The [`encode`](Self::encode)d codes of the cell containing the point
+    /// and of the up to eight cells around it, without duplicates. Cells
+    /// wrap around the antimeridian but not over the poles.
+    pub fn neighbors(&self, latitude: f64, longitude: f64) -> Result<Vec<Vec<u8>>, Error> {
+        let (lat, lon) = self.indices(latitude, longitude)?;
+        let (lon_bits, lat_bits) = self.split();
+        let (lon_cells, lat_cells) = (1u64 << lon_bits, 1u64 << lat_bits);
+        let mut codes = Vec::with_capacity(9);
+        for dlat in [-1i64, 0, 1] {
+            let Some(lat) = lat.checked_add_signed(dlat).filter(|&l| l < lat_cells) else {
+                continue;
+            };
+            for dlon in [-1i64, 0, 1] {
+                let lon = (lon as i64 + dlon).rem_euclid(lon_cells as i64) as u64;
+                let code = self.code_bytes(self.interleave(lat, lon));
+                if !codes.contains(&code) {
+                    codes.push(code);
+                }
+            }
+        }
+        Ok(codes)
+    }
+
+    /// The collapse under `config` of the [`encode`](Self::encode)d cell.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::geo::GeoEncoder;
+    ///
+    /// let encoder = GeoEncoder::new(40).unwrap();
+    /// let config = TbfConfig::new(0.125);
+    /// // Two fixes a few meters apart on the Place de la Concorde.
+    /// let a = encoder.fingerprint(48.86555, 2.32121, &config).unwrap();
+    /// let b = encoder.fingerprint(48.86558, 2.32125, &config).unwrap();
+    /// assert_eq!(a, b);
+    /// ```
+    pub fn fingerprint(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        config: &TbfConfig,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(config.collapse(&self.encode(latitude, longitude)?))
+    }
+
+    /// Bits of longitude and of latitude; longitude gets the odd one.
+    fn split(&self) -> (u32, u32) {
+        (self.bits.div_ceil(2), self.bits / 2)
+    }
+
+    /// Row and column of the cell containing the point.
+    fn indices(&self, latitude: f64, longitude: f64) -> Result<(u64, u64), Error> {
+        if !((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)) {
+            return Err(Error::InvalidParameter(
+                "coordinates must be within ±90° latitude and ±180° longitude",
+            ));
+        }
+        let (lon_bits, lat_bits) = self.split();
+        let index = |value: f64, min: f64, span: f64, bits: u32| {
+            // The upper edge belongs to the last cell.
+            (((value - min) / span * pow2(bits)) as u64).min((1u64 << bits) - 1)
+        };
+        Ok((
+            index(latitude, -90.0, 180.0, lat_bits),
+            index(longitude, -180.0, 360.0, lon_bits),
+        ))
+    }
+
+    /// The code of the cell at row `lat`, column `lon`: their bits
+    /// interleaved from the most significant, longitude first.
+    fn interleave(&self, lat: u64, lon: u64) -> u64 {
+        let (lon_bits, lat_bits) = self.split();
+        (0..self.bits).fold(0u64, |code, k| {
+            let bit = if k % 2 == 0 {
+                lon >> (lon_bits - 1 - k / 2) & 1
+            } else {
+                lat >> (lat_bits - 1 - k / 2) & 1
+            };
+            code << 1 | bit
+        })
+    }
+
+    fn code_bytes(&self, code: u64) -> Vec<u8> {
+        let aligned = code << (64 - self.bits);
+        aligned.to_be_bytes()[..self.bits.div_ceil(8) as usize].to_vec()
+    }
+}
+
+/// `2^exponent` as a float, for exponents up to 64.
+fn pow2(exponent: u32) -> f64 {
+    (1u128 << exponent) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The geohash base32 spelling of a code of a multiple of 5 bits.
+    fn geohash(encoder: &GeoEncoder, code: u64) -> alloc::string::String {
+        const ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+        (0..encoder.bits() / 5)
+            .rev()
+            .map(|i| ALPHABET[(code >> (5 * i) & 31) as usize] as char)
+            .collect()
+    }
+
+    #[test]
+    fn test_cells_are_geohashes() {
+        // The example point of the geohash specification.
+        let encoder = GeoEncoder::new(55).unwrap();
+        let code = encoder.cell(57.64911, 10.40744).unwrap();
+        assert_eq!(geohash(&encoder, code), "u4pruydqqvj");
+
+        let coarse = GeoEncoder::new(25).unwrap();
+        assert_eq!(coarse.cell(57.64911, 10.40744), Ok(code >> 30));
+        assert_eq!(coarse.encode(57.64911, 10.40744).unwrap().len(), 4);
+
+        let corners = GeoEncoder::new(2).unwrap();
+        assert_eq!(corners.cell(-90.0, -180.0), Ok(0b00));
+        assert_eq!(corners.cell(90.0, 180.0), Ok(0b11));
+        assert_eq!(corners.encode(90.0, 180.0), Ok(alloc::vec![0b1100_0000]));
+    }
+
+    #[test]
+    fn test_neighbors_cover_the_radius() {
+        let encoder = GeoEncoder::for_radius(100.0).unwrap();
+        let (width, height) = encoder.cell_degrees();
+        assert!(height * METERS_PER_DEGREE >= 100.0);
+        assert!(height * METERS_PER_DEGREE < 400.0, "the finest such cells");
+        assert!(width * METERS_PER_DEGREE >= 200.0);
+
+        // Points straddling a cell edge are each other's neighbors.
+        let edge = 48.0 + height * 1000.0 - 48.0 % height;
+        let (below, above) = (edge - height / 10.0, edge + height / 10.0);
+        assert_ne!(encoder.encode(below, 2.3), encoder.encode(above, 2.3));
+        let around = encoder.neighbors(below, 2.3).unwrap();
+        assert_eq!(around.len(), 9);
+        assert!(around.contains(&encoder.encode(above, 2.3).unwrap()));
+
+        // At the pole there is no row above; at the antimeridian columns wrap.
+        assert_eq!(encoder.neighbors(90.0, 0.0).unwrap().len(), 6);
+        let east = encoder.encode(0.0, 180.0).unwrap();
+        assert!(encoder.neighbors(0.0, -180.0).unwrap().contains(&east));
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(GeoEncoder::new(0).is_err());
+        assert!(GeoEncoder::new(65).is_err());
+        assert!(GeoEncoder::for_radius(0.0).is_err());
+        assert!(GeoEncoder::for_radius(1e9).is_err());
+        assert_eq!(GeoEncoder::for_radius(1e-9).map(|e| e.bits()), Ok(64));
+        let encoder = GeoEncoder::new(20).unwrap();
+        for (lat, lon) in [(90.1, 0.0), (0.0, -180.5), (f64::NAN, 0.0)] {
+            assert!(encoder.cell(lat, lon).is_err());
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Fuzzy group-by in ordinary hash maps and sets.
+//!
+//! A [`FuzzyKey`] wraps a byte key together with its collapsed digest and is
+//! equal to, and hashes like, every other key collapsing to the same digest.
+//! Used as the key of a `HashMap` or `HashSet`, it groups readings of the
+//! same source that differ only within the tolerance:
+//!
+//! ```rust
+//! use pensieve::TbfConfig;
+//! use pensieve::grouping::FuzzyKey;
+//! use std::collections::HashMap;
+//!
+//! let config = TbfConfig::new(0.125);
+//! let mut faint = [0x00; 16];
+//! faint[3] = 0x01;
+//! let readings: [&[u8]; 4] = [&[0xFF; 16], &[0x00; 16], &[0xFE; 16], &faint];
+//! let mut groups: HashMap<FuzzyKey<&[u8]>, usize> = HashMap::new();
+//! for reading in readings {
+//!     *groups.entry(FuzzyKey::new(reading, &config)).or_default() += 1;
+//! }
+//! assert_eq!(groups.len(), 2);
+//! assert_eq!(groups[&FuzzyKey::new(&[0xFF; 16][..], &config)], 2);
+//! ```
+//!
+//! [`FuzzyBuildHasher`] hashes plain byte keys by their digest instead, for
+//! code that only controls the hasher of a map. Equality of the keys
+//! themselves stays exact, so it sends near-equal keys to the same bucket
+//! without merging them.
+
+use crate::TbfConfig;
+use crate::simhash::mix;
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash, Hasher};
+
+/// A key that is equal to every key collapsing to the same digest.
+///
+/// Equality and hashing only look at the digest, so they agree as `Eq` and
+/// `Hash` require, and equality is transitive even though closeness within
+/// a tolerance is not. Keys collapsed with different configurations should
+/// not be mixed in one collection.
+#[derive(Debug, Clone)]
+pub struct FuzzyKey<K> {
+    key: K,
+    digest: Vec<u8>,
+}
+
+impl<K: AsRef<[u8]>> FuzzyKey<K> {
+    /// Wraps `key`, collapsing it with `config`.
+    pub fn new(key: K, config: &TbfConfig) -> Self {
+        let digest = config.collapse(key.as_ref());
+        Self { key, digest }
+    }
+}
+
+impl<K> FuzzyKey<K> {
+    /// The wrapped key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// The digest the key collapsed to.
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    /// Gives the wrapped key back.
+    pub fn into_inner(self) -> K {
+        self.key
+    }
+}
+
+impl<K> PartialEq for FuzzyKey<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.digest == other.digest
+    }
+}
+
+impl<K> Eq for FuzzyKey<K> {}
+
+impl<K> Hash for FuzzyKey<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.digest.hash(state);
+    }
+}
+
+/// Builds [`FuzzyHasher`]s collapsing with one configuration.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::grouping::FuzzyBuildHasher;
+/// use std::hash::BuildHasher;
+///
+/// let hasher = FuzzyBuildHasher::new(TbfConfig::new(0.125));
+/// assert_eq!(hasher.hash_one(&[0xFFu8; 16][..]), hasher.hash_one(&[0xFEu8; 16][..]));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FuzzyBuildHasher {
+    config: TbfConfig,
+}
+
+impl FuzzyBuildHasher {
+    /// Hashers collapsing with `config`.
+    pub fn new(config: TbfConfig) -> Self {
+        Self { config }
+    }
+
+    /// The configuration keys are collapsed with.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+}
+
+impl BuildHasher for FuzzyBuildHasher {
+    type Hasher = FuzzyHasher;
+
+    fn build_hasher(&self) -> FuzzyHasher {
+        FuzzyHasher {
+            config: self.config,
+            bytes: Vec::new(),
+        }
+    }
+}
+
+/// Hashes the digest of the bytes written to it.
+///
+/// Byte slices, vectors, arrays and strings write their contents with
+/// [`Hasher::write`], which is what gets collapsed. Integers, including the
+/// length prefixes those types write first, are ignored, so a key that is
+/// not made of bytes hashes like the bytes it contains.
+#[derive(Debug, Clone)]
+pub struct FuzzyHasher {
+    config: TbfConfig,
+    bytes: Vec<u8>, // Everything written so far.
+}
+
+impl Hasher for FuzzyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn write_u8(&mut self, _: u8) {}
+
+    fn write_u16(&mut self, _: u16) {}
+
+    fn write_u32(&mut self, _: u32) {}
+
+    fn write_u64(&mut self, _: u64) {}
+
+    fn write_u128(&mut self, _: u128) {}
+
+    fn write_usize(&mut self, _: usize) {}
+
+    /// FNV-1a over the digest, finished with a SplitMix64 mixer.
+    fn finish(&self) -> u64 {
+        let digest = self.config.collapse(&self.bytes);
+        mix(digest.iter().fold(0xCBF2_9CE4_8422_2325u64, |hash, &b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01B3)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Thresholding;
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn test_keys_group_by_digest() {
+        let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+        let mut noisy = [0xF7u8; 32];
+        noisy[3] ^= 0x10;
+        let key = FuzzyKey::new(&[0xF7u8; 32][..], &config);
+        let near = FuzzyKey::new(&noisy[..], &config);
+        let far = FuzzyKey::new(&[0x08u8; 32][..], &config);
+        assert_eq!(key, near);
+        assert_ne!(key, far);
+        assert_eq!(key.digest(), config.collapse(&noisy));
+        assert_eq!(near.key(), &&noisy[..]);
+
+        let set: HashSet<_> = [key.clone(), near, far].into_iter().collect();
+        assert_eq!(set.len(), 2);
+        assert_eq!(key.into_inner(), [0xF7; 32]);
+    }
+
+    #[test]
+    fn test_build_hasher_buckets_near_keys_together() {
+        let hasher =
+            FuzzyBuildHasher::new(TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric));
+        let hash = |bytes: &[u8]| hasher.hash_one(bytes);
+        let mut noisy = alloc::vec![0xF7u8; 32];
+        noisy[3] ^= 0x10;
+        assert_eq!(hash(&[0xF7; 32]), hash(&noisy));
+        assert_ne!(hash(&[0xF7; 32]), hash(&[0x08; 32]));
+        // Vectors and arrays hash like the slices they hold.
+        assert_eq!(hasher.hash_one(&noisy), hash(&noisy));
+        assert_eq!(hasher.hash_one([0xF7u8; 32]), hash(&[0xF7; 32]));
+
+        // Exact equality still decides which keys are the same.
+        let mut map = HashMap::with_hasher(hasher);
+        map.insert(alloc::vec![0xF7u8; 32], 1);
+        map.insert(noisy, 2);
+        assert_eq!(map.len(), 2);
+    }
+}
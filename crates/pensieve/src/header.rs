@@ -0,0 +1,153 @@
+//! The configuration header every serialized format starts with.
+//!
+//! Envelopes, compact digests, threshold profiles, learned layouts and
+//! partial collapses record the configuration they were produced under in
+//! the same fields, follow them with fields of their own, and end with the
+//! same checksum:
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 4 | magic of the format |
+//! | 1 | format version |
+//! | 1 | algorithm version ([`TbfConfig::ALGORITHM_VERSION`]) |
+//! | 1 | bit order ([`BitOrder::id`]) |
+//! | 1 | output transform ([`FinalTransform::id`]) |
+//! | 1 | mask base of [`FinalTransform::XorMask`], otherwise 0 |
+//! | 1 | thresholding ([`Thresholding::id`]) |
+//! | 4 | tolerance, f32 little-endian |
+//! | n | fields of the format |
+//! | 4 | first 4 bytes of SHA-256 over everything above |
+
+use crate::crypto::sha256::Sha256;
+use crate::{BitOrder, Error, FinalTransform, TbfConfig, Thresholding};
+use alloc::vec::Vec;
+
+/// Bytes of the configuration header.
+pub(crate) const CONFIG_LEN: usize = 14;
+/// Bytes of truncated SHA-256 after the fields of a format.
+pub(crate) const CHECKSUM_LEN: usize = 4;
+
+/// A serialized format, and the messages of the errors naming it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Format {
+    pub(crate) magic: [u8; 4],
+    /// Newest format version this build writes and reads.
+    pub(crate) version: u8,
+    /// Error for other magic bytes.
+    pub(crate) foreign: &'static str,
+    /// Error for other format versions.
+    pub(crate) unsupported: &'static str,
+    /// Error for keyed transforms read without a key.
+    pub(crate) needs_key: &'static str,
+}
+
+impl Format {
+    /// The configuration header of `config`, with room for `capacity`
+    /// bytes in all.
+    pub(crate) fn write(&self, config: &TbfConfig, capacity: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(capacity);
+        out.extend_from_slice(&self.magic);
+        out.push(self.version);
+        out.push(TbfConfig::ALGORITHM_VERSION);
+        out.push(config.bit_order().id());
+        out.extend_from_slice(&transform_bytes(&config.transform()));
+        out.push(config.thresholding().id());
+        out.extend_from_slice(&config.tolerance().to_le_bytes());
+        out
+    }
+
+    /// Checks the magic and checksum of `bytes`, which hold at least
+    /// `header_len` bytes before the checksum, and reads the configuration
+    /// they were written under. Returns it with everything before the
+    /// checksum.
+    pub(crate) fn open<'a>(
+        &self,
+        bytes: &'a [u8],
+        header_len: usize,
+        key: Option<&[u8; 32]>,
+    ) -> Result<(TbfConfig, &'a [u8]), Error> {
+        if bytes.len() < header_len + CHECKSUM_LEN {
+            return Err(Error::InvalidLength {
+                expected: header_len + CHECKSUM_LEN,
+                found: bytes.len(),
+            });
+        }
+        if bytes[..4] != self.magic {
+            return Err(Error::InvalidParameter(self.foreign));
+        }
+        let body = checked_body(bytes)?;
+        Ok((self.read_config(body, key)?, body))
+    }
+
+    /// The configuration in the first [`CONFIG_LEN`] bytes of `header`,
+    /// whose format version may be any up to this one's.
+    pub(crate) fn read_config(
+        &self,
+        header: &[u8],
+        key: Option<&[u8; 32]>,
+    ) -> Result<TbfConfig, Error> {
+        if !(1..=self.version).contains(&header[4]) {
+            return Err(Error::InvalidParameter(self.unsupported));
+        }
+        if !(1..=TbfConfig::ALGORITHM_VERSION).contains(&header[5]) {
+            return Err(Error::InvalidParameter("unsupported algorithm version"));
+        }
+        let bit_order =
+            BitOrder::from_id(header[6]).ok_or(Error::InvalidParameter("unsupported bit order"))?;
+        let transform = match (header[7], header[8], key) {
+            (0, 0, _) => FinalTransform::Identity,
+            (1, base, _) => FinalTransform::XorMask { base },
+            (2, 0, Some(key)) => FinalTransform::KeyedPrf { key: *key },
+            (2, 0, None) => return Err(Error::InvalidParameter(self.needs_key)),
+            (3, 0, _) => FinalTransform::Hash,
+            _ => return Err(Error::InvalidParameter("unsupported output transform")),
+        };
+        let thresholding = Thresholding::from_id(header[9])
+            .ok_or(Error::InvalidParameter("unsupported thresholding"))?;
+        let tolerance = f32::from_le_bytes(header[10..CONFIG_LEN].try_into().expect("4 bytes"));
+        if !(TbfConfig::MIN_TOLERANCE..=TbfConfig::MAX_TOLERANCE).contains(&tolerance) {
+            return Err(Error::InvalidParameter("unsupported tolerance"));
+        }
+        Ok(TbfConfig::new(tolerance)
+            .with_bit_order(bit_order)
+            .with_thresholding(thresholding)
+            .with_transform(transform))
+    }
+}
+
+/// The id and mask base of `transform`, as the header stores them.
+pub(crate) fn transform_bytes(transform: &FinalTransform) -> [u8; 2] {
+    match transform {
+        FinalTransform::XorMask { base } => [transform.id(), *base],
+        _ => [transform.id(), 0],
+    }
+}
+
+/// Appends the checksum of everything in `out`.
+pub(crate) fn seal(out: &mut Vec<u8>) {
+    let checksum = Sha256::digest(&[out.as_slice()]);
+    out.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+}
+
+/// `bytes` without its checksum, if that matches.
+pub(crate) fn checked_body(bytes: &[u8]) -> Result<&[u8], Error> {
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if Sha256::digest(&[body])[..CHECKSUM_LEN] != *checksum {
+        return Err(Error::InvalidChecksum);
+    }
+    Ok(body)
+}
+
+/// The input length at `bytes[CONFIG_LEN..CONFIG_LEN + 8]`, rejecting
+/// lengths whose bit count does not fit a `usize`.
+pub(crate) fn read_input_len(bytes: &[u8]) -> Result<usize, Error> {
+    let len = u64::from_le_bytes(
+        bytes[CONFIG_LEN..CONFIG_LEN + 8]
+            .try_into()
+            .expect("8 bytes"),
+    );
+    usize::try_from(len)
+        .ok()
+        .filter(|len| len.checked_mul(8).is_some())
+        .ok_or(Error::InvalidParameter("input length overflows"))
+}
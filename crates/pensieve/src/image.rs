@@ -0,0 +1,320 @@
+//! Perceptual fingerprints of images.
+//!
+//! Re-encoding, rescaling or slightly recoloring a picture changes nearly
+//! every byte of it, so raw pixels cannot be collapsed directly. The
+//! standard remedy reduces the picture to its coarse structure first:
+//!
+//! 1. convert to grayscale (ITU-R BT.601 luma);
+//! 2. resize to a small square by area averaging;
+//! 3. optionally keep only the lowest frequencies of its 2D DCT;
+//! 4. set one bit per remaining value that lies above their median.
+//!
+//! Without the DCT this is the "average hash"; with it, the "perceptual
+//! hash". The resulting bitstring, from [`preprocess`], is ready for
+//! [`collapse_deterministic`](crate::collapse_deterministic) or the
+//! [`index`](crate::index) structures.
+//!
+//! Pixels are taken as raw 8-bit buffers; decoding image files is left to
+//! dedicated crates.
+
+use crate::Error;
+use alloc::vec::Vec;
+
+/// Layout of one pixel in an [`ImageRef`] buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// One luma byte.
+    Gray8,
+    /// Red, green and blue bytes.
+    Rgb8,
+    /// Red, green, blue and alpha bytes; alpha is ignored.
+    Rgba8,
+}
+
+impl PixelFormat {
+    /// Bytes per pixel.
+    pub fn channels(self) -> usize {
+        match self {
+            Self::Gray8 => 1,
+            Self::Rgb8 => 3,
+            Self::Rgba8 => 4,
+        }
+    }
+}
+
+/// A borrowed, row-major 8-bit image without padding between rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageRef<'a> {
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    pixels: &'a [u8],
+}
+
+impl<'a> ImageRef<'a> {
+    /// Wraps `pixels`, which must hold exactly `width * height` pixels.
+    ///
+    /// Fails with [`Error::InvalidParameter`] for an empty image and with
+    /// [`Error::InvalidLength`] if the buffer has the wrong size.
+    pub fn new(
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        pixels: &'a [u8],
+    ) -> Result<Self, Error> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidParameter("image has no pixels"));
+        }
+        let expected = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(format.channels()))
+            .ok_or(Error::InvalidParameter("image dimensions overflow"))?;
+        if pixels.len() != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                found: pixels.len(),
+            });
+        }
+        Ok(Self {
+            width,
+            height,
+            format,
+            pixels,
+        })
+    }
+}
+
+/// The resize and frequency-selection steps of [`preprocess`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pipeline {
+    size: usize,        // Side of the resized square.
+    dct: Option<usize>, // Side of the kept low-frequency square, if any.
+}
+
+impl Pipeline {
+    /// Average hash: 8x8 pixels, no DCT, 64 bits.
+    pub const AVERAGE: Self = Self { size: 8, dct: None };
+    /// Perceptual hash: 32x32 pixels, the lowest 8x8 DCT frequencies, 64 bits.
+    pub const PERCEPTUAL: Self = Self {
+        size: 32,
+        dct: Some(8),
+    };
+
+    /// Resizes to `size` x `size` and, with `Some(keep)`, keeps the lowest
+    /// `keep` x `keep` DCT frequencies.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `size` is zero or `keep`
+    /// is zero or larger than `size`.
+    pub fn new(size: usize, dct: Option<usize>) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::InvalidParameter("resized image has no pixels"));
+        }
+        if dct.is_some_and(|keep| keep == 0 || keep > size) {
+            return Err(Error::InvalidParameter(
+                "kept frequencies must be 1 to the resized size",
+            ));
+        }
+        Ok(Self { size, dct })
+    }
+
+    /// Number of bits [`preprocess`] produces.
+    pub fn bits(&self) -> usize {
+        let side = self.dct.unwrap_or(self.size);
+        side * side
+    }
+}
+
+/// Runs `image` through `pipeline` and returns its bitstring, MSB-first,
+/// padded with zero bits to whole bytes (see
+/// [`collapse_bits`](crate::collapse_bits) for collapsing padded inputs).
+///
+/// # Examples
+/// ```rust
+/// use pensieve::image::{ImageRef, Pipeline, PixelFormat, preprocess};
+///
+/// // A horizontal gradient and a brighter copy of it.
+/// let dark: Vec<u8> = (0..64 * 64).map(|i| (i % 64) as u8).collect();
+/// let bright: Vec<u8> = dark.iter().map(|&p| p * 2 + 40).collect();
+/// let hash = |pixels: &[u8]| {
+///     let image = ImageRef::new(64, 64, PixelFormat::Gray8, pixels).unwrap();
+///     preprocess(&image, &Pipeline::AVERAGE)
+/// };
+/// assert_eq!(hash(&dark), hash(&bright));
+/// assert_eq!(hash(&dark).len(), 8);
+/// ```
+pub fn preprocess(image: &ImageRef<'_>, pipeline: &Pipeline) -> Vec<u8> {
+    let resized = resize(&grayscale(image), image.width, image.height, pipeline.size);
+    let values = match pipeline.dct {
+        Some(keep) => dct_low_frequencies(&resized, pipeline.size, keep),
+        None => resized,
+    };
+    threshold(&values)
+}
+
+/// Luma of every pixel, in 0.0..=255.0.
+fn grayscale(image: &ImageRef<'_>) -> Vec<f32> {
+    image
+        .pixels
+        .chunks_exact(image.format.channels())
+        .map(|pixel| match image.format {
+            PixelFormat::Gray8 => f32::from(pixel[0]),
+            PixelFormat::Rgb8 | PixelFormat::Rgba8 => {
+                0.299 * f32::from(pixel[0])
+                    + 0.587 * f32::from(pixel[1])
+                    + 0.114 * f32::from(pixel[2])
+            }
+        })
+        .collect()
+}
+
+/// Area-averages a `width` x `height` image into `size` x `size`; each
+/// target pixel averages the source pixels it covers (at least one).
+fn resize(gray: &[f32], width: usize, height: usize, size: usize) -> Vec<f32> {
+    let span = |i: usize, len: usize| {
+        let start = i * len / size;
+        (start, ((i + 1) * len / size).max(start + 1))
+    };
+    let mut out = Vec::with_capacity(size * size);
+    for y in 0..size {
+        let (y0, y1) = span(y, height);
+        for x in 0..size {
+            let (x0, x1) = span(x, width);
+            let sum: f32 = (y0..y1)
+                .flat_map(|row| &gray[row * width + x0..row * width + x1])
+                .sum();
+            out.push(sum / ((y1 - y0) * (x1 - x0)) as f32);
+        }
+    }
+    out
+}
+
+/// The `keep` x `keep` lowest-frequency coefficients of the (unnormalized)
+/// 2D DCT-II of a `size` x `size` image, row-major.
+fn dct_low_frequencies(values: &[f32], size: usize, keep: usize) -> Vec<f32> {
+    // basis[u * size + x] = cos((2x + 1) u π / 2 size)
+    let basis: Vec<f32> = (0..keep)
+        .flat_map(|u| {
+            (0..size).map(move |x| {
+                ((2 * x + 1) as f32 * u as f32 * core::f32::consts::PI / (2 * size) as f32).cos()
+            })
+        })
+        .collect();
+    // Transform the rows, then the columns of the kept rows' coefficients.
+    let rows: Vec<f32> = (0..size)
+        .flat_map(|y| {
+            let row = &values[y * size..(y + 1) * size];
+            let basis = &basis;
+            (0..keep).map(move |u| {
+                row.iter()
+                    .zip(&basis[u * size..(u + 1) * size])
+                    .map(|(p, c)| p * c)
+                    .sum::<f32>()
+            })
+        })
+        .collect();
+    let mut out = Vec::with_capacity(keep * keep);
+    for v in 0..keep {
+        for u in 0..keep {
+            out.push(
+                (0..size)
+                    .map(|y| rows[y * keep + u] * basis[v * size + y])
+                    .sum(),
+            );
+        }
+    }
+    out
+}
+
+/// One bit per value, set when it lies above the median, MSB-first.
+fn threshold(values: &[f32]) -> Vec<u8> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let median = sorted[(sorted.len() - 1) / 2];
+    let mut out = alloc::vec![0u8; values.len().div_ceil(8)];
+    for (i, &value) in values.iter().enumerate() {
+        out[i / 8] |= u8::from(value > median) << (7 - i % 8);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::hamming;
+
+    /// A smooth synthetic scene: two blobs on a gradient, `w` x `h` RGB.
+    fn scene(w: usize, h: usize, shift: f32) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(w * h * 3);
+        for y in 0..h {
+            for x in 0..w {
+                let (fx, fy) = (x as f32 / w as f32, y as f32 / h as f32);
+                let blob = |cx: f32, cy: f32| {
+                    let d = (fx - cx) * (fx - cx) + (fy - cy) * (fy - cy);
+                    (-d * 20.0).exp() * 150.0
+                };
+                let value = 40.0 * fx + blob(0.3 + shift, 0.3) + blob(0.7, 0.6 - shift);
+                let value = value.min(255.0) as u8;
+                pixels.extend_from_slice(&[value, value / 2, 255 - value]);
+            }
+        }
+        pixels
+    }
+
+    fn hash(pixels: &[u8], w: usize, h: usize, pipeline: &Pipeline) -> Vec<u8> {
+        preprocess(
+            &ImageRef::new(w, h, PixelFormat::Rgb8, pixels).unwrap(),
+            pipeline,
+        )
+    }
+
+    #[test]
+    fn test_robust_to_rescaling_and_noise() {
+        for pipeline in [Pipeline::AVERAGE, Pipeline::PERCEPTUAL] {
+            let original = hash(&scene(120, 90, 0.0), 120, 90, &pipeline);
+            assert_eq!(original.len(), 8);
+            let rescaled = hash(&scene(240, 180, 0.0), 240, 180, &pipeline);
+            let mut noisy = scene(120, 90, 0.0);
+            noisy
+                .iter_mut()
+                .enumerate()
+                .for_each(|(i, p)| *p = p.saturating_add((i * 7 % 5) as u8));
+            let noisy = hash(&noisy, 120, 90, &pipeline);
+            let other = hash(&scene(120, 90, 0.35), 120, 90, &pipeline);
+
+            assert!(hamming(&original, &rescaled) <= 6, "{pipeline:?}");
+            assert!(hamming(&original, &noisy) <= 4, "{pipeline:?}");
+            assert!(hamming(&original, &other) >= 16, "{pipeline:?}");
+        }
+    }
+
+    #[test]
+    fn test_formats_agree_and_bits_are_balanced() {
+        let gray: Vec<u8> = (0..16 * 16).map(|i| (i * 13 % 251) as u8).collect();
+        let rgba: Vec<u8> = gray.iter().flat_map(|&g| [g, g, g, 0x80]).collect();
+        let pipeline = Pipeline::new(4, None).unwrap();
+        let from_gray = preprocess(
+            &ImageRef::new(16, 16, PixelFormat::Gray8, &gray).unwrap(),
+            &pipeline,
+        );
+        let from_rgba = preprocess(
+            &ImageRef::new(16, 16, PixelFormat::Rgba8, &rgba).unwrap(),
+            &pipeline,
+        );
+        assert_eq!(from_gray, from_rgba);
+        // Half of the 16 values lie above the median.
+        assert_eq!(crate::distance::popcount(&from_gray), 8);
+    }
+
+    #[test]
+    fn test_parameters_are_validated() {
+        assert!(ImageRef::new(0, 4, PixelFormat::Gray8, &[]).is_err());
+        assert!(ImageRef::new(2, 2, PixelFormat::Rgb8, &[0; 11]).is_err());
+        assert!(Pipeline::new(0, None).is_err());
+        assert!(Pipeline::new(8, Some(9)).is_err());
+        assert!(Pipeline::new(8, Some(0)).is_err());
+        assert_eq!(Pipeline::new(5, Some(3)).unwrap().bits(), 9);
+        // 9 bits are padded to 2 bytes; the 1x1 image upscales to a constant.
+        let one = ImageRef::new(1, 1, PixelFormat::Gray8, &[7]).unwrap();
+        assert_eq!(preprocess(&one, &Pipeline::new(3, None).unwrap()), [0, 0]);
+    }
+}
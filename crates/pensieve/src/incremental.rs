@@ -0,0 +1,150 @@
+//! Re-collapsing large inputs after small in-place edits.
+//!
+//! A collapsed value depends on the input only through the popcount of each
+//! chunk. [`ChunkCounts`] keeps those popcounts, so an edit of a few bytes
+//! updates the chunks it touches in time proportional to the edit, and the
+//! new output is re-derived from the counts without reading the input
+//! again.
+//!
+//! Edits must keep the input length: inserting or deleting bytes moves every
+//! chunk boundary after the edit, which is a fresh collapse.
+//!
+//! Updates branch on the edited bits, so like
+//! [`collapse_deterministic`](crate::collapse_deterministic) this is meant
+//! for public data.
+
+use crate::{ChunkLayout, Error, FinalTransform, ct_popcount_range};
+use alloc::vec::Vec;
+
+/// Per-chunk popcounts of an input, kept up to date through edits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkCounts {
+    len: usize,                  // Input length in bytes.
+    layout: Option<ChunkLayout>, // `None` for empty input.
+    counts: Vec<u32>,            // Set bits of every chunk, in order.
+}
+
+impl ChunkCounts {
+    /// Counts the chunks of `input` under `tolerance` (clamped like in
+    /// [`collapse_ct`](crate::collapse_ct)).
+    pub fn new(input: &[u8], tolerance: f32) -> Self {
+        let layout = ChunkLayout::new(input.len(), tolerance);
+        let counts = layout.map_or_else(Vec::new, |layout| {
+            layout
+                .ranges()
+                .map(|(start, end)| ct_popcount_range(input, start, end))
+                .collect()
+        });
+        Self {
+            len: input.len(),
+            layout,
+            counts,
+        }
+    }
+
+    /// The popcount of every chunk, in order.
+    pub fn counts(&self) -> &[u32] {
+        &self.counts
+    }
+
+    /// Replaces the bytes `old` at `offset` with `new`, updating only the
+    /// chunks whose bits changed.
+    ///
+    /// `old` must be what the input currently holds at `offset`; the counts
+    /// are not checked against the original input. Fails with
+    /// [`Error::InvalidParameter`] if `old` and `new` differ in length or
+    /// the edit extends past the end of the input.
+    pub fn apply_edit(&mut self, offset: usize, old: &[u8], new: &[u8]) -> Result<(), Error> {
+        if old.len() != new.len() {
+            return Err(Error::InvalidParameter(
+                "an edit must not change the input length",
+            ));
+        }
+        if offset
+            .checked_add(old.len())
+            .is_none_or(|end| end > self.len)
+        {
+            return Err(Error::InvalidParameter("edit extends past the input"));
+        }
+        let Some(layout) = self.layout else {
+            return Ok(()); // Only an empty edit fits an empty input.
+        };
+        for (i, (&before, &after)) in old.iter().zip(new).enumerate() {
+            let mut changed = before ^ after;
+            while changed != 0 {
+                let bit = changed.leading_zeros() as usize; // MSB-first offset in the byte.
+                changed &= !(0x80 >> bit);
+                let chunk = ((offset + i) * 8 + bit) / layout.chunk_size;
+                if after & (0x80 >> bit) != 0 {
+                    self.counts[chunk] += 1;
+                } else {
+                    self.counts[chunk] = self.counts[chunk].saturating_sub(1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The collapsed output of the input as currently edited; identical to
+    /// [`collapse_ct`](crate::collapse_ct) of that input.
+    pub fn digest(&self) -> Vec<u8> {
+        let Some(layout) = self.layout else {
+            return Vec::new();
+        };
+        let levels: Vec<u8> = self
+            .counts
+            .iter()
+            .map(|&count| u8::from(count >= layout.threshold))
+            .collect();
+        FinalTransform::default().apply(&levels, self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collapse_ct;
+    use crate::entropy::tests::seeded;
+
+    #[test]
+    fn test_edits_match_full_recollapse() {
+        let mut rng = seeded(17);
+        for len in [1usize, 7, 15, 16, 100, 1000] {
+            let mut input = alloc::vec![0u8; len];
+            rng(&mut input);
+            // Sparse inputs keep chunks near the threshold, so edits matter.
+            input.iter_mut().for_each(|b| *b &= 0x11);
+            let mut counts = ChunkCounts::new(&input, 0.125);
+            for _ in 0..50 {
+                let mut pick = [0u8; 4];
+                rng(&mut pick);
+                let offset = usize::from(u16::from_le_bytes([pick[0], pick[1]])) % len;
+                let edit_len = (usize::from(pick[2]) % 4 + 1).min(len - offset);
+                let mut new = alloc::vec![0u8; edit_len];
+                rng(&mut new);
+                counts
+                    .apply_edit(offset, &input[offset..offset + edit_len], &new)
+                    .unwrap();
+                input[offset..offset + edit_len].copy_from_slice(&new);
+                assert_eq!(counts.digest(), collapse_ct(&input, 0.125), "len {len}");
+            }
+            assert_eq!(counts, ChunkCounts::new(&input, 0.125));
+        }
+    }
+
+    #[test]
+    fn test_counts_and_rejected_edits() {
+        let mut counts = ChunkCounts::new(&[0xFF, 0x00, 0x0F, 0x01], 0.1);
+        // 32 bits in two chunks of 16.
+        assert_eq!(counts.counts(), &[8, 5]);
+        counts.apply_edit(1, &[0x00], &[0x03]).unwrap();
+        assert_eq!(counts.counts(), &[10, 5]);
+        assert!(counts.apply_edit(3, &[0x01, 0x00], &[0, 0]).is_err());
+        assert!(counts.apply_edit(0, &[0xFF], &[]).is_err());
+        assert!(counts.apply_edit(usize::MAX, &[0], &[1]).is_err());
+
+        let mut empty = ChunkCounts::new(&[], 0.1);
+        empty.apply_edit(0, &[], &[]).unwrap();
+        assert!(empty.digest().is_empty());
+    }
+}
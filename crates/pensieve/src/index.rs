@@ -0,0 +1,102 @@
+//! Near-duplicate search over stored digests.
+//!
+//! Collapsed digests of two readings of the same source are identical or,
+//! for readings near the tolerance boundary, differ in a few chunks. Finding
+//! the stored digests within a small Hamming distance of a query is the
+//! building block of deduplication and similarity search; the structures
+//! here do it without comparing the query against every stored digest.
+//!
+//! - [`BkTree`] answers exact range and k-nearest-neighbor queries and
+//!   suits up to a few million distinct keys with small query radii;
+//!   entries sharing a key share its node.
+//! - [`LshIndex`] finds most (not provably all) near matches with a
+//!   sub-linear number of comparisons, for very large collections.
+//! - [`FuzzyBloom`] stores no keys at all, only a fixed-size bit array, and
+//!   answers "is anything near this key?" with a tunable false-positive
+//!   rate, for stream filtering.
+//! - `ShardedBkTree` (feature `std`) spreads a [`BkTree`] over
+//!   independently locked shards, so many threads can insert and query at
+//!   once.
+//! - `JournaledBkTree` (feature `std`) logs every insertion into a
+//!   [`BkTree`] to disk and periodically compacts the log into a saved
+//!   tree, so an indexing job survives crashes.
+//! - [`StoredIndex`] is [`LshIndex`] on top of a
+//!   [`FingerprintStore`](crate::store::FingerprintStore), for collections
+//!   that live in a database.
+//! - [`CascadeMatcher`] looks the query's digest up under a coarse
+//!   configuration and confirms the candidates through stricter
+//!   configurations or a Hamming bound, stage by stage.
+//!
+//! Items that are not bitstrings reach these indexes through an
+//! [`LshFamily`], which hashes them to digests whose Hamming distance
+//! follows their own distance; [`CosineLsh`] does so for embedding vectors
+//! under cosine similarity, and [`FamilyIndex`] wraps an [`LshIndex`] to
+//! take such items directly.
+//!
+//! All keys of one index, except of a [`CascadeMatcher`], must have the
+//! same length.
+//!
+//! # Map-reduce
+//! `merge` appends one index to another of the same kind, so a corpus can be
+//! fingerprinted and indexed in parts on several machines and the parts
+//! combined into a single index; [`LshIndex`]es must be created from
+//! identically seeded sources to merge. `snapshot` freezes an index into a
+//! [`Snapshot`] that query threads share by cloning, while the original
+//! keeps taking insertions.
+//!
+//! # Files
+//! With `u64` values, both indexes serialize with `to_bytes` and, with the
+//! `std` feature, `save` to and `load` from any reader or writer. A saved
+//! index is wrapped in a container so a restarted service can tell a good
+//! file from a damaged, foreign or too-new one:
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 4 | magic `PNSI` |
+//! | 2 | format version, little-endian (currently 1) |
+//! | 1 | index type: 1 = [`BkTree`], 2 = [`LshIndex`] |
+//! | 1 | reserved, zero |
+//! | 8 | payload length, little-endian |
+//! | n | payload: the index's `to_bytes` |
+//! | 32 | SHA-256 of everything above |
+//!
+//! Loading checks every field and the checksum before parsing the payload.
+
+mod bk_tree;
+mod bloom;
+mod cascade;
+mod cosine;
+mod family;
+#[cfg(feature = "std")]
+mod file;
+#[cfg(feature = "std")]
+mod journal;
+mod lsh;
+#[cfg(feature = "std")]
+mod sharded;
+mod snapshot;
+mod stored;
+
+pub use bk_tree::BkTree;
+pub use bloom::{BloomParams, FuzzyBloom};
+pub use cascade::{CascadeMatcher, Stage};
+pub use cosine::CosineLsh;
+pub use family::{FamilyIndex, LshFamily};
+#[cfg(feature = "std")]
+pub use journal::JournaledBkTree;
+pub use lsh::{LshIndex, LshParams, LshStats};
+#[cfg(feature = "std")]
+pub use sharded::ShardedBkTree;
+pub use snapshot::Snapshot;
+pub use stored::StoredIndex;
+
+/// A stored entry returned by a range or nearest-neighbor query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'a, V> {
+    /// The stored key.
+    pub key: &'a [u8],
+    /// The value stored with it.
+    pub value: &'a V,
+    /// Hamming distance between the key and the query, in bits.
+    pub distance: u32,
+}
@@ -0,0 +1,691 @@
+//! Burkhard–Keller tree over the Hamming metric.
+
+#[cfg(feature = "std")]
+use super::file::{self, Kind};
+use super::{Match, Snapshot};
+use crate::distance::hamming;
+use crate::error::Error;
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+
+/// Marks the root in the serialized parent column.
+const NO_PARENT: u32 = u32::MAX;
+
+/// A metric tree answering "all keys within distance `d` of this query".
+///
+/// Every node stores its children keyed by their distance to it; by the
+/// triangle inequality a query only has to descend into children whose
+/// distance lies within `d` of the query's own distance to the node. For
+/// small `d` relative to the key length this visits a small fraction of the
+/// tree.
+///
+/// Duplicate keys are allowed and are all returned, but each distinct key
+/// is stored once, with the values of its entries in a postings list: a
+/// corpus in which many records collapse to the same digest costs one node
+/// per distinct digest. [`BkTree::unique_keys`] and
+/// [`BkTree::heaviest_keys`] show how skewed the stored keys are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BkTree<V> {
+    key_len: usize,      // Bytes per key; fixed by the first insertion.
+    keys: Vec<u8>,       // Node keys, back to back, in order of first insertion.
+    nodes: Vec<Node<V>>, // Node 0 is the root.
+    entries: usize,      // Values over all postings lists.
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Node<V> {
+    values: Vec<V>,            // Postings of this key, in insertion order.
+    children: Vec<(u32, u32)>, // (distance to this node, child index).
+}
+
+impl<V> BkTree<V> {
+    /// Creates an empty tree.
+    pub fn new() -> Self {
+        Self {
+            key_len: 0,
+            keys: Vec::new(),
+            nodes: Vec::new(),
+            entries: 0,
+        }
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        self.entries
+    }
+
+    /// Number of distinct keys, which is the number of nodes.
+    pub fn unique_keys(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The `n` keys with the most entries, with the values of their
+    /// entries, most entries first; among keys with as many entries the
+    /// earlier inserted ones come first.
+    pub fn heaviest_keys(&self, n: usize) -> Vec<(&[u8], &[V])> {
+        let mut heaviest: Vec<usize> = (0..self.nodes.len()).collect();
+        heaviest.sort_by_key(|&index| core::cmp::Reverse(self.nodes[index].values.len()));
+        heaviest
+            .into_iter()
+            .take(n)
+            .map(|index| (self.key(index), &self.nodes[index].values[..]))
+            .collect()
+    }
+
+    /// Whether nothing has been inserted yet.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Length of every key in bytes, once the first entry is inserted.
+    pub fn key_len(&self) -> Option<usize> {
+        (!self.is_empty()).then_some(self.key_len)
+    }
+
+    /// Stores `value` under `key`, appending it to the postings of `key` if
+    /// the key is already stored.
+    ///
+    /// Fails with [`Error::InvalidLength`] if `key` is not as long as the
+    /// keys already stored.
+    pub fn insert(&mut self, key: &[u8], value: V) -> Result<(), Error> {
+        if self.is_empty() {
+            self.key_len = key.len();
+        }
+        self.check_len(key)?;
+
+        self.entries += 1;
+        let new = self.nodes.len() as u32;
+        if !self.is_empty() {
+            let mut current = 0usize;
+            loop {
+                let distance = hamming(key, self.key(current));
+                if distance == 0 {
+                    self.nodes[current].values.push(value);
+                    return Ok(());
+                }
+                let next = self.nodes[current]
+                    .children
+                    .iter()
+                    .find(|&&(d, _)| d == distance)
+                    .map(|&(_, child)| child as usize);
+                match next {
+                    Some(child) => current = child,
+                    None => {
+                        self.nodes[current].children.push((distance, new));
+                        break;
+                    }
+                }
+            }
+        }
+        self.keys.extend_from_slice(key);
+        self.nodes.push(Node {
+            values: alloc::vec![value],
+            children: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Inserts every `(key, value)` of `entries` in order, first asking
+    /// `should_continue` each time, so a service can abandon a large build
+    /// once its request has timed out.
+    ///
+    /// Fails with [`Error::Cancelled`] as soon as `should_continue` returns
+    /// `false`, and like [`BkTree::insert`] otherwise; the entries inserted
+    /// until then stay.
+    pub fn insert_all<K: AsRef<[u8]>>(
+        &mut self,
+        entries: impl IntoIterator<Item = (K, V)>,
+        mut should_continue: impl FnMut() -> bool,
+    ) -> Result<(), Error> {
+        for (key, value) in entries {
+            if !should_continue() {
+                return Err(Error::Cancelled);
+            }
+            self.insert(key.as_ref(), value)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts every entry of `other` after the entries of this tree, key
+    /// by key in the order the keys were first inserted into `other`. Trees
+    /// built from parts of
+    /// a corpus, on different machines, combine into the tree of the whole
+    /// corpus this way.
+    ///
+    /// Fails with [`Error::InvalidLength`], before inserting anything, if
+    /// both trees hold keys of different lengths.
+    pub fn merge(&mut self, other: BkTree<V>) -> Result<(), Error> {
+        if !self.is_empty() && !other.is_empty() && other.key_len != self.key_len {
+            return Err(Error::InvalidLength {
+                expected: self.key_len,
+                found: other.key_len,
+            });
+        }
+        let BkTree {
+            key_len,
+            keys,
+            nodes,
+            ..
+        } = other;
+        for (i, node) in nodes.into_iter().enumerate() {
+            for value in node.values {
+                self.insert(&keys[i * key_len..(i + 1) * key_len], value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A frozen copy of this tree for concurrent queries; see [`Snapshot`].
+    pub fn snapshot(&self) -> Snapshot<Self>
+    where
+        V: Clone,
+    {
+        Snapshot::new(self.clone())
+    }
+
+    /// Returns every entry whose key is at most `max_distance` bits away
+    /// from `query`, closest first.
+    pub fn query_within(
+        &self,
+        query: &[u8],
+        max_distance: u32,
+    ) -> Result<Vec<Match<'_, V>>, Error> {
+        let mut matches = Vec::new();
+        if self.is_empty() {
+            return Ok(matches);
+        }
+        self.check_len(query)?;
+
+        let mut examined = 0;
+        let mut stack = alloc::vec![0usize];
+        while let Some(index) = stack.pop() {
+            examined += 1;
+            let key = self.key(index);
+            let distance = hamming(query, key);
+            if distance <= max_distance {
+                matches.extend(self.nodes[index].values.iter().map(|value| Match {
+                    key,
+                    value,
+                    distance,
+                }));
+            }
+            let low = distance.saturating_sub(max_distance);
+            let high = distance.saturating_add(max_distance);
+            stack.extend(
+                self.nodes[index]
+                    .children
+                    .iter()
+                    .filter(|&&(d, _)| (low..=high).contains(&d))
+                    .map(|&(_, child)| child as usize),
+            );
+        }
+        crate::metrics::candidates_examined("bk_tree", examined);
+        matches.sort_by_key(|m| m.distance);
+        Ok(matches)
+    }
+
+    /// Returns the `k` entries whose keys are closest to `query`, closest
+    /// first; among equally distant keys the earlier inserted ones win, and
+    /// among the entries of one key the earlier inserted ones. Fewer are
+    /// returned only if fewer are stored.
+    ///
+    /// The search radius shrinks to the distance of the `k`-th best entry
+    /// found so far, so small `k` prunes like a small [`query_within`]
+    /// radius once good matches turn up.
+    ///
+    /// [`query_within`]: BkTree::query_within
+    pub fn query_knn(&self, query: &[u8], k: usize) -> Result<Vec<Match<'_, V>>, Error> {
+        if self.is_empty() || k == 0 {
+            return Ok(Vec::new());
+        }
+        self.check_len(query)?;
+
+        // The best `(distance, index)` nodes so far, worst on top, and the
+        // number of entries they hold; the worst is dropped while the others
+        // still hold `k` entries.
+        let mut best = BinaryHeap::new();
+        let (mut entries, mut examined) = (0, 0);
+        let mut stack = alloc::vec![0usize];
+        while let Some(index) = stack.pop() {
+            examined += 1;
+            let distance = hamming(query, self.key(index));
+            best.push((distance, index));
+            entries += self.nodes[index].values.len();
+            while let Some(&(_, worst)) = best.peek() {
+                let without = entries - self.nodes[worst].values.len();
+                if without < k {
+                    break;
+                }
+                best.pop();
+                entries = without;
+            }
+            let radius = match best.peek() {
+                Some(&(worst, _)) if entries >= k => worst,
+                _ => u32::MAX,
+            };
+            let low = distance.saturating_sub(radius);
+            let high = distance.saturating_add(radius);
+            stack.extend(
+                self.nodes[index]
+                    .children
+                    .iter()
+                    .filter(|&&(d, _)| (low..=high).contains(&d))
+                    .map(|&(_, child)| child as usize),
+            );
+        }
+        crate::metrics::candidates_examined("bk_tree", examined);
+        Ok(best
+            .into_sorted_vec()
+            .into_iter()
+            .flat_map(|(distance, index)| {
+                let key = self.key(index);
+                self.nodes[index].values.iter().map(move |value| Match {
+                    key,
+                    value,
+                    distance,
+                })
+            })
+            .take(k)
+            .collect())
+    }
+
+    fn key(&self, index: usize) -> &[u8] {
+        &self.keys[index * self.key_len..(index + 1) * self.key_len]
+    }
+
+    fn check_len(&self, key: &[u8]) -> Result<(), Error> {
+        if key.len() != self.key_len {
+            return Err(Error::InvalidLength {
+                expected: self.key_len,
+                found: key.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<V> Default for BkTree<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BkTree<u64> {
+    /// Serializes as `key_len (u32) || entry_count (u32)` followed by every
+    /// entry as `key || value (u64) || parent (u32)`, the root's parent
+    /// being `u32::MAX`. All integers are little-endian.
+    ///
+    /// Entries are written node by node. The first entry of a node points
+    /// at the first entry of its parent node, every further entry of the
+    /// node at the entry before it, as a tree storing every duplicate in a
+    /// node of its own would.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut parents = alloc::vec![NO_PARENT; self.nodes.len()];
+        for (index, node) in self.nodes.iter().enumerate() {
+            for &(_, child) in &node.children {
+                parents[child as usize] = index as u32;
+            }
+        }
+
+        let mut out = Vec::with_capacity(8 + self.entries * (self.key_len + 12));
+        out.extend_from_slice(&(self.key_len as u32).to_le_bytes());
+        out.extend_from_slice(&(self.entries as u32).to_le_bytes());
+        let mut first_entries = Vec::with_capacity(self.nodes.len());
+        let mut written = 0u32;
+        for (index, node) in self.nodes.iter().enumerate() {
+            first_entries.push(written);
+            let mut parent = match parents[index] {
+                NO_PARENT => NO_PARENT,
+                parent => first_entries[parent as usize],
+            };
+            for value in &node.values {
+                out.extend_from_slice(self.key(index));
+                out.extend_from_slice(&value.to_le_bytes());
+                out.extend_from_slice(&parent.to_le_bytes());
+                parent = written;
+                written += 1;
+            }
+        }
+        out
+    }
+
+    /// Parses the output of [`BkTree::to_bytes`], rejecting trees whose
+    /// structure is inconsistent with their keys.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 8 {
+            return Err(Error::InvalidLength {
+                expected: 8,
+                found: bytes.len(),
+            });
+        }
+        let key_len = u32::from_le_bytes(bytes[..4].try_into().expect("4 bytes")) as usize;
+        let count = u32::from_le_bytes(bytes[4..8].try_into().expect("4 bytes")) as usize;
+        let record_len = key_len + 12;
+        let expected = count
+            .checked_mul(record_len)
+            .and_then(|body| body.checked_add(8))
+            .ok_or(Error::InvalidParameter("node count overflows"))?;
+        if bytes.len() != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let mut tree = Self {
+            key_len,
+            keys: Vec::new(),
+            nodes: Vec::new(),
+            entries: count,
+        };
+        // The node holding each entry read so far.
+        let mut entry_nodes: Vec<usize> = Vec::with_capacity(count);
+        for (index, record) in bytes[8..].chunks_exact(record_len).enumerate() {
+            let (key, rest) = record.split_at(key_len);
+            let value = u64::from_le_bytes(rest[..8].try_into().expect("8 bytes"));
+            let parent = u32::from_le_bytes(rest[8..].try_into().expect("4 bytes"));
+            let parent = match (index, parent) {
+                (0, NO_PARENT) => None,
+                (0, _) | (_, NO_PARENT) => {
+                    return Err(Error::InvalidParameter("only the first node is a root"));
+                }
+                (_, parent) if parent as usize >= index => {
+                    return Err(Error::InvalidParameter("parent must precede its child"));
+                }
+                (_, parent) => Some(entry_nodes[parent as usize]),
+            };
+            let node = tree.nodes.len();
+            if let Some(parent) = parent {
+                let distance = hamming(key, tree.key(parent));
+                if distance == 0 {
+                    // Another entry of the parent's key.
+                    tree.nodes[parent].values.push(value);
+                    entry_nodes.push(parent);
+                    continue;
+                }
+                if tree.nodes[parent]
+                    .children
+                    .iter()
+                    .any(|&(d, _)| d == distance)
+                {
+                    return Err(Error::InvalidParameter("duplicate child distance"));
+                }
+                tree.nodes[parent].children.push((distance, node as u32));
+            }
+            tree.keys.extend_from_slice(key);
+            tree.nodes.push(Node {
+                values: alloc::vec![value],
+                children: Vec::new(),
+            });
+            entry_nodes.push(node);
+        }
+        Ok(tree)
+    }
+
+    /// Writes [`BkTree::to_bytes`] to `writer` as a versioned, checksummed
+    /// [index file](crate::index#files).
+    #[cfg(feature = "std")]
+    pub fn save(&self, writer: impl std::io::Write) -> std::io::Result<()> {
+        file::write(Kind::BkTree, &self.to_bytes(), writer)
+    }
+
+    /// Reads a tree written by [`BkTree::save`]. Damaged, foreign or
+    /// too-new files fail with [`std::io::ErrorKind::InvalidData`].
+    #[cfg(feature = "std")]
+    pub fn load(reader: impl std::io::Read) -> std::io::Result<Self> {
+        file::read(Kind::BkTree, reader, Self::from_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    fn random_keys(count: usize, len: usize, seed: u64) -> Vec<Vec<u8>> {
+        let mut rng = seeded(seed);
+        (0..count)
+            .map(|_| {
+                let mut key = vec![0u8; len];
+                rng(&mut key);
+                key
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_all_stops_when_cancelled() {
+        let keys = random_keys(100, 4, 410);
+        let mut tree = BkTree::new();
+        let mut budget = 60;
+        let result = tree.insert_all(keys.iter().zip(0u64..), || {
+            budget -= 1;
+            budget >= 0
+        });
+        assert_eq!(result, Err(Error::Cancelled));
+        assert_eq!(tree.len(), 60);
+        assert_eq!(tree.query_within(&keys[59], 0).unwrap()[0].value, &59);
+
+        let mut whole = BkTree::new();
+        whole.insert_all(keys.iter().zip(0u64..), || true).unwrap();
+        assert_eq!(whole.len(), 100);
+    }
+
+    #[test]
+    fn test_query_matches_linear_scan() {
+        let keys = random_keys(500, 4, 1);
+        let mut tree = BkTree::new();
+        for (i, key) in keys.iter().enumerate() {
+            tree.insert(key, i as u64).unwrap();
+        }
+        for query in random_keys(20, 4, 2).iter().chain(&keys[..5]) {
+            for max in [0, 3, 8] {
+                let mut found: Vec<u64> = tree
+                    .query_within(query, max)
+                    .unwrap()
+                    .iter()
+                    .map(|m| *m.value)
+                    .collect();
+                found.sort();
+                let expected: Vec<u64> = (0..keys.len() as u64)
+                    .filter(|&i| hamming(&keys[i as usize], query) <= max)
+                    .collect();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_knn_matches_linear_scan() {
+        let keys = random_keys(500, 4, 3);
+        let mut tree = BkTree::new();
+        for (i, key) in keys.iter().enumerate() {
+            tree.insert(key, i as u64).unwrap();
+        }
+        for query in random_keys(20, 4, 4).iter().chain(&keys[..5]) {
+            let mut ranked: Vec<(u32, u64)> = (0..keys.len() as u64)
+                .map(|i| (hamming(&keys[i as usize], query), i))
+                .collect();
+            ranked.sort();
+            for k in [1, 5, 40] {
+                let found: Vec<(u32, u64)> = tree
+                    .query_knn(query, k)
+                    .unwrap()
+                    .iter()
+                    .map(|m| (m.distance, *m.value))
+                    .collect();
+                assert_eq!(found, ranked[..k]);
+            }
+        }
+        assert_eq!(tree.query_knn(&keys[0], 1000).unwrap().len(), 500);
+        assert!(tree.query_knn(&keys[0], 0).unwrap().is_empty());
+        assert!(tree.query_knn(&[0; 3], 1).is_err());
+        assert!(
+            BkTree::<u64>::new()
+                .query_knn(&[0; 3], 1)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_merge_equals_one_tree_and_snapshots_stay_frozen() {
+        let keys = random_keys(300, 4, 5);
+        let (mut whole, mut left, mut right) = (BkTree::new(), BkTree::new(), BkTree::new());
+        for (i, key) in keys.iter().enumerate() {
+            whole.insert(key, i).unwrap();
+            if i < 100 {
+                left.insert(key, i).unwrap();
+            } else {
+                right.insert(key, i).unwrap();
+            }
+        }
+        left.merge(right).unwrap();
+        assert_eq!(left, whole);
+        left.merge(BkTree::new()).unwrap();
+        let mut empty = BkTree::new();
+        empty.merge(left.clone()).unwrap();
+        assert_eq!(empty, left);
+        let mut short = BkTree::new();
+        short.insert(&[0; 3], 0).unwrap();
+        assert_eq!(
+            left.merge(short),
+            Err(Error::InvalidLength {
+                expected: 4,
+                found: 3
+            })
+        );
+        assert_eq!(left, whole);
+
+        let snapshot = left.snapshot();
+        let shared = snapshot.clone();
+        left.insert(&keys[0], 1000).unwrap();
+        assert_eq!(snapshot.len(), 300);
+        assert_eq!(
+            shared.query_within(&keys[0], 0).unwrap(),
+            whole.query_within(&keys[0], 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_duplicates_and_ordering() {
+        let mut tree = BkTree::new();
+        tree.insert(&[0b0000_0000], "a").unwrap();
+        tree.insert(&[0b0000_0111], "b").unwrap();
+        tree.insert(&[0b0000_0000], "c").unwrap();
+        tree.insert(&[0b0000_0001], "d").unwrap();
+        let found = tree.query_within(&[0], 1).unwrap();
+        let distances: Vec<u32> = found.iter().map(|m| m.distance).collect();
+        assert_eq!(distances, [0, 0, 1]);
+        assert_eq!(tree.len(), 4);
+    }
+
+    #[test]
+    fn test_duplicate_keys_share_a_node() {
+        // A skewed corpus: a few keys, most entries on the first.
+        let keys = random_keys(5, 4, 6);
+        let mut tree = BkTree::new();
+        let mut naive = Vec::new();
+        for i in 0..200u64 {
+            let key = &keys[if i % 4 == 0 {
+                1 + (i as usize / 4) % 4
+            } else {
+                0
+            }];
+            tree.insert(key, i).unwrap();
+            naive.push((key.clone(), i));
+        }
+        assert_eq!(tree.len(), 200);
+        assert_eq!(tree.unique_keys(), 5);
+        let heaviest = tree.heaviest_keys(2);
+        assert_eq!(heaviest[0].0, &keys[0][..]);
+        assert_eq!(heaviest[0].1.len(), 150);
+        assert_eq!(heaviest[0].1[..3], [1, 2, 3]);
+        assert_eq!(tree.heaviest_keys(10).len(), 5);
+
+        for query in &keys {
+            for max in [0, 12] {
+                let found: Vec<(u32, u64)> = tree
+                    .query_within(query, max)
+                    .unwrap()
+                    .iter()
+                    .map(|m| (m.distance, *m.value))
+                    .collect();
+                let expected = naive
+                    .iter()
+                    .filter(|(key, _)| hamming(key, query) <= max)
+                    .count();
+                assert_eq!(found.len(), expected);
+            }
+            let nearest = tree.query_knn(query, 160).unwrap();
+            assert_eq!(nearest.len(), 160);
+            assert!(nearest.windows(2).all(|w| w[0].distance <= w[1].distance));
+            let mut distances: Vec<u32> =
+                naive.iter().map(|(key, _)| hamming(key, query)).collect();
+            distances.sort();
+            assert_eq!(
+                nearest.iter().map(|m| m.distance).collect::<Vec<_>>(),
+                distances[..160]
+            );
+        }
+
+        // Postings survive serialization in the format of one node per entry.
+        let bytes = tree.to_bytes();
+        assert_eq!(bytes.len(), 8 + 200 * 16);
+        assert_eq!(BkTree::from_bytes(&bytes).unwrap(), tree);
+    }
+
+    #[test]
+    fn test_key_length_enforced() {
+        let mut tree = BkTree::new();
+        assert_eq!(tree.key_len(), None);
+        tree.insert(&[1, 2], 0u64).unwrap();
+        assert_eq!(tree.key_len(), Some(2));
+        assert_eq!(
+            tree.insert(&[1, 2, 3], 1),
+            Err(Error::InvalidLength {
+                expected: 2,
+                found: 3
+            })
+        );
+        assert!(tree.query_within(&[1], 0).is_err());
+    }
+
+    #[test]
+    fn test_serialization_roundtrip_and_validation() {
+        let mut tree = BkTree::new();
+        for (i, key) in random_keys(50, 8, 3).iter().enumerate() {
+            tree.insert(key, i as u64 * 7).unwrap();
+        }
+        let bytes = tree.to_bytes();
+        assert_eq!(BkTree::from_bytes(&bytes).unwrap(), tree);
+        assert_eq!(
+            BkTree::from_bytes(&BkTree::new().to_bytes()).unwrap(),
+            BkTree::new()
+        );
+
+        // Point the second node at itself.
+        let mut corrupt = bytes.clone();
+        let parent_offset = 8 + 20 + 16;
+        corrupt[parent_offset..parent_offset + 4].copy_from_slice(&1u32.to_le_bytes());
+        assert!(BkTree::from_bytes(&corrupt).is_err());
+        assert!(BkTree::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_save_load_roundtrip() {
+        let mut tree = BkTree::new();
+        for (i, key) in random_keys(20, 4, 4).iter().enumerate() {
+            tree.insert(key, i as u64).unwrap();
+        }
+        let mut file = Vec::new();
+        tree.save(&mut file).unwrap();
+        assert_eq!(BkTree::load(file.as_slice()).unwrap(), tree);
+
+        let last = file.len() - 1;
+        file[last] ^= 1;
+        let error = BkTree::load(file.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
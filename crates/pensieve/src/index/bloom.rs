@@ -0,0 +1,310 @@
+//! A Bloom filter answering "have I seen a key near this one?".
+
+use crate::error::Error;
+use crate::simhash::mix;
+use alloc::vec::Vec;
+
+/// Shape of a [`FuzzyBloom`].
+///
+/// Every key is cut into `bands` contiguous bit ranges and each range,
+/// tagged with its band number, is inserted into one shared bit array under
+/// `hashes` hash functions. Two keys differing in fewer than `bands` bits
+/// agree on at least one whole band (pigeonhole), so a query within
+/// `bands - 1` bits of an inserted key is always reported; more bands widen
+/// that radius at the cost of more bits per key for the same false-positive
+/// rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BloomParams {
+    bits: usize, // Size of the bit array.
+    hashes: u32, // Bits set per band sub-key.
+    bands: u16,  // Sub-keys per key; tolerated distance plus one.
+}
+
+impl BloomParams {
+    /// Validates the parameters: a non-empty bit array, 1 to 32 hash
+    /// functions and at least one band.
+    pub fn new(bits: usize, hashes: u32, bands: u16) -> Result<Self, Error> {
+        if bits == 0 {
+            return Err(Error::InvalidParameter("the bit array cannot be empty"));
+        }
+        if !(1..=32).contains(&hashes) {
+            return Err(Error::InvalidParameter("hashes must be in 1..=32"));
+        }
+        if bands == 0 {
+            return Err(Error::InvalidParameter("at least one band is required"));
+        }
+        Ok(Self {
+            bits,
+            hashes,
+            bands,
+        })
+    }
+
+    /// Sizes a filter holding `expected_items` keys so that a query more
+    /// than `max_distance` bits from all of them is reported with
+    /// probability about `false_positive_rate`.
+    #[cfg(feature = "std")]
+    pub fn for_false_positive_rate(
+        max_distance: u16,
+        expected_items: usize,
+        false_positive_rate: f64,
+    ) -> Result<Self, Error> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidParameter(
+                "false-positive rate must be in (0, 1)",
+            ));
+        }
+        let bands = max_distance
+            .checked_add(1)
+            .ok_or(Error::InvalidParameter("max distance too large"))?;
+        // A query probes every band, so each probe gets an equal share of the budget.
+        let per_probe = 1.0 - (1.0 - false_positive_rate).powf(1.0 / f64::from(bands));
+        let sub_keys = (expected_items.max(1) * usize::from(bands)) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-sub_keys * per_probe.ln() / (ln2 * ln2)).ceil();
+        let hashes = (bits / sub_keys * ln2).round().clamp(1.0, 32.0);
+        Self::new(bits as usize, hashes as u32, bands)
+    }
+
+    /// Size of the bit array.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// Bits set per band sub-key.
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    /// Number of bands each key is cut into.
+    pub fn bands(&self) -> u16 {
+        self.bands
+    }
+
+    /// Largest distance at which an inserted key is guaranteed to be found.
+    pub fn max_distance(&self) -> u32 {
+        u32::from(self.bands) - 1
+    }
+
+    /// Expected probability that a query sharing no whole band with any of
+    /// `items` inserted keys is still reported.
+    #[cfg(feature = "std")]
+    pub fn false_positive_rate(&self, items: usize) -> f64 {
+        let k = f64::from(self.hashes);
+        let fill = 1.0 - (-k * (items * usize::from(self.bands)) as f64 / self.bits as f64).exp();
+        let per_probe = fill.powf(k);
+        1.0 - (1.0 - per_probe).powi(i32::from(self.bands))
+    }
+}
+
+/// A compact, probabilistic set of fixed-length keys queried by proximity.
+///
+/// Only a fixed-size bit array is stored, never the keys, so memory does not
+/// grow with the number of insertions; the false-positive rate does. There
+/// are no false negatives within [`BloomParams::max_distance`]. A query
+/// farther away is reported if, by chance, one of its bands equals the same
+/// band of some inserted key (likely only for narrow bands) or if its bits in
+/// the array are all set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyBloom {
+    params: BloomParams,
+    key_len: usize,  // Bytes per key.
+    words: Vec<u64>, // The bit array.
+    items: usize,    // Insertions so far.
+}
+
+impl FuzzyBloom {
+    /// Creates an empty filter over keys of `key_len` bytes, which must have
+    /// at least one bit per band.
+    pub fn new(key_len: usize, params: BloomParams) -> Result<Self, Error> {
+        if key_len * 8 < usize::from(params.bands) {
+            return Err(Error::InvalidParameter(
+                "keys must have at least one bit per band",
+            ));
+        }
+        Ok(Self {
+            params,
+            key_len,
+            words: alloc::vec![0; params.bits.div_ceil(64)],
+            items: 0,
+        })
+    }
+
+    /// The parameters the filter was built with.
+    pub fn params(&self) -> BloomParams {
+        self.params
+    }
+
+    /// Number of keys inserted so far.
+    pub fn len(&self) -> usize {
+        self.items
+    }
+
+    /// Whether nothing has been inserted yet.
+    pub fn is_empty(&self) -> bool {
+        self.items == 0
+    }
+
+    /// Records `key`.
+    pub fn insert(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.check_len(key)?;
+        for band in 0..self.params.bands {
+            let (h1, h2) = self.band_hashes(key, band);
+            for i in 0..u64::from(self.params.hashes) {
+                let bit = self.bit_index(h1, h2, i);
+                self.words[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        self.items += 1;
+        Ok(())
+    }
+
+    /// Whether some inserted key may be within
+    /// [`max_distance`](BloomParams::max_distance) bits of `query`; `false`
+    /// is definite.
+    pub fn maybe_contains(&self, query: &[u8]) -> Result<bool, Error> {
+        self.check_len(query)?;
+        Ok((0..self.params.bands).any(|band| {
+            let (h1, h2) = self.band_hashes(query, band);
+            (0..u64::from(self.params.hashes)).all(|i| {
+                let bit = self.bit_index(h1, h2, i);
+                self.words[bit / 64] >> (bit % 64) & 1 == 1
+            })
+        }))
+    }
+
+    /// Fraction of set bits; the false-positive rate climbs steeply once
+    /// this passes one half.
+    pub fn fill_ratio(&self) -> f64 {
+        let set: u32 = self.words.iter().map(|w| w.count_ones()).sum();
+        f64::from(set) / self.params.bits as f64
+    }
+
+    /// Two independent hashes of one band's bits, for double hashing.
+    fn band_hashes(&self, key: &[u8], band: u16) -> (u64, u64) {
+        let total_bits = self.key_len * 8;
+        let bands = usize::from(self.params.bands);
+        let band_index = usize::from(band);
+        let start = band_index * total_bits / bands;
+        let end = (band_index + 1) * total_bits / bands;
+
+        let mut hash = mix(u64::from(band));
+        let (mut word, mut filled) = (0u64, 0);
+        for position in start..end {
+            word = word << 1 | u64::from(key[position / 8] >> (7 - position % 8) & 1);
+            filled += 1;
+            if filled == 64 {
+                hash = mix(hash ^ word);
+                (word, filled) = (0, 0);
+            }
+        }
+        let h1 = mix(hash ^ word);
+        (h1, mix(h1 ^ 0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// The `i`-th probed bit for a sub-key (Kirsch–Mitzenmacher).
+    fn bit_index(&self, h1: u64, h2: u64, i: u64) -> usize {
+        (h1.wrapping_add(i.wrapping_mul(h2)) % self.params.bits as u64) as usize
+    }
+
+    fn check_len(&self, key: &[u8]) -> Result<(), Error> {
+        if key.len() != self.key_len {
+            return Err(Error::InvalidLength {
+                expected: self.key_len,
+                found: key.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    fn random_keys(count: usize, len: usize, seed: u64) -> Vec<Vec<u8>> {
+        let mut rng = seeded(seed);
+        (0..count)
+            .map(|_| {
+                let mut key = vec![0u8; len];
+                rng(&mut key);
+                key
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_no_false_negatives_within_radius() {
+        let params = BloomParams::new(1 << 16, 4, 4).unwrap();
+        let mut filter = FuzzyBloom::new(32, params).unwrap();
+        let keys = random_keys(200, 32, 1);
+        for key in &keys {
+            filter.insert(key).unwrap();
+        }
+        let mut rng = seeded(2);
+        for key in &keys {
+            // Flip three bits (the guaranteed radius) at random positions.
+            let mut near = key.clone();
+            for _ in 0..params.max_distance() {
+                let mut byte = [0u8; 2];
+                rng(&mut byte);
+                near[usize::from(byte[0]) % 32] ^= 1 << (byte[1] % 8);
+            }
+            assert!(filter.maybe_contains(&near).unwrap());
+        }
+        assert_eq!(filter.len(), 200);
+    }
+
+    #[test]
+    fn test_far_queries_are_mostly_rejected() {
+        let params = BloomParams::new(1 << 14, 5, 3).unwrap();
+        let mut filter = FuzzyBloom::new(32, params).unwrap();
+        for key in random_keys(500, 32, 3) {
+            filter.insert(&key).unwrap();
+        }
+        let false_positives = random_keys(2000, 32, 4)
+            .iter()
+            .filter(|q| filter.maybe_contains(q).unwrap())
+            .count();
+        assert!(false_positives < 40, "{false_positives} false positives");
+        assert!(filter.fill_ratio() < 0.5);
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(BloomParams::new(0, 1, 1).is_err());
+        assert!(BloomParams::new(64, 0, 1).is_err());
+        assert!(BloomParams::new(64, 1, 0).is_err());
+        let params = BloomParams::new(64, 1, 9).unwrap();
+        assert!(FuzzyBloom::new(1, params).is_err());
+        let mut filter = FuzzyBloom::new(2, params).unwrap();
+        assert_eq!(
+            filter.insert(&[0]),
+            Err(Error::InvalidLength {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert!(filter.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_sizing_meets_target_rate() {
+        let params = BloomParams::for_false_positive_rate(2, 1000, 0.01).unwrap();
+        assert_eq!(params.bands(), 3);
+        let expected = params.false_positive_rate(1000);
+        assert!(expected <= 0.0105, "expected rate {expected}");
+
+        let mut filter = FuzzyBloom::new(32, params).unwrap();
+        for key in random_keys(1000, 32, 5) {
+            filter.insert(&key).unwrap();
+        }
+        let false_positives = random_keys(5000, 32, 6)
+            .iter()
+            .filter(|q| filter.maybe_contains(q).unwrap())
+            .count();
+        assert!(false_positives < 100, "{false_positives} false positives");
+    }
+}
@@ -0,0 +1,215 @@
+//! Coarse-to-fine matching through a chain of filters.
+
+use super::Match;
+use crate::TbfConfig;
+use crate::distance::hamming;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// One filter of a [`CascadeMatcher`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    /// Keeps the candidates whose key collapses like the query under this
+    /// configuration.
+    Collapse(TbfConfig),
+    /// Keeps the candidates whose key is at most this many bits away from
+    /// the query.
+    Hamming(u32),
+}
+
+/// Finds the entries matching a query by narrowing the entries that
+/// collapse like it under a coarse configuration through ever stricter
+/// stages.
+///
+/// The coarse stage is a lookup, so a query only ever examines the entries
+/// sharing its coarse digest; each further stage only examines the
+/// survivors of the one before. A forgiving coarse configuration keeps the
+/// readings of one source together, and the later stages, such as a
+/// [`OneSided`](crate::Thresholding::OneSided) configuration with a low
+/// tolerance or an exact Hamming bound, drop the look-alikes it lets
+/// through.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::index::{CascadeMatcher, Stage};
+/// use pensieve::{TbfConfig, Thresholding};
+///
+/// let coarse = TbfConfig::new(0.25).with_thresholding(Thresholding::Symmetric);
+/// let faint = TbfConfig::new(0.1);
+/// let mut matcher = CascadeMatcher::new(coarse)
+///     .then(Stage::Collapse(faint))
+///     .then(Stage::Hamming(4));
+/// matcher.insert(&[0x00; 16], "quiet");
+/// matcher.insert(&[0x01; 16], "faint");
+/// matcher.insert(&[0xF0; 16], "half");
+/// matcher.insert(&[0xFF; 16], "loud");
+///
+/// let mut query = [0x00u8; 16];
+/// query[5] = 0x01;
+/// let found = matcher.query(&query);
+/// assert_eq!(found.len(), 1);
+/// assert_eq!((found[0].value, found[0].distance), (&"quiet", 1));
+/// // Three collapse like the query, one of them also under `faint`.
+/// assert_eq!(matcher.funnel(&query), [3, 1, 1]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CascadeMatcher<V> {
+    coarse: TbfConfig,
+    stages: Vec<Stage>,                     // The stages after the coarse one.
+    buckets: BTreeMap<Vec<u8>, Vec<usize>>, // Coarse digest -> entries, in insertion order.
+    keys: Vec<Vec<u8>>,
+    values: Vec<V>,
+}
+
+impl<V> CascadeMatcher<V> {
+    /// An empty matcher whose first stage keeps the entries collapsing
+    /// like the query under `coarse`.
+    pub fn new(coarse: TbfConfig) -> Self {
+        Self {
+            coarse,
+            stages: Vec::new(),
+            buckets: BTreeMap::new(),
+            keys: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    /// This matcher with `stage` appended to its stages.
+    pub fn then(mut self, stage: Stage) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    /// The configuration of the coarse stage.
+    pub fn coarse(&self) -> &TbfConfig {
+        &self.coarse
+    }
+
+    /// The stages after the coarse one, in the order they run.
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether the matcher is empty.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Stores `value` under `key`. Keys may have any length; only keys of
+    /// the query's length ever match it.
+    pub fn insert(&mut self, key: &[u8], value: V) {
+        let digest = self.coarse.collapse(key);
+        self.buckets
+            .entry(digest)
+            .or_default()
+            .push(self.values.len());
+        self.keys.push(key.to_vec());
+        self.values.push(value);
+    }
+
+    /// The entries passing every stage for `query`, closest first, then in
+    /// insertion order.
+    pub fn query(&self, query: &[u8]) -> Vec<Match<'_, V>> {
+        let mut matches: Vec<Match<'_, V>> = self
+            .run(query, |_| {})
+            .into_iter()
+            .map(|index| Match {
+                key: &self.keys[index],
+                value: &self.values[index],
+                distance: hamming(query, &self.keys[index]),
+            })
+            .collect();
+        matches.sort_by_key(|m| m.distance);
+        matches
+    }
+
+    /// Number of entries surviving each stage for `query`, the coarse one
+    /// first; for tuning the stages against a sample of queries.
+    pub fn funnel(&self, query: &[u8]) -> Vec<usize> {
+        let mut counts = Vec::with_capacity(self.stages.len() + 1);
+        self.run(query, |survivors| counts.push(survivors));
+        counts
+    }
+
+    /// Indices of the entries passing every stage, reporting the number of
+    /// survivors of each stage to `report`.
+    fn run(&self, query: &[u8], mut report: impl FnMut(usize)) -> Vec<usize> {
+        let mut survivors = self
+            .buckets
+            .get(&self.coarse.collapse(query))
+            .cloned()
+            .unwrap_or_default();
+        // Equal digests imply equal lengths, so every later stage compares
+        // keys as long as the query.
+        report(survivors.len());
+        for stage in &self.stages {
+            match *stage {
+                Stage::Collapse(config) => {
+                    let digest = config.collapse(query);
+                    survivors.retain(|&index| config.collapse(&self.keys[index]) == digest);
+                }
+                Stage::Hamming(max_distance) => {
+                    survivors.retain(|&index| hamming(query, &self.keys[index]) <= max_distance);
+                }
+            }
+            report(survivors.len());
+        }
+        crate::metrics::candidates_examined("cascade", survivors.len());
+        survivors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    #[test]
+    fn test_stages_narrow_down_to_a_linear_scan() {
+        let mut fill = seeded(414);
+        let mut keys = Vec::new();
+        for _ in 0..300 {
+            let mut key = [0u8; 8];
+            fill(&mut key);
+            // Mostly dense keys, so many share a coarse digest.
+            key.iter_mut().for_each(|b| *b |= 0xE7);
+            keys.push(key);
+        }
+        let strict = TbfConfig::new(0.05);
+        let matcher = keys.iter().enumerate().fold(
+            CascadeMatcher::new(TbfConfig::new(0.25))
+                .then(Stage::Collapse(strict))
+                .then(Stage::Hamming(6)),
+            |mut matcher, (i, key)| {
+                matcher.insert(key, i);
+                matcher
+            },
+        );
+        assert_eq!(matcher.len(), 300);
+        assert_eq!(matcher.stages().len(), 2);
+
+        for query in &keys[..20] {
+            let expected: Vec<usize> = (0..keys.len())
+                .filter(|&i| {
+                    TbfConfig::new(0.25).collapse(&keys[i]) == TbfConfig::new(0.25).collapse(query)
+                        && strict.collapse(&keys[i]) == strict.collapse(query)
+                        && hamming(&keys[i], query) <= 6
+                })
+                .collect();
+            let mut found: Vec<usize> = matcher.query(query).iter().map(|m| *m.value).collect();
+            found.sort();
+            assert_eq!(found, expected);
+
+            let funnel = matcher.funnel(query);
+            assert_eq!(funnel.len(), 3);
+            assert!(funnel.windows(2).all(|w| w[0] >= w[1]));
+            assert_eq!(funnel[2], expected.len());
+        }
+        assert!(matcher.query(&[0u8; 9]).is_empty());
+    }
+}
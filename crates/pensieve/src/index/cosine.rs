@@ -0,0 +1,152 @@
+//! Random-hyperplane (SimHash-style) LSH for cosine similarity.
+
+use super::LshFamily;
+use crate::embedding::{Projections, Quantizer};
+use crate::error::Error;
+use alloc::vec::Vec;
+
+/// Signed random projections: bit `i` of a digest says on which side of the
+/// `i`-th seeded random hyperplane a vector lies.
+///
+/// Two vectors at angle `θ` disagree on each bit with probability `θ / π`,
+/// so the Hamming distance `d` of their `n`-bit digests estimates
+/// `θ ≈ π d / n`, and with it their cosine similarity, independently of
+/// their lengths. The seed fixes the hyperplanes: digests are comparable
+/// only between families with the same dimensions, bits and seed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CosineLsh {
+    projections: Projections,
+}
+
+impl CosineLsh {
+    /// Draws `bits` hyperplanes in `dimensions`-dimensional space from
+    /// `seed`.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `dimensions` is zero or
+    /// `bits` is not a positive multiple of 8.
+    pub fn new(dimensions: usize, bits: usize, seed: u64) -> Result<Self, Error> {
+        if !bits.is_multiple_of(8) {
+            return Err(Error::InvalidParameter(
+                "cosine digests must be a whole number of bytes",
+            ));
+        }
+        Ok(Self {
+            projections: Projections::new(dimensions, bits, seed)?,
+        })
+    }
+
+    /// Number of dimensions of the hashed vectors.
+    pub fn dimensions(&self) -> usize {
+        self.projections.dimensions()
+    }
+
+    /// Bits per digest.
+    pub fn bits(&self) -> usize {
+        self.projections.bits()
+    }
+
+    /// The seed the hyperplanes were drawn from.
+    pub fn seed(&self) -> u64 {
+        self.projections.seed()
+    }
+
+    /// The cosine similarity estimated from the Hamming distance between two
+    /// digests: `cos(π d / n)`.
+    #[cfg(feature = "std")]
+    pub fn estimate_similarity(&self, distance: u32) -> f64 {
+        let fraction = (f64::from(distance) / self.bits() as f64).min(1.0);
+        (core::f64::consts::PI * fraction).cos()
+    }
+
+    /// The largest Hamming distance whose [estimated
+    /// similarity](Self::estimate_similarity) is still at least
+    /// `similarity`: the radius to query for vectors that similar.
+    #[cfg(feature = "std")]
+    pub fn max_distance(&self, similarity: f64) -> u32 {
+        let fraction = similarity.clamp(-1.0, 1.0).acos() / core::f64::consts::PI;
+        (fraction * self.bits() as f64).floor() as u32
+    }
+}
+
+impl LshFamily for CosineLsh {
+    type Item = [f32];
+
+    fn digest_len(&self) -> usize {
+        self.bits() / 8
+    }
+
+    fn digest(&self, item: &[f32]) -> Result<Vec<u8>, Error> {
+        self.projections.quantize(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use crate::index::{FamilyIndex, LshParams};
+
+    /// Pseudo-random vectors with entries in -1.0..1.0.
+    fn vectors(count: usize, dimensions: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = seeded(seed);
+        (0..count)
+            .map(|_| {
+                let mut bytes = alloc::vec![0u8; dimensions];
+                rng(&mut bytes);
+                bytes.iter().map(|&b| f32::from(b) / 128.0 - 1.0).collect()
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "std")]
+    fn cosine(a: &[f32], b: &[f32]) -> f64 {
+        let dot =
+            |x: &[f32], y: &[f32]| -> f64 { x.iter().zip(y).map(|(p, q)| f64::from(p * q)).sum() };
+        dot(a, b) / (dot(a, a) * dot(b, b)).sqrt()
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_distance_estimates_cosine() {
+        let lsh = CosineLsh::new(32, 1024, 11).unwrap();
+        assert_eq!(lsh.digest_len(), 128);
+        let data = vectors(6, 32, 1);
+        for pair in data.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            let mixed: Vec<f32> = a.iter().zip(b).map(|(x, y)| x + 0.5 * y).collect();
+            for other in [b, &mixed] {
+                let distance =
+                    crate::distance::hamming(&lsh.digest(a).unwrap(), &lsh.digest(other).unwrap());
+                let error = (lsh.estimate_similarity(distance) - cosine(a, other)).abs();
+                assert!(error < 0.15, "error {error}");
+            }
+        }
+        assert_eq!(lsh.max_distance(1.0), 0);
+        assert_eq!(lsh.max_distance(-1.0), 1024);
+        assert_eq!(lsh.max_distance(0.0), 512);
+    }
+
+    #[test]
+    fn test_family_index_finds_similar_vectors() {
+        let lsh = CosineLsh::new(16, 64, 3).unwrap();
+        let radius = 6; // About cosine 0.95 at 64 bits.
+        let params = LshParams::new(8, 16, 1).unwrap();
+        let mut index = FamilyIndex::new(lsh, params, &mut seeded(4)).unwrap();
+        let data = vectors(200, 16, 5);
+        for (i, vector) in data.iter().enumerate() {
+            index.insert(vector, i).unwrap();
+        }
+        assert_eq!(index.len(), 200);
+
+        let noise = vectors(1, 16, 6).remove(0);
+        let query: Vec<f32> = data[7]
+            .iter()
+            .zip(&noise)
+            .map(|(x, n)| 2.0 * x + 0.1 * n)
+            .collect();
+        let matches = index.query_within(&query, radius).unwrap();
+        assert_eq!(matches.first().map(|m| *m.value), Some(7));
+        assert!(index.query_within(&[0.0; 3], radius).is_err());
+        assert!(CosineLsh::new(16, 12, 0).is_err());
+    }
+}
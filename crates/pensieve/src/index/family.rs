@@ -0,0 +1,81 @@
+//! Locality-sensitive hash families feeding the Hamming-space indexes.
+
+use super::{LshIndex, LshParams, Match};
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use alloc::vec::Vec;
+
+/// Maps items of some metric space to fixed-length digests whose Hamming
+/// distance grows with the items' distance, so the indexes here can search
+/// that space.
+pub trait LshFamily {
+    /// The items being hashed.
+    type Item: ?Sized;
+
+    /// Bytes per digest.
+    fn digest_len(&self) -> usize;
+
+    /// The digest of `item`, [`digest_len`](Self::digest_len) bytes.
+    fn digest(&self, item: &Self::Item) -> Result<Vec<u8>, Error>;
+}
+
+/// An [`LshIndex`] over the digests of an [`LshFamily`], taking items
+/// instead of keys.
+#[derive(Debug, Clone)]
+pub struct FamilyIndex<F, V> {
+    family: F,
+    index: LshIndex<V>,
+}
+
+impl<F: LshFamily, V> FamilyIndex<F, V> {
+    /// Creates an empty index over the digests of `family`; see
+    /// [`LshIndex::new`].
+    pub fn new(family: F, params: LshParams, rng: &mut impl EntropySource) -> Result<Self, Error> {
+        let index = LshIndex::new(family.digest_len(), params, rng)?;
+        Ok(Self { family, index })
+    }
+
+    /// The family items are hashed with.
+    pub fn family(&self) -> &F {
+        &self.family
+    }
+
+    /// The underlying index, keyed by digest.
+    pub fn index(&self) -> &LshIndex<V> {
+        &self.index
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Stores `value` under the digest of `item`.
+    pub fn insert(&mut self, item: &F::Item, value: V) -> Result<(), Error> {
+        let key = self.family.digest(item)?;
+        self.index.insert(&key, value)
+    }
+
+    /// Returns the stored entries whose digests are at most `max_distance`
+    /// bits from the digest of `item`; see [`LshIndex::query_within`].
+    pub fn query_within(
+        &self,
+        item: &F::Item,
+        max_distance: u32,
+    ) -> Result<Vec<Match<'_, V>>, Error> {
+        let key = self.family.digest(item)?;
+        self.index.query_within(&key, max_distance)
+    }
+
+    /// Returns the `k` stored entries whose digests are closest to the
+    /// digest of `item`; see [`LshIndex::query_knn`].
+    pub fn query_knn(&self, item: &F::Item, k: usize) -> Result<Vec<Match<'_, V>>, Error> {
+        let key = self.family.digest(item)?;
+        self.index.query_knn(&key, k)
+    }
+}
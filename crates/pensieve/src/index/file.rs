@@ -0,0 +1,139 @@
+//! The versioned, checksummed container behind `save` and `load`.
+
+use crate::crypto::sha256::Sha256;
+use crate::error::Error;
+use alloc::vec::Vec;
+
+/// First bytes of every index file.
+const MAGIC: [u8; 4] = *b"PNSI";
+/// Newest container version this build writes and reads.
+const VERSION: u16 = 1;
+/// `magic || version (u16) || kind (u8) || reserved (u8) || payload_len (u64)`.
+const HEADER_LEN: usize = 16;
+/// SHA-256 over header and payload.
+const CHECKSUM_LEN: usize = 32;
+
+/// Which index a file holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    BkTree = 1,
+    Lsh = 2,
+}
+
+/// Wraps a serialized index of type `kind` into a file image.
+pub(super) fn seal(kind: Kind, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len() + CHECKSUM_LEN);
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.push(kind as u8);
+    out.push(0);
+    out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    out.extend_from_slice(payload);
+    let checksum = Sha256::digest(&[&out]);
+    out.extend_from_slice(&checksum);
+    out
+}
+
+/// Checks a file image and returns the serialized index inside it.
+pub(super) fn open(kind: Kind, bytes: &[u8]) -> Result<&[u8], Error> {
+    if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+        return Err(Error::InvalidLength {
+            expected: HEADER_LEN + CHECKSUM_LEN,
+            found: bytes.len(),
+        });
+    }
+    if bytes[..4] != MAGIC {
+        return Err(Error::InvalidParameter("not a pensieve index file"));
+    }
+    let version = u16::from_le_bytes(bytes[4..6].try_into().expect("2 bytes"));
+    if version == 0 || version > VERSION {
+        return Err(Error::InvalidParameter("unsupported index file version"));
+    }
+    let payload_len = u64::from_le_bytes(bytes[8..16].try_into().expect("8 bytes"));
+    let expected = usize::try_from(payload_len)
+        .ok()
+        .and_then(|len| len.checked_add(HEADER_LEN + CHECKSUM_LEN))
+        .ok_or(Error::InvalidParameter("payload length overflows"))?;
+    if bytes.len() != expected {
+        return Err(Error::InvalidLength {
+            expected,
+            found: bytes.len(),
+        });
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if Sha256::digest(&[body]) != checksum {
+        return Err(Error::InvalidChecksum);
+    }
+    // Only trust the remaining header fields once the checksum vouches for them.
+    if bytes[6] != kind as u8 {
+        return Err(Error::InvalidParameter("file holds a different index type"));
+    }
+    if bytes[7] != 0 {
+        return Err(Error::InvalidParameter("reserved header byte is set"));
+    }
+    Ok(&body[HEADER_LEN..])
+}
+
+/// Writes `payload` sealed as `kind` to `writer`.
+#[cfg(feature = "std")]
+pub(super) fn write(
+    kind: Kind,
+    payload: &[u8],
+    mut writer: impl std::io::Write,
+) -> std::io::Result<()> {
+    writer.write_all(&seal(kind, payload))?;
+    writer.flush()
+}
+
+/// Reads a whole file image of type `kind` from `reader` and hands its
+/// payload to `parse`; format errors surface as [`std::io::ErrorKind::InvalidData`].
+#[cfg(feature = "std")]
+pub(super) fn read<T>(
+    kind: Kind,
+    mut reader: impl std::io::Read,
+    parse: impl FnOnce(&[u8]) -> Result<T, Error>,
+) -> std::io::Result<T> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    open(kind, &bytes)
+        .and_then(parse)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let sealed = seal(Kind::Lsh, b"payload");
+        assert_eq!(sealed.len(), HEADER_LEN + 7 + CHECKSUM_LEN);
+        assert_eq!(open(Kind::Lsh, &sealed), Ok(&b"payload"[..]));
+        assert_eq!(open(Kind::Lsh, &seal(Kind::Lsh, &[])), Ok(&[][..]));
+    }
+
+    #[test]
+    fn test_open_rejects_damage() {
+        let sealed = seal(Kind::BkTree, b"some index");
+        assert_eq!(
+            open(Kind::Lsh, &sealed),
+            Err(Error::InvalidParameter("file holds a different index type"))
+        );
+
+        let mut flipped = sealed.clone();
+        flipped[HEADER_LEN + 3] ^= 1;
+        assert_eq!(open(Kind::BkTree, &flipped), Err(Error::InvalidChecksum));
+
+        let mut future = sealed.clone();
+        future[4] = 2;
+        assert_eq!(
+            open(Kind::BkTree, &future),
+            Err(Error::InvalidParameter("unsupported index file version"))
+        );
+
+        let mut foreign = sealed.clone();
+        foreign[0] = b'X';
+        assert!(open(Kind::BkTree, &foreign).is_err());
+        assert!(open(Kind::BkTree, &sealed[..sealed.len() - 1]).is_err());
+    }
+}
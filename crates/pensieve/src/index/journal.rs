@@ -0,0 +1,404 @@
+//! A [`BkTree`] made durable by a snapshot file and an append-only log.
+
+use super::{BkTree, Snapshot};
+use crate::crypto::sha256::Sha256;
+use crate::error::Error;
+use alloc::vec::Vec;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// First bytes of every log file.
+const MAGIC: [u8; 4] = *b"PNSJ";
+/// Newest log version this build writes and reads.
+const VERSION: u16 = 1;
+/// `magic || version (u16) || reserved (u16) || base (u64)`, where `base`
+/// is the number of entries in the snapshot the log continues.
+const HEADER_LEN: usize = 16;
+/// Bytes of truncated SHA-256 after every record.
+const CHECKSUM_LEN: usize = 4;
+/// Logged insertions after which [`JournaledBkTree::insert`] compacts.
+const DEFAULT_COMPACTION_INTERVAL: u64 = 65_536;
+
+/// A [`BkTree`] with `u64` values that survives crashes without
+/// re-collapsing the corpus it indexes.
+///
+/// The tree lives in two files: a snapshot at `path` in the format of
+/// [`BkTree::save`], and a log at `path` with `.log` appended. Every
+/// insertion appends one checksummed record to the log and is handed to
+/// the operating system before returning; [`JournaledBkTree::sync`] also
+/// waits for the disk. [`JournaledBkTree::open`] loads the snapshot and
+/// replays the log on top of it, so an interrupted indexing job resumes
+/// where it stopped.
+///
+/// Once the log holds as many records as the compaction interval, the next
+/// insertion [compacts](JournaledBkTree::compact) it into a fresh snapshot,
+/// keeping reopening fast. The snapshot is replaced atomically before the
+/// log is emptied, and the log remembers the size of the snapshot it
+/// continues, so a crash at any point of a compaction loses nothing and
+/// replays nothing twice.
+///
+/// A record cut short by a crash is discarded on the next open; any other
+/// damage fails the open with [`io::ErrorKind::InvalidData`].
+///
+/// # Examples
+/// ```rust
+/// use pensieve::index::JournaledBkTree;
+///
+/// let path = std::env::temp_dir().join(format!("pensieve-doc-{}", std::process::id()));
+/// let mut index = JournaledBkTree::open(&path).unwrap();
+/// index.insert(&[0b1010_0000, 0], 7).unwrap();
+/// drop(index); // A crash would do.
+///
+/// let index = JournaledBkTree::open(&path).unwrap();
+/// let found = index.tree().query_within(&[0b1010_0001, 0], 1).unwrap();
+/// assert_eq!(*found[0].value, 7);
+/// # std::fs::remove_file(path.with_extension("log")).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct JournaledBkTree {
+    path: PathBuf,
+    log_path: PathBuf,
+    log: File,          // Opened for appending.
+    tree: BkTree<u64>,  // Everything snapshot and log describe.
+    logged: u64,        // Records in the log.
+    compact_every: u64, // Compaction interval; 0 never compacts.
+}
+
+impl JournaledBkTree {
+    /// Opens the tree whose snapshot is at `path`, creating an empty one if
+    /// neither the snapshot nor its log exists.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let log_path = suffixed(&path, ".log");
+        let tree = match File::open(&path) {
+            Ok(file) => BkTree::load(BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BkTree::new(),
+            Err(e) => return Err(e),
+        };
+        let mut log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&log_path)?;
+        let mut bytes = Vec::new();
+        log.read_to_end(&mut bytes)?;
+        if bytes.is_empty() {
+            bytes = header(tree.len() as u64).to_vec();
+            log.write_all(&bytes)?;
+        } else if bytes.len() < HEADER_LEN
+            || bytes[..4] != MAGIC
+            || !(1..=VERSION).contains(&u16::from_le_bytes([bytes[4], bytes[5]]))
+            || bytes[6..8] != [0, 0]
+        {
+            return Err(invalid("not a supported pensieve index log"));
+        }
+        let base = u64::from_le_bytes(bytes[8..16].try_into().expect("8 bytes"));
+        // A compaction that replaced the snapshot but not the log leaves
+        // records the snapshot already holds.
+        let mut skip = (tree.len() as u64)
+            .checked_sub(base)
+            .ok_or_else(|| invalid("index log continues a larger snapshot"))?;
+
+        let mut journal = Self {
+            path,
+            log_path,
+            log,
+            tree,
+            logged: 0,
+            compact_every: DEFAULT_COMPACTION_INTERVAL,
+        };
+        let mut at = HEADER_LEN;
+        while at < bytes.len() {
+            let Some(len) = record_len(&bytes[at..]) else {
+                // A torn final write: drop it so new records follow good ones.
+                journal.log.set_len(at as u64)?;
+                journal.log.seek(SeekFrom::End(0))?;
+                break;
+            };
+            let (record, checksum) = bytes[at..at + len].split_at(len - CHECKSUM_LEN);
+            if Sha256::digest(&[record])[..CHECKSUM_LEN] != *checksum {
+                return Err(invalid("index log record checksum mismatch"));
+            }
+            if skip > 0 {
+                skip -= 1;
+            } else {
+                let (key, value) = record.split_at(record.len() - 8);
+                let value = u64::from_le_bytes(value.try_into().expect("8 bytes"));
+                journal
+                    .tree
+                    .insert(&key[4..], value)
+                    .map_err(|_| invalid("index log record has the wrong key length"))?;
+            }
+            journal.logged += 1;
+            at += len;
+        }
+        if skip > 0 {
+            return Err(invalid("index log is shorter than its snapshot"));
+        }
+        Ok(journal)
+    }
+
+    /// This tree compacting after `records` logged insertions instead of
+    /// the default 65 536; 0 only compacts when asked to.
+    pub fn with_compaction_interval(self, records: u64) -> Self {
+        Self {
+            compact_every: records,
+            ..self
+        }
+    }
+
+    /// The path of the snapshot file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The tree, for queries.
+    pub fn tree(&self) -> &BkTree<u64> {
+        &self.tree
+    }
+
+    /// A frozen copy of the tree for concurrent queries; see [`Snapshot`].
+    pub fn snapshot(&self) -> Snapshot<BkTree<u64>> {
+        self.tree.snapshot()
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Whether nothing has been inserted yet.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Number of records in the log that [`JournaledBkTree::compact`] would
+    /// fold into the snapshot.
+    pub fn logged_records(&self) -> u64 {
+        self.logged
+    }
+
+    /// Stores `value` under `key`, logging it first.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if `key` is not as long
+    /// as the keys already stored.
+    pub fn insert(&mut self, key: &[u8], value: u64) -> io::Result<()> {
+        if let Some(expected) = self.tree.key_len()
+            && key.len() != expected
+        {
+            return Err(Error::InvalidLength {
+                expected,
+                found: key.len(),
+            }
+            .into());
+        }
+        if u32::try_from(key.len()).is_err() {
+            return Err(Error::InvalidParameter("index log record is too large").into());
+        }
+        self.log.write_all(&record(key, value))?;
+        self.log.flush()?;
+        self.tree.insert(key, value)?;
+        self.logged += 1;
+        if self.compact_every != 0 && self.logged >= self.compact_every {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Waits until every insertion logged so far is on disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.log.sync_data()
+    }
+
+    /// Writes the whole tree to a new snapshot and empties the log, each
+    /// file replaced atomically once its successor is on disk.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut snapshot = Vec::new();
+        self.tree.save(&mut snapshot)?;
+        replace(&self.path, &snapshot)?;
+        replace(&self.log_path, &header(self.tree.len() as u64))?;
+        self.log = OpenOptions::new().append(true).open(&self.log_path)?;
+        self.logged = 0;
+        Ok(())
+    }
+}
+
+/// `path` with `suffix` appended to its file name.
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Atomically replaces the file at `path` with `bytes`.
+fn replace(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temporary = suffixed(path, ".compact");
+    {
+        let mut file = File::create(&temporary)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temporary, path)
+}
+
+fn header(base: u64) -> [u8; HEADER_LEN] {
+    let mut header = [0u8; HEADER_LEN];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4..6].copy_from_slice(&VERSION.to_le_bytes());
+    header[8..].copy_from_slice(&base.to_le_bytes());
+    header
+}
+
+/// `len (u32) || key || value (u64) || checksum`, integers little-endian.
+fn record(key: &[u8], value: u64) -> Vec<u8> {
+    let mut out = Vec::with_capacity(12 + key.len() + CHECKSUM_LEN);
+    out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    out.extend_from_slice(key);
+    out.extend_from_slice(&value.to_le_bytes());
+    let checksum = Sha256::digest(&[&out]);
+    out.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+    out
+}
+
+/// Length of the record at the start of `bytes`, checksum included, or
+/// `None` if `bytes` ends before it does.
+fn record_len(bytes: &[u8]) -> Option<usize> {
+    let key = u32::from_le_bytes(bytes.get(..4)?.try_into().expect("4 bytes")) as usize;
+    let len = key.checked_add(12 + CHECKSUM_LEN)?;
+    (bytes.len() >= len).then_some(len)
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, Error::InvalidParameter(reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    /// A fresh snapshot path in the system temporary directory.
+    fn temporary(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(std::format!(
+            "pensieve-{}-journal-{name}",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(suffixed(&path, ".log"));
+        path
+    }
+
+    fn remove(path: &Path) {
+        let _ = fs::remove_file(path);
+        fs::remove_file(suffixed(path, ".log")).unwrap();
+    }
+
+    fn random_keys(count: usize, seed: u64) -> Vec<[u8; 4]> {
+        let mut rng = seeded(seed);
+        (0..count)
+            .map(|_| {
+                let mut key = [0u8; 4];
+                rng(&mut key);
+                key
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resumes_across_reopening_and_compaction() {
+        let path = temporary("resume");
+        let keys = random_keys(50, 1);
+        let mut expected = BkTree::new();
+        let mut journal = JournaledBkTree::open(&path)
+            .unwrap()
+            .with_compaction_interval(20);
+        for (i, key) in keys.iter().enumerate() {
+            journal.insert(key, i as u64).unwrap();
+            expected.insert(key, i as u64).unwrap();
+            if i % 15 == 14 {
+                // Resume with a new handle, as after a crash.
+                journal = JournaledBkTree::open(&path)
+                    .unwrap()
+                    .with_compaction_interval(20);
+            }
+        }
+        assert_eq!(journal.tree(), &expected);
+        assert!(fs::metadata(&path).is_ok());
+        assert_eq!(journal.logged_records(), 50 % 20);
+        assert_eq!(
+            journal.insert(&[1, 2], 0).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        journal.sync().unwrap();
+        drop(journal);
+
+        let mut journal = JournaledBkTree::open(&path).unwrap();
+        assert_eq!(journal.tree(), &expected);
+        let snapshot = journal.snapshot();
+        journal.compact().unwrap();
+        assert_eq!(journal.logged_records(), 0);
+        assert_eq!(
+            fs::metadata(suffixed(&path, ".log")).unwrap().len(),
+            HEADER_LEN as u64
+        );
+        drop(journal);
+        assert_eq!(JournaledBkTree::open(&path).unwrap().tree(), &*snapshot);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_interrupted_compaction_replays_nothing_twice() {
+        let path = temporary("interrupted");
+        let log_path = suffixed(&path, ".log");
+        let mut journal = JournaledBkTree::open(&path).unwrap();
+        for (i, key) in random_keys(10, 2).iter().enumerate() {
+            journal.insert(key, i as u64).unwrap();
+        }
+        let log = fs::read(&log_path).unwrap();
+        // Crash after the new snapshot is in place but before the log is.
+        journal.compact().unwrap();
+        let expected = journal.tree().clone();
+        drop(journal);
+        fs::write(&log_path, &log).unwrap();
+        let mut journal = JournaledBkTree::open(&path).unwrap();
+        assert_eq!(journal.tree(), &expected);
+        journal.insert(&[0; 4], 10).unwrap();
+        assert_eq!(JournaledBkTree::open(&path).unwrap().len(), 11);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_torn_tail_is_dropped_and_damage_rejected() {
+        let path = temporary("damage");
+        let log_path = suffixed(&path, ".log");
+        let mut journal = JournaledBkTree::open(&path).unwrap();
+        journal.insert(&[1, 2, 3, 4], 1).unwrap();
+        journal.insert(&[5, 6, 7, 8], 2).unwrap();
+        drop(journal);
+        let bytes = fs::read(&log_path).unwrap();
+
+        fs::write(&log_path, &bytes[..bytes.len() - 3]).unwrap();
+        let mut journal = JournaledBkTree::open(&path).unwrap();
+        assert_eq!(journal.len(), 1);
+        journal.insert(&[9, 9, 9, 9], 3).unwrap();
+        drop(journal);
+        assert_eq!(JournaledBkTree::open(&path).unwrap().len(), 2);
+
+        let mut flipped = bytes.clone();
+        flipped[HEADER_LEN + 5] ^= 1;
+        fs::write(&log_path, &flipped).unwrap();
+        let error = JournaledBkTree::open(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // The log claims to continue a snapshot of one entry, which is missing.
+        let mut orphaned = bytes.clone();
+        orphaned[8] = 1;
+        fs::write(&log_path, &orphaned).unwrap();
+        assert!(JournaledBkTree::open(&path).is_err());
+
+        fs::write(&log_path, b"not a log").unwrap();
+        assert!(JournaledBkTree::open(&path).is_err());
+        remove(&path);
+    }
+}
@@ -0,0 +1,719 @@
+//! Bit-sampling locality-sensitive hashing with multi-probe queries.
+
+#[cfg(feature = "std")]
+use super::file::{self, Kind};
+use super::{Match, Snapshot};
+use crate::distance::hamming;
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// Shape of an [`LshIndex`].
+///
+/// Each of the `bands` tables hashes a key to the `rows` bits found at
+/// randomly sampled positions. Two keys at Hamming distance `d` out of `n`
+/// bits agree on a sampled bit with probability `1 - d / n`, so they share a
+/// bucket in some table with a probability that falls steeply with `d`; see
+/// [`LshParams::collision_probability`]. More rows make buckets more
+/// selective, more bands recover recall, and a probe radius `r` also visits
+/// the buckets whose key differs from the query's in up to `r` bits, which
+/// buys recall without more tables at the price of `C(rows, r)` extra
+/// lookups per band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LshParams {
+    bands: u16,       // Number of hash tables.
+    rows: u8,         // Sampled bits per table, 1..=64.
+    probe_radius: u8, // Bits flipped when probing neighbouring buckets.
+}
+
+impl LshParams {
+    /// Validates the parameters: at least one band, 1 to 64 rows and a probe
+    /// radius no larger than `rows`.
+    pub fn new(bands: u16, rows: u8, probe_radius: u8) -> Result<Self, Error> {
+        if bands == 0 {
+            return Err(Error::InvalidParameter("at least one band is required"));
+        }
+        if !(1..=64).contains(&rows) {
+            return Err(Error::InvalidParameter("rows must be in 1..=64"));
+        }
+        if probe_radius > rows {
+            return Err(Error::InvalidParameter(
+                "probe radius cannot exceed the row count",
+            ));
+        }
+        Ok(Self {
+            bands,
+            rows,
+            probe_radius,
+        })
+    }
+
+    /// Number of hash tables.
+    pub fn bands(&self) -> u16 {
+        self.bands
+    }
+
+    /// Sampled bits per table.
+    pub fn rows(&self) -> u8 {
+        self.rows
+    }
+
+    /// Hamming radius of the probed neighbourhood of each bucket.
+    pub fn probe_radius(&self) -> u8 {
+        self.probe_radius
+    }
+
+    /// Probability that a key at `distance` bits from the query (out of
+    /// `key_bits`) becomes a candidate, assuming sampled positions are
+    /// independent: `1 - (1 - P[Binomial(rows, distance / key_bits) <= radius])^bands`.
+    pub fn collision_probability(&self, distance: u32, key_bits: u32) -> f64 {
+        let q = (f64::from(distance) / f64::from(key_bits.max(1))).min(1.0);
+        let rows = u32::from(self.rows);
+        let mut band = 0.0;
+        let mut choose = 1.0;
+        for k in 0..=u32::from(self.probe_radius) {
+            band += choose * powu(q, k) * powu(1.0 - q, rows - k);
+            choose = choose * f64::from(rows - k) / f64::from(k + 1);
+        }
+        1.0 - powu(1.0 - band.min(1.0), u32::from(self.bands))
+    }
+}
+
+/// Occupancy of an [`LshIndex`]'s tables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LshStats {
+    /// Stored entries.
+    pub entries: usize,
+    /// Non-empty buckets over all tables.
+    pub buckets: usize,
+    /// Entries in the fullest bucket; a large value means the sampled bits
+    /// are not discriminative for this data and queries degrade to scans.
+    pub largest_bucket: usize,
+    /// Average entries per non-empty bucket.
+    pub mean_bucket: f64,
+}
+
+/// An approximate near-neighbour index over fixed-length keys.
+///
+/// Queries only compare the query against stored keys sharing a (probed)
+/// bucket with it, and then filter those candidates by their exact
+/// distance, so every returned match is genuine but matches whose sampled
+/// bits happen to differ too much are missed.
+#[derive(Debug, Clone)]
+pub struct LshIndex<V> {
+    params: LshParams,
+    key_len: usize,                       // Bytes per key.
+    positions: Vec<u32>,                  // `rows` sampled bit positions per band, MSB-first.
+    tables: Vec<BTreeMap<u64, Vec<u32>>>, // Per band: bucket key -> entry indices.
+    keys: Vec<u8>,                        // Entry keys, back to back.
+    values: Vec<V>,                       // Entry values, in insertion order.
+}
+
+impl<V> LshIndex<V> {
+    /// Creates an empty index over keys of `key_len` bytes, sampling its bit
+    /// positions from `rng`.
+    pub fn new(
+        key_len: usize,
+        params: LshParams,
+        rng: &mut impl EntropySource,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            params,
+            key_len,
+            positions: sample_positions(key_len, params, rng)?,
+            tables: (0..params.bands).map(|_| BTreeMap::new()).collect(),
+            keys: Vec::new(),
+            values: Vec::new(),
+        })
+    }
+
+    /// The parameters the index was built with.
+    pub fn params(&self) -> LshParams {
+        self.params
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Stores `value` under `key`.
+    pub fn insert(&mut self, key: &[u8], value: V) -> Result<(), Error> {
+        self.check_len(key)?;
+        let index = self.values.len() as u32;
+        for band in 0..self.tables.len() {
+            let bucket = self.bucket(band, key);
+            self.tables[band].entry(bucket).or_default().push(index);
+        }
+        self.keys.extend_from_slice(key);
+        self.values.push(value);
+        Ok(())
+    }
+
+    /// Inserts every `(key, value)` of `entries` in order, first asking
+    /// `should_continue` each time, so a service can abandon a large build
+    /// once its request has timed out.
+    ///
+    /// Fails with [`Error::Cancelled`] as soon as `should_continue` returns
+    /// `false`, and like [`LshIndex::insert`] otherwise; the entries inserted
+    /// until then stay.
+    pub fn insert_all<K: AsRef<[u8]>>(
+        &mut self,
+        entries: impl IntoIterator<Item = (K, V)>,
+        mut should_continue: impl FnMut() -> bool,
+    ) -> Result<(), Error> {
+        for (key, value) in entries {
+            if !should_continue() {
+                return Err(Error::Cancelled);
+            }
+            self.insert(key.as_ref(), value)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts every entry of `other` after the entries of this index, in
+    /// the order they were inserted into `other`.
+    ///
+    /// Both indexes must sample the same bit positions, which they do when
+    /// they were created with the same key length and parameters from
+    /// identically seeded sources, as the shards of a map-reduce job should
+    /// be. Fails with [`Error::InvalidParameter`], before inserting
+    /// anything, otherwise.
+    pub fn merge(&mut self, other: LshIndex<V>) -> Result<(), Error> {
+        if other.key_len != self.key_len
+            || other.params != self.params
+            || other.positions != self.positions
+        {
+            return Err(Error::InvalidParameter(
+                "indexes sample different bit positions",
+            ));
+        }
+        for (i, value) in other.values.into_iter().enumerate() {
+            self.insert(&other.keys[i * self.key_len..(i + 1) * self.key_len], value)?;
+        }
+        Ok(())
+    }
+
+    /// A frozen copy of this index for concurrent queries; see
+    /// [`Snapshot`].
+    pub fn snapshot(&self) -> Snapshot<Self>
+    where
+        V: Clone,
+    {
+        Snapshot::new(self.clone())
+    }
+
+    /// Returns the stored entries sharing a probed bucket with `query` that
+    /// are at most `max_distance` bits away from it, closest first.
+    pub fn query_within(
+        &self,
+        query: &[u8],
+        max_distance: u32,
+    ) -> Result<Vec<Match<'_, V>>, Error> {
+        let mut matches = self.ranked(query)?;
+        matches.retain(|m| m.distance <= max_distance);
+        Ok(matches)
+    }
+
+    /// Returns the `k` stored entries closest to `query` among those
+    /// sharing a probed bucket with it, closest first; among equally
+    /// distant keys the earlier inserted ones win.
+    ///
+    /// Like [`query_within`](LshIndex::query_within) this may miss close
+    /// entries that share no bucket with the query, and returns fewer than
+    /// `k` entries when fewer candidates turn up.
+    pub fn query_knn(&self, query: &[u8], k: usize) -> Result<Vec<Match<'_, V>>, Error> {
+        let mut matches = self.ranked(query)?;
+        matches.truncate(k);
+        Ok(matches)
+    }
+
+    /// Every candidate for `query`, closest first, then in insertion order.
+    fn ranked(&self, query: &[u8]) -> Result<Vec<Match<'_, V>>, Error> {
+        let mut matches: Vec<Match<'_, V>> = self
+            .candidates(query)?
+            .into_iter()
+            .map(|index| {
+                let key = self.key(index);
+                Match {
+                    key,
+                    value: &self.values[index],
+                    distance: hamming(query, key),
+                }
+            })
+            .collect();
+        crate::metrics::candidates_examined("lsh", matches.len());
+        // Stable, and candidates come in insertion order.
+        matches.sort_by_key(|m| m.distance);
+        Ok(matches)
+    }
+
+    /// Indices (in insertion order) of the entries sharing a probed bucket
+    /// with `query`; their count is the work a query does.
+    pub fn candidates(&self, query: &[u8]) -> Result<Vec<usize>, Error> {
+        self.check_len(query)?;
+        let mut seen = alloc::vec![false; self.values.len()];
+        let mut out = Vec::new();
+        for band in 0..self.tables.len() {
+            let bucket = self.bucket(band, query);
+            probe(
+                bucket,
+                self.params.rows,
+                self.params.probe_radius,
+                &mut |probed| {
+                    for &index in self.tables[band].get(&probed).into_iter().flatten() {
+                        let index = index as usize;
+                        if !seen[index] {
+                            seen[index] = true;
+                            out.push(index);
+                        }
+                    }
+                },
+            );
+        }
+        out.sort_unstable();
+        Ok(out)
+    }
+
+    /// Bucket occupancy over all tables.
+    pub fn stats(&self) -> LshStats {
+        let buckets: usize = self.tables.iter().map(BTreeMap::len).sum();
+        let largest_bucket = self
+            .tables
+            .iter()
+            .flat_map(BTreeMap::values)
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0);
+        LshStats {
+            entries: self.values.len(),
+            buckets,
+            largest_bucket,
+            mean_bucket: if buckets == 0 {
+                0.0
+            } else {
+                (self.values.len() * self.tables.len()) as f64 / buckets as f64
+            },
+        }
+    }
+
+    /// The band's sampled bits of `key`, packed into a bucket key.
+    fn bucket(&self, band: usize, key: &[u8]) -> u64 {
+        let rows = usize::from(self.params.rows);
+        bucket_key(&self.positions[band * rows..(band + 1) * rows], key)
+    }
+
+    fn key(&self, index: usize) -> &[u8] {
+        &self.keys[index * self.key_len..(index + 1) * self.key_len]
+    }
+
+    fn check_len(&self, key: &[u8]) -> Result<(), Error> {
+        if key.len() != self.key_len {
+            return Err(Error::InvalidLength {
+                expected: self.key_len,
+                found: key.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl LshIndex<u64> {
+    /// Serializes as `key_len (u32) || bands (u16) || rows (u8) ||
+    /// probe_radius (u8)`, the sampled positions (`bands * rows` times u32),
+    /// `entry_count (u32)` and every entry in insertion order as
+    /// `key || value (u64)`. All integers are little-endian; the tables are
+    /// rebuilt on load.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(
+            12 + self.positions.len() * 4 + self.values.len() * (self.key_len + 8),
+        );
+        out.extend_from_slice(&(self.key_len as u32).to_le_bytes());
+        out.extend_from_slice(&self.params.bands.to_le_bytes());
+        out.push(self.params.rows);
+        out.push(self.params.probe_radius);
+        for position in &self.positions {
+            out.extend_from_slice(&position.to_le_bytes());
+        }
+        out.extend_from_slice(&(self.values.len() as u32).to_le_bytes());
+        for (index, value) in self.values.iter().enumerate() {
+            out.extend_from_slice(self.key(index));
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out
+    }
+
+    /// Parses the output of [`LshIndex::to_bytes`], rejecting invalid
+    /// parameters and sampled positions.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 8 {
+            return Err(Error::InvalidLength {
+                expected: 8,
+                found: bytes.len(),
+            });
+        }
+        let key_len = u32::from_le_bytes(bytes[..4].try_into().expect("4 bytes")) as usize;
+        let params = LshParams::new(
+            u16::from_le_bytes(bytes[4..6].try_into().expect("2 bytes")),
+            bytes[6],
+            bytes[7],
+        )?;
+        let rows = usize::from(params.rows);
+        let positions_end = 8 + usize::from(params.bands) * rows * 4;
+        if bytes.len() < positions_end + 4 {
+            return Err(Error::InvalidLength {
+                expected: positions_end + 4,
+                found: bytes.len(),
+            });
+        }
+        let count = u32::from_le_bytes(
+            bytes[positions_end..positions_end + 4]
+                .try_into()
+                .expect("4 bytes"),
+        ) as usize;
+        let expected = count
+            .checked_mul(key_len + 8)
+            .and_then(|body| body.checked_add(positions_end + 4))
+            .ok_or(Error::InvalidParameter("entry count overflows"))?;
+        if bytes.len() != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let positions: Vec<u32> = bytes[8..positions_end]
+            .chunks_exact(4)
+            .map(|p| u32::from_le_bytes(p.try_into().expect("4 bytes")))
+            .collect();
+        check_positions(&positions, key_len, params)?;
+
+        let mut index = Self {
+            params,
+            key_len,
+            positions,
+            tables: (0..params.bands).map(|_| BTreeMap::new()).collect(),
+            keys: Vec::with_capacity(count * key_len),
+            values: Vec::with_capacity(count),
+        };
+        for entry in bytes[positions_end + 4..].chunks_exact(key_len + 8) {
+            let (key, value) = entry.split_at(key_len);
+            index.insert(key, u64::from_le_bytes(value.try_into().expect("8 bytes")))?;
+        }
+        Ok(index)
+    }
+
+    /// Writes [`LshIndex::to_bytes`] to `writer` as a versioned, checksummed
+    /// [index file](crate::index#files).
+    #[cfg(feature = "std")]
+    pub fn save(&self, writer: impl std::io::Write) -> std::io::Result<()> {
+        file::write(Kind::Lsh, &self.to_bytes(), writer)
+    }
+
+    /// Reads an index written by [`LshIndex::save`]. Damaged, foreign or
+    /// too-new files fail with [`std::io::ErrorKind::InvalidData`].
+    #[cfg(feature = "std")]
+    pub fn load(reader: impl std::io::Read) -> std::io::Result<Self> {
+        file::read(Kind::Lsh, reader, Self::from_bytes)
+    }
+}
+
+/// Samples `rows` distinct bit positions of `key_len`-byte keys for each
+/// band (positions repeat across bands).
+pub(super) fn sample_positions(
+    key_len: usize,
+    params: LshParams,
+    rng: &mut impl EntropySource,
+) -> Result<Vec<u32>, Error> {
+    let key_bits = check_key_bits(key_len, params)?;
+    let mut positions = Vec::with_capacity(usize::from(params.bands) * usize::from(params.rows));
+    for _ in 0..params.bands {
+        let band_start = positions.len();
+        while positions.len() - band_start < usize::from(params.rows) {
+            let candidate = uniform_below(key_bits, rng);
+            if !positions[band_start..].contains(&candidate) {
+                positions.push(candidate);
+            }
+        }
+    }
+    Ok(positions)
+}
+
+/// Checks that `positions` could have come from [`sample_positions`].
+pub(super) fn check_positions(
+    positions: &[u32],
+    key_len: usize,
+    params: LshParams,
+) -> Result<(), Error> {
+    let key_bits = check_key_bits(key_len, params)?;
+    let rows = usize::from(params.rows);
+    if positions.len() != usize::from(params.bands) * rows {
+        return Err(Error::InvalidParameter(
+            "sampled positions do not match the parameters",
+        ));
+    }
+    for band in positions.chunks_exact(rows) {
+        for (i, &position) in band.iter().enumerate() {
+            if position >= key_bits || band[..i].contains(&position) {
+                return Err(Error::InvalidParameter("invalid sampled bit position"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Bits per key, which must cover the rows of a band and fit in a `u32`.
+fn check_key_bits(key_len: usize, params: LshParams) -> Result<u32, Error> {
+    let key_bits = key_len * 8;
+    if key_bits < usize::from(params.rows) || key_bits > u32::MAX as usize {
+        return Err(Error::InvalidParameter(
+            "keys must have at least `rows` bits",
+        ));
+    }
+    Ok(key_bits as u32)
+}
+
+/// The bits of `key` at a band's sampled `positions`, packed into a bucket
+/// key.
+pub(super) fn bucket_key(positions: &[u32], key: &[u8]) -> u64 {
+    positions.iter().fold(0u64, |acc, &position| {
+        let bit = key[position as usize / 8] >> (7 - position % 8) & 1;
+        acc << 1 | u64::from(bit)
+    })
+}
+
+/// Calls `visit` on `bucket` and every bucket key differing from it in at
+/// most `radius` of its low `rows` bits.
+pub(super) fn probe(bucket: u64, rows: u8, radius: u8, visit: &mut impl FnMut(u64)) {
+    fn flip(bucket: u64, from: u8, rows: u8, left: u8, visit: &mut impl FnMut(u64)) {
+        visit(bucket);
+        if left == 0 {
+            return;
+        }
+        for bit in from..rows {
+            flip(bucket ^ (1 << bit), bit + 1, rows, left - 1, visit);
+        }
+    }
+    flip(bucket, 0, rows, radius, visit);
+}
+
+/// `base^exponent` by repeated squaring (`f64::powi` needs `std`).
+fn powu(base: f64, mut exponent: u32) -> f64 {
+    let (mut result, mut square) = (1.0, base);
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result *= square;
+        }
+        square *= square;
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Uniform integer in `0..bound` by rejection sampling.
+fn uniform_below(bound: u32, rng: &mut impl EntropySource) -> u32 {
+    let zone = u32::MAX - u32::MAX % bound;
+    loop {
+        let mut bytes = [0u8; 4];
+        rng.fill_bytes(&mut bytes);
+        let value = u32::from_le_bytes(bytes);
+        if value < zone {
+            return value % bound;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    fn flip_bits(key: &[u8], count: usize, seed: u64) -> Vec<u8> {
+        let mut out = key.to_vec();
+        let mut rng = seeded(seed);
+        let mut flipped = 0;
+        while flipped < count {
+            let bit = uniform_below(out.len() as u32 * 8, &mut rng) as usize;
+            let before = hamming(&out, key);
+            out[bit / 8] ^= 0x80 >> (bit % 8);
+            if hamming(&out, key) > before {
+                flipped += 1;
+            } else {
+                out[bit / 8] ^= 0x80 >> (bit % 8);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_finds_near_duplicates_with_few_candidates() {
+        let params = LshParams::new(16, 16, 1).unwrap();
+        let mut index = LshIndex::new(32, params, &mut seeded(1)).unwrap();
+        let mut rng = seeded(2);
+        let mut keys = Vec::new();
+        for i in 0..2000u64 {
+            let mut key = vec![0u8; 32];
+            rng(&mut key);
+            index.insert(&key, i).unwrap();
+            keys.push(key);
+        }
+
+        let mut hits = 0;
+        for (i, key) in keys.iter().enumerate().take(50) {
+            let query = flip_bits(key, 8, 100 + i as u64);
+            let matches = index.query_within(&query, 8).unwrap();
+            hits += usize::from(matches.iter().any(|m| *m.value == i as u64));
+            if let Some(&nearest) = matches.first() {
+                assert_eq!(index.query_knn(&query, 1).unwrap(), [nearest]);
+            }
+            let ranked = index.query_knn(&query, usize::MAX).unwrap();
+            assert_eq!(ranked.len(), index.candidates(&query).unwrap().len());
+            assert!(ranked.windows(2).all(|w| w[0].distance <= w[1].distance));
+            // Random keys sit ~128 bits away and should almost never be candidates.
+            assert!(index.candidates(&query).unwrap().len() < 20);
+        }
+        // collision_probability(8 of 256 bits) is above 0.999 for these parameters.
+        assert!(hits >= 48, "only {hits} of 50 near duplicates found");
+        assert!(params.collision_probability(8, 256) > 0.999);
+    }
+
+    #[test]
+    fn test_probing_widens_candidates() {
+        let key = [0b1010_1010u8; 8];
+        let near = flip_bits(&key, 6, 7);
+        for radius in [0, 2] {
+            let params = LshParams::new(8, 32, radius).unwrap();
+            let mut index = LshIndex::new(8, params, &mut seeded(3)).unwrap();
+            index.insert(&key, ()).unwrap();
+            let found = index.candidates(&near).unwrap().len();
+            let expected = params.collision_probability(6, 64);
+            // Radius 2 makes a collision far more likely than plain banding.
+            if radius == 0 {
+                assert!(expected < 0.9);
+            } else {
+                assert!(expected > 0.9);
+                assert_eq!(found, 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_collision_probability_edges() {
+        let params = LshParams::new(8, 8, 0).unwrap();
+        assert_eq!(params.collision_probability(0, 64), 1.0);
+        assert_eq!(params.collision_probability(64, 64), 0.0);
+        let probed = LshParams::new(8, 8, 8).unwrap();
+        assert!((probed.collision_probability(64, 64) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_stats_and_validation() {
+        let params = LshParams::new(2, 4, 0).unwrap();
+        let mut index = LshIndex::new(1, params, &mut seeded(4)).unwrap();
+        for value in 0..10u8 {
+            index.insert(&[value], value).unwrap();
+        }
+        let stats = index.stats();
+        assert_eq!(stats.entries, 10);
+        assert!(stats.buckets <= 2 * 16);
+        assert!(stats.largest_bucket >= 1);
+        assert!((stats.mean_bucket * stats.buckets as f64 - 20.0).abs() < 1e-9);
+
+        assert!(index.insert(&[1, 2], 0).is_err());
+        assert!(LshParams::new(0, 4, 0).is_err());
+        assert!(LshParams::new(1, 65, 0).is_err());
+        assert!(LshParams::new(1, 4, 5).is_err());
+        assert!(LshIndex::<()>::new(1, LshParams::new(1, 16, 0).unwrap(), &mut seeded(5)).is_err());
+    }
+
+    #[test]
+    fn test_merge_needs_the_same_positions() {
+        let params = LshParams::new(4, 8, 1).unwrap();
+        let new_index = |seed| LshIndex::new(4, params, &mut seeded(seed)).unwrap();
+        let (mut whole, mut left, mut right) = (new_index(7), new_index(7), new_index(7));
+        let mut rng = seeded(8);
+        for value in 0..40u64 {
+            let mut key = [0u8; 4];
+            rng(&mut key);
+            whole.insert(&key, value).unwrap();
+            let part = if value % 2 == 0 {
+                &mut left
+            } else {
+                &mut right
+            };
+            part.insert(&key, value).unwrap();
+        }
+        let snapshot = whole.snapshot();
+        left.merge(right).unwrap();
+        assert_eq!(left.stats(), snapshot.stats());
+        let query = &whole.keys[..4];
+        let mut merged: Vec<u64> = left
+            .query_within(query, 8)
+            .unwrap()
+            .iter()
+            .map(|m| *m.value)
+            .collect();
+        let mut expected: Vec<u64> = snapshot
+            .query_within(query, 8)
+            .unwrap()
+            .iter()
+            .map(|m| *m.value)
+            .collect();
+        merged.sort();
+        expected.sort();
+        assert_eq!(merged, expected);
+
+        assert!(left.merge(new_index(9)).is_err());
+        assert_eq!(left.stats().entries, 40);
+    }
+
+    #[test]
+    fn test_serialization_roundtrip_and_validation() {
+        let params = LshParams::new(4, 8, 1).unwrap();
+        let mut index = LshIndex::new(4, params, &mut seeded(5)).unwrap();
+        let mut rng = seeded(6);
+        for value in 0..30u64 {
+            let mut key = [0u8; 4];
+            rng(&mut key);
+            index.insert(&key, value).unwrap();
+        }
+        let bytes = index.to_bytes();
+        let decoded = LshIndex::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+        assert_eq!(decoded.stats(), index.stats());
+        let query = &bytes[bytes.len() - 12..bytes.len() - 8];
+        assert_eq!(decoded.candidates(query), index.candidates(query));
+
+        // Sample the same bit twice in the first band.
+        let mut corrupt = bytes.clone();
+        corrupt.copy_within(8..12, 12);
+        assert!(LshIndex::from_bytes(&corrupt).is_err());
+        assert!(LshIndex::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_save_load_roundtrip() {
+        let params = LshParams::new(2, 8, 0).unwrap();
+        let mut index = LshIndex::new(2, params, &mut seeded(7)).unwrap();
+        index.insert(&[1, 2], 3).unwrap();
+        let mut file = Vec::new();
+        index.save(&mut file).unwrap();
+        assert_eq!(
+            LshIndex::load(file.as_slice()).unwrap().to_bytes(),
+            index.to_bytes()
+        );
+
+        let mut tree_file = Vec::new();
+        crate::index::BkTree::<u64>::new()
+            .save(&mut tree_file)
+            .unwrap();
+        assert!(LshIndex::load(tree_file.as_slice()).is_err());
+    }
+}
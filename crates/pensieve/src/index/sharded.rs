@@ -0,0 +1,260 @@
+//! A [`BkTree`] split into independently locked shards.
+
+use super::{BkTree, Snapshot};
+use crate::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+
+/// A near-duplicate index that many threads insert into and query at once.
+///
+/// Entries are spread over `shards` [`BkTree`]s, each behind its own
+/// read-write lock. An insertion write-locks a single shard, preferring
+/// one no other thread holds, so writers only wait for each other when
+/// there are more of them than shards. A query read-locks the shards one
+/// at a time and merges their answers, so it runs alongside other queries
+/// and waits at most for the insertion in progress on each shard. Every
+/// method takes `&self`; share the index through an `Arc` or a reference.
+///
+/// Results are owned `(value, distance)` pairs rather than [`Match`]es,
+/// which would borrow from a shard after its lock is released. An entry
+/// inserted while a query runs may or may not be in its answer.
+///
+/// [`Match`]: super::Match
+///
+/// # Examples
+/// ```rust
+/// use pensieve::index::ShardedBkTree;
+/// use std::thread;
+///
+/// let index = ShardedBkTree::new(4).unwrap();
+/// thread::scope(|scope| {
+///     for worker in 0..8u8 {
+///         let index = &index;
+///         scope.spawn(move || index.insert(&[worker, 0], worker).unwrap());
+///     }
+/// });
+/// assert_eq!(index.len(), 8);
+/// assert_eq!(index.query_within(&[3, 1], 1).unwrap(), [(3, 1)]);
+/// ```
+#[derive(Debug)]
+pub struct ShardedBkTree<V> {
+    shards: Vec<RwLock<BkTree<V>>>,
+    next: AtomicUsize,        // Shard the next insertion tries first.
+    key_len: OnceLock<usize>, // Fixed by the first insertion, like in `BkTree`.
+}
+
+impl<V> ShardedBkTree<V> {
+    /// Creates an empty index of `shards` trees; about as many shards as
+    /// writing threads keeps them from waiting for each other.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `shards` is zero.
+    pub fn new(shards: usize) -> Result<Self, Error> {
+        if shards == 0 {
+            return Err(Error::InvalidParameter("at least one shard is required"));
+        }
+        Ok(Self {
+            shards: (0..shards).map(|_| RwLock::new(BkTree::new())).collect(),
+            next: AtomicUsize::new(0),
+            key_len: OnceLock::new(),
+        })
+    }
+
+    /// Number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        (0..self.shards.len()).map(|i| self.read(i).len()).sum()
+    }
+
+    /// Whether nothing has been inserted yet.
+    pub fn is_empty(&self) -> bool {
+        self.key_len.get().is_none()
+    }
+
+    /// Length of every key in bytes, once the first entry is inserted.
+    pub fn key_len(&self) -> Option<usize> {
+        self.key_len.get().copied()
+    }
+
+    /// Stores `value` under `key`.
+    ///
+    /// Fails with [`Error::InvalidLength`] if `key` is not as long as the
+    /// keys already stored.
+    pub fn insert(&self, key: &[u8], value: V) -> Result<(), Error> {
+        self.check_len(*self.key_len.get_or_init(|| key.len()), key)?;
+        let first = self.next.fetch_add(1, Ordering::Relaxed);
+        let shards = self.shards.len();
+        let mut shard = (0..shards)
+            .find_map(|i| match self.shards[(first + i) % shards].try_write() {
+                Ok(shard) => Some(shard),
+                Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            })
+            .unwrap_or_else(|| self.write(first % shards));
+        shard.insert(key, value)
+    }
+
+    /// A frozen copy of all shards merged into one tree; see [`Snapshot`].
+    ///
+    /// Shards are copied one at a time, so insertions made meanwhile may or
+    /// may not be in the snapshot.
+    pub fn snapshot(&self) -> Snapshot<BkTree<V>>
+    where
+        V: Clone,
+    {
+        let mut tree = BkTree::new();
+        for i in 0..self.shards.len() {
+            let shard = self.read(i).clone();
+            tree.merge(shard).expect("all shards share the key length");
+        }
+        Snapshot::new(tree)
+    }
+
+    fn read(&self, shard: usize) -> RwLockReadGuard<'_, BkTree<V>> {
+        // A panicking thread cannot leave a tree half-updated.
+        self.shards[shard].read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self, shard: usize) -> RwLockWriteGuard<'_, BkTree<V>> {
+        self.shards[shard]
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn check_len(&self, expected: usize, key: &[u8]) -> Result<(), Error> {
+        if key.len() != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                found: key.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<V: Clone> ShardedBkTree<V> {
+    /// The `(value, distance)` of every entry whose key is at most
+    /// `max_distance` bits away from `query`, closest first; see
+    /// [`BkTree::query_within`].
+    pub fn query_within(&self, query: &[u8], max_distance: u32) -> Result<Vec<(V, u32)>, Error> {
+        self.merge(query, usize::MAX, |tree| {
+            tree.query_within(query, max_distance)
+        })
+    }
+
+    /// The `(value, distance)` of the `k` entries whose keys are closest to
+    /// `query`, closest first; see [`BkTree::query_knn`]. Ties between
+    /// shards are broken by shard order.
+    pub fn query_knn(&self, query: &[u8], k: usize) -> Result<Vec<(V, u32)>, Error> {
+        self.merge(query, k, |tree| tree.query_knn(query, k))
+    }
+
+    /// Runs `query` on every shard and keeps the `k` closest answers.
+    fn merge(
+        &self,
+        key: &[u8],
+        k: usize,
+        query: impl Fn(&BkTree<V>) -> Result<Vec<super::Match<'_, V>>, Error>,
+    ) -> Result<Vec<(V, u32)>, Error> {
+        let Some(&key_len) = self.key_len.get() else {
+            return Ok(Vec::new());
+        };
+        self.check_len(key_len, key)?;
+        let mut matches = Vec::new();
+        for i in 0..self.shards.len() {
+            let shard = self.read(i);
+            // Shards that have not received an entry yet have no key length.
+            if !shard.is_empty() {
+                let found = query(&shard)?;
+                matches.extend(found.into_iter().map(|m| (m.value.clone(), m.distance)));
+            }
+        }
+        matches.sort_by_key(|&(_, distance)| distance);
+        matches.truncate(k);
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::hamming;
+    use crate::entropy::tests::seeded;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_concurrent_inserts_and_queries_match_one_tree() {
+        let mut rng = seeded(1);
+        let keys: Vec<[u8; 4]> = (0..800)
+            .map(|_| {
+                let mut key = [0u8; 4];
+                rng(&mut key);
+                key
+            })
+            .collect();
+        let keys = Arc::new(keys);
+        let index = Arc::new(ShardedBkTree::new(4).unwrap());
+        let workers: Vec<_> = (0..8)
+            .map(|worker| {
+                let (index, keys) = (Arc::clone(&index), Arc::clone(&keys));
+                thread::spawn(move || {
+                    for i in (worker..keys.len()).step_by(8) {
+                        index.insert(&keys[i], i).unwrap();
+                        // Queries run between insertions of other workers.
+                        assert!(!index.query_within(&keys[i], 0).unwrap().is_empty());
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().for_each(|w| w.join().unwrap());
+
+        assert_eq!(index.len(), keys.len());
+        for query in keys.iter().take(10) {
+            let mut found = index.query_within(query, 6).unwrap();
+            found.sort();
+            let expected: Vec<(usize, u32)> = keys
+                .iter()
+                .enumerate()
+                .map(|(i, key)| (i, hamming(key, query)))
+                .filter(|&(_, distance)| distance <= 6)
+                .collect();
+            assert_eq!(found, expected);
+
+            let nearest = index.query_knn(query, 5).unwrap();
+            let mut distances: Vec<u32> = keys.iter().map(|key| hamming(key, query)).collect();
+            distances.sort();
+            assert_eq!(
+                nearest.iter().map(|&(_, d)| d).collect::<Vec<_>>(),
+                distances[..5]
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_length_and_shard_count_enforced() {
+        assert!(ShardedBkTree::<u8>::new(0).is_err());
+        let index = ShardedBkTree::new(3).unwrap();
+        assert_eq!(index.shard_count(), 3);
+        assert!(index.is_empty());
+        assert_eq!(index.query_knn(&[1, 2], 3), Ok(Vec::new()));
+        index.insert(&[1, 2], 'a').unwrap();
+        assert_eq!(index.key_len(), Some(2));
+        assert_eq!(
+            index.insert(&[1], 'b'),
+            Err(Error::InvalidLength {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert!(index.query_within(&[1, 2, 3], 0).is_err());
+        assert_eq!(index.query_knn(&[1, 3], 3), Ok(vec![('a', 1)]));
+        index.insert(&[1, 0], 'c').unwrap();
+        let snapshot = index.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot.key_len(), Some(2));
+    }
+}
@@ -0,0 +1,34 @@
+//! Immutable, shareable views of an index.
+
+use alloc::sync::Arc;
+use core::ops::Deref;
+
+/// A frozen copy of an index that any number of readers can query.
+///
+/// Taking a snapshot copies the index once; cloning the snapshot afterwards
+/// only bumps a reference count, so every query thread can hold its own
+/// clone while the original index keeps changing. The snapshot derefs to
+/// the index it froze, exposing its read-only methods only.
+#[derive(Debug)]
+pub struct Snapshot<T>(Arc<T>);
+
+impl<T> Snapshot<T> {
+    /// Freezes `index` without copying it.
+    pub fn new(index: T) -> Self {
+        Self(Arc::new(index))
+    }
+}
+
+impl<T> Clone for Snapshot<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> Deref for Snapshot<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
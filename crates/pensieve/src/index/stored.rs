@@ -0,0 +1,237 @@
+//! Bit-sampling LSH over a pluggable [`FingerprintStore`].
+
+use super::LshParams;
+use super::lsh::{bucket_key, check_positions, probe, sample_positions};
+use crate::distance::hamming;
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use crate::store::FingerprintStore;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+/// An [`LshIndex`](super::LshIndex) whose entries live in a
+/// [`FingerprintStore`].
+///
+/// Entries are identified by caller-chosen ids instead of values, and each
+/// one is filed under its bucket in every band, so a query only reads the
+/// entries sharing a probed bucket with it. The sampled bit positions are
+/// not kept in the store: save [`StoredIndex::positions`] next to it and
+/// reopen with [`StoredIndex::open`].
+#[derive(Debug, Clone)]
+pub struct StoredIndex<S> {
+    params: LshParams,
+    key_len: usize,      // Bytes per key.
+    positions: Vec<u32>, // `rows` sampled bit positions per band, MSB-first.
+    store: S,
+}
+
+impl<S: FingerprintStore> StoredIndex<S> {
+    /// Creates an index over keys of `key_len` bytes in `store`, which
+    /// should be empty, sampling its bit positions from `rng`.
+    pub fn new(
+        key_len: usize,
+        params: LshParams,
+        rng: &mut impl EntropySource,
+        store: S,
+    ) -> Result<Self, Error> {
+        let positions = sample_positions(key_len, params, rng)?;
+        Ok(Self {
+            params,
+            key_len,
+            positions,
+            store,
+        })
+    }
+
+    /// Reopens an index over `store` with the `positions` of the index that
+    /// filled it.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if the positions do not fit
+    /// `key_len` and `params`.
+    pub fn open(
+        key_len: usize,
+        params: LshParams,
+        positions: Vec<u32>,
+        store: S,
+    ) -> Result<Self, Error> {
+        check_positions(&positions, key_len, params)?;
+        Ok(Self {
+            params,
+            key_len,
+            positions,
+            store,
+        })
+    }
+
+    /// The parameters the index was built with.
+    pub fn params(&self) -> LshParams {
+        self.params
+    }
+
+    /// The sampled bit positions, `rows` per band.
+    pub fn positions(&self) -> &[u32] {
+        &self.positions
+    }
+
+    /// The backing store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Gives the backing store back.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Stores `key` under `id`, replacing any previous key with that id.
+    pub fn insert(&mut self, id: u64, key: &[u8]) -> Result<(), S::Error> {
+        let buckets = self.buckets(key)?;
+        self.store.put(id, key, &buckets)
+    }
+
+    /// Removes the key stored under `id`; returns whether there was one.
+    pub fn remove(&mut self, id: u64) -> Result<bool, S::Error> {
+        self.store.delete(id)
+    }
+
+    /// The `(id, distance)` of every stored key sharing a probed bucket with
+    /// `query` and at most `max_distance` bits away from it, closest first.
+    pub fn query_within(
+        &self,
+        query: &[u8],
+        max_distance: u32,
+    ) -> Result<Vec<(u64, u32)>, S::Error> {
+        let mut matches = self.ranked(query)?;
+        matches.retain(|&(_, distance)| distance <= max_distance);
+        Ok(matches)
+    }
+
+    /// The `(id, distance)` of the `k` stored keys closest to `query` among
+    /// those sharing a probed bucket with it, closest first; among equally
+    /// distant keys the smaller ids win. See [`LshIndex::query_knn`].
+    ///
+    /// [`LshIndex::query_knn`]: super::LshIndex::query_knn
+    pub fn query_knn(&self, query: &[u8], k: usize) -> Result<Vec<(u64, u32)>, S::Error> {
+        let mut matches = self.ranked(query)?;
+        matches.truncate(k);
+        Ok(matches)
+    }
+
+    /// The `(id, distance)` of every candidate for `query`, closest first,
+    /// then by id.
+    fn ranked(&self, query: &[u8]) -> Result<Vec<(u64, u32)>, S::Error> {
+        let buckets = self.buckets(query)?;
+        let mut candidates = BTreeSet::new();
+        for (band, &bucket) in buckets.iter().enumerate() {
+            let mut result = Ok(());
+            probe(
+                bucket,
+                self.params.rows(),
+                self.params.probe_radius(),
+                &mut |probed| {
+                    if result.is_ok() {
+                        result = self
+                            .store
+                            .scan_band(band, probed)
+                            .map(|ids| candidates.extend(ids));
+                    }
+                },
+            );
+            result?;
+        }
+        crate::metrics::candidates_examined("stored_lsh", candidates.len());
+        let mut matches = Vec::new();
+        for id in candidates {
+            // Entries may have been replaced or deleted behind the index's back.
+            let Some(key) = self.store.get(id)? else {
+                continue;
+            };
+            if key.len() == self.key_len {
+                matches.push((id, hamming(query, &key)));
+            }
+        }
+        // Stable, and candidates come in id order.
+        matches.sort_by_key(|&(_, distance)| distance);
+        Ok(matches)
+    }
+
+    /// The bucket of `key` in every band.
+    fn buckets(&self, key: &[u8]) -> Result<Vec<u64>, Error> {
+        if key.len() != self.key_len {
+            return Err(Error::InvalidLength {
+                expected: self.key_len,
+                found: key.len(),
+            });
+        }
+        Ok(self
+            .positions
+            .chunks_exact(usize::from(self.params.rows()))
+            .map(|band| bucket_key(band, key))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use crate::index::LshIndex;
+    use crate::store::MemoryStore;
+
+    #[test]
+    fn test_matches_in_memory_index() {
+        let params = LshParams::new(6, 12, 1).unwrap();
+        let mut memory = LshIndex::new(8, params, &mut seeded(1)).unwrap();
+        let mut stored = StoredIndex::new(8, params, &mut seeded(1), MemoryStore::new()).unwrap();
+        let mut rng = seeded(2);
+        let mut keys = Vec::new();
+        for id in 0..300u64 {
+            let mut key = [0u8; 8];
+            rng(&mut key);
+            memory.insert(&key, id).unwrap();
+            stored.insert(id, &key).unwrap();
+            keys.push(key);
+        }
+        for key in keys.iter().take(20) {
+            let mut query = *key;
+            query[3] ^= 0b0000_0101;
+            let expected: Vec<(u64, u32)> = memory
+                .query_within(&query, 10)
+                .unwrap()
+                .iter()
+                .map(|m| (*m.value, m.distance))
+                .collect();
+            assert_eq!(stored.query_within(&query, 10).unwrap(), expected);
+            let nearest: Vec<(u64, u32)> = memory
+                .query_knn(&query, 3)
+                .unwrap()
+                .iter()
+                .map(|m| (*m.value, m.distance))
+                .collect();
+            assert_eq!(stored.query_knn(&query, 3).unwrap(), nearest);
+        }
+    }
+
+    #[test]
+    fn test_remove_and_reopen() {
+        let params = LshParams::new(4, 8, 0).unwrap();
+        let mut index = StoredIndex::new(2, params, &mut seeded(3), MemoryStore::new()).unwrap();
+        index.insert(7, &[0xAB, 0xCD]).unwrap();
+        index.insert(8, &[0x12, 0x34]).unwrap();
+        assert_eq!(index.remove(8), Ok(true));
+        assert_eq!(index.query_within(&[0x12, 0x34], 0), Ok(Vec::new()));
+
+        let positions = index.positions().to_vec();
+        let reopened = StoredIndex::open(2, params, positions.clone(), index.into_store()).unwrap();
+        assert_eq!(
+            reopened.query_within(&[0xAB, 0xCD], 0),
+            Ok(alloc::vec![(7, 0)])
+        );
+        assert!(reopened.query_within(&[0xAB], 0).is_err());
+
+        assert!(StoredIndex::open(2, params, positions[1..].to_vec(), MemoryStore::new()).is_err());
+        let mut repeated = positions;
+        repeated[1] = repeated[0];
+        assert!(StoredIndex::open(2, params, repeated, MemoryStore::new()).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Lazy collapsing of iterators.
+//!
+//! [`CollapseIteratorExt`] adds [`collapsed`](CollapseIteratorExt::collapsed)
+//! and [`collapsed_with_keys`](CollapseIteratorExt::collapsed_with_keys) to
+//! every iterator over byte buffers, so records can be collapsed inside an
+//! iterator chain without collecting them into an intermediate vector.
+
+use crate::TbfConfig;
+use alloc::vec::Vec;
+
+/// Collapsing adaptors for iterators whose items are byte buffers.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::{CollapseIteratorExt, TbfConfig};
+///
+/// let config = TbfConfig::new(0.125);
+/// let records = [vec![0xFFu8; 16], vec![0x00; 16], vec![0xFE; 16]];
+/// let digests: Vec<Vec<u8>> = records.iter().collapsed(&config).collect();
+/// assert_eq!(digests[0], digests[2]);
+///
+/// let (first, digest) = records.iter().collapsed_with_keys(&config).next().unwrap();
+/// assert_eq!((first, &digest), (&records[0], &digests[0]));
+/// ```
+pub trait CollapseIteratorExt: Iterator + Sized
+where
+    Self::Item: AsRef<[u8]>,
+{
+    /// Lazily collapses every item with `config`.
+    fn collapsed(self, config: &TbfConfig) -> Collapsed<'_, Self> {
+        Collapsed {
+            items: self,
+            config,
+        }
+    }
+
+    /// Lazily collapses every item with `config`, yielding it together with
+    /// its digest.
+    fn collapsed_with_keys(self, config: &TbfConfig) -> CollapsedWithKeys<'_, Self> {
+        CollapsedWithKeys {
+            items: self,
+            config,
+        }
+    }
+}
+
+impl<I> CollapseIteratorExt for I
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+}
+
+/// Iterator returned by [`CollapseIteratorExt::collapsed`].
+#[derive(Debug, Clone)]
+pub struct Collapsed<'a, I> {
+    items: I,
+    config: &'a TbfConfig,
+}
+
+impl<I> Iterator for Collapsed<'_, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let item = self.items.next()?;
+        Some(self.config.collapse(item.as_ref()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.items.size_hint()
+    }
+}
+
+/// Iterator returned by [`CollapseIteratorExt::collapsed_with_keys`].
+#[derive(Debug, Clone)]
+pub struct CollapsedWithKeys<'a, I> {
+    items: I,
+    config: &'a TbfConfig,
+}
+
+impl<I> Iterator for CollapsedWithKeys<'_, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = (I::Item, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.items.next()?;
+        let digest = self.config.collapse(item.as_ref());
+        Some((item, digest))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.items.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptors_are_lazy_and_match_collapse() {
+        let config = TbfConfig::new(0.125);
+        let mut collapsed = 0;
+        let records = (0..=255u8).map(|byte| {
+            collapsed += 1;
+            [byte; 8]
+        });
+        let mut digests = records.collapsed(&config).skip(3);
+        assert_eq!(digests.size_hint(), (253, Some(253)));
+        assert_eq!(digests.next(), Some(config.collapse(&[3; 8])));
+        drop(digests);
+        assert_eq!(collapsed, 4);
+
+        let owned = alloc::vec![alloc::vec![1u8, 2, 3], Vec::new()];
+        let pairs: Vec<(Vec<u8>, Vec<u8>)> = owned
+            .clone()
+            .into_iter()
+            .collapsed_with_keys(&config)
+            .collect();
+        assert_eq!(pairs[0], (owned[0].clone(), config.collapse(&owned[0])));
+        assert_eq!(pairs[1].1, config.collapse(&[]));
+    }
+}
@@ -0,0 +1,218 @@
+//! Similarity joins between two datasets.
+//!
+//! Record linkage asks which records of one dataset are near which records
+//! of another. [`similarity_join`] indexes the chunk levels of every left
+//! record in a [`BkTree`] per record length, then streams the right
+//! records past it one at a time, yielding every pair whose collapsed
+//! values differ in at most `radius` chunks. Only the left fingerprints are
+//! held in memory, a few bytes per record whatever its length, so the right
+//! side can be a file or a table cursor of any size; index the smaller
+//! dataset on the left.
+//!
+//! Like [`cluster`](crate::cluster), distances count the chunks whose
+//! collapsed level differs, so radius 0 joins exactly the records with
+//! identical collapsed outputs, and records of different lengths never
+//! match.
+
+use crate::TbfConfig;
+use crate::index::BkTree;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+
+/// A pair of records found by [`similarity_join`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JoinMatch {
+    /// Index of the record in the left dataset.
+    pub left: usize,
+    /// Index of the record in the right dataset.
+    pub right: usize,
+    /// Number of chunks whose collapsed levels differ.
+    pub distance: u32,
+    /// Fraction of chunks whose collapsed levels agree, 1.0 for identical
+    /// collapsed values. Unlike [`similarity`](crate::similarity), which
+    /// needs both records, disagreeing chunks earn no partial credit.
+    pub score: f32,
+}
+
+/// Joins `left` and `right` under `config`, pairing records whose
+/// collapsed values differ in at most `radius` chunks.
+///
+/// `left` is indexed up front; `right` is consumed lazily as the returned
+/// iterator advances. Matches come in order of their right record, and
+/// those of one right record closest first, then by left index.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::join::similarity_join;
+///
+/// let customers = [[0xFFu8; 16], [0x00; 16]];
+/// let mut typo = [0xFFu8; 16];
+/// typo[4] ^= 0b0001_0000;
+/// let mut faint = [0x00u8; 16];
+/// faint[3] = 0x01;
+/// let orders = [faint, typo];
+///
+/// let matches: Vec<_> = similarity_join(&customers, &orders, &TbfConfig::new(0.125), 0)
+///     .map(|m| (m.left, m.right, m.score))
+///     .collect();
+/// assert_eq!(matches, [(1, 0, 1.0), (0, 1, 1.0)]);
+/// ```
+pub fn similarity_join<L, R>(
+    left: L,
+    right: R,
+    config: &TbfConfig,
+    radius: u32,
+) -> SimilarityJoin<R::IntoIter>
+where
+    L: IntoIterator,
+    L::Item: AsRef<[u8]>,
+    R: IntoIterator,
+    R::Item: AsRef<[u8]>,
+{
+    let mut by_len: BTreeMap<usize, BkTree<u64>> = BTreeMap::new();
+    for (index, record) in left.into_iter().enumerate() {
+        let record = record.as_ref();
+        by_len
+            .entry(record.len())
+            .or_default()
+            .insert(&config.collapse_packed(record), index as u64)
+            .expect("keys of one record length have one length");
+    }
+    SimilarityJoin {
+        config: *config,
+        radius,
+        by_len,
+        right: right.into_iter(),
+        next_right: 0,
+        pending: VecDeque::new(),
+    }
+}
+
+/// The iterator of [`JoinMatch`]es returned by [`similarity_join`].
+#[derive(Debug, Clone)]
+pub struct SimilarityJoin<R> {
+    config: TbfConfig,
+    radius: u32,
+    by_len: BTreeMap<usize, BkTree<u64>>, // Per record length: packed levels -> left index.
+    right: R,
+    next_right: usize,            // Index of the next right record.
+    pending: VecDeque<JoinMatch>, // Matches of the last right record not yet yielded.
+}
+
+impl<R> SimilarityJoin<R> {
+    /// Number of left records indexed.
+    pub fn left_len(&self) -> usize {
+        self.by_len.values().map(BkTree::len).sum()
+    }
+}
+
+impl<R> Iterator for SimilarityJoin<R>
+where
+    R: Iterator,
+    R::Item: AsRef<[u8]>,
+{
+    type Item = JoinMatch;
+
+    fn next(&mut self) -> Option<JoinMatch> {
+        while self.pending.is_empty() {
+            let record = self.right.next()?;
+            let record = record.as_ref();
+            let right = self.next_right;
+            self.next_right += 1;
+            let Some(tree) = self.by_len.get(&record.len()) else {
+                continue;
+            };
+            let chunks = self.config.chunk_count(record.len());
+            let mut matches: Vec<JoinMatch> = tree
+                .query_within(&self.config.collapse_packed(record), self.radius)
+                .expect("keys of one record length have one length")
+                .into_iter()
+                .map(|m| JoinMatch {
+                    left: *m.value as usize,
+                    right,
+                    distance: m.distance,
+                    score: if chunks == 0 {
+                        1.0
+                    } else {
+                        1.0 - m.distance as f32 / chunks as f32
+                    },
+                })
+                .collect();
+            matches.sort_by_key(|m| (m.distance, m.left));
+            self.pending.extend(matches);
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 16-byte record whose eight 16-bit chunks collapse to `levels`.
+    fn record(levels: u8) -> [u8; 16] {
+        let mut out = [0u8; 16];
+        for chunk in 0..8 {
+            if levels >> (7 - chunk) & 1 == 1 {
+                out[2 * chunk..2 * chunk + 2].fill(0xFF);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_join_matches_a_nested_loop() {
+        let config = TbfConfig::default();
+        let left: Vec<[u8; 16]> = [0b1010_0000, 0b0000_0001, 0b1010_0000, 0b1111_0000]
+            .map(record)
+            .to_vec();
+        let right: Vec<[u8; 16]> = [0b1010_0001, 0b0000_0000, 0b0101_0101].map(record).to_vec();
+        for radius in 0..=8 {
+            let joined: Vec<JoinMatch> = similarity_join(&left, &right, &config, radius).collect();
+            let mut expected = Vec::new();
+            for (r, b) in right.iter().enumerate() {
+                let mut row = Vec::new();
+                for (l, a) in left.iter().enumerate() {
+                    let distance = crate::distance::hamming(
+                        &config.collapse_packed(a),
+                        &config.collapse_packed(b),
+                    );
+                    if distance <= radius {
+                        row.push((distance, l, r));
+                    }
+                }
+                row.sort();
+                expected.extend(row);
+            }
+            let found: Vec<(u32, usize, usize)> = joined
+                .iter()
+                .map(|m| (m.distance, m.left, m.right))
+                .collect();
+            assert_eq!(found, expected, "radius {radius}");
+            for m in &joined {
+                assert_eq!(m.score, 1.0 - m.distance as f32 / 8.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_right_side_is_streamed_and_lengths_never_mix() {
+        let config = TbfConfig::default();
+        let left: [&[u8]; 3] = [&[0xFF; 16], &[0xFF; 32], &[]];
+        let right: [&[u8]; 3] = [&[0xFF; 32], &[0xFF; 24], &[]];
+        let consumed = core::cell::Cell::new(0);
+        let mut join = similarity_join(
+            left,
+            right.iter().inspect(|_| consumed.set(consumed.get() + 1)),
+            &config,
+            1,
+        );
+        assert_eq!(join.left_len(), 3);
+        let first = join.next().unwrap();
+        assert_eq!((first.left, first.right, first.score), (1, 0, 1.0));
+        assert_eq!(consumed.get(), 1);
+        let rest: Vec<(usize, usize)> = join.map(|m| (m.left, m.right)).collect();
+        assert_eq!(rest, [(2, 2)]);
+    }
+}
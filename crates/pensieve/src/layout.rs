@@ -0,0 +1,293 @@
+//! Chunk assignments learned from labeled pairs.
+//!
+//! The collapse splits the input into contiguous chunks. That suits inputs
+//! whose noise is spread evenly, but sources often have a structure the
+//! contiguous split ignores: a sensor whose noisy pixels cluster in one
+//! corner, a record whose stable fields sit at its end. A [`LearnedLayout`]
+//! assigns every input bit to a chunk of its choosing instead, keeping the
+//! number and the sizes of the chunks, and therefore the thresholds, of the
+//! contiguous layout. [`calibrate_layout`](crate::analysis::calibrate_layout)
+//! searches for the assignment that best separates genuine from impostor
+//! pairs.
+//!
+//! A layout is tied to one input length. [`LearnedLayout::to_bytes`] writes
+//! it with the configuration it applies to:
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 4 | magic `PNSL` |
+//! | 1 | format version (currently 1) |
+//! | 1 | algorithm version ([`TbfConfig::ALGORITHM_VERSION`]) |
+//! | 1 | bit order ([`BitOrder::id`](crate::BitOrder::id)) |
+//! | 1 | output transform ([`FinalTransform::id`](crate::FinalTransform::id)) |
+//! | 1 | mask base of [`FinalTransform::XorMask`](crate::FinalTransform::XorMask), otherwise 0 |
+//! | 1 | thresholding ([`Thresholding::id`](crate::Thresholding::id)) |
+//! | 4 | tolerance, f32 little-endian |
+//! | 8 | input length in bytes, little-endian |
+//! | 1 per input bit | chunk of the bit, in the configured bit order |
+//! | 4 | first 4 bytes of SHA-256 over everything above |
+//!
+//! The key of [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) is
+//! never stored; such layouts are read with
+//! [`LearnedLayout::from_bytes_keyed`].
+
+use crate::header::{self, CHECKSUM_LEN, Format};
+use crate::{ChunkLayout, Error, TbfConfig};
+use alloc::vec::Vec;
+
+/// Header of every serialized layout.
+const FORMAT: Format = Format {
+    magic: *b"PNSL",
+    version: 1,
+    foreign: "not a pensieve learned layout",
+    unsupported: "unsupported learned layout format version",
+    needs_key: "keyed transform needs from_bytes_keyed",
+};
+/// Bytes before the assignment.
+const HEADER_LEN: usize = 22;
+
+/// An assignment of every bit of inputs of one length to a chunk.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::layout::LearnedLayout;
+///
+/// let config = TbfConfig::default();
+/// let layout = LearnedLayout::contiguous(&config, 16).unwrap();
+/// let input = [0x5Eu8; 16];
+/// assert_eq!(layout.collapse(&input), Ok(config.collapse(&input)));
+/// assert_eq!(LearnedLayout::from_bytes(&layout.to_bytes()), Ok(layout));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LearnedLayout {
+    config: TbfConfig,
+    input_len: usize,
+    chunks: Vec<u8>, // Chunk of every input bit, in the configured bit order.
+}
+
+impl LearnedLayout {
+    /// The layout [`TbfConfig::collapse`] uses for inputs of `input_len`
+    /// bytes: every chunk a run of adjacent bits.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `input_len` is zero.
+    pub fn contiguous(config: &TbfConfig, input_len: usize) -> Result<Self, Error> {
+        let layout = ChunkLayout::for_config(input_len * 8, config)
+            .ok_or(Error::InvalidParameter("input must not be empty"))?;
+        let mut chunks = Vec::with_capacity(input_len * 8);
+        for (chunk, (start, end)) in layout.ranges().enumerate() {
+            chunks.resize(chunks.len() + (end - start), chunk as u8);
+        }
+        Ok(Self {
+            config: *config,
+            input_len,
+            chunks,
+        })
+    }
+
+    /// The configuration the layout collapses under.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// Length of the inputs the layout applies to.
+    pub fn input_len(&self) -> usize {
+        self.input_len
+    }
+
+    /// The chunk of every input bit, in the configured bit order.
+    pub fn chunks(&self) -> &[u8] {
+        &self.chunks
+    }
+
+    /// Exchanges the chunks of input bits `a` and `b`, keeping the sizes of
+    /// the chunks.
+    ///
+    /// # Panics
+    /// If `a` or `b` is not below eight times the input length.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.chunks.swap(a, b);
+    }
+
+    /// Collapses `input` like [`TbfConfig::collapse`], counting every bit
+    /// towards its assigned chunk; in constant time in the input.
+    ///
+    /// Fails with [`Error::InvalidLength`] unless `input` has the length of
+    /// the layout.
+    pub fn collapse(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let levels = self.levels(input)?;
+        Ok(self.config.transform().apply(&levels, input.len()))
+    }
+
+    /// The 0/1 chunk levels of `input`, before the output transform.
+    pub(crate) fn levels(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let sums = self.sums(input)?;
+        Ok(self.chunk_layout().levels(&sums))
+    }
+
+    /// The set bits of every chunk of `input`.
+    pub(crate) fn sums(&self, input: &[u8]) -> Result<Vec<u32>, Error> {
+        if input.len() != self.input_len {
+            return Err(Error::InvalidLength {
+                expected: self.input_len,
+                found: input.len(),
+            });
+        }
+        let bits = self.config.msb_first(input);
+        let mut sums = alloc::vec![0u32; self.chunk_layout().chunk_count()];
+        // The assignment is public; only the bits are secret.
+        for (bit, &chunk) in self.chunks.iter().enumerate() {
+            sums[usize::from(chunk)] += u32::from(bits[bit / 8] >> (7 - bit % 8) & 1);
+        }
+        Ok(sums)
+    }
+
+    /// The contiguous layout, whose chunk sizes and thresholds this one
+    /// keeps.
+    pub(crate) fn chunk_layout(&self) -> ChunkLayout {
+        ChunkLayout::for_config(self.input_len * 8, &self.config).expect("non-empty input")
+    }
+
+    /// Serializes in the format described in the [module documentation](self).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = FORMAT.write(&self.config, HEADER_LEN + self.chunks.len() + CHECKSUM_LEN);
+        out.extend_from_slice(&(self.input_len as u64).to_le_bytes());
+        out.extend_from_slice(&self.chunks);
+        header::seal(&mut out);
+        out
+    }
+
+    /// Parses the output of [`LearnedLayout::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidLength`] if `bytes` is truncated or assigns
+    /// the wrong number of bits, with [`Error::InvalidChecksum`] on corruption
+    /// and with [`Error::InvalidParameter`] for layouts this build cannot
+    /// interpret, including assignments that change the chunk sizes. Layouts
+    /// under [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) need
+    /// [`LearnedLayout::from_bytes_keyed`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, None)
+    }
+
+    /// [`LearnedLayout::from_bytes`] for layouts that may be under
+    /// [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) with
+    /// `key`.
+    pub fn from_bytes_keyed(bytes: &[u8], key: &[u8; 32]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, Some(key))
+    }
+
+    fn from_bytes_with(bytes: &[u8], key: Option<&[u8; 32]>) -> Result<Self, Error> {
+        let (config, body) = FORMAT.open(bytes, HEADER_LEN, key)?;
+        let input_len = header::read_input_len(bytes)?;
+
+        let chunks = &body[HEADER_LEN..];
+        if input_len.checked_mul(8) != Some(chunks.len()) {
+            return Err(Error::InvalidLength {
+                expected: HEADER_LEN + input_len.saturating_mul(8) + CHECKSUM_LEN,
+                found: bytes.len(),
+            });
+        }
+        let layout = ChunkLayout::for_config(chunks.len(), &config)
+            .ok_or(Error::InvalidParameter("input must not be empty"))?;
+        let mut sizes = alloc::vec![0usize; layout.chunk_count()];
+        for &chunk in chunks {
+            *sizes
+                .get_mut(usize::from(chunk))
+                .ok_or(Error::InvalidParameter("bit assigned to a missing chunk"))? += 1;
+        }
+        if !sizes
+            .into_iter()
+            .eq(layout.ranges().map(|(start, end)| end - start))
+        {
+            return Err(Error::InvalidParameter(
+                "chunk sizes differ from the collapse",
+            ));
+        }
+        Ok(Self {
+            config,
+            input_len,
+            chunks: chunks.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use crate::{BitOrder, FinalTransform, Thresholding};
+
+    #[test]
+    fn test_contiguous_layouts_collapse_like_the_config() {
+        let mut fill = seeded(417);
+        for config in [
+            TbfConfig::default(),
+            TbfConfig::new(0.05).with_bit_order(BitOrder::LsbFirst),
+            TbfConfig::new(0.2)
+                .with_thresholding(Thresholding::Mean)
+                .with_transform(FinalTransform::Hash),
+        ] {
+            for len in [1, 3, 16, 40] {
+                let layout = LearnedLayout::contiguous(&config, len).unwrap();
+                let mut input = alloc::vec![0u8; len];
+                fill(&mut input);
+                assert_eq!(layout.collapse(&input), Ok(config.collapse(&input)));
+            }
+        }
+        assert!(LearnedLayout::contiguous(&TbfConfig::default(), 0).is_err());
+    }
+
+    #[test]
+    fn test_swapped_bits_count_towards_their_new_chunk() {
+        let config = TbfConfig::default().with_transform(FinalTransform::Identity);
+        let mut layout = LearnedLayout::contiguous(&config, 16).unwrap();
+        // Chunk 0 is bytes 0 and 1; move the 9 high bits of byte 2 and 3
+        // into it, in exchange for 9 of its own.
+        let mut input = [0u8; 16];
+        input[2] = 0xFF;
+        input[3] = 0x80;
+        assert_eq!(layout.collapse(&input).unwrap()[0], 0);
+        for bit in 0..9 {
+            layout.swap(bit, 16 + bit);
+        }
+        assert_eq!(layout.collapse(&input).unwrap()[..2], [0xFF, 0]);
+        assert_eq!(
+            layout.collapse(&input[1..]),
+            Err(Error::InvalidLength {
+                expected: 16,
+                found: 15
+            })
+        );
+    }
+
+    #[test]
+    fn test_serialization_round_trips_and_rejects_corruption() {
+        let key = [9u8; 32];
+        let config = TbfConfig::new(0.1).with_transform(FinalTransform::KeyedPrf { key });
+        let mut layout = LearnedLayout::contiguous(&config, 20).unwrap();
+        layout.swap(0, 159);
+        let bytes = layout.to_bytes();
+        assert_eq!(bytes.len(), HEADER_LEN + 160 + CHECKSUM_LEN);
+        assert!(LearnedLayout::from_bytes(&bytes).is_err());
+        assert_eq!(LearnedLayout::from_bytes_keyed(&bytes, &key), Ok(layout));
+
+        let mut bytes = LearnedLayout::contiguous(&TbfConfig::default(), 4)
+            .unwrap()
+            .to_bytes();
+        bytes[HEADER_LEN] ^= 1;
+        assert_eq!(
+            LearnedLayout::from_bytes(&bytes),
+            Err(Error::InvalidChecksum)
+        );
+
+        // A valid checksum over chunk sizes the collapse does not use.
+        let mut body = bytes[..bytes.len() - CHECKSUM_LEN].to_vec();
+        header::seal(&mut body);
+        assert_eq!(
+            LearnedLayout::from_bytes(&body),
+            Err(Error::InvalidParameter(
+                "chunk sizes differ from the collapse"
+            ))
+        );
+    }
+}
@@ -1,3 +1,219 @@
+//! Pensieve: error-tolerant, deterministic collapsing of noisy byte strings.
+//!
+//! The core algorithm, Thresholded Bit Folding, is [`collapse_deterministic`];
+//! higher-level modules build key derivation and protocols on top of it.
+//! [`similarity`] scores how alike two inputs are under a [`TbfConfig`];
+//! [`matches()`] only asks whether they collapse to the same output.
+//!
+//! Chunk levels are decided by a one-sided threshold that the tolerance
+//! sets; a [`TbfConfig`] can opt into another rule, see [`Thresholding`].
+//!
+//! # Secret inputs
+//! When the input is a secret (a seed, a biometric template, a PUF response)
+//! the time an operation takes must not depend on its value.
+//!
+//! - [`collapse_ct`] is the branch-free variant of the core algorithm and is
+//!   safe for secret inputs. Its output is identical to
+//!   [`collapse_deterministic`]. [`collapse_bits`] is its variant for inputs
+//!   that are not a whole number of bytes.
+//! - [`collapse_deterministic`] branches on input bits and is only meant for
+//!   public data (deduplication, similarity search).
+//! - [`fuzzy_extractor`], [`pake`], [`privacy_amplification`], [`puf`] and
+//!   [`secret_sharing`] use constant-time code paths for everything derived
+//!   from the secret. Lengths, tolerances and other parameters are always
+//!   treated as public.
+//!
+//! # Features
+//! - `std` (default): everything below plus the statistical sizing helpers
+//!   ([`analysis`], [`biometric`], [`secret_sharing`]), `OsEntropy`, the
+//!   file-backed `store::LogStore` and the threaded [`pipeline`] stage.
+//! - `alloc` (implied by `std`): the collapse itself and every protocol
+//!   returning heap-allocated values.
+//! - Without either, the crate is `no_std` and allocation-free. What
+//!   remains works on caller-provided buffers and fixed-size state, enough
+//!   to enroll and reproduce keys on a microcontroller:
+//!   [`TbfConfig::collapse_into`], [`stream::StreamingCollapse`], [`fuzzy_extractor::FuzzyExtractor`] and
+//!   [`puf`].
+//! - `argon2`: Argon2id stretching (`fuzzy_extractor::stretched`); needs `alloc`.
+//! - `text`: normalization and shingling of text for near-duplicate
+//!   document detection (`text`); needs `alloc`.
+//! - `image`: grayscale, resize and DCT preprocessing of raw pixels into
+//!   perceptual fingerprints (`image`); needs `std`.
+//! - `columnar`: collapse and similarity kernels over Arrow binary columns
+//!   (`columnar`); needs `alloc`.
+//! - `derive`: `#[derive(FuzzyFingerprint)]` for structs, fingerprinting
+//!   them field by field (`record`); needs `alloc`.
+//! - `parallel`: counts the chunks of inputs of 1 MiB or more on every
+//!   available core, so one huge collapse is not bound to a single thread;
+//!   needs `std`.
+//! - `testing`: [`Arbitrary`](testing::Arbitrary) configurations and digests
+//!   and generators of noisy, genuine and impostor input pairs, for property
+//!   tests of downstream crates (`testing`); needs `alloc`.
+//!
+//! Throughput and match rates are reported to a [`metrics::Metrics`]
+//! recorder installed with `metrics::set_metrics` (needs `std`). There is
+//! no `tracing` integration; a recorder can open spans or bump counters of
+//! whatever telemetry stack the service uses.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "alloc")]
+pub mod attestation;
+#[cfg(feature = "alloc")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod biometric;
+#[cfg(feature = "alloc")]
+pub mod canonical;
+#[cfg(feature = "alloc")]
+pub mod cdc;
+#[cfg(feature = "alloc")]
+pub mod chain;
+#[cfg(feature = "alloc")]
+pub mod cluster;
+#[cfg(feature = "columnar")]
+pub mod columnar;
+#[cfg(feature = "alloc")]
+pub mod compact;
+#[cfg(feature = "alloc")]
+pub mod composite;
+mod config;
+#[cfg(feature = "alloc")]
+pub mod confusable;
+mod crypto;
+#[cfg(feature = "alloc")]
+pub mod cyclic;
+#[cfg(feature = "alloc")]
+pub mod dataset;
+#[cfg(feature = "alloc")]
+pub mod dedup;
+#[cfg(feature = "alloc")]
+pub mod digest;
+pub mod distance;
+#[cfg(feature = "alloc")]
+pub mod dna;
+pub mod ecc;
+#[cfg(feature = "alloc")]
+pub mod embedding;
+#[cfg(feature = "alloc")]
+pub mod encoding;
+#[cfg(feature = "alloc")]
+pub mod ensemble;
+pub mod entropy;
+#[cfg(feature = "alloc")]
+pub mod envelope;
+mod error;
+pub mod fuzzy_extractor;
+#[cfg(feature = "alloc")]
+pub mod geo;
+#[cfg(feature = "alloc")]
+pub mod grouping;
+#[cfg(feature = "alloc")]
+mod header;
+#[cfg(feature = "image")]
+pub mod image;
+#[cfg(feature = "alloc")]
+pub mod incremental;
+#[cfg(feature = "alloc")]
+pub mod index;
+#[cfg(feature = "alloc")]
+pub mod iter;
+#[cfg(feature = "alloc")]
+pub mod join;
+#[cfg(feature = "alloc")]
+pub mod layout;
+#[cfg(feature = "alloc")]
+pub mod logdedup;
+#[cfg(feature = "alloc")]
+pub mod map;
+#[cfg(feature = "alloc")]
+pub mod merkle;
+pub mod metrics;
+#[cfg(feature = "alloc")]
+pub mod mnemonic;
+#[cfg(feature = "alloc")]
+pub mod multiset;
+#[cfg(feature = "alloc")]
+pub mod negotiate;
+#[cfg(feature = "alloc")]
+pub mod noise;
+#[cfg(feature = "alloc")]
+pub mod oprf;
+#[cfg(feature = "alloc")]
+pub mod packet;
+#[cfg(feature = "alloc")]
+pub mod pake;
+#[cfg(feature = "alloc")]
+pub mod partial;
+#[cfg(feature = "text")]
+pub mod phonetic;
+#[cfg(feature = "alloc")]
+pub mod piecewise;
+#[cfg(feature = "std")]
+pub mod pipeline;
+pub mod policy;
+mod popcount;
+pub mod presets;
+#[cfg(feature = "alloc")]
+pub mod privacy_amplification;
+#[cfg(feature = "alloc")]
+pub mod profile;
+#[cfg(feature = "alloc")]
+pub mod psi;
+pub mod puf;
+#[cfg(feature = "alloc")]
+pub mod reconcile;
+#[cfg(feature = "alloc")]
+pub mod record;
+#[cfg(feature = "alloc")]
+pub mod regions;
+#[cfg(feature = "alloc")]
+mod retention;
+#[cfg(feature = "alloc")]
+pub mod rolling;
+#[cfg(feature = "std")]
+pub mod secret_sharing;
+#[cfg(feature = "alloc")]
+pub mod shingle;
+#[cfg(feature = "alloc")]
+pub mod simhash;
+mod similarity;
+#[cfg(feature = "alloc")]
+pub mod store;
+pub mod stream;
+#[cfg(feature = "alloc")]
+pub mod test_vectors;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "text")]
+pub mod text;
+#[cfg(feature = "alloc")]
+pub mod timeseries;
+#[cfg(feature = "alloc")]
+pub mod timestamp;
+#[cfg(feature = "alloc")]
+pub mod tokenize;
+mod transform;
+#[cfg(feature = "text")]
+pub mod unicode;
+
+#[cfg(feature = "alloc")]
+pub use config::CollapseScratch;
+pub use config::{BitOrder, FixedBytes, FixedConfig, TbfConfig, Thresholding};
+pub use error::Error;
+#[cfg(feature = "alloc")]
+pub use iter::CollapseIteratorExt;
+pub use similarity::{match_level, matches, similarity};
+pub use transform::FinalTransform;
+
 /// Performs a deterministic, lossy collapse of a byte array into a fixed output,
 /// tolerating a specified percentage of bit errors. This algorithm, called
 /// "Thresholded Bit Folding" (TBF), ensures that inputs differing by up to the
@@ -8,7 +224,8 @@
 /// - `input`: A slice of bytes to collapse. The length determines the total number
 ///   of bits processed (e.g., 16 bytes = 128 bits).
 /// - `tolerance`: A float between 0.05 (5%) and 0.25 (25%) specifying the maximum
-///   percentage of bit flips to tolerate. Values outside this range are clamped.
+///   percentage of bit flips to tolerate. Values outside this range are clamped,
+///   and values inside it are rounded to the nearest per-mille (0.1%).
 ///
 /// # Returns
 /// A `Vec<u8>` of the same length as `input`, where each byte is derived from
@@ -19,11 +236,46 @@
 /// # Behavior
 /// - For inputs ≥ 128 bits, the algorithm uses 8 chunks.
 /// - For inputs < 128 bits but ≥ 16 bits, it scales the number of chunks proportionally.
-/// - For inputs < 8 bits, it applies a simple XOR transformation.
+/// - For inputs < 16 bits, chunks shrink instead: up to 8 chunks of
+///   `ceil(bits / 8)` bits, so a single byte folds into eight 1-bit chunks.
+///   Before [algorithm version](TbfConfig::ALGORITHM_VERSION) 3 such inputs
+///   formed a single chunk.
 /// - The output is guaranteed to differ from the input due to a final XOR step.
+/// - Output byte `i` is XORed with `0xAA + i` modulo 256, so the mask repeats
+///   every 256 bytes and inputs of any length are supported. Before
+///   [algorithm version](TbfConfig::ALGORITHM_VERSION) 2 this addition
+///   overflowed past 85 bytes.
+/// - Chunks are thresholded [one-sidedly](Thresholding::OneSided); a
+///   [`TbfConfig`] can collapse them [symmetrically](Thresholding::Symmetric)
+///   instead.
+/// - A chunk of `n` bits collapses to 1 when it holds at least
+///   `ceil(permille * n / 1000)` set bits, computed in integer arithmetic so
+///   every target agrees bit for bit. Before
+///   [algorithm version](TbfConfig::ALGORITHM_VERSION) 4 this was computed
+///   in `f32`, whose rounding could raise the threshold by one (e.g. to 16
+///   for 15% of a 100-bit chunk).
+///
+/// # Empty input
+/// Empty input collapses to the empty vector, under every tolerance and in
+/// every variant of the collapse ([`collapse_ct`], [`collapse_bits`] with
+/// `bit_len == 0`, [`TbfConfig::collapse`] under any transform). No other
+/// input collapses to an empty output, so the empty vector is a sentinel
+/// for "no input" rather than a fingerprint: callers must not store it or
+/// match on it, and should reject empty input up front, for instance with
+/// a [`Policy`](policy::Policy) requiring a minimum input length.
+///
+/// # Short input
+/// Inputs under [`TbfConfig::MIN_INPUT_BITS`] collapse too, into fewer or
+/// smaller chunks, but give no meaningful guarantees: chunks of one or two
+/// bits change level with any flip, and a handful of chunks admits so few
+/// outputs that a digest of a 4-byte value is a lookup table away from
+/// the value. [`TbfConfig::try_collapse`] refuses them with
+/// [`Error::InputTooShort`].
 ///
 /// # Examples
 /// ```rust
+/// use pensieve::collapse_deterministic;
+///
 /// let data1 = [0b11111111, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
 /// let data2 = [0b11111110, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
 /// let collapsed1 = collapse_deterministic(&data1, 0.05);
@@ -31,17 +283,18 @@
 /// assert_eq!(collapsed1, collapsed2); // 1 bit flip within 5% tolerance
 /// assert_ne!(collapsed1, data1); // Output differs from input
 /// ```
-fn collapse_deterministic(input: &[u8], tolerance: f32) -> Vec<u8> {
+#[cfg(feature = "alloc")]
+pub fn collapse_deterministic(input: &[u8], tolerance: f32) -> Vec<u8> {
+    // Empty input has no chunks; its output is the documented empty sentinel.
+    if input.is_empty() {
+        return Vec::new();
+    }
+
     // Calculate total number of bits in the input (8 bits per byte).
     let total_bits = input.len() * 8;
 
-    // Handle small inputs (< 8 bits) with a simple XOR to ensure output differs.
-    if total_bits < 8 {
-        return input.iter().map(|&b| b ^ 0xAA).collect(); // XOR with 0xAA (10101010) for distinction.
-    }
-
-    // Clamp tolerance to the valid range of 5% to 25%.
-    let tolerance = tolerance.clamp(0.05, 0.25);
+    // Clamp tolerance to the valid range of 5% to 25%, in whole per-mille.
+    let permille = tolerance_permille(tolerance);
 
     // Convert input bytes to a flat vector of bits (true = 1, false = 0).
     let mut bits = Vec::new();
@@ -52,14 +305,15 @@ fn collapse_deterministic(input: &[u8], tolerance: f32) -> Vec<u8> {
         }
     }
 
-    // Determine number of chunks: 8 for 128+ bits, scaled down for smaller inputs.
-    let num_chunks = if total_bits >= 128 {
-        8
+    // Determine bits per chunk: 8 chunks for 128+ bits, one chunk per 16 bits
+    // down to 16 bits, and below that up to 8 chunks of 1 or 2 bits.
+    let chunk_size = if total_bits >= 128 {
+        total_bits / 8
+    } else if total_bits >= 16 {
+        total_bits / (total_bits / 16)
     } else {
-        total_bits / 16
+        total_bits.div_ceil(8)
     };
-    // Calculate bits per chunk, ensuring at least 1 chunk.
-    let chunk_size = total_bits / num_chunks.max(1);
 
     // Process each chunk to determine collapse level.
     let mut collapsed = Vec::new();
@@ -67,7 +321,7 @@ fn collapse_deterministic(input: &[u8], tolerance: f32) -> Vec<u8> {
         // Count the number of 1s in the chunk.
         let sum: u32 = chunk.iter().map(|&b| if b { 1 } else { 0 }).sum();
         // Calculate max tolerated flips for this chunk based on tolerance.
-        let threshold = (tolerance * chunk_size as f32).ceil() as u32;
+        let threshold = threshold(permille, chunk_size);
         // Set level to 1 if sum meets or exceeds the minimum ones needed (chunk_size - threshold).
         let level = if sum >= threshold { 1 } else { 0 }; // Changed to >= for inclusivity.
         collapsed.push(level as u8); // Store level (0 or 1) as a byte.
@@ -76,25 +330,459 @@ fn collapse_deterministic(input: &[u8], tolerance: f32) -> Vec<u8> {
     // Stretch collapsed levels across output length with transformation.
     let mut result = Vec::new();
     for i in 0..input.len() {
-        // Scale level to 0 or 255 for full byte range.
-        let base_value = collapsed[i % collapsed.len()] * 255;
+        // Scale level to 0 or 255 for full byte range, or, with more chunks
+        // than bytes, pack the level of chunk (i + k * len) into bit k.
+        let base_value = if collapsed.len() <= input.len() {
+            collapsed[i % collapsed.len()] * 255
+        } else {
+            (0..8).fold(0, |byte, k| {
+                byte << 1 | collapsed[(i + k * input.len()) % collapsed.len()]
+            })
+        };
         // Apply position-dependent XOR to ensure output differs from input.
-        result.push(base_value ^ (0xAA + i as u8)); // 0xAA + i varies from 170 to 185+.
+        result.push(base_value ^ 0xAAu8.wrapping_add(i as u8)); // 0xAA + i, wrapping after 85 bytes.
     }
 
     result // Return the transformed, collapsed output.
 }
 
+/// Constant-time variant of [`collapse_deterministic`] for secret inputs.
+///
+/// Produces exactly the same output as [`collapse_deterministic`], but the
+/// sequence of executed instructions and memory accesses depends only on
+/// `input.len()` and `tolerance`, never on the input bytes: bits are counted
+/// with a branch-free SWAR popcount, the threshold comparison is done with
+/// arithmetic masks instead of a conditional, and chunk boundaries are
+/// derived from the (public) length alone.
+///
+/// # Parameters
+/// - `input`: The secret bytes to collapse. Only its length is treated as public.
+/// - `tolerance`: Same meaning and clamping as in [`collapse_deterministic`].
+///   Treated as public.
+///
+/// # Returns
+/// The same `Vec<u8>` that [`collapse_deterministic`] returns for these arguments.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::{collapse_ct, collapse_deterministic};
+///
+/// let seed = [0x5A; 16];
+/// assert_eq!(collapse_ct(&seed, 0.125), collapse_deterministic(&seed, 0.125));
+/// ```
+#[cfg(feature = "alloc")]
+pub fn collapse_ct(input: &[u8], tolerance: f32) -> Vec<u8> {
+    FinalTransform::default().apply(&ct_chunk_levels(input, tolerance), input.len())
+}
+
+/// Collapses the first `bit_len` bits (MSB-first) of `input`, for inputs
+/// whose length is not a whole number of bytes.
+///
+/// Chunking and thresholds are computed from `bit_len`, so the padding bits
+/// at the end of the last byte neither count towards any chunk nor need a
+/// particular value. For `bit_len == 8 * input.len()` the result equals
+/// [`collapse_ct`]; otherwise it is `input.len()` bytes long with the
+/// padding bits of the last byte cleared. Runs in constant time like
+/// [`collapse_ct`].
+///
+/// Fails with [`Error::InvalidLength`] unless `input` has exactly
+/// `bit_len.div_ceil(8)` bytes.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::collapse_bits;
+///
+/// // A 100-bit codeword; the low 4 bits of the last byte are padding.
+/// let codeword = [0xA5u8; 13];
+/// let mut other_padding = codeword;
+/// other_padding[12] ^= 0x0F;
+/// assert_eq!(
+///     collapse_bits(&codeword, 100, 0.125),
+///     collapse_bits(&other_padding, 100, 0.125)
+/// );
+/// ```
+#[cfg(feature = "alloc")]
+pub fn collapse_bits(input: &[u8], bit_len: usize, tolerance: f32) -> Result<Vec<u8>, Error> {
+    if input.len() != bit_len.div_ceil(8) {
+        return Err(Error::InvalidLength {
+            expected: bit_len.div_ceil(8),
+            found: input.len(),
+        });
+    }
+    let mut output = FinalTransform::default().apply(
+        &ct_chunk_levels_bits(input, bit_len, tolerance_permille(tolerance)),
+        input.len(),
+    );
+    if let Some(last) = output.last_mut() {
+        *last &= 0xFFu8 << ((8 - bit_len % 8) % 8);
+    }
+    Ok(output)
+}
+
+/// The per-chunk levels (0 or 1) behind [`collapse_ct`], computed in
+/// constant time. Empty input has no chunks.
+#[cfg(feature = "alloc")]
+pub(crate) fn ct_chunk_levels(input: &[u8], tolerance: f32) -> Vec<u8> {
+    ct_chunk_levels_bits(input, input.len() * 8, tolerance_permille(tolerance))
+}
+
+/// [`ct_chunk_levels`] over the first `bit_len` bits of `input`.
+#[cfg(feature = "alloc")]
+fn ct_chunk_levels_bits(input: &[u8], bit_len: usize, permille: u32) -> Vec<u8> {
+    ct_layout_levels(input, ChunkLayout::for_permille(bit_len, permille))
+}
+
+/// The levels of the chunks of `layout` over `input`, in constant time.
+#[cfg(feature = "alloc")]
+pub(crate) fn ct_layout_levels(input: &[u8], layout: Option<ChunkLayout>) -> Vec<u8> {
+    // Only empty input has no layout; branching on length is fine.
+    let Some(layout) = layout else {
+        return Vec::new();
+    };
+
+    #[cfg(feature = "parallel")]
+    if input.len() >= PARALLEL_MIN_LEN {
+        return layout.levels(&parallel_popcounts(input, &layout));
+    }
+    let sums: Vec<u32> = layout
+        .ranges()
+        .map(|(start, end)| ct_popcount_range(input, start, end))
+        .collect();
+    layout.levels(&sums)
+}
+
+/// Inputs from this many bytes on are counted on several threads.
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_LEN: usize = 1 << 20;
+
+/// The popcount of every chunk of `layout`, with the input split into one
+/// byte-aligned piece per available core and the partial counts of each
+/// piece added up. The split depends only on the length.
+#[cfg(feature = "parallel")]
+fn parallel_popcounts(input: &[u8], layout: &ChunkLayout) -> Vec<u32> {
+    let ranges: Vec<(usize, usize)> = layout.ranges().collect();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let piece = layout.total_bits.div_ceil(threads).next_multiple_of(8);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..layout.total_bits)
+            .step_by(piece)
+            .map(|from| {
+                let (ranges, to) = (&ranges, (from + piece).min(layout.total_bits));
+                scope.spawn(move || {
+                    ranges
+                        .iter()
+                        .map(|&(start, end)| {
+                            let (start, end) = (start.max(from), end.min(to));
+                            if start < end {
+                                ct_popcount_range(input, start, end)
+                            } else {
+                                0
+                            }
+                        })
+                        .collect::<Vec<u32>>()
+                })
+            })
+            .collect();
+        let mut sums = vec![0u32; ranges.len()];
+        for worker in workers {
+            let part = worker.join().expect("popcounts do not panic");
+            for (sum, part) in sums.iter_mut().zip(part) {
+                *sum += part;
+            }
+        }
+        sums
+    })
+}
+
+/// Most chunks an input of whole bytes is split into.
+pub(crate) const MAX_CHUNKS: usize = 8;
+
+/// The levels of the chunks of `layout` over `input` with bits read in
+/// `order`, in constant time and without allocating, and their number.
+pub(crate) fn ct_layout_levels_array(
+    input: &[u8],
+    order: BitOrder,
+    layout: Option<ChunkLayout>,
+) -> ([u8; MAX_CHUNKS], usize) {
+    let Some(layout) = layout else {
+        return ([0; MAX_CHUNKS], 0);
+    };
+    let mut sums = [0u32; MAX_CHUNKS];
+    for (sum, (start, end)) in sums.iter_mut().zip(layout.ranges()) {
+        *sum = ct_popcount_range_in(input, start, end, order);
+    }
+    (layout.levels_array(&sums), layout.chunk_count())
+}
+
+/// The level (0 or 1) of a chunk with `sum` set bits, without branching.
+fn ct_level(sum: u32, threshold: u32) -> u8 {
+    // sum >= threshold  <=>  (threshold - 1 - sum) is negative, i.e. its sign bit is set.
+    ((i64::from(threshold) - 1 - i64::from(sum)) as u64 >> 63) as u8
+}
+
+/// Chunk geometry of the collapse for a given input length and tolerance,
+/// shared by every implementation and analysis of the algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ChunkLayout {
+    pub(crate) total_bits: usize,          // Bits in the input.
+    pub(crate) chunk_size: usize,          // Bits per chunk (the last chunk may be shorter).
+    pub(crate) threshold: u32,             // Minimum popcount for a chunk to collapse to 1, or 0.
+    pub(crate) thresholding: Thresholding, // Parity and Mean have no threshold.
+}
+
+impl ChunkLayout {
+    /// Computes the layout exactly like [`collapse_deterministic`]; `None` for
+    /// empty input, which has no chunks.
+    pub(crate) fn new(input_len: usize, tolerance: f32) -> Option<Self> {
+        // Calculate total number of bits in the input (8 bits per byte).
+        Self::for_bits(input_len * 8, tolerance)
+    }
+
+    /// The layout of an input of `total_bits` bits, which need not be a
+    /// multiple of 8; `None` for zero bits.
+    pub(crate) fn for_bits(total_bits: usize, tolerance: f32) -> Option<Self> {
+        Self::for_permille(total_bits, tolerance_permille(tolerance))
+    }
+
+    /// The layout of an input of `total_bits` bits under `config`, whose
+    /// [`Thresholding`] decides the threshold.
+    pub(crate) const fn for_config(total_bits: usize, config: &TbfConfig) -> Option<Self> {
+        let Some(layout) = Self::for_permille(total_bits, config.tolerance_permille() as u32)
+        else {
+            return None;
+        };
+        let threshold = match config.thresholding() {
+            Thresholding::OneSided => layout.threshold,
+            // More than half of a full chunk.
+            Thresholding::Symmetric => (layout.chunk_size / 2 + 1) as u32,
+            Thresholding::Parity | Thresholding::Mean => 0,
+        };
+        Some(Self {
+            threshold,
+            thresholding: config.thresholding(),
+            ..layout
+        })
+    }
+
+    /// [`ChunkLayout::for_bits`] with the tolerance in per-mille.
+    pub(crate) const fn for_permille(total_bits: usize, permille: u32) -> Option<Self> {
+        if total_bits == 0 {
+            return None;
+        }
+
+        let (min, max) = (
+            TbfConfig::MIN_TOLERANCE_PERMILLE as u32,
+            TbfConfig::MAX_TOLERANCE_PERMILLE as u32,
+        );
+        let permille = if permille < min {
+            min
+        } else if permille > max {
+            max
+        } else {
+            permille
+        };
+        // 8 chunks for 128+ bits, one chunk per 16 bits down to 16 bits, and
+        // below that (since algorithm version 3) up to 8 chunks of 1 or 2 bits.
+        let chunk_size = if total_bits >= 128 {
+            total_bits / 8
+        } else if total_bits >= 16 {
+            total_bits / (total_bits / 16)
+        } else {
+            total_bits.div_ceil(8)
+        };
+        let threshold = threshold(permille, chunk_size);
+        Some(Self {
+            total_bits,
+            chunk_size,
+            threshold,
+            thresholding: Thresholding::OneSided,
+        })
+    }
+
+    /// The `(start, end)` bit range of every chunk, in order.
+    pub(crate) fn ranges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.total_bits)
+            .step_by(self.chunk_size)
+            .map(|start| (start, (start + self.chunk_size).min(self.total_bits)))
+    }
+
+    /// Number of chunks (and therefore of levels).
+    pub(crate) const fn chunk_count(&self) -> usize {
+        self.total_bits.div_ceil(self.chunk_size)
+    }
+
+    /// The levels (0 or 1) of the chunks whose popcounts are `sums`, in
+    /// order, without branching on the popcounts.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn levels(&self, sums: &[u32]) -> Vec<u8> {
+        let total = sums.iter().map(|&sum| u64::from(sum)).sum();
+        self.ranges()
+            .zip(sums)
+            .map(|(range, &sum)| self.level(range, sum, total))
+            .collect()
+    }
+
+    /// [`ChunkLayout::levels`] without allocating, for layouts of whole
+    /// bytes; entries past the last chunk stay 0.
+    pub(crate) fn levels_array(&self, sums: &[u32; MAX_CHUNKS]) -> [u8; MAX_CHUNKS] {
+        let total = sums.iter().map(|&sum| u64::from(sum)).sum();
+        let mut levels = [0u8; MAX_CHUNKS];
+        for ((level, range), &sum) in levels.iter_mut().zip(self.ranges()).zip(sums) {
+            *level = self.level(range, sum, total);
+        }
+        levels
+    }
+
+    /// The level of the chunk over bits `range` holding `sum` set bits, in
+    /// an input holding `total`, without branching on either.
+    pub(crate) fn level(&self, (start, end): (usize, usize), sum: u32, total: u64) -> u8 {
+        match self.thresholding {
+            Thresholding::OneSided | Thresholding::Symmetric => ct_level(sum, self.threshold),
+            Thresholding::Parity => (sum & 1) as u8,
+            Thresholding::Mean => {
+                // sum / (end - start) > total / total_bits, cross-multiplied.
+                let chunk = u128::from(sum) * self.total_bits as u128;
+                let mean = u128::from(total) * (end - start) as u128;
+                // chunk > mean  <=>  (mean - chunk) is negative.
+                ((mean as i128 - chunk as i128) as u128 >> 127) as u8
+            }
+        }
+    }
+
+    /// The fewest flips of bits inside the chunk over `range` that change
+    /// its [`level`](Self::level), the rest of the input left as is;
+    /// `u32::MAX` if no such flips exist. Branches on the popcounts.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn margin(&self, range @ (start, end): (usize, usize), sum: u32, total: u64) -> u32 {
+        if self.thresholding == Thresholding::Parity {
+            return 1; // Any flip.
+        }
+        let len = (end - start) as u32;
+        let level = self.level(range, sum, total);
+        // A set chunk loses set bits, a clear one gains them.
+        let available = if level == 1 { sum } else { len - sum };
+        let flips = match self.thresholding {
+            Thresholding::OneSided | Thresholding::Symmetric if level == 1 => {
+                u64::from(sum - self.threshold + 1)
+            }
+            Thresholding::OneSided | Thresholding::Symmetric => u64::from(self.threshold - sum),
+            Thresholding::Parity => unreachable!("handled above"),
+            Thresholding::Mean => {
+                // Every flip moves sum * total_bits - total * len by total_bits - len.
+                let step = (self.total_bits - len as usize) as i128;
+                let excess =
+                    i128::from(sum) * self.total_bits as i128 - i128::from(total) * i128::from(len);
+                match (step, level) {
+                    (0, _) => return u32::MAX, // One chunk: always at the mean.
+                    (_, 1) => (excess + step - 1) as u64 / step as u64,
+                    _ => (-excess / step + 1) as u64,
+                }
+            }
+        };
+        if flips > u64::from(available) {
+            u32::MAX
+        } else {
+            flips as u32
+        }
+    }
+}
+
+/// `tolerance` clamped to 5%..=25% and rounded to the nearest per-mille.
+///
+/// Since algorithm version 4 thresholds depend on this integer alone, so
+/// configurations built with [`TbfConfig::from_permille`] never touch
+/// floating point. NaN and negative tolerances clamp to 5%.
+pub(crate) fn tolerance_permille(tolerance: f32) -> u32 {
+    // `as` saturates: NaN and negatives become 0, huge values `u32::MAX`.
+    ((tolerance * 1000.0 + 0.5) as u32).clamp(
+        u32::from(TbfConfig::MIN_TOLERANCE_PERMILLE),
+        u32::from(TbfConfig::MAX_TOLERANCE_PERMILLE),
+    )
+}
+
+/// Minimum popcount for a chunk of `chunk_size` bits to collapse to 1:
+/// `ceil(permille * chunk_size / 1000)`, in exact integer arithmetic,
+/// saturating at `u32::MAX` for chunks whose popcount would not fit either.
+const fn threshold(permille: u32, chunk_size: usize) -> u32 {
+    let threshold = (permille as u128 * chunk_size as u128).div_ceil(1000);
+    if threshold > u32::MAX as u128 {
+        u32::MAX
+    } else {
+        threshold as u32
+    }
+}
+
+/// Counts the set bits in MSB-first bit positions `start..end` of `input`
+/// without branching on the bit values.
+#[cfg(feature = "alloc")]
+fn ct_popcount_range(input: &[u8], start: usize, end: usize) -> u32 {
+    ct_popcount_range_in(input, start, end, BitOrder::MsbFirst)
+}
+
+/// [`ct_popcount_range`] with bit positions numbered in `order`.
+fn ct_popcount_range_in(input: &[u8], start: usize, end: usize, order: BitOrder) -> u32 {
+    // Positions are public; only the bits are secret. Whole bytes count
+    // alike in either bit order, so only the partial ones at the ends need
+    // `order`.
+    let partial = |byte: u8, first: usize, last: usize| {
+        let mask = (0xFFu16 >> first) as u8 & !(0xFFu16 >> last) as u8;
+        let byte = match order {
+            BitOrder::MsbFirst => byte,
+            BitOrder::LsbFirst => byte.reverse_bits(),
+        };
+        ct_popcount8(byte & mask)
+    };
+    if start >= end {
+        return 0;
+    }
+    let (whole_start, whole_end) = (start.div_ceil(8), end / 8);
+    if whole_start > whole_end {
+        return partial(input[start / 8], start % 8, end % 8);
+    }
+    let mut sum = popcount::ct_popcount(&input[whole_start..whole_end]);
+    if !start.is_multiple_of(8) {
+        sum += partial(input[start / 8], start % 8, 8);
+    }
+    if !end.is_multiple_of(8) {
+        sum += partial(input[whole_end], 0, end % 8);
+    }
+    sum
+}
+
+/// Branch- and table-free population count of a byte (SWAR).
+fn ct_popcount8(x: u8) -> u32 {
+    let x = x - ((x >> 1) & 0x55);
+    let x = (x & 0x33) + ((x >> 2) & 0x33);
+    u32::from((x + (x >> 4)) & 0x0F)
+}
+
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     use super::*;
 
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_popcounts_match_serial_ones() {
+        let mut input = vec![0u8; (3 << 20) + 5];
+        crate::entropy::tests::seeded(402)(&mut input);
+        for config in [TbfConfig::new(0.125), TbfConfig::new(0.05)] {
+            let layout = ChunkLayout::for_config(input.len() * 8, &config).unwrap();
+            let serial: Vec<u32> = layout
+                .ranges()
+                .map(|(start, end)| ct_popcount_range(&input, start, end))
+                .collect();
+            assert_eq!(parallel_popcounts(&input, &layout), serial);
+            assert_eq!(config.chunk_levels(&input), layout.levels(&serial));
+        }
+    }
+
     #[test]
     fn test_collapse_128_5_percent_concentrated() {
         let data0 = [0u8; 16];
-        let mut data1 = data0.clone();
+        let mut data1 = data0;
         data1[0] = 0b11111111;
 
-        let mut data2 = data1.clone();
+        let mut data2 = data1;
         data2[0] ^= 0b00000001; // 1 bit (0.78%, within 5%)
 
         assert_ne!(data1, data2);
@@ -107,10 +795,10 @@ mod tests {
     #[test]
     fn test_collapse_128_12_5_percent_spread() {
         let data0 = [0u8; 16];
-        let mut data1 = data0.clone();
+        let mut data1 = data0;
         data1[0] = 0b11111111;
 
-        let mut data2 = data1.clone();
+        let mut data2 = data1;
         data2[0] ^= 0b00000001; // 1 bit
         data2[4] ^= 0b00000001; // 2 bits
         data2[8] ^= 0b00000001; // 3 bits
@@ -126,10 +814,10 @@ mod tests {
     #[test]
     fn test_collapse_128_20_percent_concentrated() {
         let data0 = [0u8; 16];
-        let mut data1 = data0.clone();
+        let mut data1 = data0;
         data1[0] = 0b11111111;
 
-        let mut data2 = data1.clone();
+        let mut data2 = data1;
         data2[0] ^= 0b00001111; // 4 bits (3.1%, within 20%)
 
         assert_ne!(data1, data2);
@@ -142,10 +830,10 @@ mod tests {
     #[test]
     fn test_collapse_128_25_percent_spread() {
         let data0 = [0u8; 16];
-        let mut data1 = data0.clone();
+        let mut data1 = data0;
         data1[0] = 0b11111111;
 
-        let mut data2 = data1.clone();
+        let mut data2 = data1;
         data2[0] ^= 0b00000011; // 2 bits
         data2[2] ^= 0b00000011; // 4 bits
         data2[4] ^= 0b00000011; // 6 bits (4.7%, within 25%)
@@ -160,10 +848,10 @@ mod tests {
     #[test]
     fn test_collapse_16_12_5_percent() {
         let data0 = [0u8; 2];
-        let mut data1 = data0.clone();
+        let mut data1 = data0;
         data1[0] = 0b11111111;
 
-        let mut data2 = data1.clone();
+        let mut data2 = data1;
         data2[0] ^= 0b00000011; // 2 bits (12.5%)
 
         assert_ne!(data1, data2);
@@ -176,10 +864,10 @@ mod tests {
     #[test]
     fn test_collapse_128_too_many_errors_5_percent() {
         let data0 = [0u8; 16];
-        let mut data1 = data0.clone();
+        let mut data1 = data0;
         data1[0] = 0b11111111;
 
-        let mut data2 = data1.clone();
+        let mut data2 = data1;
         data2[0] ^= 0b11111111; // 8 bits (6.25%, exceeds 5%)
 
         assert_ne!(data1, data2);
@@ -188,4 +876,166 @@ mod tests {
         assert_ne!(data0.as_slice(), collapsed1.as_slice());
         assert_ne!(collapsed1, collapsed2);
     }
+
+    #[test]
+    fn test_collapse_ct_matches_reference() {
+        for len in (0..=130usize).chain([255, 256, 257, 1000]) {
+            for pattern in [0x00u8, 0xFF, 0x5A, 0x81, 0x0F] {
+                let input: Vec<u8> = (0..len)
+                    .map(|i| pattern.rotate_left(i as u32) ^ (i as u8).wrapping_mul(37))
+                    .collect();
+                for tolerance in [0.05, 0.125, 0.2, 0.25] {
+                    assert_eq!(
+                        collapse_ct(&input, tolerance),
+                        collapse_deterministic(&input, tolerance),
+                        "len {len}, pattern {pattern:#x}, tolerance {tolerance}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_inputs_wrap_position_mask() {
+        let input = [0xFFu8; 600];
+        let collapsed = collapse_deterministic(&input, 0.125);
+        assert_eq!(collapsed.len(), 600);
+        // Every chunk collapses to 1, so byte i is 0xFF ^ (0xAA + i mod 256).
+        assert_eq!((collapsed[85], collapsed[86]), (0x00, 0xFF));
+        assert_eq!(collapsed[..256], collapsed[256..512]);
+        assert_eq!(collapse_ct(&input, 0.125), collapsed);
+    }
+
+    #[test]
+    fn test_collapse_bits() {
+        let input = [0x5Au8; 13];
+        assert_eq!(
+            collapse_bits(&input, 104, 0.2),
+            Ok(collapse_ct(&input, 0.2))
+        );
+        // 100 bits: 6 chunks of 16 bits and a 4-bit remainder, where the
+        // padded 104 bits would give chunks of 17.
+        let collapsed = collapse_bits(&input, 100, 0.2).unwrap();
+        assert_eq!(collapsed.len(), 13);
+        assert_eq!(collapsed[12] & 0x0F, 0);
+        let mut padded = input;
+        padded[12] = 0x50;
+        assert_eq!(collapse_bits(&padded, 100, 0.2), Ok(collapsed));
+        assert_eq!(
+            collapse_bits(&input, 96, 0.2),
+            Err(Error::InvalidLength {
+                expected: 12,
+                found: 13
+            })
+        );
+        assert_eq!(collapse_bits(&[], 0, 0.2), Ok(Vec::new()));
+        assert_eq!(collapse_bits(&[0x80], 1, 0.2).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_empty_input_is_empty_sentinel() {
+        for tolerance in [0.0, 0.05, 0.125, 0.25, 1.0] {
+            assert_eq!(collapse_deterministic(&[], tolerance), Vec::<u8>::new());
+            assert_eq!(collapse_ct(&[], tolerance), Vec::<u8>::new());
+            assert_eq!(collapse_bits(&[], 0, tolerance), Ok(Vec::new()));
+        }
+        for transform in [
+            FinalTransform::Identity,
+            FinalTransform::default(),
+            FinalTransform::KeyedPrf { key: [7; 32] },
+            FinalTransform::Hash,
+        ] {
+            let config = TbfConfig::default().with_transform(transform);
+            assert!(config.collapse(&[]).is_empty());
+            assert!(config.chunk_levels(&[]).is_empty());
+        }
+        // Nothing else collapses to the sentinel.
+        for len in 1..=32 {
+            assert_eq!(collapse_deterministic(&vec![0; len], 0.125).len(), len);
+            assert_eq!(collapse_ct(&vec![0xFF; len], 0.125).len(), len);
+        }
+        for bit_len in 1..=16usize {
+            assert!(
+                !collapse_bits(&vec![0; bit_len.div_ceil(8)], bit_len, 0.125)
+                    .unwrap()
+                    .is_empty()
+            );
+        }
+    }
+
+    #[test]
+    fn test_thresholds_are_exact_integers() {
+        // 15% of a 100-bit chunk: 15.000001 in `f32`, exactly 15 here.
+        let layout = ChunkLayout::new(100, 0.15).unwrap();
+        assert_eq!((layout.chunk_size, layout.threshold), (100, 15));
+        let mut input = [0u8; 100];
+        for (start, _) in layout.ranges() {
+            for bit in start..start + 15 {
+                input[bit / 8] |= 0x80 >> (bit % 8);
+            }
+        }
+        assert_eq!(ct_chunk_levels(&input, 0.15), [1; 8]);
+        assert_eq!(
+            collapse_deterministic(&input, 0.15),
+            collapse_ct(&input, 0.15)
+        );
+
+        for permille in 50..=250u32 {
+            for chunk_size in [16usize, 17, 100, 1125, 1 << 24, (1 << 24) + 1] {
+                let layout = ChunkLayout::for_permille(chunk_size * 8, permille).unwrap();
+                assert_eq!(layout.chunk_size, chunk_size);
+                let exact = (permille as usize * chunk_size).div_ceil(1000);
+                assert_eq!(layout.threshold as usize, exact, "{permille} {chunk_size}");
+            }
+        }
+        // Tolerances round to the nearest per-mille before the threshold.
+        assert_eq!(ChunkLayout::new(100, 0.1496), ChunkLayout::new(100, 0.15));
+    }
+
+    #[test]
+    fn test_small_inputs_fold_into_short_chunks() {
+        for bit_len in 1..=32usize {
+            let layout = ChunkLayout::for_bits(bit_len, 0.125).unwrap();
+            let expected = if bit_len < 16 {
+                bit_len.div_ceil(8)
+            } else {
+                bit_len / (bit_len / 16)
+            };
+            assert_eq!(layout.chunk_size, expected, "{bit_len} bits");
+            assert!((1..=8).contains(&layout.chunk_count()), "{bit_len} bits");
+
+            let zeros = vec![0u8; bit_len.div_ceil(8)];
+            let collapsed = collapse_bits(&zeros, bit_len, 0.125).unwrap();
+            for bit in 0..bit_len {
+                let mut input = zeros.clone();
+                input[bit / 8] |= 0x80 >> (bit % 8);
+                let levels = ct_chunk_levels_bits(&input, bit_len, 125);
+                let flipped = collapse_bits(&input, bit_len, 0.125).unwrap();
+                if bit_len < 16 {
+                    // Threshold 1: the bit sets its own chunk, and every
+                    // chunk reaches the output.
+                    let mut expected = vec![0u8; layout.chunk_count()];
+                    expected[bit / layout.chunk_size] = 1;
+                    assert_eq!(levels, expected, "{bit_len} bits, bit {bit}");
+                    assert_ne!(flipped, collapsed, "{bit_len} bits, bit {bit}");
+                } else {
+                    // One flip stays within the tolerance.
+                    assert_eq!(flipped, collapsed, "{bit_len} bits, bit {bit}");
+                }
+            }
+        }
+        // A byte folds into eight 1-bit chunks, packed into its output.
+        assert_eq!(collapse_ct(&[0b1000_0001], 0.125), [0b1000_0001 ^ 0xAA]);
+        assert_eq!(
+            collapse_ct(&[0b0110_0000], 0.25),
+            collapse_deterministic(&[0b0110_0000], 0.25)
+        );
+    }
+
+    #[test]
+    fn test_ct_popcount8() {
+        for x in 0..=255u8 {
+            assert_eq!(ct_popcount8(x), x.count_ones());
+        }
+    }
 }
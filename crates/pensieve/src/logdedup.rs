@@ -0,0 +1,303 @@
+//! Suppressing near-duplicate log lines.
+//!
+//! A failing dependency makes a service log the same message thousands of
+//! times a minute, each copy with its own timestamp, request id or
+//! latency. A [`LogDeduplicator`] turns every line into a SimHash of its
+//! tokens ([`LogWords`] by default, which drops the words holding digits)
+//! and compares it with the lines passed through within a sliding time
+//! window: a line whose SimHash is within the tolerance of one of them is
+//! suppressed as a duplicate of it, anything else passes through and is
+//! remembered in turn.
+//!
+//! A suppressed line keeps its original in the window, so a message that
+//! keeps repeating stays suppressed until it pauses for a whole window.
+//! [`LogDeduplicator::drain_suppressed`] reports how many copies of each
+//! line were suppressed since the last call, for a periodic "repeated n
+//! times" summary.
+//!
+//! SimHashes are compared bit by bit rather than collapsed: two lines
+//! differing in a word differ in many bits spread over the whole SimHash,
+//! which a Hamming radius absorbs and chunk thresholds do not. Lines and
+//! their tokens are public data.
+
+use crate::Error;
+use crate::dedup::Decision;
+use crate::distance::hamming;
+use crate::simhash::SimHash;
+use crate::tokenize::{LogWords, Tokenizer};
+use alloc::vec::Vec;
+
+/// Bits of the SimHash of every line.
+const SIMHASH_BITS: usize = 256;
+
+/// A line passed through and remembered.
+#[derive(Debug, Clone)]
+struct Seen {
+    simhash: Vec<u8>,
+    id: u64,
+    last_seen: u64,  // Time of the line or of its latest suppressed copy.
+    suppressed: u64, // Copies suppressed since the last drain.
+}
+
+/// Classifies log lines as new or as near-duplicates of recent ones.
+///
+/// Lines are numbered from 0 in the order they are checked, and a line's
+/// number is its id, like records of a
+/// [`Deduplicator`](crate::dedup::Deduplicator).
+///
+/// # Examples
+/// ```rust
+/// use pensieve::dedup::Decision;
+/// use pensieve::logdedup::LogDeduplicator;
+///
+/// let mut logs = LogDeduplicator::new(0.125, 60, 1000).unwrap();
+/// let lines = [
+///     "12:00:01 ERROR upstream db-7 timed out after 3000ms (request 8812)",
+///     "12:00:01 INFO served /health in 2ms",
+///     "12:00:02 ERROR upstream db-7 timed out after 3001ms (request 8813)",
+///     "12:00:02 ERROR upstream db-7 timed out after 3000ms (request 8814)",
+/// ];
+/// let decisions: Vec<Decision> = lines
+///     .iter()
+///     .map(|line| logs.check_at(line.as_bytes(), 0))
+///     .collect();
+/// assert_eq!(
+///     decisions,
+///     [
+///         Decision::Unique(0),
+///         Decision::Unique(1),
+///         Decision::DuplicateOf(0),
+///         Decision::DuplicateOf(0),
+///     ]
+/// );
+/// assert_eq!(logs.drain_suppressed(), [(0, 2)]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LogDeduplicator<T = LogWords> {
+    tokenizer: T,
+    max_distance: u32, // Largest Hamming distance between duplicate SimHashes.
+    window: u64,
+    capacity: usize,
+    seen: Vec<Seen>,          // Remembered lines, by id.
+    drained: Vec<(u64, u64)>, // Undrained counts of lines already forgotten.
+    next_id: u64,             // Id of the next line checked.
+    now: u64,                 // Latest time passed in.
+}
+
+impl LogDeduplicator {
+    /// Creates a deduplicator suppressing lines whose SimHashes differ in
+    /// at most `tolerance` of their bits (clamped to 5%..=25%, like a
+    /// [`TbfConfig`](crate::TbfConfig)'s) from a line seen less than
+    /// `window` time units ago, remembering at most `capacity` lines.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `capacity` is zero.
+    pub fn new(tolerance: f32, window: u64, capacity: usize) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(Error::InvalidParameter(
+                "capacity must be at least one line",
+            ));
+        }
+        let permille = crate::tolerance_permille(tolerance) as usize;
+        Ok(Self {
+            tokenizer: LogWords,
+            max_distance: (SIMHASH_BITS * permille / 1000) as u32,
+            window,
+            capacity,
+            seen: Vec::new(),
+            drained: Vec::new(),
+            next_id: 0,
+            now: 0,
+        })
+    }
+}
+
+impl<T: Tokenizer> LogDeduplicator<T> {
+    /// This deduplicator splitting lines into features with `tokenizer`,
+    /// such as [`WhitespaceWords`](crate::tokenize::WhitespaceWords) for
+    /// logs whose numbers matter.
+    pub fn with_tokenizer<U: Tokenizer>(self, tokenizer: U) -> LogDeduplicator<U> {
+        LogDeduplicator {
+            tokenizer,
+            max_distance: self.max_distance,
+            window: self.window,
+            capacity: self.capacity,
+            seen: self.seen,
+            drained: self.drained,
+            next_id: self.next_id,
+            now: self.now,
+        }
+    }
+
+    /// Largest number of differing SimHash bits between a line and one it
+    /// duplicates.
+    pub fn max_distance(&self) -> u32 {
+        self.max_distance
+    }
+
+    /// How long a line is remembered after it or a copy of it was last
+    /// seen.
+    pub fn window(&self) -> u64 {
+        self.window
+    }
+
+    /// Number of currently remembered lines.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Returns `true` if no line is remembered.
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// Classifies the next line, arriving at time `now`; a `now` earlier
+    /// than a previous one counts as that one. Among several remembered
+    /// lines within the tolerance, a duplicate counts against the closest,
+    /// the earliest on a tie.
+    pub fn check_at(&mut self, line: &[u8], now: u64) -> Decision {
+        self.expire(now);
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut simhash = SimHash::new(SIMHASH_BITS).expect("a positive multiple of 8 bits");
+        simhash.add_features(&self.tokenizer, line);
+        let simhash = simhash.finish();
+        let closest = self
+            .seen
+            .iter_mut()
+            .map(|seen| (hamming(&seen.simhash, &simhash), seen))
+            .filter(|(distance, _)| *distance <= self.max_distance)
+            .min_by_key(|(distance, seen)| (*distance, seen.id));
+        if let Some((_, seen)) = closest {
+            seen.last_seen = self.now;
+            seen.suppressed += 1;
+            return Decision::DuplicateOf(seen.id);
+        }
+
+        if self.seen.len() == self.capacity {
+            let stalest = (0..self.seen.len())
+                .min_by_key(|&i| (self.seen[i].last_seen, self.seen[i].id))
+                .expect("capacity is non-zero");
+            self.forget(stalest);
+        }
+        self.seen.push(Seen {
+            simhash,
+            id,
+            last_seen: self.now,
+            suppressed: 0,
+        });
+        Decision::Unique(id)
+    }
+
+    /// Advances the clock to `now` without checking a line and forgets the
+    /// lines not seen for a whole window by then; returns how many.
+    pub fn expire(&mut self, now: u64) -> usize {
+        self.now = self.now.max(now);
+        let mut expired = 0;
+        let mut i = 0;
+        while i < self.seen.len() {
+            if self.now - self.seen[i].last_seen >= self.window {
+                self.forget(i);
+                expired += 1;
+            } else {
+                i += 1;
+            }
+        }
+        expired
+    }
+
+    /// The ids of the lines with suppressed copies since the last call,
+    /// each with their number, in order of id; lines forgotten since are
+    /// included.
+    pub fn drain_suppressed(&mut self) -> Vec<(u64, u64)> {
+        let mut counts = core::mem::take(&mut self.drained);
+        for seen in &mut self.seen {
+            if seen.suppressed > 0 {
+                counts.push((seen.id, core::mem::take(&mut seen.suppressed)));
+            }
+        }
+        counts.sort_unstable();
+        counts
+    }
+
+    fn forget(&mut self, index: usize) {
+        let seen = self.seen.remove(index);
+        if seen.suppressed > 0 {
+            self.drained.push((seen.id, seen.suppressed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize::WhitespaceWords;
+
+    #[test]
+    fn test_window_slides_with_the_latest_copy() {
+        let mut logs = LogDeduplicator::new(0.1, 10, 100).unwrap();
+        let spam = b"WARN disk /dev/sda1 at 91% capacity";
+        assert_eq!(logs.check_at(spam, 0), Decision::Unique(0));
+        // Copies keep the line alive for another window each.
+        for (i, now) in [5, 12, 19, 28].into_iter().enumerate() {
+            assert_eq!(logs.check_at(spam, now), Decision::DuplicateOf(0), "{i}");
+        }
+        assert_eq!(logs.drain_suppressed(), [(0, 4)]);
+        assert!(logs.drain_suppressed().is_empty());
+        assert_eq!(logs.check_at(spam, 30), Decision::DuplicateOf(0));
+        // A pause of a whole window forgets it; its last count is kept.
+        assert_eq!(logs.expire(40), 1);
+        assert!(logs.is_empty());
+        assert_eq!(logs.check_at(spam, 41), Decision::Unique(6));
+        assert_eq!(logs.drain_suppressed(), [(0, 1)]);
+    }
+
+    #[test]
+    fn test_templates_match_and_different_messages_do_not() {
+        let mut logs = LogDeduplicator::new(0.125, 60, 100).unwrap();
+        let lines: [&[u8]; 5] = [
+            b"INFO user 1001 logged in from 10.0.0.1",
+            b"INFO user 2002 logged in from 10.0.0.9",
+            b"ERROR payment 77 declined: card expired",
+            b"ERROR payment 78 declined: card expired",
+            b"INFO user 1001 logged out",
+        ];
+        let decisions: Vec<Decision> = lines.iter().map(|line| logs.check_at(line, 1)).collect();
+        assert_eq!(
+            decisions,
+            [
+                Decision::Unique(0),
+                Decision::DuplicateOf(0),
+                Decision::Unique(2),
+                Decision::DuplicateOf(2),
+                Decision::Unique(4),
+            ]
+        );
+        assert_eq!(logs.max_distance(), 32);
+
+        // With numbers as features, other users are other messages.
+        let mut strict = LogDeduplicator::new(0.05, 60, 100)
+            .unwrap()
+            .with_tokenizer(WhitespaceWords);
+        assert_eq!(strict.check_at(lines[0], 1), Decision::Unique(0));
+        assert_eq!(strict.check_at(lines[1], 1), Decision::Unique(1));
+        assert_eq!(strict.check_at(lines[0], 1), Decision::DuplicateOf(0));
+    }
+
+    #[test]
+    fn test_capacity_forgets_the_stalest_line() {
+        let mut logs = LogDeduplicator::new(0.05, 1000, 2).unwrap();
+        assert_eq!(logs.check_at(b"alpha beta gamma", 0), Decision::Unique(0));
+        assert_eq!(logs.check_at(b"delta epsilon zeta", 1), Decision::Unique(1));
+        assert_eq!(
+            logs.check_at(b"alpha beta gamma", 2),
+            Decision::DuplicateOf(0)
+        );
+        assert_eq!(logs.check_at(b"eta theta iota", 3), Decision::Unique(3));
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs.check_at(b"delta epsilon zeta", 4), Decision::Unique(4));
+        assert_eq!(logs.check_at(b"alpha beta gamma", 5), Decision::Unique(5));
+        assert_eq!(logs.drain_suppressed(), [(0, 1)]);
+        assert!(LogDeduplicator::new(0.1, 10, 0).is_err());
+    }
+}
@@ -0,0 +1,545 @@
+//! A dictionary keyed by noisy identifiers.
+//!
+//! A [`FuzzyMap`] files every value under the digest of its key, so a
+//! reading that differs from the stored key only within the tolerance finds
+//! the same entry:
+//!
+//! ```rust
+//! use pensieve::TbfConfig;
+//! use pensieve::map::FuzzyMap;
+//!
+//! let mut devices = FuzzyMap::new(TbfConfig::new(0.125));
+//! devices.insert(&[0xF7; 16], "sensor A");
+//! let mut reading = [0xF7u8; 16];
+//! reading[2] ^= 0x10;
+//! assert_eq!(devices.get(&reading), Some(&"sensor A"));
+//! assert_eq!(devices.get(&[0x00; 16]), None);
+//! ```
+//!
+//! A reading whose noise happens to move a chunk across its threshold
+//! collapses differently. [`FuzzyMap::with_radius`] adds a fallback for
+//! those: a key without an entry of its own resolves to the stored key of
+//! the same length closest to it, if that is at most the radius bits away.
+//! The fallback scans every entry, so it suits maps of moderate size.
+//!
+//! A [`FuzzySet`] answers "have we seen something like this before?" the
+//! same way, and combines with other sets by fuzzy equality. A
+//! [`FuzzyCache`] bounds the entries and forgets the least recently used,
+//! so an expensive result computed for one input is reused for the
+//! near-identical ones that follow.
+
+use crate::dedup::Eviction;
+use crate::distance::hamming;
+use crate::retention::Retention;
+use crate::{Error, TbfConfig};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+
+/// A map from noisy byte keys to values.
+///
+/// Every entry keeps the key it was first inserted with; later keys that
+/// resolve to the entry replace its value but not its key.
+#[derive(Debug, Clone)]
+pub struct FuzzyMap<V> {
+    config: TbfConfig,
+    radius: u32,
+    entries: BTreeMap<Vec<u8>, (Vec<u8>, V)>, // Digest to key and value.
+}
+
+impl<V> FuzzyMap<V> {
+    /// An empty map resolving keys through the collapse under `config`
+    /// alone.
+    pub fn new(config: TbfConfig) -> Self {
+        Self {
+            config,
+            radius: 0,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Lets a key without an entry of its own fall back to the closest
+    /// stored key of the same length at most `radius` bits away; the
+    /// earlier in digest order wins a tie. A radius of 0 disables the
+    /// fallback.
+    pub fn with_radius(mut self, radius: u32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// The configuration keys are collapsed with.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// The radius of the fallback, 0 if there is none.
+    pub fn radius(&self) -> u32 {
+        self.radius
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the map holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Stores `value` under the entry `key` resolves to, returning the
+    /// value it replaces, or under a new entry for `key` if it resolves to
+    /// none.
+    pub fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        match self.resolve(key) {
+            Some(digest) => {
+                let (_, old) = self.entries.get_mut(&digest).expect("resolved");
+                Some(core::mem::replace(old, value))
+            }
+            None => {
+                let digest = self.config.collapse(key);
+                self.entries.insert(digest, (key.to_vec(), value));
+                None
+            }
+        }
+    }
+
+    /// The value of the entry `key` resolves to.
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    /// The stored key and value of the entry `key` resolves to.
+    pub fn get_key_value(&self, key: &[u8]) -> Option<(&[u8], &V)> {
+        let (stored, value) = &self.entries[&self.resolve(key)?];
+        Some((stored, value))
+    }
+
+    /// The value of the entry `key` resolves to, mutably.
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        let digest = self.resolve(key)?;
+        self.entries.get_mut(&digest).map(|(_, value)| value)
+    }
+
+    /// Returns `true` if `key` resolves to an entry.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.resolve(key).is_some()
+    }
+
+    /// Removes the entry `key` resolves to, returning its value.
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let digest = self.resolve(key)?;
+        self.entries.remove(&digest).map(|(_, value)| value)
+    }
+
+    /// The stored keys and their values, in digest order.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &V)> {
+        self.entries
+            .values()
+            .map(|(key, value)| (key.as_slice(), value))
+    }
+
+    /// The digest of the entry `key` resolves to.
+    fn resolve(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let digest = self.config.collapse(key);
+        if self.entries.contains_key(&digest) {
+            return Some(digest);
+        }
+        if self.radius == 0 {
+            return None;
+        }
+        self.entries
+            .iter()
+            .filter(|(_, (stored, _))| stored.len() == key.len())
+            .map(|(digest, (stored, _))| (hamming(key, stored), digest))
+            .filter(|&(distance, _)| distance <= self.radius)
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(_, digest)| digest.clone())
+    }
+}
+
+/// A set of noisy byte keys, each standing for every key resolving to it.
+///
+/// Every key a set holds is the representative of its entry: the first key
+/// inserted that resolved to none, as in [`FuzzyMap`].
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::map::FuzzySet;
+///
+/// let config = TbfConfig::new(0.125);
+/// let mut seen = FuzzySet::new(config);
+/// assert!(seen.insert(&[0xF7; 16]));
+/// assert!(!seen.insert(&[0xF6; 16]));
+/// assert!(seen.contains_similar(&[0xF3; 16]));
+///
+/// let mut other = FuzzySet::new(config);
+/// other.insert(&[0xFF; 16]);
+/// other.insert(&[0x00; 16]);
+/// assert_eq!(seen.union(&other).len(), 2);
+/// assert_eq!(seen.intersection(&other).iter().collect::<Vec<_>>(), [&[0xF7; 16][..]]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FuzzySet {
+    map: FuzzyMap<()>,
+}
+
+impl FuzzySet {
+    /// An empty set resolving keys through the collapse under `config`
+    /// alone.
+    pub fn new(config: TbfConfig) -> Self {
+        Self {
+            map: FuzzyMap::new(config),
+        }
+    }
+
+    /// Adds the fallback of [`FuzzyMap::with_radius`].
+    pub fn with_radius(self, radius: u32) -> Self {
+        Self {
+            map: self.map.with_radius(radius),
+        }
+    }
+
+    /// The configuration keys are collapsed with.
+    pub fn config(&self) -> &TbfConfig {
+        self.map.config()
+    }
+
+    /// The radius of the fallback, 0 if there is none.
+    pub fn radius(&self) -> u32 {
+        self.map.radius()
+    }
+
+    /// Number of representatives.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the set holds no keys.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Adds `key` as a representative unless it resolves to one already;
+    /// returns whether it was added.
+    pub fn insert(&mut self, key: &[u8]) -> bool {
+        if self.contains_similar(key) {
+            return false;
+        }
+        self.map.insert(key, ());
+        true
+    }
+
+    /// Returns `true` if `key` resolves to a representative.
+    pub fn contains_similar(&self, key: &[u8]) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// The representative `key` resolves to.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.map.get_key_value(key).map(|(stored, _)| stored)
+    }
+
+    /// Removes the representative `key` resolves to; returns whether there
+    /// was one.
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        self.map.remove(key).is_some()
+    }
+
+    /// The representatives, in digest order.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.map.iter().map(|(key, _)| key)
+    }
+
+    /// The representatives of this set, then those of `other` resolving to
+    /// none of them.
+    pub fn union(&self, other: &FuzzySet) -> FuzzySet {
+        let mut union = self.clone();
+        for key in other.iter() {
+            union.insert(key);
+        }
+        union
+    }
+
+    /// The representatives of this set that a representative of `other`
+    /// resolves to.
+    ///
+    /// Here and in [`FuzzySet::difference`], keys of `other` are resolved
+    /// the way this set resolves keys.
+    pub fn intersection(&self, other: &FuzzySet) -> FuzzySet {
+        let hit = self.hit_by(other);
+        self.filtered(|digest| hit.contains(digest))
+    }
+
+    /// The representatives of this set that no representative of `other`
+    /// resolves to.
+    pub fn difference(&self, other: &FuzzySet) -> FuzzySet {
+        let hit = self.hit_by(other);
+        self.filtered(|digest| !hit.contains(digest))
+    }
+
+    /// Digests of the entries the representatives of `other` resolve to.
+    fn hit_by(&self, other: &FuzzySet) -> BTreeSet<Vec<u8>> {
+        other
+            .iter()
+            .filter_map(|key| self.map.resolve(key))
+            .collect()
+    }
+
+    fn filtered(&self, mut keep: impl FnMut(&Vec<u8>) -> bool) -> FuzzySet {
+        let mut set = self.clone();
+        set.map.entries.retain(|digest, _| keep(digest));
+        set
+    }
+}
+
+/// A [`FuzzyMap`] of bounded size, evicting the least recently used entry.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::map::FuzzyCache;
+///
+/// let mut cache = FuzzyCache::new(TbfConfig::new(0.125), 2).unwrap();
+/// let mut computed = 0;
+/// for input in [[0xF7u8; 16], [0xF6; 16], [0x00; 16], [0xF7; 16]] {
+///     cache.get_or_insert_with(&input, || {
+///         computed += 1;
+///         input[0]
+///     });
+/// }
+/// assert_eq!(computed, 2);
+/// assert_eq!(cache.get(&[0xF3; 16]), Some(&0xF7));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FuzzyCache<V> {
+    map: FuzzyMap<(u64, V)>,         // Values with the id of their entry.
+    digests: BTreeMap<u64, Vec<u8>>, // Id -> digest of its entry.
+    retention: Retention,            // Eviction order of the ids.
+    capacity: usize,
+    next_id: u64,
+    evicted: u64, // Entries forgotten to stay within the capacity.
+}
+
+impl<V> FuzzyCache<V> {
+    /// An empty cache holding at most `capacity` entries, resolving keys
+    /// through the collapse under `config` alone.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `capacity` is zero.
+    pub fn new(config: TbfConfig, capacity: usize) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(Error::InvalidParameter(
+                "capacity must be at least one entry",
+            ));
+        }
+        Ok(Self {
+            map: FuzzyMap::new(config),
+            digests: BTreeMap::new(),
+            retention: Retention::new(Eviction::LeastRecentlyUsed),
+            capacity,
+            next_id: 0,
+            evicted: 0,
+        })
+    }
+
+    /// Adds the fallback of [`FuzzyMap::with_radius`].
+    pub fn with_radius(mut self, radius: u32) -> Self {
+        self.map = self.map.with_radius(radius);
+        self
+    }
+
+    /// The configuration keys are collapsed with.
+    pub fn config(&self) -> &TbfConfig {
+        self.map.config()
+    }
+
+    /// The radius of the fallback, 0 if there is none.
+    pub fn radius(&self) -> u32 {
+        self.map.radius()
+    }
+
+    /// Maximum number of entries.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the cache holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Number of entries forgotten to stay within the capacity.
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// The value of the entry `key` resolves to, which counts as a use.
+    pub fn get(&mut self, key: &[u8]) -> Option<&V> {
+        let digest = self.map.resolve(key)?;
+        let (id, value) = &self.map.entries[&digest].1;
+        self.retention.touch(*id);
+        Some(value)
+    }
+
+    /// The value of the entry `key` resolves to, without counting as a use.
+    pub fn peek(&self, key: &[u8]) -> Option<&V> {
+        self.map.get(key).map(|(_, value)| value)
+    }
+
+    /// Stores `value` under the entry `key` resolves to, returning the
+    /// value it replaces, or under a new entry for `key`, evicting the
+    /// least recently used entry if the cache is full. Either counts as a
+    /// use.
+    pub fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        if let Some(digest) = self.map.resolve(key) {
+            let (id, old) = &mut self.map.entries.get_mut(&digest).expect("resolved").1;
+            self.retention.touch(*id);
+            return Some(core::mem::replace(old, value));
+        }
+        if self.len() == self.capacity {
+            let victim = self.retention.victim().expect("capacity is non-zero");
+            self.retention.remove(victim);
+            let digest = self.digests.remove(&victim).expect("cached");
+            self.map.entries.remove(&digest);
+            self.evicted += 1;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.retention.insert(id);
+        self.digests.insert(id, self.map.config.collapse(key));
+        self.map.insert(key, (id, value));
+        None
+    }
+
+    /// The value of the entry `key` resolves to, computed by `compute` and
+    /// inserted first if there is none.
+    pub fn get_or_insert_with(&mut self, key: &[u8], compute: impl FnOnce() -> V) -> &V {
+        if self.map.resolve(key).is_none() {
+            self.insert(key, compute());
+        }
+        self.get(key).expect("just inserted")
+    }
+
+    /// Removes the entry `key` resolves to, returning its value.
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let (id, value) = self.map.remove(key)?;
+        self.retention.remove(id);
+        self.digests.remove(&id);
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Thresholding;
+
+    /// 16 bytes whose first 2-byte chunk holds `ones` set bits and whose
+    /// other chunks are all set.
+    fn key(ones: u32) -> [u8; 16] {
+        let mut key = [0xFFu8; 16];
+        let bits = (1u32 << ones) - 1;
+        key[..2].copy_from_slice(&(bits as u16).to_be_bytes());
+        key
+    }
+
+    #[test]
+    fn test_noisy_keys_share_an_entry() {
+        let mut map =
+            FuzzyMap::new(TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric));
+        assert_eq!(map.insert(&key(16), 1), None);
+        assert_eq!(map.insert(&key(14), 2), Some(1));
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get_key_value(&key(12)), Some((&key(16)[..], &2)));
+        *map.get_mut(&key(15)).unwrap() += 10;
+        assert_eq!(map.iter().collect::<Vec<_>>(), [(&key(16)[..], &12)]);
+
+        // Other chunk levels, or other lengths, are other entries.
+        assert_eq!(map.insert(&key(4), 3), None);
+        assert_eq!(map.insert(&key(16)[..8], 4), None);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.remove(&key(13)), Some(12));
+        assert!(!map.contains_key(&key(16)));
+        assert_eq!(map.get(&key(0)), Some(&3));
+    }
+
+    #[test]
+    fn test_radius_catches_keys_across_a_threshold() {
+        let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+        // Half the bits of the first chunk set: a level away from 9 set.
+        assert_ne!(config.collapse(&key(8)), config.collapse(&key(9)));
+
+        let mut exact = FuzzyMap::new(config);
+        exact.insert(&key(9), "stored");
+        assert_eq!(exact.get(&key(8)), None);
+
+        let mut fallback = FuzzyMap::new(config).with_radius(2);
+        fallback.insert(&key(9), "stored");
+        assert_eq!(fallback.radius(), 2);
+        assert_eq!(fallback.get(&key(8)), Some(&"stored"));
+        assert_eq!(fallback.get(&key(6)), None);
+        assert_eq!(fallback.get(&key(8)[..15]), None);
+        assert_eq!(fallback.insert(&key(7), "replaced"), Some("stored"));
+        assert_eq!(fallback.len(), 1);
+    }
+
+    #[test]
+    fn test_set_algebra_by_fuzzy_equality() {
+        let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+        let mut a = FuzzySet::new(config);
+        let mut b = FuzzySet::new(config);
+        for ones in [16, 4] {
+            assert!(a.insert(&key(ones)));
+        }
+        assert!(!a.insert(&key(15)));
+        assert!(b.insert(&key(14)));
+        assert!(b.insert(&[0; 16]));
+        assert_eq!(a.get(&key(13)), Some(&key(16)[..]));
+
+        let union = a.union(&b);
+        assert_eq!(union.len(), 3);
+        assert!(union.iter().any(|k| k == key(16)));
+        assert!(union.iter().all(|k| k != key(14)));
+
+        let common: Vec<_> = a.intersection(&b).iter().map(<[u8]>::to_vec).collect();
+        assert_eq!(common, [key(16).to_vec()]);
+        let only: Vec<_> = a.difference(&b).iter().map(<[u8]>::to_vec).collect();
+        assert_eq!(only, [key(4).to_vec()]);
+
+        assert!(a.remove(&key(12)));
+        assert!(!a.contains_similar(&key(16)));
+        assert_eq!(a.len(), 1);
+    }
+
+    #[test]
+    fn test_cache_evicts_the_least_recently_used() {
+        let mut cache = FuzzyCache::new(
+            TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric),
+            2,
+        )
+        .unwrap();
+        assert_eq!(cache.insert(&key(16), 'a'), None);
+        assert_eq!(cache.insert(&key(4), 'b'), None);
+        // A near key is a use of the first entry, so the second goes.
+        assert_eq!(cache.get(&key(14)), Some(&'a'));
+        assert_eq!(cache.insert(&[0; 16], 'c'), None);
+        assert_eq!((cache.len(), cache.evicted()), (2, 1));
+        assert_eq!(cache.peek(&key(4)), None);
+        assert_eq!(cache.peek(&key(15)), Some(&'a'));
+
+        // Peeking is no use: the first entry goes next.
+        assert_eq!(cache.insert(&key(2), 'e'), None);
+        assert_eq!(cache.peek(&key(16)), None);
+        assert_eq!(cache.remove(&[0; 16]), Some('c'));
+        assert_eq!(*cache.get_or_insert_with(&key(16), || 'f'), 'f');
+        assert_eq!(*cache.get_or_insert_with(&key(1), || 'g'), 'e');
+        assert_eq!(cache.len(), 2);
+
+        assert!(FuzzyCache::<()>::new(TbfConfig::default(), 0).is_err());
+    }
+}
@@ -0,0 +1,397 @@
+//! Merkle trees of collapsed blocks, for large inputs.
+//!
+//! A collapse of a whole disk image or firmware dump matches or not as a
+//! whole. A [`MerkleTree`] cuts the input into fixed-size blocks, collapses
+//! each, and hashes the collapses pairwise up to a root: the root is the
+//! fingerprint of the whole input, and equal subtrees mark regions that
+//! collapse alike. [`MerkleTree::similar_regions`] finds them by descending
+//! only into subtrees that differ, and a [`MerkleProof`] lets a holder of
+//! just the root check a single block without the rest of the input.
+//!
+//! Blocks are aligned to the start of the input, so an insertion or
+//! deletion makes every later block differ; see [`cdc`](crate::cdc) for
+//! chunking that survives shifts. At each level a node without a sibling
+//! moves up unchanged. An empty input is a single empty block.
+
+use crate::crypto::sha256::Sha256;
+use crate::{Error, TbfConfig};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Domain separation for the hashes of blocks and of inner nodes.
+const LEAF_INFO: &[u8] = b"pensieve merkle leaf";
+const NODE_INFO: &[u8] = b"pensieve merkle node";
+
+/// A Merkle tree over the collapsed blocks of an input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleTree {
+    block_len: usize,
+    config: TbfConfig,
+    len: usize,                 // Length of the input.
+    levels: Vec<Vec<[u8; 32]>>, // Node hashes by height: the blocks first, the root last.
+}
+
+impl MerkleTree {
+    /// The tree of `input` cut into blocks of `block_len` bytes (the last
+    /// one possibly shorter), each collapsed under `config`.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `block_len` is zero.
+    pub fn new(input: &[u8], block_len: usize, config: &TbfConfig) -> Result<Self, Error> {
+        check_block_len(block_len)?;
+        let leaves = if input.is_empty() {
+            vec![leaf(&[], config)]
+        } else {
+            input
+                .chunks(block_len)
+                .map(|block| leaf(block, config))
+                .collect()
+        };
+        Ok(Self::build(block_len, config, input.len(), leaves))
+    }
+
+    /// Like [`MerkleTree::new`], reading the input from `reader` one block
+    /// at a time.
+    #[cfg(feature = "std")]
+    pub fn from_reader(
+        mut reader: impl std::io::Read,
+        block_len: usize,
+        config: &TbfConfig,
+    ) -> std::io::Result<Self> {
+        check_block_len(block_len)?;
+        let mut leaves = Vec::new();
+        let mut len = 0;
+        let mut block = vec![0u8; block_len];
+        loop {
+            let mut filled = 0;
+            while filled < block_len {
+                match reader.read(&mut block[filled..]) {
+                    Ok(0) => break,
+                    Ok(read) => filled += read,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if filled > 0 || leaves.is_empty() {
+                leaves.push(leaf(&block[..filled], config));
+            }
+            len += filled;
+            if filled < block_len {
+                return Ok(Self::build(block_len, config, len, leaves));
+            }
+        }
+    }
+
+    fn build(block_len: usize, config: &TbfConfig, len: usize, leaves: Vec<[u8; 32]>) -> Self {
+        let mut levels = vec![leaves];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let parents = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node(left, right),
+                    [alone] => *alone,
+                    _ => unreachable!("chunks of two"),
+                })
+                .collect();
+            levels.push(parents);
+        }
+        Self {
+            block_len,
+            config: *config,
+            len,
+            levels,
+        }
+    }
+
+    /// The root hash, a fingerprint of the whole input.
+    pub fn root(&self) -> [u8; 32] {
+        self.levels.last().expect("at least one level")[0]
+    }
+
+    /// Length of the blocks, in bytes.
+    pub fn block_len(&self) -> usize {
+        self.block_len
+    }
+
+    /// Number of blocks; 1 for an empty input.
+    pub fn block_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Length of the input, in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the input was empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The byte range of block `index` of the input.
+    pub fn block_range(&self, index: usize) -> Range<usize> {
+        let start = (index * self.block_len).min(self.len);
+        start..(start + self.block_len).min(self.len)
+    }
+
+    /// The proof that block `index` belongs to the tree; `None` if there is
+    /// no such block.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.block_count() {
+            return None;
+        }
+        let mut siblings = Vec::with_capacity(self.levels.len() - 1);
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(position ^ 1) {
+                siblings.push(*sibling);
+            }
+            position /= 2;
+        }
+        Some(MerkleProof {
+            index,
+            block_count: self.block_count(),
+            siblings,
+        })
+    }
+
+    /// The byte ranges, at the same offsets in both inputs, whose blocks
+    /// collapse alike, with adjacent ranges merged. Takes time proportional
+    /// to the number of differing blocks times the height of the tree.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless both trees have the
+    /// same block length and configuration.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::merkle::MerkleTree;
+    ///
+    /// let config = TbfConfig::new(0.125);
+    /// let image: Vec<u8> = (0..1u32 << 20)
+    ///     .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8 & 0x11)
+    ///     .collect();
+    /// let mut patched = image.clone();
+    /// patched[300_000..302_000].fill(0);
+    /// patched[700_000] ^= 0x01; // Noise the collapse absorbs.
+    ///
+    /// let a = MerkleTree::new(&image, 4096, &config).unwrap();
+    /// let b = MerkleTree::new(&patched, 4096, &config).unwrap();
+    /// assert_ne!(a.root(), b.root());
+    /// assert_eq!(
+    ///     a.similar_regions(&b).unwrap(),
+    ///     [0..299_008, 303_104..1 << 20]
+    /// );
+    /// ```
+    pub fn similar_regions(&self, other: &Self) -> Result<Vec<Range<usize>>, Error> {
+        if self.block_len != other.block_len || self.config != other.config {
+            return Err(Error::InvalidParameter(
+                "trees differ in block length or configuration",
+            ));
+        }
+        let height = self.levels.len().min(other.levels.len()) - 1;
+        let mut blocks = Vec::new();
+        for index in 0..self.levels[height].len() {
+            self.walk(other, height, index, &mut blocks);
+        }
+
+        let mut regions: Vec<Range<usize>> = Vec::new();
+        for Range { start, end } in blocks {
+            let range = self.block_range(start).start..self.block_range(end - 1).end;
+            match regions.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => regions.push(range),
+            }
+        }
+        Ok(regions)
+    }
+
+    /// Collects into `blocks`, in order, the ranges of blocks under node
+    /// `index` at `height` that are equal in both trees.
+    fn walk(&self, other: &Self, height: usize, index: usize, blocks: &mut Vec<Range<usize>>) {
+        let (Some(a), Some(b)) = (
+            self.levels[height].get(index),
+            other.levels[height].get(index),
+        ) else {
+            return;
+        };
+        if a == b {
+            // Equal hashes cover the same blocks.
+            let first = index << height;
+            blocks.push(first..((index + 1) << height).min(self.block_count()));
+        } else if height > 0 {
+            self.walk(other, height - 1, 2 * index, blocks);
+            self.walk(other, height - 1, 2 * index + 1, blocks);
+        }
+    }
+}
+
+/// The hashes linking one block of a [`MerkleTree`] to its root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    index: usize,
+    block_count: usize,
+    siblings: Vec<[u8; 32]>, // From the block up; levels where it has none are skipped.
+}
+
+impl MerkleProof {
+    /// Index of the block the proof is for.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Whether `block` collapses under `config` like the block the proof is
+    /// for in the tree with `root`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::merkle::MerkleTree;
+    ///
+    /// let config = TbfConfig::new(0.125);
+    /// let input = [0x11u8; 10_000];
+    /// let tree = MerkleTree::new(&input, 1024, &config).unwrap();
+    /// let proof = tree.proof(3).unwrap();
+    /// let mut block = input[tree.block_range(3)].to_vec();
+    /// block[100] ^= 0x01;
+    /// assert!(proof.verify(&tree.root(), &block, &config));
+    /// assert!(!proof.verify(&tree.root(), &block[1..], &config));
+    /// ```
+    pub fn verify(&self, root: &[u8; 32], block: &[u8], config: &TbfConfig) -> bool {
+        if self.index >= self.block_count {
+            return false;
+        }
+        let mut hash = leaf(block, config);
+        let mut siblings = self.siblings.iter();
+        let (mut position, mut count) = (self.index, self.block_count);
+        while count > 1 {
+            if position % 2 == 1 {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                hash = node(sibling, &hash);
+            } else if position + 1 < count {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                hash = node(&hash, sibling);
+            }
+            position /= 2;
+            count = count.div_ceil(2);
+        }
+        siblings.next().is_none() && hash == *root
+    }
+}
+
+fn check_block_len(block_len: usize) -> Result<(), Error> {
+    if block_len == 0 {
+        return Err(Error::InvalidParameter("blocks must be at least one byte"));
+    }
+    Ok(())
+}
+
+/// The hash of the collapse of `block`.
+fn leaf(block: &[u8], config: &TbfConfig) -> [u8; 32] {
+    Sha256::digest(&[LEAF_INFO, &config.collapse(block)])
+}
+
+/// The hash of an inner node with children `left` and `right`.
+fn node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::digest(&[NODE_INFO, left, right])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    fn sparse(len: usize, seed: u64) -> Vec<u8> {
+        let mut input = vec![0u8; len];
+        seeded(seed)(&mut input);
+        input.iter_mut().for_each(|b| *b &= 0x11);
+        input
+    }
+
+    #[test]
+    fn test_shape_and_root() {
+        let config = TbfConfig::new(0.125);
+        let input = sparse(1000, 1);
+        let tree = MerkleTree::new(&input, 64, &config).unwrap();
+        assert_eq!(tree.block_count(), 16);
+        assert_eq!(tree.len(), 1000);
+        assert_eq!(tree.block_range(15), 960..1000);
+        let leaves: Vec<[u8; 32]> = input.chunks(64).map(|b| leaf(b, &config)).collect();
+        assert_eq!(tree.levels[0], leaves);
+        assert_eq!(tree.levels.len(), 5);
+
+        // Three blocks: the third moves up unchanged.
+        let three = MerkleTree::new(&input[..150], 64, &config).unwrap();
+        let [a, b, c] = [0..64, 64..128, 128..150].map(|r| leaf(&input[r], &config));
+        assert_eq!(three.root(), node(&node(&a, &b), &c));
+
+        let empty = MerkleTree::new(&[], 64, &config).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.block_count(), 1);
+        assert_eq!(empty.root(), leaf(&[], &config));
+        assert!(MerkleTree::new(&input, 0, &config).is_err());
+    }
+
+    #[test]
+    fn test_proofs() {
+        let config = TbfConfig::new(0.125);
+        for len in [1, 64, 65, 700, 1000] {
+            let input = sparse(len, 2);
+            let tree = MerkleTree::new(&input, 64, &config).unwrap();
+            let root = tree.root();
+            for index in 0..tree.block_count() {
+                let proof = tree.proof(index).unwrap();
+                assert_eq!(proof.index(), index);
+                let block = &input[tree.block_range(index)];
+                assert!(proof.verify(&root, block, &config), "{len} {index}");
+                assert!(!proof.verify(&root, &[0x11; 3], &config));
+            }
+            assert!(tree.proof(tree.block_count()).is_none());
+        }
+    }
+
+    #[test]
+    fn test_similar_regions() {
+        let config = TbfConfig::new(0.125);
+        let a = sparse(64 * 100, 3);
+        let mut b = a.clone();
+        b[64 * 10..64 * 12].fill(0xFF);
+        b[64 * 50..64 * 51].fill(0);
+        b.truncate(64 * 90 + 5);
+        let (ta, tb) = (
+            MerkleTree::new(&a, 64, &config).unwrap(),
+            MerkleTree::new(&b, 64, &config).unwrap(),
+        );
+        let expected = [0..640, 768..3200, 3264..5760];
+        assert_eq!(ta.similar_regions(&tb).unwrap(), expected);
+        assert_eq!(tb.similar_regions(&ta).unwrap(), expected);
+        assert_eq!(ta.similar_regions(&ta).unwrap(), vec![0..6400]);
+
+        let unrelated = MerkleTree::new(&sparse(6400, 4), 64, &config).unwrap();
+        // A block collapses like an unrelated one by chance 1 in 256.
+        let shared: usize = ta
+            .similar_regions(&unrelated)
+            .unwrap()
+            .iter()
+            .map(|range| range.len())
+            .sum();
+        assert!(shared <= 5 * 64);
+        let coarse = MerkleTree::new(&a, 128, &config).unwrap();
+        assert!(ta.similar_regions(&coarse).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_reader() {
+        let config = TbfConfig::new(0.125);
+        for len in [0, 63, 64, 1000] {
+            let input = sparse(len, 5);
+            let streamed = MerkleTree::from_reader(&input[..], 64, &config).unwrap();
+            assert_eq!(streamed, MerkleTree::new(&input, 64, &config).unwrap());
+        }
+    }
+}
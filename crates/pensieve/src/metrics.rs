@@ -0,0 +1,197 @@
+//! Observing throughput and match rates.
+//!
+//! A service embedding pensieve installs one [`Metrics`] recorder for the
+//! whole process with [`set_metrics`]; from then on the crate reports every
+//! collapse, the candidates every index query examines and every
+//! deduplication decision to it. Each method has an empty default, so a
+//! recorder implements only what it exports, and a process without one
+//! pays a single atomic load per event.
+//!
+//! ```rust
+//! use pensieve::TbfConfig;
+//! use pensieve::metrics::{self, Metrics};
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use std::time::Duration;
+//!
+//! #[derive(Default)]
+//! struct Throughput {
+//!     bytes: AtomicU64,
+//! }
+//!
+//! impl Metrics for Throughput {
+//!     fn collapsed(&self, bytes: usize, _chunks: usize, _elapsed: Duration) {
+//!         self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! static THROUGHPUT: Throughput = Throughput { bytes: AtomicU64::new(0) };
+//!
+//! # #[cfg(feature = "std")] {
+//! metrics::set_metrics(&THROUGHPUT).unwrap();
+//! TbfConfig::default().collapse(&[0xA5; 64]);
+//! assert!(THROUGHPUT.bytes.load(Ordering::Relaxed) >= 64);
+//! # }
+//! ```
+//!
+//! Without the `std` feature there is nowhere to install a recorder and
+//! nothing is reported; the trait stays available so recorders compile
+//! either way.
+
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use crate::Error;
+#[cfg(feature = "std")]
+use std::{sync::OnceLock, time::Instant};
+
+/// Receives the events of the crate. Recorders are called from whatever
+/// thread the event happens on and should be cheap.
+pub trait Metrics: Send + Sync {
+    /// A collapse of `bytes` bytes into `chunks` chunks took `elapsed`.
+    fn collapsed(&self, bytes: usize, chunks: usize, elapsed: Duration) {
+        let _ = (bytes, chunks, elapsed);
+    }
+
+    /// A query on the index named `index` (`"bk_tree"`, `"lsh"`,
+    /// `"stored_lsh"` or `"cascade"`) compared the query with `count`
+    /// stored keys.
+    fn candidates_examined(&self, index: &'static str, count: usize) {
+        let _ = (index, count);
+    }
+
+    /// A deduplicator classified a record as a duplicate or as unique.
+    fn decided(&self, duplicate: bool) {
+        let _ = duplicate;
+    }
+}
+
+#[cfg(feature = "std")]
+static METRICS: OnceLock<&'static dyn Metrics> = OnceLock::new();
+
+/// Installs the recorder of this process.
+///
+/// Fails with [`Error::InvalidParameter`] if one is installed already; it
+/// cannot be replaced.
+#[cfg(feature = "std")]
+pub fn set_metrics(metrics: &'static dyn Metrics) -> Result<(), Error> {
+    METRICS
+        .set(metrics)
+        .map_err(|_| Error::InvalidParameter("metrics are already installed"))
+}
+
+/// The installed recorder, if any.
+#[cfg(feature = "std")]
+pub fn metrics() -> Option<&'static dyn Metrics> {
+    METRICS.get().copied()
+}
+
+/// Reports a collapse when dropped; see [`time_collapse`].
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) struct CollapseTimer {
+    #[cfg(feature = "std")]
+    started: Option<(&'static dyn Metrics, Instant, usize, usize)>,
+}
+
+/// Starts timing a collapse of `bytes` bytes into `chunks()` chunks, which
+/// is only counted when a recorder is installed.
+#[cfg_attr(not(feature = "std"), allow(unused_variables))]
+pub(crate) fn time_collapse(bytes: usize, chunks: impl FnOnce() -> usize) -> CollapseTimer {
+    CollapseTimer {
+        #[cfg(feature = "std")]
+        started: metrics().map(|m| (m, Instant::now(), bytes, chunks())),
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for CollapseTimer {
+    fn drop(&mut self) {
+        if let Some((metrics, start, bytes, chunks)) = self.started {
+            metrics.collapsed(bytes, chunks, start.elapsed());
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "std"), allow(unused_variables, dead_code))]
+pub(crate) fn candidates_examined(index: &'static str, count: usize) {
+    #[cfg(feature = "std")]
+    if let Some(metrics) = metrics() {
+        metrics.candidates_examined(index, count);
+    }
+}
+
+#[cfg_attr(not(feature = "std"), allow(unused_variables, dead_code))]
+pub(crate) fn decided(duplicate: bool) {
+    #[cfg(feature = "std")]
+    if let Some(metrics) = metrics() {
+        metrics.decided(duplicate);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::TbfConfig;
+    use crate::dedup::{Deduplicator, Eviction};
+    use crate::index::BkTree;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Tests share the process and may run concurrently, so they only
+    /// look at lower bounds of what was recorded.
+    #[derive(Default)]
+    struct Counts {
+        bytes: AtomicUsize,
+        chunks: AtomicUsize,
+        candidates: AtomicUsize,
+        duplicates: AtomicUsize,
+        uniques: AtomicUsize,
+    }
+
+    impl Metrics for Counts {
+        fn collapsed(&self, bytes: usize, chunks: usize, _: Duration) {
+            self.bytes.fetch_add(bytes, Ordering::SeqCst);
+            self.chunks.fetch_add(chunks, Ordering::SeqCst);
+        }
+
+        fn candidates_examined(&self, _: &'static str, count: usize) {
+            self.candidates.fetch_add(count, Ordering::SeqCst);
+        }
+
+        fn decided(&self, duplicate: bool) {
+            let counter = if duplicate {
+                &self.duplicates
+            } else {
+                &self.uniques
+            };
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_installed_metrics_see_every_kind_of_event() {
+        static COUNTS: OnceLock<Counts> = OnceLock::new();
+        let counts = COUNTS.get_or_init(Counts::default);
+        set_metrics(counts).unwrap();
+        assert!(set_metrics(counts).is_err());
+        let read = |counter: &AtomicUsize| counter.load(Ordering::SeqCst);
+
+        let (bytes, chunks) = (read(&counts.bytes), read(&counts.chunks));
+        TbfConfig::default().collapse(&[0xA5; 4096]);
+        assert!(read(&counts.bytes) >= bytes + 4096);
+        assert!(read(&counts.chunks) >= chunks + 8);
+
+        let mut tree = BkTree::new();
+        for byte in 0..32u8 {
+            tree.insert(&[byte; 4], byte).unwrap();
+        }
+        let candidates = read(&counts.candidates);
+        tree.query_within(&[0; 4], 0).unwrap();
+        assert!(read(&counts.candidates) > candidates);
+
+        let (duplicates, uniques) = (read(&counts.duplicates), read(&counts.uniques));
+        let mut dedup = Deduplicator::new(TbfConfig::default(), 8, Eviction::Oldest).unwrap();
+        dedup.check(&[0xFF; 16]);
+        dedup.check(&[0xFF; 16]);
+        assert!(read(&counts.duplicates) > duplicates);
+        assert!(read(&counts.uniques) > uniques);
+    }
+}
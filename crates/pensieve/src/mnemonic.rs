@@ -0,0 +1,511 @@
+//! BIP39 mnemonics and fuzzy recovery of mistyped phrases.
+//!
+//! A BIP39 phrase encodes 128–256 bits of entropy as 12–24 words of 11 bits
+//! each, the trailing bits being a SHA-256 checksum. [`Mnemonic`] converts
+//! between phrases, entropy and the 64-byte wallet seed.
+//!
+//! Unlike the biometric and PUF readings elsewhere in the crate, a phrase has
+//! no slack: a single wrong bit is a different wallet. Its only redundancy is
+//! the 4–8 bit checksum, so [`Mnemonic::recover`] performs bounded-distance
+//! list decoding instead of collapsing: it tries every combination of at most
+//! `max_corrections` corrections, where a correction either replaces a word
+//! with a near miss (a word sharing its first three letters, or one edit
+//! away) or swaps two words, and keeps the candidates whose checksum
+//! verifies, fewest corrections first. With 12 words about one wrong
+//! candidate in 16 passes the checksum, so several results are normal; tell
+//! them apart by deriving a known address from each.
+//!
+//! A word typed as `?` is an erasure and may be any of the 2048 words.
+
+use crate::crypto::hmac::pbkdf2_sha512;
+use crate::crypto::sha256::Sha256;
+use crate::error::Error;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+mod wordlist;
+
+use wordlist::WORDS;
+
+/// PBKDF2 iterations for seed derivation, fixed by BIP39.
+const SEED_ROUNDS: u32 = 2048;
+/// Token marking a word as unknown.
+const ERASURE: &str = "?";
+
+/// A BIP39 mnemonic with a valid checksum.
+///
+/// `Debug` only prints the word count; use `Display` to show the phrase.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Mnemonic {
+    indices: Vec<u16>, // Wordlist index of every word, in order.
+}
+
+impl Mnemonic {
+    /// Encodes 16, 20, 24, 28 or 32 bytes of entropy as 12 to 24 words.
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, Error> {
+        if !matches!(entropy.len(), 16 | 20 | 24 | 28 | 32) {
+            return Err(Error::InvalidParameter(
+                "entropy must be 16, 20, 24, 28 or 32 bytes",
+            ));
+        }
+        let mut packed = [0u8; 33];
+        packed[..entropy.len()].copy_from_slice(entropy);
+        packed[entropy.len()] = Sha256::digest(&[entropy])[0];
+
+        let word_count = entropy.len() * 3 / 4;
+        let indices = (0..word_count)
+            .map(|word| {
+                (0..11).fold(0u16, |acc, bit| {
+                    let i = 11 * word + bit;
+                    acc << 1 | u16::from(packed[i / 8] >> (7 - i % 8) & 1)
+                })
+            })
+            .collect();
+        Ok(Self { indices })
+    }
+
+    /// Parses a phrase of whitespace-separated words.
+    ///
+    /// Words are matched case-insensitively and may be abbreviated to any
+    /// unambiguous prefix of at least four letters, as BIP39 permits.
+    pub fn parse(phrase: &str) -> Result<Self, Error> {
+        let indices = phrase
+            .split_whitespace()
+            .enumerate()
+            .map(|(position, word)| lookup(word).ok_or(Error::UnknownWord { position }))
+            .collect::<Result<Vec<_>, _>>()?;
+        check_word_count(indices.len())?;
+        if !checksum_matches(&indices) {
+            return Err(Error::InvalidChecksum);
+        }
+        Ok(Self { indices })
+    }
+
+    /// Number of words.
+    pub fn word_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// The words, in order.
+    pub fn words(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.indices.iter().map(|&i| WORDS[usize::from(i)])
+    }
+
+    /// The encoded entropy (without the checksum).
+    pub fn entropy(&self) -> Vec<u8> {
+        let (packed, entropy_len) = pack(&self.indices);
+        packed[..entropy_len].to_vec()
+    }
+
+    /// Derives the 64-byte BIP39 seed, PBKDF2-HMAC-SHA512 over the phrase
+    /// with salt `"mnemonic" || passphrase`.
+    ///
+    /// BIP39 requires the passphrase in Unicode NFKD form; this function uses
+    /// it as given, which only matters for non-ASCII passphrases.
+    pub fn to_seed(&self, passphrase: &str) -> [u8; 64] {
+        let phrase = self.words().collect::<Vec<_>>().join(" ");
+        let mut salt = String::from("mnemonic");
+        salt.push_str(passphrase);
+        let mut seed = [0u8; 64];
+        pbkdf2_sha512(phrase.as_bytes(), salt.as_bytes(), SEED_ROUNDS, &mut seed);
+        seed
+    }
+
+    /// Recovers the mnemonics a mistyped `phrase` may have been, using at
+    /// most `max_corrections` replacements and swaps.
+    ///
+    /// Returns every checksum-valid candidate that needs the fewest
+    /// corrections (a valid phrase is returned unchanged), or
+    /// [`Error::ReconstructionFailed`] if none is within reach. The search
+    /// grows quickly with `max_corrections`, and erasures cost 2048
+    /// candidates each.
+    pub fn recover(phrase: &str, max_corrections: usize) -> Result<Vec<Recovery>, Error> {
+        let slots: Vec<Slot> = phrase.split_whitespace().map(Slot::new).collect();
+        check_word_count(slots.len())?;
+
+        let mut search = Search {
+            current: slots.iter().map(|slot| slot.typed.unwrap_or(0)).collect(),
+            locked: alloc::vec![false; slots.len()],
+            corrections: Vec::new(),
+            found: Vec::new(),
+            slots,
+        };
+        for budget in 0..=max_corrections {
+            search.swaps(0, budget);
+            if !search.found.is_empty() {
+                return Ok(search.found);
+            }
+        }
+        Err(Error::ReconstructionFailed)
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, word) in self.words().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(word)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mnemonic({} words)", self.indices.len())
+    }
+}
+
+/// One change applied to a typed phrase during recovery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Correction {
+    /// The word at `position` (0-based) was replaced by `word`.
+    Replaced {
+        /// Position of the replaced word.
+        position: usize,
+        /// The word put in its place.
+        word: &'static str,
+    },
+    /// The words at two positions were swapped back.
+    Swapped {
+        /// The earlier position.
+        first: usize,
+        /// The later position.
+        second: usize,
+    },
+}
+
+/// A candidate produced by [`Mnemonic::recover`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recovery {
+    /// The recovered, checksum-valid mnemonic.
+    pub mnemonic: Mnemonic,
+    /// The corrections turning the typed phrase into `mnemonic`, by position.
+    pub corrections: Vec<Correction>,
+}
+
+/// What recovery knows about one typed word.
+struct Slot {
+    typed: Option<u16>,     // The typed word, if it is one.
+    alternatives: Vec<u16>, // Near misses a replacement may pick.
+}
+
+impl Slot {
+    fn new(token: &str) -> Self {
+        if token == ERASURE {
+            return Self::erasure();
+        }
+        let typed = lookup(token);
+        let token = token.to_ascii_lowercase();
+        let alternatives: Vec<u16> = (0..WORDS.len() as u16)
+            .filter(|&i| Some(i) != typed && is_near_miss(&token, WORDS[usize::from(i)]))
+            .collect();
+        if typed.is_none() && alternatives.is_empty() {
+            // Too garbled to guess from: any word will do.
+            return Self::erasure();
+        }
+        Self {
+            typed,
+            alternatives,
+        }
+    }
+
+    fn erasure() -> Self {
+        Self {
+            typed: None,
+            alternatives: (0..WORDS.len() as u16).collect(),
+        }
+    }
+}
+
+/// Depth-first enumeration of correction sets of an exact size.
+struct Search {
+    slots: Vec<Slot>,
+    current: Vec<u16>,            // Candidate being built.
+    locked: Vec<bool>,            // Positions fixed by a swap.
+    corrections: Vec<Correction>, // Corrections applied to `current`.
+    found: Vec<Recovery>,         // Valid candidates so far.
+}
+
+impl Search {
+    /// Applies swaps of pairs starting at `from` or later, then spends the
+    /// rest of the budget on replacements.
+    fn swaps(&mut self, from: usize, budget: usize) {
+        self.replacements(0, budget);
+        if budget == 0 {
+            return;
+        }
+        let n = self.slots.len();
+        for first in from..n {
+            for second in first + 1..n {
+                let (Some(a), Some(b)) = (self.slots[first].typed, self.slots[second].typed) else {
+                    continue;
+                };
+                if a == b || self.locked[first] || self.locked[second] {
+                    continue;
+                }
+                self.current[first] = b;
+                self.current[second] = a;
+                self.locked[first] = true;
+                self.locked[second] = true;
+                self.corrections.push(Correction::Swapped { first, second });
+                self.swaps(first + 1, budget - 1);
+                self.corrections.pop();
+                self.locked[first] = false;
+                self.locked[second] = false;
+                self.current[first] = a;
+                self.current[second] = b;
+            }
+        }
+    }
+
+    /// Replaces exactly `budget` of the unlocked words at `position` or later.
+    fn replacements(&mut self, position: usize, budget: usize) {
+        if position == self.slots.len() {
+            if budget == 0 && checksum_matches(&self.current) {
+                self.record();
+            }
+            return;
+        }
+        if budget > self.slots.len() - position {
+            return;
+        }
+        if self.locked[position] {
+            return self.replacements(position + 1, budget);
+        }
+        if let Some(typed) = self.slots[position].typed {
+            self.current[position] = typed;
+            self.replacements(position + 1, budget);
+        }
+        if budget > 0 {
+            for k in 0..self.slots[position].alternatives.len() {
+                let word = self.slots[position].alternatives[k];
+                self.current[position] = word;
+                self.corrections.push(Correction::Replaced {
+                    position,
+                    word: WORDS[usize::from(word)],
+                });
+                self.replacements(position + 1, budget - 1);
+                self.corrections.pop();
+            }
+        }
+    }
+
+    fn record(&mut self) {
+        // Different correction sets of the same size can reach one phrase.
+        if self
+            .found
+            .iter()
+            .any(|r| r.mnemonic.indices == self.current)
+        {
+            return;
+        }
+        let mut corrections = self.corrections.clone();
+        corrections.sort_by_key(|c| match *c {
+            Correction::Replaced { position, .. } => position,
+            Correction::Swapped { first, .. } => first,
+        });
+        self.found.push(Recovery {
+            mnemonic: Mnemonic {
+                indices: self.current.clone(),
+            },
+            corrections,
+        });
+    }
+}
+
+/// Finds a word by exact match or unambiguous prefix of at least four letters.
+fn lookup(word: &str) -> Option<u16> {
+    let word = word.to_ascii_lowercase();
+    if let Ok(i) = WORDS.binary_search(&word.as_str()) {
+        return Some(i as u16);
+    }
+    if word.len() < 4 {
+        return None;
+    }
+    // Four letters already identify a word, so a longer prefix has at most one match.
+    let i = WORDS.partition_point(|w| *w < word.as_str());
+    WORDS
+        .get(i)
+        .filter(|w| w.starts_with(word.as_str()))
+        .map(|_| i as u16)
+}
+
+/// Whether `candidate` is a plausible intended word for the typed `token`.
+fn is_near_miss(token: &str, candidate: &str) -> bool {
+    let shares_prefix = token.len() >= 3 && candidate.get(..3) == token.get(..3);
+    shares_prefix || edit_distance(token.as_bytes(), candidate.as_bytes()) <= 1
+}
+
+/// Optimal string alignment distance (Levenshtein plus adjacent transpositions).
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let width = b.len() + 1;
+    let mut d = alloc::vec![0usize; (a.len() + 1) * width];
+    for i in 0..=a.len() {
+        for j in 0..=b.len() {
+            d[i * width + j] = if i == 0 || j == 0 {
+                i + j
+            } else {
+                let cost = usize::from(a[i - 1] != b[j - 1]);
+                let mut best = (d[(i - 1) * width + j] + 1)
+                    .min(d[i * width + j - 1] + 1)
+                    .min(d[(i - 1) * width + j - 1] + cost);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    best = best.min(d[(i - 2) * width + j - 2] + 1);
+                }
+                best
+            };
+        }
+    }
+    d[a.len() * width + b.len()]
+}
+
+fn check_word_count(count: usize) -> Result<(), Error> {
+    if matches!(count, 12 | 15 | 18 | 21 | 24) {
+        Ok(())
+    } else {
+        Err(Error::InvalidParameter(
+            "a mnemonic has 12, 15, 18, 21 or 24 words",
+        ))
+    }
+}
+
+/// Packs word indices into bytes, returning them with the entropy length.
+fn pack(indices: &[u16]) -> ([u8; 33], usize) {
+    let mut packed = [0u8; 33];
+    for (word, &index) in indices.iter().enumerate() {
+        for bit in 0..11 {
+            let i = 11 * word + bit;
+            packed[i / 8] |= ((index >> (10 - bit)) as u8 & 1) << (7 - i % 8);
+        }
+    }
+    (packed, indices.len() * 4 / 3)
+}
+
+fn checksum_matches(indices: &[u16]) -> bool {
+    let (packed, entropy_len) = pack(indices);
+    let checksum_bits = indices.len() / 3;
+    let expected = Sha256::digest(&[&packed[..entropy_len]])[0] >> (8 - checksum_bits);
+    packed[entropy_len] >> (8 - checksum_bits) == expected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::tests::hex;
+
+    const ZEROS: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const SEVENS: &str =
+        "legal winner thank year wave sausage worth useful legal winner thank yellow";
+
+    #[test]
+    fn test_wordlist_sorted_with_unique_prefixes() {
+        // `lookup` relies on both properties.
+        assert!(WORDS.windows(2).all(|pair| pair[0] < pair[1]));
+        let prefix = |w: &'static str| &w[..w.len().min(4)];
+        assert!(
+            WORDS
+                .windows(2)
+                .all(|pair| prefix(pair[0]) != prefix(pair[1]))
+        );
+    }
+
+    #[test]
+    fn test_bip39_vectors() {
+        for (entropy, phrase) in [
+            ("00000000000000000000000000000000", ZEROS),
+            ("7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f", SEVENS),
+            (
+                "6610b25967cdcca9d59875f5cb50b0ea75433311869e930b",
+                "gravity machine north sort system female filter attitude volume fold club stay feature office ecology stable narrow fog",
+            ),
+            (
+                "68a79eaca2324873eacc50cb9c6eca8cc68ea5d936f98787c60c7ebc74e6ce7c",
+                "hamster diagram private dutch cause delay private meat slide toddler razor book happy fancy gospel tennis maple dilemma loan word shrug inflict delay length",
+            ),
+        ] {
+            let mnemonic = Mnemonic::from_entropy(&hex(entropy)).unwrap();
+            assert_eq!(mnemonic.to_string(), phrase);
+            assert_eq!(Mnemonic::parse(phrase).unwrap(), mnemonic);
+            assert_eq!(mnemonic.entropy(), hex(entropy));
+        }
+    }
+
+    #[test]
+    fn test_seed_vector() {
+        let seed = Mnemonic::parse(ZEROS).unwrap().to_seed("TREZOR");
+        assert_eq!(
+            seed.to_vec(),
+            hex(concat!(
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e5349553",
+                "1f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_and_abbreviations() {
+        assert_eq!(
+            Mnemonic::parse(&SEVENS.replace("wave", "wavy")),
+            Err(Error::UnknownWord { position: 4 })
+        );
+        assert_eq!(
+            Mnemonic::parse(&SEVENS.replace("yellow", "year")),
+            Err(Error::InvalidChecksum)
+        );
+        let abbreviated = "LEGA winn than year wave saus wort usef lega winn than yell";
+        assert_eq!(Mnemonic::parse(abbreviated).unwrap().to_string(), SEVENS);
+        assert!(Mnemonic::parse("abandon about").is_err());
+    }
+
+    #[test]
+    fn test_recover_valid_phrase_unchanged() {
+        let recovered = Mnemonic::recover(SEVENS, 2).unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert!(recovered[0].corrections.is_empty());
+    }
+
+    #[test]
+    fn test_recover_near_miss_and_typo() {
+        let expected = Mnemonic::parse(SEVENS).unwrap();
+        for (typed, position, word) in [
+            (SEVENS.replacen("winner", "winer", 1), 1, "winner"),
+            (SEVENS.replacen("sausage", "sauce", 1), 5, "sausage"),
+        ] {
+            let recovered = Mnemonic::recover(&typed, 1).unwrap();
+            let hit = recovered.iter().find(|r| r.mnemonic == expected).unwrap();
+            assert_eq!(hit.corrections, [Correction::Replaced { position, word }]);
+        }
+    }
+
+    #[test]
+    fn test_recover_swap_and_erasure() {
+        let expected = Mnemonic::parse(SEVENS).unwrap();
+        let swapped = "legal winner thank year sausage wave worth useful legal winner thank yellow";
+        let recovered = Mnemonic::recover(swapped, 1).unwrap();
+        assert!(recovered.iter().any(|r| r.mnemonic == expected
+            && r.corrections
+                == [Correction::Swapped {
+                    first: 4,
+                    second: 5
+                }]));
+
+        let erased = SEVENS.replacen("worth", "?", 1);
+        let recovered = Mnemonic::recover(&erased, 1).unwrap();
+        assert!(recovered.iter().any(|r| r.mnemonic == expected));
+    }
+
+    #[test]
+    fn test_recover_gives_up_beyond_budget() {
+        let typed = SEVENS
+            .replacen("legal", "lethal", 1)
+            .replacen("thank", "thick", 1);
+        assert_eq!(
+            Mnemonic::recover(&typed, 0),
+            Err(Error::ReconstructionFailed)
+        );
+    }
+}
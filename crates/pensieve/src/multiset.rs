@@ -0,0 +1,171 @@
+//! Collapsing unordered collections of elements.
+//!
+//! The collapse counts set bits by position, which is the wrong model for
+//! a bag of features or the minutiae of a fingerprint: the same elements in
+//! another order give another input, and a missing element shifts every
+//! later one. A [`MultisetCollapse`] first turns the collection into a
+//! bitmap of fixed length, in which every element sets one bit chosen by
+//! hashing it, and collapses the bitmap. The bitmap does not depend on the
+//! order of the elements, and each missing or extra element changes at
+//! most one of its bits, so a few of them act like the bit errors the
+//! collapse already tolerates. A repeated element sets another bit for
+//! every copy, so multiplicities count.
+//!
+//! Elements that hash to the same bit count once. Size the bitmap so the
+//! chunks land near their thresholds: with the default one-sided
+//! thresholding, a chunk is set once the tolerance of its bits are, so
+//! about `1 / tolerance` bits per expected element, eight at 12.5%, puts
+//! them there.
+//!
+//! Element hashing is the fast non-cryptographic hash of
+//! [`SimHash`](crate::simhash::SimHash), and the chosen bits depend on the
+//! elements, so this is meant for public data.
+
+use crate::simhash::{feature_hash, mix};
+use crate::{Error, TbfConfig};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// A collapse of collections whose order does not matter.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::multiset::MultisetCollapse;
+///
+/// let minutiae: Vec<[u8; 3]> = (0..40u8).map(|i| [i, i.wrapping_mul(37), 1]).collect();
+/// let mut shuffled = minutiae.clone();
+/// shuffled.reverse();
+///
+/// let multiset = MultisetCollapse::new(TbfConfig::new(0.125), 8).unwrap();
+/// assert_eq!(multiset.collapse(&minutiae), multiset.collapse(&shuffled));
+/// // One element more changes a single bit of the bitmap.
+/// shuffled.push([99, 99, 1]);
+/// let distance = pensieve::distance::hamming(
+///     &multiset.bitmap(&minutiae),
+///     &multiset.bitmap(&shuffled),
+/// );
+/// assert!(distance <= 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MultisetCollapse {
+    config: TbfConfig,
+    len: usize, // Bitmap bytes.
+}
+
+impl MultisetCollapse {
+    /// Collapses collections through a bitmap of `len` bytes under
+    /// `config`.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `len` is zero.
+    pub fn new(config: TbfConfig, len: usize) -> Result<Self, Error> {
+        if len == 0 {
+            return Err(Error::InvalidParameter("bitmap must not be empty"));
+        }
+        Ok(Self { config, len })
+    }
+
+    /// The configuration bitmaps are collapsed under.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// Length of the bitmap, and so of the digest, in bytes.
+    pub fn bitmap_len(&self) -> usize {
+        self.len
+    }
+
+    /// The bitmap of `elements`, with the bit of every copy of every element
+    /// set, MSB-first.
+    pub fn bitmap<T: AsRef<[u8]>>(&self, elements: &[T]) -> Vec<u8> {
+        let mut copies: BTreeMap<&[u8], u64> = BTreeMap::new();
+        let mut bitmap = alloc::vec![0u8; self.len];
+        let bits = (self.len * 8) as u64;
+        for element in elements {
+            let copy = copies.entry(element.as_ref()).or_default();
+            let bit = (mix(feature_hash(element.as_ref()).wrapping_add(*copy)) % bits) as usize;
+            bitmap[bit / 8] |= 0x80 >> (bit % 8);
+            *copy += 1;
+        }
+        bitmap
+    }
+
+    /// The digest of `elements`, the same for every order of them.
+    pub fn collapse<T: AsRef<[u8]>>(&self, elements: &[T]) -> Vec<u8> {
+        self.config.collapse(&self.bitmap(elements))
+    }
+
+    /// How many elements can be added to or removed from `elements`, in any
+    /// combination, without changing the digest; `u32::MAX` if none would.
+    ///
+    /// Every change moves one chunk's popcount by at most one, so this is
+    /// one less than the smallest margin of
+    /// [`TbfConfig::collapse_with_margins`]. Under
+    /// [`Thresholding::Mean`](crate::Thresholding::Mean) it assumes the
+    /// other chunks unchanged, as the margins do.
+    pub fn tolerated<T: AsRef<[u8]>>(&self, elements: &[T]) -> u32 {
+        let (_, margins) = self.config.collapse_with_margins(&self.bitmap(elements));
+        match margins.into_iter().min() {
+            Some(u32::MAX) | None => u32::MAX,
+            Some(margin) => margin - 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use crate::{BitOrder, Thresholding};
+
+    fn elements(count: usize, seed: u64) -> Vec<[u8; 4]> {
+        let mut fill = seeded(seed);
+        (0..count)
+            .map(|_| {
+                let mut element = [0u8; 4];
+                fill(&mut element);
+                element
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_order_does_not_matter_but_multiplicity_does() {
+        let configs = [
+            TbfConfig::new(0.125),
+            TbfConfig::new(0.2)
+                .with_bit_order(BitOrder::LsbFirst)
+                .with_thresholding(Thresholding::Symmetric),
+        ];
+        for config in configs {
+            let multiset = MultisetCollapse::new(config, 16).unwrap();
+            let bag = elements(80, 424);
+            let mut reordered = bag.clone();
+            reordered.rotate_left(17);
+            reordered.swap(0, 50);
+            assert_eq!(multiset.bitmap(&bag), multiset.bitmap(&reordered));
+            assert_eq!(multiset.collapse(&bag), multiset.collapse(&reordered));
+            assert_eq!(multiset.collapse(&bag).len(), multiset.bitmap_len());
+
+            let twice: Vec<[u8; 4]> = bag.iter().chain(&bag).copied().collect();
+            assert_ne!(multiset.bitmap(&bag), multiset.bitmap(&twice));
+        }
+        assert!(MultisetCollapse::new(TbfConfig::default(), 0).is_err());
+    }
+
+    #[test]
+    fn test_tolerated_changes_keep_the_digest() {
+        let config = TbfConfig::new(0.05);
+        let multiset = MultisetCollapse::new(config, 32).unwrap();
+        let bag = elements(120, 1);
+        let tolerated = multiset.tolerated(&bag) as usize;
+        assert!(tolerated > 1 && tolerated < 120, "{tolerated}");
+
+        let removed = tolerated / 2;
+        let extra = elements(tolerated - removed, 2);
+        let changed: Vec<[u8; 4]> = bag[removed..].iter().chain(&extra).copied().collect();
+        assert_eq!(multiset.collapse(&bag), multiset.collapse(&changed));
+        let empty: [&[u8]; 0] = [];
+        assert_eq!(multiset.bitmap(&empty), alloc::vec![0u8; 32]);
+    }
+}
@@ -0,0 +1,623 @@
+//! Agreeing on collapse parameters between two parties.
+//!
+//! Two parties comparing digests must collapse under the same
+//! configuration, and a single differing parameter makes every digest
+//! differ without either side noticing. Before exchanging digests, one
+//! party sends a [`ParamOffer`] listing what it accepts: its algorithm
+//! version, a range of tolerances and, in order of preference, bit orders,
+//! thresholding rules and output transforms. The other party answers with
+//! a [`ParamAccept`] naming one choice of each that both accept, from which
+//! both sides build the same [`TbfConfig`]:
+//!
+//! ```rust
+//! use pensieve::negotiate::{ParamAccept, ParamOffer};
+//! use pensieve::{TbfConfig, Thresholding};
+//!
+//! // Alice prefers symmetric thresholds but accepts some variation.
+//! let alice = ParamOffer::new(&TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric))
+//!     .with_tolerance_range(0.1, 0.15)
+//!     .with_thresholding(Thresholding::OneSided);
+//! // Bob's deployment only thresholds one-sidedly, the default.
+//! let bob = ParamOffer::new(&TbfConfig::new(0.15)).with_tolerance_range(0.12, 0.2);
+//!
+//! let offer = ParamOffer::from_bytes(&alice.to_bytes()).unwrap();
+//! let accept = offer.accept(&bob).unwrap();
+//! let accept = ParamAccept::from_bytes(&accept.to_bytes()).unwrap();
+//! alice.check(&accept).unwrap();
+//!
+//! let config = accept.config().unwrap();
+//! assert_eq!(config.tolerance_permille(), 125);
+//! assert_eq!(config.thresholding(), Thresholding::OneSided);
+//! ```
+//!
+//! Tolerances are negotiated in whole per-mille, as
+//! [`TbfConfig::tolerance_permille`], so both sides derive the same
+//! thresholds bit for bit. A build only implements its own
+//! [`TbfConfig::ALGORITHM_VERSION`], so peers must implement the same one.
+//! The key of [`FinalTransform::KeyedPrf`] is never sent; parties agreeing
+//! on it build the configuration with [`ParamAccept::config_keyed`] and a
+//! key they already share.
+//!
+//! An offer is written as:
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 4 | magic `PNSO` |
+//! | 1 | format version (currently 1) |
+//! | 1 | algorithm version ([`TbfConfig::ALGORITHM_VERSION`]) |
+//! | 2 | smallest tolerance, per-mille, little-endian |
+//! | 2 | largest tolerance, per-mille, little-endian |
+//! | 2 | preferred tolerance, per-mille, little-endian |
+//! | 1 + n | n bit orders ([`BitOrder::id`]), preferred first |
+//! | 1 + n | n thresholding rules ([`Thresholding::id`]), preferred first |
+//! | 1 + 2n | n transforms ([`FinalTransform::id`], then the mask base of [`FinalTransform::XorMask`] or 0), preferred first |
+//! | 4 | first 4 bytes of SHA-256 over everything above |
+//!
+//! and an accept as:
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 4 | magic `PNSA` |
+//! | 1 | format version (currently 1) |
+//! | 1 | algorithm version |
+//! | 2 | tolerance, per-mille, little-endian |
+//! | 1 | bit order |
+//! | 1 | thresholding |
+//! | 2 | transform and mask base, as in the offer |
+//! | 4 | first 4 bytes of SHA-256 over everything above |
+//!
+//! The checksums catch corruption, not tampering: authenticate the
+//! handshake, for instance inside the channel the digests are exchanged
+//! over, if the peers can be impersonated.
+
+use crate::crypto::sha256::Sha256;
+use crate::{BitOrder, Error, FinalTransform, TbfConfig, Thresholding};
+use alloc::vec::Vec;
+
+/// First bytes of every serialized offer.
+const OFFER_MAGIC: [u8; 4] = *b"PNSO";
+/// First bytes of every serialized accept.
+const ACCEPT_MAGIC: [u8; 4] = *b"PNSA";
+/// Newest format this build writes and reads.
+const FORMAT_VERSION: u8 = 1;
+/// Bytes of a serialized accept.
+const ACCEPT_LEN: usize = 16;
+/// Bytes of truncated SHA-256 at the end of both messages.
+const CHECKSUM_LEN: usize = 4;
+
+/// A transform without its key: its id and the mask base of
+/// [`FinalTransform::XorMask`], otherwise 0.
+fn transform_params(transform: &FinalTransform) -> (u8, u8) {
+    match transform {
+        FinalTransform::XorMask { base } => (1, *base),
+        other => (other.id(), 0),
+    }
+}
+
+/// Whether `params` name a transform this build implements.
+fn known_transform(params: (u8, u8)) -> bool {
+    matches!(params, (0 | 2 | 3, 0) | (1, _))
+}
+
+/// What one party accepts, sent to the other to start a negotiation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamOffer {
+    algorithm_version: u8,
+    min_permille: u16,
+    max_permille: u16,
+    preferred_permille: u16,
+    bit_orders: Vec<BitOrder>,        // Preferred first.
+    thresholdings: Vec<Thresholding>, // Preferred first.
+    transforms: Vec<(u8, u8)>,        // Preferred first; see `transform_params`.
+}
+
+impl ParamOffer {
+    /// An offer of exactly `config`, under this build's algorithm version,
+    /// to be widened with the `with_*` methods.
+    pub fn new(config: &TbfConfig) -> Self {
+        let permille = config.tolerance_permille();
+        Self {
+            algorithm_version: TbfConfig::ALGORITHM_VERSION,
+            min_permille: permille,
+            max_permille: permille,
+            preferred_permille: permille,
+            bit_orders: alloc::vec![config.bit_order()],
+            thresholdings: alloc::vec![config.thresholding()],
+            transforms: alloc::vec![transform_params(&config.transform())],
+        }
+    }
+
+    /// This offer accepting tolerances from `min` to `max`, clamped and
+    /// rounded like [`TbfConfig::new`]'s; the preferred tolerance moves
+    /// into the range if it lies outside. Bounds in the wrong order are
+    /// swapped.
+    pub fn with_tolerance_range(self, min: f32, max: f32) -> Self {
+        let min = TbfConfig::new(min).tolerance_permille();
+        let max = TbfConfig::new(max).tolerance_permille();
+        let (min, max) = (min.min(max), min.max(max));
+        Self {
+            min_permille: min,
+            max_permille: max,
+            preferred_permille: self.preferred_permille.clamp(min, max),
+            ..self
+        }
+    }
+
+    /// This offer also accepting `bit_order`, after those it prefers.
+    pub fn with_bit_order(mut self, bit_order: BitOrder) -> Self {
+        if !self.bit_orders.contains(&bit_order) {
+            self.bit_orders.push(bit_order);
+        }
+        self
+    }
+
+    /// This offer also accepting `thresholding`, after those it prefers.
+    pub fn with_thresholding(mut self, thresholding: Thresholding) -> Self {
+        if !self.thresholdings.contains(&thresholding) {
+            self.thresholdings.push(thresholding);
+        }
+        self
+    }
+
+    /// This offer also accepting `transform`, after those it prefers. The
+    /// key of a [`FinalTransform::KeyedPrf`] is ignored: the parties must
+    /// already share it.
+    pub fn with_transform(mut self, transform: FinalTransform) -> Self {
+        let params = transform_params(&transform);
+        if !self.transforms.contains(&params) {
+            self.transforms.push(params);
+        }
+        self
+    }
+
+    /// The algorithm version of the offering party.
+    pub fn algorithm_version(&self) -> u8 {
+        self.algorithm_version
+    }
+
+    /// The smallest, largest and preferred tolerance accepted, in
+    /// per-mille.
+    pub fn tolerance_permille(&self) -> (u16, u16, u16) {
+        (
+            self.min_permille,
+            self.max_permille,
+            self.preferred_permille,
+        )
+    }
+
+    /// The bit orders accepted, preferred first.
+    pub fn bit_orders(&self) -> &[BitOrder] {
+        &self.bit_orders
+    }
+
+    /// The thresholding rules accepted, preferred first.
+    pub fn thresholdings(&self) -> &[Thresholding] {
+        &self.thresholdings
+    }
+
+    /// Answers this offer, received from a peer, with parameters that both
+    /// it and `local`, the offer this party would make, accept: the
+    /// peer's preferred tolerance moved into the range both accept, and
+    /// the first of the peer's bit orders, thresholding rules and
+    /// transforms that `local` accepts too.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if the algorithm versions
+    /// differ or no value of some parameter is accepted by both.
+    pub fn accept(&self, local: &ParamOffer) -> Result<ParamAccept, Error> {
+        if self.algorithm_version != local.algorithm_version {
+            return Err(Error::InvalidParameter(
+                "peers implement different algorithm versions",
+            ));
+        }
+        let min = self.min_permille.max(local.min_permille);
+        let max = self.max_permille.min(local.max_permille);
+        if min > max {
+            return Err(Error::InvalidParameter("no tolerance both parties accept"));
+        }
+        let bit_order = first_shared(&self.bit_orders, &local.bit_orders)
+            .ok_or(Error::InvalidParameter("no bit order both parties accept"))?;
+        let thresholding = first_shared(&self.thresholdings, &local.thresholdings).ok_or(
+            Error::InvalidParameter("no thresholding both parties accept"),
+        )?;
+        let transform = first_shared(&self.transforms, &local.transforms)
+            .ok_or(Error::InvalidParameter("no transform both parties accept"))?;
+        Ok(ParamAccept {
+            algorithm_version: self.algorithm_version,
+            permille: self.preferred_permille.clamp(min, max),
+            bit_order,
+            thresholding,
+            transform,
+        })
+    }
+
+    /// Checks that `accept`, the peer's answer to this offer, only names
+    /// parameters this offer accepts.
+    ///
+    /// Fails with [`Error::InvalidParameter`] otherwise.
+    pub fn check(&self, accept: &ParamAccept) -> Result<(), Error> {
+        if accept.algorithm_version == self.algorithm_version
+            && (self.min_permille..=self.max_permille).contains(&accept.permille)
+            && self.bit_orders.contains(&accept.bit_order)
+            && self.thresholdings.contains(&accept.thresholding)
+            && self.transforms.contains(&accept.transform)
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidParameter("accept lies outside the offer"))
+        }
+    }
+
+    /// Serializes in the format described in the [module documentation](self).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(
+            15 + self.bit_orders.len()
+                + self.thresholdings.len()
+                + 2 * self.transforms.len()
+                + CHECKSUM_LEN,
+        );
+        out.extend_from_slice(&OFFER_MAGIC);
+        out.push(FORMAT_VERSION);
+        out.push(self.algorithm_version);
+        for permille in [
+            self.min_permille,
+            self.max_permille,
+            self.preferred_permille,
+        ] {
+            out.extend_from_slice(&permille.to_le_bytes());
+        }
+        out.push(self.bit_orders.len() as u8);
+        out.extend(self.bit_orders.iter().map(|order| order.id()));
+        out.push(self.thresholdings.len() as u8);
+        out.extend(self.thresholdings.iter().map(|rule| rule.id()));
+        out.push(self.transforms.len() as u8);
+        for &(id, base) in &self.transforms {
+            out.extend_from_slice(&[id, base]);
+        }
+        let checksum = Sha256::digest(&[&out]);
+        out.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+        out
+    }
+
+    /// Parses the output of [`ParamOffer::to_bytes`], of any algorithm
+    /// version.
+    ///
+    /// Fails with [`Error::InvalidLength`] if `bytes` is truncated or too
+    /// long, with [`Error::InvalidChecksum`] on corruption and with
+    /// [`Error::InvalidParameter`] for offers this build cannot interpret.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        const FIXED_LEN: usize = 12;
+        if bytes.len() < FIXED_LEN + 3 + CHECKSUM_LEN {
+            return Err(Error::InvalidLength {
+                expected: FIXED_LEN + 3 + CHECKSUM_LEN,
+                found: bytes.len(),
+            });
+        }
+        if bytes[..4] != OFFER_MAGIC {
+            return Err(Error::InvalidParameter("not a pensieve parameter offer"));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if Sha256::digest(&[body])[..CHECKSUM_LEN] != *checksum {
+            return Err(Error::InvalidChecksum);
+        }
+        if bytes[4] != FORMAT_VERSION {
+            return Err(Error::InvalidParameter(
+                "unsupported parameter offer format version",
+            ));
+        }
+        let permille = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let (min, max, preferred) = (permille(6), permille(8), permille(10));
+        let supported = TbfConfig::MIN_TOLERANCE_PERMILLE..=TbfConfig::MAX_TOLERANCE_PERMILLE;
+        if !supported.contains(&min) || !supported.contains(&max) {
+            return Err(Error::InvalidParameter("unsupported tolerance"));
+        }
+        if !(min..=max).contains(&preferred) {
+            return Err(Error::InvalidParameter(
+                "preferred tolerance outside the offered range",
+            ));
+        }
+
+        let mut rest = &body[FIXED_LEN..];
+        let mut list = |width: usize| -> Result<&[u8], Error> {
+            let (&count, tail) = rest.split_first().ok_or(Error::InvalidLength {
+                expected: bytes.len() + 1,
+                found: bytes.len(),
+            })?;
+            let len = usize::from(count) * width;
+            if count == 0 {
+                return Err(Error::InvalidParameter("an offered list is empty"));
+            }
+            if tail.len() < len {
+                return Err(Error::InvalidLength {
+                    expected: bytes.len() + len - tail.len(),
+                    found: bytes.len(),
+                });
+            }
+            let (items, tail) = tail.split_at(len);
+            rest = tail;
+            Ok(items)
+        };
+        let bit_orders = list(1)?
+            .iter()
+            .map(|&id| {
+                BitOrder::from_id(id).ok_or(Error::InvalidParameter("unsupported bit order"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let thresholdings = list(1)?
+            .iter()
+            .map(|&id| {
+                Thresholding::from_id(id).ok_or(Error::InvalidParameter("unsupported thresholding"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let transforms = list(2)?
+            .chunks(2)
+            .map(|pair| {
+                let params = (pair[0], pair[1]);
+                if known_transform(params) {
+                    Ok(params)
+                } else {
+                    Err(Error::InvalidParameter("unsupported output transform"))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !rest.is_empty() {
+            return Err(Error::InvalidLength {
+                expected: bytes.len() - rest.len(),
+                found: bytes.len(),
+            });
+        }
+        Ok(Self {
+            algorithm_version: bytes[5],
+            min_permille: min,
+            max_permille: max,
+            preferred_permille: preferred,
+            bit_orders,
+            thresholdings,
+            transforms,
+        })
+    }
+}
+
+/// The first of `preferred` that `accepted` contains.
+fn first_shared<T: Copy + PartialEq>(preferred: &[T], accepted: &[T]) -> Option<T> {
+    preferred
+        .iter()
+        .copied()
+        .find(|item| accepted.contains(item))
+}
+
+/// The parameters both parties agreed on, returned by
+/// [`ParamOffer::accept`] and sent back to the offering party.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParamAccept {
+    algorithm_version: u8,
+    permille: u16,
+    bit_order: BitOrder,
+    thresholding: Thresholding,
+    transform: (u8, u8), // See `transform_params`.
+}
+
+impl ParamAccept {
+    /// The agreed algorithm version.
+    pub fn algorithm_version(&self) -> u8 {
+        self.algorithm_version
+    }
+
+    /// The agreed tolerance, in per-mille.
+    pub fn tolerance_permille(&self) -> u16 {
+        self.permille
+    }
+
+    /// The agreed bit order.
+    pub fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    /// The agreed thresholding rule.
+    pub fn thresholding(&self) -> Thresholding {
+        self.thresholding
+    }
+
+    /// The agreed configuration.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if the parties agreed on
+    /// [`FinalTransform::KeyedPrf`], which needs
+    /// [`ParamAccept::config_keyed`].
+    pub fn config(&self) -> Result<TbfConfig, Error> {
+        self.config_with(None)
+    }
+
+    /// The agreed configuration, with `key` for a
+    /// [`FinalTransform::KeyedPrf`]; other transforms ignore it.
+    pub fn config_keyed(&self, key: &[u8; 32]) -> Result<TbfConfig, Error> {
+        self.config_with(Some(key))
+    }
+
+    fn config_with(&self, key: Option<&[u8; 32]>) -> Result<TbfConfig, Error> {
+        let transform = match (self.transform, key) {
+            ((0, _), _) => FinalTransform::Identity,
+            ((1, base), _) => FinalTransform::XorMask { base },
+            ((2, _), Some(key)) => FinalTransform::KeyedPrf { key: *key },
+            ((2, _), None) => {
+                return Err(Error::InvalidParameter(
+                    "keyed transform needs config_keyed",
+                ));
+            }
+            _ => FinalTransform::Hash,
+        };
+        Ok(TbfConfig::from_permille(self.permille)
+            .with_bit_order(self.bit_order)
+            .with_thresholding(self.thresholding)
+            .with_transform(transform))
+    }
+
+    /// Serializes in the format described in the [module documentation](self).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(ACCEPT_LEN);
+        out.extend_from_slice(&ACCEPT_MAGIC);
+        out.push(FORMAT_VERSION);
+        out.push(self.algorithm_version);
+        out.extend_from_slice(&self.permille.to_le_bytes());
+        out.push(self.bit_order.id());
+        out.push(self.thresholding.id());
+        out.extend_from_slice(&[self.transform.0, self.transform.1]);
+        let checksum = Sha256::digest(&[&out]);
+        out.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+        out
+    }
+
+    /// Parses the output of [`ParamAccept::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidLength`] unless `bytes` is 16 bytes long,
+    /// with [`Error::InvalidChecksum`] on corruption and with
+    /// [`Error::InvalidParameter`] for parameters this build cannot
+    /// interpret identically, including other algorithm versions.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != ACCEPT_LEN {
+            return Err(Error::InvalidLength {
+                expected: ACCEPT_LEN,
+                found: bytes.len(),
+            });
+        }
+        if bytes[..4] != ACCEPT_MAGIC {
+            return Err(Error::InvalidParameter("not a pensieve parameter accept"));
+        }
+        let (body, checksum) = bytes.split_at(ACCEPT_LEN - CHECKSUM_LEN);
+        if Sha256::digest(&[body])[..CHECKSUM_LEN] != *checksum {
+            return Err(Error::InvalidChecksum);
+        }
+        if bytes[4] != FORMAT_VERSION {
+            return Err(Error::InvalidParameter(
+                "unsupported parameter accept format version",
+            ));
+        }
+        if bytes[5] != TbfConfig::ALGORITHM_VERSION {
+            return Err(Error::InvalidParameter(
+                "peers implement different algorithm versions",
+            ));
+        }
+        let permille = u16::from_le_bytes([bytes[6], bytes[7]]);
+        if !(TbfConfig::MIN_TOLERANCE_PERMILLE..=TbfConfig::MAX_TOLERANCE_PERMILLE)
+            .contains(&permille)
+        {
+            return Err(Error::InvalidParameter("unsupported tolerance"));
+        }
+        let bit_order =
+            BitOrder::from_id(bytes[8]).ok_or(Error::InvalidParameter("unsupported bit order"))?;
+        let thresholding = Thresholding::from_id(bytes[9])
+            .ok_or(Error::InvalidParameter("unsupported thresholding"))?;
+        let transform = (bytes[10], bytes[11]);
+        if !known_transform(transform) {
+            return Err(Error::InvalidParameter("unsupported output transform"));
+        }
+        Ok(Self {
+            algorithm_version: bytes[5],
+            permille,
+            bit_order,
+            thresholding,
+            transform,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_both_sides_build_the_same_config() {
+        let key = [7u8; 32];
+        let alice =
+            ParamOffer::new(&TbfConfig::new(0.2).with_transform(FinalTransform::KeyedPrf { key }))
+                .with_tolerance_range(0.1, 0.2)
+                .with_transform(FinalTransform::Hash)
+                .with_bit_order(BitOrder::LsbFirst);
+        let bob = ParamOffer::new(
+            &TbfConfig::new(0.15)
+                .with_bit_order(BitOrder::LsbFirst)
+                .with_transform(FinalTransform::Hash),
+        )
+        .with_tolerance_range(0.05, 0.15)
+        .with_transform(FinalTransform::KeyedPrf { key: [0; 32] });
+
+        let accept = ParamOffer::from_bytes(&alice.to_bytes())
+            .unwrap()
+            .accept(&bob)
+            .unwrap();
+        let received = ParamAccept::from_bytes(&accept.to_bytes()).unwrap();
+        assert_eq!(received, accept);
+        assert_eq!(alice.check(&received), Ok(()));
+        assert_eq!(received.tolerance_permille(), 150);
+        assert_eq!(received.bit_order(), BitOrder::LsbFirst);
+        // Alice prefers the keyed transform, which Bob also accepts.
+        assert!(received.config().is_err());
+        let config = received.config_keyed(&key).unwrap();
+        assert_eq!(
+            config,
+            TbfConfig::new(0.15)
+                .with_bit_order(BitOrder::LsbFirst)
+                .with_transform(FinalTransform::KeyedPrf { key })
+        );
+        let input = [0x3Cu8; 24];
+        assert_eq!(
+            received.config_keyed(&key).unwrap().collapse(&input),
+            config.collapse(&input)
+        );
+    }
+
+    #[test]
+    fn test_disjoint_offers_fail() {
+        let base = ParamOffer::new(&TbfConfig::new(0.1));
+        let stricter = ParamOffer::new(&TbfConfig::new(0.2));
+        assert_eq!(
+            base.accept(&stricter),
+            Err(Error::InvalidParameter("no tolerance both parties accept"))
+        );
+        let masked = ParamOffer::new(
+            &TbfConfig::new(0.1).with_transform(FinalTransform::XorMask { base: 0x11 }),
+        );
+        assert_eq!(
+            base.accept(&masked),
+            Err(Error::InvalidParameter("no transform both parties accept"))
+        );
+        let mut future = base.clone();
+        future.algorithm_version += 1;
+        assert!(future.accept(&base).is_err());
+        assert!(base.accept(&future).is_err());
+
+        // An accept naming anything outside the offer is refused.
+        let accept = base.accept(&base).unwrap();
+        assert_eq!(base.check(&accept), Ok(()));
+        assert!(stricter.check(&accept).is_err());
+        assert!(masked.check(&accept).is_err());
+    }
+
+    #[test]
+    fn test_serialization_rejects_damage() {
+        let offer = ParamOffer::new(&TbfConfig::default())
+            .with_thresholding(Thresholding::Mean)
+            .with_tolerance_range(0.3, 0.01);
+        assert_eq!(offer.tolerance_permille(), (50, 250, 125));
+        let bytes = offer.to_bytes();
+        assert_eq!(ParamOffer::from_bytes(&bytes), Ok(offer.clone()));
+        for len in 0..bytes.len() {
+            assert!(ParamOffer::from_bytes(&bytes[..len]).is_err(), "{len}");
+        }
+        let mut corrupted = bytes.clone();
+        corrupted[7] ^= 1;
+        assert_eq!(
+            ParamOffer::from_bytes(&corrupted),
+            Err(Error::InvalidChecksum)
+        );
+
+        let accept = offer.accept(&offer).unwrap().to_bytes();
+        assert_eq!(accept.len(), ACCEPT_LEN);
+        let mut corrupted = accept.clone();
+        corrupted[9] ^= 1;
+        assert_eq!(
+            ParamAccept::from_bytes(&corrupted),
+            Err(Error::InvalidChecksum)
+        );
+        assert!(ParamAccept::from_bytes(&bytes).is_err());
+        assert!(ParamOffer::from_bytes(&accept).is_err());
+    }
+}
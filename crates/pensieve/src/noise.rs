@@ -0,0 +1,232 @@
+//! Seeded corruption of inputs for reproducible tests and evaluations.
+//!
+//! Every generator draws its randomness from an [`EntropySource`], so the
+//! same source corrupts the same input the same way. [`Seeded`] is such a
+//! source built from a single `u64`, which is all a test or a benchmark
+//! needs to replay a noise pattern:
+//! - [`flip_random_bits`] flips an exact number of distinct bits, the model
+//!   behind a Hamming-distance budget;
+//! - [`flip_each_bit`] flips every bit independently, the binary symmetric
+//!   channel that [`stability`](crate::analysis::stability) assumes;
+//! - [`burst_corrupt`] randomizes a run of adjacent bits, like a scratch or
+//!   a dropped packet;
+//! - [`erase_random_bytes`] zeroes whole bytes, like unreadable cells.
+//!
+//! Bit positions are numbered MSB-first, like in
+//! [`collapse_bits`](crate::collapse_bits).
+
+use crate::entropy::EntropySource;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A deterministic byte source (SplitMix64) seeded with a single `u64`.
+///
+/// It is fast and reproducible but predictable: use it to simulate noise,
+/// never to generate keys or salts.
+#[derive(Debug, Clone)]
+pub struct Seeded {
+    state: u64,
+}
+
+impl Seeded {
+    /// A source whose output depends on `seed` alone.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl EntropySource for Seeded {
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes()[..chunk.len()]);
+        }
+    }
+}
+
+/// Flips `count` distinct bits of `input` chosen uniformly at random, all
+/// of them if `count` exceeds the input; returns their positions in the
+/// order they were drawn.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::noise::{Seeded, flip_random_bits};
+///
+/// let enrolled = [0xFFu8; 32];
+/// let mut noisy = enrolled;
+/// let flipped = flip_random_bits(&mut noisy, 8, &mut Seeded::new(7));
+/// assert_eq!(flipped.len(), 8);
+/// let config = TbfConfig::new(0.125);
+/// assert_eq!(config.collapse(&noisy), config.collapse(&enrolled));
+/// ```
+pub fn flip_random_bits(
+    input: &mut [u8],
+    count: usize,
+    rng: &mut impl EntropySource,
+) -> Vec<usize> {
+    let positions = distinct_below(input.len() * 8, count, rng);
+    for &bit in &positions {
+        flip_bit(input, bit);
+    }
+    positions
+}
+
+/// Flips every bit of `input` independently with probability
+/// `probability` (clamped to 0.0..=1.0); returns how many were flipped.
+pub fn flip_each_bit(input: &mut [u8], probability: f64, rng: &mut impl EntropySource) -> usize {
+    // Compare 53 random bits against the probability scaled to 2^53.
+    let cutoff = (probability.clamp(0.0, 1.0) * (1u64 << 53) as f64) as u64;
+    let mut flipped = 0;
+    for bit in 0..input.len() * 8 {
+        if random_u64(rng) >> 11 < cutoff {
+            flip_bit(input, bit);
+            flipped += 1;
+        }
+    }
+    flipped
+}
+
+/// Overwrites a run of `length` adjacent bits of `input`, starting at a
+/// uniformly random position, with random bits; returns the bit range of
+/// the burst. A burst longer than the input covers all of it.
+///
+/// On average half the bits of the burst change, so a burst of `length`
+/// bits costs about `length / 2` bits of Hamming distance, concentrated in
+/// one or two chunks.
+pub fn burst_corrupt(
+    input: &mut [u8],
+    length: usize,
+    rng: &mut impl EntropySource,
+) -> Range<usize> {
+    let total_bits = input.len() * 8;
+    let length = length.min(total_bits);
+    let start = random_below(total_bits - length + 1, rng);
+    for bit in start..start + length {
+        if random_u64(rng) & 1 == 1 {
+            flip_bit(input, bit);
+        }
+    }
+    start..start + length
+}
+
+/// Sets `count` distinct bytes of `input`, chosen uniformly at random, to
+/// zero (all of them if `count` exceeds the input); returns their indices
+/// in ascending order, as a decoder that knows its erasures would see them.
+pub fn erase_random_bytes(
+    input: &mut [u8],
+    count: usize,
+    rng: &mut impl EntropySource,
+) -> Vec<usize> {
+    let mut indices = distinct_below(input.len(), count, rng);
+    indices.sort_unstable();
+    for &i in &indices {
+        input[i] = 0;
+    }
+    indices
+}
+
+fn flip_bit(bytes: &mut [u8], bit: usize) {
+    bytes[bit / 8] ^= 0x80 >> (bit % 8);
+}
+
+fn random_u64(rng: &mut impl EntropySource) -> u64 {
+    let mut bytes = [0u8; 8];
+    rng.fill_bytes(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
+
+/// Roughly uniform integer in `0..bound`; the modulo bias of a 64-bit draw
+/// is irrelevant for simulations.
+fn random_below(bound: usize, rng: &mut impl EntropySource) -> usize {
+    (random_u64(rng) % bound as u64) as usize
+}
+
+/// `count` distinct values in `0..bound` (partial Fisher-Yates).
+fn distinct_below(bound: usize, count: usize, rng: &mut impl EntropySource) -> Vec<usize> {
+    let mut pool: Vec<usize> = (0..bound).collect();
+    for i in 0..count.min(bound) {
+        let j = i + random_below(bound - i, rng);
+        pool.swap(i, j);
+    }
+    pool.truncate(count);
+    pool
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hamming(a: &[u8], b: &[u8]) -> u32 {
+        a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+    }
+
+    #[test]
+    fn test_seeded_noise_is_reproducible() {
+        let corrupt = |seed| {
+            let mut input = [0x5Au8; 64];
+            let mut rng = Seeded::new(seed);
+            flip_random_bits(&mut input, 10, &mut rng);
+            flip_each_bit(&mut input, 0.01, &mut rng);
+            burst_corrupt(&mut input, 24, &mut rng);
+            erase_random_bytes(&mut input, 3, &mut rng);
+            input
+        };
+        assert_eq!(corrupt(1), corrupt(1));
+        assert_ne!(corrupt(1), corrupt(2));
+    }
+
+    #[test]
+    fn test_flips_are_exact_and_distinct() {
+        let mut rng = Seeded::new(3);
+        let mut input = [0u8; 16];
+        let positions = flip_random_bits(&mut input, 20, &mut rng);
+        assert_eq!(hamming(&input, &[0; 16]), 20);
+        assert!(
+            positions
+                .iter()
+                .all(|&bit| input[bit / 8] & 0x80 >> (bit % 8) != 0)
+        );
+        // More flips than bits flip every bit once.
+        assert_eq!(flip_random_bits(&mut input, 1000, &mut rng).len(), 128);
+        assert_eq!(hamming(&input, &[0; 16]), 108);
+
+        let mut input = [0u8; 1000];
+        let flipped = flip_each_bit(&mut input, 0.1, &mut rng);
+        assert_eq!(hamming(&input, &[0; 1000]) as usize, flipped);
+        assert!((700..900).contains(&flipped), "{flipped}");
+        assert_eq!(flip_each_bit(&mut input, 0.0, &mut rng), 0);
+        assert_eq!(flip_each_bit(&mut input, 1.0, &mut rng), 8000);
+    }
+
+    #[test]
+    fn test_bursts_and_erasures_stay_in_place() {
+        let mut rng = Seeded::new(5);
+        for _ in 0..100 {
+            let mut input = [0u8; 8];
+            let burst = burst_corrupt(&mut input, 12, &mut rng);
+            assert_eq!(burst.len(), 12);
+            assert!(burst.end <= 64);
+            for bit in (0..64).filter(|bit| !burst.contains(bit)) {
+                assert_eq!(input[bit / 8] & 0x80 >> (bit % 8), 0);
+            }
+        }
+        assert_eq!(burst_corrupt(&mut [0u8; 2], 100, &mut rng), 0..16);
+        assert_eq!(burst_corrupt(&mut [], 4, &mut rng), 0..0);
+
+        let mut input = [0xFFu8; 10];
+        let erased = erase_random_bytes(&mut input, 4, &mut rng);
+        assert_eq!(erased.len(), 4);
+        assert!(erased.windows(2).all(|pair| pair[0] < pair[1]));
+        for (i, &byte) in input.iter().enumerate() {
+            assert_eq!(byte == 0, erased.contains(&i));
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Private fuzzy matching through an oblivious PRF.
+//!
+//! A server holds enrollments of noisy secrets (biometric templates, PUF
+//! responses) and a client holds a fresh reading. The client learns whether
+//! its reading collapses like an enrollment; the server learns nothing
+//! about the reading, and the client learns nothing about the enrollment
+//! beyond that one bit.
+//!
+//! Both sides run collapsed values through a PRF keyed by the server's
+//! [`OprfKey`]: F(k, c) = SHA-256(k * H(c) || c), where H hashes onto the
+//! prime-order group of edwards25519 (the 2HashDH construction). The server
+//! stores F of each enrolled value as an [`Enrollment`]. The client
+//! evaluates F on its own collapsed value obliviously, by blinding H(c)
+//! with a random scalar r, and compares the result with the enrollment:
+//!
+//! ```text
+//! Client                                        Server (k, enrollment)
+//! c = collapse(input)
+//! B = r * H(c)            -- MatchRequest -->
+//!                         <-- MatchResponse --  k * B, enrollment
+//! F(k, c) = SHA-256(r^-1 * (k * B) || c)
+//! match iff F(k, c) == enrollment
+//! ```
+//!
+//! The input is collapsed with [`collapse_ct`] under the enrollment's
+//! tolerance, which the server publishes with the enrollment.
+//!
+//! Without the key, an enrollment does not help guess the enrolled value,
+//! but every request lets the client test one guess: servers must
+//! rate-limit requests, as with any online check of a low-entropy secret.
+//! The server is trusted to answer honestly; a server answering with
+//! another key makes the client see no match.
+//!
+//! All messages serialize to fixed-size byte strings with `to_bytes` /
+//! `from_bytes`; transport is up to the caller.
+
+use crate::collapse_ct;
+use crate::crypto::ct_eq;
+use crate::crypto::ed25519::{hash_to_point, invert_scalar, mul_point, random_scalar, reduce};
+use crate::crypto::sha256::Sha256;
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use alloc::vec::Vec;
+
+/// Domain separation labels.
+const HASH_LABEL: &[u8] = b"pensieve/oprf/v1/hash";
+const FINALIZE_LABEL: &[u8] = b"pensieve/oprf/v1/finalize";
+
+/// The server's secret PRF key.
+#[derive(Clone)]
+pub struct OprfKey([u8; 32]); // A nonzero scalar modulo the group order.
+
+impl OprfKey {
+    /// Length of the serialized form.
+    pub const ENCODED_LEN: usize = 32;
+
+    /// A fresh key drawn from `rng`.
+    pub fn generate(rng: &mut impl EntropySource) -> Self {
+        Self(random_scalar(rng))
+    }
+
+    /// The key as a little-endian scalar, for storing it.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        self.0
+    }
+
+    /// Parses the output of [`OprfKey::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless the bytes are a
+    /// nonzero scalar below the group order.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        check_len(bytes, Self::ENCODED_LEN)?;
+        let scalar: [u8; 32] = bytes.try_into().expect("32 bytes");
+        if reduce(&scalar) != scalar || scalar == [0; 32] {
+            return Err(Error::InvalidParameter("not a nonzero reduced scalar"));
+        }
+        Ok(Self(scalar))
+    }
+
+    /// Enrolls `input`, collapsed under `tolerance`.
+    pub fn enroll(&self, input: &[u8], tolerance: f32) -> Enrollment {
+        let collapsed = collapse_ct(input, tolerance);
+        let evaluated =
+            mul_point(&hash(&collapsed), &self.0).expect("hashed points have prime order");
+        Enrollment {
+            output: finalize(&evaluated, &collapsed),
+            tolerance,
+        }
+    }
+
+    /// Answers `request` for a match against `enrollment`.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if the blinded point is not an
+    /// element of the prime-order group other than the identity; answering
+    /// those would leak information about the key.
+    pub fn evaluate(
+        &self,
+        request: &MatchRequest,
+        enrollment: &Enrollment,
+    ) -> Result<MatchResponse, Error> {
+        let evaluated = mul_point(&request.blinded, &self.0).ok_or(Error::InvalidParameter(
+            "blinded point is not in the prime-order group",
+        ))?;
+        Ok(MatchResponse {
+            evaluated,
+            enrollment: enrollment.output,
+        })
+    }
+}
+
+impl core::fmt::Debug for OprfKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("OprfKey(..)")
+    }
+}
+
+/// The PRF output of an enrolled value, with the tolerance it was collapsed
+/// under. Reveals nothing about the value without the key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Enrollment {
+    output: [u8; 32],
+    tolerance: f32,
+}
+
+impl Enrollment {
+    /// Length of the serialized form.
+    pub const ENCODED_LEN: usize = 36;
+
+    /// The tolerance clients must collapse their input under.
+    pub fn tolerance(&self) -> f32 {
+        self.tolerance
+    }
+
+    /// Serializes as `output || tolerance` (f32 little-endian).
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut out = [0u8; Self::ENCODED_LEN];
+        out[..32].copy_from_slice(&self.output);
+        out[32..].copy_from_slice(&self.tolerance.to_le_bytes());
+        out
+    }
+
+    /// Parses the output of [`Enrollment::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        check_len(bytes, Self::ENCODED_LEN)?;
+        Ok(Self {
+            output: bytes[..32].try_into().expect("32 bytes"),
+            tolerance: f32::from_le_bytes(bytes[32..].try_into().expect("4 bytes")),
+        })
+    }
+}
+
+/// First message, sent by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchRequest {
+    blinded: [u8; 32], // r * H(c).
+}
+
+impl MatchRequest {
+    /// Length of the serialized form.
+    pub const ENCODED_LEN: usize = 32;
+
+    /// Serializes the blinded point.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        self.blinded
+    }
+
+    /// Parses the output of [`MatchRequest::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        check_len(bytes, Self::ENCODED_LEN)?;
+        Ok(Self {
+            blinded: bytes.try_into().expect("32 bytes"),
+        })
+    }
+}
+
+/// Second message, sent by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchResponse {
+    evaluated: [u8; 32],  // k * r * H(c).
+    enrollment: [u8; 32], // F(k, enrolled value).
+}
+
+impl MatchResponse {
+    /// Length of the serialized form.
+    pub const ENCODED_LEN: usize = 64;
+
+    /// Serializes as `evaluated || enrollment`.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut out = [0u8; Self::ENCODED_LEN];
+        out[..32].copy_from_slice(&self.evaluated);
+        out[32..].copy_from_slice(&self.enrollment);
+        out
+    }
+
+    /// Parses the output of [`MatchResponse::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        check_len(bytes, Self::ENCODED_LEN)?;
+        Ok(Self {
+            evaluated: bytes[..32].try_into().expect("32 bytes"),
+            enrollment: bytes[32..].try_into().expect("32 bytes"),
+        })
+    }
+}
+
+/// Client state between sending the [`MatchRequest`] and receiving the
+/// [`MatchResponse`].
+pub struct MatchClient {
+    collapsed: Vec<u8>,
+    blind: [u8; 32], // r.
+}
+
+impl MatchClient {
+    /// Collapses `input` under `tolerance`, the enrollment's
+    /// [`tolerance`](Enrollment::tolerance), and blinds it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::oprf::{MatchClient, MatchRequest, MatchResponse, OprfKey};
+    ///
+    /// let mut rng = |dest: &mut [u8]| dest.iter_mut().for_each(|b| *b = 0x42);
+    /// let server_key = OprfKey::generate(&mut rng);
+    /// let mut enrolled = [0u8; 16];
+    /// enrolled[0] = 0xFF;
+    /// let enrollment = server_key.enroll(&enrolled, 0.05);
+    ///
+    /// let mut reading = enrolled;
+    /// reading[0] ^= 0x01; // One bit of noise.
+    /// let (client, request) = MatchClient::start(&reading, enrollment.tolerance(), &mut rng);
+    /// let request = MatchRequest::from_bytes(&request.to_bytes()).unwrap();
+    /// let response = server_key.evaluate(&request, &enrollment).unwrap();
+    /// let response = MatchResponse::from_bytes(&response.to_bytes()).unwrap();
+    /// assert_eq!(client.finish(&response), Ok(true));
+    /// ```
+    pub fn start(
+        input: &[u8],
+        tolerance: f32,
+        rng: &mut impl EntropySource,
+    ) -> (Self, MatchRequest) {
+        let collapsed = collapse_ct(input, tolerance);
+        let blind = random_scalar(rng);
+        let blinded = mul_point(&hash(&collapsed), &blind).expect("hashed points have prime order");
+        (Self { collapsed, blind }, MatchRequest { blinded })
+    }
+
+    /// Whether the input matches the enrollment the server answered for.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if the evaluated point is not
+    /// an element of the prime-order group.
+    pub fn finish(self, response: &MatchResponse) -> Result<bool, Error> {
+        let unblinded = mul_point(&response.evaluated, &invert_scalar(&self.blind)).ok_or(
+            Error::InvalidParameter("evaluated point is not in the prime-order group"),
+        )?;
+        Ok(ct_eq(
+            &finalize(&unblinded, &self.collapsed),
+            &response.enrollment,
+        ))
+    }
+}
+
+/// H(c), hashing a collapsed value onto the prime-order group.
+fn hash(collapsed: &[u8]) -> [u8; 32] {
+    hash_to_point(&Sha256::digest(&[HASH_LABEL, collapsed]))
+}
+
+/// F(k, c) from k * H(c).
+fn finalize(evaluated: &[u8; 32], collapsed: &[u8]) -> [u8; 32] {
+    Sha256::digest(&[FINALIZE_LABEL, evaluated, collapsed])
+}
+
+fn check_len(bytes: &[u8], expected: usize) -> Result<(), Error> {
+    if bytes.len() != expected {
+        return Err(Error::InvalidLength {
+            expected,
+            found: bytes.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    /// Runs the full exchange, serializing every message on the way.
+    fn run(key: &OprfKey, enrollment: &Enrollment, input: &[u8]) -> Result<bool, Error> {
+        let enrollment = Enrollment::from_bytes(&enrollment.to_bytes())?;
+        let (client, request) = MatchClient::start(input, enrollment.tolerance(), &mut seeded(5));
+        let request = MatchRequest::from_bytes(&request.to_bytes())?;
+        let response = key.evaluate(&request, &enrollment)?;
+        client.finish(&MatchResponse::from_bytes(&response.to_bytes())?)
+    }
+
+    #[test]
+    fn test_matches_noisy_readings_only() {
+        let key = OprfKey::generate(&mut seeded(1));
+        let mut enrolled = [0u8; 16];
+        enrolled[0] = 0b11111111;
+        let enrollment = key.enroll(&enrolled, 0.05);
+
+        let mut noisy = enrolled;
+        noisy[0] ^= 0b00000001; // 1 bit flip, within 5%
+        assert_eq!(run(&key, &enrollment, &noisy), Ok(true));
+        assert_eq!(run(&key, &enrollment, &[0xFF; 16]), Ok(false));
+
+        // Another key evaluates another PRF.
+        let other = OprfKey::generate(&mut seeded(2));
+        assert_eq!(run(&other, &enrollment, &enrolled), Ok(false));
+        let restored = OprfKey::from_bytes(&key.to_bytes()).unwrap();
+        assert_eq!(run(&restored, &enrollment, &enrolled), Ok(true));
+    }
+
+    #[test]
+    fn test_requests_are_blinded() {
+        let (_, first) = MatchClient::start(&[7; 16], 0.05, &mut seeded(3));
+        let (_, second) = MatchClient::start(&[7; 16], 0.05, &mut seeded(4));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_invalid_points_rejected() {
+        let key = OprfKey::generate(&mut seeded(1));
+        let enrollment = key.enroll(&[7; 16], 0.05);
+        // The identity, a point of order 2 and a non-canonical encoding.
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        let mut order_two = [0xffu8; 32];
+        order_two[0] = 0xec;
+        order_two[31] = 0x7f;
+        for blinded in [identity, order_two, [0xff; 32]] {
+            assert!(
+                key.evaluate(&MatchRequest { blinded }, &enrollment)
+                    .is_err()
+            );
+        }
+
+        let (client, _) = MatchClient::start(&[7; 16], 0.05, &mut seeded(3));
+        let response = MatchResponse {
+            evaluated: identity,
+            enrollment: enrollment.to_bytes()[..32].try_into().unwrap(),
+        };
+        assert!(client.finish(&response).is_err());
+
+        assert!(OprfKey::from_bytes(&[0; 32]).is_err());
+        assert!(OprfKey::from_bytes(&[0xff; 32]).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+//! Fingerprints of network packets and flows.
+//!
+//! Packets of the same kind of traffic, such as one scanner's probes or one
+//! botnet's beacons, differ in their addresses, ports, sequence numbers and
+//! checksums, and in a few payload bytes: counters, nonces, host names. A
+//! [`PacketProfile`] keeps only the header fields that stay put, each
+//! masked to its stable bits, and summarizes the payload by a SimHash of
+//! its byte shingles, so a few changed payload bytes move only a few bits.
+//! [`PacketProfile::fingerprint`] collapses the two together, and traffic
+//! with equal fingerprints can be clustered or looked up like any other
+//! digest.
+//!
+//! A [`FlowFingerprint`] does the same for the last packets of a flow:
+//! it keeps one SimHash over the header fields and payload shingles of a
+//! sliding window of packets, updated as packets arrive and leave, so a
+//! long-lived connection can be compared with known flows at any time.
+//!
+//! Offsets count from the first byte handed in, whatever layer that is;
+//! [`PacketProfile::ipv4_tcp`] assumes an IPv4 header without options
+//! followed by a TCP header without options. Packets are public data.
+
+use crate::simhash::SimHash;
+use crate::tokenize::ByteNgrams;
+use crate::{Error, TbfConfig};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// Bits of the SimHash summarizing a payload or a flow.
+pub const SUMMARY_BITS: usize = 128;
+
+/// A header field kept by a [`PacketProfile`]: the bytes at `offset`,
+/// ANDed with a mask of the same length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderField {
+    offset: usize,
+    mask: Vec<u8>,
+}
+
+impl HeaderField {
+    /// The `len` bytes at `offset`, all of their bits kept.
+    pub fn bytes(offset: usize, len: usize) -> Self {
+        Self::masked(offset, alloc::vec![0xFF; len])
+    }
+
+    /// The `mask.len()` bytes at `offset`, keeping the bits set in `mask`.
+    pub fn masked(offset: usize, mask: Vec<u8>) -> Self {
+        Self { offset, mask }
+    }
+
+    /// Offset of the first byte of the field.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The mask; its length is the field's.
+    pub fn mask(&self) -> &[u8] {
+        &self.mask
+    }
+
+    /// Appends the field of `packet` to `out`; bytes past the end of a
+    /// truncated packet count as zero.
+    fn extract(&self, packet: &[u8], out: &mut Vec<u8>) {
+        out.extend(self.mask.iter().enumerate().map(|(i, &mask)| {
+            let byte = self.offset.checked_add(i).and_then(|at| packet.get(at));
+            byte.map_or(0, |&byte| byte & mask)
+        }));
+    }
+}
+
+/// Which parts of a packet make up its fingerprint.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::packet::PacketProfile;
+///
+/// // Probes of the same scanner from other source ports, with other
+/// // sequence numbers and session ids in the payload.
+/// let probe = |source_port: u16, seq: u32, session: &[u8; 4]| {
+///     let mut packet = vec![0x45, 0, 0, 60, 0x1C, 0x46, 0x40, 0, 64, 6, 0, 0];
+///     packet.extend([10, 0, 0, 1, 10, 0, 0, 2]);
+///     packet.extend(source_port.to_be_bytes());
+///     packet.extend(443u16.to_be_bytes());
+///     packet.extend(seq.to_be_bytes());
+///     packet.extend([0, 0, 0, 0, 0x50, 0x18, 0xFF, 0xFF, 0, 0, 0, 0]);
+///     packet.extend(b"HELLO scanner/2.1 session=");
+///     packet.extend(session);
+///     packet.extend(b" probing ports 1-1024 with default payload");
+///     packet
+/// };
+/// let profile = PacketProfile::ipv4_tcp();
+/// let config = TbfConfig::new(0.125);
+/// assert_eq!(
+///     profile.fingerprint(&probe(40_001, 7, b"a1f3"), &config),
+///     profile.fingerprint(&probe(51_234, 99_999, b"a1f3"), &config),
+/// );
+/// // The other session id moves a few of the 184 encoded bits.
+/// let distance = pensieve::distance::hamming(
+///     &profile.encode(&probe(40_001, 7, b"a1f3")),
+///     &profile.encode(&probe(51_234, 99_999, b"c0de")),
+/// );
+/// assert!(distance < 16);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketProfile {
+    fields: Vec<HeaderField>,
+    payload_offset: usize,
+    shingles: ByteNgrams,
+}
+
+impl PacketProfile {
+    /// A profile without header fields whose payload starts at
+    /// `payload_offset` and is cut into shingles of `shingle_width` bytes.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `shingle_width` is zero.
+    pub fn new(payload_offset: usize, shingle_width: usize) -> Result<Self, Error> {
+        Ok(Self {
+            fields: Vec::new(),
+            payload_offset,
+            shingles: ByteNgrams::new(shingle_width)?,
+        })
+    }
+
+    /// The stable fields of an IPv4 header without options and the TCP
+    /// header after it, with 4-byte payload shingles: version and header
+    /// length, type of service without the ECN bits, protocol, destination
+    /// port, and the TCP data offset and flags. Addresses, source ports,
+    /// lengths, identifiers, TTLs, sequence numbers, windows and checksums
+    /// vary between packets of the same traffic and are left out.
+    pub fn ipv4_tcp() -> Self {
+        Self::new(40, 4)
+            .expect("non-zero shingle width")
+            .with_field(HeaderField::bytes(0, 1))
+            .with_field(HeaderField::masked(1, alloc::vec![0xFC]))
+            .with_field(HeaderField::bytes(9, 1))
+            .with_field(HeaderField::bytes(22, 2))
+            .with_field(HeaderField::masked(32, alloc::vec![0xF0, 0x3F]))
+    }
+
+    /// This profile with `field` added after the others.
+    pub fn with_field(mut self, field: HeaderField) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    /// The header fields, in order.
+    pub fn fields(&self) -> &[HeaderField] {
+        &self.fields
+    }
+
+    /// Offset of the first payload byte.
+    pub fn payload_offset(&self) -> usize {
+        self.payload_offset
+    }
+
+    /// The masked header fields of `packet`, concatenated in order.
+    pub fn header(&self, packet: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for field in &self.fields {
+            field.extract(packet, &mut out);
+        }
+        out
+    }
+
+    /// The payload of `packet`; empty if the packet ends before it starts.
+    pub fn payload<'a>(&self, packet: &'a [u8]) -> &'a [u8] {
+        packet.get(self.payload_offset..).unwrap_or_default()
+    }
+
+    /// The [`header`](Self::header) of `packet` followed by the
+    /// [`SUMMARY_BITS`]-bit SimHash of its payload shingles; all zeros for
+    /// an empty payload.
+    pub fn encode(&self, packet: &[u8]) -> Vec<u8> {
+        let mut summary = SimHash::new(SUMMARY_BITS).expect("a positive multiple of 8 bits");
+        summary.add_features(&self.shingles, self.payload(packet));
+        let mut out = self.header(packet);
+        out.extend(summary.finish());
+        out
+    }
+
+    /// The collapse of [`encode`](Self::encode) under `config`.
+    pub fn fingerprint(&self, packet: &[u8], config: &TbfConfig) -> Vec<u8> {
+        config.collapse(&self.encode(packet))
+    }
+}
+
+/// A rolling fingerprint of the last packets of a flow.
+///
+/// Every packet contributes its masked header as one feature and each of
+/// its payload shingles as another to a single SimHash; the oldest packet's
+/// features are taken back once more than `window` packets were pushed.
+/// Flows carrying the same traffic share most features, whatever the order
+/// of packets within the window.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::packet::{FlowFingerprint, PacketProfile};
+///
+/// let profile = PacketProfile::new(0, 3).unwrap();
+/// let mut flow = FlowFingerprint::new(profile.clone(), 2).unwrap();
+/// let mut replay = FlowFingerprint::new(profile, 2).unwrap();
+/// flow.push(b"GET /index.html HTTP/1.1");
+/// flow.push(b"GET /style.css HTTP/1.1");
+/// replay.push(b"GET /style.css HTTP/1.1");
+/// replay.push(b"GET /index.html HTTP/1.1");
+/// assert_eq!(flow.summary(), replay.summary());
+///
+/// // A third packet pushes the first out of both windows.
+/// flow.push(b"GET /app.js HTTP/1.1");
+/// replay.push(b"GET /app.js HTTP/1.1");
+/// assert_ne!(flow.summary(), replay.summary());
+/// assert_eq!(flow.len(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FlowFingerprint {
+    profile: PacketProfile,
+    window: usize,
+    packets: VecDeque<(Vec<u8>, Vec<u8>)>, // Header and payload of the packets in the window.
+    summary: SimHash,
+}
+
+impl FlowFingerprint {
+    /// An empty flow fingerprinted over its last `window` packets under
+    /// `profile`.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `window` is zero.
+    pub fn new(profile: PacketProfile, window: usize) -> Result<Self, Error> {
+        if window == 0 {
+            return Err(Error::InvalidParameter(
+                "flow window must hold at least one packet",
+            ));
+        }
+        Ok(Self {
+            profile,
+            window,
+            packets: VecDeque::with_capacity(window),
+            summary: SimHash::new(SUMMARY_BITS).expect("a positive multiple of 8 bits"),
+        })
+    }
+
+    /// The profile packets are read with.
+    pub fn profile(&self) -> &PacketProfile {
+        &self.profile
+    }
+
+    /// Number of packets in the window.
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    /// Whether no packet was pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Adds `packet`, dropping the oldest packet from a full window.
+    pub fn push(&mut self, packet: &[u8]) {
+        if self.packets.len() == self.window {
+            let (header, payload) = self.packets.pop_front().expect("window is non-zero");
+            self.summary.remove_weighted(&header_feature(&header), 1);
+            self.summary
+                .remove_features(&self.profile.shingles, &payload);
+        }
+        let header = self.profile.header(packet);
+        let payload = self.profile.payload(packet).to_vec();
+        self.summary.add(&header_feature(&header));
+        self.summary.add_features(&self.profile.shingles, &payload);
+        self.packets.push_back((header, payload));
+    }
+
+    /// The [`SUMMARY_BITS`]-bit SimHash of the packets in the window.
+    pub fn summary(&self) -> Vec<u8> {
+        self.summary.finish()
+    }
+
+    /// The collapse of the [`summary`](Self::summary) under `config`.
+    pub fn fingerprint(&self, config: &TbfConfig) -> Vec<u8> {
+        config.collapse(&self.summary())
+    }
+}
+
+/// A masked header as a feature, kept apart from payload shingles.
+fn header_feature(header: &[u8]) -> Vec<u8> {
+    let mut feature = Vec::with_capacity(header.len() + 1);
+    feature.push(0xFF); // Header features are one byte longer than shingles of their bytes.
+    feature.extend_from_slice(header);
+    feature
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::hamming;
+
+    #[test]
+    fn test_header_fields_are_masked_and_padded() {
+        let profile = PacketProfile::new(4, 2)
+            .unwrap()
+            .with_field(HeaderField::bytes(1, 2))
+            .with_field(HeaderField::masked(3, alloc::vec![0x0F, 0xFF]));
+        assert_eq!(
+            profile.header(&[0xAA, 0x01, 0x02, 0xF7, 0x33]),
+            [0x01, 0x02, 0x07, 0x33]
+        );
+        assert_eq!(profile.header(&[0xAA, 0x01]), [0x01, 0, 0, 0]);
+        assert_eq!(profile.payload(&[0xAA, 0x01]), b"");
+        assert_eq!(profile.encode(&[0xAA, 0x01]).len(), 4 + SUMMARY_BITS / 8);
+        assert_eq!(profile.encode(&[0xAA, 0x01])[4..], [0; SUMMARY_BITS / 8]);
+        assert_eq!(profile.fields().len(), 2);
+        assert!(PacketProfile::new(0, 0).is_err());
+    }
+
+    #[test]
+    fn test_small_payload_changes_move_few_bits() {
+        let profile = PacketProfile::ipv4_tcp();
+        let mut packet = alloc::vec![0u8; 40];
+        packet[0] = 0x45;
+        packet[9] = 6;
+        packet.extend(
+            b"POST /api/v1/upload HTTP/1.1\r\nHost: example.com\r\nUser-Agent: bot/7\r\n\r\n",
+        );
+        let mut variant = packet.clone();
+        variant[12..20].copy_from_slice(&[192, 168, 1, 7, 8, 8, 8, 8]); // Addresses.
+        variant[24..28].copy_from_slice(&[1, 2, 3, 4]); // Sequence number.
+        variant[40 + 68] = b'9'; // bot/9.
+        let other = {
+            let mut other = packet[..40].to_vec();
+            other.extend(b"\x16\x03\x01\x02\x00\x01\x00\x01\xfc\x03\x03 random TLS hello bytes");
+            other
+        };
+        let (a, b, c) = (
+            profile.encode(&packet),
+            profile.encode(&variant),
+            profile.encode(&other),
+        );
+        assert_eq!(a[..7], b[..7]);
+        assert!(hamming(&a, &b) < 12, "{}", hamming(&a, &b));
+        assert!(hamming(&a, &c) > 30, "{}", hamming(&a, &c));
+
+        packet[23] = 80; // Another destination port.
+        assert_ne!(profile.header(&packet), profile.header(&variant));
+    }
+
+    #[test]
+    fn test_flow_window_rolls() {
+        let profile = PacketProfile::new(0, 3)
+            .unwrap()
+            .with_field(HeaderField::bytes(0, 1));
+        let packets: [&[u8]; 4] = [b"alpha beta", b"gamma delta", b"epsilon zeta", b"eta theta"];
+        let mut rolling = FlowFingerprint::new(profile.clone(), 2).unwrap();
+        for (i, packet) in packets.iter().enumerate() {
+            rolling.push(packet);
+            let mut fresh = FlowFingerprint::new(profile.clone(), 2).unwrap();
+            for packet in &packets[i.saturating_sub(1)..=i] {
+                fresh.push(packet);
+            }
+            assert_eq!(rolling.summary(), fresh.summary(), "{i}");
+            assert_eq!(rolling.summary, fresh.summary);
+        }
+        let config = TbfConfig::new(0.125);
+        assert_eq!(
+            rolling.fingerprint(&config),
+            config.collapse(&rolling.summary())
+        );
+        assert!(FlowFingerprint::new(profile, 0).is_err());
+    }
+}
@@ -0,0 +1,335 @@
+//! Fuzzy password-authenticated key exchange.
+//!
+//! Two parties each hold a noisy copy of the same secret (a shared phrase
+//! with typos, two readings of a sensor). They end up with a common session
+//! key iff their copies collapse to the same value under the initiator's
+//! tolerance; otherwise key confirmation fails on both sides.
+//!
+//! The collapsed value is low-entropy, so it is never used as a plain key.
+//! Instead the [`FuzzyExtractor`] output selects the Diffie-Hellman
+//! generator of an X25519 exchange (the CPace construction): the generator
+//! is obtained by hashing onto the curve with Elligator 2, so an active
+//! attacker learns at most whether a single guess was right per session and
+//! a passive one learns nothing.
+//!
+//! ```text
+//! Initiator                                   Responder
+//! (key, helper) = generate(input_a)
+//! Y_a = y_a * G(key)          -- Offer -->    key' = reproduce(input_b, helper)
+//!                                             Y_b = y_b * G(key')
+//!                             <-- Reply --    tag_b
+//! check tag_b
+//!                             -- Confirmation -->  check tag_a
+//! ```
+//!
+//! All messages serialize to fixed-size byte strings with `to_bytes` /
+//! `from_bytes`; transport is up to the caller.
+
+use crate::crypto::ct_eq;
+use crate::crypto::curve25519::{elligator2, x25519};
+use crate::crypto::hmac::{HmacSha256, hkdf_expand, hkdf_extract};
+use crate::crypto::sha256::Sha256;
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use crate::fuzzy_extractor::{ExtractedKey, FuzzyExtractor, HelperData};
+
+/// Domain separation labels.
+const GENERATOR_LABEL: &[u8] = b"pensieve/pake/v1/generator";
+const TRANSCRIPT_LABEL: &[u8] = b"pensieve/pake/v1/transcript";
+const SESSION_INFO: &[u8] = b"pensieve/pake/v1/session";
+const INITIATOR_CONFIRM_INFO: &[u8] = b"pensieve/pake/v1/confirm/initiator";
+const RESPONDER_CONFIRM_INFO: &[u8] = b"pensieve/pake/v1/confirm/responder";
+
+/// First message, sent by the initiator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Offer {
+    helper: HelperData, // Lets the responder reproduce the extractor key.
+    share: [u8; 32],    // Initiator's public share Y_a.
+}
+
+impl Offer {
+    /// Length of the serialized form.
+    pub const ENCODED_LEN: usize = HelperData::ENCODED_LEN + 32;
+
+    /// Serializes as `helper || share`.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut out = [0u8; Self::ENCODED_LEN];
+        out[..HelperData::ENCODED_LEN].copy_from_slice(&self.helper.to_bytes());
+        out[HelperData::ENCODED_LEN..].copy_from_slice(&self.share);
+        out
+    }
+
+    /// Parses the output of [`Offer::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        check_len(bytes, Self::ENCODED_LEN)?;
+        let helper = HelperData::from_bytes(&bytes[..HelperData::ENCODED_LEN])?;
+        let share = bytes[HelperData::ENCODED_LEN..]
+            .try_into()
+            .expect("32 bytes");
+        Ok(Self { helper, share })
+    }
+}
+
+/// Second message, sent by the responder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reply {
+    share: [u8; 32],        // Responder's public share Y_b.
+    confirmation: [u8; 32], // Responder's key confirmation tag.
+}
+
+impl Reply {
+    /// Length of the serialized form.
+    pub const ENCODED_LEN: usize = 64;
+
+    /// Serializes as `share || confirmation`.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut out = [0u8; Self::ENCODED_LEN];
+        out[..32].copy_from_slice(&self.share);
+        out[32..].copy_from_slice(&self.confirmation);
+        out
+    }
+
+    /// Parses the output of [`Reply::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        check_len(bytes, Self::ENCODED_LEN)?;
+        Ok(Self {
+            share: bytes[..32].try_into().expect("32 bytes"),
+            confirmation: bytes[32..].try_into().expect("32 bytes"),
+        })
+    }
+}
+
+/// Third message, sent by the initiator once it has accepted the reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Confirmation {
+    tag: [u8; 32],
+}
+
+impl Confirmation {
+    /// Length of the serialized form.
+    pub const ENCODED_LEN: usize = 32;
+
+    /// Serializes the confirmation tag.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        self.tag
+    }
+
+    /// Parses the output of [`Confirmation::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        check_len(bytes, Self::ENCODED_LEN)?;
+        Ok(Self {
+            tag: bytes.try_into().expect("32 bytes"),
+        })
+    }
+}
+
+/// The shared key both parties end up with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SessionKey(ExtractedKey);
+
+impl SessionKey {
+    /// The raw key bytes.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        self.0.as_bytes()
+    }
+}
+
+impl core::fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("SessionKey(..)")
+    }
+}
+
+/// Initiator state between sending the [`Offer`] and receiving the [`Reply`].
+pub struct Initiator {
+    scalar: [u8; 32], // Ephemeral secret y_a.
+    offer: Offer,     // Kept for the transcript.
+}
+
+impl Initiator {
+    /// Enrolls `input` with a fresh salt and produces the first message.
+    pub fn start(
+        input: &[u8],
+        extractor: &FuzzyExtractor,
+        rng: &mut impl EntropySource,
+    ) -> (Self, Offer) {
+        let (key, helper) = extractor.generate(input, rng);
+        let mut scalar = [0u8; 32];
+        rng.fill_bytes(&mut scalar);
+        let share = x25519(&scalar, &generator(&key, &helper));
+        let offer = Offer { helper, share };
+        (Self { scalar, offer }, offer)
+    }
+
+    /// Processes the responder's reply.
+    ///
+    /// On success returns the session key and the [`Confirmation`] to send
+    /// back. Fails with [`Error::KeyConfirmationFailed`] if the responder's
+    /// input did not collapse to the same value.
+    pub fn finish(self, reply: &Reply) -> Result<(SessionKey, Confirmation), Error> {
+        let keys = KeySchedule::derive(&self.scalar, &reply.share, &self.offer, &reply.share)?;
+        if !ct_eq(&keys.responder_tag, &reply.confirmation) {
+            return Err(Error::KeyConfirmationFailed);
+        }
+        let confirmation = Confirmation {
+            tag: keys.initiator_tag,
+        };
+        Ok((keys.session, confirmation))
+    }
+}
+
+/// Responder state between sending the [`Reply`] and receiving the [`Confirmation`].
+pub struct Responder {
+    session: SessionKey,
+    expected_tag: [u8; 32],
+}
+
+impl Responder {
+    /// Answers an [`Offer`] using the responder's own noisy copy of the secret.
+    pub fn respond(
+        input: &[u8],
+        offer: &Offer,
+        rng: &mut impl EntropySource,
+    ) -> Result<(Self, Reply), Error> {
+        let key = FuzzyExtractor::reproduce(input, &offer.helper);
+        let mut scalar = [0u8; 32];
+        rng.fill_bytes(&mut scalar);
+        let share = x25519(&scalar, &generator(&key, &offer.helper));
+        let keys = KeySchedule::derive(&scalar, &offer.share, offer, &share)?;
+        let reply = Reply {
+            share,
+            confirmation: keys.responder_tag,
+        };
+        let state = Self {
+            session: keys.session,
+            expected_tag: keys.initiator_tag,
+        };
+        Ok((state, reply))
+    }
+
+    /// Checks the initiator's confirmation and releases the session key.
+    pub fn finish(self, confirmation: &Confirmation) -> Result<SessionKey, Error> {
+        if !ct_eq(&self.expected_tag, &confirmation.tag) {
+            return Err(Error::KeyConfirmationFailed);
+        }
+        Ok(self.session)
+    }
+}
+
+/// Keys derived from the Diffie-Hellman result and the transcript.
+struct KeySchedule {
+    session: SessionKey,
+    initiator_tag: [u8; 32],
+    responder_tag: [u8; 32],
+}
+
+impl KeySchedule {
+    /// Computes `scalar * peer_share` and derives all session secrets from it,
+    /// bound to the full transcript (`offer`, `responder_share`).
+    fn derive(
+        scalar: &[u8; 32],
+        peer_share: &[u8; 32],
+        offer: &Offer,
+        responder_share: &[u8; 32],
+    ) -> Result<Self, Error> {
+        let shared = x25519(scalar, peer_share);
+        if ct_eq(&shared, &[0u8; 32]) {
+            return Err(Error::LowOrderPoint);
+        }
+
+        let transcript = Sha256::digest(&[TRANSCRIPT_LABEL, &offer.to_bytes(), responder_share]);
+        let prk = hkdf_extract(&transcript, &shared);
+
+        let mut session = [0u8; 32];
+        hkdf_expand(&prk, SESSION_INFO, &mut session);
+        let mut initiator_key = [0u8; 32];
+        hkdf_expand(&prk, INITIATOR_CONFIRM_INFO, &mut initiator_key);
+        let mut responder_key = [0u8; 32];
+        hkdf_expand(&prk, RESPONDER_CONFIRM_INFO, &mut responder_key);
+
+        Ok(Self {
+            session: SessionKey(ExtractedKey::from_bytes(session)),
+            initiator_tag: HmacSha256::mac(&initiator_key, &[&transcript]),
+            responder_tag: HmacSha256::mac(&responder_key, &[&transcript]),
+        })
+    }
+}
+
+/// Hashes the extractor key (and the session salt) onto the curve.
+fn generator(key: &ExtractedKey, helper: &HelperData) -> [u8; 32] {
+    let seed = Sha256::digest(&[GENERATOR_LABEL, helper.salt(), key.as_bytes()]);
+    elligator2(&seed)
+}
+
+fn check_len(bytes: &[u8], expected: usize) -> Result<(), Error> {
+    if bytes.len() != expected {
+        return Err(Error::InvalidLength {
+            expected,
+            found: bytes.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    /// Runs the full exchange, serializing every message on the way.
+    fn run(a: &[u8], b: &[u8]) -> Result<(SessionKey, SessionKey), Error> {
+        let extractor = FuzzyExtractor::new(0.05);
+        let (initiator, offer) = Initiator::start(a, &extractor, &mut seeded(3));
+        let offer = Offer::from_bytes(&offer.to_bytes())?;
+        let (responder, reply) = Responder::respond(b, &offer, &mut seeded(11))?;
+        let reply = Reply::from_bytes(&reply.to_bytes())?;
+        let (key_a, confirmation) = initiator.finish(&reply)?;
+        let confirmation = Confirmation::from_bytes(&confirmation.to_bytes())?;
+        let key_b = responder.finish(&confirmation)?;
+        Ok((key_a, key_b))
+    }
+
+    #[test]
+    fn test_matching_inputs_agree() {
+        let mut a = [0u8; 16];
+        a[0] = 0b11111111;
+        let mut b = a;
+        b[0] ^= 0b00000001; // 1 bit flip, within 5%
+
+        let (key_a, key_b) = run(&a, &b).unwrap();
+        assert_eq!(key_a, key_b);
+    }
+
+    #[test]
+    fn test_mismatching_inputs_fail_confirmation() {
+        let mut a = [0u8; 16];
+        a[0] = 0b11111111;
+        let mut b = a;
+        b[0] ^= 0b11111111; // 8 bit flips, exceeds 5%
+
+        assert_eq!(run(&a, &b).unwrap_err(), Error::KeyConfirmationFailed);
+    }
+
+    #[test]
+    fn test_low_order_share_rejected() {
+        let extractor = FuzzyExtractor::new(0.05);
+        let (_, mut offer) = Initiator::start(&[1; 16], &extractor, &mut seeded(3));
+        offer.share = [0u8; 32]; // u = 0 is a point of order 2
+        assert!(matches!(
+            Responder::respond(&[1; 16], &offer, &mut seeded(5)),
+            Err(Error::LowOrderPoint)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_reply_rejected() {
+        let extractor = FuzzyExtractor::new(0.05);
+        let (initiator, offer) = Initiator::start(&[7; 16], &extractor, &mut seeded(3));
+        let (_, mut reply) = Responder::respond(&[7; 16], &offer, &mut seeded(5)).unwrap();
+        reply.confirmation[0] ^= 1;
+        assert_eq!(
+            initiator.finish(&reply).unwrap_err(),
+            Error::KeyConfirmationFailed
+        );
+    }
+}
@@ -0,0 +1,403 @@
+//! Collapsing one input on many machines.
+//!
+//! The collapse depends on the input only through the popcount of every
+//! chunk, and popcounts add up. A [`PartialCollapse`] holds the popcounts
+//! of the slices of an input one worker has counted; partials of the same
+//! input [`merge`](PartialCollapse::merge) by adding them, in any grouping
+//! and any order, and once every byte is counted the merged partial
+//! [`finish`](PartialCollapse::finish)es into the digest. Workers of a
+//! map-reduce job each count a slice of a huge input and ship their
+//! partial to the coordinator as bytes.
+//!
+//! Like the digest, the popcounts are computed in constant time, but they
+//! reveal more about the input than the digest does: treat a partial of a
+//! secret input as secret.
+//!
+//! [`PartialCollapse::to_bytes`] writes it with the configuration it counts
+//! for, in the style of a [`DigestEnvelope`](crate::envelope::DigestEnvelope):
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 4 | magic `PNSR` |
+//! | 1 | format version (currently 1) |
+//! | 1 | algorithm version ([`TbfConfig::ALGORITHM_VERSION`]) |
+//! | 1 | bit order ([`BitOrder::id`](crate::BitOrder::id)) |
+//! | 1 | output transform ([`FinalTransform::id`](crate::FinalTransform::id)) |
+//! | 1 | mask base of [`FinalTransform::XorMask`](crate::FinalTransform::XorMask), otherwise 0 |
+//! | 1 | thresholding ([`Thresholding::id`](crate::Thresholding::id)) |
+//! | 4 | tolerance, f32 little-endian |
+//! | 8 | input length in bytes, little-endian |
+//! | 1 | number of chunks |
+//! | 4 | number of counted byte ranges, little-endian |
+//! | 16 each | counted byte ranges, start and end as u64 little-endian |
+//! | 4 each | chunk popcounts, little-endian |
+//! | 4 | first 4 bytes of SHA-256 over everything above |
+//!
+//! The key of [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) is
+//! never stored; such partials are read with
+//! [`PartialCollapse::from_bytes_keyed`].
+
+use crate::header::{self, CHECKSUM_LEN, Format};
+use crate::{ChunkLayout, Error, TbfConfig, ct_popcount_range_in};
+use alloc::vec::Vec;
+
+/// Header of every serialized partial.
+const FORMAT: Format = Format {
+    magic: *b"PNSR",
+    version: 1,
+    foreign: "not a pensieve partial collapse",
+    unsupported: "unsupported partial collapse format version",
+    needs_key: "keyed transform needs from_bytes_keyed",
+};
+/// Bytes before the byte ranges.
+const HEADER_LEN: usize = 27;
+
+/// The chunk popcounts of the counted slices of one input.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::partial::PartialCollapse;
+///
+/// let config = TbfConfig::new(0.125);
+/// let input = vec![0xF7u8; 1000];
+/// // Three workers, one slice each.
+/// let partials: Vec<Vec<u8>> = [0..300, 300..301, 301..1000]
+///     .into_iter()
+///     .map(|range| {
+///         let start = range.start;
+///         PartialCollapse::slice(config, input.len(), start, &input[range])
+///             .unwrap()
+///             .to_bytes()
+///     })
+///     .collect();
+///
+/// let mut merged = PartialCollapse::new(config, input.len());
+/// for bytes in partials.iter().rev() {
+///     merged = merged.merge(&PartialCollapse::from_bytes(bytes).unwrap()).unwrap();
+/// }
+/// assert_eq!(merged.finish().unwrap(), config.collapse(&input));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialCollapse {
+    config: TbfConfig,
+    input_len: usize,
+    counted: Vec<(usize, usize)>, // Disjoint byte ranges, sorted and coalesced.
+    sums: Vec<u32>,               // Set bits counted in every chunk.
+}
+
+impl PartialCollapse {
+    /// A partial of an input of `input_len` bytes under `config` with
+    /// nothing counted yet; merging it changes nothing.
+    pub fn new(config: TbfConfig, input_len: usize) -> Self {
+        Self {
+            config,
+            input_len,
+            counted: Vec::new(),
+            sums: alloc::vec![0; config.chunk_count(input_len)],
+        }
+    }
+
+    /// A partial holding the popcounts of `bytes`, found at `offset` in an
+    /// input of `input_len` bytes under `config`.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `bytes` extends past the
+    /// end of the input.
+    pub fn slice(
+        config: TbfConfig,
+        input_len: usize,
+        offset: usize,
+        bytes: &[u8],
+    ) -> Result<Self, Error> {
+        let end = offset
+            .checked_add(bytes.len())
+            .filter(|&end| end <= input_len)
+            .ok_or(Error::InvalidParameter("slice extends past the input"))?;
+        let mut partial = Self::new(config, input_len);
+        let Some(layout) = partial.layout() else {
+            return Ok(partial); // Only an empty input has no layout.
+        };
+        let (from, to) = (offset * 8, end * 8);
+        for (sum, (start, end)) in partial.sums.iter_mut().zip(layout.ranges()) {
+            // Chunk boundaries are public; only the bits inside are secret.
+            let (start, end) = (start.max(from), end.min(to));
+            if start < end {
+                *sum = ct_popcount_range_in(bytes, start - from, end - from, config.bit_order());
+            }
+        }
+        if !bytes.is_empty() {
+            partial.counted.push((offset, end));
+        }
+        Ok(partial)
+    }
+
+    /// The configuration the input is collapsed under.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// Length of the whole input in bytes.
+    pub fn input_len(&self) -> usize {
+        self.input_len
+    }
+
+    /// The byte ranges counted so far, sorted, with adjacent ones joined.
+    pub fn counted(&self) -> &[(usize, usize)] {
+        &self.counted
+    }
+
+    /// Whether every byte of the input has been counted.
+    pub fn is_complete(&self) -> bool {
+        self.input_len == 0 || self.counted == [(0, self.input_len)]
+    }
+
+    /// The partial counting what `self` and `other` count.
+    ///
+    /// Merging is associative and commutative. Fails with
+    /// [`Error::InvalidParameter`] if the partials belong to different
+    /// configurations or input lengths, or if both counted the same byte.
+    pub fn merge(&self, other: &Self) -> Result<Self, Error> {
+        if self.config != other.config || self.input_len != other.input_len {
+            return Err(Error::InvalidParameter("partials of different collapses"));
+        }
+        let mut ranges: Vec<(usize, usize)> =
+            self.counted.iter().chain(&other.counted).copied().collect();
+        ranges.sort_unstable();
+        let mut counted: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match counted.last_mut() {
+                Some(last) if start < last.1 => {
+                    return Err(Error::InvalidParameter("partials overlap"));
+                }
+                Some(last) if start == last.1 => last.1 = end,
+                _ => counted.push((start, end)),
+            }
+        }
+        Ok(Self {
+            config: self.config,
+            input_len: self.input_len,
+            counted,
+            sums: self
+                .sums
+                .iter()
+                .zip(&other.sums)
+                .map(|(a, b)| a + b)
+                .collect(),
+        })
+    }
+
+    /// The digest of the input, in constant time.
+    ///
+    /// Fails with [`Error::InvalidLength`], reporting how many bytes were
+    /// counted, unless the partial [`is_complete`](Self::is_complete).
+    pub fn finish(&self) -> Result<Vec<u8>, Error> {
+        if !self.is_complete() {
+            return Err(Error::InvalidLength {
+                expected: self.input_len,
+                found: self.counted.iter().map(|(start, end)| end - start).sum(),
+            });
+        }
+        Ok(match self.layout() {
+            None => Vec::new(),
+            Some(layout) => self
+                .config
+                .transform()
+                .apply(&layout.levels(&self.sums), self.input_len),
+        })
+    }
+
+    fn layout(&self) -> Option<ChunkLayout> {
+        ChunkLayout::for_config(self.input_len * 8, &self.config)
+    }
+
+    /// Serializes in the format described in the [module documentation](self).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = HEADER_LEN + 16 * self.counted.len() + 4 * self.sums.len() + CHECKSUM_LEN;
+        let mut out = FORMAT.write(&self.config, len);
+        out.extend_from_slice(&(self.input_len as u64).to_le_bytes());
+        out.push(self.sums.len() as u8);
+        out.extend_from_slice(&(self.counted.len() as u32).to_le_bytes());
+        for &(start, end) in &self.counted {
+            out.extend_from_slice(&(start as u64).to_le_bytes());
+            out.extend_from_slice(&(end as u64).to_le_bytes());
+        }
+        for sum in &self.sums {
+            out.extend_from_slice(&sum.to_le_bytes());
+        }
+        header::seal(&mut out);
+        out
+    }
+
+    /// Parses the output of [`PartialCollapse::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidLength`] if `bytes` is truncated or holds the
+    /// wrong number of popcounts for its input length, with
+    /// [`Error::InvalidChecksum`] on corruption and with
+    /// [`Error::InvalidParameter`] for partials this build cannot interpret or
+    /// whose ranges or popcounts do not fit the input. Partials under
+    /// [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) need
+    /// [`PartialCollapse::from_bytes_keyed`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, None)
+    }
+
+    /// [`PartialCollapse::from_bytes`] for partials that may be under
+    /// [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) with
+    /// `key`.
+    pub fn from_bytes_keyed(bytes: &[u8], key: &[u8; 32]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, Some(key))
+    }
+
+    fn from_bytes_with(bytes: &[u8], key: Option<&[u8; 32]>) -> Result<Self, Error> {
+        let (config, body) = FORMAT.open(bytes, HEADER_LEN, key)?;
+        let input_len = header::read_input_len(bytes)?;
+        let to_usize = |field: &[u8]| {
+            usize::try_from(u64::from_le_bytes(field.try_into().expect("8 bytes")))
+                .map_err(|_| Error::InvalidParameter("input length overflows"))
+        };
+        let chunks = config.chunk_count(input_len);
+        let ranges = u32::from_le_bytes(bytes[23..27].try_into().expect("4 bytes")) as usize;
+        let rest = &body[HEADER_LEN..];
+        if usize::from(bytes[22]) != chunks
+            || ranges
+                .checked_mul(16)
+                .is_none_or(|len| rest.len() != len + 4 * chunks)
+        {
+            return Err(Error::InvalidLength {
+                expected: HEADER_LEN + 16 * ranges + 4 * chunks + CHECKSUM_LEN,
+                found: bytes.len(),
+            });
+        }
+        let (ranges, sums) = rest.split_at(rest.len() - 4 * chunks);
+
+        let mut partial = Self::new(config, input_len);
+        for range in ranges.chunks_exact(16) {
+            let (start, end) = (to_usize(&range[..8])?, to_usize(&range[8..])?);
+            // Ranges as `merge` leaves them: sorted, disjoint and coalesced.
+            let follows = partial.counted.last().is_none_or(|&(_, last)| start > last);
+            if start >= end || end > input_len || !follows {
+                return Err(Error::InvalidParameter("invalid counted ranges"));
+            }
+            partial.counted.push((start, end));
+        }
+        partial.sums = sums
+            .chunks_exact(4)
+            .map(|sum| u32::from_le_bytes(sum.try_into().expect("4 bytes")))
+            .collect();
+        if let Some(layout) = partial.layout()
+            && layout
+                .ranges()
+                .zip(&partial.sums)
+                .any(|((start, end), &sum)| sum as usize > end - start)
+        {
+            return Err(Error::InvalidParameter("popcount exceeds its chunk"));
+        }
+        Ok(partial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use crate::{BitOrder, FinalTransform, Thresholding};
+
+    #[test]
+    fn test_merging_in_any_grouping_gives_the_digest() {
+        let mut input = alloc::vec![0u8; 777];
+        seeded(425)(&mut input);
+        let configs = [
+            TbfConfig::default(),
+            TbfConfig::new(0.05)
+                .with_bit_order(BitOrder::LsbFirst)
+                .with_thresholding(Thresholding::Mean),
+            TbfConfig::new(0.2).with_transform(FinalTransform::Hash),
+        ];
+        for config in configs {
+            let cuts = [0, 1, 97, 98, 400, 776, 777];
+            let slices: Vec<PartialCollapse> = cuts
+                .windows(2)
+                .map(|w| PartialCollapse::slice(config, 777, w[0], &input[w[0]..w[1]]).unwrap())
+                .collect();
+            let left = slices
+                .iter()
+                .try_fold(PartialCollapse::new(config, 777), |acc, p| acc.merge(p))
+                .unwrap();
+            let right = slices
+                .iter()
+                .rev()
+                .try_fold(PartialCollapse::new(config, 777), |acc, p| p.merge(&acc))
+                .unwrap();
+            let pairs = slices[0]
+                .merge(&slices[3])
+                .and_then(|a| slices[1].merge(&slices[4]).and_then(|b| a.merge(&b)))
+                .and_then(|ab| slices[2].merge(&slices[5]).and_then(|c| c.merge(&ab)))
+                .unwrap();
+            assert_eq!(left, right);
+            assert_eq!(left, pairs);
+            assert_eq!(left.counted(), [(0, 777)]);
+            assert_eq!(left.finish(), Ok(config.collapse(&input)));
+        }
+        let empty = PartialCollapse::new(TbfConfig::default(), 0);
+        assert_eq!(empty.finish(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_merge_rejects_mismatches_and_overlaps() {
+        let config = TbfConfig::default();
+        let input = [0xA5u8; 64];
+        let head = PartialCollapse::slice(config, 64, 0, &input[..40]).unwrap();
+        let tail = PartialCollapse::slice(config, 64, 30, &input[30..]).unwrap();
+        assert!(head.merge(&tail).is_err());
+        assert_eq!(
+            head.finish(),
+            Err(Error::InvalidLength {
+                expected: 64,
+                found: 40
+            })
+        );
+        let other = PartialCollapse::slice(TbfConfig::new(0.2), 64, 40, &input[40..]).unwrap();
+        assert!(head.merge(&other).is_err());
+        assert!(PartialCollapse::slice(config, 64, 60, &input[..8]).is_err());
+        assert!(PartialCollapse::slice(config, 64, usize::MAX, &[1]).is_err());
+    }
+
+    #[test]
+    fn test_serialization_round_trips_and_rejects_corruption() {
+        let key = [9u8; 32];
+        let input = [0x3Cu8; 100];
+        for config in [
+            TbfConfig::new(0.1),
+            TbfConfig::default().with_transform(FinalTransform::KeyedPrf { key }),
+        ] {
+            let partial = PartialCollapse::slice(config, 200, 50, &input).unwrap();
+            let bytes = partial.to_bytes();
+            assert_eq!(
+                PartialCollapse::from_bytes_keyed(&bytes, &key).as_ref(),
+                Ok(&partial)
+            );
+        }
+        let partial = PartialCollapse::slice(TbfConfig::new(0.1), 200, 50, &input).unwrap();
+        let mut bytes = partial.to_bytes();
+        assert_eq!(PartialCollapse::from_bytes(&bytes).as_ref(), Ok(&partial));
+        bytes[30] ^= 1;
+        assert_eq!(
+            PartialCollapse::from_bytes(&bytes),
+            Err(Error::InvalidChecksum)
+        );
+        assert!(PartialCollapse::from_bytes(&bytes[..10]).is_err());
+        let keyed = TbfConfig::default().with_transform(FinalTransform::KeyedPrf { key });
+        let bytes = PartialCollapse::new(keyed, 8).to_bytes();
+        assert!(PartialCollapse::from_bytes(&bytes).is_err());
+
+        // A length whose bit count overflows, under a valid checksum.
+        let mut body = PartialCollapse::new(TbfConfig::new(0.1), 8).to_bytes();
+        body.truncate(body.len() - CHECKSUM_LEN);
+        body[14..22].copy_from_slice(&u64::MAX.to_le_bytes());
+        header::seal(&mut body);
+        assert_eq!(
+            PartialCollapse::from_bytes(&body),
+            Err(Error::InvalidParameter("input length overflows"))
+        );
+    }
+}
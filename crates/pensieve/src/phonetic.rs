@@ -0,0 +1,786 @@
+//! Phonetic codes for names.
+//!
+//! Names are spelled many ways but pronounced few: "Smith" and "Smyth",
+//! "Schmidt" and "Shmit", "Catherine" and "Kathryn". Bit tolerance does not
+//! help there, since the spellings differ in length and letters. A
+//! [`Phonetic`] algorithm first reduces a name to a short code of how it
+//! sounds, which [`fingerprints`] then collapses:
+//!
+//! - [`soundex`] is the American Soundex of the US census: a letter and
+//!   three digits. It is coarse, and keeps the first letter as spelled, so
+//!   "Catherine" and "Kathryn" still differ.
+//! - [`double_metaphone`] is Lawrence Philips' Double Metaphone, as in his
+//!   original implementation and PostgreSQL's `dmetaphone`: a primary code
+//!   of up to four consonant sounds, and an alternate for names whose
+//!   pronunciation depends on their origin ("Schmidt" is `XMT`, or `SMT`).
+//!
+//! Both read ASCII letters, plus "Ç" and "Ñ" for Double Metaphone, and skip
+//! everything else, so transliterate other scripts first. Encode the parts
+//! of a full name separately: the codes only look at the first few sounds.
+
+use crate::TbfConfig;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A phonetic algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phonetic {
+    /// [`soundex`].
+    Soundex,
+    /// [`double_metaphone`].
+    DoubleMetaphone,
+}
+
+/// The phonetic codes of `name`: one for Soundex, and the primary code
+/// followed by a different alternate, if any, for Double Metaphone. A name
+/// without letters has no codes.
+pub fn encode(name: &str, phonetic: Phonetic) -> Vec<String> {
+    let mut codes = Vec::with_capacity(2);
+    match phonetic {
+        Phonetic::Soundex => codes.push(soundex(name)),
+        Phonetic::DoubleMetaphone => {
+            let (primary, alternate) = double_metaphone(name);
+            codes.push(primary);
+            if alternate != codes[0] {
+                codes.push(alternate);
+            }
+        }
+    }
+    codes.retain(|code| !code.is_empty());
+    codes
+}
+
+/// The collapses under `config` of the [`encode`]d codes of `name`; two
+/// names sound alike if any of their fingerprints are equal.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::phonetic::{Phonetic, fingerprints};
+///
+/// let config = TbfConfig::new(0.125);
+/// let a = fingerprints("Schmidt", Phonetic::DoubleMetaphone, &config);
+/// let b = fingerprints("Smith", Phonetic::DoubleMetaphone, &config);
+/// assert!(a.iter().any(|fingerprint| b.contains(fingerprint)));
+/// ```
+pub fn fingerprints(name: &str, phonetic: Phonetic, config: &TbfConfig) -> Vec<Vec<u8>> {
+    encode(name, phonetic)
+        .iter()
+        .map(|code| config.collapse(code.as_bytes()))
+        .collect()
+}
+
+/// The American Soundex of `name`: its first letter, uppercase, then the
+/// digits of the next three consonant sounds, padded with zeros; empty if
+/// `name` has no ASCII letters.
+///
+/// Adjacent letters with the same digit count once, also across "h" and
+/// "w" but not across vowels.
+pub fn soundex(name: &str) -> String {
+    fn digit(letter: u8) -> u8 {
+        b"01230120022455012623010202"[usize::from(letter - b'A')]
+    }
+    let mut letters = name
+        .bytes()
+        .filter(u8::is_ascii_alphabetic)
+        .map(|b| b.to_ascii_uppercase());
+    let Some(first) = letters.next() else {
+        return String::new();
+    };
+    let mut code = String::with_capacity(4);
+    code.push(char::from(first));
+    let mut last = digit(first);
+    for letter in letters {
+        let d = digit(letter);
+        if d != b'0' && d != last {
+            code.push(char::from(d));
+            if code.len() == 4 {
+                break;
+            }
+        }
+        if !matches!(letter, b'H' | b'W') {
+            last = d;
+        }
+    }
+    while code.len() < 4 {
+        code.push('0');
+    }
+    code
+}
+
+/// The Double Metaphone codes of `name`, primary and alternate, each of up
+/// to four characters from `0AFHJKLMNPRSTX` (`0` is "th"); empty if `name`
+/// has no letters.
+pub fn double_metaphone(name: &str) -> (String, String) {
+    DoubleMetaphone::new(name).encode()
+}
+
+/// Maximum length of a Double Metaphone code.
+const METAPHONE_LEN: usize = 4;
+
+/// The state of one Double Metaphone encoding.
+struct DoubleMetaphone {
+    /// The name, uppercase, followed by five spaces.
+    word: Vec<char>,
+    /// Length of the name without the padding.
+    length: isize,
+    slavo_germanic: bool,
+    primary: String,
+    alternate: String,
+}
+
+impl DoubleMetaphone {
+    fn new(name: &str) -> Self {
+        let mut word: Vec<char> = name.chars().flat_map(char::to_uppercase).collect();
+        let length = word.len() as isize;
+        word.extend([' '; 5]);
+        let text: String = word.iter().collect();
+        let slavo_germanic = ["W", "K", "CZ", "WITZ"].iter().any(|s| text.contains(s));
+        Self {
+            word,
+            length,
+            slavo_germanic,
+            primary: String::new(),
+            alternate: String::new(),
+        }
+    }
+
+    /// The character at `position`, or NUL outside the padded name.
+    fn at(&self, position: isize) -> char {
+        usize::try_from(position)
+            .ok()
+            .and_then(|p| self.word.get(p))
+            .copied()
+            .unwrap_or('\0')
+    }
+
+    /// Whether one of `options`, all `len` characters long, starts at
+    /// `start`.
+    fn at_any(&self, start: isize, len: usize, options: &[&str]) -> bool {
+        let Ok(start) = usize::try_from(start) else {
+            return false;
+        };
+        let Some(slice) = self.word.get(start..(start + len).min(self.word.len())) else {
+            return false;
+        };
+        options
+            .iter()
+            .any(|option| option.chars().eq(slice.iter().copied()))
+    }
+
+    fn is_vowel(&self, position: isize) -> bool {
+        matches!(self.at(position), 'A' | 'E' | 'I' | 'O' | 'U' | 'Y')
+    }
+
+    /// Whether the name starts like a Germanic one.
+    fn germanic(&self) -> bool {
+        self.at_any(0, 4, &["VAN ", "VON "]) || self.at_any(0, 3, &["SCH"])
+    }
+
+    fn add(&mut self, primary: &str, alternate: &str) {
+        self.primary.push_str(primary);
+        self.alternate.push_str(alternate);
+    }
+
+    fn add_both(&mut self, code: &str) {
+        self.add(code, code);
+    }
+
+    /// Adds `code` and steps over `letter` and a doubled `letter`.
+    fn add_letter(&mut self, current: isize, letter: char, code: &str) -> isize {
+        self.add_both(code);
+        if self.at(current + 1) == letter {
+            current + 2
+        } else {
+            current + 1
+        }
+    }
+
+    fn encode(mut self) -> (String, String) {
+        let last = self.length - 1;
+        let mut current = 0;
+        // Silent first letters.
+        if self.at_any(0, 2, &["GN", "KN", "PN", "WR", "PS"]) {
+            current += 1;
+        }
+        // An initial "X" is pronounced "Z", as in "Xavier".
+        if self.at(0) == 'X' {
+            self.add_both("S");
+            current += 1;
+        }
+        while (self.primary.len() < METAPHONE_LEN || self.alternate.len() < METAPHONE_LEN)
+            && current < self.length
+        {
+            current = match self.at(current) {
+                'A' | 'E' | 'I' | 'O' | 'U' | 'Y' => {
+                    if current == 0 {
+                        self.add_both("A"); // Only initial vowels count.
+                    }
+                    current + 1
+                }
+                'B' => self.add_letter(current, 'B', "P"),
+                'Ç' => {
+                    self.add_both("S");
+                    current + 1
+                }
+                'C' => self.c(current),
+                'D' => {
+                    if self.at_any(current, 2, &["DG"]) {
+                        if self.at_any(current + 2, 1, &["I", "E", "Y"]) {
+                            self.add_both("J"); // "Edge".
+                            current + 3
+                        } else {
+                            self.add_both("TK"); // "Edgar".
+                            current + 2
+                        }
+                    } else if self.at_any(current, 2, &["DT", "DD"]) {
+                        self.add_both("T");
+                        current + 2
+                    } else {
+                        self.add_both("T");
+                        current + 1
+                    }
+                }
+                'F' => self.add_letter(current, 'F', "F"),
+                'G' => self.g(current),
+                'H' => {
+                    // Only when first or between vowels, and before a vowel.
+                    if (current == 0 || self.is_vowel(current - 1)) && self.is_vowel(current + 1) {
+                        self.add_both("H");
+                        current + 2
+                    } else {
+                        current + 1
+                    }
+                }
+                'J' => self.j(current, last),
+                'K' => self.add_letter(current, 'K', "K"),
+                'L' => {
+                    if self.at(current + 1) == 'L' {
+                        // Spanish, as in "Cabrillo" and "Gallegos".
+                        if (current == self.length - 3
+                            && self.at_any(current - 1, 4, &["ILLO", "ILLA", "ALLE"]))
+                            || ((self.at_any(last - 1, 2, &["AS", "OS"])
+                                || self.at_any(last, 1, &["A", "O"]))
+                                && self.at_any(current - 1, 4, &["ALLE"]))
+                        {
+                            self.add("L", "");
+                            current + 2
+                        } else {
+                            self.add_both("L");
+                            current + 2
+                        }
+                    } else {
+                        self.add_both("L");
+                        current + 1
+                    }
+                }
+                'M' => {
+                    self.add_both("M");
+                    // "Dumb", "thumb".
+                    if (self.at_any(current - 1, 3, &["UMB"])
+                        && (current + 1 == last || self.at_any(current + 2, 2, &["ER"])))
+                        || self.at(current + 1) == 'M'
+                    {
+                        current + 2
+                    } else {
+                        current + 1
+                    }
+                }
+                'N' => self.add_letter(current, 'N', "N"),
+                'Ñ' => {
+                    self.add_both("N");
+                    current + 1
+                }
+                'P' => {
+                    if self.at(current + 1) == 'H' {
+                        self.add_both("F");
+                        current + 2
+                    } else {
+                        self.add_both("P");
+                        // "Campbell", "raspberry".
+                        if self.at_any(current + 1, 1, &["P", "B"]) {
+                            current + 2
+                        } else {
+                            current + 1
+                        }
+                    }
+                }
+                'Q' => self.add_letter(current, 'Q', "K"),
+                'R' => {
+                    // French, as in "Rogier", but not "Hochmeier".
+                    if current == last
+                        && !self.slavo_germanic
+                        && self.at_any(current - 2, 2, &["IE"])
+                        && !self.at_any(current - 4, 2, &["ME", "MA"])
+                    {
+                        self.add("", "R");
+                    } else {
+                        self.add_both("R");
+                    }
+                    if self.at(current + 1) == 'R' {
+                        current + 2
+                    } else {
+                        current + 1
+                    }
+                }
+                'S' => self.s(current, last),
+                'T' => self.t(current),
+                'V' => self.add_letter(current, 'V', "F"),
+                'W' => self.w(current, last),
+                'X' => {
+                    // French, as in "Breaux".
+                    if !(current == last
+                        && (self.at_any(current - 3, 3, &["IAU", "EAU"])
+                            || self.at_any(current - 2, 2, &["AU", "OU"])))
+                    {
+                        self.add_both("KS");
+                    }
+                    if self.at_any(current + 1, 1, &["C", "X"]) {
+                        current + 2
+                    } else {
+                        current + 1
+                    }
+                }
+                'Z' => {
+                    if self.at(current + 1) == 'H' {
+                        self.add_both("J"); // Pinyin, as in "Zhao".
+                        current + 2
+                    } else {
+                        if self.at_any(current + 1, 2, &["ZO", "ZI", "ZA"])
+                            || (self.slavo_germanic && current > 0 && self.at(current - 1) != 'T')
+                        {
+                            self.add("S", "TS");
+                        } else {
+                            self.add_both("S");
+                        }
+                        if self.at(current + 1) == 'Z' {
+                            current + 2
+                        } else {
+                            current + 1
+                        }
+                    }
+                }
+                _ => current + 1,
+            };
+        }
+        self.primary.truncate(METAPHONE_LEN);
+        self.alternate.truncate(METAPHONE_LEN);
+        (self.primary, self.alternate)
+    }
+
+    fn c(&mut self, current: isize) -> isize {
+        // Germanic, as in "Bacher" and "Macher".
+        if current > 1
+            && !self.is_vowel(current - 2)
+            && self.at_any(current - 1, 3, &["ACH"])
+            && self.at(current + 2) != 'I'
+            && (self.at(current + 2) != 'E' || self.at_any(current - 2, 6, &["BACHER", "MACHER"]))
+        {
+            self.add_both("K");
+            return current + 2;
+        }
+        if current == 0 && self.at_any(current, 6, &["CAESAR"]) {
+            self.add_both("S");
+            return current + 2;
+        }
+        if self.at_any(current, 4, &["CHIA"]) {
+            self.add_both("K"); // Italian, as in "Chianti".
+            return current + 2;
+        }
+        if self.at_any(current, 2, &["CH"]) {
+            if current > 0 && self.at_any(current, 4, &["CHAE"]) {
+                self.add("K", "X"); // "Michael".
+            } else if current == 0
+                && (self.at_any(current + 1, 5, &["HARAC", "HARIS"])
+                    || self.at_any(current + 1, 3, &["HOR", "HYM", "HIA", "HEM"]))
+                && !self.at_any(0, 5, &["CHORE"])
+            {
+                self.add_both("K"); // Greek roots, as in "chemistry" and "chorus".
+            } else if self.germanic()
+                // "Architect", but not "arch", "orchestra" or "orchid".
+                || self.at_any(current - 2, 6, &["ORCHES", "ARCHIT", "ORCHID"])
+                || self.at_any(current + 2, 1, &["T", "S"])
+                // "Wachtler" and "Wechsler", but not "Tichner".
+                || ((self.at_any(current - 1, 1, &["A", "O", "U", "E"]) || current == 0)
+                    && self.at_any(
+                        current + 2,
+                        1,
+                        &["L", "R", "N", "M", "B", "H", "F", "V", "W", " "],
+                    ))
+            {
+                self.add_both("K");
+            } else if current > 0 {
+                if self.at_any(0, 2, &["MC"]) {
+                    self.add_both("K"); // "McHugh".
+                } else {
+                    self.add("X", "K");
+                }
+            } else {
+                self.add_both("X");
+            }
+            return current + 2;
+        }
+        if self.at_any(current, 2, &["CZ"]) && !self.at_any(current - 2, 4, &["WICZ"]) {
+            self.add("S", "X"); // "Czerny".
+            return current + 2;
+        }
+        if self.at_any(current + 1, 3, &["CIA"]) {
+            self.add_both("X"); // "Focaccia".
+            return current + 3;
+        }
+        // A double "C", but not as in "McClellan".
+        if self.at_any(current, 2, &["CC"]) && !(current == 1 && self.at(0) == 'M') {
+            // "Bellocchio", but not "Bacchus".
+            if self.at_any(current + 2, 1, &["I", "E", "H"])
+                && !self.at_any(current + 2, 2, &["HU"])
+            {
+                if (current == 1 && self.at(current - 1) == 'A')
+                    || self.at_any(current - 1, 5, &["UCCEE", "UCCES"])
+                {
+                    self.add_both("KS"); // "Accident", "succeed".
+                } else {
+                    self.add_both("X"); // "Bacci", "Bertucci".
+                }
+                return current + 3;
+            }
+            self.add_both("K"); // Pierce's rule.
+            return current + 2;
+        }
+        if self.at_any(current, 2, &["CK", "CG", "CQ"]) {
+            self.add_both("K");
+            return current + 2;
+        }
+        if self.at_any(current, 2, &["CI", "CE", "CY"]) {
+            if self.at_any(current, 3, &["CIO", "CIE", "CIA"]) {
+                self.add("S", "X"); // Italian.
+            } else {
+                self.add_both("S");
+            }
+            return current + 2;
+        }
+        self.add_both("K");
+        if self.at_any(current + 1, 2, &[" C", " Q", " G"]) {
+            current + 3 // "Mac Caffrey", "Mac Gregor".
+        } else if self.at_any(current + 1, 1, &["C", "K", "Q"])
+            && !self.at_any(current + 1, 2, &["CE", "CI"])
+        {
+            current + 2
+        } else {
+            current + 1
+        }
+    }
+
+    fn g(&mut self, current: isize) -> isize {
+        if self.at(current + 1) == 'H' {
+            if current > 0 && !self.is_vowel(current - 1) {
+                self.add_both("K");
+                return current + 2;
+            }
+            if current == 0 {
+                // "Ghislane", "Ghiradelli".
+                if self.at(current + 2) == 'I' {
+                    self.add_both("J");
+                } else {
+                    self.add_both("K");
+                }
+                return current + 2;
+            }
+            // Parker's rule: silent as in "Hugh", "bough" and "Broughton".
+            if (current > 1 && self.at_any(current - 2, 1, &["B", "H", "D"]))
+                || (current > 2 && self.at_any(current - 3, 1, &["B", "H", "D"]))
+                || (current > 3 && self.at_any(current - 4, 1, &["B", "H"]))
+            {
+                return current + 2;
+            }
+            // "Laugh", "McLaughlin", "cough", "rough", "tough".
+            if current > 2
+                && self.at(current - 1) == 'U'
+                && self.at_any(current - 3, 1, &["C", "G", "L", "R", "T"])
+            {
+                self.add_both("F");
+            } else if current > 0 && self.at(current - 1) != 'I' {
+                self.add_both("K");
+            }
+            return current + 2;
+        }
+        if self.at(current + 1) == 'N' {
+            if current == 1 && self.is_vowel(0) && !self.slavo_germanic {
+                self.add("KN", "N");
+            } else if !self.at_any(current + 2, 2, &["EY"])
+                && self.at(current + 1) != 'Y'
+                && !self.slavo_germanic
+            {
+                self.add("N", "KN"); // Not as in "Cagney".
+            } else {
+                self.add_both("KN");
+            }
+            return current + 2;
+        }
+        if self.at_any(current + 1, 2, &["LI"]) && !self.slavo_germanic {
+            self.add("KL", "L"); // "Tagliaro".
+            return current + 2;
+        }
+        // "-ges-", "-gep-", "-gel-" and "-gie-" at the beginning.
+        if current == 0
+            && (self.at(current + 1) == 'Y'
+                || self.at_any(
+                    current + 1,
+                    2,
+                    &[
+                        "ES", "EP", "EB", "EL", "EY", "IB", "IL", "IN", "IE", "EI", "ER",
+                    ],
+                ))
+        {
+            self.add("K", "J");
+            return current + 2;
+        }
+        // "-ger-" and "-gy-".
+        if (self.at_any(current + 1, 2, &["ER"]) || self.at(current + 1) == 'Y')
+            && !self.at_any(0, 6, &["DANGER", "RANGER", "MANGER"])
+            && !self.at_any(current - 1, 1, &["E", "I"])
+            && !self.at_any(current - 1, 3, &["RGY", "OGY"])
+        {
+            self.add("K", "J");
+            return current + 2;
+        }
+        // Italian, as in "Biaggi".
+        if self.at_any(current + 1, 1, &["E", "I", "Y"])
+            || self.at_any(current - 1, 4, &["AGGI", "OGGI"])
+        {
+            if self.germanic() || self.at_any(current + 1, 2, &["ET"]) {
+                self.add_both("K");
+            } else if self.at_any(current + 1, 4, &["IER "]) {
+                self.add_both("J"); // Always soft in French endings.
+            } else {
+                self.add("J", "K");
+            }
+            return current + 2;
+        }
+        self.add_letter(current, 'G', "K")
+    }
+
+    fn j(&mut self, current: isize, last: isize) -> isize {
+        // Spanish, as in "Jose" and "San Jacinto".
+        if self.at_any(current, 4, &["JOSE"]) || self.at_any(0, 4, &["SAN "]) {
+            if (current == 0 && self.at(current + 4) == ' ') || self.at_any(0, 4, &["SAN "]) {
+                self.add_both("H");
+            } else {
+                self.add("J", "H");
+            }
+            return current + 1;
+        }
+        if current == 0 {
+            self.add("J", "A"); // "Yankelovich" and "Jankelowicz".
+        } else if self.is_vowel(current - 1)
+            && !self.slavo_germanic
+            && matches!(self.at(current + 1), 'A' | 'O')
+        {
+            self.add("J", "H"); // Spanish, as in "bajador".
+        } else if current == last {
+            self.add("J", "");
+        } else if !self.at_any(current + 1, 1, &["L", "T", "K", "S", "N", "M", "B", "Z"])
+            && !self.at_any(current - 1, 1, &["S", "K", "L"])
+        {
+            self.add_both("J");
+        }
+        if self.at(current + 1) == 'J' {
+            current + 2
+        } else {
+            current + 1
+        }
+    }
+
+    fn s(&mut self, current: isize, last: isize) -> isize {
+        // "Island", "isle", "Carlisle", "Carlysle".
+        if self.at_any(current - 1, 3, &["ISL", "YSL"]) {
+            return current + 1;
+        }
+        if current == 0 && self.at_any(current, 5, &["SUGAR"]) {
+            self.add("X", "S");
+            return current + 1;
+        }
+        if self.at_any(current, 2, &["SH"]) {
+            if self.at_any(current + 1, 4, &["HEIM", "HOEK", "HOLM", "HOLZ"]) {
+                self.add_both("S"); // Germanic.
+            } else {
+                self.add_both("X");
+            }
+            return current + 2;
+        }
+        // Italian and Armenian.
+        if self.at_any(current, 3, &["SIO", "SIA"]) || self.at_any(current, 4, &["SIAN"]) {
+            if self.slavo_germanic {
+                self.add_both("S");
+            } else {
+                self.add("S", "X");
+            }
+            return current + 3;
+        }
+        // German and anglicized, so that "Smith" matches "Schmidt" and
+        // "Snider" "Schneider"; also Slavic "-sz-".
+        if (current == 0 && self.at_any(current + 1, 1, &["M", "N", "L", "W"]))
+            || self.at_any(current + 1, 1, &["Z"])
+        {
+            self.add("S", "X");
+            return if self.at_any(current + 1, 1, &["Z"]) {
+                current + 2
+            } else {
+                current + 1
+            };
+        }
+        if self.at_any(current, 2, &["SC"]) {
+            // Schlesinger's rule.
+            if self.at(current + 2) == 'H' {
+                // Dutch, as in "school" and "schooner".
+                if self.at_any(current + 3, 2, &["OO", "ER", "EN", "UY", "ED", "EM"]) {
+                    if self.at_any(current + 3, 2, &["ER", "EN"]) {
+                        self.add("X", "SK"); // "Schermerhorn", "Schenker".
+                    } else {
+                        self.add_both("SK");
+                    }
+                } else if current == 0 && !self.is_vowel(3) && self.at(3) != 'W' {
+                    self.add("X", "S");
+                } else {
+                    self.add_both("X");
+                }
+            } else if self.at_any(current + 2, 1, &["I", "E", "Y"]) {
+                self.add_both("S");
+            } else {
+                self.add_both("SK");
+            }
+            return current + 3;
+        }
+        // French, as in "Resnais" and "Artois".
+        if current == last && self.at_any(current - 2, 2, &["AI", "OI"]) {
+            self.add("", "S");
+        } else {
+            self.add_both("S");
+        }
+        if self.at_any(current + 1, 1, &["S", "Z"]) {
+            current + 2
+        } else {
+            current + 1
+        }
+    }
+
+    fn t(&mut self, current: isize) -> isize {
+        if self.at_any(current, 4, &["TION"]) {
+            self.add_both("X");
+            return current + 3;
+        }
+        if self.at_any(current, 3, &["TIA", "TCH"]) {
+            self.add_both("X");
+            return current + 3;
+        }
+        if self.at_any(current, 2, &["TH"]) || self.at_any(current, 3, &["TTH"]) {
+            // "Thomas", "Thames", or Germanic.
+            if self.at_any(current + 2, 2, &["OM", "AM"]) || self.germanic() {
+                self.add_both("T");
+            } else {
+                self.add("0", "T");
+            }
+            return current + 2;
+        }
+        self.add_both("T");
+        if self.at_any(current + 1, 1, &["T", "D"]) {
+            current + 2
+        } else {
+            current + 1
+        }
+    }
+
+    fn w(&mut self, current: isize, last: isize) -> isize {
+        if self.at_any(current, 2, &["WR"]) {
+            self.add_both("R");
+            return current + 2;
+        }
+        if current == 0 && (self.is_vowel(current + 1) || self.at_any(current, 2, &["WH"])) {
+            if self.is_vowel(current + 1) {
+                self.add("A", "F"); // "Wasserman" matches "Vasserman".
+            } else {
+                self.add_both("A"); // "Womo" matches "Uomo".
+            }
+        }
+        // "Arnow" matches "Arnoff".
+        if (current == last && self.is_vowel(current - 1))
+            || self.at_any(current - 1, 5, &["EWSKI", "EWSKY", "OWSKI", "OWSKY"])
+            || self.at_any(0, 3, &["SCH"])
+        {
+            self.add("", "F");
+            return current + 1;
+        }
+        if self.at_any(current, 4, &["WICZ", "WITZ"]) {
+            self.add("TS", "FX"); // Polish, as in "Filipowicz".
+            return current + 4;
+        }
+        current + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_soundex() {
+        for (name, code) in [
+            ("Robert", "R163"),
+            ("Rupert", "R163"),
+            ("Rubin", "R150"),
+            ("Ashcraft", "A261"),
+            ("Tymczak", "T522"),
+            ("Pfister", "P236"),
+            ("Honeyman", "H555"),
+            ("Lee", "L000"),
+            ("O'Hara", "O600"),
+            ("", ""),
+            ("42", ""),
+        ] {
+            assert_eq!(soundex(name), code, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_double_metaphone() {
+        // Checked against PostgreSQL's `dmetaphone` and `dmetaphone_alt`.
+        for (name, primary, alternate) in [
+            ("Schmidt", "XMT", "SMT"),
+            ("Smith", "SM0", "XMT"),
+            ("Thompson", "TMPS", "TMPS"),
+            ("Catherine", "K0RN", "KTRN"),
+            ("Kathryn", "K0RN", "KTRN"),
+            ("Jose", "HS", "HS"),
+            ("Xavier", "SF", "SFR"),
+            ("Caesar", "SSR", "SSR"),
+            ("Michael", "MKL", "MXL"),
+            ("Gallegos", "KLKS", "KKS"),
+            ("Filipowicz", "FLPT", "FLPF"),
+            ("Wasserman", "ASRM", "FSRM"),
+            ("", "", ""),
+        ] {
+            assert_eq!(
+                double_metaphone(name),
+                (primary.into(), alternate.into()),
+                "{name}"
+            );
+        }
+        assert_eq!(double_metaphone("Ça ña"), ("SN".into(), "SN".into()));
+    }
+
+    #[test]
+    fn test_fingerprints() {
+        let config = TbfConfig::new(0.125);
+        assert_eq!(encode("Smith", Phonetic::DoubleMetaphone), ["SM0", "XMT"]);
+        assert_eq!(encode("Thompson", Phonetic::DoubleMetaphone), ["TMPS"]);
+        assert_eq!(encode("Robert", Phonetic::Soundex), ["R163"]);
+        assert!(encode("--", Phonetic::DoubleMetaphone).is_empty());
+        assert_eq!(
+            fingerprints("Rupert", Phonetic::Soundex, &config),
+            fingerprints("Robert", Phonetic::Soundex, &config)
+        );
+        assert_eq!(
+            fingerprints("Smith", Phonetic::DoubleMetaphone, &config)[1],
+            config.collapse(b"XMT")
+        );
+    }
+}
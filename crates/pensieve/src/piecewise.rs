@@ -0,0 +1,184 @@
+//! Matching under a bounded edit distance.
+//!
+//! A collapse tolerates bits flipped in place, but a single inserted or
+//! deleted byte shifts everything after it and the collapse no longer
+//! matches. [`ShingleSet`](crate::shingle::ShingleSet)s measure how similar
+//! two inputs are despite such edits; a [`PiecewiseDigest`] instead decides
+//! whether an input is within `k` edits (insertions, deletions or
+//! substitutions of bytes) of the digested one.
+//!
+//! The digest cuts its input into pieces of `piece_len` bytes and keeps the
+//! collapse of each. Each edit damages at most one piece, and shifts the
+//! later ones by at most one byte, so an input within `k` edits still
+//! contains all but `k` of the pieces, each no more than `k` bytes from its
+//! original offset. [`PiecewiseDigest::matches`] checks exactly that: it
+//! collapses the windows of the candidate around each piece's offset and
+//! requires all but `k` pieces to find an equal one.
+//!
+//! # Trade-offs
+//! - No false negatives: an input within `k` edits always matches, and so
+//!   does one whose pieces only differ by bit flips the collapse absorbs.
+//! - False positives grow with `k`: each piece gets `2k + 1` chances to
+//!   meet an equal collapse by accident, and `k` pieces may miss. Keep `k`
+//!   well below the number of pieces; with `k` at or above it, every input
+//!   of about the same length matches.
+//! - Shorter pieces make more pieces, so more edits can be told apart from
+//!   noise, but each piece's collapse has fewer chunks and collides more
+//!   often. Telling pieces apart is only as good as their collapses.
+//! - Only the digested side is compact (one byte per piece); the candidate
+//!   must be at hand as bytes, and is collapsed at every offset in time
+//!   linear in its length. Bytes after the last full piece of the digested
+//!   input are not compared, beyond counting towards its length.
+//!
+//! This branches on the data and is meant for public inputs.
+
+use crate::rolling::window_levels;
+use crate::{Error, TbfConfig};
+use alloc::vec::Vec;
+
+/// The collapsed pieces of an input, for matching under edits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PiecewiseDigest {
+    piece_len: usize,
+    config: TbfConfig,
+    len: usize,      // Length of the digested input.
+    pieces: Vec<u8>, // Chunk-level bitmask of every full piece, in order.
+}
+
+impl PiecewiseDigest {
+    /// Collapses every full piece of `piece_len` bytes of `input` under
+    /// `config`. The output transform of `config` does not affect which
+    /// pieces are equal and is ignored.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `piece_len` is zero.
+    pub fn new(input: &[u8], piece_len: usize, config: &TbfConfig) -> Result<Self, Error> {
+        let windows = window_levels(input, piece_len, config)
+            .map_err(|_| Error::InvalidParameter("pieces must be at least one byte"))?;
+        Ok(Self {
+            piece_len,
+            config: *config,
+            len: input.len(),
+            pieces: windows.into_iter().step_by(piece_len).collect(),
+        })
+    }
+
+    /// Length of the pieces, in bytes.
+    pub fn piece_len(&self) -> usize {
+        self.piece_len
+    }
+
+    /// Number of full pieces of the digested input.
+    pub fn piece_count(&self) -> usize {
+        self.pieces.len()
+    }
+
+    /// Number of pieces that collapse like some window of `candidate`
+    /// starting at most `max_shift` bytes from the piece's offset.
+    pub fn matching_pieces(&self, candidate: &[u8], max_shift: usize) -> usize {
+        let windows = window_levels(candidate, self.piece_len, &self.config)
+            .expect("the piece length was validated");
+        self.pieces
+            .iter()
+            .enumerate()
+            .filter(|&(i, level)| {
+                let offset = i * self.piece_len;
+                let start = offset.saturating_sub(max_shift).min(windows.len());
+                let end = (offset + max_shift + 1).min(windows.len());
+                windows[start..end].contains(level)
+            })
+            .count()
+    }
+
+    /// Whether `candidate` may be within `max_edits` insertions, deletions
+    /// or substitutions of the digested input: it is if its length is
+    /// within `max_edits` and all but `max_edits` pieces match it. Inputs
+    /// that are within the edits always match; see the
+    /// [module documentation](self) for the false positives.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::piecewise::PiecewiseDigest;
+    ///
+    /// let original = b"Pensieve collapses noisy inputs to one stable value!".repeat(3);
+    /// let mut edited = original.clone();
+    /// edited.insert(40, b'#');
+    /// edited.remove(100);
+    /// let config = TbfConfig::new(0.125);
+    /// let digest = PiecewiseDigest::new(&original, 16, &config).unwrap();
+    /// assert!(digest.matches(&edited, 2));
+    /// ```
+    pub fn matches(&self, candidate: &[u8], max_edits: usize) -> bool {
+        self.len.abs_diff(candidate.len()) <= max_edits
+            && self.matching_pieces(candidate, max_edits) + max_edits >= self.piece_count()
+    }
+}
+
+/// Whether `a` and `b` may be within `max_edits` edits of each other; see
+/// [`PiecewiseDigest::matches`].
+pub fn matches(
+    a: &[u8],
+    b: &[u8],
+    piece_len: usize,
+    max_edits: usize,
+    config: &TbfConfig,
+) -> Result<bool, Error> {
+    Ok(PiecewiseDigest::new(a, piece_len, config)?.matches(b, max_edits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use alloc::vec;
+
+    fn sparse(len: usize, seed: u64) -> Vec<u8> {
+        let mut input = vec![0u8; len];
+        seeded(seed)(&mut input);
+        input.iter_mut().for_each(|b| *b &= 0x11);
+        input
+    }
+
+    #[test]
+    fn test_pieces_match_full_collapse() {
+        let config = TbfConfig::new(0.125);
+        let input = sparse(70, 1);
+        let digest = PiecewiseDigest::new(&input, 16, &config).unwrap();
+        assert_eq!(digest.piece_count(), 4);
+        for (i, piece) in input.chunks_exact(16).enumerate() {
+            let other = PiecewiseDigest::new(piece, 16, &config).unwrap();
+            assert_eq!(other.pieces, [digest.pieces[i]]);
+        }
+        assert_eq!(digest.matching_pieces(&input, 0), 4);
+    }
+
+    #[test]
+    fn test_edits_within_the_bound_match() {
+        let config = TbfConfig::new(0.125);
+        let original = sparse(160, 2);
+        let digest = PiecewiseDigest::new(&original, 16, &config).unwrap();
+        let mut edited = original.clone();
+        edited.insert(37, 0x10);
+        edited.remove(90);
+        edited[130] ^= 0x01;
+        assert!(digest.matches(&edited, 3));
+        assert!(digest.matching_pieces(&edited, 3) >= 7);
+
+        let unrelated = sparse(160, 3);
+        assert!(!digest.matches(&unrelated, 3));
+        // Lengths alone rule out candidates.
+        assert!(!digest.matches(&original[..150], 3));
+        assert!(digest.matches(&original[..157], 3));
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        let config = TbfConfig::default();
+        assert!(PiecewiseDigest::new(b"abc", 0, &config).is_err());
+        let short = PiecewiseDigest::new(b"abc", 4, &config).unwrap();
+        assert_eq!(short.piece_count(), 0);
+        assert!(short.matches(b"ab", 1));
+        assert!(!short.matches(b"", 1));
+        assert_eq!(matches(b"abcdefgh", b"abcdefgh", 4, 0, &config), Ok(true));
+    }
+}
@@ -0,0 +1,221 @@
+//! A ready-made stage for channel-based stream processing.
+//!
+//! [`CollapseStage`] sits between two [`std::sync::mpsc`] channels: it takes
+//! byte buffers from a receiver, collapses them on a pool of worker threads
+//! and sends `(input_id, digest)` pairs downstream. Input ids number the
+//! buffers from 0 in the order they are received; digests arrive in the
+//! order their workers finish, so a consumer that needs input order sorts
+//! by id.
+//!
+//! The downstream channel is bounded. Once it is full the workers block, stop
+//! receiving, and a producer sending on a bounded
+//! [`sync_channel`](std::sync::mpsc::sync_channel) blocks in turn, so a slow
+//! consumer slows the whole pipeline down instead of letting buffers pile
+//! up in memory.
+//!
+//! [`CollapseStage::cancel`] stops the workers early, for a batch whose
+//! results are no longer wanted.
+
+use crate::{Error, TbfConfig};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Worker threads collapsing buffers from one channel into another.
+///
+/// The stage runs until its input channel is closed and drained, until
+/// the receiver of its output is dropped, or until it is cancelled;
+/// [`CollapseStage::join`] waits for that.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::pipeline::CollapseStage;
+/// use std::sync::mpsc;
+///
+/// let (input, buffers) = mpsc::sync_channel(16);
+/// let config = TbfConfig::new(0.125);
+/// let (stage, digests) = CollapseStage::spawn(config, buffers, 4, 16).unwrap();
+/// std::thread::spawn(move || {
+///     for byte in 0..100u8 {
+///         input.send(vec![byte; 32]).unwrap();
+///     }
+///     // Dropping the sender ends the stream.
+/// });
+/// let mut results: Vec<(u64, Vec<u8>)> = digests.iter().collect();
+/// stage.join();
+/// results.sort();
+/// assert_eq!(results.len(), 100);
+/// assert_eq!(results[7], (7, config.collapse(&[7; 32])));
+/// ```
+#[derive(Debug)]
+pub struct CollapseStage {
+    workers: Vec<JoinHandle<()>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// The receiving end of a stage's `(input_id, digest)` pairs.
+pub type Digests = Receiver<(u64, Vec<u8>)>;
+
+/// The input channel and the id of the next buffer it delivers.
+type Input<B> = Mutex<(Receiver<B>, u64)>;
+
+impl CollapseStage {
+    /// Starts `workers` threads collapsing the buffers received from
+    /// `input` with `config`; returns the stage and the receiver of its
+    /// `(input_id, digest)` pairs, of which at most `capacity` wait to be
+    /// received at a time.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `workers` is zero.
+    pub fn spawn<B>(
+        config: TbfConfig,
+        input: Receiver<B>,
+        workers: usize,
+        capacity: usize,
+    ) -> Result<(Self, Digests), Error>
+    where
+        B: AsRef<[u8]> + Send + 'static,
+    {
+        if workers == 0 {
+            return Err(Error::InvalidParameter("at least one worker is required"));
+        }
+        let (output, digests) = mpsc::sync_channel(capacity);
+        let input: Arc<Input<B>> = Arc::new(Mutex::new((input, 0)));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let workers = (0..workers)
+            .map(|_| {
+                let (input, output) = (Arc::clone(&input), output.clone());
+                let cancelled = Arc::clone(&cancelled);
+                thread::spawn(move || work(&config, &input, &output, &cancelled))
+            })
+            .collect();
+        Ok((Self { workers, cancelled }, digests))
+    }
+
+    /// Number of worker threads.
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Asks every worker to stop; each finishes the buffer it is collapsing
+    /// and then collapses no more. A worker waiting for input stops once
+    /// the next buffer arrives or the input channel is closed.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Waits until every worker has stopped; a panic in a worker is
+    /// resumed here.
+    pub fn join(self) {
+        for worker in self.workers {
+            if let Err(panic) = worker.join() {
+                std::panic::resume_unwind(panic);
+            }
+        }
+    }
+}
+
+fn work<B: AsRef<[u8]>>(
+    config: &TbfConfig,
+    input: &Input<B>,
+    output: &SyncSender<(u64, Vec<u8>)>,
+    cancelled: &AtomicBool,
+) {
+    while !cancelled.load(Ordering::Relaxed) {
+        let (id, buffer) = {
+            // Workers only panic outside the lock, so a poisoned one is intact.
+            let mut input = input.lock().unwrap_or_else(|e| e.into_inner());
+            let Ok(buffer) = input.0.recv() else {
+                return;
+            };
+            input.1 += 1;
+            (input.1 - 1, buffer)
+        };
+        if cancelled.load(Ordering::Relaxed) {
+            return;
+        }
+        if output.send((id, config.collapse(buffer.as_ref()))).is_err() {
+            return; // Nobody is listening anymore.
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    #[test]
+    fn test_every_buffer_is_collapsed_once() {
+        let config = TbfConfig::new(0.125);
+        let (input, buffers) = mpsc::channel();
+        let inputs: Vec<Vec<u8>> = (0..500u32).map(|i| i.to_le_bytes().repeat(8)).collect();
+        for buffer in &inputs {
+            input.send(buffer.clone()).unwrap();
+        }
+        drop(input);
+        let (stage, digests) = CollapseStage::spawn(config, buffers, 3, 4).unwrap();
+        assert_eq!(stage.workers(), 3);
+        let mut results: Vec<_> = digests.iter().collect();
+        stage.join();
+        results.sort();
+        assert_eq!(results.len(), inputs.len());
+        for (i, (id, digest)) in results.into_iter().enumerate() {
+            assert_eq!(id, i as u64);
+            assert_eq!(digest, config.collapse(&inputs[i]));
+        }
+
+        let (_, buffers) = mpsc::channel::<Vec<u8>>();
+        assert!(CollapseStage::spawn(config, buffers, 0, 4).is_err());
+    }
+
+    #[test]
+    fn test_slow_consumers_hold_producers_back() {
+        let (input, buffers) = mpsc::sync_channel(0);
+        let sent = Arc::new(AtomicUsize::new(0));
+        let producer = {
+            let sent = Arc::clone(&sent);
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    if input.send([0xA5u8; 16]).is_err() {
+                        break;
+                    }
+                    sent.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+        let (stage, digests) = CollapseStage::spawn(TbfConfig::default(), buffers, 2, 3).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        // Three digests wait downstream and each worker holds one more.
+        assert!(sent.load(Ordering::SeqCst) <= 3 + 2, "{sent:?}");
+
+        assert_eq!(digests.iter().take(10).count(), 10);
+        // Hanging up downstream stops the workers, and then the producer.
+        drop(digests);
+        stage.join();
+        producer.join().unwrap();
+        assert!(sent.load(Ordering::SeqCst) < 100);
+    }
+
+    #[test]
+    fn test_cancelled_stages_stop_collapsing() {
+        let (input, buffers) = mpsc::sync_channel(0);
+        let (stage, digests) = CollapseStage::spawn(TbfConfig::default(), buffers, 2, 1).unwrap();
+        let producer = thread::spawn(move || {
+            let mut sent = 0;
+            while sent < 1000 && input.send([0x5Au8; 16]).is_ok() {
+                sent += 1;
+            }
+            sent
+        });
+        assert_eq!(digests.iter().take(5).count(), 5);
+        stage.cancel();
+        // Unblock workers waiting to send, then let them stop.
+        let late: Vec<_> = digests.iter().collect();
+        stage.join();
+        assert!(late.len() <= 3, "{}", late.len());
+        assert!(producer.join().unwrap() < 1000);
+    }
+}
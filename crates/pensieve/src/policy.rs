@@ -0,0 +1,247 @@
+//! Deployment limits on how inputs may be collapsed.
+//!
+//! The collapse accepts any tolerance from 5% to 25% and inputs of any
+//! length, which is right for a library but easy to misuse: a 25% tolerance
+//! on a 16-bit secret leaves almost nothing to guess. A [`Policy`] states
+//! the limits a deployment allows (a maximum tolerance, a minimum input
+//! length and the algorithm version digests were stored under), and its
+//! entry points collapse only inputs and configurations within them,
+//! failing with [`Error::PolicyViolation`] otherwise.
+//!
+//! The crate's own functions keep accepting every configuration, for
+//! compatibility. Code under a policy collapses through the policy's
+//! [`collapse`](Policy::collapse), [`collapse_bits`](Policy::collapse_bits),
+//! [`digest`](Policy::digest) and [`seal`](Policy::seal), and checks every
+//! configuration it hands to other constructions, such as a
+//! [`FuzzyExtractor`](crate::fuzzy_extractor::FuzzyExtractor), with
+//! [`check`](Policy::check) first.
+
+use crate::{Error, TbfConfig};
+#[cfg(feature = "alloc")]
+use crate::{digest::CollapsedDigest, envelope::DigestEnvelope};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// Limits on the configurations and inputs a deployment collapses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Policy {
+    max_tolerance: f32,
+    min_input_bits: usize,
+    algorithm_version: Option<u8>, // `None` allows any.
+}
+
+impl Default for Policy {
+    /// Allows everything the crate supports.
+    fn default() -> Self {
+        Self {
+            max_tolerance: TbfConfig::MAX_TOLERANCE,
+            min_input_bits: 0,
+            algorithm_version: None,
+        }
+    }
+}
+
+impl Policy {
+    /// A policy allowing everything the crate supports, to be narrowed
+    /// with the `with_*` methods.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// This policy allowing tolerances up to `max_tolerance`.
+    pub fn with_max_tolerance(self, max_tolerance: f32) -> Self {
+        Self {
+            max_tolerance,
+            ..self
+        }
+    }
+
+    /// This policy allowing inputs of at least `min_input_bits` bits;
+    /// [`TbfConfig::MIN_INPUT_BITS`] is the shortest with meaningful
+    /// guarantees.
+    pub fn with_min_input_bits(self, min_input_bits: usize) -> Self {
+        Self {
+            min_input_bits,
+            ..self
+        }
+    }
+
+    /// This policy requiring [`TbfConfig::ALGORITHM_VERSION`] to be
+    /// `version`, so that a build implementing another revision of the
+    /// collapse refuses to produce digests to compare with stored ones.
+    pub fn with_algorithm_version(self, version: u8) -> Self {
+        Self {
+            algorithm_version: Some(version),
+            ..self
+        }
+    }
+
+    /// The largest tolerance allowed.
+    pub fn max_tolerance(&self) -> f32 {
+        self.max_tolerance
+    }
+
+    /// The shortest input allowed, in bits.
+    pub fn min_input_bits(&self) -> usize {
+        self.min_input_bits
+    }
+
+    /// The algorithm version required, if any.
+    pub fn algorithm_version(&self) -> Option<u8> {
+        self.algorithm_version
+    }
+
+    /// Checks that `config` is allowed: its (clamped) tolerance is at most
+    /// the maximum, and this build implements the required algorithm
+    /// version.
+    ///
+    /// Fails with [`Error::PolicyViolation`] otherwise.
+    pub fn check(&self, config: &TbfConfig) -> Result<(), Error> {
+        if config.tolerance() > self.max_tolerance {
+            return Err(Error::PolicyViolation(
+                "tolerance exceeds the policy's maximum",
+            ));
+        }
+        if self
+            .algorithm_version
+            .is_some_and(|version| version != TbfConfig::ALGORITHM_VERSION)
+        {
+            return Err(Error::PolicyViolation(
+                "algorithm version differs from the policy's",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that `config` is allowed and an input of `input_bits` bits is
+    /// long enough.
+    ///
+    /// Fails with [`Error::PolicyViolation`] otherwise.
+    pub fn check_input(&self, config: &TbfConfig, input_bits: usize) -> Result<(), Error> {
+        self.check(config)?;
+        if input_bits < self.min_input_bits {
+            return Err(Error::PolicyViolation(
+                "input is shorter than the policy's minimum",
+            ));
+        }
+        Ok(())
+    }
+
+    /// [`TbfConfig::collapse`] of `input`, if the policy allows it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::policy::Policy;
+    /// use pensieve::{Error, TbfConfig};
+    ///
+    /// let policy = Policy::new()
+    ///     .with_max_tolerance(0.125)
+    ///     .with_min_input_bits(128);
+    /// let secret = [0x5A; 16];
+    /// assert!(policy.collapse(&secret, &TbfConfig::new(0.1)).is_ok());
+    /// assert_eq!(
+    ///     policy.collapse(&secret, &TbfConfig::new(0.25)),
+    ///     Err(Error::PolicyViolation("tolerance exceeds the policy's maximum"))
+    /// );
+    /// assert!(policy.collapse(&secret[..2], &TbfConfig::new(0.1)).is_err());
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn collapse(&self, input: &[u8], config: &TbfConfig) -> Result<Vec<u8>, Error> {
+        self.check_input(config, 8 * input.len())?;
+        Ok(config.collapse(input))
+    }
+
+    /// [`collapse_bits`](crate::collapse_bits) of the first `bit_len` bits
+    /// of `input`, if the policy allows a default configuration with
+    /// `tolerance` and inputs of `bit_len` bits.
+    #[cfg(feature = "alloc")]
+    pub fn collapse_bits(
+        &self,
+        input: &[u8],
+        bit_len: usize,
+        tolerance: f32,
+    ) -> Result<Vec<u8>, Error> {
+        self.check_input(&TbfConfig::new(tolerance), bit_len)?;
+        crate::collapse_bits(input, bit_len, tolerance)
+    }
+
+    /// [`CollapsedDigest::collapse`] of `input`, if the policy allows it.
+    #[cfg(feature = "alloc")]
+    pub fn digest(&self, input: &[u8], config: &TbfConfig) -> Result<CollapsedDigest, Error> {
+        self.check_input(config, 8 * input.len())?;
+        Ok(CollapsedDigest::collapse(input, config))
+    }
+
+    /// [`DigestEnvelope::seal`] of `input`, if the policy allows it.
+    #[cfg(feature = "alloc")]
+    pub fn seal(&self, input: &[u8], config: &TbfConfig) -> Result<DigestEnvelope, Error> {
+        self.check_input(config, 8 * input.len())?;
+        Ok(DigestEnvelope::seal(input, config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let config = TbfConfig::new(0.2);
+        let permissive = Policy::new();
+        assert_eq!(permissive.check_input(&TbfConfig::new(1.0), 0), Ok(()));
+
+        let policy = Policy::new()
+            .with_max_tolerance(0.15)
+            .with_min_input_bits(64)
+            .with_algorithm_version(TbfConfig::ALGORITHM_VERSION);
+        assert_eq!(
+            policy.check(&config),
+            Err(Error::PolicyViolation(
+                "tolerance exceeds the policy's maximum"
+            ))
+        );
+        // Tolerances are compared after clamping.
+        assert_eq!(policy.check(&TbfConfig::new(0.0)), Ok(()));
+        assert_eq!(policy.check_input(&TbfConfig::new(0.15), 64), Ok(()));
+        assert_eq!(
+            policy.check_input(&TbfConfig::new(0.15), 63),
+            Err(Error::PolicyViolation(
+                "input is shorter than the policy's minimum"
+            ))
+        );
+        assert_eq!(
+            policy
+                .with_algorithm_version(TbfConfig::ALGORITHM_VERSION + 1)
+                .check(&TbfConfig::new(0.1)),
+            Err(Error::PolicyViolation(
+                "algorithm version differs from the policy's"
+            ))
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_entry_points() {
+        let policy = Policy::new().with_min_input_bits(100);
+        let config = TbfConfig::new(0.125);
+        let input = [0xA5u8; 13];
+        assert_eq!(
+            policy.collapse(&input, &config),
+            Ok(config.collapse(&input))
+        );
+        assert_eq!(
+            policy.collapse_bits(&input, 100, 0.125),
+            crate::collapse_bits(&input, 100, 0.125)
+        );
+        assert!(policy.collapse_bits(&input, 99, 0.125).is_err());
+        assert_eq!(
+            policy.digest(&input, &config),
+            Ok(CollapsedDigest::collapse(&input, &config))
+        );
+        assert_eq!(
+            policy.seal(&input, &config),
+            Ok(DigestEnvelope::seal(&input, &config))
+        );
+        assert!(policy.seal(&input[..12], &config).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+//! Population count of whole bytes, dispatched on the CPU at runtime.
+//!
+//! The chunk popcounts dominate the cost of every collapse. A build for a
+//! generic target cannot assume AVX2 or even the `popcnt` instruction, so
+//! with `std` the fastest implementation the running CPU supports is picked
+//! once, on first use, and called through a function pointer afterwards.
+//! Without `std` there is no feature detection, and the portable
+//! implementation uses whatever the compile-time target enables.
+//!
+//! Every implementation counts 64-bit words with `count_ones`, which
+//! compiles to the `popcnt` instruction, a vectorized nibble count or a
+//! branch-free bit-twiddling sequence, never to a branch or a table lookup
+//! in memory on the data; all are constant time.
+
+/// Signature of every implementation.
+type Popcount = fn(&[u8]) -> u32;
+
+/// An implementation of [`ct_popcount`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Backend {
+    /// `count_ones` for whatever the compile-time target enables.
+    Portable,
+    /// The `popcnt` instruction of x86-64 CPUs with SSE4.2.
+    #[cfg(all(feature = "std", target_arch = "x86_64"))]
+    Sse42,
+    /// 256-bit vector counts on x86-64 CPUs with AVX2.
+    #[cfg(all(feature = "std", target_arch = "x86_64"))]
+    Avx2,
+    /// The vector `cnt` instruction of AArch64 CPUs.
+    #[cfg(all(feature = "std", target_arch = "aarch64"))]
+    Neon,
+}
+
+impl Backend {
+    /// Name reported by [`popcount_backend`](crate::distance::popcount_backend).
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Portable => "portable",
+            #[cfg(all(feature = "std", target_arch = "x86_64"))]
+            Self::Sse42 => "sse4.2",
+            #[cfg(all(feature = "std", target_arch = "x86_64"))]
+            Self::Avx2 => "avx2",
+            #[cfg(all(feature = "std", target_arch = "aarch64"))]
+            Self::Neon => "neon",
+        }
+    }
+
+    /// The implementation; only call it on CPUs [`detected`] reports.
+    #[cfg(feature = "std")]
+    fn function(self) -> Popcount {
+        match self {
+            Self::Portable => portable,
+            #[cfg(all(feature = "std", target_arch = "x86_64"))]
+            Self::Sse42 => x86::sse42,
+            #[cfg(all(feature = "std", target_arch = "x86_64"))]
+            Self::Avx2 => x86::avx2,
+            #[cfg(all(feature = "std", target_arch = "aarch64"))]
+            Self::Neon => arm::neon,
+        }
+    }
+}
+
+/// Number of set bits in `bytes`, in constant time, with the best
+/// implementation for the running CPU.
+pub(crate) fn ct_popcount(bytes: &[u8]) -> u32 {
+    selected().1(bytes)
+}
+
+/// The implementation [`ct_popcount`] uses.
+pub(crate) fn backend() -> Backend {
+    selected().0
+}
+
+#[cfg(feature = "std")]
+fn selected() -> (Backend, Popcount) {
+    static SELECTED: std::sync::OnceLock<(Backend, Popcount)> = std::sync::OnceLock::new();
+    *SELECTED.get_or_init(|| {
+        let backend = *detected().last().expect("portable is always available");
+        (backend, backend.function())
+    })
+}
+
+#[cfg(not(feature = "std"))]
+fn selected() -> (Backend, Popcount) {
+    (Backend::Portable, portable)
+}
+
+/// The implementations the running CPU supports, fastest last.
+#[cfg(feature = "std")]
+fn detected() -> Vec<Backend> {
+    let mut found = vec![Backend::Portable];
+    #[cfg(target_arch = "x86_64")]
+    if std::arch::is_x86_feature_detected!("popcnt") {
+        if std::arch::is_x86_feature_detected!("sse4.2") {
+            found.push(Backend::Sse42);
+        }
+        if std::arch::is_x86_feature_detected!("avx2") {
+            found.push(Backend::Avx2);
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        found.push(Backend::Neon);
+    }
+    found
+}
+
+/// Counts eight bytes at a time. `#[inline(always)]` lets each
+/// `target_feature` wrapper compile it with its own instructions.
+#[inline(always)]
+fn count_words(bytes: &[u8]) -> u32 {
+    let mut words = bytes.chunks_exact(8);
+    let mut count: u32 = words
+        .by_ref()
+        .map(|word| u64::from_ne_bytes(word.try_into().expect("8 bytes")).count_ones())
+        .sum();
+    for &byte in words.remainder() {
+        count += byte.count_ones();
+    }
+    count
+}
+
+fn portable(bytes: &[u8]) -> u32 {
+    count_words(bytes)
+}
+
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+mod x86 {
+    use super::count_words;
+
+    #[target_feature(enable = "sse4.2,popcnt")]
+    fn sse42_impl(bytes: &[u8]) -> u32 {
+        count_words(bytes)
+    }
+
+    #[target_feature(enable = "avx2,popcnt")]
+    fn avx2_impl(bytes: &[u8]) -> u32 {
+        count_words(bytes)
+    }
+
+    pub(super) fn sse42(bytes: &[u8]) -> u32 {
+        // SAFETY: only selected once the CPU reported SSE4.2 and popcnt.
+        unsafe { sse42_impl(bytes) }
+    }
+
+    pub(super) fn avx2(bytes: &[u8]) -> u32 {
+        // SAFETY: only selected once the CPU reported AVX2 and popcnt.
+        unsafe { avx2_impl(bytes) }
+    }
+}
+
+#[cfg(all(feature = "std", target_arch = "aarch64"))]
+mod arm {
+    use super::count_words;
+
+    #[target_feature(enable = "neon")]
+    fn neon_impl(bytes: &[u8]) -> u32 {
+        count_words(bytes)
+    }
+
+    pub(super) fn neon(bytes: &[u8]) -> u32 {
+        // SAFETY: only selected once the CPU reported NEON.
+        unsafe { neon_impl(bytes) }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    #[test]
+    fn test_every_detected_backend_counts_alike() {
+        let mut fill = seeded(419);
+        let mut input = vec![0u8; 1000];
+        fill(&mut input);
+        for backend in detected() {
+            for len in [0, 1, 7, 8, 31, 33, 1000] {
+                let bytes = &input[1000 - len..];
+                let expected: u32 = bytes.iter().map(|b| b.count_ones()).sum();
+                assert_eq!(backend.function()(bytes), expected, "{}", backend.name());
+            }
+        }
+        assert!(detected().contains(&backend()));
+        assert_eq!(ct_popcount(&[0xFF; 3]), 24);
+    }
+}
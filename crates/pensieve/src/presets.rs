@@ -0,0 +1,129 @@
+//! Error-correcting codes sized for common kinds of input.
+//!
+//! Choosing a [`RepetitionCode`] from scratch means guessing a bit-error
+//! rate. A [`Preset`] bundles a code with the input it was sized for: its
+//! length, the fraction of set bits, and the fraction of bits that differ
+//! between two captures of the same thing. Each preset documents the
+//! probability that its code fails to recover a 128-bit secret, as in a
+//! [`PufStabilizer`](crate::puf::PufStabilizer) or code-offset
+//! [fuzzy extractor](crate::fuzzy_extractor), which is the way to release a
+//! key from a noisy input. A response from another source recovers it with
+//! a probability of about 2^-128. The figures are derived from that model
+//! rather than measured on real data.
+//!
+//! Presets carry no collapse configuration. A digest has at most eight
+//! levels, so equal digests accept at least 1 in 256 unrelated inputs, and
+//! at the noise of these inputs no [`TbfConfig`](crate::TbfConfig) keeps
+//! the levels of two captures equal as well: under
+//! [`analysis::predict`](crate::analysis::predict), one-sided thresholds
+//! accept 84-100% of unrelated inputs and the other rules reject at least
+//! 27% of genuine captures. Compare digests of such inputs with a
+//! [`BiometricVault`](crate::biometric::BiometricVault) or a distance
+//! instead.
+//!
+//! Real inputs are biased and their bits correlated. Start from the preset
+//! closest to your data, then measure the bit-error rate and size the code
+//! with [`RepetitionCode::for_bit_error_rate`].
+
+use crate::ecc::RepetitionCode;
+#[cfg(feature = "std")]
+use crate::puf::SECRET_LEN;
+
+/// An error-correcting code sized for one kind of input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preset {
+    /// Short name, such as `"biometric-iris"`.
+    pub name: &'static str,
+    /// The code protecting secrets derived from the input.
+    pub code: RepetitionCode,
+    /// Input length the figures are given for, in bytes.
+    pub input_len: usize,
+    /// Expected fraction of set input bits.
+    pub one_probability: f32,
+    /// Expected fraction of bits that differ between two captures.
+    pub bit_error_rate: f32,
+}
+
+impl Preset {
+    /// Probability that [`code`](Self::code) fails to recover a 128-bit
+    /// secret at the expected bit-error rate.
+    #[cfg(feature = "std")]
+    pub fn key_failure_rate(&self) -> f64 {
+        let bit = self.code.failure_probability(self.bit_error_rate);
+        -((SECRET_LEN * 8) as f64 * (-bit).ln_1p()).exp_m1()
+    }
+}
+
+/// Iris codes: 2048 bits, half of them set, with 10% of the bits differing
+/// between two captures of the same eye.
+///
+/// Key recovery fails with probability 7.2e-11; for keys released under
+/// FAR/FRR targets of your own, use a
+/// [`BiometricVault`](crate::biometric::BiometricVault).
+pub const BIOMETRIC_IRIS: Preset = Preset {
+    name: "biometric-iris",
+    code: RepetitionCode::new(49),
+    input_len: 256,
+    one_probability: 0.5,
+    bit_error_rate: 0.10,
+};
+
+/// Quantized readings of a slowly varying sensor, such as a
+/// [`timeseries`](crate::timeseries) encoding: 256 bits, half of them set,
+/// with 1% differing between two readings of the same state.
+///
+/// Key recovery fails with probability 3.3e-22.
+pub const SENSOR_TELEMETRY: Preset = Preset {
+    name: "sensor-telemetry",
+    code: RepetitionCode::new(31),
+    input_len: 32,
+    one_probability: 0.5,
+    bit_error_rate: 0.01,
+};
+
+/// The 128-bit SimHash of a [`text`](crate::text) fingerprint, with 3% of
+/// the bits differing between near-duplicate documents.
+///
+/// Key recovery fails with probability 1.1e-14.
+pub const DOCUMENT_TEXT: Preset = Preset {
+    name: "document-text",
+    code: RepetitionCode::new(31),
+    input_len: 16,
+    one_probability: 0.5,
+    bit_error_rate: 0.03,
+};
+
+/// SRAM power-up contents for a [`PufStabilizer`](crate::puf::PufStabilizer):
+/// 9600 bits, half of them set, with 15% flipping between power cycles.
+///
+/// Key recovery through the stabilizer fails with probability 6.4e-11.
+pub const PUF_SRAM: Preset = Preset {
+    name: "puf-sram",
+    code: RepetitionCode::new(75),
+    input_len: 1200,
+    one_probability: 0.5,
+    bit_error_rate: 0.15,
+};
+
+/// Every preset, in the order documented.
+pub const ALL: [Preset; 4] = [BIOMETRIC_IRIS, SENSOR_TELEMETRY, DOCUMENT_TEXT, PUF_SRAM];
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_match_their_documented_figures() {
+        let documented = [7.2e-11, 3.3e-22, 1.1e-14, 6.4e-11];
+        let close = |a: f64, b: f64| (a - b).abs() <= 0.05 * b;
+        for (preset, key) in ALL.into_iter().zip(documented) {
+            assert!(close(preset.key_failure_rate(), key), "{}", preset.name);
+            assert_eq!(
+                preset.code,
+                RepetitionCode::for_bit_error_rate(preset.bit_error_rate)
+            );
+        }
+        let stabilizer = crate::puf::PufStabilizer::new(PUF_SRAM.code);
+        assert_eq!(stabilizer.response_len(), PUF_SRAM.input_len);
+    }
+}
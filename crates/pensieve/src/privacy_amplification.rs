@@ -0,0 +1,276 @@
+//! Privacy amplification of collapsed values with a seeded universal hash.
+//!
+//! A collapsed digest is long but highly structured: every output byte is
+//! one of two values determined by its chunk's level, so a 1 MiB input still
+//! carries at most one bit of entropy per chunk. Privacy amplification
+//! compresses the chunk levels with a randomly chosen member of a
+//! 2-universal family (a Toeplitz matrix over GF(2)). By the leftover hash
+//! lemma, compressing `k` bits of min-entropy to `k - m` bits gives a value
+//! within statistical distance 2^(-m/2) of uniform. The random seed
+//! selecting the hash is public and is carried in [`AmplificationHelperData`].
+//!
+//! By default the min-entropy is estimated as one bit per chunk, which is
+//! only correct if every chunk level is an independent, unbiased bit, and
+//! the output is [`PrivacyAmplifier::SECURITY_MARGIN_BITS`] shorter. When
+//! the source is biased, pass a lower estimate (for example from
+//! [`crate::analysis::estimate_min_entropy`]), less the margin, to
+//! [`PrivacyAmplifier::with_output_bits`].
+
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use crate::{ChunkLayout, TbfConfig, ct_chunk_levels};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Public data needed to re-run the amplification on a new reading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmplificationHelperData {
+    tolerance: f32,   // Tolerance the input was collapsed with.
+    level_count: u16, // Number of chunk levels hashed (fixed by input length).
+    output_bits: u16, // Length of the amplified value.
+    seed: Vec<u8>,    // level_count + output_bits - 1 Toeplitz seed bits, MSB-first.
+}
+
+impl AmplificationHelperData {
+    /// Number of bits in the amplified output.
+    pub fn output_bits(&self) -> u16 {
+        self.output_bits
+    }
+
+    /// Serializes as `tolerance (f32) || level_count (u16) || output_bits (u16) || seed`,
+    /// integers little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + self.seed.len());
+        out.extend_from_slice(&self.tolerance.to_le_bytes());
+        out.extend_from_slice(&self.level_count.to_le_bytes());
+        out.extend_from_slice(&self.output_bits.to_le_bytes());
+        out.extend_from_slice(&self.seed);
+        out
+    }
+
+    /// Parses the output of [`AmplificationHelperData::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 8 {
+            return Err(Error::InvalidLength {
+                expected: 8,
+                found: bytes.len(),
+            });
+        }
+        let tolerance = f32::from_le_bytes(bytes[0..4].try_into().expect("4 bytes"));
+        let level_count = u16::from_le_bytes(bytes[4..6].try_into().expect("2 bytes"));
+        let output_bits = u16::from_le_bytes(bytes[6..8].try_into().expect("2 bytes"));
+        if output_bits == 0 || output_bits > level_count {
+            return Err(Error::InvalidParameter(
+                "output bits must be in 1..=level count",
+            ));
+        }
+        let expected = 8 + seed_len(level_count, output_bits);
+        if bytes.len() != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+        Ok(Self {
+            tolerance,
+            level_count,
+            output_bits,
+            seed: bytes[8..].to_vec(),
+        })
+    }
+}
+
+/// Collapses an input and compresses its chunk levels to their estimated min-entropy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrivacyAmplifier {
+    tolerance: f32,
+    output_bits: Option<u16>, // None: one bit per chunk, less the margin.
+}
+
+impl PrivacyAmplifier {
+    /// Bits of min-entropy the default output length leaves uncompressed,
+    /// bounding its distance from uniform by 2^-2.
+    pub const SECURITY_MARGIN_BITS: u16 = 4;
+
+    /// Creates an amplifier that outputs one bit per chunk, less
+    /// [`PrivacyAmplifier::SECURITY_MARGIN_BITS`].
+    pub fn new(tolerance: f32) -> Self {
+        Self {
+            tolerance,
+            output_bits: None,
+        }
+    }
+
+    /// Creates an amplifier with an explicit output length, typically a
+    /// min-entropy estimate for the source that is below one bit per chunk.
+    pub fn with_output_bits(tolerance: f32, output_bits: u16) -> Self {
+        Self {
+            tolerance,
+            output_bits: Some(output_bits),
+        }
+    }
+
+    /// The default min-entropy estimate, in bits, of the collapsed value of an
+    /// `input_len`-byte input: its number of chunks.
+    pub fn estimated_min_entropy_bits(input_len: usize) -> usize {
+        ChunkLayout::new(input_len, TbfConfig::MIN_TOLERANCE).map_or(0, |l| l.chunk_count())
+    }
+
+    /// Collapses `input`, draws a fresh hash seed and returns the amplified
+    /// value (packed MSB-first) with the helper data that reproduces it.
+    ///
+    /// Fails with [`Error::InvalidParameter`] for empty input, if the
+    /// requested output is longer than the number of chunks, or by default
+    /// if the chunks do not exceed the security margin.
+    pub fn enroll(
+        &self,
+        input: &[u8],
+        rng: &mut impl EntropySource,
+    ) -> Result<(Vec<u8>, AmplificationHelperData), Error> {
+        let levels = ct_chunk_levels(input, self.tolerance);
+        let level_count =
+            u16::try_from(levels.len()).map_err(|_| Error::InvalidParameter("too many chunks"))?;
+        if level_count == 0 {
+            return Err(Error::InvalidParameter("cannot amplify empty input"));
+        }
+        let output_bits = match self.output_bits {
+            Some(output_bits) => output_bits,
+            None => level_count
+                .checked_sub(Self::SECURITY_MARGIN_BITS)
+                .filter(|&bits| bits > 0)
+                .ok_or(Error::InvalidParameter(
+                    "too few chunks for the security margin",
+                ))?,
+        };
+        if output_bits == 0 || output_bits > level_count {
+            return Err(Error::InvalidParameter(
+                "output bits must be in 1..=chunk count",
+            ));
+        }
+
+        let mut seed = vec![0u8; seed_len(level_count, output_bits)];
+        rng.fill_bytes(&mut seed);
+        let helper = AmplificationHelperData {
+            tolerance: self.tolerance,
+            level_count,
+            output_bits,
+            seed,
+        };
+        Ok((toeplitz(&helper, &levels), helper))
+    }
+
+    /// Re-derives the amplified value for a new reading.
+    ///
+    /// Returns the enrolled value iff `input` collapses to the same chunk
+    /// levels. Fails with [`Error::InvalidParameter`] if the input length
+    /// yields a different number of chunks than at enrollment.
+    pub fn reproduce(input: &[u8], helper: &AmplificationHelperData) -> Result<Vec<u8>, Error> {
+        let levels = ct_chunk_levels(input, helper.tolerance);
+        if levels.len() != usize::from(helper.level_count) {
+            return Err(Error::InvalidParameter(
+                "input length does not match enrollment",
+            ));
+        }
+        Ok(toeplitz(helper, &levels))
+    }
+}
+
+/// Seed length in bytes for an `output_bits` x `level_count` Toeplitz matrix.
+fn seed_len(level_count: u16, output_bits: u16) -> usize {
+    (usize::from(level_count) + usize::from(output_bits) - 1).div_ceil(8)
+}
+
+/// Multiplies the level vector by the Toeplitz matrix T[i][j] = s[i - j + n - 1].
+///
+/// Only AND and XOR touch the (secret) levels, so this runs in constant time.
+fn toeplitz(helper: &AmplificationHelperData, levels: &[u8]) -> Vec<u8> {
+    let n = usize::from(helper.level_count);
+    let seed_bit = |k: usize| (helper.seed[k / 8] >> (7 - k % 8)) & 1;
+
+    let mut out = vec![0u8; usize::from(helper.output_bits).div_ceil(8)];
+    for i in 0..usize::from(helper.output_bits) {
+        let mut bit = 0u8;
+        for (j, &level) in levels.iter().enumerate() {
+            bit ^= seed_bit(i + n - 1 - j) & level;
+        }
+        out[i / 8] |= bit << (7 - i % 8);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    #[test]
+    fn test_reproduce_within_tolerance() {
+        let mut enrolled = [0u8; 32];
+        enrolled[0] = 0xFF;
+        enrolled[9] = 0xF0;
+        let mut noisy = enrolled;
+        noisy[0] ^= 0x01;
+
+        let amplifier = PrivacyAmplifier::new(0.05);
+        let (value, helper) = amplifier.enroll(&enrolled, &mut seeded(1)).unwrap();
+        assert_eq!(
+            helper.output_bits(),
+            8 - PrivacyAmplifier::SECURITY_MARGIN_BITS
+        );
+        assert_eq!(value.len(), 1);
+        assert_eq!(PrivacyAmplifier::reproduce(&noisy, &helper).unwrap(), value);
+    }
+
+    #[test]
+    fn test_output_length_follows_estimate() {
+        let amplifier = PrivacyAmplifier::with_output_bits(0.1, 5);
+        let (value, helper) = amplifier.enroll(&[0xA5; 64], &mut seeded(2)).unwrap();
+        assert_eq!(helper.output_bits(), 5);
+        assert_eq!(value[0] & 0b111, 0); // Padding bits stay clear.
+        assert!(
+            PrivacyAmplifier::with_output_bits(0.1, 9)
+                .enroll(&[0xA5; 64], &mut seeded(2))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_toeplitz_is_linear() {
+        // T(a ^ b) == T(a) ^ T(b) for every seed: the defining property used by the lemma.
+        let helper = AmplificationHelperData {
+            tolerance: 0.05,
+            level_count: 8,
+            output_bits: 6,
+            seed: vec![0b1011_0110, 0b0110_1000],
+        };
+        let a = [1, 0, 1, 1, 0, 0, 1, 0];
+        let b = [0, 1, 1, 0, 1, 0, 0, 1];
+        let ab: Vec<u8> = a.iter().zip(&b).map(|(x, y)| x ^ y).collect();
+        let (ta, tb) = (toeplitz(&helper, &a), toeplitz(&helper, &b));
+        assert_eq!(toeplitz(&helper, &ab), vec![ta[0] ^ tb[0]]);
+    }
+
+    #[test]
+    fn test_helper_roundtrip_and_length_check() {
+        let (_, helper) = PrivacyAmplifier::new(0.2)
+            .enroll(&[7; 20], &mut seeded(3))
+            .unwrap();
+        let decoded = AmplificationHelperData::from_bytes(&helper.to_bytes()).unwrap();
+        assert_eq!(decoded, helper);
+        assert!(PrivacyAmplifier::reproduce(&[7; 40], &helper).is_ok()); // still 8 chunks
+        assert!(PrivacyAmplifier::reproduce(&[7; 4], &helper).is_err());
+        // Two chunks leave nothing once the margin is taken.
+        assert!(
+            PrivacyAmplifier::new(0.2)
+                .enroll(&[7; 4], &mut seeded(3))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_estimated_min_entropy() {
+        assert_eq!(PrivacyAmplifier::estimated_min_entropy_bits(0), 0);
+        assert_eq!(PrivacyAmplifier::estimated_min_entropy_bits(4), 2);
+        assert_eq!(PrivacyAmplifier::estimated_min_entropy_bits(1024), 8);
+    }
+}
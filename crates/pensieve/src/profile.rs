@@ -0,0 +1,350 @@
+//! Per-chunk thresholds learned from samples of a source.
+//!
+//! The collapse compares every chunk against one threshold derived from the
+//! tolerance. Real sources are rarely that uniform: SRAM cells, sensor
+//! pixels and ADC bits are biased, some towards 1 and some towards 0, so a
+//! global threshold leaves some chunks at the same level for every device
+//! and those levels carry no information. A [`ThresholdProfile`] learns one
+//! threshold per chunk from a corpus of samples instead, at a quantile of
+//! the chunk's popcounts across the corpus: at the median, every chunk
+//! collapses to 1 for about half the corpus. The price is stability: at the
+//! median, many readings of a source sit right at a threshold, where a few
+//! flipped bits change the level. Enroll with readings whose popcounts keep
+//! a margin from the learned thresholds.
+//!
+//! A profile is tied to one input length, since the chunks are.
+//! [`ThresholdProfile::to_bytes`] writes it with the configuration it
+//! applies to, in the style of a [`DigestEnvelope`](crate::envelope::DigestEnvelope):
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 4 | magic `PNSP` |
+//! | 1 | format version (currently 1) |
+//! | 1 | algorithm version ([`TbfConfig::ALGORITHM_VERSION`]) |
+//! | 1 | bit order ([`BitOrder::id`](crate::BitOrder::id)) |
+//! | 1 | output transform ([`FinalTransform::id`](crate::FinalTransform::id)) |
+//! | 1 | mask base of [`FinalTransform::XorMask`](crate::FinalTransform::XorMask), otherwise 0 |
+//! | 1 | thresholding ([`Thresholding::id`](crate::Thresholding::id)), unused by the profile |
+//! | 4 | tolerance, f32 little-endian, unused by the profile |
+//! | 8 | input length in bytes, little-endian |
+//! | 1 | number of chunks |
+//! | 4 each | chunk thresholds, little-endian |
+//! | 4 | first 4 bytes of SHA-256 over everything above |
+//!
+//! The key of [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) is
+//! never stored; such profiles are read with
+//! [`ThresholdProfile::from_bytes_keyed`].
+
+use crate::header::{self, CHECKSUM_LEN, Format};
+use crate::{ChunkLayout, Error, TbfConfig};
+use alloc::vec::Vec;
+
+/// Header of every serialized profile.
+const FORMAT: Format = Format {
+    magic: *b"PNSP",
+    version: 1,
+    foreign: "not a pensieve threshold profile",
+    unsupported: "unsupported threshold profile format version",
+    needs_key: "keyed transform needs from_bytes_keyed",
+};
+/// Bytes before the thresholds.
+const HEADER_LEN: usize = 23;
+
+/// Chunk thresholds learned for inputs of one length.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::profile::ThresholdProfile;
+///
+/// // A biased source: the high nibble of every byte is stuck at 1.
+/// let samples: Vec<Vec<u8>> = (0..16u8).map(|i| vec![0xF0 | i; 16]).collect();
+/// let config = TbfConfig::default();
+/// let profile = ThresholdProfile::train(&samples, &config).unwrap();
+/// assert_eq!(profile.thresholds(), [12; 8]);
+///
+/// // The global threshold cannot tell these samples apart; the profile can.
+/// assert_eq!(config.collapse(&samples[1]), config.collapse(&samples[15]));
+/// assert_ne!(profile.collapse(&samples[1]), profile.collapse(&samples[15]));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThresholdProfile {
+    config: TbfConfig,    // Bit order and output transform.
+    input_len: usize,     // Bytes of every input.
+    thresholds: Vec<u32>, // A chunk collapses to 1 above its threshold.
+}
+
+impl ThresholdProfile {
+    /// Learns the median popcount of every chunk of `samples`, which must
+    /// all have the same length; see [`ThresholdProfile::train_quantile`].
+    pub fn train<S: AsRef<[u8]>>(samples: &[S], config: &TbfConfig) -> Result<Self, Error> {
+        Self::train_quantile(samples, config, 0.5)
+    }
+
+    /// Learns, for every chunk, the popcount that a `quantile` of the
+    /// chunks of `samples` stay at or below: a chunk collapses to 1 when it
+    /// holds more set bits than that. The bit order and output transform
+    /// come from `config`.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `samples` is empty, its
+    /// samples are empty or `quantile` is outside `0.0..=1.0`, and with
+    /// [`Error::InvalidLength`] if the samples differ in length.
+    pub fn train_quantile<S: AsRef<[u8]>>(
+        samples: &[S],
+        config: &TbfConfig,
+        quantile: f32,
+    ) -> Result<Self, Error> {
+        if !(0.0..=1.0).contains(&quantile) {
+            return Err(Error::InvalidParameter("quantile must be within 0.0..=1.0"));
+        }
+        let Some(first) = samples.first() else {
+            return Err(Error::InvalidParameter("at least one sample is required"));
+        };
+        let input_len = first.as_ref().len();
+        let Some(layout) = ChunkLayout::for_config(input_len * 8, config) else {
+            return Err(Error::InvalidParameter("samples must not be empty"));
+        };
+        let mut popcounts: Vec<Vec<u32>> = alloc::vec![Vec::new(); layout.chunk_count()];
+        for sample in samples {
+            let sample = sample.as_ref();
+            if sample.len() != input_len {
+                return Err(Error::InvalidLength {
+                    expected: input_len,
+                    found: sample.len(),
+                });
+            }
+            let bits = config.msb_first(sample);
+            for (counts, (start, end)) in popcounts.iter_mut().zip(layout.ranges()) {
+                counts.push(crate::ct_popcount_range(&bits, start, end));
+            }
+        }
+        let rank = (quantile * (samples.len() - 1) as f32 + 0.5) as usize;
+        let thresholds = popcounts
+            .into_iter()
+            .map(|mut counts| *counts.select_nth_unstable(rank).1)
+            .collect();
+        Ok(Self {
+            config: *config,
+            input_len,
+            thresholds,
+        })
+    }
+
+    /// The configuration whose bit order and output transform the profile
+    /// collapses with.
+    pub fn config(&self) -> &TbfConfig {
+        &self.config
+    }
+
+    /// Length of the inputs the profile applies to.
+    pub fn input_len(&self) -> usize {
+        self.input_len
+    }
+
+    /// The threshold of every chunk, in chunk order.
+    pub fn thresholds(&self) -> &[u32] {
+        &self.thresholds
+    }
+
+    /// Collapses `input` like [`TbfConfig::collapse`], comparing every
+    /// chunk against its learned threshold; in constant time.
+    ///
+    /// Fails with [`Error::InvalidLength`] unless `input` has the length
+    /// the profile was trained on.
+    pub fn collapse(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        if input.len() != self.input_len {
+            return Err(Error::InvalidLength {
+                expected: self.input_len,
+                found: input.len(),
+            });
+        }
+        let layout = ChunkLayout::for_config(input.len() * 8, &self.config).expect("non-empty");
+        let bits = self.config.msb_first(input);
+        let levels: Vec<u8> = layout
+            .ranges()
+            .zip(&self.thresholds)
+            .map(|(range, &threshold)| {
+                let sum = crate::ct_popcount_range(&bits, range.0, range.1);
+                crate::ct_level(sum, threshold.saturating_add(1))
+            })
+            .collect();
+        Ok(self.config.transform().apply(&levels, input.len()))
+    }
+
+    /// Serializes in the format described in the [module documentation](self).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = HEADER_LEN + 4 * self.thresholds.len() + CHECKSUM_LEN;
+        let mut out = FORMAT.write(&self.config, len);
+        out.extend_from_slice(&(self.input_len as u64).to_le_bytes());
+        out.push(self.thresholds.len() as u8);
+        for threshold in &self.thresholds {
+            out.extend_from_slice(&threshold.to_le_bytes());
+        }
+        header::seal(&mut out);
+        out
+    }
+
+    /// Parses the output of [`ThresholdProfile::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidLength`] if `bytes` is truncated or holds the
+    /// wrong number of thresholds for its input length, with
+    /// [`Error::InvalidChecksum`] on corruption and with
+    /// [`Error::InvalidParameter`] for profiles this build cannot interpret.
+    /// Profiles under
+    /// [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) need
+    /// [`ThresholdProfile::from_bytes_keyed`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, None)
+    }
+
+    /// [`ThresholdProfile::from_bytes`] for profiles that may be under
+    /// [`FinalTransform::KeyedPrf`](crate::FinalTransform::KeyedPrf) with
+    /// `key`.
+    pub fn from_bytes_keyed(bytes: &[u8], key: &[u8; 32]) -> Result<Self, Error> {
+        Self::from_bytes_with(bytes, Some(key))
+    }
+
+    fn from_bytes_with(bytes: &[u8], key: Option<&[u8; 32]>) -> Result<Self, Error> {
+        let (config, body) = FORMAT.open(bytes, HEADER_LEN, key)?;
+        let input_len = header::read_input_len(bytes)?;
+        let chunks = config.chunk_count(input_len);
+        let thresholds = &body[HEADER_LEN..];
+        if chunks == 0 || usize::from(bytes[22]) != chunks || thresholds.len() != 4 * chunks {
+            return Err(Error::InvalidLength {
+                expected: HEADER_LEN + 4 * chunks + CHECKSUM_LEN,
+                found: bytes.len(),
+            });
+        }
+        Ok(Self {
+            config,
+            input_len,
+            thresholds: thresholds
+                .chunks_exact(4)
+                .map(|t| u32::from_le_bytes(t.try_into().expect("4 bytes")))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use crate::{BitOrder, FinalTransform, Thresholding};
+
+    /// `count` samples of 32 bytes whose chunk `i` has each bit set with
+    /// probability about `(i + 1) / 9`.
+    fn biased_samples(count: usize, seed: u64) -> Vec<Vec<u8>> {
+        let mut fill = seeded(seed);
+        (0..count)
+            .map(|_| {
+                let mut sample = alloc::vec![0u8; 32];
+                for (i, byte) in sample.iter_mut().enumerate() {
+                    let mut noise = [0u8; 8];
+                    fill(&mut noise);
+                    let density = (i / 4 + 1) as u32 * 256 / 9;
+                    *byte = noise.iter().enumerate().fold(0, |acc, (bit, &r)| {
+                        acc | (u8::from(u32::from(r) < density) << bit)
+                    });
+                }
+                sample
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_median_thresholds_balance_every_chunk() {
+        let samples = biased_samples(200, 416);
+        let config = TbfConfig::new(0.125).with_thresholding(Thresholding::Symmetric);
+        let profile = ThresholdProfile::train(&samples, &config).unwrap();
+        assert_eq!(profile.input_len(), 32);
+        assert!(profile.thresholds().windows(2).all(|w| w[0] <= w[1]));
+
+        // The global threshold fixes the sparse and the dense chunks; the
+        // profile sets every chunk for a good share of the corpus.
+        let ones = |collapse: &dyn Fn(&[u8]) -> Vec<u8>, chunk: usize| {
+            samples.iter().filter(|s| collapse(s)[chunk] != 0).count()
+        };
+        let global = |s: &[u8]| {
+            TbfConfig::new(0.125)
+                .with_thresholding(Thresholding::Symmetric)
+                .with_transform(FinalTransform::Identity)
+                .collapse(s)
+        };
+        let identity =
+            ThresholdProfile::train(&samples, &config.with_transform(FinalTransform::Identity))
+                .unwrap();
+        let learned = |s: &[u8]| identity.collapse(s).unwrap();
+        assert_eq!(ones(&global, 0), 0);
+        assert_eq!(ones(&global, 7), 200);
+        for chunk in 0..8 {
+            assert!((40..=120).contains(&ones(&learned, chunk)), "chunk {chunk}");
+        }
+
+        assert_eq!(
+            profile.collapse(&samples[0][..31]),
+            Err(Error::InvalidLength {
+                expected: 32,
+                found: 31
+            })
+        );
+        // Nothing in the corpus exceeds its maximum.
+        let identity = config.with_transform(FinalTransform::Identity);
+        let highest = ThresholdProfile::train_quantile(&samples, &identity, 1.0).unwrap();
+        assert!(
+            samples
+                .iter()
+                .all(|s| highest.collapse(s).unwrap() == [0; 32])
+        );
+    }
+
+    #[test]
+    fn test_training_checks_its_samples() {
+        let config = TbfConfig::default();
+        assert!(ThresholdProfile::train::<&[u8]>(&[], &config).is_err());
+        assert!(ThresholdProfile::train(&[[0u8; 0]], &config).is_err());
+        assert!(ThresholdProfile::train(&[&[0u8; 4][..], &[0u8; 5]], &config).is_err());
+        assert!(ThresholdProfile::train_quantile(&[[0u8; 4]], &config, 1.5).is_err());
+    }
+
+    #[test]
+    fn test_serialization_round_trips_and_rejects_corruption() {
+        let samples = biased_samples(20, 1);
+        let key = [7u8; 32];
+        for config in [
+            TbfConfig::new(0.05)
+                .with_thresholding(Thresholding::Symmetric)
+                .with_bit_order(BitOrder::LsbFirst),
+            TbfConfig::new(0.2)
+                .with_thresholding(Thresholding::Symmetric)
+                .with_transform(FinalTransform::KeyedPrf { key }),
+        ] {
+            let profile = ThresholdProfile::train(&samples, &config).unwrap();
+            let bytes = profile.to_bytes();
+            assert_eq!(bytes.len(), HEADER_LEN + 32 + CHECKSUM_LEN);
+            assert_eq!(
+                ThresholdProfile::from_bytes_keyed(&bytes, &key),
+                Ok(profile)
+            );
+        }
+        let profile = ThresholdProfile::train(&samples, &TbfConfig::default()).unwrap();
+        let mut bytes = profile.to_bytes();
+        assert_eq!(ThresholdProfile::from_bytes(&bytes).as_ref(), Ok(&profile));
+        bytes[HEADER_LEN] ^= 1;
+        assert_eq!(
+            ThresholdProfile::from_bytes(&bytes),
+            Err(Error::InvalidChecksum)
+        );
+        assert!(ThresholdProfile::from_bytes(&bytes[..10]).is_err());
+
+        // A length whose bit count overflows, under a valid checksum.
+        let mut body = profile.to_bytes();
+        body.truncate(body.len() - CHECKSUM_LEN);
+        body[14..22].copy_from_slice(&u64::MAX.to_le_bytes());
+        header::seal(&mut body);
+        assert_eq!(
+            ThresholdProfile::from_bytes(&body),
+            Err(Error::InvalidParameter("input length overflows"))
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Private set intersection over fuzzy fingerprints.
+//!
+//! Two organizations each hold fingerprints of their records, such as
+//! [`CollapsedDigest`](crate::digest::CollapsedDigest)s of customer files.
+//! The requester learns which of its records the responder also holds, up
+//! to the tolerance; neither side learns anything else about the other's
+//! records beyond their number.
+//!
+//! Fingerprints of two readings of one record often differ in a few bytes,
+//! so records are compared by bands, as in locality-sensitive hashing:
+//! each fingerprint is cut into `bands` contiguous sub-digests of (nearly)
+//! equal length, and two records are held in common if any band of one
+//! equals the same band of the other. More bands tolerate more differing
+//! bytes and also let more unrelated records match.
+//!
+//! The bands are compared under commutative blinding (Diffie-Hellman PSI)
+//! in the prime-order group of edwards25519, with a fresh secret scalar on
+//! each side: a band `x` becomes `a * H(x)` for the requester's `a` and
+//! `b * H(x)` for the responder's `b`, and the requester compares the
+//! `a * b * H(x)` of both sides.
+//!
+//! ```text
+//! Requester                                  Responder
+//! a * H(x) per band          -- Request -->
+//!                            <-- Response --  b * (a * H(x)) per band,
+//!                                             b * H(y) per distinct band
+//! compare a * (b * H(y)) with b * (a * H(x))
+//! ```
+//!
+//! The protocol is secure against semi-honest parties only: a responder
+//! that deviates can make the requester see wrong matches, and a requester
+//! that sends chosen points learns whether the responder holds records of
+//! its choosing. The requester also learns how many distinct bands the
+//! responder holds. For a mutual result, the requester shares its result or
+//! the parties run the protocol a second time with the roles swapped.
+//!
+//! Messages serialize with `to_bytes` / `from_bytes`; transport is up to the
+//! caller.
+
+use crate::crypto::ed25519::{hash_to_point, mul_point, random_scalar};
+use crate::crypto::sha256::Sha256;
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use alloc::vec::Vec;
+
+/// Domain separation for hashing bands onto the group.
+const BAND_LABEL: &[u8] = b"pensieve/psi/v1/band";
+
+/// Bytes per group element.
+const POINT_LEN: usize = 32;
+
+/// First message, sent by the requester: its blinded bands, record by
+/// record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    bands: u32,
+    blinded: Vec<[u8; POINT_LEN]>, // Band `j` of record `i` at `i * bands + j`.
+}
+
+impl Request {
+    /// Number of bands per fingerprint.
+    pub fn bands(&self) -> u32 {
+        self.bands
+    }
+
+    /// Number of records the requester holds.
+    pub fn record_count(&self) -> usize {
+        self.blinded.len() / self.bands as usize
+    }
+
+    /// Serializes as the band count and the number of blinded bands (4
+    /// bytes each, little-endian) followed by the blinded bands.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + POINT_LEN * self.blinded.len());
+        out.extend_from_slice(&self.bands.to_le_bytes());
+        write_points(&mut out, &self.blinded);
+        out
+    }
+
+    /// Parses the output of [`Request::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 4 {
+            return Err(Error::InvalidLength {
+                expected: 8,
+                found: bytes.len(),
+            });
+        }
+        let bands = u32::from_le_bytes(bytes[..4].try_into().expect("4 bytes"));
+        let (blinded, rest) = read_points(&bytes[4..])?;
+        check_len(rest, 0)?;
+        if bands == 0 || !blinded.len().is_multiple_of(bands as usize) {
+            return Err(Error::InvalidParameter(
+                "blinded bands do not form whole records",
+            ));
+        }
+        Ok(Self { bands, blinded })
+    }
+}
+
+/// Second message, sent by the responder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    evaluated: Vec<[u8; POINT_LEN]>, // The request's bands, blinded again, in order.
+    bands: Vec<[u8; POINT_LEN]>,     // The responder's blinded bands, sorted and distinct.
+}
+
+impl Response {
+    /// Serializes as the number of evaluated bands (4 bytes, little-endian)
+    /// and the evaluated bands, then the same for the responder's bands.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + POINT_LEN * (self.evaluated.len() + self.bands.len()));
+        write_points(&mut out, &self.evaluated);
+        write_points(&mut out, &self.bands);
+        out
+    }
+
+    /// Parses the output of [`Response::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let (evaluated, rest) = read_points(bytes)?;
+        let (bands, rest) = read_points(rest)?;
+        check_len(rest, 0)?;
+        Ok(Self { evaluated, bands })
+    }
+}
+
+/// Requester state between sending the [`Request`] and receiving the
+/// [`Response`].
+pub struct Requester {
+    scalar: [u8; 32], // a.
+    bands: usize,
+    record_count: usize,
+}
+
+impl Requester {
+    /// Blinds the bands of `fingerprints`, each cut into `bands` bands.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `bands` is zero or a
+    /// fingerprint is shorter than `bands` bytes.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::psi::{Request, Requester, Response, respond};
+    ///
+    /// let mut rng = |dest: &mut [u8]| dest.iter_mut().for_each(|b| *b = 0x42);
+    /// let ours: [&[u8]; 3] = [b"alice...", b"bob.....", b"carol..."];
+    /// // A noisy copy of bob's fingerprint, and someone else's.
+    /// let theirs: [&[u8]; 2] = [b"bob...!.", b"dave...."];
+    ///
+    /// let (requester, request) = Requester::start(&ours, 2, &mut rng).unwrap();
+    /// let request = Request::from_bytes(&request.to_bytes()).unwrap();
+    /// let response = respond(&theirs, &request, &mut rng).unwrap();
+    /// let response = Response::from_bytes(&response.to_bytes()).unwrap();
+    /// assert_eq!(requester.finish(&response).unwrap(), [1]);
+    /// ```
+    pub fn start(
+        fingerprints: &[impl AsRef<[u8]>],
+        bands: usize,
+        rng: &mut impl EntropySource,
+    ) -> Result<(Self, Request), Error> {
+        let bands_u32 =
+            u32::try_from(bands).map_err(|_| Error::InvalidParameter("too many bands"))?;
+        let scalar = random_scalar(rng);
+        let blinded = blind_bands(fingerprints, bands, &scalar)?;
+        let state = Self {
+            scalar,
+            bands,
+            record_count: fingerprints.len(),
+        };
+        let request = Request {
+            bands: bands_u32,
+            blinded,
+        };
+        Ok((state, request))
+    }
+
+    /// The indices, in ascending order, of the requester's fingerprints
+    /// sharing a band with one of the responder's.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if the response does not
+    /// answer the request or holds invalid group elements.
+    pub fn finish(self, response: &Response) -> Result<Vec<usize>, Error> {
+        if response.evaluated.len() != self.record_count * self.bands {
+            return Err(Error::InvalidParameter(
+                "response does not answer the request",
+            ));
+        }
+        let mut theirs = response
+            .bands
+            .iter()
+            .map(|point| blind(point, &self.scalar))
+            .collect::<Result<Vec<_>, _>>()?;
+        theirs.sort_unstable();
+        Ok(response
+            .evaluated
+            .chunks(self.bands)
+            .enumerate()
+            .filter(|(_, bands)| bands.iter().any(|band| theirs.binary_search(band).is_ok()))
+            .map(|(index, _)| index)
+            .collect())
+    }
+}
+
+/// Answers `request` with the bands of the responder's `fingerprints`, cut
+/// into the request's number of bands.
+///
+/// Fails with [`Error::InvalidParameter`] if a fingerprint is shorter than
+/// the band count or the request holds invalid group elements.
+pub fn respond(
+    fingerprints: &[impl AsRef<[u8]>],
+    request: &Request,
+    rng: &mut impl EntropySource,
+) -> Result<Response, Error> {
+    let scalar = random_scalar(rng);
+    let evaluated = request
+        .blinded
+        .iter()
+        .map(|point| blind(point, &scalar))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut bands = blind_bands(fingerprints, request.bands as usize, &scalar)?;
+    bands.sort_unstable();
+    bands.dedup();
+    Ok(Response { evaluated, bands })
+}
+
+/// `scalar * H(band)` for every band of every fingerprint, record by record.
+fn blind_bands(
+    fingerprints: &[impl AsRef<[u8]>],
+    bands: usize,
+    scalar: &[u8; 32],
+) -> Result<Vec<[u8; POINT_LEN]>, Error> {
+    if bands == 0 {
+        return Err(Error::InvalidParameter("at least one band is required"));
+    }
+    let mut blinded = Vec::with_capacity(fingerprints.len() * bands);
+    for fingerprint in fingerprints {
+        let fingerprint = fingerprint.as_ref();
+        if fingerprint.len() < bands {
+            return Err(Error::InvalidParameter(
+                "fingerprints need at least one byte per band",
+            ));
+        }
+        for band in 0..bands {
+            let range = band * fingerprint.len() / bands..(band + 1) * fingerprint.len() / bands;
+            let index = (band as u32).to_le_bytes();
+            let hashed = hash_to_point(&Sha256::digest(&[BAND_LABEL, &index, &fingerprint[range]]));
+            blinded.push(blind(&hashed, scalar)?);
+        }
+    }
+    Ok(blinded)
+}
+
+fn blind(point: &[u8; POINT_LEN], scalar: &[u8; 32]) -> Result<[u8; POINT_LEN], Error> {
+    mul_point(point, scalar).ok_or(Error::InvalidParameter(
+        "point is not in the prime-order group",
+    ))
+}
+
+fn write_points(out: &mut Vec<u8>, points: &[[u8; POINT_LEN]]) {
+    let count = u32::try_from(points.len()).expect("point count fits in u32");
+    out.extend_from_slice(&count.to_le_bytes());
+    points.iter().for_each(|point| out.extend_from_slice(point));
+}
+
+/// A count-prefixed list of points, and the bytes after it.
+fn read_points(bytes: &[u8]) -> Result<(Vec<[u8; POINT_LEN]>, &[u8]), Error> {
+    if bytes.len() < 4 {
+        return Err(Error::InvalidLength {
+            expected: 4,
+            found: bytes.len(),
+        });
+    }
+    let count = u32::from_le_bytes(bytes[..4].try_into().expect("4 bytes")) as usize;
+    let len = count
+        .checked_mul(POINT_LEN)
+        .ok_or(Error::InvalidParameter("point count overflows"))?;
+    let rest = &bytes[4..];
+    if rest.len() < len {
+        return Err(Error::InvalidLength {
+            expected: 4 + len,
+            found: bytes.len(),
+        });
+    }
+    let points = rest[..len]
+        .chunks_exact(POINT_LEN)
+        .map(|point| point.try_into().expect("32 bytes"))
+        .collect();
+    Ok((points, &rest[len..]))
+}
+
+fn check_len(bytes: &[u8], expected: usize) -> Result<(), Error> {
+    if bytes.len() != expected {
+        return Err(Error::InvalidLength {
+            expected,
+            found: bytes.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TbfConfig;
+    use crate::digest::{CollapsedDigest, compose};
+    use crate::entropy::tests::seeded;
+
+    /// A fingerprint of eight 16-byte fields, each collapsed on its own.
+    fn fingerprint(fields: &[[u8; 16]; 8]) -> Vec<u8> {
+        let config = TbfConfig::new(0.125);
+        let parts: Vec<_> = fields
+            .iter()
+            .map(|field| CollapsedDigest::collapse(field, &config))
+            .collect();
+        compose(&parts).unwrap().as_bytes().to_vec()
+    }
+
+    fn record(seed: u64) -> [[u8; 16]; 8] {
+        let mut fields = [[0u8; 16]; 8];
+        let mut rng = seeded(seed);
+        for field in &mut fields {
+            rng(field);
+            field.iter_mut().for_each(|b| *b &= 0x11);
+        }
+        fields
+    }
+
+    /// Runs the protocol, serializing every message on the way.
+    fn run(ours: &[Vec<u8>], theirs: &[Vec<u8>], bands: usize) -> Result<Vec<usize>, Error> {
+        let (requester, request) = Requester::start(ours, bands, &mut seeded(1))?;
+        let request = Request::from_bytes(&request.to_bytes())?;
+        let response = respond(theirs, &request, &mut seeded(2))?;
+        requester.finish(&Response::from_bytes(&response.to_bytes())?)
+    }
+
+    #[test]
+    fn test_finds_shared_records() {
+        let ours: Vec<_> = (0..6).map(|seed| record(10 + seed)).collect();
+        // Records 1 and 4 again, with one field changed beyond the tolerance.
+        let mut theirs: Vec<_> = (0..5).map(|seed| record(100 + seed)).collect();
+        let (mut one, mut four) = (ours[1], ours[4]);
+        one[0] = [0xff; 16];
+        four[7] = [0xff; 16];
+        theirs.extend([one, four]);
+
+        let ours: Vec<_> = ours.iter().map(fingerprint).collect();
+        let theirs: Vec<_> = theirs.iter().map(fingerprint).collect();
+        assert_eq!(run(&ours, &theirs, 4), Ok(vec![1, 4]));
+        // A single band needs exact equality.
+        assert_eq!(run(&ours, &theirs, 1), Ok(vec![]));
+        assert_eq!(run(&ours, &[], 4), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let ours = [vec![1u8; 8]];
+        assert!(Requester::start(&ours, 0, &mut seeded(1)).is_err());
+        assert!(Requester::start(&ours, 9, &mut seeded(1)).is_err());
+
+        let (requester, request) = Requester::start(&ours, 2, &mut seeded(1)).unwrap();
+        let bytes = request.to_bytes();
+        assert!(Request::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Request::from_bytes(&[&[3, 0, 0, 0], &bytes[4..]].concat()).is_err());
+        let mut tampered = request.clone();
+        tampered.blinded[0] = [0xff; 32];
+        assert!(respond(&ours, &tampered, &mut seeded(2)).is_err());
+
+        let mut response = respond(&ours, &request, &mut seeded(2)).unwrap();
+        response.evaluated.pop();
+        assert!(requester.finish(&response).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Stabilization of physically unclonable function (PUF) responses.
+//!
+//! An SRAM PUF response (the power-up contents of an uninitialized SRAM
+//! region) is unique per device but noisy: typically 5–15% of its bits flip
+//! from one power cycle to the next. [`PufStabilizer`] turns it into a stable
+//! device key with a code-offset construction over a [`RepetitionCode`]:
+//!
+//! - `enroll` picks a random 128-bit secret, encodes it, and stores
+//!   `response ^ codeword` plus a short check value as helper data.
+//! - `reconstruct` XORs a fresh response with the helper data, majority-decodes
+//!   the secret, and verifies it against the check value.
+//!
+//! Everything works on caller-provided buffers with a small, fixed amount of
+//! stack and no heap, so this module is available without `std` or `alloc`.
+//! Decoding and the check comparison are constant-time.
+//!
+//! Helper data is public but not free: it reveals the XOR of the response
+//! bits within each repetition group, leaving about one bit of secrecy per
+//! group, i.e. per secret bit, when response bits are independent and
+//! unbiased. Biased cells leak more; debias them before enrollment.
+
+use crate::crypto::ct_eq;
+use crate::crypto::hmac::{HmacSha256, hkdf_expand, hkdf_extract};
+use crate::ecc::RepetitionCode;
+use crate::entropy::EntropySource;
+use crate::error::Error;
+use crate::fuzzy_extractor::ExtractedKey;
+
+/// Bytes of the random secret encoded into the helper data.
+pub const SECRET_LEN: usize = 16;
+/// Bytes of the check value appended to the helper data.
+const CHECK_LEN: usize = 16;
+/// Domain separation label for the check value.
+const CHECK_INFO: &[u8] = b"pensieve/puf/v1/check";
+/// HKDF salt and info for the device key.
+const KEY_SALT: &[u8] = b"pensieve/puf/v1";
+const KEY_INFO: &[u8] = b"pensieve/puf/v1/key";
+
+/// Enrolls PUF responses and reconstructs the device key on every boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PufStabilizer {
+    code: RepetitionCode,
+}
+
+impl PufStabilizer {
+    /// Creates a stabilizer protecting the secret with `code`.
+    pub fn new(code: RepetitionCode) -> Self {
+        Self { code }
+    }
+
+    /// Sizes the code for responses whose bits flip independently with
+    /// probability `bit_error_rate` (see [`RepetitionCode::for_bit_error_rate`]).
+    #[cfg(feature = "std")]
+    pub fn for_bit_error_rate(bit_error_rate: f32) -> Self {
+        Self::new(RepetitionCode::for_bit_error_rate(bit_error_rate))
+    }
+
+    /// The code protecting the secret.
+    pub fn code(&self) -> RepetitionCode {
+        self.code
+    }
+
+    /// Bytes of PUF response consumed by `enroll` and `reconstruct`.
+    pub fn response_len(&self) -> usize {
+        SECRET_LEN * self.code.repetitions()
+    }
+
+    /// Bytes of helper data written by `enroll`.
+    pub fn helper_len(&self) -> usize {
+        self.response_len() + CHECK_LEN
+    }
+
+    /// Enrolls `response`, writing [`helper_len`](Self::helper_len) bytes of
+    /// helper data into `helper` and returning the device key.
+    pub fn enroll(
+        &self,
+        response: &[u8],
+        helper: &mut [u8],
+        rng: &mut impl EntropySource,
+    ) -> Result<ExtractedKey, Error> {
+        self.check_lengths(response, helper.len())?;
+        let mut secret = [0u8; SECRET_LEN];
+        rng.fill_bytes(&mut secret);
+
+        let (offset, check) = helper.split_at_mut(self.response_len());
+        self.code
+            .encode_into(&secret, offset)
+            .expect("length checked above");
+        for (byte, noisy) in offset.iter_mut().zip(response) {
+            *byte ^= noisy;
+        }
+        check.copy_from_slice(&check_value(&secret));
+        Ok(device_key(&secret))
+    }
+
+    /// Reconstructs the device key from a fresh `response` and the stored helper data.
+    ///
+    /// Fails with [`Error::ReconstructionFailed`] when too many bits flipped
+    /// (or `response` comes from another device).
+    pub fn reconstruct(&self, response: &[u8], helper: &[u8]) -> Result<ExtractedKey, Error> {
+        self.check_lengths(response, helper.len())?;
+        let (offset, check) = helper.split_at(self.response_len());
+        let mut secret = [0u8; SECRET_LEN];
+        self.code
+            .decode_masked_into(offset, Some(response), &mut secret)
+            .expect("length checked above");
+        if !ct_eq(&check_value(&secret), check) {
+            return Err(Error::ReconstructionFailed);
+        }
+        Ok(device_key(&secret))
+    }
+
+    fn check_lengths(&self, response: &[u8], helper_len: usize) -> Result<(), Error> {
+        for (expected, found) in [
+            (self.response_len(), response.len()),
+            (self.helper_len(), helper_len),
+        ] {
+            if expected != found {
+                return Err(Error::InvalidLength { expected, found });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_value(secret: &[u8; SECRET_LEN]) -> [u8; CHECK_LEN] {
+    let tag = HmacSha256::mac(secret, &[CHECK_INFO]);
+    let mut check = [0u8; CHECK_LEN];
+    check.copy_from_slice(&tag[..CHECK_LEN]);
+    check
+}
+
+fn device_key(secret: &[u8; SECRET_LEN]) -> ExtractedKey {
+    let prk = hkdf_extract(KEY_SALT, secret);
+    let mut key = [0u8; 32];
+    hkdf_expand(&prk, KEY_INFO, &mut key);
+    ExtractedKey::from_bytes(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    /// Flips each bit of `response` with probability `rate`.
+    fn power_cycle(response: &[u8], rate: f64, seed: u64) -> Vec<u8> {
+        let mut noise = seeded(seed);
+        response
+            .iter()
+            .map(|&byte| {
+                let mut flips = 0u8;
+                for bit in 0..8 {
+                    let mut sample = [0u8; 8];
+                    noise(&mut sample);
+                    let uniform = (u64::from_le_bytes(sample) >> 11) as f64 / (1u64 << 53) as f64;
+                    if uniform < rate {
+                        flips |= 1 << bit;
+                    }
+                }
+                byte ^ flips
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reconstruct_across_power_cycles() {
+        // What `for_bit_error_rate(0.10)` picks.
+        let puf = PufStabilizer::new(RepetitionCode::new(49));
+        let mut enrolled = vec![0u8; puf.response_len()];
+        seeded(1)(&mut enrolled);
+        let mut helper = vec![0u8; puf.helper_len()];
+        let key = puf.enroll(&enrolled, &mut helper, &mut seeded(2)).unwrap();
+
+        for boot in 0..5 {
+            let response = power_cycle(&enrolled, 0.10, 100 + boot);
+            assert_eq!(puf.reconstruct(&response, &helper).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn test_other_device_rejected() {
+        let puf = PufStabilizer::new(RepetitionCode::new(15));
+        let mut enrolled = vec![0u8; puf.response_len()];
+        seeded(3)(&mut enrolled);
+        let mut helper = vec![0u8; puf.helper_len()];
+        puf.enroll(&enrolled, &mut helper, &mut seeded(4)).unwrap();
+
+        let mut other = vec![0u8; puf.response_len()];
+        seeded(5)(&mut other);
+        assert_eq!(
+            puf.reconstruct(&other, &helper),
+            Err(Error::ReconstructionFailed)
+        );
+    }
+
+    #[test]
+    fn test_buffer_lengths_checked() {
+        let puf = PufStabilizer::new(RepetitionCode::new(3));
+        assert_eq!(puf.response_len(), 48);
+        assert_eq!(puf.helper_len(), 64);
+        assert_eq!(
+            puf.enroll(&[0; 47], &mut [0; 64], &mut seeded(6)),
+            Err(Error::InvalidLength {
+                expected: 48,
+                found: 47
+            })
+        );
+        assert_eq!(
+            puf.reconstruct(&[0; 48], &[0; 63]),
+            Err(Error::InvalidLength {
+                expected: 64,
+                found: 63
+            })
+        );
+    }
+}
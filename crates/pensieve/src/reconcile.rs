@@ -0,0 +1,394 @@
+//! Reconciling sets of digests between two nodes.
+//!
+//! Two nodes deduplicating the same data each hold a large set of digests
+//! and want to learn which ones only the other has. Sending either set
+//! costs its full size; an invertible Bloom lookup table (IBLT) costs space
+//! proportional to the *difference* of the sets instead.
+//!
+//! Each node inserts its digests into an [`Iblt`] of the same size and key
+//! length. One sends its table; the other [`subtract`](Iblt::subtract)s it
+//! from its own, which cancels every digest both hold, and
+//! [`decode`](Iblt::decode)s the rest into the digests on either side.
+//!
+//! A table of `n` cells decodes a difference of up to about `n / 1.5`
+//! digests with high probability, and fails more and more often as the
+//! difference grows beyond that; [`Iblt::for_difference`] sizes a table
+//! for an expected difference with some slack. A failed decode reports nothing
+//! rather than a wrong difference, so the nodes can retry with a larger
+//! table. The tables hold sets: inserting a digest twice, or removing one
+//! that was never inserted, leaves cells that cannot be decoded.
+//!
+//! Every node hashes digests the same way, so anyone can craft digests
+//! that collide in the table; reconcile only with nodes that would not
+//! gain from a failed decode.
+
+use crate::Error;
+use crate::crypto::sha256::Sha256;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Domain separation for the hash placing and checking keys.
+const HASH_INFO: &[u8] = b"pensieve iblt";
+
+/// Number of cells, one in each third of the table, every key is added to.
+const HASH_COUNT: usize = 3;
+
+/// An invertible Bloom lookup table of fixed-length digests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Iblt {
+    key_len: usize,
+    counts: Vec<i32>,    // Per cell: insertions minus removals.
+    key_sums: Vec<u8>,   // Per cell: XOR of the keys, `key_len` bytes each.
+    hash_sums: Vec<u64>, // Per cell: XOR of the keys' check hashes.
+}
+
+/// The result of [`Iblt::decode`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Difference {
+    /// Digests inserted into the table but not into the subtracted one.
+    pub local: Vec<Vec<u8>>,
+    /// Digests inserted into the subtracted table but not into this one.
+    pub remote: Vec<Vec<u8>>,
+}
+
+impl Iblt {
+    /// Length of the serialized header: key length and cell count.
+    const HEADER_LEN: usize = 8;
+
+    /// An empty table of `cell_count` cells, rounded up to a multiple of
+    /// three, for digests of `key_len` bytes.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if either is zero.
+    pub fn new(cell_count: usize, key_len: usize) -> Result<Self, Error> {
+        if cell_count == 0 || key_len == 0 {
+            return Err(Error::InvalidParameter(
+                "cell count and key length must be positive",
+            ));
+        }
+        let cell_count = cell_count.div_ceil(HASH_COUNT) * HASH_COUNT;
+        Ok(Self {
+            key_len,
+            counts: vec![0; cell_count],
+            key_sums: vec![0; cell_count * key_len],
+            hash_sums: vec![0; cell_count],
+        })
+    }
+
+    /// An empty table that decodes a difference of up to `difference`
+    /// digests of `key_len` bytes with high probability.
+    pub fn for_difference(difference: usize, key_len: usize) -> Result<Self, Error> {
+        Self::new(difference + difference / 2 + 24, key_len)
+    }
+
+    /// Number of cells.
+    pub fn cell_count(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Length of the digests in the table, in bytes.
+    pub fn key_len(&self) -> usize {
+        self.key_len
+    }
+
+    /// Adds `key` to the table.
+    ///
+    /// Fails with [`Error::InvalidLength`] unless `key` has the table's key
+    /// length.
+    pub fn insert(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.toggle(key, 1)
+    }
+
+    /// Takes `key` out of the table.
+    ///
+    /// Fails with [`Error::InvalidLength`] unless `key` has the table's key
+    /// length.
+    pub fn remove(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.toggle(key, -1)
+    }
+
+    /// The table of the digests in this one minus those in `other`, cell by
+    /// cell; digests in both cancel out.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless the tables have the
+    /// same cell count and key length.
+    pub fn subtract(&self, other: &Self) -> Result<Self, Error> {
+        if self.key_len != other.key_len || self.cell_count() != other.cell_count() {
+            return Err(Error::InvalidParameter(
+                "tables differ in cell count or key length",
+            ));
+        }
+        let mut out = self.clone();
+        for (count, other) in out.counts.iter_mut().zip(&other.counts) {
+            *count = count.wrapping_sub(*other);
+        }
+        xor_into(&mut out.key_sums, &other.key_sums);
+        for (sum, other) in out.hash_sums.iter_mut().zip(&other.hash_sums) {
+            *sum ^= other;
+        }
+        Ok(out)
+    }
+
+    /// Lists every digest in the table by peeling cells holding a single
+    /// one; for a [`subtract`](Self::subtract)ed table, the difference of
+    /// the two sets. `None` if the table is too small for the difference.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::digest::{CollapsedDigest, compose};
+    /// use pensieve::reconcile::Iblt;
+    ///
+    /// // Records of two fields, digested field by field; the fields of
+    /// // record `i` collapse to the two bytes of `i`.
+    /// let config = TbfConfig::new(0.125);
+    /// let field = |byte: u8| -> [u8; 16] {
+    ///     core::array::from_fn(|k| if byte >> (k / 2) & 1 == 1 { 0xFF } else { 0 })
+    /// };
+    /// let digest = |i: u16| {
+    ///     let [high, low] = i.to_be_bytes();
+    ///     let parts = [field(high), field(low)].map(|f| CollapsedDigest::collapse(&f, &config));
+    ///     compose(&parts).unwrap().as_bytes().to_vec()
+    /// };
+    /// let table = |records: core::ops::Range<u16>| {
+    ///     let mut table = Iblt::for_difference(10, 32).unwrap();
+    ///     for i in records {
+    ///         table.insert(&digest(i)).unwrap();
+    ///     }
+    ///     table
+    /// };
+    ///
+    /// // The server has 1000 records, the agent lacks the last one and sends
+    /// // a table of 39 cells instead of its 999 digests.
+    /// let server = table(0..1000);
+    /// let agent = table(0..999);
+    /// let difference = server.subtract(&agent).unwrap().decode().unwrap();
+    /// assert_eq!(difference.local, [digest(999)]);
+    /// assert!(difference.remote.is_empty());
+    /// ```
+    pub fn decode(&self) -> Option<Difference> {
+        let mut table = self.clone();
+        let mut difference = Difference::default();
+        let mut pending: Vec<usize> = (0..table.cell_count()).collect();
+        while let Some(cell) = pending.pop() {
+            let sign = table.counts[cell];
+            if !matches!(sign, 1 | -1) {
+                continue;
+            }
+            let key = table.key_sum(cell).to_vec();
+            if hashes(&key).0 != table.hash_sums[cell] {
+                continue;
+            }
+            table
+                .toggle(&key, -sign)
+                .expect("the key has the table's key length");
+            pending.extend(table.cells(&key));
+            if sign == 1 {
+                difference.local.push(key);
+            } else {
+                difference.remote.push(key);
+            }
+        }
+        let empty = table.counts.iter().all(|&count| count == 0)
+            && table.key_sums.iter().all(|&byte| byte == 0)
+            && table.hash_sums.iter().all(|&sum| sum == 0);
+        empty.then_some(difference)
+    }
+
+    /// Serializes as `key_len (u32) || cell_count (u32)` followed by every
+    /// cell as `count (i32) || key_sum || hash_sum (u64)`. All integers are
+    /// little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let cell_len = 12 + self.key_len;
+        let mut out = Vec::with_capacity(Self::HEADER_LEN + self.cell_count() * cell_len);
+        out.extend_from_slice(&(self.key_len as u32).to_le_bytes());
+        out.extend_from_slice(&(self.cell_count() as u32).to_le_bytes());
+        for cell in 0..self.cell_count() {
+            out.extend_from_slice(&self.counts[cell].to_le_bytes());
+            out.extend_from_slice(self.key_sum(cell));
+            out.extend_from_slice(&self.hash_sums[cell].to_le_bytes());
+        }
+        out
+    }
+
+    /// Parses the output of [`Iblt::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidLength`] if `bytes` is truncated or too
+    /// long, and with [`Error::InvalidParameter`] unless the key length is
+    /// positive and the cell count a positive multiple of three.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < Self::HEADER_LEN {
+            return Err(Error::InvalidLength {
+                expected: Self::HEADER_LEN,
+                found: bytes.len(),
+            });
+        }
+        let key_len = u32::from_le_bytes(bytes[0..4].try_into().expect("4 bytes")) as usize;
+        let cell_count = u32::from_le_bytes(bytes[4..8].try_into().expect("4 bytes")) as usize;
+        if key_len == 0 || cell_count == 0 || !cell_count.is_multiple_of(HASH_COUNT) {
+            return Err(Error::InvalidParameter(
+                "cell count must be a positive multiple of three and key length positive",
+            ));
+        }
+        let cell_len = key_len
+            .checked_add(12)
+            .ok_or(Error::InvalidParameter("key length overflows"))?;
+        let expected = cell_count
+            .checked_mul(cell_len)
+            .and_then(|n| n.checked_add(Self::HEADER_LEN))
+            .ok_or(Error::InvalidParameter("cell count overflows"))?;
+        if bytes.len() != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let mut table = Self::new(cell_count, key_len)?;
+        for (cell, chunk) in bytes[Self::HEADER_LEN..].chunks_exact(cell_len).enumerate() {
+            let (count, rest) = chunk.split_at(4);
+            let (key_sum, hash_sum) = rest.split_at(key_len);
+            table.counts[cell] = i32::from_le_bytes(count.try_into().expect("4 bytes"));
+            table.key_sums[cell * key_len..][..key_len].copy_from_slice(key_sum);
+            table.hash_sums[cell] = u64::from_le_bytes(hash_sum.try_into().expect("8 bytes"));
+        }
+        Ok(table)
+    }
+
+    /// Adds `key` to, or with `delta` -1 takes it out of, its cells.
+    fn toggle(&mut self, key: &[u8], delta: i32) -> Result<(), Error> {
+        if key.len() != self.key_len {
+            return Err(Error::InvalidLength {
+                expected: self.key_len,
+                found: key.len(),
+            });
+        }
+        let check = hashes(key).0;
+        for cell in self.cells(key) {
+            self.counts[cell] = self.counts[cell].wrapping_add(delta);
+            xor_into(
+                &mut self.key_sums[cell * self.key_len..][..self.key_len],
+                key,
+            );
+            self.hash_sums[cell] ^= check;
+        }
+        Ok(())
+    }
+
+    /// The cell of `key` in each third of the table.
+    fn cells(&self, key: &[u8]) -> [usize; HASH_COUNT] {
+        let third = self.cell_count() / HASH_COUNT;
+        let (_, positions) = hashes(key);
+        core::array::from_fn(|i| i * third + (positions[i] % third as u64) as usize)
+    }
+
+    /// The XOR of the keys in `cell`.
+    fn key_sum(&self, cell: usize) -> &[u8] {
+        &self.key_sums[cell * self.key_len..][..self.key_len]
+    }
+}
+
+/// The check hash of `key` and a position for each of its cells.
+fn hashes(key: &[u8]) -> (u64, [u64; HASH_COUNT]) {
+    let hash = Sha256::digest(&[HASH_INFO, key]);
+    let word = |i: usize| u64::from_le_bytes(hash[8 * i..][..8].try_into().expect("8 bytes"));
+    (word(0), core::array::from_fn(|i| word(i + 1)))
+}
+
+/// XORs `other` into `out`, byte by byte.
+fn xor_into(out: &mut [u8], other: &[u8]) {
+    out.iter_mut().zip(other).for_each(|(a, b)| *a ^= b);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    fn keys(count: usize, seed: u64) -> Vec<Vec<u8>> {
+        let mut fill = seeded(seed);
+        (0..count)
+            .map(|_| {
+                let mut key = vec![0u8; 16];
+                fill(&mut key);
+                key
+            })
+            .collect()
+    }
+
+    fn table(keys: &[Vec<u8>], cell_count: usize) -> Iblt {
+        let mut table = Iblt::new(cell_count, 16).unwrap();
+        keys.iter().for_each(|key| table.insert(key).unwrap());
+        table
+    }
+
+    #[test]
+    fn test_reconciles_symmetric_difference() {
+        let shared = keys(5000, 1);
+        let (only_a, only_b) = (keys(30, 2), keys(20, 3));
+        let a = table(&[shared.clone(), only_a.clone()].concat(), 100);
+        let b = table(&[shared, only_b.clone()].concat(), 100);
+
+        let mut difference = a.subtract(&b).unwrap().decode().unwrap();
+        difference.local.sort();
+        difference.remote.sort();
+        let (mut only_a, mut only_b) = (only_a, only_b);
+        only_a.sort();
+        only_b.sort();
+        assert_eq!(difference.local, only_a);
+        assert_eq!(difference.remote, only_b);
+
+        // Equal sets leave nothing, and a difference too large fails.
+        assert_eq!(
+            a.subtract(&a).unwrap().decode(),
+            Some(Difference::default())
+        );
+        let c = table(&keys(200, 4), 100);
+        assert_eq!(a.subtract(&c).unwrap().decode(), None);
+    }
+
+    #[test]
+    fn test_sizing_decodes_reliably() {
+        for difference in [0, 1, 5, 50, 500] {
+            for seed in 0..20 {
+                let mut table = Iblt::for_difference(difference, 16).unwrap();
+                for key in keys(difference, seed) {
+                    table.insert(&key).unwrap();
+                }
+                let decoded = table.decode().expect("sized for the difference");
+                assert_eq!(decoded.local.len(), difference);
+            }
+        }
+    }
+
+    #[test]
+    fn test_serialization_and_errors() {
+        let mut a = table(&keys(10, 5), 30);
+        a.remove(&keys(1, 6)[0]).unwrap();
+        assert_eq!(a.cell_count(), 30);
+        assert_eq!(a.key_len(), 16);
+        let bytes = a.to_bytes();
+        assert_eq!(bytes.len(), 8 + 30 * 28);
+        assert_eq!(Iblt::from_bytes(&bytes), Ok(a.clone()));
+        assert!(Iblt::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Iblt::from_bytes(&bytes[..7]).is_err());
+
+        assert_eq!(Iblt::new(31, 16).unwrap().cell_count(), 33);
+        assert!(Iblt::new(0, 16).is_err());
+        assert!(Iblt::new(30, 0).is_err());
+        assert_eq!(
+            a.insert(&[0; 15]),
+            Err(Error::InvalidLength {
+                expected: 16,
+                found: 15
+            })
+        );
+        assert!(a.subtract(&Iblt::new(33, 16).unwrap()).is_err());
+
+        // Counts at the ends of their range neither panic nor peel.
+        let mut bytes = Iblt::new(30, 16).unwrap().to_bytes();
+        bytes[8..12].copy_from_slice(&i32::MIN.to_le_bytes());
+        let extreme = Iblt::from_bytes(&bytes).unwrap();
+        assert_eq!(extreme.decode(), None);
+        assert!(extreme.subtract(&a).unwrap().decode().is_none());
+    }
+}
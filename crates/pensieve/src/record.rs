@@ -0,0 +1,270 @@
+//! Fingerprints of structured records, field by field.
+//!
+//! Matching whole records (customer entries, telemetry events) by
+//! collapsing one serialization of them is brittle: the serialization is a
+//! choice every project makes differently, and a length change in one field
+//! shifts every bit after it. A [`RecordFingerprint`] instead collapses each
+//! field's [`CanonicalBytes`] on its own and keeps the per-field digests, so
+//! two records can be compared by how many fields collapse alike, each
+//! field counting with its own weight.
+//!
+//! [`FuzzyFingerprint`] is the trait for types that produce one; with the
+//! `derive` feature, `#[derive(FuzzyFingerprint)]` implements it for a
+//! struct from its fields in declaration order. Field attributes tune each
+//! field:
+//!
+//! - `#[fuzzy(skip)]` leaves a field out, e.g. a database id;
+//! - `#[fuzzy(tolerance = 0.2)]` collapses it at its own tolerance instead
+//!   of the caller's, e.g. looser for free text;
+//! - `#[fuzzy(weight = 3)]` makes it count three times in
+//!   [`RecordFingerprint::score`].
+//!
+//! Tolerance and weight combine: `#[fuzzy(tolerance = 0.05, weight = 5)]`.
+
+use crate::TbfConfig;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(feature = "derive")]
+pub use pensieve_derive::FuzzyFingerprint;
+
+/// A fixed, documented byte representation of a value, so that records
+/// fingerprint the same way in every project.
+///
+/// Integers, floats (their IEEE 754 bits) and `char`s are big-endian and
+/// as wide as their type, with `usize` and `isize` always 8 bytes; `bool` is
+/// one byte, 0 or 1. Strings are their UTF-8 bytes and sequences the
+/// concatenation of their elements, so a `Vec<u8>` is its bytes. `None`
+/// is empty.
+pub trait CanonicalBytes {
+    /// Appends the representation of `self` to `out`.
+    fn canonical_bytes(&self, out: &mut Vec<u8>);
+}
+
+macro_rules! impl_canonical_bytes_be {
+    ($($t:ty => $as:ty),* $(,)?) => {$(
+        impl CanonicalBytes for $t {
+            fn canonical_bytes(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&(*self as $as).to_be_bytes());
+            }
+        }
+    )*};
+}
+
+impl_canonical_bytes_be!(
+    u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128, usize => u64,
+    i8 => i8, i16 => i16, i32 => i32, i64 => i64, i128 => i128, isize => i64,
+    char => u32,
+);
+
+impl CanonicalBytes for bool {
+    fn canonical_bytes(&self, out: &mut Vec<u8>) {
+        out.push(u8::from(*self));
+    }
+}
+
+impl CanonicalBytes for f32 {
+    fn canonical_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_bits().to_be_bytes());
+    }
+}
+
+impl CanonicalBytes for f64 {
+    fn canonical_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_bits().to_be_bytes());
+    }
+}
+
+impl CanonicalBytes for str {
+    fn canonical_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl CanonicalBytes for String {
+    fn canonical_bytes(&self, out: &mut Vec<u8>) {
+        self.as_str().canonical_bytes(out);
+    }
+}
+
+impl<T: CanonicalBytes> CanonicalBytes for [T] {
+    fn canonical_bytes(&self, out: &mut Vec<u8>) {
+        self.iter().for_each(|item| item.canonical_bytes(out));
+    }
+}
+
+impl<T: CanonicalBytes, const N: usize> CanonicalBytes for [T; N] {
+    fn canonical_bytes(&self, out: &mut Vec<u8>) {
+        self.as_slice().canonical_bytes(out);
+    }
+}
+
+impl<T: CanonicalBytes> CanonicalBytes for Vec<T> {
+    fn canonical_bytes(&self, out: &mut Vec<u8>) {
+        self.as_slice().canonical_bytes(out);
+    }
+}
+
+impl<T: CanonicalBytes> CanonicalBytes for Option<T> {
+    fn canonical_bytes(&self, out: &mut Vec<u8>) {
+        if let Some(value) = self {
+            value.canonical_bytes(out);
+        }
+    }
+}
+
+impl<T: CanonicalBytes + ?Sized> CanonicalBytes for &T {
+    fn canonical_bytes(&self, out: &mut Vec<u8>) {
+        (**self).canonical_bytes(out);
+    }
+}
+
+/// The collapsed fields of one record, in order, with their weights.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct RecordFingerprint {
+    fields: Vec<(Vec<u8>, u32)>, // (digest, weight)
+}
+
+impl RecordFingerprint {
+    /// A fingerprint of no fields.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the collapse of `field`'s [`CanonicalBytes`] under `config`,
+    /// with weight 1.
+    pub fn push<T: CanonicalBytes + ?Sized>(&mut self, field: &T, config: &TbfConfig) {
+        self.push_weighted(field, config, 1);
+    }
+
+    /// Appends the collapse of `field`'s [`CanonicalBytes`] under `config`,
+    /// counting `weight` times in [`RecordFingerprint::score`].
+    pub fn push_weighted<T: CanonicalBytes + ?Sized>(
+        &mut self,
+        field: &T,
+        config: &TbfConfig,
+        weight: u32,
+    ) {
+        let mut bytes = Vec::new();
+        field.canonical_bytes(&mut bytes);
+        self.fields.push((config.collapse(&bytes), weight));
+    }
+
+    /// The per-field digests, in the order they were pushed.
+    pub fn fields(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.fields.iter().map(|(digest, _)| digest.as_slice())
+    }
+
+    /// Number of fields at the same position that collapsed alike in both
+    /// fingerprints.
+    pub fn matching_fields(&self, other: &Self) -> usize {
+        self.matches(other).count()
+    }
+
+    /// The weighted fraction of fields that collapsed alike in both
+    /// fingerprints, from 0.0 to 1.0, using the weights of `self`. A
+    /// fingerprint without weight scores 1.0.
+    pub fn score(&self, other: &Self) -> f32 {
+        let total: u64 = self.fields.iter().map(|&(_, w)| u64::from(w)).sum();
+        if total == 0 {
+            return 1.0;
+        }
+        let matched: u64 = self.matches(other).map(|&(_, w)| u64::from(w)).sum();
+        (matched as f64 / total as f64) as f32
+    }
+
+    /// The fields of `self` that match `other` at the same position.
+    fn matches<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = &'a (Vec<u8>, u32)> {
+        self.fields
+            .iter()
+            .zip(&other.fields)
+            .filter(|((a, _), (b, _))| a == b)
+            .map(|(field, _)| field)
+    }
+
+    /// All fields as one byte string: each digest prefixed by its length as
+    /// a big-endian `u32`. Equal iff every field matches, so it serves as
+    /// the key for exact deduplication or joins.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (field, _) in &self.fields {
+            out.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            out.extend_from_slice(field);
+        }
+        out
+    }
+}
+
+/// Types that can be fingerprinted field by field.
+pub trait FuzzyFingerprint {
+    /// The fingerprint of `self` under `config`.
+    fn fingerprint(&self, config: &TbfConfig) -> RecordFingerprint;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn bytes(value: &(impl CanonicalBytes + ?Sized)) -> Vec<u8> {
+        let mut out = Vec::new();
+        value.canonical_bytes(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_canonical_bytes() {
+        assert_eq!(bytes(&0x0102u16), [1, 2]);
+        assert_eq!(bytes(&-2i32), [0xFF, 0xFF, 0xFF, 0xFE]);
+        assert_eq!(bytes(&1usize), [0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(bytes(&'é'), [0, 0, 0, 0xE9]);
+        assert_eq!(bytes(&true), [1]);
+        assert_eq!(bytes(&1.0f32), [0x3F, 0x80, 0, 0]);
+        assert_eq!(bytes("hé"), [b'h', 0xC3, 0xA9]);
+        assert_eq!(bytes(&vec![1u8, 2, 3]), [1, 2, 3]);
+        assert_eq!(bytes(&[1u16, 2]), [0, 1, 0, 2]);
+        assert_eq!(bytes(&Some(7u8)), [7]);
+        assert_eq!(bytes(&None::<u8>), []);
+    }
+
+    #[test]
+    fn test_record_fingerprint() {
+        let config = TbfConfig::new(0.125);
+        let record = |name: &str, balance: u64| {
+            let mut fingerprint = RecordFingerprint::new();
+            fingerprint.push(name, &config);
+            fingerprint.push(&balance, &config);
+            fingerprint
+        };
+        let (a, b) = (record("Ada Lovelace", 1815), record("Ada Lovelacf", 1815));
+        assert_eq!(a.fields().count(), 2);
+        assert_eq!(
+            a.fields().next(),
+            Some(config.collapse(b"Ada Lovelace").as_slice())
+        );
+        assert_eq!(a, b);
+        assert_eq!(a.to_bytes(), b.to_bytes());
+
+        let c = record("Grace Brewster Hopper", 1815);
+        assert_eq!(a.matching_fields(&c), 1);
+        assert_ne!(a.to_bytes(), c.to_bytes());
+        assert_eq!(a.to_bytes().len(), 4 + 12 + 4 + 8);
+        assert_eq!(a.score(&c), 0.5);
+    }
+
+    #[test]
+    fn test_weighted_score() {
+        let config = TbfConfig::new(0.125);
+        let record = |name: &str, id: u64| {
+            let mut fingerprint = RecordFingerprint::new();
+            fingerprint.push_weighted(name, &config.with_tolerance(0.25), 1);
+            fingerprint.push_weighted(&id, &config, 3);
+            fingerprint
+        };
+        let a = record("Ada Lovelace", 7);
+        assert_eq!(a.score(&record("Grace Brewster Hopper", 7)), 0.75);
+        assert_eq!(a.score(&record("Ada Lovelace", 8)), 0.25);
+        assert_eq!(a.score(&a), 1.0);
+        assert_eq!(RecordFingerprint::new().score(&a), 1.0);
+    }
+}
@@ -0,0 +1,207 @@
+//! Finding regions that collapse alike in two buffers.
+//!
+//! Forensic comparison of firmware dumps or binaries asks which parts of
+//! one input reappear, possibly with noise and at another offset, in the
+//! other. [`find_similar_regions`] cuts the first input into consecutive
+//! windows, collapses the windows of the second at every offset, and
+//! reports the runs of consecutive windows of the first that collapse like
+//! the windows of the second at one fixed shift.
+//!
+//! A window collapses to one of at most 256 values, so a single equal
+//! window means little. Runs must span at least four windows to be
+//! reported, and only start where the four windows' collapses occur at
+//! most 16 times in the second input: runs of padding, or of dense data,
+//! whose windows all collapse alike, are reported only as continuations of
+//! a run found elsewhere. Shifting a window by a few bytes may leave its
+//! collapse unchanged, so a region can also match at nearby shifts; only
+//! the longest of those runs is reported, and its shift is only as exact
+//! as the collapses can tell shifts apart.
+//!
+//! This branches on the data and is meant for public inputs.
+
+use crate::rolling::window_levels;
+use crate::{Error, TbfConfig};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::ops::Range;
+
+/// A byte range of the first input and the equally long range of the
+/// second that collapse alike.
+pub type RegionPair = (Range<usize>, Range<usize>);
+
+/// Consecutive equal windows needed for a region.
+const MIN_RUN: usize = 4;
+
+/// Most offsets of the second input a run may start at.
+const MAX_REPEATS: usize = 16;
+
+/// The regions of `a` and `b` that collapse alike under `tolerance`: pairs
+/// of equally long byte ranges, `a`'s made of whole `window`-byte windows,
+/// ordered by their start in `a`. See the [module documentation](self) for
+/// what is reported.
+///
+/// Fails with [`Error::InvalidParameter`] if `window` is zero.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::regions::find_similar_regions;
+///
+/// // Sparse xorshift noise.
+/// let noise = |mut x: u32, len: usize| -> Vec<u8> {
+///     let mut next = move || {
+///         x ^= x << 13;
+///         x ^= x >> 17;
+///         x ^= x << 5;
+///         x as u8 & 0x11
+///     };
+///     (0..len).map(|_| next()).collect()
+/// };
+/// // A 4 KiB function moved from offset 8192 to 1000, with a flipped bit.
+/// let function = noise(7, 4096);
+/// let mut old = noise(1, 16_384);
+/// old[8192..12_288].copy_from_slice(&function);
+/// let mut new = noise(2, 10_000);
+/// new[1000..5096].copy_from_slice(&function);
+/// new[2000] ^= 0x10;
+///
+/// let regions = find_similar_regions(&old, &new, 256, 0.125).unwrap();
+/// assert_eq!(regions, [(8192..12_288, 1000..5096)]);
+/// ```
+pub fn find_similar_regions(
+    a: &[u8],
+    b: &[u8],
+    window: usize,
+    tolerance: f32,
+) -> Result<Vec<RegionPair>, Error> {
+    let config = TbfConfig::new(tolerance);
+    let windows_a: Vec<u8> = window_levels(a, window, &config)?
+        .into_iter()
+        .step_by(window)
+        .collect();
+    let windows_b = window_levels(b, window, &config)?;
+
+    // Window `k` of `a` against the window of `b` at offset `k * window + shift`.
+    let equal = |k: usize, shift: isize| {
+        (k * window)
+            .checked_add_signed(shift)
+            .and_then(|offset| windows_b.get(offset))
+            .is_some_and(|&levels| levels == windows_a[k])
+    };
+    let key = |levels: &mut dyn Iterator<Item = u8>| {
+        levels.fold(0u32, |key, level| key << 8 | u32::from(level))
+    };
+    let mut anchors: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+    for offset in 0..windows_b.len().saturating_sub((MIN_RUN - 1) * window) {
+        let run = &mut (0..MIN_RUN).map(|i| windows_b[offset + i * window]);
+        anchors.entry(key(run)).or_default().push(offset);
+    }
+
+    let mut found: Vec<(Range<usize>, isize)> = Vec::new(); // Windows of `a` and shift.
+    let covered = |found: &[(Range<usize>, isize)], k: usize, shift: isize| {
+        found
+            .iter()
+            .any(|(windows, other)| windows.contains(&k) && shift.abs_diff(*other) < window)
+    };
+    for k in 0..windows_a.len().saturating_sub(MIN_RUN - 1) {
+        let run = &mut windows_a[k..k + MIN_RUN].iter().copied();
+        let Some(offsets) = anchors.get(&key(run)).filter(|o| o.len() <= MAX_REPEATS) else {
+            continue;
+        };
+        let mut runs: Vec<(Range<usize>, isize)> = offsets
+            .iter()
+            .map(|&offset| offset as isize - (k * window) as isize)
+            .filter(|&shift| !covered(&found, k, shift))
+            .map(|shift| {
+                let (mut start, mut end) = (k, k + MIN_RUN);
+                while start > 0 && equal(start - 1, shift) && !covered(&found, start - 1, shift) {
+                    start -= 1;
+                }
+                while end < windows_a.len() && equal(end, shift) {
+                    end += 1;
+                }
+                (start..end, shift)
+            })
+            .collect();
+        runs.sort_by_key(|(windows, shift)| (Reverse(windows.len()), shift.unsigned_abs()));
+        for (windows, shift) in runs {
+            if !covered(&found, k, shift) {
+                found.push((windows, shift));
+            }
+        }
+    }
+
+    let mut regions: Vec<RegionPair> = found
+        .into_iter()
+        .map(|(windows, shift)| {
+            let in_a = windows.start * window..windows.end * window;
+            let start = in_a
+                .start
+                .checked_add_signed(shift)
+                .expect("an offset of b");
+            let in_b = start..start + in_a.len();
+            (in_a, in_b)
+        })
+        .collect();
+    regions.sort_by_key(|(in_a, in_b)| (in_a.start, in_b.start));
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use alloc::vec;
+
+    fn sparse(len: usize, seed: u64) -> Vec<u8> {
+        let mut input = vec![0u8; len];
+        seeded(seed)(&mut input);
+        input.iter_mut().for_each(|b| *b &= 0x11);
+        input
+    }
+
+    #[test]
+    fn test_finds_moved_and_repeated_regions() {
+        let (x, y) = (sparse(2048, 1), sparse(1024, 2));
+        let mut a = sparse(8192, 3);
+        a[1024..3072].copy_from_slice(&x);
+        a[6144..7168].copy_from_slice(&y);
+        let mut b = sparse(9000, 4);
+        b[5000..7048].copy_from_slice(&x);
+        b[100..1124].copy_from_slice(&y);
+        b[7500..8524].copy_from_slice(&y);
+        let regions = find_similar_regions(&a, &b, 128, 0.125).unwrap();
+        assert_eq!(
+            regions,
+            [
+                (1024..3072, 5000..7048),
+                (6144..7168, 100..1124),
+                (6144..7168, 7500..8524)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_runs_and_repeats() {
+        let x = sparse(1024, 5);
+        // Too short for a run of four 128-byte windows.
+        let mut b = sparse(4096, 6);
+        b[1000..1000 + 3 * 128].copy_from_slice(&x[..3 * 128]);
+        assert!(find_similar_regions(&x, &b, 128, 0.125).unwrap().is_empty());
+
+        // Padding matches everywhere and anchors nothing, but extends a run.
+        let mut a = vec![0u8; 4096];
+        a[..1024].copy_from_slice(&x);
+        let mut b = vec![0u8; 8192];
+        b[2048..3072].copy_from_slice(&x);
+        let regions = find_similar_regions(&a, &b, 128, 0.125).unwrap();
+        assert_eq!(regions, [(0..4096, 2048..6144)]);
+
+        assert!(
+            find_similar_regions(&a, &[], 128, 0.125)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(find_similar_regions(&a, &b, 0, 0.125).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Eviction order and expiry of remembered ids, shared by
+//! [`Deduplicator`](crate::dedup::Deduplicator),
+//! [`MemoryStore`](crate::store::MemoryStore) and
+//! [`FuzzyCache`](crate::map::FuzzyCache).
+
+use crate::dedup::Eviction;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// Bookkeeping of when each id was inserted, used and last seen.
+///
+/// Time is whatever monotonic clock the caller passes to
+/// [`Retention::expire`]; it never runs backwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Retention {
+    eviction: Eviction,
+    ttl: Option<u64>,
+    usage: BTreeMap<u64, Usage>,        // Id -> its usage.
+    by_rank: BTreeMap<(u64, u64), u64>, // (rank, tick) -> id, first to evict first.
+    by_seen: BTreeMap<(u64, u64), u64>, // (time last seen, tick) -> id, first to expire first.
+    next_tick: u64,                     // Monotonic counter ordering insertions and uses.
+    now: u64,                           // Latest time passed to `expire`.
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Usage {
+    uses: u64,      // The insertion and every use since.
+    tick: u64,      // Tick of the insertion, or of the last use unless evicting the oldest.
+    seen: u64,      // Time of the insertion or last use.
+    seen_tick: u64, // Tick of the insertion or last use, ordering equal times.
+}
+
+impl Retention {
+    pub(crate) fn new(eviction: Eviction) -> Self {
+        Self {
+            eviction,
+            ttl: None,
+            usage: BTreeMap::new(),
+            by_rank: BTreeMap::new(),
+            by_seen: BTreeMap::new(),
+            next_tick: 0,
+            now: 0,
+        }
+    }
+
+    pub(crate) fn ttl(&self) -> Option<u64> {
+        self.ttl
+    }
+
+    pub(crate) fn set_ttl(&mut self, ttl: Option<u64>) {
+        self.ttl = ttl;
+    }
+
+    pub(crate) fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Starts tracking `id`, seen now.
+    pub(crate) fn insert(&mut self, id: u64) {
+        let tick = self.tick();
+        let usage = Usage {
+            uses: 1,
+            tick,
+            seen: self.now,
+            seen_tick: tick,
+        };
+        self.index(id, usage);
+    }
+
+    /// Records a use of `id`, which refreshes it.
+    pub(crate) fn touch(&mut self, id: u64) {
+        let Some(mut usage) = self.unindex(id) else {
+            return;
+        };
+        let tick = self.tick();
+        usage.uses = usage.uses.saturating_add(1);
+        if self.eviction != Eviction::Oldest {
+            usage.tick = tick;
+        }
+        usage.seen = self.now;
+        usage.seen_tick = tick;
+        self.index(id, usage);
+    }
+
+    /// Stops tracking `id`.
+    pub(crate) fn remove(&mut self, id: u64) {
+        self.unindex(id);
+    }
+
+    /// The id the eviction policy forgets first.
+    pub(crate) fn victim(&self) -> Option<u64> {
+        self.by_rank.first_key_value().map(|(_, &id)| id)
+    }
+
+    /// Advances the clock to `now` and stops tracking every id not seen
+    /// for the time to live; returns those ids, longest unseen first.
+    pub(crate) fn expire(&mut self, now: u64) -> Vec<u64> {
+        self.now = self.now.max(now);
+        let mut expired = Vec::new();
+        let Some(ttl) = self.ttl else {
+            return expired;
+        };
+        while let Some((&(seen, _), &id)) = self.by_seen.first_key_value() {
+            if seen.saturating_add(ttl) > self.now {
+                break;
+            }
+            self.unindex(id);
+            expired.push(id);
+        }
+        expired
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.usage.clear();
+        self.by_rank.clear();
+        self.by_seen.clear();
+    }
+
+    fn tick(&mut self) -> u64 {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        tick
+    }
+
+    fn rank(&self, usage: &Usage) -> (u64, u64) {
+        match self.eviction {
+            Eviction::LeastFrequentlyUsed => (usage.uses, usage.tick),
+            Eviction::Oldest | Eviction::LeastRecentlyUsed => (0, usage.tick),
+        }
+    }
+
+    fn index(&mut self, id: u64, usage: Usage) {
+        self.by_rank.insert(self.rank(&usage), id);
+        self.by_seen.insert((usage.seen, usage.seen_tick), id);
+        self.usage.insert(id, usage);
+    }
+
+    fn unindex(&mut self, id: u64) -> Option<Usage> {
+        let usage = self.usage.remove(&id)?;
+        self.by_rank.remove(&self.rank(&usage));
+        self.by_seen.remove(&(usage.seen, usage.seen_tick));
+        Some(usage)
+    }
+}
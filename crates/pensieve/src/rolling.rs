@@ -0,0 +1,186 @@
+//! Collapsing a sliding window over a byte stream.
+//!
+//! A [`RollingCollapser`] keeps the last `window` bytes of a stream and the
+//! popcount of every chunk of that window. When a byte enters (and the
+//! oldest leaves), each chunk boundary moves by eight bits, so each chunk
+//! loses the popcount of its first byte's worth of bits and gains that of
+//! the bits after its end. A window has at most 8 chunks, so an update is
+//! constant work regardless of the window size, and the window's chunk
+//! levels are available after every byte. That is what online motif
+//! detection in telemetry needs: a recurring pattern shows up as a
+//! recurring level pattern without re-collapsing every window.
+//!
+//! Like [`collapse_deterministic`](crate::collapse_deterministic), the
+//! updates branch on the data and are meant for public streams.
+
+use crate::{BitOrder, ChunkLayout, Error, FinalTransform, TbfConfig, ct_popcount_range};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// Collapses the most recent `window` bytes of a stream after every byte.
+#[derive(Debug, Clone)]
+pub struct RollingCollapser {
+    layout: ChunkLayout,
+    window: usize,
+    bytes: VecDeque<u8>, // The current window, oldest first.
+    counts: Vec<u32>,    // Popcount of every chunk, valid once the window is full.
+}
+
+impl RollingCollapser {
+    /// Creates a collapser over windows of `window` bytes; `tolerance` is
+    /// clamped like in [`collapse_ct`](crate::collapse_ct).
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `window` is zero.
+    pub fn new(window: usize, tolerance: f32) -> Result<Self, Error> {
+        Self::with_layout(window, ChunkLayout::new(window, tolerance))
+    }
+
+    /// [`RollingCollapser::new`] with the tolerance and [`Thresholding`]
+    /// of `config`; its bit order and transform are left to the caller.
+    ///
+    /// [`Thresholding`]: crate::Thresholding
+    pub(crate) fn for_config(window: usize, config: &TbfConfig) -> Result<Self, Error> {
+        Self::with_layout(window, ChunkLayout::for_config(window * 8, config))
+    }
+
+    fn with_layout(window: usize, layout: Option<ChunkLayout>) -> Result<Self, Error> {
+        let layout = layout.ok_or(Error::InvalidParameter("window must be at least one byte"))?;
+        Ok(Self {
+            layout,
+            window,
+            bytes: VecDeque::with_capacity(window + 1),
+            counts: Vec::new(),
+        })
+    }
+
+    /// The window length in bytes.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Whether `window` bytes have been pushed, i.e. results are available.
+    pub fn is_full(&self) -> bool {
+        self.bytes.len() == self.window
+    }
+
+    /// Slides the window by one byte.
+    pub fn push(&mut self, byte: u8) {
+        if !self.is_full() {
+            self.bytes.push_back(byte);
+            if self.is_full() {
+                let (front, back) = self.bytes.as_slices();
+                let window = [front, back].concat();
+                self.counts = self
+                    .layout
+                    .ranges()
+                    .map(|(start, end)| ct_popcount_range(&window, start, end))
+                    .collect();
+            }
+            return;
+        }
+
+        // Bits are numbered over the old window followed by the new byte;
+        // every chunk `[start, end)` becomes `[start + 8, end + 8)`.
+        self.bytes.push_back(byte);
+        for ((start, end), count) in self.layout.ranges().zip(self.counts.iter_mut()) {
+            *count -= popcount_bits(&self.bytes, start, end.min(start + 8));
+            *count += popcount_bits(&self.bytes, end.max(start + 8), end + 8);
+        }
+        self.bytes.pop_front();
+    }
+
+    /// Pushes every byte of `bytes`.
+    pub fn extend(&mut self, bytes: &[u8]) {
+        bytes.iter().for_each(|&b| self.push(b));
+    }
+
+    /// The chunk levels of the current window as a bitmask, chunk 0 in the
+    /// most significant of the low `chunk_count` bits; `None` until the
+    /// window is full. Two windows collapse identically iff their masks are
+    /// equal. Constant time.
+    pub fn levels(&self) -> Option<u8> {
+        self.is_full().then(|| {
+            self.layout
+                .levels(&self.counts)
+                .into_iter()
+                .fold(0u8, |mask, level| mask << 1 | level)
+        })
+    }
+
+    /// The collapsed output of the current window, identical to
+    /// [`collapse_ct`](crate::collapse_ct) of it; `None` until the window is
+    /// full. Takes time proportional to the window to write out.
+    pub fn digest(&self) -> Option<Vec<u8>> {
+        self.is_full().then(|| {
+            FinalTransform::default().apply(&self.layout.levels(&self.counts), self.window)
+        })
+    }
+}
+
+/// The chunk-level bitmask of the `window`-byte window at every offset of
+/// `input`, in order; fails like [`RollingCollapser::new`].
+pub(crate) fn window_levels(
+    input: &[u8],
+    window: usize,
+    config: &TbfConfig,
+) -> Result<Vec<u8>, Error> {
+    let mut rolling = RollingCollapser::for_config(window, config)?;
+    Ok(input
+        .iter()
+        .filter_map(|&byte| {
+            rolling.push(match config.bit_order() {
+                BitOrder::MsbFirst => byte,
+                BitOrder::LsbFirst => byte.reverse_bits(),
+            });
+            rolling.levels()
+        })
+        .collect())
+}
+
+/// Set bits among MSB-first bit positions `start..end` of `bytes`.
+fn popcount_bits(bytes: &VecDeque<u8>, start: usize, end: usize) -> u32 {
+    (start..end)
+        .map(|bit| u32::from(bytes[bit / 8] >> (7 - bit % 8) & 1))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collapse_ct;
+    use crate::entropy::tests::seeded;
+
+    #[test]
+    fn test_every_window_matches_full_collapse() {
+        let mut stream = alloc::vec![0u8; 300];
+        seeded(5)(&mut stream);
+        // Sparse bytes keep chunk popcounts near the thresholds.
+        stream.iter_mut().for_each(|b| *b &= 0x21);
+        for window in [1usize, 2, 7, 15, 16, 33, 100] {
+            let mut rolling = RollingCollapser::new(window, 0.125).unwrap();
+            for (i, &byte) in stream.iter().enumerate() {
+                rolling.push(byte);
+                if i + 1 < window {
+                    assert!(rolling.digest().is_none());
+                    continue;
+                }
+                let expected = collapse_ct(&stream[i + 1 - window..=i], 0.125);
+                assert_eq!(rolling.digest(), Some(expected), "window {window}, end {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_levels_detect_recurring_motif() {
+        let mut rolling = RollingCollapser::new(16, 0.2).unwrap();
+        assert!(RollingCollapser::new(0, 0.2).is_err());
+        let motif = [0xFFu8, 0xFF, 0, 0].repeat(4);
+        rolling.extend(&motif);
+        let first = rolling.levels().unwrap();
+        assert_eq!(first, 0b1010_1010);
+        rolling.extend(&[0u8; 16]);
+        assert_eq!(rolling.levels(), Some(0));
+        rolling.extend(&motif);
+        assert_eq!(rolling.levels(), Some(first));
+    }
+}
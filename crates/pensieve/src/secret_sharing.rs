@@ -0,0 +1,304 @@
+//! Threshold secret sharing whose shares survive bit errors.
+//!
+//! A secret is split with Shamir's scheme over GF(2^8) into `share_count`
+//! shares, any `threshold` of which reconstruct it. Each issued share is
+//! protected by a [`RepetitionCode`] sized for the configured bit-error rate
+//! plus a short checksum, so a share can be read back from damaged paper or
+//! steel and still contribute, while a share that is damaged beyond repair
+//! is detected and skipped instead of silently corrupting the secret.
+//!
+//! The tolerance is statistical: the code is sized so that a share whose bits
+//! flip independently at the configured rate decodes correctly except with
+//! probability around 2^-32. No code of useful rate can correct an
+//! arbitrary (adversarially placed) 25% of errors, so damage concentrated on
+//! the copies of a single bit may still make that share unusable; presenting
+//! more than `threshold` shares gives reconstruction room to skip it.
+//!
+//! Field arithmetic and decoding are branch-free in the share contents;
+//! whether a given share verified is treated as public.
+
+use crate::crypto::sha256::Sha256;
+use crate::ecc::RepetitionCode;
+use crate::entropy::EntropySource;
+use crate::error::Error;
+
+/// Domain separation label for share checksums.
+const CHECKSUM_LABEL: &[u8] = b"pensieve/secret-sharing/v1/checksum";
+/// Bytes of checksum appended to every share before encoding.
+const CHECKSUM_LEN: usize = 8;
+/// Bytes of header (x-coordinate and threshold) in front of every share.
+const HEADER_LEN: usize = 2;
+
+/// Parameters of a noisy-share split; both the dealer and the party
+/// reconstructing need the same value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoisyShareScheme {
+    threshold: u8,       // Shares needed to reconstruct.
+    share_count: u8,     // Shares issued by `split`.
+    bit_error_rate: f32, // Noise level each share must survive.
+}
+
+impl NoisyShareScheme {
+    /// Creates a `threshold`-of-`share_count` scheme whose shares tolerate
+    /// `bit_error_rate` (clamped to 5%–25%) independent bit flips.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless
+    /// `1 <= threshold <= share_count`.
+    pub fn new(threshold: u8, share_count: u8, bit_error_rate: f32) -> Result<Self, Error> {
+        if threshold == 0 {
+            return Err(Error::InvalidParameter("threshold must be at least 1"));
+        }
+        if threshold > share_count {
+            return Err(Error::InvalidParameter(
+                "threshold must not exceed the number of shares",
+            ));
+        }
+        Ok(Self {
+            threshold,
+            share_count,
+            bit_error_rate: bit_error_rate.clamp(0.05, 0.25),
+        })
+    }
+
+    /// Number of shares required to reconstruct.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Number of shares produced by [`NoisyShareScheme::split`].
+    pub fn share_count(&self) -> u8 {
+        self.share_count
+    }
+
+    /// The (clamped) bit-error rate shares are sized for.
+    pub fn bit_error_rate(&self) -> f32 {
+        self.bit_error_rate
+    }
+
+    /// The code protecting each share.
+    pub fn code(&self) -> RepetitionCode {
+        RepetitionCode::for_bit_error_rate(self.bit_error_rate)
+    }
+
+    /// Length in bytes of each issued share for a secret of `secret_len` bytes.
+    pub fn share_len(&self, secret_len: usize) -> usize {
+        (HEADER_LEN + secret_len + CHECKSUM_LEN) * self.code().repetitions()
+    }
+
+    /// Splits `secret` into [`share_count`](Self::share_count) encoded shares.
+    pub fn split(&self, secret: &[u8], rng: &mut impl EntropySource) -> Vec<Vec<u8>> {
+        // One random polynomial of degree threshold - 1 per secret byte;
+        // coefficient 0 is the secret byte itself.
+        let degree = usize::from(self.threshold) - 1;
+        let mut coefficients = vec![0u8; secret.len() * degree];
+        rng.fill_bytes(&mut coefficients);
+
+        let code = self.code();
+        (1..=self.share_count)
+            .map(|x| {
+                let mut payload = Vec::with_capacity(HEADER_LEN + secret.len() + CHECKSUM_LEN);
+                payload.extend_from_slice(&[x, self.threshold]);
+                for (i, &s) in secret.iter().enumerate() {
+                    let poly = &coefficients[i * degree..(i + 1) * degree];
+                    payload.push(gf256::eval(s, poly, x));
+                }
+                let checksum = checksum(&payload);
+                payload.extend_from_slice(&checksum);
+                code.encode(&payload)
+            })
+            .collect()
+    }
+
+    /// Reconstructs the secret from (possibly noisy) shares.
+    ///
+    /// Shares that fail to decode, fail their checksum, belong to a different
+    /// threshold, or repeat an x-coordinate already seen are skipped. Fails
+    /// with [`Error::NotEnoughShares`] if fewer than `threshold` usable
+    /// shares remain.
+    pub fn reconstruct(&self, shares: &[&[u8]]) -> Result<Vec<u8>, Error> {
+        let code = self.code();
+        let mut points: Vec<(u8, Vec<u8>)> = Vec::new();
+
+        for share in shares {
+            let Some(payload) = code.decode(share) else {
+                continue;
+            };
+            if payload.len() < HEADER_LEN + CHECKSUM_LEN {
+                continue;
+            }
+            let (body, tag) = payload.split_at(payload.len() - CHECKSUM_LEN);
+            if !crate::crypto::ct_eq(&checksum(body), tag) {
+                continue;
+            }
+            let (x, threshold) = (body[0], body[1]);
+            if x == 0 || threshold != self.threshold || points.iter().any(|(px, _)| *px == x) {
+                continue;
+            }
+            if points
+                .first()
+                .is_some_and(|(_, y)| y.len() != body.len() - HEADER_LEN)
+            {
+                continue;
+            }
+            points.push((x, body[HEADER_LEN..].to_vec()));
+            if points.len() == usize::from(self.threshold) {
+                break;
+            }
+        }
+
+        if points.len() < usize::from(self.threshold) {
+            return Err(Error::NotEnoughShares {
+                required: usize::from(self.threshold),
+                valid: points.len(),
+            });
+        }
+
+        // Lagrange interpolation at x = 0, independently for every byte.
+        let xs: Vec<u8> = points.iter().map(|(x, _)| *x).collect();
+        let secret_len = points[0].1.len();
+        let secret = (0..secret_len)
+            .map(|i| {
+                let ys: Vec<u8> = points.iter().map(|(_, y)| y[i]).collect();
+                gf256::interpolate_at_zero(&xs, &ys)
+            })
+            .collect();
+        Ok(secret)
+    }
+}
+
+/// Truncated hash over a share's header and body.
+fn checksum(body: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let digest = Sha256::digest(&[CHECKSUM_LABEL, body]);
+    digest[..CHECKSUM_LEN].try_into().expect("checksum length")
+}
+
+/// Arithmetic in GF(2^8) modulo the AES polynomial x^8 + x^4 + x^3 + x + 1.
+mod gf256 {
+    /// Carry-less multiplication with reduction, without branching on the operands.
+    pub(super) fn mul(mut a: u8, mut b: u8) -> u8 {
+        let mut product = 0u8;
+        for _ in 0..8 {
+            product ^= a & 0u8.wrapping_sub(b & 1); // Add `a` iff the low bit of `b` is set.
+            let carry = 0u8.wrapping_sub(a >> 7); // 0xFF iff the shift overflows.
+            a = (a << 1) ^ (0x1b & carry);
+            b >>= 1;
+        }
+        product
+    }
+
+    /// Multiplicative inverse (a^254); zero maps to zero.
+    pub(super) fn inv(a: u8) -> u8 {
+        let mut result = 1u8;
+        let mut base = a;
+        let mut exponent = 254u8;
+        while exponent != 0 {
+            if exponent & 1 == 1 {
+                result = mul(result, base);
+            }
+            base = mul(base, base);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    /// Evaluates `constant + poly[0] x + poly[1] x^2 + ...` at `x` (Horner).
+    pub(super) fn eval(constant: u8, poly: &[u8], x: u8) -> u8 {
+        let mut acc = 0u8;
+        for &c in poly.iter().rev() {
+            acc = mul(acc, x) ^ c;
+        }
+        mul(acc, x) ^ constant
+    }
+
+    /// Value at zero of the unique polynomial through `(xs[i], ys[i])`.
+    pub(super) fn interpolate_at_zero(xs: &[u8], ys: &[u8]) -> u8 {
+        let mut result = 0u8;
+        for (i, (&xi, &yi)) in xs.iter().zip(ys).enumerate() {
+            // Basis polynomial l_i(0) = prod_{j != i} x_j / (x_j - x_i); subtraction is XOR.
+            let mut basis = 1u8;
+            for (j, &xj) in xs.iter().enumerate() {
+                if i != j {
+                    basis = mul(basis, mul(xj, inv(xj ^ xi)));
+                }
+            }
+            result ^= mul(yi, basis);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+
+    /// Flips roughly `rate` of the bits of `share` using a deterministic LCG.
+    fn damage(share: &mut [u8], rate: f64, seed: u64) {
+        let mut state = seed;
+        for byte in share.iter_mut() {
+            for bit in 0..8 {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                if ((state >> 11) as f64 / (1u64 << 53) as f64) < rate {
+                    *byte ^= 1 << bit;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_and_reconstruct_clean() {
+        let scheme = NoisyShareScheme::new(3, 5, 0.05).unwrap();
+        let secret = b"correct horse battery staple";
+        let shares = scheme.split(secret, &mut seeded(1));
+        assert_eq!(shares.len(), 5);
+        assert_eq!(shares[0].len(), scheme.share_len(secret.len()));
+
+        let presented: Vec<&[u8]> = vec![&shares[4], &shares[1], &shares[2]];
+        assert_eq!(scheme.reconstruct(&presented).unwrap(), secret);
+    }
+
+    #[test]
+    fn test_reconstruct_from_noisy_shares() {
+        let scheme = NoisyShareScheme::new(2, 3, 0.125).unwrap();
+        let secret = [0xA5u8; 32];
+        let mut shares = scheme.split(&secret, &mut seeded(7));
+        damage(&mut shares[0], 0.125, 1);
+        damage(&mut shares[2], 0.125, 2);
+
+        let presented: Vec<&[u8]> = vec![&shares[0], &shares[2]];
+        assert_eq!(scheme.reconstruct(&presented).unwrap(), secret);
+    }
+
+    #[test]
+    fn test_destroyed_share_is_skipped() {
+        let scheme = NoisyShareScheme::new(2, 3, 0.05).unwrap();
+        let secret = b"seed";
+        let mut shares = scheme.split(secret, &mut seeded(3));
+        damage(&mut shares[0], 0.5, 9); // far beyond tolerance
+
+        let presented: Vec<&[u8]> = shares.iter().map(Vec::as_slice).collect();
+        assert_eq!(scheme.reconstruct(&presented).unwrap(), secret);
+        assert_eq!(
+            scheme.reconstruct(&presented[..2]),
+            Err(Error::NotEnoughShares {
+                required: 2,
+                valid: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(NoisyShareScheme::new(0, 3, 0.1).is_err());
+        assert!(NoisyShareScheme::new(4, 3, 0.1).is_err());
+    }
+
+    #[test]
+    fn test_gf256_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(gf256::mul(a, gf256::inv(a)), 1);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Set similarity over collapsed shingles, tolerating insertions and
+//! deletions.
+//!
+//! A collapse compares inputs position by position, so a single inserted
+//! byte shifts every later bit and defeats it. Shingling restores the
+//! alignment: every overlapping window of `width` bytes is collapsed on
+//! its own, and two inputs are compared by the Jaccard similarity of the
+//! sets of collapsed windows. An edit only changes the windows that
+//! overlap it; the rest of the set is unchanged.
+//!
+//! A collapsed window is determined by its chunk levels, so each one is
+//! stored as a chunk-level bitmask of at most 8 bits and a set holds at most
+//! 256 fingerprints. Windows are collapsed incrementally with a
+//! [`RollingCollapser`], in time linear in the input.
+//!
+//! This branches on the data and is meant for public inputs.
+
+use crate::rolling::RollingCollapser;
+use crate::{BitOrder, Error, TbfConfig};
+use alloc::collections::BTreeSet;
+
+/// The distinct collapsed windows of an input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShingleSet {
+    width: usize,
+    config: TbfConfig,
+    fingerprints: BTreeSet<u8>, // Chunk-level bitmasks of the windows.
+}
+
+impl ShingleSet {
+    /// Collapses every window of `width` bytes of `input` under `config`.
+    /// Inputs shorter than `width` have no windows. The output transform of
+    /// `config` does not affect which windows are equal and is ignored.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `width` is zero.
+    pub fn new(input: &[u8], width: usize, config: &TbfConfig) -> Result<Self, Error> {
+        let mut rolling = RollingCollapser::for_config(width, config)
+            .map_err(|_| Error::InvalidParameter("shingle width must be at least one byte"))?;
+        let mut fingerprints = BTreeSet::new();
+        for &byte in input {
+            rolling.push(match config.bit_order() {
+                BitOrder::MsbFirst => byte,
+                BitOrder::LsbFirst => byte.reverse_bits(),
+            });
+            fingerprints.extend(rolling.levels());
+        }
+        Ok(Self {
+            width,
+            config: *config,
+            fingerprints,
+        })
+    }
+
+    /// Number of distinct collapsed windows.
+    pub fn len(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    /// Whether the input was shorter than the window.
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty()
+    }
+
+    /// The Jaccard similarity `|A ∩ B| / |A ∪ B|` of the two sets, from 0.0
+    /// to 1.0; 1.0 if both are empty. Sets built with different widths or
+    /// configurations are never alike (0.0).
+    pub fn jaccard(&self, other: &Self) -> f32 {
+        if (self.width, self.config) != (other.width, other.config) {
+            return 0.0;
+        }
+        let union = self.fingerprints.union(&other.fingerprints).count();
+        if union == 0 {
+            return 1.0;
+        }
+        let intersection = self.fingerprints.intersection(&other.fingerprints).count();
+        intersection as f32 / union as f32
+    }
+}
+
+/// The Jaccard similarity of the collapsed `width`-byte shingles of `a`
+/// and `b`; see [`ShingleSet::jaccard`].
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::shingle::jaccard;
+///
+/// let original = b"the quick brown fox jumps over the lazy dog";
+/// let edited = b"the quick brown fox leaps over the lazy dog";
+/// let config = TbfConfig::new(0.125);
+/// let score = jaccard(original, edited, 8, &config).unwrap();
+/// assert!(score > 0.5);
+/// ```
+pub fn jaccard(a: &[u8], b: &[u8], width: usize, config: &TbfConfig) -> Result<f32, Error> {
+    Ok(ShingleSet::new(a, width, config)?.jaccard(&ShingleSet::new(b, width, config)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use alloc::vec::Vec;
+
+    fn sparse(len: usize, seed: u64) -> Vec<u8> {
+        let mut input = alloc::vec![0u8; len];
+        seeded(seed)(&mut input);
+        input.iter_mut().for_each(|b| *b &= 0x11);
+        input
+    }
+
+    #[test]
+    fn test_windows_match_full_collapse() {
+        let input = sparse(40, 1);
+        let config = TbfConfig::new(0.125);
+        let set = ShingleSet::new(&input, 16, &config).unwrap();
+        let direct: BTreeSet<Vec<u8>> = input.windows(16).map(|w| config.collapse(w)).collect();
+        assert_eq!(set.len(), direct.len());
+    }
+
+    #[test]
+    fn test_insertions_and_deletions_are_tolerated() {
+        let config = TbfConfig::new(0.125);
+        let original = sparse(60, 2);
+        let mut edited = original.clone();
+        edited.insert(30, 0x11);
+        edited.remove(10);
+        let unrelated = sparse(60, 3);
+
+        let near = jaccard(&original, &edited, 16, &config).unwrap();
+        let far = jaccard(&original, &unrelated, 16, &config).unwrap();
+        assert!(near > far, "near {near}, far {far}");
+        assert_eq!(jaccard(&original, &original, 16, &config), Ok(1.0));
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        let config = TbfConfig::default();
+        assert!(ShingleSet::new(b"abc", 0, &config).is_err());
+        let short = ShingleSet::new(b"abc", 4, &config).unwrap();
+        assert!(short.is_empty());
+        assert_eq!(short.jaccard(&short), 1.0);
+        let a = ShingleSet::new(b"abcdef", 4, &config).unwrap();
+        let b = ShingleSet::new(b"abcdef", 5, &config).unwrap();
+        assert_eq!(a.jaccard(&b), 0.0);
+    }
+}
@@ -0,0 +1,165 @@
+//! SimHash: a fixed-length bitstring summarizing a bag of features.
+//!
+//! Every feature is hashed to `bits` pseudo-random bits. For each output
+//! bit, features whose hash has a 1 there vote up by their weight and the
+//! others vote down; the bit is set when the up-votes win. Documents that
+//! share most of their features share most of their votes, so the Hamming
+//! distance between two SimHashes tracks how different the feature bags
+//! are. The result is an ordinary bitstring, ready for
+//! [`collapse_ct`](crate::collapse_ct) or the [`index`](crate::index)
+//! structures.
+//!
+//! Feature hashing is a fast non-cryptographic mixer, stable across
+//! platforms and releases. Features are public.
+
+use crate::Error;
+use crate::tokenize::Tokenizer;
+use alloc::vec::Vec;
+
+/// Accumulates weighted features into a SimHash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimHash {
+    votes: Vec<i64>, // Net weight of every output bit, MSB-first.
+}
+
+impl SimHash {
+    /// Starts a SimHash of `bits` bits.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless `bits` is a positive
+    /// multiple of 8.
+    pub fn new(bits: usize) -> Result<Self, Error> {
+        if bits == 0 || !bits.is_multiple_of(8) {
+            return Err(Error::InvalidParameter(
+                "SimHash length must be a positive multiple of 8 bits",
+            ));
+        }
+        Ok(Self {
+            votes: alloc::vec![0; bits],
+        })
+    }
+
+    /// Adds `feature` with weight 1.
+    pub fn add(&mut self, feature: &[u8]) {
+        self.add_weighted(feature, 1);
+    }
+
+    /// Adds `feature` with `weight`; a weight of 0 has no effect.
+    pub fn add_weighted(&mut self, feature: &[u8], weight: u32) {
+        self.vote(feature, i64::from(weight));
+    }
+
+    /// Takes back an earlier [`add_weighted`](Self::add_weighted) of
+    /// `feature` with `weight`, so a SimHash can roll over a sliding
+    /// window of features. Removing what was never added leaves votes no
+    /// set of features produces.
+    pub fn remove_weighted(&mut self, feature: &[u8], weight: u32) {
+        self.vote(feature, -i64::from(weight));
+    }
+
+    /// Adds every feature `tokenizer` finds in `input`, weighted by its
+    /// number of occurrences.
+    pub fn add_features(&mut self, tokenizer: &(impl Tokenizer + ?Sized), input: &[u8]) {
+        for (feature, weight) in tokenizer.features(input) {
+            self.add_weighted(feature, weight);
+        }
+    }
+
+    /// Takes back an earlier [`add_features`](Self::add_features) of
+    /// `input` with the same `tokenizer`.
+    pub fn remove_features(&mut self, tokenizer: &(impl Tokenizer + ?Sized), input: &[u8]) {
+        for (feature, weight) in tokenizer.features(input) {
+            self.remove_weighted(feature, weight);
+        }
+    }
+
+    /// The SimHash of the features added so far, `bits / 8` bytes. Ties
+    /// (including no features at all) give 0 bits.
+    pub fn finish(&self) -> Vec<u8> {
+        let mut out = alloc::vec![0u8; self.votes.len() / 8];
+        for (i, &vote) in self.votes.iter().enumerate() {
+            out[i / 8] |= u8::from(vote > 0) << (7 - i % 8);
+        }
+        out
+    }
+
+    /// Moves every bit's vote by `weight` towards the hash of `feature`.
+    fn vote(&mut self, feature: &[u8], weight: i64) {
+        let seed = feature_hash(feature);
+        for (word, votes) in self.votes.chunks_mut(64).enumerate() {
+            let hash = mix(seed ^ word as u64);
+            for (bit, vote) in votes.iter_mut().enumerate() {
+                if hash >> (63 - bit) & 1 == 1 {
+                    *vote += weight;
+                } else {
+                    *vote -= weight;
+                }
+            }
+        }
+    }
+}
+
+/// FNV-1a over the feature bytes, finished with [`mix`].
+pub(crate) fn feature_hash(feature: &[u8]) -> u64 {
+    let hash = feature.iter().fold(0xCBF2_9CE4_8422_2325u64, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01B3)
+    });
+    mix(hash)
+}
+
+/// SplitMix64 finalizer: a fast bijective mixer of 64-bit words.
+pub(crate) fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::hamming;
+    use alloc::format;
+
+    fn simhash(features: impl Iterator<Item = alloc::string::String>) -> Vec<u8> {
+        let mut hash = SimHash::new(128).unwrap();
+        features.for_each(|f| hash.add(f.as_bytes()));
+        hash.finish()
+    }
+
+    #[test]
+    fn test_distance_tracks_feature_overlap() {
+        let base = simhash((0..100).map(|i| format!("feature-{i}")));
+        let near = simhash((5..105).map(|i| format!("feature-{i}")));
+        let far = simhash((1000..1100).map(|i| format!("feature-{i}")));
+        assert!(hamming(&base, &near) < 24, "{}", hamming(&base, &near));
+        assert!(hamming(&base, &far) > 40, "{}", hamming(&base, &far));
+    }
+
+    #[test]
+    fn test_weights_and_parameters() {
+        let mut heavy = SimHash::new(64).unwrap();
+        heavy.add_weighted(b"dominant", 10);
+        (0..9).for_each(|i| heavy.add(format!("minor-{i}").as_bytes()));
+        let mut alone = SimHash::new(64).unwrap();
+        alone.add(b"dominant");
+        assert_eq!(heavy.finish(), alone.finish());
+
+        let mut order = SimHash::new(64).unwrap();
+        order.add(b"b");
+        order.add(b"a");
+        let mut reordered = SimHash::new(64).unwrap();
+        reordered.add(b"a");
+        reordered.add(b"b");
+        assert_eq!(order.finish(), reordered.finish());
+        order.add_weighted(b"c", 3);
+        order.remove_weighted(b"c", 3);
+        order.remove_weighted(b"a", 1);
+        let mut single = SimHash::new(64).unwrap();
+        single.add(b"b");
+        assert_eq!(order, single);
+
+        assert_eq!(SimHash::new(64).unwrap().finish(), [0; 8]);
+        assert!(SimHash::new(0).is_err());
+        assert!(SimHash::new(12).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+//! Graded comparisons of two raw inputs: a similarity score and the
+//! tolerance at which they start to match.
+
+use crate::{BitOrder, ChunkLayout, TbfConfig, Thresholding, ct_popcount_range_in};
+
+/// How alike `a` and `b` are under `config`, from 0.0 to 1.0.
+///
+/// Both inputs are collapsed and compared chunk by chunk:
+/// - a chunk on which both inputs collapse to the same level scores 1;
+/// - a chunk on which they disagree scores `1 / (2 * flips)`, where `flips`
+///   is the fewest bit flips that would have made them agree. A near miss
+///   right at the threshold still earns partial credit, a clear
+///   disagreement earns almost none. Under [`Thresholding::Parity`] one
+///   flip always suffices, and under [`Thresholding::Mean`], whose
+///   threshold moves with the whole input, one flip is assumed.
+///
+/// The score is the mean over all chunks, so it is exactly 1.0 iff the two
+/// inputs collapse to the same output. Inputs of different lengths are
+/// never alike (0.0); two empty inputs are identical (1.0).
+///
+/// Like [`collapse_deterministic`](crate::collapse_deterministic), this
+/// branches on the inputs and is meant for public data.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::{TbfConfig, similarity};
+///
+/// let enrolled = [0xFF, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// let mut noisy = enrolled;
+/// noisy[0] ^= 0b0000_0001;
+/// let config = TbfConfig::new(0.05);
+/// assert_eq!(similarity(&enrolled, &noisy, &config), 1.0);
+/// assert!(similarity(&enrolled, &[0; 16], &config) < 1.0);
+/// ```
+pub fn similarity(a: &[u8], b: &[u8], config: &TbfConfig) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let Some(layout) = ChunkLayout::for_config(a.len() * 8, config) else {
+        return 1.0; // Both empty.
+    };
+
+    let order = config.bit_order();
+    let (total_a, total_b) = (
+        u64::from(ct_popcount_range_in(a, 0, layout.total_bits, order)),
+        u64::from(ct_popcount_range_in(b, 0, layout.total_bits, order)),
+    );
+    let threshold = layout.threshold;
+    let mut chunks = 0u32;
+    let mut score = 0.0f32;
+    for range @ (start, end) in layout.ranges() {
+        let sum_a = ct_popcount_range_in(a, start, end, order);
+        let sum_b = ct_popcount_range_in(b, start, end, order);
+        chunks += 1;
+        if layout.level(range, sum_a, total_a) == layout.level(range, sum_b, total_b) {
+            score += 1.0;
+            continue;
+        }
+        let flips = match config.thresholding() {
+            Thresholding::OneSided | Thresholding::Symmetric => {
+                // Either the heavier chunk drops below the threshold or the lighter one reaches it.
+                let (heavy, light) = (sum_a.max(sum_b), sum_a.min(sum_b));
+                (heavy - threshold + 1).min(threshold - light)
+            }
+            Thresholding::Parity | Thresholding::Mean => 1,
+        };
+        score += 0.5 / flips as f32;
+    }
+    score / chunks as f32
+}
+
+/// Whether `a` and `b` collapse to the same output at `tolerance`.
+///
+/// This is exactly
+/// `collapse_deterministic(a, tolerance) == collapse_deterministic(b, tolerance)`
+/// (inputs of different lengths never match, two empty inputs always do),
+/// but compares the chunks directly, stops at the first one that differs
+/// and allocates nothing. For other bit orders, test
+/// `similarity(a, b, &config) == 1.0` instead.
+///
+/// Like [`similarity`], this branches on the inputs and is meant for public
+/// data.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::matches;
+///
+/// let enrolled = [0xFF, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// let mut noisy = enrolled;
+/// noisy[0] ^= 0b0000_0011;
+/// assert!(matches(&enrolled, &noisy, 0.125));
+/// assert!(!matches(&enrolled, &[0; 16], 0.125));
+/// ```
+pub fn matches(a: &[u8], b: &[u8], tolerance: f32) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let Some(layout) = ChunkLayout::new(a.len(), tolerance) else {
+        return true; // Both empty.
+    };
+    layout.ranges().all(|(start, end)| {
+        let sum_a = ct_popcount_range_in(a, start, end, BitOrder::MsbFirst);
+        let sum_b = ct_popcount_range_in(b, start, end, BitOrder::MsbFirst);
+        (sum_a >= layout.threshold) == (sum_b >= layout.threshold)
+    })
+}
+
+/// The smallest supported tolerance at which `a` and `b` collapse to the
+/// same output, or `None` if they collapse differently at every tolerance
+/// from [`TbfConfig::MIN_TOLERANCE`] to [`TbfConfig::MAX_TOLERANCE`] (or
+/// differ in length).
+///
+/// A chunk on which the inputs have `low` and `high` set bits collapses
+/// differently exactly while the threshold lies in `low + 1..=high`; the
+/// result is the smallest tolerance whose threshold avoids all of those
+/// ranges. Only [`Thresholding::OneSided`] thresholds depend on the
+/// tolerance, so that is the rule this searches. Bits are read MSB-first
+/// and, like [`similarity`], this branches on the inputs.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::{TbfConfig, match_level, similarity};
+///
+/// // 2 set bits against none in the first 16-bit chunk: thresholds 1 and 2
+/// // tell them apart, 3 (tolerances above 12.5%) no longer does.
+/// let enrolled = [0b11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// let level = match_level(&enrolled, &[0; 16]).unwrap();
+/// assert_eq!(level, 0.126);
+/// assert_eq!(similarity(&enrolled, &[0; 16], &TbfConfig::new(level)), 1.0);
+/// assert!(similarity(&enrolled, &[0; 16], &TbfConfig::new(0.125)) < 1.0);
+/// ```
+pub fn match_level(a: &[u8], b: &[u8]) -> Option<f32> {
+    if a.len() != b.len() {
+        return None;
+    }
+    let Some(strictest) = ChunkLayout::new(a.len(), TbfConfig::MIN_TOLERANCE) else {
+        return Some(TbfConfig::MIN_TOLERANCE); // Both empty.
+    };
+    let loosest = ChunkLayout::new(a.len(), TbfConfig::MAX_TOLERANCE).expect("non-empty");
+
+    // At most 8 chunks; `(low, high)` popcounts of each.
+    let mut sums = [(0u32, 0u32); 8];
+    for ((start, end), sum) in strictest.ranges().zip(sums.iter_mut()) {
+        let sum_a = ct_popcount_range_in(a, start, end, BitOrder::MsbFirst);
+        let sum_b = ct_popcount_range_in(b, start, end, BitOrder::MsbFirst);
+        *sum = (sum_a.min(sum_b), sum_a.max(sum_b));
+    }
+    // Raise the threshold past every range it falls into; each raise
+    // clears one chunk for good, so this settles within 8 rounds.
+    let mut threshold = strictest.threshold;
+    while let Some(&(_, high)) = sums
+        .iter()
+        .find(|&&(low, high)| low < threshold && threshold <= high)
+    {
+        threshold = high + 1;
+    }
+    (threshold <= loosest.threshold).then(|| tolerance_for(threshold, a.len()))
+}
+
+/// The smallest tolerance whose threshold for inputs of `len` bytes is at
+/// least `threshold`, which must be reachable.
+fn tolerance_for(threshold: u32, len: usize) -> f32 {
+    let chunk_size = ChunkLayout::new(len, TbfConfig::MIN_TOLERANCE)
+        .expect("non-empty")
+        .chunk_size as u64;
+    // ceil(p * chunk_size / 1000) >= threshold  <=>  p * chunk_size > 1000 * (threshold - 1).
+    let permille = (1000 * u64::from(threshold - 1) / chunk_size + 1)
+        .max(u64::from(TbfConfig::MIN_TOLERANCE_PERMILLE));
+    TbfConfig::from_permille(permille as u16).tolerance()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enrolled() -> [u8; 16] {
+        let mut input = [0u8; 16];
+        input[0] = 0b11111111;
+        input
+    }
+
+    #[test]
+    fn test_within_tolerance_is_identical() {
+        let config = TbfConfig::new(0.125);
+        let mut noisy = enrolled();
+        noisy[0] ^= 0b00000011;
+        noisy[9] ^= 0b00000001;
+        assert_eq!(similarity(&enrolled(), &enrolled(), &config), 1.0);
+        assert_eq!(similarity(&enrolled(), &noisy, &config), 1.0);
+    }
+
+    #[test]
+    fn test_near_miss_scores_higher_than_clear_miss() {
+        // 8 chunks of 16 bits, threshold 2: chunk 0 of `near` is one flip from
+        // collapsing to 1, chunk 0 of `far` two flips.
+        let config = TbfConfig::new(0.125);
+        let mut near = [0u8; 16];
+        near[0] = 0b00000001;
+        let far = [0u8; 16];
+
+        let near_score = similarity(&enrolled(), &near, &config);
+        let far_score = similarity(&enrolled(), &far, &config);
+        assert_eq!(near_score, 7.5 / 8.0);
+        assert_eq!(far_score, 7.25 / 8.0);
+        assert_eq!(similarity(&near, &enrolled(), &config), near_score);
+    }
+
+    #[test]
+    fn test_every_chunk_disagreeing() {
+        let config = TbfConfig::new(0.25);
+        let score = similarity(&[0xFF; 16], &[0x00; 16], &config);
+        // Threshold 4 on 16-bit chunks: the zero chunks need 4 flips, full ones 13.
+        assert_eq!(score, 0.125);
+        // Threshold 9: the zero chunks need 9 flips, full ones 8.
+        let symmetric = config.with_thresholding(Thresholding::Symmetric);
+        assert_eq!(similarity(&[0xFF; 16], &[0x00; 16], &symmetric), 0.0625);
+    }
+
+    #[test]
+    fn test_follows_bit_order() {
+        // 40 bits in two chunks of 20, threshold 1: bit 0 of byte 2 belongs
+        // to chunk 0 LSB-first and to chunk 1 MSB-first.
+        let config = TbfConfig::new(0.05);
+        let lsb = config.with_bit_order(crate::BitOrder::LsbFirst);
+        let (a, b) = ([0, 0, 0x01, 0, 0], [0x01, 0, 0, 0, 0]);
+        assert_eq!(similarity(&a, &b, &config), 0.5);
+        assert_eq!(similarity(&a, &b, &lsb), 1.0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_matches_is_digest_equality() {
+        let mut rng = crate::entropy::tests::seeded(5);
+        for len in [0usize, 1, 3, 16, 40, 300] {
+            for tolerance in [0.0, 0.05, 0.125, 0.2, 0.9] {
+                let mut a = alloc::vec![0u8; len];
+                rng(&mut a);
+                let mut b = a.clone();
+                for byte in b.iter_mut().step_by(5) {
+                    *byte ^= 0b0001_0000;
+                }
+                for other in [&a, &b] {
+                    assert_eq!(
+                        matches(&a, other, tolerance),
+                        crate::collapse_deterministic(&a, tolerance)
+                            == crate::collapse_deterministic(other, tolerance),
+                        "len {len}, tolerance {tolerance}"
+                    );
+                }
+            }
+        }
+        assert!(!matches(&[0], &[0, 0], 0.125));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_match_level_is_smallest_matching_tolerance() {
+        let mut rng = crate::entropy::tests::seeded(3);
+        for len in [1usize, 2, 7, 16, 40] {
+            for _ in 0..40 {
+                let mut a = alloc::vec![0u8; len];
+                let mut b = alloc::vec![0u8; len];
+                rng(&mut a);
+                rng(&mut b);
+                // Sparse inputs keep chunk popcounts near the thresholds.
+                a.iter_mut().for_each(|x| *x &= 0x25);
+                b.iter_mut().for_each(|x| *x &= 0x31);
+                let collapses_equal = |t| crate::collapse_ct(&a, t) == crate::collapse_ct(&b, t);
+                let level = match_level(&a, &b);
+                let mut first = None;
+                for permille in 50..=250 {
+                    let t = TbfConfig::from_permille(permille).tolerance();
+                    if collapses_equal(t) {
+                        first = Some(t);
+                        break;
+                    }
+                }
+                match level {
+                    Some(level) => {
+                        assert!(collapses_equal(level), "len {len}");
+                        assert_eq!(Some(level), first, "len {len}");
+                        if level > TbfConfig::MIN_TOLERANCE {
+                            let permille = TbfConfig::new(level).tolerance_permille();
+                            let stricter = TbfConfig::from_permille(permille - 1).tolerance();
+                            assert!(!collapses_equal(stricter));
+                        }
+                    }
+                    None => assert_eq!(first, None, "len {len}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_match_level_edge_cases() {
+        assert_eq!(match_level(&enrolled(), &enrolled()), Some(0.05));
+        assert_eq!(match_level(&[], &[]), Some(0.05));
+        assert_eq!(match_level(&[1], &[1, 2]), None);
+        // 16 vs 0 set bits in every chunk: no threshold up to 4 separates them.
+        assert_eq!(match_level(&[0xFF; 16], &[0; 16]), None);
+    }
+
+    #[test]
+    fn test_length_edge_cases() {
+        let config = TbfConfig::default();
+        assert_eq!(similarity(&[], &[], &config), 1.0);
+        assert_eq!(similarity(&[1, 2], &[1, 2, 3], &config), 0.0);
+    }
+}
@@ -0,0 +1,265 @@
+//! Pluggable storage for fingerprints.
+//!
+//! [`Deduplicator`](crate::dedup::Deduplicator) and the indexes in
+//! [`index`](crate::index) keep everything in memory. Their store-backed
+//! counterparts, [`StoreDeduplicator`](crate::dedup::StoreDeduplicator) and
+//! [`StoredIndex`](crate::index::StoredIndex), keep fingerprints in a
+//! [`FingerprintStore`] instead, so the same matching logic can run on top
+//! of any database.
+//!
+//! A store is a map from ids to fingerprint bytes with a secondary index:
+//! every entry is filed under one bucket key per band, and a band scan
+//! returns the ids filed under a bucket. What the bands and buckets mean is
+//! up to the caller; the store only keeps them. [`MemoryStore`] is the
+//! in-memory implementation, optionally bounded in size and age, and, with
+//! the `std` feature, [`LogStore`] persists to a single append-only file.
+
+#[cfg(feature = "std")]
+mod log;
+
+#[cfg(feature = "std")]
+pub use log::LogStore;
+
+use crate::Error;
+use crate::dedup::Eviction;
+use crate::retention::Retention;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+
+/// A map from ids to fingerprints, searchable by banded bucket keys.
+pub trait FingerprintStore {
+    /// Failures of the backing storage. Parameter errors of the callers
+    /// are converted into it.
+    type Error: From<Error>;
+
+    /// Stores `fingerprint` under `id`, filed under `buckets[band]` for
+    /// every band, replacing any previous entry with that id.
+    fn put(&mut self, id: u64, fingerprint: &[u8], buckets: &[u64]) -> Result<(), Self::Error>;
+
+    /// The fingerprint stored under `id`, if any.
+    fn get(&self, id: u64) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// The ids of the entries filed under `bucket` in `band`, in ascending
+    /// order.
+    fn scan_band(&self, band: usize, bucket: u64) -> Result<Vec<u64>, Self::Error>;
+
+    /// Removes the entry stored under `id`; returns whether there was one.
+    fn delete(&mut self, id: u64) -> Result<bool, Self::Error>;
+}
+
+/// A [`FingerprintStore`] held in memory.
+///
+/// A store created with [`MemoryStore::bounded`] holds at most a fixed
+/// number of entries and deletes one, picked by its [`Eviction`] policy,
+/// to make room for a new one; with [`MemoryStore::with_ttl`] it also
+/// deletes entries once they have not been written for that long. The
+/// store only sees writes, so replacing an entry counts as using it and
+/// reading does not. Time is any monotonic clock the caller passes to
+/// [`MemoryStore::expire`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryStore {
+    entries: BTreeMap<u64, (Vec<u8>, Vec<u64>)>, // Id -> fingerprint and its buckets.
+    bands: Vec<BTreeMap<u64, BTreeSet<u64>>>,    // Per band: bucket -> ids.
+    capacity: usize,                             // `usize::MAX` when unbounded.
+    retention: Retention,                        // Eviction and expiry order of the ids.
+    evicted: u64,                                // Entries deleted to stay within capacity.
+    expired: u64,                                // Entries deleted for their age.
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            bands: Vec::new(),
+            capacity: usize::MAX,
+            retention: Retention::new(Eviction::Oldest),
+            evicted: 0,
+            expired: 0,
+        }
+    }
+}
+
+impl MemoryStore {
+    /// Creates an empty, unbounded store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty store holding at most `capacity` entries.
+    pub fn bounded(capacity: usize, eviction: Eviction) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(Error::InvalidParameter(
+                "capacity must be at least one entry",
+            ));
+        }
+        Ok(Self {
+            capacity,
+            retention: Retention::new(eviction),
+            ..Self::default()
+        })
+    }
+
+    /// This store deleting every entry not written for `ttl` time units.
+    pub fn with_ttl(mut self, ttl: u64) -> Self {
+        if !self.is_limited() {
+            // Entries of an unlimited store are not tracked; start now.
+            self.entries
+                .keys()
+                .for_each(|&id| self.retention.insert(id));
+        }
+        self.retention.set_ttl(Some(ttl));
+        self
+    }
+
+    /// Advances the clock to `now` and deletes the entries that expired by
+    /// then; returns how many. Later writes happen at `now`.
+    pub fn expire(&mut self, now: u64) -> usize {
+        let expired = self.retention.expire(now);
+        for &id in &expired {
+            self.unfile(id);
+        }
+        self.expired += expired.len() as u64;
+        expired.len()
+    }
+
+    /// Number of entries deleted to stay within the capacity.
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// Number of entries deleted because they outlived the time to live.
+    pub fn expired(&self) -> u64 {
+        self.expired
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl FingerprintStore for MemoryStore {
+    type Error = Error;
+
+    fn put(&mut self, id: u64, fingerprint: &[u8], buckets: &[u64]) -> Result<(), Error> {
+        if self.unfile(id) {
+            self.retention.touch(id);
+        } else if self.is_limited() {
+            if self.entries.len() == self.capacity {
+                let victim = self.retention.victim().expect("capacity is non-zero");
+                self.delete(victim)?;
+                self.evicted += 1;
+            }
+            self.retention.insert(id);
+        }
+        if self.bands.len() < buckets.len() {
+            self.bands.resize_with(buckets.len(), BTreeMap::new);
+        }
+        for (band, &bucket) in buckets.iter().enumerate() {
+            self.bands[band].entry(bucket).or_default().insert(id);
+        }
+        self.entries
+            .insert(id, (fingerprint.to_vec(), buckets.to_vec()));
+        Ok(())
+    }
+
+    fn get(&self, id: u64) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self
+            .entries
+            .get(&id)
+            .map(|(fingerprint, _)| fingerprint.clone()))
+    }
+
+    fn scan_band(&self, band: usize, bucket: u64) -> Result<Vec<u64>, Error> {
+        Ok(self
+            .bands
+            .get(band)
+            .and_then(|buckets| buckets.get(&bucket))
+            .map_or_else(Vec::new, |ids| ids.iter().copied().collect()))
+    }
+
+    fn delete(&mut self, id: u64) -> Result<bool, Error> {
+        self.retention.remove(id);
+        Ok(self.unfile(id))
+    }
+}
+
+impl MemoryStore {
+    /// Whether entries can be evicted or expire, and so are tracked.
+    fn is_limited(&self) -> bool {
+        self.capacity != usize::MAX || self.retention.ttl().is_some()
+    }
+
+    /// Removes the entry stored under `id` and its buckets, but not its
+    /// retention; returns whether there was one.
+    fn unfile(&mut self, id: u64) -> bool {
+        let Some((_, buckets)) = self.entries.remove(&id) else {
+            return false;
+        };
+        for (band, bucket) in buckets.into_iter().enumerate() {
+            let ids = self.bands[band].get_mut(&bucket).expect("filed on put");
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.bands[band].remove(&bucket);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryStore::new();
+        store.put(1, b"one", &[10, 20]).unwrap();
+        store.put(2, b"two", &[10, 21]).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(1), Ok(Some(b"one".to_vec())));
+        assert_eq!(store.scan_band(0, 10), Ok(alloc::vec![1, 2]));
+        assert_eq!(store.scan_band(1, 21), Ok(alloc::vec![2]));
+        assert_eq!(store.scan_band(5, 10), Ok(Vec::new()));
+
+        // Replacing an entry refiles it.
+        store.put(1, b"uno", &[11]).unwrap();
+        assert_eq!(store.scan_band(0, 10), Ok(alloc::vec![2]));
+        assert_eq!(store.scan_band(1, 20), Ok(Vec::new()));
+        assert_eq!(store.get(1), Ok(Some(b"uno".to_vec())));
+
+        assert_eq!(store.delete(2), Ok(true));
+        assert_eq!(store.delete(2), Ok(false));
+        assert_eq!(store.get(2), Ok(None));
+        assert_eq!(store.scan_band(0, 10), Ok(Vec::new()));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_bounded_memory_store() {
+        assert!(MemoryStore::bounded(0, Eviction::Oldest).is_err());
+        let mut store = MemoryStore::bounded(2, Eviction::LeastRecentlyUsed)
+            .unwrap()
+            .with_ttl(10);
+        store.put(1, b"one", &[10]).unwrap();
+        store.put(2, b"two", &[10]).unwrap();
+        store.put(1, b"uno", &[10]).unwrap(); // Using 1 leaves 2 to evict.
+        store.put(3, b"three", &[10]).unwrap();
+        assert_eq!(store.scan_band(0, 10), Ok(alloc::vec![1, 3]));
+        assert_eq!(store.evicted(), 1);
+
+        assert_eq!(store.expire(5), 0);
+        store.put(3, b"tres", &[11]).unwrap();
+        assert_eq!(store.expire(10), 1);
+        assert_eq!((store.get(1), store.len()), (Ok(None), 1));
+        assert_eq!(store.scan_band(0, 10), Ok(Vec::new()));
+        assert_eq!(store.expire(15), 1);
+        assert!(store.is_empty());
+        assert_eq!(store.expired(), 2);
+    }
+}
@@ -0,0 +1,358 @@
+//! A persistent [`FingerprintStore`] in a single append-only file.
+
+use super::{FingerprintStore, MemoryStore};
+use crate::crypto::sha256::Sha256;
+use crate::error::Error;
+use alloc::vec::Vec;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// First bytes of every store file.
+const MAGIC: [u8; 4] = *b"PNSS";
+/// Newest file version this build writes and reads.
+const VERSION: u16 = 1;
+/// `magic || version (u16) || reserved (u16)`.
+const HEADER_LEN: usize = 8;
+/// Bytes of truncated SHA-256 after every record.
+const CHECKSUM_LEN: usize = 4;
+/// Record tags.
+const PUT: u8 = 1;
+const DELETE: u8 = 2;
+
+/// A [`FingerprintStore`] persisted to an append-only log file: a
+/// zero-configuration near-duplicate store for desktop apps and
+/// single-node services.
+///
+/// Every `put` and `delete` appends one checksummed record and is handed to
+/// the operating system before returning; [`LogStore::sync`] also waits for
+/// the disk. Reads are served from memory: the whole store, band index
+/// included, is rebuilt from the log on [`LogStore::open`], so it must fit
+/// in RAM. Replaced and deleted entries keep taking space in the file until
+/// [`LogStore::compact`] rewrites it.
+///
+/// A record cut short by a crash is discarded on the next open; any other
+/// damage fails the open with [`io::ErrorKind::InvalidData`].
+#[derive(Debug)]
+pub struct LogStore {
+    path: PathBuf,
+    file: File,          // Opened for appending.
+    memory: MemoryStore, // Everything the log describes.
+    stale: u64,          // Records no longer needed to describe `memory`.
+}
+
+impl LogStore {
+    /// Opens the store at `path`, creating an empty one if the file does not
+    /// exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        if bytes.is_empty() {
+            file.write_all(&header())?;
+        } else if bytes.len() < HEADER_LEN
+            || bytes[..4] != MAGIC
+            || !(1..=VERSION).contains(&u16::from_le_bytes([bytes[4], bytes[5]]))
+            || bytes[6..8] != [0, 0]
+        {
+            return Err(invalid("not a supported pensieve store file"));
+        }
+
+        let mut store = Self {
+            path,
+            file,
+            memory: MemoryStore::new(),
+            stale: 0,
+        };
+        let mut at = HEADER_LEN.min(bytes.len());
+        while at < bytes.len() {
+            if ![PUT, DELETE].contains(&bytes[at]) {
+                return Err(invalid("unknown store record"));
+            }
+            let Some(len) = record_len(&bytes[at..]) else {
+                // A torn final write: drop it so new records follow good ones.
+                store.file.set_len(at as u64)?;
+                store.file.seek(SeekFrom::End(0))?;
+                break;
+            };
+            let (record, checksum) = bytes[at..at + len].split_at(len - CHECKSUM_LEN);
+            if Sha256::digest(&[record])[..CHECKSUM_LEN] != *checksum {
+                return Err(invalid("store record checksum mismatch"));
+            }
+            store.replay(record);
+            at += len;
+        }
+        Ok(store)
+    }
+
+    /// The path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_empty()
+    }
+
+    /// Number of records in the file that [`LogStore::compact`] would drop.
+    pub fn stale_records(&self) -> u64 {
+        self.stale
+    }
+
+    /// Waits until every record written so far is on disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// Rewrites the file with one record per live entry, replacing it
+    /// atomically once the new file is on disk.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".compact");
+        let temporary = PathBuf::from(temporary);
+        let mut bytes = header().to_vec();
+        for (&id, (fingerprint, buckets)) in &self.memory.entries {
+            bytes.extend_from_slice(&put_record(id, fingerprint, buckets));
+        }
+        {
+            let mut file = File::create(&temporary)?;
+            file.write_all(&bytes)?;
+            file.sync_all()?;
+        }
+        fs::rename(&temporary, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.stale = 0;
+        Ok(())
+    }
+
+    /// Applies a checksummed `record` (without its checksum) to memory.
+    fn replay(&mut self, record: &[u8]) {
+        let id = u64::from_le_bytes(record[1..9].try_into().expect("8 bytes"));
+        let replaced = if record[0] == PUT {
+            let (fingerprint, buckets) = parse_put(&record[9..]);
+            let replaced = self.memory.entries.contains_key(&id);
+            self.memory
+                .put(id, fingerprint, &buckets)
+                .expect("in memory");
+            replaced
+        } else {
+            // The delete record itself is stale too, once applied.
+            self.stale += 1;
+            self.memory.delete(id).expect("in memory")
+        };
+        self.stale += u64::from(replaced);
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        self.file.write_all(record)?;
+        self.file.flush()
+    }
+}
+
+impl FingerprintStore for LogStore {
+    type Error = io::Error;
+
+    fn put(&mut self, id: u64, fingerprint: &[u8], buckets: &[u64]) -> io::Result<()> {
+        if u32::try_from(fingerprint.len()).is_err() || u32::try_from(buckets.len()).is_err() {
+            return Err(Error::InvalidParameter("store record is too large").into());
+        }
+        self.append(&put_record(id, fingerprint, buckets))?;
+        self.stale += u64::from(self.memory.entries.contains_key(&id));
+        Ok(self.memory.put(id, fingerprint, buckets)?)
+    }
+
+    fn get(&self, id: u64) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.memory.get(id)?)
+    }
+
+    fn scan_band(&self, band: usize, bucket: u64) -> io::Result<Vec<u64>> {
+        Ok(self.memory.scan_band(band, bucket)?)
+    }
+
+    fn delete(&mut self, id: u64) -> io::Result<bool> {
+        if !self.memory.entries.contains_key(&id) {
+            return Ok(false);
+        }
+        self.append(&checksummed(|out| {
+            out.push(DELETE);
+            out.extend_from_slice(&id.to_le_bytes());
+        }))?;
+        self.stale += 2;
+        Ok(self.memory.delete(id)?)
+    }
+}
+
+fn header() -> [u8; HEADER_LEN] {
+    let mut header = [0u8; HEADER_LEN];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4..6].copy_from_slice(&VERSION.to_le_bytes());
+    header
+}
+
+/// `1 || id (u64) || len (u32) || fingerprint || bands (u32) || buckets
+/// (u64 each) || checksum`, integers little-endian.
+fn put_record(id: u64, fingerprint: &[u8], buckets: &[u64]) -> Vec<u8> {
+    checksummed(|out| {
+        out.push(PUT);
+        out.extend_from_slice(&id.to_le_bytes());
+        out.extend_from_slice(&(fingerprint.len() as u32).to_le_bytes());
+        out.extend_from_slice(fingerprint);
+        out.extend_from_slice(&(buckets.len() as u32).to_le_bytes());
+        for bucket in buckets {
+            out.extend_from_slice(&bucket.to_le_bytes());
+        }
+    })
+}
+
+/// The record written by `write`, followed by its checksum.
+fn checksummed(write: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+    let mut out = Vec::new();
+    write(&mut out);
+    let checksum = Sha256::digest(&[&out]);
+    out.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+    out
+}
+
+/// Length of the record at the start of `bytes`, checksum included, or
+/// `None` if `bytes` ends before it does. The tag must be known.
+fn record_len(bytes: &[u8]) -> Option<usize> {
+    let u32_at = |at: usize| -> Option<usize> {
+        let field = bytes.get(at..at + 4)?;
+        Some(u32::from_le_bytes(field.try_into().expect("4 bytes")) as usize)
+    };
+    let body = match *bytes.first()? {
+        DELETE => 9,
+        PUT => {
+            let fingerprint = u32_at(9)?;
+            let bands = u32_at(13 + fingerprint)?;
+            17 + fingerprint + bands.checked_mul(8)?
+        }
+        _ => unreachable!("checked by the caller"),
+    };
+    let len = body.checked_add(CHECKSUM_LEN)?;
+    (bytes.len() >= len).then_some(len)
+}
+
+/// The fingerprint and buckets of a put record after its tag and id.
+fn parse_put(bytes: &[u8]) -> (&[u8], Vec<u64>) {
+    let len = u32::from_le_bytes(bytes[..4].try_into().expect("4 bytes")) as usize;
+    let fingerprint = &bytes[4..4 + len];
+    let buckets = bytes[8 + len..]
+        .chunks_exact(8)
+        .map(|b| u64::from_le_bytes(b.try_into().expect("8 bytes")))
+        .collect();
+    (fingerprint, buckets)
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, Error::InvalidParameter(reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use crate::index::{LshParams, StoredIndex};
+
+    /// A fresh path in the system temporary directory.
+    fn temporary(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(std::format!("pensieve-{}-{name}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_survives_reopening() {
+        let path = temporary("reopen");
+        let mut store = LogStore::open(&path).unwrap();
+        store.put(1, b"one", &[10, 20]).unwrap();
+        store.put(2, b"two", &[10]).unwrap();
+        store.put(1, b"uno", &[11]).unwrap();
+        assert!(store.delete(2).unwrap());
+        assert!(!store.delete(2).unwrap());
+        assert_eq!(store.stale_records(), 3);
+        drop(store);
+
+        let mut store = LogStore::open(&path).unwrap();
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.stale_records(), 3);
+        assert_eq!(store.get(1).unwrap(), Some(b"uno".to_vec()));
+        assert_eq!(store.scan_band(0, 11).unwrap(), [1]);
+        assert!(store.scan_band(0, 10).unwrap().is_empty());
+
+        let before = fs::metadata(&path).unwrap().len();
+        store.compact().unwrap();
+        assert!(fs::metadata(&path).unwrap().len() < before);
+        assert_eq!(store.stale_records(), 0);
+        store.put(3, b"three", &[12]).unwrap();
+        drop(store);
+        let store = LogStore::open(&path).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.scan_band(0, 12).unwrap(), [3]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_torn_tail_is_dropped_and_damage_rejected() {
+        let path = temporary("damage");
+        let mut store = LogStore::open(&path).unwrap();
+        store.put(1, b"one", &[10]).unwrap();
+        store.put(2, b"two", &[20]).unwrap();
+        drop(store);
+        let bytes = fs::read(&path).unwrap();
+
+        fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+        let mut store = LogStore::open(&path).unwrap();
+        assert_eq!((store.len(), store.get(2).unwrap()), (1, None));
+        store.put(3, b"three", &[30]).unwrap();
+        drop(store);
+        assert_eq!(LogStore::open(&path).unwrap().len(), 2);
+
+        let mut flipped = bytes.clone();
+        flipped[HEADER_LEN + 13] ^= 1;
+        fs::write(&path, &flipped).unwrap();
+        let error = LogStore::open(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut unknown = bytes.clone();
+        unknown[HEADER_LEN] = 9;
+        fs::write(&path, &unknown).unwrap();
+        assert!(LogStore::open(&path).is_err());
+
+        fs::write(&path, b"not a store").unwrap();
+        assert!(LogStore::open(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_backs_a_stored_index() {
+        let path = temporary("index");
+        let params = LshParams::new(4, 8, 0).unwrap();
+        let store = LogStore::open(&path).unwrap();
+        let mut index = StoredIndex::new(4, params, &mut seeded(1), store).unwrap();
+        index.insert(42, &[1, 2, 3, 4]).unwrap();
+        let positions = index.positions().to_vec();
+        drop(index);
+
+        let index =
+            StoredIndex::open(4, params, positions, LogStore::open(&path).unwrap()).unwrap();
+        assert_eq!(index.query_within(&[1, 2, 3, 5], 1).unwrap(), [(42, 1)]);
+        assert_eq!(
+            index.query_within(&[1, 2], 1).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+//! Collapsing inputs that arrive in pieces, in fixed-size state.
+//!
+//! A [`StreamingCollapse`] counts the set bits of every chunk as bytes
+//! arrive, so neither the whole input nor a heap is needed: a
+//! microcontroller can collapse a flash region or a sensor capture larger
+//! than its RAM. The chunk boundaries depend on the input length, which is
+//! therefore fixed up front by the [`FixedConfig`] the state is built from.
+//! Inputs of unknown length, read from any [`std::io::Read`], are collapsed
+//! by `collapse_reader` (needs `std`).
+//!
+//! ```rust
+//! use pensieve::TbfConfig;
+//! use pensieve::stream::StreamingCollapse;
+//!
+//! let config = TbfConfig::new(0.125);
+//! let input = [0xF7u8; 100];
+//! let mut state = StreamingCollapse::new(config.fixed(input.len()));
+//! for piece in input.chunks(7) {
+//!     state.update(piece).unwrap();
+//! }
+//! let mut digest = [0u8; 100];
+//! state.finish_into(&mut digest).unwrap();
+//! # #[cfg(feature = "alloc")]
+//! assert_eq!(digest[..], config.collapse(&input)[..]);
+//! ```
+
+use crate::{Error, FixedConfig, MAX_CHUNKS, ct_popcount_range_in};
+
+/// The chunk popcounts of an input read so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamingCollapse {
+    config: FixedConfig,
+    fed: usize,              // Bytes received so far.
+    sums: [u32; MAX_CHUNKS], // Set bits of every chunk so far.
+}
+
+impl StreamingCollapse {
+    /// Starts collapsing an input of the length and under the
+    /// configuration of `config`.
+    pub fn new(config: FixedConfig) -> Self {
+        Self {
+            config,
+            fed: 0,
+            sums: [0; MAX_CHUNKS],
+        }
+    }
+
+    /// The configuration the input is collapsed with.
+    pub fn config(&self) -> &FixedConfig {
+        &self.config
+    }
+
+    /// Bytes still to be received.
+    pub fn remaining(&self) -> usize {
+        self.config.input_len() - self.fed
+    }
+
+    /// Receives the next bytes of the input, in constant time in their
+    /// values.
+    ///
+    /// Fails with [`Error::InvalidLength`], receiving nothing, if `bytes`
+    /// is longer than [`remaining`](Self::remaining) bytes.
+    pub fn update(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if bytes.len() > self.remaining() {
+            return Err(Error::InvalidLength {
+                expected: self.remaining(),
+                found: bytes.len(),
+            });
+        }
+        let Some(layout) = self.config.layout() else {
+            return Ok(()); // Only an empty input has no layout.
+        };
+        let (from, to) = (self.fed * 8, (self.fed + bytes.len()) * 8);
+        let order = self.config.config().bit_order();
+        for (sum, (start, end)) in self.sums.iter_mut().zip(layout.ranges()) {
+            // Chunk boundaries are public; only the bits inside are secret.
+            let (start, end) = (start.max(from), end.min(to));
+            if start < end {
+                *sum += ct_popcount_range_in(bytes, start - from, end - from, order);
+            }
+        }
+        self.fed += bytes.len();
+        Ok(())
+    }
+
+    /// Receives the rest of the input from `reader`, calling
+    /// `progress(processed, total)` whenever the count passes another
+    /// multiple of `interval` bytes and once all are counted; `total` is
+    /// the input length. Only a small buffer is held, however long the input.
+    /// Returning `false` from `progress` cancels reading; what was received
+    /// until then stays received.
+    ///
+    /// Fails with [`std::io::ErrorKind::UnexpectedEof`] if `reader` ends
+    /// before the input does, with an error wrapping [`Error::Cancelled`]
+    /// when cancelled, and with [`std::io::ErrorKind::InvalidInput`] if
+    /// `interval` is zero.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::stream::StreamingCollapse;
+    ///
+    /// let config = TbfConfig::new(0.125);
+    /// let input = vec![0xF7u8; 100_000];
+    /// let mut state = StreamingCollapse::new(config.fixed(input.len()));
+    /// let mut last = (0, 0);
+    /// state
+    ///     .read_from(&input[..], 1 << 14, |done, total| {
+    ///         last = (done, total);
+    ///         true
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(last, (100_000, 100_000));
+    /// let mut digest = vec![0u8; input.len()];
+    /// state.finish_into(&mut digest).unwrap();
+    /// assert_eq!(digest, config.collapse(&input));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn read_from(
+        &mut self,
+        mut reader: impl std::io::Read,
+        interval: usize,
+        mut progress: impl FnMut(usize, usize) -> bool,
+    ) -> std::io::Result<()> {
+        if interval == 0 {
+            return Err(Error::InvalidParameter("progress interval must be non-zero").into());
+        }
+        let total = self.config.input_len();
+        let mut buffer = [0u8; 1 << 13];
+        let mut next_report = (self.fed / interval + 1)
+            .saturating_mul(interval)
+            .min(total);
+        while self.remaining() > 0 {
+            let want = buffer.len().min(self.remaining());
+            let read = match reader.read(&mut buffer[..want]) {
+                Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.update(&buffer[..read])?;
+            if self.fed >= next_report {
+                if !progress(self.fed, total) {
+                    return Err(Error::Cancelled.into());
+                }
+                next_report = (self.fed / interval + 1)
+                    .saturating_mul(interval)
+                    .min(total);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the digest into `out`, which must be as long as the input.
+    ///
+    /// Fails with [`Error::InvalidLength`] if bytes of the input are still
+    /// missing or `out` has the wrong length.
+    pub fn finish_into(&self, out: &mut [u8]) -> Result<(), Error> {
+        let len = self.config.input_len();
+        if self.fed != len || out.len() != len {
+            return Err(Error::InvalidLength {
+                expected: len,
+                found: if self.fed != len { self.fed } else { out.len() },
+            });
+        }
+        let Some(layout) = self.config.layout() else {
+            return Ok(());
+        };
+        let levels = layout.levels_array(&self.sums);
+        let transform = self.config.config().transform();
+        transform.apply_at(&levels[..layout.chunk_count()], len, 0, out);
+        Ok(())
+    }
+}
+
+/// Collapses everything `reader` yields until it ends, under `config`.
+///
+/// The chunk boundaries depend on the input length, which a reader does
+/// not announce, so the input is read into memory before it is counted;
+/// the digest is as long as the input anyway. When the length is known up
+/// front, [`StreamingCollapse::read_from`] counts the chunks as they
+/// arrive and holds only a small buffer.
+///
+/// Fails with whatever error `reader` returns other than
+/// [`std::io::ErrorKind::Interrupted`], on which reading resumes.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::stream::collapse_reader;
+///
+/// let config = TbfConfig::new(0.125);
+/// let input = vec![0xF7u8; 100_000];
+/// let digest = collapse_reader(&input[..], &config).unwrap();
+/// assert_eq!(digest, config.collapse(&input));
+/// ```
+#[cfg(feature = "std")]
+pub fn collapse_reader(
+    mut reader: impl std::io::Read,
+    config: &crate::TbfConfig,
+) -> std::io::Result<Vec<u8>> {
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input)?;
+    Ok(config.collapse(&input))
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::entropy::tests::seeded;
+    use crate::{BitOrder, FinalTransform, TbfConfig, Thresholding};
+
+    #[test]
+    fn test_any_split_gives_the_digest() {
+        let mut fill = seeded(404);
+        let configs = [
+            TbfConfig::default(),
+            TbfConfig::new(0.05)
+                .with_bit_order(BitOrder::LsbFirst)
+                .with_thresholding(Thresholding::Mean),
+            TbfConfig::new(0.2).with_transform(FinalTransform::Hash),
+        ];
+        for config in configs {
+            for len in [0, 1, 5, 16, 33, 130] {
+                let mut input = alloc::vec![0u8; len];
+                fill(&mut input);
+                for piece in [1, 3, 64] {
+                    let mut state = StreamingCollapse::new(config.fixed(len));
+                    for bytes in input.chunks(piece) {
+                        state.update(bytes).unwrap();
+                    }
+                    assert_eq!(state.remaining(), 0);
+                    let mut digest = alloc::vec![0u8; len];
+                    state.finish_into(&mut digest).unwrap();
+                    assert_eq!(digest, config.collapse(&input));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lengths_are_enforced() {
+        let mut state = StreamingCollapse::new(TbfConfig::default().fixed(4));
+        state.update(&[0xFF; 3]).unwrap();
+        let mut digest = [0u8; 4];
+        assert!(state.finish_into(&mut digest).is_err());
+        assert!(state.update(&[0xFF; 2]).is_err());
+        assert_eq!(state.remaining(), 1);
+        state.update(&[0xFF]).unwrap();
+        assert!(state.finish_into(&mut digest[..3]).is_err());
+        state.finish_into(&mut digest).unwrap();
+        assert_eq!(digest[..], TbfConfig::default().collapse(&[0xFF; 4])[..]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_progress_is_reported_up_to_the_total() {
+        let config = TbfConfig::new(0.1);
+        let mut input = alloc::vec![0u8; 50_000];
+        seeded(409)(&mut input);
+        let digest = config.collapse(&input);
+
+        let mut reports = Vec::new();
+        let collapsed = config
+            .collapse_with_progress(&input, 7_000, |done, total| {
+                reports.push((done, total));
+                true
+            })
+            .unwrap();
+        assert_eq!(collapsed, digest);
+        assert_eq!(reports.len(), 8);
+        assert_eq!(reports[0], (7_000, 50_000));
+        assert_eq!(reports.last(), Some(&(50_000, 50_000)));
+
+        let mut state = StreamingCollapse::new(config.fixed(input.len()));
+        state.update(&input[..123]).unwrap();
+        let mut reports = Vec::new();
+        state
+            .read_from(&input[123..], 10_000, |done, _| {
+                reports.push(done);
+                true
+            })
+            .unwrap();
+        // Reads are 8 KiB, so the reports trail the multiples of 10 000.
+        assert_eq!(reports, [16_507, 24_699, 32_891, 41_083, 50_000]);
+        let mut out = alloc::vec![0u8; input.len()];
+        state.finish_into(&mut out).unwrap();
+        assert_eq!(out, digest);
+
+        let mut state = StreamingCollapse::new(config.fixed(input.len()));
+        let error = state
+            .read_from(&input[1..], 10_000, |_, _| true)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(
+            config
+                .collapse_with_progress(&input, 0, |_, _| true)
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_cancelled_reads_keep_what_was_received() {
+        let config = TbfConfig::default().fixed(100_000);
+        let input = alloc::vec![0xA5u8; 100_000];
+        let mut state = StreamingCollapse::new(config);
+        let error = state
+            .read_from(&input[..], 20_000, |done, _| done < 40_000)
+            .unwrap_err();
+        let cancelled = error.get_ref().and_then(|e| e.downcast_ref::<Error>());
+        assert_eq!(cancelled, Some(&Error::Cancelled));
+        // Cancelled at the first report past 40 000 bytes, of 8 KiB reads.
+        assert_eq!(state.remaining(), 100_000 - 40_960);
+
+        state
+            .read_from(&input[40_960..], 20_000, |_, _| true)
+            .unwrap();
+        let mut digest = alloc::vec![0u8; input.len()];
+        state.finish_into(&mut digest).unwrap();
+        assert_eq!(digest, TbfConfig::default().collapse(&input));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_collapse_reader_reads_to_the_end() {
+        /// Interrupted before every read, then returns at most 1000 bytes.
+        struct Flaky<'a>(&'a [u8], bool);
+        impl std::io::Read for Flaky<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.1 = !self.1;
+                if self.1 {
+                    return Err(std::io::ErrorKind::Interrupted.into());
+                }
+                let n = buf.len().min(1000).min(self.0.len());
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+        let config = TbfConfig::new(0.2).with_bit_order(BitOrder::LsbFirst);
+        let mut input = alloc::vec![0u8; 12_345];
+        seeded(423)(&mut input);
+        let digest = collapse_reader(Flaky(&input, false), &config).unwrap();
+        assert_eq!(digest, config.collapse(&input));
+        assert!(collapse_reader(&[][..], &config).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,475 @@
+//! Conformance vectors for reimplementations of the collapse.
+//!
+//! [`generate`] returns input/tolerance/output triples covering the edge
+//! cases of the algorithm: empty input, the scaled chunk counts below
+//! 128 bits, short last chunks, popcounts exactly at and one
+//! below the threshold, and tolerances outside the clamped range. They are
+//! exchanged as JSON, with bytes as lowercase hex:
+//!
+//! ```json
+//! {
+//!   "algorithm_version": 5,
+//!   "vectors": [
+//!     {"name": "empty", "input": "", "tolerance": 0.05, "output": ""}
+//!   ]
+//! }
+//! ```
+//!
+//! A port (or a binding over the C ABI) passes when it maps every `input`
+//! and `tolerance` to the listed `output` of [`collapse_ct`], whose chunks
+//! are thresholded [one-sidedly](crate::Thresholding::OneSided). [`verify`] performs that check
+//! for this crate, e.g. against a file produced by an older release. Files
+//! of algorithm versions 1 to 3 are still read, but the one-byte vectors
+//! of versions 1 and 2 predate the chunking of version 3 and fail
+//! verification. The integer thresholds of version 4 and the symmetric
+//! default of version 5 leave every vector of earlier files unchanged.
+
+use crate::{ChunkLayout, Error, TbfConfig, collapse_ct};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// One input/parameter/output triple.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestVector {
+    /// Short description of the case, unique within a file.
+    pub name: String,
+    /// The bytes to collapse.
+    pub input: Vec<u8>,
+    /// The tolerance passed to the collapse, before clamping.
+    pub tolerance: f32,
+    /// The expected collapsed output.
+    pub output: Vec<u8>,
+}
+
+impl TestVector {
+    /// Computes the expected output of `input` under `tolerance`.
+    pub fn new(name: &str, input: Vec<u8>, tolerance: f32) -> Self {
+        Self {
+            name: String::from(name),
+            output: collapse_ct(&input, tolerance),
+            input,
+            tolerance,
+        }
+    }
+}
+
+/// The authoritative edge-case suite for the current algorithm version.
+pub fn generate() -> Vec<TestVector> {
+    let mut vectors = Vec::new();
+    // Clamped below, each supported tolerance, clamped above.
+    let tolerances = [0.0, 0.05, 0.125, 0.2, 0.25, 0.9];
+    // Empty input has no chunks; 1 byte folds into eight 1-bit chunks;
+    // 2 to 15 bytes scale the chunk count down, leaving a short last chunk
+    // at 7 and 15 bytes; from 16 bytes on there
+    // are 8 chunks. Past 85 bytes the position XOR wraps, past 256 it repeats.
+    for len in [0usize, 1, 2, 3, 7, 8, 15, 16, 17, 32, 85, 86, 300] {
+        for tolerance in tolerances {
+            for (pattern, input) in [
+                ("zeros", vec![0x00; len]),
+                ("ones", vec![0xFF; len]),
+                ("alternating", vec![0x55; len]),
+                ("pseudorandom", pseudorandom(len, 0x5EED)),
+            ] {
+                let name = format!("{pattern}-{len}-bytes-tolerance-{tolerance}");
+                vectors.push(TestVector::new(&name, input, tolerance));
+            }
+        }
+    }
+    // Every chunk holding exactly `threshold` and `threshold - 1` ones (or
+    // as many as fit into a short last chunk).
+    for len in [1usize, 2, 15, 16] {
+        for tolerance in tolerances {
+            let layout = ChunkLayout::new(len, tolerance).expect("non-empty");
+            for (offset, label) in [(0, "at"), (1, "below")] {
+                let mut input = vec![0u8; len];
+                for (start, end) in layout.ranges() {
+                    let ones = layout.threshold.saturating_sub(offset) as usize;
+                    for bit in start..(start + ones).min(end) {
+                        input[bit / 8] |= 0x80 >> (bit % 8);
+                    }
+                }
+                let name = format!("{label}-threshold-{len}-bytes-tolerance-{tolerance}");
+                vectors.push(TestVector::new(&name, input, tolerance));
+            }
+        }
+    }
+    // 15% of a 100-bit chunk is exactly 15 bits; `f32` arithmetic made the
+    // threshold 16 before version 4.
+    for (offset, label) in [(0, "at"), (1, "below")] {
+        let mut input = vec![0u8; 100];
+        let layout = ChunkLayout::new(100, 0.15).expect("non-empty");
+        for (start, _) in layout.ranges() {
+            for bit in start..start + (layout.threshold - offset) as usize {
+                input[bit / 8] |= 0x80 >> (bit % 8);
+            }
+        }
+        let name = format!("{label}-exact-threshold-100-bytes-tolerance-0.15");
+        vectors.push(TestVector::new(&name, input, 0.15));
+    }
+    vectors
+}
+
+/// Indices of the vectors whose output this build does not reproduce;
+/// empty when the build conforms.
+pub fn verify(vectors: &[TestVector]) -> Vec<usize> {
+    vectors
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| collapse_ct(&v.input, v.tolerance) != v.output)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Serializes vectors in the format described in the [module documentation](self).
+pub fn to_json(vectors: &[TestVector]) -> String {
+    let mut out = String::new();
+    out.push_str("{\n");
+    let _ = writeln!(
+        out,
+        "  \"algorithm_version\": {},",
+        TbfConfig::ALGORITHM_VERSION
+    );
+    out.push_str("  \"vectors\": [");
+    for (i, vector) in vectors.iter().enumerate() {
+        let separator = if i == 0 { "\n" } else { ",\n" };
+        let _ = write!(
+            out,
+            "{separator}    {{\"name\": \"{}\", \"input\": \"{}\", \"tolerance\": {}, \"output\": \"{}\"}}",
+            escape(&vector.name),
+            hex(&vector.input),
+            vector.tolerance,
+            hex(&vector.output)
+        );
+    }
+    out.push_str(if vectors.is_empty() {
+        "]\n}\n"
+    } else {
+        "\n  ]\n}\n"
+    });
+    out
+}
+
+/// Parses the output of [`to_json`] (or any JSON document of that shape).
+///
+/// Fails with [`Error::InvalidParameter`] for malformed JSON, missing
+/// fields, or an `algorithm_version` this build does not implement.
+pub fn from_json(json: &str) -> Result<Vec<TestVector>, Error> {
+    let mut parser = Parser { text: json, pos: 0 };
+    let document = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != json.len() {
+        return Err(Error::InvalidParameter("trailing characters after JSON"));
+    }
+
+    // Older outputs are unchanged in later versions, except as documented
+    // in the module documentation.
+    let version = document.field("algorithm_version")?.number()?;
+    if !version
+        .parse::<u8>()
+        .is_ok_and(|v| (1..=TbfConfig::ALGORITHM_VERSION).contains(&v))
+    {
+        return Err(Error::InvalidParameter("unsupported algorithm version"));
+    }
+    let Json::Array(items) = document.field("vectors")? else {
+        return Err(Error::InvalidParameter("\"vectors\" must be an array"));
+    };
+    items
+        .iter()
+        .map(|item| {
+            Ok(TestVector {
+                name: String::from(item.field("name")?.string()?),
+                input: unhex(item.field("input")?.string()?)?,
+                tolerance: item
+                    .field("tolerance")?
+                    .number()?
+                    .parse()
+                    .map_err(|_| Error::InvalidParameter("invalid tolerance"))?,
+                output: unhex(item.field("output")?.string()?)?,
+            })
+        })
+        .collect()
+}
+
+/// SplitMix64 bytes; only used to fill inputs, which the file records.
+fn pseudorandom(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            (z ^ (z >> 31)) as u8
+        })
+        .collect()
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{b:02x}");
+        out
+    })
+}
+
+pub(crate) fn unhex(text: &str) -> Result<Vec<u8>, Error> {
+    if !text.len().is_multiple_of(2) {
+        return Err(Error::InvalidParameter("odd-length hex string"));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or(Error::InvalidCharacter { position: i })
+        })
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    text.chars().fold(String::new(), |mut out, c| {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+        out
+    })
+}
+
+/// The JSON values the format uses; numbers keep their source text.
+#[derive(Debug)]
+enum Json<'a> {
+    Literal,
+    Number(&'a str),
+    String(String),
+    Array(Vec<Json<'a>>),
+    Object(Vec<(String, Json<'a>)>),
+}
+
+impl Json<'_> {
+    fn field(&self, name: &'static str) -> Result<&Self, Error> {
+        let Json::Object(fields) = self else {
+            return Err(Error::InvalidParameter("expected a JSON object"));
+        };
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .ok_or(Error::InvalidParameter("missing field in test vector file"))
+    }
+
+    fn string(&self) -> Result<&str, Error> {
+        match self {
+            Json::String(s) => Ok(s),
+            _ => Err(Error::InvalidParameter("expected a JSON string")),
+        }
+    }
+
+    fn number(&self) -> Result<&str, Error> {
+        match self {
+            Json::Number(n) => Ok(n),
+            _ => Err(Error::InvalidParameter("expected a JSON number")),
+        }
+    }
+}
+
+/// Recursive-descent JSON parser; numbers are only split off, not validated.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize, // Byte offset of the next unread character.
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self) -> Result<Json<'a>, Error> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        match rest.bytes().next() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => {
+                let len = rest
+                    .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                Ok(Json::Number(&rest[..len]))
+            }
+            _ => {
+                for literal in ["true", "false", "null"] {
+                    if rest.starts_with(literal) {
+                        self.pos += literal.len();
+                        return Ok(Json::Literal);
+                    }
+                }
+                Err(Error::InvalidCharacter { position: self.pos })
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json<'a>, Error> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        if !self.eat(b'}') {
+            loop {
+                self.skip_whitespace();
+                let key = self.string()?;
+                self.expect(b':')?;
+                fields.push((key, self.value()?));
+                if self.eat(b'}') {
+                    break;
+                }
+                self.expect(b',')?;
+            }
+        }
+        Ok(Json::Object(fields))
+    }
+
+    fn array(&mut self) -> Result<Json<'a>, Error> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if !self.eat(b']') {
+            loop {
+                items.push(self.value()?);
+                if self.eat(b']') {
+                    break;
+                }
+                self.expect(b',')?;
+            }
+        }
+        Ok(Json::Array(items))
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(out);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, e)| e) {
+                        Some('u') => {
+                            let code: String = chars.by_ref().take(4).map(|(_, h)| h).collect();
+                            u32::from_str_radix(&code, 16).ok().and_then(char::from_u32)
+                        }
+                        Some('n') => Some('\n'),
+                        Some('t') => Some('\t'),
+                        Some('r') => Some('\r'),
+                        Some('b') => Some('\u{8}'),
+                        Some('f') => Some('\u{c}'),
+                        Some(e @ ('"' | '\\' | '/')) => Some(e),
+                        _ => None,
+                    };
+                    out.push(escaped.ok_or(Error::InvalidCharacter {
+                        position: self.pos + offset,
+                    })?);
+                }
+                c => out.push(c),
+            }
+        }
+        Err(Error::InvalidParameter("unterminated JSON string"))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `byte` (after whitespace) if it is next.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.text.as_bytes().get(self.pos) == Some(&byte);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), Error> {
+        if self.eat(byte) {
+            Ok(())
+        } else {
+            Err(Error::InvalidCharacter { position: self.pos })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_vectors_cover_edge_cases_and_verify() {
+        let vectors = generate();
+        assert!(verify(&vectors).is_empty());
+        let names: Vec<&str> = vectors.iter().map(|v| v.name.as_str()).collect();
+        for name in names.iter() {
+            assert_eq!(names.iter().filter(|n| n == &name).count(), 1, "{name}");
+        }
+        // At the threshold every chunk collapses to 1, one below to 0.
+        let at = vectors
+            .iter()
+            .find(|v| v.name == "at-threshold-16-bytes-tolerance-0.125");
+        let below = vectors
+            .iter()
+            .find(|v| v.name == "below-threshold-16-bytes-tolerance-0.125");
+        assert_eq!(
+            at.unwrap().output,
+            collapse_ct(&[0xC0, 0x00].repeat(8), 0.125)
+        );
+        assert_eq!(
+            below.unwrap().output,
+            collapse_ct(&[0x80, 0x00].repeat(8), 0.125)
+        );
+        assert_ne!(at.unwrap().output, below.unwrap().output);
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let vectors = generate();
+        let json = to_json(&vectors);
+        assert!(json.starts_with("{\n  \"algorithm_version\": 5,\n"));
+        let parsed = from_json(&json).unwrap();
+        assert_eq!(parsed.len(), vectors.len());
+        for (parsed, vector) in parsed.iter().zip(&vectors) {
+            assert_eq!(parsed.name, vector.name);
+            assert_eq!(parsed.input, vector.input);
+            assert_eq!(parsed.tolerance.to_bits(), vector.tolerance.to_bits());
+            assert_eq!(parsed.output, vector.output);
+        }
+        assert_eq!(from_json(&to_json(&[])).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_verify_reports_mismatches_and_bad_files() {
+        let mut vectors = generate();
+        let last = vectors.len() - 1;
+        vectors[last].output[0] ^= 1;
+        assert_eq!(verify(&vectors), vec![last]);
+
+        let json = r#"{"algorithm_version": 1, "vectors": [
+            {"name": "a\"b", "input": "00ff", "tolerance": 0.125, "output": "xx"}
+        ]}"#;
+        assert_eq!(
+            from_json(json),
+            Err(Error::InvalidCharacter { position: 0 })
+        );
+        assert_eq!(
+            from_json(r#"{"algorithm_version": 6, "vectors": []}"#),
+            Err(Error::InvalidParameter("unsupported algorithm version"))
+        );
+        assert_eq!(
+            from_json(r#"{"algorithm_version": 1, "vectors": []}"#),
+            Ok(Vec::new())
+        );
+        assert!(from_json(r#"{"vectors": []"#).is_err());
+        assert!(from_json(r#"{"algorithm_version": 1, "vectors": []} x"#).is_err());
+    }
+}
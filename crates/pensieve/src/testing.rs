@@ -0,0 +1,267 @@
+//! Generators of valid parameters and inputs for property tests.
+//!
+//! Downstream crates that build on the collapse want to check their own
+//! invariants over every configuration the library accepts, not just the
+//! default one. [`Arbitrary`] draws configurations and digests from any
+//! [`EntropySource`], covering every tolerance, bit order, thresholding and
+//! transform, and the functions below draw inputs around them:
+//! - [`noisy_pair`] returns an input and a copy with exactly the given
+//!   number of bits flipped;
+//! - [`genuine_pair`] returns two inputs that collapse alike, the copy as
+//!   noisy as the chunk margins allow;
+//! - [`impostor_pair`] returns two independent inputs.
+//!
+//! The generators are plain functions of a source, so they plug into any
+//! property-testing framework: seed a [`noise::Seeded`] from the
+//! framework's value (or loop over seeds without one) and a failing case
+//! replays from its seed alone.
+//!
+//! # Examples
+//! ```rust
+//! use pensieve::TbfConfig;
+//! use pensieve::noise::Seeded;
+//! use pensieve::testing::{Arbitrary, genuine_pair};
+//!
+//! for seed in 0..100 {
+//!     let mut rng = Seeded::new(seed);
+//!     let config = TbfConfig::arbitrary(&mut rng);
+//!     let (a, b) = genuine_pair(&config, 24, &mut rng);
+//!     assert_eq!(config.collapse(&a), config.collapse(&b), "seed {seed}");
+//! }
+//! ```
+
+use crate::compact::CompactDigest;
+use crate::digest::{self, CollapsedDigest};
+use crate::entropy::EntropySource;
+use crate::envelope::DigestEnvelope;
+use crate::noise;
+use crate::{BitOrder, FinalTransform, TbfConfig, Thresholding};
+use alloc::vec::Vec;
+
+/// Longest input behind an arbitrary digest, in bytes.
+const MAX_INPUT_LEN: usize = 64;
+
+/// A type whose values can be drawn at random, each valid by the library's
+/// own rules.
+pub trait Arbitrary: Sized {
+    /// Draws a value from `rng`.
+    fn arbitrary(rng: &mut impl EntropySource) -> Self;
+}
+
+impl Arbitrary for BitOrder {
+    fn arbitrary(rng: &mut impl EntropySource) -> Self {
+        [Self::MsbFirst, Self::LsbFirst][below(rng, 2)]
+    }
+}
+
+impl Arbitrary for Thresholding {
+    fn arbitrary(rng: &mut impl EntropySource) -> Self {
+        [Self::Symmetric, Self::OneSided, Self::Parity, Self::Mean][below(rng, 4)]
+    }
+}
+
+impl Arbitrary for FinalTransform {
+    fn arbitrary(rng: &mut impl EntropySource) -> Self {
+        let mut key = [0u8; 32];
+        rng.fill_bytes(&mut key);
+        match below(rng, 4) {
+            0 => Self::Identity,
+            1 => Self::XorMask { base: key[0] },
+            2 => Self::KeyedPrf { key },
+            _ => Self::Hash,
+        }
+    }
+}
+
+/// Any supported tolerance, in whole per-mille, with any bit order,
+/// thresholding and transform.
+impl Arbitrary for TbfConfig {
+    fn arbitrary(rng: &mut impl EntropySource) -> Self {
+        let span = TbfConfig::MAX_TOLERANCE_PERMILLE - TbfConfig::MIN_TOLERANCE_PERMILLE;
+        let permille = TbfConfig::MIN_TOLERANCE_PERMILLE + below(rng, usize::from(span) + 1) as u16;
+        TbfConfig::from_permille(permille)
+            .with_bit_order(BitOrder::arbitrary(rng))
+            .with_thresholding(Thresholding::arbitrary(rng))
+            .with_transform(FinalTransform::arbitrary(rng))
+    }
+}
+
+/// The composition of one to three parts, each an arbitrary input of 1 to
+/// 64 bytes collapsed under one arbitrary configuration.
+impl Arbitrary for CollapsedDigest {
+    fn arbitrary(rng: &mut impl EntropySource) -> Self {
+        let config = TbfConfig::arbitrary(rng);
+        let parts: Vec<CollapsedDigest> = (0..1 + below(rng, 3))
+            .map(|_| CollapsedDigest::collapse(&arbitrary_input(rng), &config))
+            .collect();
+        digest::compose(&parts).expect("parts share a configuration")
+    }
+}
+
+/// An arbitrary input of 1 to 64 bytes under an arbitrary configuration.
+impl Arbitrary for CompactDigest {
+    fn arbitrary(rng: &mut impl EntropySource) -> Self {
+        let config = TbfConfig::arbitrary(rng);
+        CompactDigest::collapse(&arbitrary_input(rng), &config)
+    }
+}
+
+/// An arbitrary input of 1 to 64 bytes under an arbitrary configuration.
+impl Arbitrary for DigestEnvelope {
+    fn arbitrary(rng: &mut impl EntropySource) -> Self {
+        let config = TbfConfig::arbitrary(rng);
+        DigestEnvelope::seal(&arbitrary_input(rng), &config)
+    }
+}
+
+/// A uniform `len`-byte input and a copy of it with exactly `flips`
+/// distinct bits flipped, all of them if `flips` exceeds the input.
+pub fn noisy_pair(len: usize, flips: usize, rng: &mut impl EntropySource) -> (Vec<u8>, Vec<u8>) {
+    let input = input(len, rng);
+    let mut copy = input.clone();
+    noise::flip_random_bits(&mut copy, flips, rng);
+    (input, copy)
+}
+
+/// A uniform `len`-byte input and a noisy copy of it that `config`
+/// collapses alike.
+///
+/// Every chunk of the copy has a random number of bits flipped, up to one
+/// less than its margin in
+/// [`collapse_with_margins`](TbfConfig::collapse_with_margins), so no
+/// chunk changes level; under [`Thresholding::Parity`] that is none. Under
+/// [`Thresholding::Mean`] every flip moves the mean all chunks are compared
+/// against, so set and clear bits of a chunk flip in pairs instead, keeping
+/// every popcount.
+pub fn genuine_pair(
+    config: &TbfConfig,
+    len: usize,
+    rng: &mut impl EntropySource,
+) -> (Vec<u8>, Vec<u8>) {
+    let input = input(len, rng);
+    let mut copy = input.clone();
+    let Some(layout) = crate::ChunkLayout::for_config(len * 8, config) else {
+        return (input, copy);
+    };
+    // Positions are MSB-first, as in the layout.
+    let mask = |bit: usize| match config.bit_order() {
+        BitOrder::MsbFirst => 0x80 >> (bit % 8),
+        BitOrder::LsbFirst => 1 << (bit % 8),
+    };
+    let (_, margins) = config.collapse_with_margins(&input);
+    for ((start, end), margin) in layout.ranges().zip(margins) {
+        let mut positions: Vec<usize> = (start..end).collect();
+        for i in (1..positions.len()).rev() {
+            positions.swap(i, below(rng, i + 1)); // Fisher-Yates.
+        }
+        let flipped: Vec<usize> = if config.thresholding() == Thresholding::Mean {
+            let (set, clear): (Vec<usize>, Vec<usize>) = positions
+                .into_iter()
+                .partition(|&bit| input[bit / 8] & mask(bit) != 0);
+            let pairs = below(rng, set.len().min(clear.len()) + 1);
+            set[..pairs]
+                .iter()
+                .chain(&clear[..pairs])
+                .copied()
+                .collect()
+        } else {
+            let most = positions.len().min(margin.saturating_sub(1) as usize);
+            positions.truncate(below(rng, most + 1));
+            positions
+        };
+        for bit in flipped {
+            copy[bit / 8] ^= mask(bit);
+        }
+    }
+    (input, copy)
+}
+
+/// Two independent uniform `len`-byte inputs.
+///
+/// They collapse alike only by chance, with the false accept rate of the
+/// configuration; tests asserting that impostors are told apart must
+/// allow for it or check the digests first.
+pub fn impostor_pair(len: usize, rng: &mut impl EntropySource) -> (Vec<u8>, Vec<u8>) {
+    (input(len, rng), input(len, rng))
+}
+
+/// `len` uniform bytes.
+fn input(len: usize, rng: &mut impl EntropySource) -> Vec<u8> {
+    let mut input = alloc::vec![0u8; len];
+    rng.fill_bytes(&mut input);
+    input
+}
+
+/// A uniform input of 1 to [`MAX_INPUT_LEN`] bytes.
+fn arbitrary_input(rng: &mut impl EntropySource) -> Vec<u8> {
+    let len = 1 + below(rng, MAX_INPUT_LEN);
+    input(len, rng)
+}
+
+/// A uniform draw from `0..n`, up to a negligible modulo bias; `n` must not
+/// be zero.
+fn below(rng: &mut impl EntropySource, n: usize) -> usize {
+    let mut bytes = [0u8; 8];
+    rng.fill_bytes(&mut bytes);
+    (u64::from_le_bytes(bytes) % n as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::Seeded;
+
+    #[test]
+    fn test_generated_values_are_valid_and_cover_the_parameters() {
+        let mut rng = Seeded::new(429);
+        let mut thresholdings = Vec::new();
+        let mut transforms = Vec::new();
+        for _ in 0..200 {
+            let config = TbfConfig::arbitrary(&mut rng);
+            let permille = config.tolerance_permille();
+            assert!(
+                (TbfConfig::MIN_TOLERANCE_PERMILLE..=TbfConfig::MAX_TOLERANCE_PERMILLE)
+                    .contains(&permille)
+            );
+            if !thresholdings.contains(&config.thresholding()) {
+                thresholdings.push(config.thresholding());
+            }
+            if !transforms.contains(&config.transform().id()) {
+                transforms.push(config.transform().id());
+            }
+
+            let compact = CompactDigest::arbitrary(&mut rng);
+            assert_eq!(
+                CompactDigest::from_digest(&compact.expand(), compact.config()),
+                Ok(compact)
+            );
+            let envelope = DigestEnvelope::arbitrary(&mut rng);
+            assert_eq!(
+                DigestEnvelope::decode_expecting(&envelope.encode(), envelope.config()),
+                Ok(envelope)
+            );
+            assert!(!CollapsedDigest::arbitrary(&mut rng).as_bytes().is_empty());
+        }
+        assert_eq!((thresholdings.len(), transforms.len()), (4, 4));
+    }
+
+    #[test]
+    fn test_pairs_are_as_noisy_as_promised() {
+        let mut rng = Seeded::new(7);
+        let mut noisy = 0;
+        for _ in 0..300 {
+            let config = TbfConfig::arbitrary(&mut rng);
+            let len = below(&mut rng, 40);
+            let (a, b) = genuine_pair(&config, len, &mut rng);
+            assert_eq!(config.collapse(&a), config.collapse(&b), "{config:?} {len}");
+            noisy += usize::from(a != b);
+
+            let flips = below(&mut rng, 20);
+            let (a, b) = noisy_pair(len, flips, &mut rng);
+            assert_eq!(crate::distance::hamming(&a, &b), flips.min(len * 8) as u32);
+        }
+        assert!(noisy > 100, "{noisy}");
+        let (a, b) = impostor_pair(32, &mut rng);
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,157 @@
+//! Near-duplicate detection for text.
+//!
+//! Two copies of a document rarely differ in their bits the way a noisy
+//! sensor reading does: they differ in case, punctuation, whitespace and a
+//! few words. This front-end removes the first three with [`normalize`],
+//! turns the rest into overlapping word or character [`Shingles`], and
+//! summarizes them with a [`SimHash`]. [`fingerprint`] then collapses the
+//! SimHash, so documents that share most of their shingles get the same
+//! fingerprint.
+
+use crate::simhash::SimHash;
+use crate::{Error, TbfConfig};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Length of the SimHash [`fingerprint`] collapses, in bits.
+pub const FINGERPRINT_BITS: usize = 128;
+
+/// How normalized text is cut into overlapping features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shingles {
+    /// Runs of this many consecutive words, joined by single spaces.
+    Words(usize),
+    /// Runs of this many consecutive characters, spaces included.
+    Chars(usize),
+}
+
+/// Lowercases `text`, drops everything that is neither alphanumeric nor
+/// whitespace, and joins the remaining words with single spaces.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::text::normalize;
+///
+/// assert_eq!(normalize("  Hello,\tWorld! It's  me. "), "hello world its me");
+/// ```
+pub fn normalize(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for word in text.split_whitespace() {
+        let start = out.len();
+        if start > 0 {
+            out.push(' ');
+        }
+        out.extend(
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase),
+        );
+        if out.len() == start + usize::from(start > 0) {
+            out.truncate(start); // The word was all punctuation.
+        }
+    }
+    out
+}
+
+/// The overlapping shingles of already `normalized` text, in order.
+///
+/// Text with fewer words or characters than a shingle holds yields itself
+/// as the only shingle; empty text yields none. Fails with
+/// [`Error::InvalidParameter`] for shingles of length 0.
+pub fn shingles(normalized: &str, kind: Shingles) -> Result<Vec<String>, Error> {
+    if matches!(kind, Shingles::Words(0) | Shingles::Chars(0)) {
+        return Err(Error::InvalidParameter(
+            "shingles must hold at least one unit",
+        ));
+    }
+    if normalized.is_empty() {
+        return Ok(Vec::new());
+    }
+    let shingles = match kind {
+        Shingles::Words(n) => {
+            let words: Vec<&str> = normalized.split(' ').collect();
+            words
+                .windows(n.min(words.len()))
+                .map(|w| w.join(" "))
+                .collect()
+        }
+        Shingles::Chars(n) => {
+            let chars: Vec<char> = normalized.chars().collect();
+            chars
+                .windows(n.min(chars.len()))
+                .map(|w| w.iter().collect())
+                .collect()
+        }
+    };
+    Ok(shingles)
+}
+
+/// The `bits`-bit [`SimHash`] of the shingles of `text`, normalized first.
+pub fn simhash(text: &str, kind: Shingles, bits: usize) -> Result<Vec<u8>, Error> {
+    let mut hash = SimHash::new(bits)?;
+    for shingle in shingles(&normalize(text), kind)? {
+        hash.add(shingle.as_bytes());
+    }
+    Ok(hash.finish())
+}
+
+/// The collapse under `config` of the [`FINGERPRINT_BITS`]-bit
+/// [`simhash`] of `text`: equal for near-duplicate documents.
+///
+/// # Examples
+/// ```rust
+/// use pensieve::TbfConfig;
+/// use pensieve::text::{Shingles, fingerprint};
+///
+/// let config = TbfConfig::new(0.25);
+/// let original = "The quick brown fox jumps over the lazy dog.";
+/// let copy = "the QUICK brown fox -- jumps over the lazy dog";
+/// assert_eq!(
+///     fingerprint(original, Shingles::Chars(4), &config),
+///     fingerprint(copy, Shingles::Chars(4), &config)
+/// );
+/// ```
+pub fn fingerprint(text: &str, kind: Shingles, config: &TbfConfig) -> Result<Vec<u8>, Error> {
+    Ok(config.collapse(&simhash(text, kind, FINGERPRINT_BITS)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::hamming;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("ÀB-c  d\n\n— e"), "àbc d e");
+        assert_eq!(normalize("... !!"), "");
+        assert_eq!(normalize(""), "");
+    }
+
+    #[test]
+    fn test_shingles() {
+        assert_eq!(
+            shingles("a b c d", Shingles::Words(2)).unwrap(),
+            ["a b", "b c", "c d"]
+        );
+        assert_eq!(shingles("a b", Shingles::Words(3)).unwrap(), ["a b"]);
+        assert_eq!(shingles("añb", Shingles::Chars(2)).unwrap(), ["añ", "ñb"]);
+        assert!(shingles("", Shingles::Chars(2)).unwrap().is_empty());
+        assert!(shingles("a", Shingles::Words(0)).is_err());
+    }
+
+    #[test]
+    fn test_near_duplicates_are_close() {
+        let text = "Pensieve collapses noisy inputs into stable fingerprints, \
+                    so that two readings of the same source compare equal \
+                    while readings of different sources do not.";
+        let edited = text.replace("stable", "robust");
+        let other = "An entirely different paragraph about the weather, which \
+                     was cold and rainy all week long in the northern hills.";
+        for kind in [Shingles::Words(2), Shingles::Chars(5)] {
+            let base = simhash(text, kind, 128).unwrap();
+            let near = hamming(&base, &simhash(&edited, kind, 128).unwrap());
+            let far = hamming(&base, &simhash(other, kind, 128).unwrap());
+            assert!(near < far, "{kind:?}: near {near}, far {far}");
+        }
+    }
+}
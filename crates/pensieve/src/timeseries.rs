@@ -0,0 +1,153 @@
+//! Dead-band encoding of numeric time series.
+//!
+//! Two recordings of the same sensor trace never agree sample for sample:
+//! every value carries some jitter, and any fixed quantization turns jitter
+//! near a step boundary into flipped bits. A [`DeadBand`] encoder instead
+//! tracks a reference value and only registers a move once a sample leaves
+//! the band of ±`band` around it; the sample then becomes the new
+//! reference. Each step between consecutive samples becomes one bit, the
+//! direction of the latest move: 1 after a move up, 0 after a move down (and
+//! before the first move).
+//!
+//! Bits thus only flip where the trace turns by more than the band. Jitter
+//! smaller than the band changes nothing, and traces with the same shape
+//! encode alike or differ in a few bits around their turns, which the
+//! collapse then absorbs. Encoding the trend rather than the moves alone
+//! also keeps the bit density balanced enough for the collapse to tell
+//! shapes apart.
+
+use crate::{Error, TbfConfig};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A dead-band delta encoder for series of samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeadBand {
+    band: f64,
+}
+
+impl DeadBand {
+    /// An encoder ignoring moves of up to `band` from the reference value.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless `band` is finite and
+    /// positive.
+    pub fn new(band: f64) -> Result<Self, Error> {
+        if !(band.is_finite() && band > 0.0) {
+            return Err(Error::InvalidParameter(
+                "dead band must be finite and positive",
+            ));
+        }
+        Ok(Self { band })
+    }
+
+    /// Half-width of the band.
+    pub fn band(&self) -> f64 {
+        self.band
+    }
+
+    /// One bit per step between consecutive `samples`, MSB-first, with the
+    /// last byte zero-padded; `samples.len() - 1` steps in all, so series
+    /// of equal length encode to equal lengths.
+    ///
+    /// The first sample is the initial reference. NaN samples (gaps) count
+    /// as within the band.
+    pub fn encode(&self, samples: &[f64]) -> Vec<u8> {
+        let steps = samples.len().saturating_sub(1);
+        let mut out = vec![0u8; steps.div_ceil(8)];
+        let Some((&first, rest)) = samples.split_first() else {
+            return out;
+        };
+        let (mut reference, mut rising) = (first, false);
+        for (step, &sample) in rest.iter().enumerate() {
+            if reference.is_nan() {
+                // A gap as reference gives way to the next sample.
+                reference = sample;
+            } else if sample > reference + self.band {
+                (reference, rising) = (sample, true);
+            } else if sample < reference - self.band {
+                (reference, rising) = (sample, false);
+            }
+            out[step / 8] |= u8::from(rising) << (7 - step % 8);
+        }
+        out
+    }
+
+    /// The collapse under `config` of the encoding of `samples`: equal for
+    /// traces of the same shape and length.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::timeseries::DeadBand;
+    ///
+    /// let encoder = DeadBand::new(0.5).unwrap();
+    /// let clean = [20.0, 20.0, 23.0, 23.0, 21.0, 21.0, 21.0, 25.0, 25.0];
+    /// let jittery = [20.1, 19.8, 23.2, 22.9, 21.3, 20.9, 21.1, 24.8, 25.1];
+    /// let config = TbfConfig::new(0.125);
+    /// assert_eq!(
+    ///     encoder.fingerprint(&clean, &config),
+    ///     encoder.fingerprint(&jittery, &config)
+    /// );
+    /// ```
+    pub fn fingerprint(&self, samples: &[f64], config: &TbfConfig) -> Vec<u8> {
+        config.collapse(&self.encode(samples))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let encoder = DeadBand::new(1.0).unwrap();
+        assert_eq!(encoder.encode(&[]), Vec::<u8>::new());
+        assert_eq!(encoder.encode(&[5.0]), Vec::<u8>::new());
+        // Up, hold, down (the reference is now 1.5), hold, up.
+        assert_eq!(
+            encoder.encode(&[0.0, 3.0, 3.9, 1.5, 2.2, 4.5]),
+            [0b1100_1000]
+        );
+        assert_eq!(encoder.encode(&[0.0; 10]), [0, 0]);
+        // Drift within the band never reports, however long it lasts.
+        assert_eq!(encoder.encode(&[0.0, 0.5, 1.0, 0.2, -0.9]), [0]);
+        // Gaps hold, and a leading gap takes the next sample as reference.
+        assert_eq!(
+            encoder.encode(&[f64::NAN, 4.0, f64::NAN, 6.0]),
+            [0b0010_0000]
+        );
+    }
+
+    #[test]
+    fn test_same_shape_fingerprints_alike() {
+        let config = TbfConfig::new(0.125);
+        let encoder = DeadBand::new(0.5).unwrap();
+        let mut rng = crate::entropy::tests::seeded(7);
+        let shape: Vec<f64> = (0..256).map(|i| f64::from((i / 16) % 4) * 2.0).collect();
+        let mut noise = [0u8; 256];
+        rng(&mut noise);
+        let jittery: Vec<f64> = shape
+            .iter()
+            .zip(noise)
+            .map(|(&value, n)| value + (f64::from(n) / 255.0 - 0.5) * 0.4)
+            .collect();
+        assert_eq!(encoder.encode(&shape), encoder.encode(&jittery));
+        assert_eq!(
+            encoder.fingerprint(&shape, &config),
+            encoder.fingerprint(&jittery, &config)
+        );
+
+        let flat = vec![0.0; 256];
+        assert_ne!(
+            encoder.fingerprint(&shape, &config),
+            encoder.fingerprint(&flat, &config)
+        );
+    }
+
+    #[test]
+    fn test_band_must_be_positive() {
+        for band in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(DeadBand::new(band).is_err(), "{band}");
+        }
+    }
+}
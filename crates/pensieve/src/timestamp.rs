@@ -0,0 +1,237 @@
+//! Fingerprints of timestamps that tolerate clock skew.
+//!
+//! Two systems logging the same event rarely agree on the second. A
+//! [`TimeBuckets`] encoder drops the precision below a calendar
+//! [`Granularity`] (UTC year, month, day or hour), and encodes the
+//! remaining fields from the coarsest down, so that a coarser encoding is a
+//! prefix of a finer one.
+//!
+//! Timestamps within the skew of each other usually share a bucket and so
+//! encode, and collapse, alike. Near a bucket edge they may still fall on
+//! either side; [`TimeBuckets::candidates`] lists the buckets within the
+//! skew of a timestamp, so that a lookup can check all of them.
+//!
+//! Timestamps are Unix time in seconds, so leap seconds do not exist.
+
+use crate::{Error, TbfConfig};
+use alloc::vec::Vec;
+
+const HOUR: u64 = 3600;
+const DAY: u64 = 24 * HOUR;
+
+/// The finest calendar field kept by a [`TimeBuckets`] encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Granularity {
+    /// Calendar years.
+    Year,
+    /// Calendar months.
+    Month,
+    /// Days, from midnight UTC.
+    Day,
+    /// Hours of the day.
+    Hour,
+}
+
+impl Granularity {
+    /// All granularities, finest first.
+    const FINEST_FIRST: [Self; 4] = [Self::Hour, Self::Day, Self::Month, Self::Year];
+
+    /// Length of the shortest bucket, in seconds: a common year, February
+    /// of a common year, a day or an hour.
+    pub fn shortest_bucket(self) -> u64 {
+        match self {
+            Self::Year => 365 * DAY,
+            Self::Month => 28 * DAY,
+            Self::Day => DAY,
+            Self::Hour => HOUR,
+        }
+    }
+
+    /// Length of the encoding, in bytes: two for the year and one for each
+    /// finer field.
+    pub fn encoded_len(self) -> usize {
+        match self {
+            Self::Year => 2,
+            Self::Month => 3,
+            Self::Day => 4,
+            Self::Hour => 5,
+        }
+    }
+}
+
+/// Buckets timestamps by calendar field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBuckets {
+    granularity: Granularity,
+    max_skew: u64,
+}
+
+impl TimeBuckets {
+    /// An encoder keeping fields down to `granularity`, tolerating as much
+    /// skew as its [shortest bucket](Granularity::shortest_bucket).
+    pub fn new(granularity: Granularity) -> Self {
+        Self {
+            granularity,
+            max_skew: granularity.shortest_bucket(),
+        }
+    }
+
+    /// The finest encoder whose buckets are at least `max_skew` seconds
+    /// long.
+    ///
+    /// Fails with [`Error::InvalidParameter`] if `max_skew` is zero or
+    /// longer than a common year.
+    pub fn for_skew(max_skew: u64) -> Result<Self, Error> {
+        if max_skew == 0 {
+            return Err(Error::InvalidParameter("maximum skew must be positive"));
+        }
+        Granularity::FINEST_FIRST
+            .into_iter()
+            .find(|granularity| granularity.shortest_bucket() >= max_skew)
+            .map(|granularity| Self {
+                granularity,
+                max_skew,
+            })
+            .ok_or(Error::InvalidParameter("maximum skew exceeds a year"))
+    }
+
+    /// The finest field kept.
+    pub fn granularity(&self) -> Granularity {
+        self.granularity
+    }
+
+    /// The skew tolerated by [`candidates`](Self::candidates), in seconds.
+    pub fn max_skew(&self) -> u64 {
+        self.max_skew
+    }
+
+    /// The bucket of `unix_seconds`: the UTC year as a big-endian `u16`,
+    /// then the month (1 to 12), the day of the month (1 to 31) and the
+    /// hour (0 to 23) as one byte each, down to the granularity.
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless the year is from 0 to
+    /// 65535.
+    pub fn encode(&self, unix_seconds: i64) -> Result<Vec<u8>, Error> {
+        let (year, month, day) = civil_from_days(unix_seconds.div_euclid(DAY as i64));
+        let year = u16::try_from(year)
+            .map_err(|_| Error::InvalidParameter("year must be from 0 to 65535"))?;
+        let hour = (unix_seconds.rem_euclid(DAY as i64) / HOUR as i64) as u8;
+        let [high, low] = year.to_be_bytes();
+        let mut out = Vec::from([high, low, month, day, hour]);
+        out.truncate(self.granularity.encoded_len());
+        Ok(out)
+    }
+
+    /// The [`encode`](Self::encode)d buckets of every timestamp within the
+    /// maximum skew of `unix_seconds`, its own first, without duplicates.
+    /// At most three, as buckets are at least the skew long.
+    pub fn candidates(&self, unix_seconds: i64) -> Result<Vec<Vec<u8>>, Error> {
+        let skew = self.max_skew as i64;
+        let mut buckets = Vec::with_capacity(3);
+        for t in [
+            unix_seconds,
+            unix_seconds.saturating_sub(skew),
+            unix_seconds.saturating_add(skew),
+        ] {
+            let bucket = self.encode(t)?;
+            if !buckets.contains(&bucket) {
+                buckets.push(bucket);
+            }
+        }
+        Ok(buckets)
+    }
+
+    /// The collapse under `config` of the bucket of `unix_seconds`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::TbfConfig;
+    /// use pensieve::timestamp::{Granularity, TimeBuckets};
+    ///
+    /// let buckets = TimeBuckets::for_skew(300).unwrap();
+    /// assert_eq!(buckets.granularity(), Granularity::Hour);
+    /// let config = TbfConfig::new(0.125);
+    /// // 2024-03-01 12:10:00 and 12:13:07 UTC.
+    /// assert_eq!(
+    ///     buckets.fingerprint(1_709_295_000, &config),
+    ///     buckets.fingerprint(1_709_295_187, &config)
+    /// );
+    /// ```
+    pub fn fingerprint(&self, unix_seconds: i64, config: &TbfConfig) -> Result<Vec<u8>, Error> {
+        Ok(config.collapse(&self.encode(unix_seconds)?))
+    }
+}
+
+/// Year, month and day of the proleptic Gregorian calendar `days` after
+/// 1970-01-01 (Howard Hinnant's `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097); // Day of the 400-year era.
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // Day of the year from March.
+    let mp = (5 * doy + 2) / 153; // Month from March.
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-03-01 00:00:00 UTC.
+    const MARCH_2024: i64 = 1_709_251_200;
+
+    #[test]
+    fn test_encode() {
+        let hours = TimeBuckets::new(Granularity::Hour);
+        assert_eq!(hours.encode(0), Ok(alloc::vec![0x07, 0xB2, 1, 1, 0]));
+        assert_eq!(
+            hours.encode(MARCH_2024 - 1),
+            Ok(alloc::vec![0x07, 0xE8, 2, 29, 23])
+        );
+        assert_eq!(
+            hours.encode(MARCH_2024),
+            Ok(alloc::vec![0x07, 0xE8, 3, 1, 0])
+        );
+        // Before the epoch: 1969-12-31 23:59:59.
+        assert_eq!(hours.encode(-1), Ok(alloc::vec![0x07, 0xB1, 12, 31, 23]));
+        assert_eq!(
+            TimeBuckets::new(Granularity::Month).encode(MARCH_2024),
+            Ok(alloc::vec![0x07, 0xE8, 3])
+        );
+        assert!(hours.encode(i64::MAX).is_err());
+        assert!(hours.encode(-62_167_219_201).is_err()); // 0000-01-01 minus 1 s.
+    }
+
+    #[test]
+    fn test_skew() {
+        assert_eq!(
+            TimeBuckets::for_skew(90).unwrap().granularity(),
+            Granularity::Hour
+        );
+        assert_eq!(
+            TimeBuckets::for_skew(2 * DAY).unwrap().granularity(),
+            Granularity::Month
+        );
+        assert!(TimeBuckets::for_skew(0).is_err());
+        assert!(TimeBuckets::for_skew(366 * DAY).is_err());
+
+        // Five minutes either side of midnight: different buckets, each
+        // within the other's candidates.
+        let buckets = TimeBuckets::for_skew(600).unwrap();
+        let (before, after) = (MARCH_2024 - 300, MARCH_2024 + 300);
+        let config = TbfConfig::new(0.125);
+        assert_ne!(buckets.encode(before), buckets.encode(after));
+        let candidates = buckets.candidates(before).unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0], buckets.encode(before).unwrap());
+        assert!(candidates.contains(&buckets.encode(after).unwrap()));
+        assert_eq!(buckets.candidates(MARCH_2024 + 1800).unwrap().len(), 1);
+        assert_eq!(
+            buckets.fingerprint(after, &config).unwrap(),
+            config.collapse(&buckets.encode(after).unwrap())
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Turning raw inputs into weighted features for the hash families.
+//!
+//! Feature-based digests such as [`SimHash`](crate::simhash::SimHash) do
+//! not care what a feature is, only how often it occurs. A [`Tokenizer`]
+//! decides that for one kind of data: byte n-grams suit URLs and binary
+//! logs, whitespace-separated words suit prose and source code, and words
+//! without digits suit text logs. The same back-end then serves all of
+//! them.
+
+use crate::Error;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// Splits an input into features, each weighted by how often it occurs.
+pub trait Tokenizer {
+    /// The distinct features of `input` with their occurrence counts, in
+    /// ascending byte order. Empty input has no features.
+    fn features<'a>(&self, input: &'a [u8]) -> Vec<(&'a [u8], u32)>;
+}
+
+/// Every run of `n` consecutive bytes; inputs shorter than `n` are a single
+/// feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteNgrams {
+    n: usize,
+}
+
+impl ByteNgrams {
+    /// Fails with [`Error::InvalidParameter`] if `n` is zero.
+    pub fn new(n: usize) -> Result<Self, Error> {
+        if n == 0 {
+            return Err(Error::InvalidParameter(
+                "n-grams must hold at least one byte",
+            ));
+        }
+        Ok(Self { n })
+    }
+}
+
+impl Tokenizer for ByteNgrams {
+    fn features<'a>(&self, input: &'a [u8]) -> Vec<(&'a [u8], u32)> {
+        if input.is_empty() {
+            return Vec::new();
+        }
+        count(input.windows(self.n.min(input.len())))
+    }
+}
+
+/// Every maximal run of bytes that are not ASCII whitespace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WhitespaceWords;
+
+impl Tokenizer for WhitespaceWords {
+    fn features<'a>(&self, input: &'a [u8]) -> Vec<(&'a [u8], u32)> {
+        count(
+            input
+                .split(u8::is_ascii_whitespace)
+                .filter(|word| !word.is_empty()),
+        )
+    }
+}
+
+/// The [`WhitespaceWords`] of a log line that hold no ASCII digit.
+///
+/// Timestamps, request ids, addresses and durations vary from one line of
+/// a message to the next; dropping every word with a digit in it leaves the
+/// message's template, so repeats of it share their features.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LogWords;
+
+impl Tokenizer for LogWords {
+    fn features<'a>(&self, input: &'a [u8]) -> Vec<(&'a [u8], u32)> {
+        count(
+            input
+                .split(u8::is_ascii_whitespace)
+                .filter(|word| !word.is_empty() && !word.iter().any(u8::is_ascii_digit)),
+        )
+    }
+}
+
+/// Occurrence counts of `tokens`, in ascending byte order.
+fn count<'a>(tokens: impl Iterator<Item = &'a [u8]>) -> Vec<(&'a [u8], u32)> {
+    let mut counts = BTreeMap::new();
+    for token in tokens {
+        *counts.entry(token).or_insert(0u32) += 1;
+    }
+    counts.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::hamming;
+    use crate::simhash::SimHash;
+
+    #[test]
+    fn test_byte_ngrams() {
+        let trigrams = ByteNgrams::new(3).unwrap();
+        assert_eq!(
+            trigrams.features(b"abcabc"),
+            [(&b"abc"[..], 2), (b"bca", 1), (b"cab", 1)]
+        );
+        assert_eq!(trigrams.features(b"ab"), [(&b"ab"[..], 1)]);
+        assert!(trigrams.features(b"").is_empty());
+        assert!(ByteNgrams::new(0).is_err());
+    }
+
+    #[test]
+    fn test_whitespace_words() {
+        assert_eq!(
+            WhitespaceWords.features(b" GET /a  GET\t/b\n"),
+            [(&b"/a"[..], 1), (b"/b", 1), (b"GET", 2)]
+        );
+        assert!(WhitespaceWords.features(b" \n ").is_empty());
+        assert_eq!(
+            LogWords.features(b"12:00:01 GET /a took 3ms GET"),
+            [(&b"/a"[..], 1), (b"GET", 2), (b"took", 1)]
+        );
+    }
+
+    #[test]
+    fn test_tokenizers_feed_simhash() {
+        let digest = |tokenizer: &dyn Tokenizer, input: &[u8]| {
+            let mut hash = SimHash::new(64).unwrap();
+            hash.add_features(tokenizer, input);
+            hash.finish()
+        };
+        let url = b"https://example.com/search?q=pensieve&page=2";
+        let near = b"https://example.com/search?q=pensieve&page=3";
+        let far = b"ftp://mirror.example.org/pub/archive.tar.gz";
+        let grams = ByteNgrams::new(4).unwrap();
+        assert!(hamming(&digest(&grams, url), &digest(&grams, near)) < 16);
+        assert!(hamming(&digest(&grams, url), &digest(&grams, far)) > 16);
+
+        let line = b"fn main ( ) { println ! ( ) ; }";
+        assert_eq!(
+            digest(&WhitespaceWords, line),
+            digest(&WhitespaceWords, b"fn  main ( )\n{ println ! ( ) ; }")
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! The last step of the collapse: turning chunk levels into output bytes.
+//!
+//! The chunk levels are stretched over the output length, byte `i` taking
+//! the level of chunk `i % chunks`, and then passed through a
+//! [`FinalTransform`]. Inputs under 16 bits have more chunks than output
+//! bytes; their levels are packed instead, bit `k` (MSB-first) of byte `i`
+//! holding the level of chunk `(i + k * len) % chunks`, so that every level
+//! reaches the output. Applications that post-process the levels want them
+//! raw, storage that should not reveal the levels at a glance wants them
+//! masked, and anything exposed to an adversary wants them non-invertible.
+
+use crate::crypto::{hmac::HmacSha256, sha256::Sha256};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// Domain separation of the keyed PRF output.
+const PRF_INFO: &[u8] = b"pensieve/transform/v1/prf";
+/// Domain separation of the hash output.
+const HASH_INFO: &[u8] = b"pensieve/transform/v1/hash";
+
+/// How stretched chunk levels become output bytes.
+///
+/// Every transform is a function of the levels and the output length
+/// only, so inputs that collapse to the same levels still produce the same
+/// output, and every transform runs in constant time in the levels.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FinalTransform {
+    /// Each level as a 0x00 or 0xFF byte (or packed, for inputs under 16
+    /// bits).
+    Identity,
+    /// The [`Identity`](Self::Identity) bytes XORed with `base + i`
+    /// (wrapping) at position `i`. `XorMask { base: 0xAA }` is the original
+    /// output of [`collapse_deterministic`](crate::collapse_deterministic)
+    /// and the default.
+    XorMask {
+        /// Mask of the first byte.
+        base: u8,
+    },
+    /// HMAC-SHA256 of the levels under `key`, in counter mode. Outputs
+    /// under different keys cannot be linked without the keys.
+    KeyedPrf {
+        /// The PRF key; keep it secret.
+        key: [u8; 32],
+    },
+    /// SHA-256 of the levels, in counter mode, for digests stored where
+    /// anyone can read them. Does not reveal the levels beyond what
+    /// guessing all of them reveals; with at most eight levels that is
+    /// little work, so levels that must stay secret need
+    /// [`KeyedPrf`](Self::KeyedPrf) with a key the readers lack.
+    ///
+    /// Like the PRF it also avalanches: inputs whose levels differ in a
+    /// single chunk get unrelated outputs, so digests of inputs beyond the
+    /// tolerance never look partially similar to whoever ranks or eyeballs
+    /// them, while equal levels still give equal outputs.
+    Hash,
+}
+
+impl FinalTransform {
+    /// Stable one-byte identifier of the kind of transform, used by storage
+    /// formats. Parameters (the mask base, the key) are not included.
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::Identity => 0,
+            Self::XorMask { .. } => 1,
+            Self::KeyedPrf { .. } => 2,
+            Self::Hash => 3,
+        }
+    }
+
+    /// Produces `len` output bytes from the 0/1 chunk `levels`.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn apply(&self, levels: &[u8], len: usize) -> Vec<u8> {
+        let mut out = alloc::vec![0u8; len];
+        self.apply_at(levels, len, 0, &mut out);
+        out
+    }
+
+    /// Writes bytes `offset..offset + out.len()` of the `len` output bytes
+    /// [`apply`](Self::apply) produces from `levels`, without allocating.
+    pub(crate) fn apply_at(&self, levels: &[u8], len: usize, offset: usize, out: &mut [u8]) {
+        match self {
+            Self::Identity | Self::XorMask { .. } => {
+                for (i, byte) in (offset..).zip(out.iter_mut()) {
+                    let stretched = (0..8).fold(0u8, |byte, k| {
+                        byte << 1 | levels[level_index(levels.len(), len, i, k)]
+                    });
+                    *byte = match self {
+                        Self::XorMask { base } => stretched ^ base.wrapping_add(i as u8),
+                        _ => stretched,
+                    };
+                }
+            }
+            // Counter mode: 32-byte blocks, counter little-endian from 0.
+            Self::KeyedPrf { .. } | Self::Hash => {
+                let mut written = 0;
+                while written < out.len() {
+                    let at = offset + written;
+                    let block = self.block(levels, len, ((at / 32) as u64).to_le_bytes());
+                    let n = (32 - at % 32).min(out.len() - written);
+                    out[written..written + n].copy_from_slice(&block[at % 32..at % 32 + n]);
+                    written += n;
+                }
+            }
+        }
+    }
+
+    /// Passes the `len` output bytes [`apply`](Self::apply) produces from
+    /// `levels` to `sink`, a window at a time, without allocating.
+    pub(crate) fn emit(&self, levels: &[u8], len: usize, mut sink: impl FnMut(&[u8])) {
+        let mut window = [0u8; 64];
+        for offset in (0..len).step_by(window.len()) {
+            let window = &mut window[..(len - offset).min(64)];
+            self.apply_at(levels, len, offset, window);
+            sink(window);
+        }
+    }
+
+    /// The 0/1 chunk levels, `chunks` of them, that [`apply`](Self::apply)
+    /// turns into `output`, or `None` if no levels do. Not constant-time:
+    /// the PRF and hash outputs are inverted by trying every combination
+    /// of levels against their first block.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn invert(&self, output: &[u8], chunks: usize) -> Option<Vec<u8>> {
+        let len = output.len();
+        let levels = match self {
+            Self::Identity | Self::XorMask { .. } => {
+                let mut levels = alloc::vec![0u8; chunks];
+                for (i, &byte) in output.iter().enumerate() {
+                    let byte = match self {
+                        Self::XorMask { base } => byte ^ base.wrapping_add(i as u8),
+                        _ => byte,
+                    };
+                    for k in 0..8 {
+                        levels[level_index(chunks, len, i, k)] = byte >> (7 - k) & 1;
+                    }
+                }
+                levels
+            }
+            Self::KeyedPrf { .. } | Self::Hash => {
+                let head = &output[..len.min(32)];
+                (0..1u32 << chunks)
+                    .map(|bits| {
+                        (0..chunks)
+                            .map(|c| (bits >> (chunks - 1 - c) & 1) as u8)
+                            .collect::<Vec<u8>>()
+                    })
+                    .find(|levels| self.block(levels, len, [0; 8])[..head.len()] == *head)?
+            }
+        };
+        (self.apply(&levels, len) == output).then_some(levels)
+    }
+
+    /// Block `counter` of the PRF or hash output; the identity and the mask
+    /// have no blocks.
+    fn block(&self, levels: &[u8], len: usize, counter: [u8; 8]) -> [u8; 32] {
+        let len = (len as u64).to_le_bytes();
+        match self {
+            Self::KeyedPrf { key } => HmacSha256::mac(key, &[PRF_INFO, &counter, &len, levels]),
+            _ => Sha256::digest(&[HASH_INFO, &counter, &len, levels]),
+        }
+    }
+}
+
+impl Default for FinalTransform {
+    /// The original position-dependent XOR.
+    fn default() -> Self {
+        Self::XorMask { base: 0xAA }
+    }
+}
+
+impl core::fmt::Debug for FinalTransform {
+    /// Like a derived `Debug`, but never prints the PRF key.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Identity => f.write_str("Identity"),
+            Self::XorMask { base } => f.debug_struct("XorMask").field("base", base).finish(),
+            Self::KeyedPrf { .. } => f.write_str("KeyedPrf { .. }"),
+            Self::Hash => f.write_str("Hash"),
+        }
+    }
+}
+
+/// The chunk whose level bit `k` (MSB-first) of output byte `i` holds, for
+/// `chunks` levels stretched over `len` bytes.
+pub(crate) fn level_index(chunks: usize, len: usize, i: usize, k: usize) -> usize {
+    if chunks <= len {
+        i % chunks
+    } else {
+        (i + k * len) % chunks
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::{TbfConfig, collapse_ct};
+
+    #[test]
+    fn test_default_is_original_output() {
+        let input = [0xF0u8; 40];
+        let config = TbfConfig::new(0.125);
+        assert_eq!(config.transform(), FinalTransform::default());
+        assert_eq!(config.collapse(&input), collapse_ct(&input, 0.125));
+    }
+
+    #[test]
+    fn test_identity_exposes_levels() {
+        let mut input = [0u8; 16];
+        input[..2].copy_from_slice(&[0xFF, 0xFF]);
+        let config = TbfConfig::new(0.125).with_transform(FinalTransform::Identity);
+        let output = config.collapse(&input);
+        assert_eq!(&output[..8], &[0xFF, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(output[8..], output[..8]);
+    }
+
+    #[test]
+    fn test_keyed_and_hashed_outputs() {
+        let input = [0x3Cu8; 100];
+        let mut noisy = input;
+        noisy[0] ^= 1;
+        let keyed = |key| TbfConfig::new(0.125).with_transform(FinalTransform::KeyedPrf { key });
+        let hashed = TbfConfig::new(0.125).with_transform(FinalTransform::Hash);
+        for config in [keyed([1; 32]), hashed] {
+            let output = config.collapse(&input);
+            assert_eq!(output.len(), 100);
+            assert_eq!(output, config.collapse(&noisy));
+            assert_ne!(output[..32], output[32..64]);
+        }
+        assert_ne!(
+            keyed([1; 32]).collapse(&input),
+            keyed([2; 32]).collapse(&input)
+        );
+        assert_ne!(keyed([1; 32]).collapse(&input), hashed.collapse(&input));
+        // The output length is bound in, so prefixes of longer outputs differ.
+        assert_ne!(hashed.collapse(&[0; 32]), hashed.collapse(&[0; 64])[..32]);
+        assert!(hashed.collapse(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_windows_match_the_whole_output() {
+        let levels = [1, 0, 0, 1, 1, 0, 1, 0];
+        for transform in [
+            FinalTransform::default(),
+            FinalTransform::KeyedPrf { key: [3; 32] },
+            FinalTransform::Hash,
+        ] {
+            let whole = transform.apply(&levels, 100);
+            let mut window = [0u8; 45];
+            transform.apply_at(&levels, 100, 30, &mut window);
+            assert_eq!(window[..], whole[30..75]);
+            let mut emitted = Vec::new();
+            transform.emit(&levels, 100, |bytes| emitted.extend_from_slice(bytes));
+            assert_eq!(emitted, whole);
+        }
+    }
+
+    #[test]
+    fn test_hashed_outputs_avalanche_on_one_level() {
+        let (levels, flipped) = ([1, 0, 0, 1, 1, 0, 1, 0], [1, 0, 0, 1, 1, 0, 1, 1]);
+        let differing = |transform: FinalTransform| {
+            let (a, b) = (
+                transform.apply(&levels, 256),
+                transform.apply(&flipped, 256),
+            );
+            a.iter()
+                .zip(&b)
+                .map(|(x, y)| (x ^ y).count_ones())
+                .sum::<u32>()
+        };
+        // One chunk in eight: an eighth of the bits of the stretched output.
+        assert_eq!(differing(FinalTransform::default()), 256);
+        let hashed = differing(FinalTransform::Hash);
+        assert!(
+            (768..=1280).contains(&hashed),
+            "{hashed} of 2048 bits differ"
+        );
+    }
+
+    #[test]
+    fn test_debug_hides_key() {
+        let debug = alloc::format!("{:?}", FinalTransform::KeyedPrf { key: [0x42; 32] });
+        assert_eq!(debug, "KeyedPrf { .. }");
+        let ids = [
+            FinalTransform::Identity,
+            FinalTransform::default(),
+            FinalTransform::KeyedPrf { key: [0; 32] },
+            FinalTransform::Hash,
+        ]
+        .map(|t| t.id());
+        assert_eq!(ids, [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,275 @@
+//! Unicode normalization of strings before collapsing.
+//!
+//! The same visible text reaches two systems as different code points: "é"
+//! precomposed or as "e" and a combining acute accent, "ﬁ" as a ligature,
+//! "ＡＢＣ" in fullwidth forms, a no-break space for a space. Their UTF-8
+//! bytes differ, and so would their collapses. A [`Normalizer`] first maps
+//! such variants to one string: NFKC, Unicode's compatibility
+//! normalization, together with case folding (the `NFKC_Casefold` of
+//! Unicode Standard Annex #15), then optionally collapses whitespace.
+//!
+//! # Limitations
+//! The crate has no dependencies, so it carries its own Unicode 14.0
+//! tables, and only for the scripts most text is in: the normalization is
+//! exact for Latin, Greek, Cyrillic, kana and Hangul, and for the
+//! compatibility characters common in Western and East Asian text
+//! (ligatures, fullwidth and halfwidth forms, spaces, super- and
+//! subscripts, fractions, Roman numerals and enclosed alphanumerics).
+//! Characters outside those tables pass through unchanged, among them
+//! Arabic presentation forms, CJK compatibility ideographs and
+//! mathematical alphanumeric symbols, and combining marks of other scripts
+//! are not reordered. Case folding is Rust's lowercase mapping plus the
+//! full folding of "ß" to "ss", of final sigma and of iota subscript; the few other folds that
+//! differ from lowercasing, such as Cherokee's, are not applied. Extending
+//! the tables changes the fingerprints of the characters they add.
+
+use crate::TbfConfig;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+mod tables;
+
+use tables::{COMBINING_CLASSES, COMPOSITIONS, DECOMPOSITIONS};
+
+// Hangul syllables, which are composed and decomposed algorithmically.
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+/// Normalizes strings to NFKC with case folding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalizer {
+    collapse_whitespace: bool,
+}
+
+impl Normalizer {
+    /// A normalizer that leaves whitespace as it is.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the normalizer with whitespace collapsing turned on or off:
+    /// when on, leading and trailing whitespace is dropped and every inner
+    /// run of whitespace becomes a single space.
+    pub fn with_collapsed_whitespace(mut self, collapse: bool) -> Self {
+        self.collapse_whitespace = collapse;
+        self
+    }
+
+    /// Whether whitespace is collapsed.
+    pub fn collapses_whitespace(&self) -> bool {
+        self.collapse_whitespace
+    }
+
+    /// `text` in NFKC, case folded, with whitespace collapsed if enabled.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use pensieve::unicode::Normalizer;
+    ///
+    /// let normalizer = Normalizer::new().with_collapsed_whitespace(true);
+    /// assert_eq!(normalizer.normalize(" Ｃａｆé\u{A0} ﬁne "), "café fine");
+    /// assert_eq!(
+    ///     normalizer.normalize("cafe\u{301} FINE"),
+    ///     normalizer.normalize("CAFÉ fine")
+    /// );
+    /// ```
+    pub fn normalize(&self, text: &str) -> String {
+        let mut chars = Vec::with_capacity(text.len());
+        text.chars().for_each(|c| decompose(c, &mut chars));
+        // NFKC, then fold each composed character, then compose again: the
+        // fold of a letter with iota subscript ends in a spacing iota, which
+        // must stay right after that letter.
+        let nfkc = compose(reorder(chars));
+        let mut folded = Vec::with_capacity(nfkc.len());
+        for c in nfkc.into_iter().flat_map(char::to_lowercase) {
+            match c {
+                'ß' => folded.extend(['s', 's']),
+                'ς' => folded.push('σ'),
+                c => {
+                    let start = folded.len();
+                    decompose(c, &mut folded);
+                    for c in &mut folded[start..] {
+                        if *c == '\u{345}' {
+                            *c = 'ι';
+                        }
+                    }
+                }
+            }
+        }
+        let composed: String = compose(reorder(folded)).into_iter().collect();
+        if self.collapse_whitespace {
+            let mut out = String::with_capacity(composed.len());
+            for word in composed.split_whitespace() {
+                if !out.is_empty() {
+                    out.push(' ');
+                }
+                out.push_str(word);
+            }
+            out
+        } else {
+            composed
+        }
+    }
+
+    /// The collapse under `config` of the UTF-8 bytes of the
+    /// [`normalize`](Self::normalize)d `text`.
+    pub fn collapse(&self, text: &str, config: &TbfConfig) -> Vec<u8> {
+        config.collapse(self.normalize(text).as_bytes())
+    }
+}
+
+/// Appends the full compatibility decomposition of `c` to `out`.
+fn decompose(c: char, out: &mut Vec<char>) {
+    let s = u32::from(c).wrapping_sub(S_BASE);
+    if s < S_COUNT {
+        let [l, v, t] = [
+            L_BASE + s / N_COUNT,
+            V_BASE + s % N_COUNT / T_COUNT,
+            s % T_COUNT,
+        ];
+        out.extend([l, v].map(|j| char::from_u32(j).expect("a conjoining jamo")));
+        if t > 0 {
+            out.push(char::from_u32(T_BASE + t).expect("a trailing jamo"));
+        }
+        return;
+    }
+    match DECOMPOSITIONS.binary_search_by_key(&c, |&(c, _)| c) {
+        Ok(i) => out.extend(DECOMPOSITIONS[i].1.chars()),
+        Err(_) => out.push(c),
+    }
+}
+
+/// The canonical combining class of `c`; 0 for starters.
+fn combining_class(c: char) -> u8 {
+    COMBINING_CLASSES
+        .binary_search_by(|&(first, last, _)| {
+            if last < c {
+                Ordering::Less
+            } else if first > c {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .map_or(0, |i| COMBINING_CLASSES[i].2)
+}
+
+/// Puts every run of combining marks in canonical order: by combining
+/// class, keeping the order of marks of the same class.
+fn reorder(mut chars: Vec<char>) -> Vec<char> {
+    let mut start = 0;
+    while start < chars.len() {
+        let run = chars[start..]
+            .iter()
+            .take_while(|&&c| combining_class(c) != 0)
+            .count();
+        chars[start..start + run].sort_by_key(|&c| combining_class(c));
+        start += run.max(1);
+    }
+    chars
+}
+
+/// The primary composite of `first` and `second`, if there is one.
+fn compose_pair(first: char, second: char) -> Option<char> {
+    let (a, b) = (u32::from(first), u32::from(second));
+    if (L_BASE..L_BASE + L_COUNT).contains(&a) && (V_BASE..V_BASE + V_COUNT).contains(&b) {
+        let s = S_BASE + ((a - L_BASE) * V_COUNT + (b - V_BASE)) * T_COUNT;
+        return char::from_u32(s);
+    }
+    let s = a.wrapping_sub(S_BASE);
+    if s < S_COUNT && s % T_COUNT == 0 && (T_BASE + 1..T_BASE + T_COUNT).contains(&b) {
+        return char::from_u32(a + b - T_BASE);
+    }
+    COMPOSITIONS
+        .binary_search_by_key(&(first, second), |&(a, b, _)| (a, b))
+        .ok()
+        .map(|i| COMPOSITIONS[i].2)
+}
+
+/// The canonical composition of decomposed, canonically ordered `chars`.
+fn compose(chars: Vec<char>) -> Vec<char> {
+    let mut out: Vec<char> = Vec::with_capacity(chars.len());
+    let mut starter = None; // Index in `out` of the last starter.
+    let mut last_class = 0; // Class of the last character kept after it.
+    for c in chars {
+        let class = combining_class(c);
+        if let Some(s) = starter {
+            // A mark in between blocks `c` unless its class is lower.
+            let blocked = out.len() > s + 1 && (last_class == 0 || last_class >= class);
+            if let Some(composed) = compose_pair(out[s], c).filter(|_| !blocked) {
+                out[s] = composed;
+                continue;
+            }
+        }
+        if class == 0 {
+            starter = Some(out.len());
+        }
+        last_class = class;
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nfkc_casefold() {
+        let normalizer = Normalizer::new();
+        let cases = [
+            ("e\u{301}", "é"),
+            ("É", "é"),
+            ("ＡＢＣ ﬁle", "abc file"),
+            ("Straße", "strasse"),
+            ("ΣΟΦΟΣ σοφος", "σοφοσ σοφοσ"),
+            ("ᾼ ᾳ\u{301}", "αι άι"),
+            // Marks out of canonical order, and a nested composite.
+            ("E\u{302}\u{323}", "\u{1EC7}"),
+            ("e\u{323}\u{302}", "\u{1EC7}"),
+            ("u\u{308}\u{304}", "\u{1D6}"),
+            ("\u{1112}\u{1161}\u{11AB}", "한"),
+            ("한", "한"),
+            ("ｶﾞｷﾞ", "ガギ"),
+            ("x²½ Ⅻ ①", "x21\u{2044}2 xii 1"),
+            ("\u{212B}\u{2126}", "åω"),
+            // Outside the tables: unchanged.
+            ("مرحبا ﷺ", "مرحبا ﷺ"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalizer.normalize(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let text = " a\u{3000}\u{A0}b\t\n c ";
+        assert_eq!(Normalizer::new().normalize(text), " a  b\t\n c ");
+        let collapsing = Normalizer::new().with_collapsed_whitespace(true);
+        assert!(collapsing.collapses_whitespace());
+        assert_eq!(collapsing.normalize(text), "a b c");
+        assert_eq!(collapsing.normalize(" \t"), "");
+    }
+
+    #[test]
+    fn test_collapse() {
+        let config = TbfConfig::new(0.125);
+        let normalizer = Normalizer::new();
+        assert_eq!(
+            normalizer.collapse("ＣＡＦÉ", &config),
+            normalizer.collapse("cafe\u{301}", &config)
+        );
+        assert_eq!(
+            normalizer.collapse("ＣＡＦÉ", &config),
+            config.collapse("café".as_bytes())
+        );
+    }
+}